@@ -0,0 +1,51 @@
+//! What the headless examples check their frames with, each of them includes it with `mod common;`.
+
+// every example only uses some of it:
+#![allow(dead_code)]
+
+use vulkan_video::{Mesh, Vertex, VulkanRenderer, golden, vk};
+
+use anyhow::Result;
+
+/// The pixel at `x`, `y` of RGBA8 `pixels` that are `extent` big, as `read_pixels` returns them.
+pub fn pixel(pixels: &[u8], extent: vk::Extent2D, x: u32, y: u32) -> [u8; 4] {
+    let i = ((y * extent.width + x) * 4) as usize;
+
+    [pixels[i], pixels[i + 1], pixels[i + 2], pixels[i + 3]]
+}
+
+/// Checks a frame against the golden called `name`, saying where it went when `VULKAN_VIDEO_BLESS` blesses it.
+pub fn check_golden(name: &str, extent: vk::Extent2D, pixels: &[u8]) -> Result<()> {
+    if let Some(path) = golden::check(name, extent, pixels)? {
+        println!("golden: blessed {}", path.display());
+    }
+
+    Ok(())
+}
+
+/// Which of red, green and blue is brightest, or `None` for black.
+pub fn dominant(pixel: [u8; 4]) -> Option<usize> {
+    let channel = (0..3).max_by_key(|&channel| pixel[channel])?;
+
+    if pixel[channel] < 32 {
+        None
+    } else {
+        Some(channel)
+    }
+}
+
+/// A white quad from `min` to `max` in the XY plane, facing +Z.
+pub fn quad(renderer: &VulkanRenderer, min: [f32; 2], max: [f32; 2]) -> Result<Mesh> {
+    let corner = |x: f32, y: f32| Vertex {
+        position: [x, y, 0.0],
+        normal: [0.0, 0.0, 1.0],
+        tangent: [1.0, 0.0, 0.0, 1.0],
+        color: [1.0; 4],
+        ..Default::default()
+    };
+
+    let vertices = [corner(min[0], min[1]), corner(max[0], min[1]), corner(max[0], max[1]), corner(min[0], max[1])];
+    let indices = [0, 1, 2, 2, 3, 0];
+
+    Mesh::new(&renderer.main_device, &vertices, &indices)
+}
@@ -0,0 +1,185 @@
+//! Loads renderer settings from TOML and RON, overrides them like the environment and flags would and checks them,
+//! along with how exclusive fullscreen picks its video mode.
+//!
+//! Doesn't need a Vulkan device, nothing is created from the settings. Runs as part of `cargo test`.
+
+use vulkan_video::{RendererConfig, vk};
+use vulkan_video::renderer::config::{PresentMode, WindowMode};
+
+use winit::window::CursorIcon;
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+const TOML: &str = r#"
+present_mode = "mailbox"
+clear_color = [0.1, 0.2, 0.3, 1.0]
+device = "llvmpipe"
+
+[window]
+width = 1920
+height = 1080
+mode = "fullscreen"
+"#;
+
+const RON: &str = "(present_mode: immediate, samples: 4, frames_in_flight: 2, window: (title: \"From RON\", width: 640))";
+
+fn main() -> Result<()> {
+    // what isn't in the file is left at its default:
+    let config = RendererConfig::from_toml(TOML)?;
+
+    assert_eq!(config.present_mode, PresentMode::Mailbox);
+    assert_eq!(config.clear_color, [0.1, 0.2, 0.3, 1.0]);
+    assert_eq!(config.device.as_deref(), Some("llvmpipe"));
+    assert_eq!((config.window.width, config.window.height), (1920, 1080));
+    assert_eq!(config.window.mode, WindowMode::Borderless, "fullscreen isn't borderless");
+
+    let defaults = RendererConfig::default();
+
+    assert_eq!(config.window.title, defaults.window.title);
+    assert_eq!(config.frames_in_flight, defaults.frames_in_flight);
+    assert_eq!(config.samples, 1);
+    assert!(config.window.resizable && config.window.decorations && config.window.cursor_visible);
+    assert_eq!(config.window.position, None);
+
+    // and written back it reads the same:
+    assert_eq!(RendererConfig::from_toml(&config.to_toml()?)?, config, "TOML doesn't round trip");
+
+    let from_ron = RendererConfig::from_ron(RON)?;
+
+    assert_eq!(from_ron.present_mode, PresentMode::Immediate);
+    assert_eq!(from_ron.frames_in_flight, 2);
+    assert_eq!(from_ron.sample_count(), vk::SampleCountFlags::TYPE_4);
+    assert_eq!(from_ron.window.title, "From RON");
+    assert_eq!((from_ron.window.width, from_ron.window.height), (640, defaults.window.height));
+    assert_eq!(RendererConfig::from_ron(&from_ron.to_ron()?)?, from_ron, "RON doesn't round trip");
+
+    // overridden by flags, which are taken out of the arguments and leave the rest:
+    let mut overridden = config.clone();
+
+    let mut args: Vec<String> = [
+        "model.gltf",
+        "--window-width",
+        "1280",
+        "--hdr",
+        "--present-mode",
+        "fifo_relaxed",
+        "--window-mode",
+        "exclusive",
+        "--window-position",
+        "[100, 50]",
+        "--window-resizable",
+        "false",
+        "--window-cursor-grab",
+        "--window-cursor-icon",
+        "Crosshair",
+        "--window-icon",
+        "icons/app.png",
+    ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+
+    overridden.apply_args(&mut args)?;
+
+    assert_eq!(args, ["model.gltf"], "the flags weren't taken out");
+    assert_eq!(overridden.window.width, 1280);
+    assert_eq!(overridden.window.height, 1080);
+    assert!(overridden.hdr, "--hdr without a value didn't turn it on");
+    assert_eq!(overridden.present_mode, PresentMode::FifoRelaxed);
+    assert_eq!(overridden.window.mode, WindowMode::Exclusive);
+    assert_eq!(overridden.window.position, Some([100, 50]));
+    assert!(!overridden.window.resizable && overridden.window.cursor_grab);
+    assert_eq!(overridden.window.cursor_icon, CursorIcon::Crosshair);
+    assert_eq!(overridden.window.icon, Some(PathBuf::from("icons/app.png")));
+    assert_eq!(RendererConfig::from_toml(&overridden.to_toml()?)?, overridden, "window settings don't round trip");
+
+    let mut args = vec!["--validation".to_owned(), "false".to_owned()];
+
+    overridden.apply_args(&mut args)?;
+
+    assert!(!overridden.validation && args.is_empty(), "--validation false didn't turn it off");
+
+    // and by the environment:
+    std::env::set_var("VULKAN_VIDEO_WINDOW_TITLE", "From the environment");
+    std::env::set_var("VULKAN_VIDEO_FRAMES_IN_FLIGHT", "5");
+
+    let from_env = RendererConfig::from_env()?;
+
+    std::env::remove_var("VULKAN_VIDEO_WINDOW_TITLE");
+    std::env::remove_var("VULKAN_VIDEO_FRAMES_IN_FLIGHT");
+
+    assert_eq!(from_env.window.title, "From the environment");
+    assert_eq!(from_env.frames_in_flight, 5);
+
+    assert_eq!(RendererConfig::flag("window.width"), "--window-width");
+    assert_eq!(RendererConfig::flag("frames_in_flight"), "--frames-in-flight");
+
+    // settings that can't work are refused with what's wrong:
+    let invalid = [
+        ("window.width", "0"),
+        ("api_version", "1.0"),
+        ("api_version", "one"),
+        ("samples", "0"),
+        ("samples", "3"),
+        ("samples", "128"),
+        ("frames_in_flight", "0"),
+        ("frames_in_flight", "9"),
+        ("device", "\"\""),
+        ("window.refresh_rate", "0"),
+    ];
+
+    for (key, value) in invalid {
+        let mut config = RendererConfig::default();
+
+        config.set(key, value)?;
+
+        let error = match config.validate() {
+            Ok(()) => panic!("{} = {} was accepted", key, value),
+            Err(error) => error
+        };
+
+        println!("config: {} = {}: {}", key, value, error);
+    }
+
+    let mut config = RendererConfig::default();
+
+    assert!(config.set("frames_in_flight", "many").is_err(), "a string was accepted as a number");
+    assert!(config.set("window.depth", "1").is_err(), "a setting that doesn't exist was accepted");
+    assert!(RendererConfig::from_toml("frames_in_flight = 9").is_err(), "a file with an invalid setting was accepted");
+
+    // exclusive fullscreen goes for the window's size, then the refresh rate asked for or the highest:
+    let modes = [
+        ([2560, 1440], 144, 32),
+        ([1920, 1080], 60, 32),
+        ([1920, 1080], 144, 24),
+        ([1920, 1080], 144, 32),
+        ([1920, 1080], 75, 32),
+    ];
+
+    let mut window = RendererConfig::default().window;
+
+    window.width = 1920;
+    window.height = 1080;
+
+    assert_eq!(window.pick_video_mode(&modes), Some(3));
+
+    window.refresh_rate = Some(70);
+
+    assert_eq!(window.pick_video_mode(&modes), Some(4));
+
+    // and for the largest one without a mode of the window's size:
+    window.width = 1000;
+
+    assert_eq!(window.pick_video_mode(&modes), Some(0));
+    assert_eq!(window.pick_video_mode(&[]), None);
+
+    // assets are looked for in `assets` unless they're somewhere already:
+    assert_eq!(config.asset("models/box.glb"), PathBuf::from("assets/models/box.glb"));
+    assert_eq!(config.asset("Cargo.toml"), PathBuf::from("Cargo.toml"));
+
+    println!("config: {} settings loaded, overridden and checked", RendererConfig::KEYS.len());
+
+    Ok(())
+}
@@ -0,0 +1,126 @@
+//! A spinning cube to look at with an orbit camera, or a fly camera after pressing `Tab`.
+//! `G` grabs the cursor, `F1` hides the debug UI.
+
+use vulkan_video::{App, AppRunner, Camera, Frame, Input, Mesh, Vertex, VulkanRenderer};
+use vulkan_video::camera::controller::{FlyController, OrbitController};
+use vulkan_video::glam::{Mat4, Vec3};
+
+use winit::event::VirtualKeyCode;
+
+use anyhow::Result;
+
+enum Controller {
+    Orbit(OrbitController),
+    Fly(FlyController),
+}
+
+struct Cube {
+    mesh: Option<Mesh>,
+    camera: Camera,
+    controller: Controller,
+    angle: f32,
+    previous_angle: f32,
+}
+
+impl Cube {
+    fn mesh(renderer: &VulkanRenderer) -> Result<Mesh> {
+        let mut vertices = vec![];
+
+        for i in 0..8 {
+            let corner = |bit: u32| if i & bit != 0 { 0.5 } else { -0.5 };
+
+            vertices.push(Vertex {
+                position: [corner(1), corner(2), corner(4)],
+                color: [corner(1) + 0.5, corner(2) + 0.5, corner(4) + 0.5, 1.0],
+                ..Default::default()
+            });
+        }
+
+        let indices = [
+            0, 2, 3, 3, 1, 0,
+            4, 5, 7, 7, 6, 4,
+            0, 1, 5, 5, 4, 0,
+            2, 6, 7, 7, 3, 2,
+            0, 4, 6, 6, 2, 0,
+            1, 3, 7, 7, 5, 1,
+        ];
+
+        Mesh::new(&renderer.main_device, &vertices, &indices)
+    }
+}
+
+impl App for Cube {
+    fn init(&mut self, renderer: &mut VulkanRenderer) -> Result<()> {
+        self.mesh = Some(Self::mesh(renderer)?);
+
+        let extent = renderer.target.extent();
+        self.camera.aspect = extent.width as f32 / extent.height as f32;
+
+        Ok(())
+    }
+
+    fn update(&mut self, renderer: &mut VulkanRenderer, input: &mut Input, dt: f32) {
+        if input.key_pressed(VirtualKeyCode::Tab) {
+            self.controller = match self.controller {
+                Controller::Orbit(_) => Controller::Fly(FlyController::from_camera(&self.camera)),
+                Controller::Fly(_) => Controller::Orbit(OrbitController::new(Vec3::ZERO, 3.0)),
+            };
+        }
+
+        if input.key_pressed(VirtualKeyCode::G) {
+            if let Some(window) = &renderer.window {
+                let grab = !input.cursor_grabbed;
+
+                if let Err(err) = input.set_cursor_grab(window, grab) {
+                    println!("Couldn't grab the cursor: {}", err);
+                }
+            }
+        }
+
+        match &mut self.controller {
+            Controller::Orbit(orbit) => orbit.update(&mut self.camera, input, dt),
+            Controller::Fly(fly) => fly.update(&mut self.camera, input, dt),
+        }
+    }
+
+    fn fixed_update(&mut self, _renderer: &mut VulkanRenderer, _input: &mut Input, dt: f32) {
+        self.previous_angle = self.angle;
+        self.angle += dt;
+    }
+
+    fn render(&mut self, renderer: &mut VulkanRenderer, frame: &Frame, alpha: f32) -> Result<()> {
+        renderer.set_camera(frame, &self.camera)?;
+
+        let angle = self.previous_angle + (self.angle - self.previous_angle) * alpha;
+
+        if let Some(mesh) = &self.mesh {
+            renderer.draw_mesh(frame, mesh, Mat4::from_rotation_y(angle));
+        }
+
+        Ok(())
+    }
+
+    fn shutdown(&mut self, renderer: &mut VulkanRenderer) {
+        if let Some(mut mesh) = self.mesh.take() {
+            unsafe {
+                renderer.main_device.logical_device.device_wait_idle().unwrap();
+
+                mesh.cleanup(&renderer.main_device);
+            };
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let renderer = VulkanRenderer::new()?;
+
+    let cube = Cube {
+        mesh: None,
+        camera: Camera::perspective(60f32.to_radians(), 1.0, 0.1, 100.0),
+        controller: Controller::Orbit(OrbitController::new(Vec3::ZERO, 3.0)),
+        angle: 0.0,
+        previous_angle: 0.0,
+    };
+
+    AppRunner::new(renderer)?.with_debug_ui()?.run(cube)
+}
@@ -0,0 +1,160 @@
+//! Queues a grid of quads on screen and one off screen, half of the first behind a wall, without a window. Checks
+//! that the GPU culls the grid that's off screen, then the quads behind the wall once there's a depth pyramid,
+//! and that neither changes the picture.
+//!
+//! Runs as part of `cargo test`, so it needs a Vulkan device but no display.
+
+mod common;
+
+use common::{check_golden, quad};
+
+use vulkan_video::{VulkanRenderer, Camera, InstanceData, Mesh, WorldLight, golden, vk};
+use vulkan_video::renderer::culling::CullingSettings;
+use vulkan_video::renderer::material::RendererMaterials;
+use vulkan_video::scene::{LightKind, Material};
+use vulkan_video::glam::{Mat4, Vec3, Vec4};
+
+use anyhow::Result;
+
+const EXTENT: vk::Extent2D = vk::Extent2D { width: 64, height: 64 };
+
+/// Quads along each side of a grid.
+const GRID: u32 = 10;
+
+struct Scene {
+    quad: Mesh,
+    materials: RendererMaterials,
+    camera: Camera,
+    sun: WorldLight,
+}
+
+impl Scene {
+    /// Renders a frame and returns its pixels, with the slot it was rendered in.
+    fn render(&self, renderer: &mut VulkanRenderer) -> Result<(Vec<u8>, usize)> {
+        let frame = renderer.begin_frame()?;
+
+        let slot = frame.slot;
+
+        renderer.set_camera(&frame, &self.camera)?;
+        renderer.set_lights(&frame, &[self.sun])?;
+
+        // the wall, over the left half and in front of the grid:
+        let wall = Mat4::from_translation(Vec3::new(-0.5, 0.0, 1.0)) * Mat4::from_scale(Vec3::new(1.0, 2.0, 1.0));
+
+        renderer.draw_material_mesh(&frame, &self.quad, wall, &self.materials, Some(0));
+
+        // a red grid that fills the screen, and a blue one to the right of it:
+        let cell = 2.0 / GRID as f32;
+
+        for (offset, color) in [(0.0, Vec4::new(1.0, 0.0, 0.0, 1.0)), (4.0, Vec4::new(0.0, 0.0, 1.0, 1.0))] {
+            for y in 0..GRID {
+                for x in 0..GRID {
+                    let position = Vec3::new(
+                        offset - 1.0 + (x as f32 + 0.5) * cell,
+                        -1.0 + (y as f32 + 0.5) * cell,
+                        0.0,
+                    );
+
+                    let model = Mat4::from_translation(position) * Mat4::from_scale(Vec3::splat(cell));
+
+                    renderer.queue_material_mesh(&self.quad, InstanceData::new(model, color), &self.materials, Some(0));
+                }
+            }
+        }
+
+        renderer.end_frame(frame)?;
+
+        Ok((renderer.read_pixels()?, slot))
+    }
+}
+
+fn main() -> Result<()> {
+    let mut renderer = VulkanRenderer::headless_with_config(EXTENT, golden::config()?)?;
+
+    renderer.set_culling(CullingSettings {
+        frustum: true,
+        occlusion: true,
+    });
+
+    let white = Material {
+        metallic_factor: 0.0,
+        ..Default::default()
+    };
+
+    let materials = RendererMaterials::new(
+        &renderer.main_device,
+        &renderer.command_pools,
+        renderer.material_pipelines.set_layout,
+        &[white],
+        &[],
+    )?;
+
+    let mut camera = Camera::orthographic(2.0, 1.0, 0.1, 10.0);
+    camera.position = Vec3::new(0.0, 0.0, 2.0);
+    camera.look_at(Vec3::ZERO, Vec3::Y);
+
+    let mut scene = Scene {
+        quad: quad(&renderer, [-0.5; 2], [0.5; 2])?,
+        materials,
+        camera,
+        sun: WorldLight {
+            kind: LightKind::Directional,
+            position: Vec3::ZERO,
+            direction: -Vec3::Z,
+            color: Vec3::ONE,
+            intensity: 3.0,
+            range: None,
+            casts_shadows: false,
+        },
+    };
+
+    let total = 2 * GRID * GRID;
+
+    // there's no depth pyramid yet, so only the grid off screen is culled:
+    let (first, slot) = scene.render(&mut renderer)?;
+
+    let stats = renderer.culling.read_stats(slot)?;
+
+    println!("culling: first frame {:?}", stats);
+
+    assert_eq!(stats.tested, total, "not every instance was tested");
+    assert_eq!(stats.frustum_culled, GRID * GRID, "the grid off screen wasn't culled");
+    assert_eq!(stats.occlusion_culled, 0, "instances were culled against a pyramid that doesn't exist yet");
+    assert_eq!(stats.drawn, total - stats.frustum_culled, "the counts don't add up");
+
+    // now the quads behind the wall are too, apart from the ones that reach around it in the pyramid:
+    let (second, slot) = scene.render(&mut renderer)?;
+
+    let stats = renderer.culling.read_stats(slot)?;
+
+    println!("culling: second frame {:?}", stats);
+
+    assert_eq!(stats.frustum_culled, GRID * GRID, "the grid off screen wasn't culled");
+    assert!(stats.occlusion_culled > 0, "nothing behind the wall was culled");
+    assert!(stats.occlusion_culled <= GRID * GRID / 2, "more was culled than the wall hides");
+    assert_eq!(stats.drawn, total - stats.frustum_culled - stats.occlusion_culled, "the counts don't add up");
+
+    // culling mustn't change what's seen:
+    let differing = first.iter()
+        .zip(&second)
+        .filter(|(a, b)| a.abs_diff(**b) > 1)
+        .count();
+
+    assert_eq!(differing, 0, "culling changed the picture");
+
+    check_golden("culling", EXTENT, &second)?;
+
+    // and the right half still shows the grid:
+    let i = ((32 * EXTENT.width + 48) * 4) as usize;
+
+    assert!(second[i] > 64 && second[i + 2] < 16, "the grid isn't on the right half");
+
+    unsafe {
+        renderer.main_device.logical_device.device_wait_idle()?;
+
+        scene.materials.cleanup(&renderer.main_device);
+        scene.quad.cleanup(&renderer.main_device);
+    };
+
+    Ok(())
+}
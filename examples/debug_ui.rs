@@ -0,0 +1,112 @@
+//! The debug UI drawn over the triangle without a window, checked against the same frame without it.
+//!
+//! A window of the example's own is drawn in a corner with the built-in panels hidden, and it has to cover that
+//! corner and nothing else. The screenshot of that frame is taken before the overlay, so it mustn't have the UI in
+//! it, and the frame with it is checked against a golden image. Then the built-in panels are drawn for a few frames.
+//! Runs as part of `cargo test`, so it needs a Vulkan device but no display.
+//!
+//! `AppRunner::with_debug_ui` shows the same UI in a window, `F1` hides it.
+
+mod common;
+
+use common::{check_golden};
+
+use vulkan_video::{DebugUi, VulkanRenderer, golden, vk};
+use vulkan_video::debug_ui::panels::Panels;
+use vulkan_video::imgui::Condition;
+
+use anyhow::{Context, Result};
+
+use std::time::Duration;
+
+const EXTENT: vk::Extent2D = vk::Extent2D { width: 128, height: 128 };
+
+/// Where the example's window goes, in pixels.
+const WINDOW: [u32; 4] = [8, 8, 72, 56];
+
+fn draw(renderer: &mut VulkanRenderer, debug_ui: &mut DebugUi) -> Result<Vec<u8>> {
+    debug_ui.prepare(renderer, Duration::from_millis(16), |ui, _| {
+        ui.window("Debug UI")
+            .position([WINDOW[0] as f32, WINDOW[1] as f32], Condition::Always)
+            .size([(WINDOW[2] - WINDOW[0]) as f32, (WINDOW[3] - WINDOW[1]) as f32], Condition::Always)
+            .build(|| ui.text("hello"));
+    });
+
+    let frame = renderer.begin_frame()?;
+
+    renderer.draw_triangle(&frame);
+
+    debug_ui.draw(renderer, &frame)?;
+
+    renderer.end_frame(frame)?;
+
+    renderer.read_pixels()
+}
+
+fn inside(x: u32, y: u32) -> bool {
+    x >= WINDOW[0] && x < WINDOW[2] && y >= WINDOW[1] && y < WINDOW[3]
+}
+
+fn main() -> Result<()> {
+    let mut renderer = VulkanRenderer::headless_with_config(EXTENT, golden::config()?)?;
+
+    let mut debug_ui = DebugUi::new(&mut renderer)?;
+
+    debug_ui.panels = Panels {
+        frame_timing: false,
+        gpu_memory: false,
+        render_settings: false,
+    };
+
+    // the same frame without the UI:
+    debug_ui.visible = false;
+
+    let without = draw(&mut renderer, &mut debug_ui)?;
+
+    debug_ui.visible = true;
+
+    let path = renderer.capture_screenshot(std::env::temp_dir().join("vulkan-video-debug-ui").join("frame.png"))?;
+
+    let with = draw(&mut renderer, &mut debug_ui)?;
+
+    renderer.finish_screenshots()?;
+
+    let mut covered = 0;
+
+    for (index, (got, expected)) in with.chunks_exact(4).zip(without.chunks_exact(4)).enumerate() {
+        let (x, y) = (index as u32 % EXTENT.width, index as u32 / EXTENT.width);
+
+        if inside(x, y) {
+            covered += (got != expected) as usize;
+        } else {
+            assert_eq!(got, expected, "the UI was drawn outside of its window at {}, {}", x, y);
+        }
+    }
+
+    let area = ((WINDOW[2] - WINDOW[0]) * (WINDOW[3] - WINDOW[1])) as usize;
+
+    // the window's background is see-through, but not so much that the triangle's pixels are left as they were:
+    assert!(covered > area / 2, "the UI's window covers only {} of its {} pixels", covered, area);
+
+    // the screenshot was taken before the UI went over the frame:
+    let screenshot = image::open(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?
+        .to_rgba8();
+
+    let background = screenshot.get_pixel(WINDOW[0] + 2, WINDOW[1] + 2).0;
+
+    assert_eq!(background, screenshot.get_pixel(0, 0).0, "the screenshot has the UI in it");
+
+    check_golden("debug_ui", EXTENT, &with)?;
+
+    // the built-in panels, with everything they read from the renderer:
+    debug_ui.panels = Panels::default();
+
+    for _ in 0..3 {
+        draw(&mut renderer, &mut debug_ui)?;
+    }
+
+    println!("debug ui: {} of {} pixels covered", covered, area);
+
+    Ok(())
+}
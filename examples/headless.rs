@@ -0,0 +1,55 @@
+//! Renders the triangle without a window and checks the pixels that come back, then again with MSAA.
+//!
+//! Runs as part of `cargo test`, so it needs a Vulkan device but no display.
+
+mod common;
+
+use common::{check_golden, pixel};
+
+use vulkan_video::{RendererConfig, VulkanRenderer, golden, vk};
+
+use anyhow::Result;
+
+const EXTENT: vk::Extent2D = vk::Extent2D { width: 64, height: 64 };
+
+fn main() -> Result<()> {
+    let mut renderer = VulkanRenderer::headless_with_config(EXTENT, golden::config()?)?;
+
+    renderer.draw_frame()?;
+
+    let pixels = renderer.read_pixels()?;
+
+    check_golden("triangle", EXTENT, &pixels)?;
+
+    assert_eq!(pixels.len(), (EXTENT.width * EXTENT.height * 4) as usize);
+
+    // the triangle covers the upper middle, the corners keep the clear color:
+    let inside = pixel(&pixels, EXTENT, EXTENT.width / 2, EXTENT.height * 3 / 8);
+    let outside = pixel(&pixels, EXTENT, 0, 0);
+
+    assert_ne!(&inside[..3], &[0, 0, 0], "triangle wasn't drawn");
+    assert_eq!(outside, [0, 0, 0, 255], "corner isn't the clear color");
+
+    println!("headless: inside {:?}, outside {:?}", inside, outside);
+
+    drop(renderer);
+
+    // resolved, only the edges differ:
+    let config = RendererConfig {
+        samples: 4,
+        ..golden::config()?
+    };
+
+    let mut renderer = VulkanRenderer::headless_with_config(EXTENT, config)?;
+
+    renderer.draw_frame()?;
+
+    let multisampled = renderer.read_pixels()?;
+
+    check_golden("triangle_msaa", EXTENT, &multisampled)?;
+
+    assert_eq!(pixel(&multisampled, EXTENT, EXTENT.width / 2, EXTENT.height * 3 / 8), inside, "MSAA changed the inside");
+    assert_eq!(pixel(&multisampled, EXTENT, 0, 0), outside, "MSAA changed the clear color");
+
+    Ok(())
+}
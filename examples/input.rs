@@ -0,0 +1,151 @@
+//! Feeds synthetic key, mouse and focus events to `Input` and checks what it makes of them frame by frame, then
+//! round-trips action bindings through TOML.
+//!
+//! Doesn't need a window or a Vulkan device. Runs as part of `cargo test`.
+
+use vulkan_video::input::Input;
+use vulkan_video::input::actions::{ActionMap, Binding};
+
+use winit::event::{ElementState, MouseButton, VirtualKeyCode};
+
+use anyhow::Result;
+
+const ACTIONS: &str = r#"
+[actions]
+jump = [{ key = "Space" }]
+fire = [{ mouse = "Left" }, { key = "LControl" }]
+"#;
+
+fn main() -> Result<()> {
+    let mut input = Input::with_actions(ActionMap::from_toml(ACTIONS)?);
+
+    // a key going down is pressed and held for the frame it went down in:
+    input.key(VirtualKeyCode::W, ElementState::Pressed);
+
+    assert!(input.key_pressed(VirtualKeyCode::W));
+    assert!(input.key_held(VirtualKeyCode::W));
+    assert!(!input.key_released(VirtualKeyCode::W));
+
+    input.end_frame();
+
+    // only held after that, and repeats of it aren't pressed again:
+    input.key(VirtualKeyCode::W, ElementState::Pressed);
+
+    assert!(!input.key_pressed(VirtualKeyCode::W));
+    assert!(input.key_held(VirtualKeyCode::W));
+
+    input.end_frame();
+
+    input.key(VirtualKeyCode::W, ElementState::Released);
+
+    assert!(input.key_released(VirtualKeyCode::W));
+    assert!(!input.key_held(VirtualKeyCode::W));
+
+    input.end_frame();
+
+    assert!(!input.key_released(VirtualKeyCode::W));
+
+    // a click within one frame is both pressed and released, and isn't held:
+    input.mouse_button(MouseButton::Left, ElementState::Pressed);
+    input.mouse_button(MouseButton::Left, ElementState::Released);
+
+    assert!(input.button_pressed(MouseButton::Left));
+    assert!(input.button_released(MouseButton::Left));
+    assert!(!input.button_held(MouseButton::Left));
+
+    // releasing what was never pressed is ignored:
+    input.mouse_button(MouseButton::Right, ElementState::Released);
+
+    assert!(!input.button_released(MouseButton::Right));
+
+    input.end_frame();
+
+    assert!(!input.button_pressed(MouseButton::Left));
+    assert!(!input.button_released(MouseButton::Left));
+
+    // motion and scrolling add up within a frame and start over with the next one:
+    input.cursor_moved(10.0, 20.0);
+    input.mouse_motion(3.0, -1.0);
+    input.mouse_motion(2.0, -1.0);
+    input.scrolled(0.0, 1.0);
+    input.scrolled(0.5, 2.0);
+    input.character('a');
+    input.character('\u{8}');
+
+    assert_eq!(input.mouse_delta, (5.0, -2.0));
+    assert_eq!(input.scroll, (0.5, 3.0));
+    assert_eq!(input.text, "a", "control characters aren't text");
+
+    input.end_frame();
+
+    assert_eq!(input.mouse_delta, (0.0, 0.0));
+    assert_eq!(input.scroll, (0.0, 0.0));
+    assert!(input.text.is_empty());
+    assert_eq!(input.mouse_position, Some((10.0, 20.0)), "the cursor stays where it moved to");
+
+    // losing focus releases everything that's held, its releases never arrive:
+    input.key(VirtualKeyCode::Space, ElementState::Pressed);
+    input.mouse_button(MouseButton::Middle, ElementState::Pressed);
+
+    input.end_frame();
+
+    input.focus(false);
+
+    assert!(!input.focused);
+    assert!(!input.key_held(VirtualKeyCode::Space));
+    assert!(input.key_released(VirtualKeyCode::Space));
+    assert!(!input.button_held(MouseButton::Middle));
+    assert!(input.button_released(MouseButton::Middle));
+
+    input.end_frame();
+
+    input.focus(true);
+
+    assert!(input.focused);
+    assert!(!input.key_released(VirtualKeyCode::Space));
+
+    // actions follow any of their bindings:
+    input.key(VirtualKeyCode::LControl, ElementState::Pressed);
+
+    assert!(input.action_pressed("fire"));
+    assert!(input.action_held("fire"));
+    assert!(!input.action_pressed("jump"));
+    assert!(!input.action_held("nothing bound"));
+
+    input.end_frame();
+
+    input.mouse_button(MouseButton::Left, ElementState::Pressed);
+    input.key(VirtualKeyCode::LControl, ElementState::Released);
+
+    assert!(input.action_pressed("fire"));
+    assert!(input.action_released("fire"));
+    assert!(input.action_held("fire"), "the mouse button still holds it");
+
+    // bindings come back from TOML as they went in:
+    let mut actions = input.actions.clone();
+
+    actions.bind("jump", Binding::Mouse(MouseButton::Right));
+    actions.bind("jump", Binding::Mouse(MouseButton::Right));
+    actions.unbind("fire", Binding::Key(VirtualKeyCode::LControl));
+    actions.bind("crouch", Binding::Key(VirtualKeyCode::C));
+    actions.bind("crouch", Binding::Mouse(MouseButton::Other(4)));
+
+    assert_eq!(actions.bindings("jump"), &[Binding::Key(VirtualKeyCode::Space), Binding::Mouse(MouseButton::Right)]);
+    assert_eq!(actions.bindings("fire"), &[Binding::Mouse(MouseButton::Left)]);
+
+    let toml = actions.to_toml()?;
+
+    assert_eq!(ActionMap::from_toml(&toml)?, actions, "{}", toml);
+
+    assert!(ActionMap::from_toml("[actions]\njump = [{ key = \"NotAKey\" }]").is_err());
+    assert!(ActionMap::from_toml("[actions]\njump = [{ key = \"Space\", mouse = \"Left\" }]").is_err());
+    assert!(ActionMap::from_toml("[actions]\njump = [{}]").is_err());
+
+    let mut names: Vec<&str> = actions.actions().collect();
+
+    names.sort();
+
+    println!("input: actions {:?} round-tripped through TOML", names);
+
+    Ok(())
+}
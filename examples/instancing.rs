@@ -0,0 +1,245 @@
+//! Draws ten thousand queued quads that get merged into one instanced draw, then the same quads from a buffer of
+//! instances, directly and through indirect commands, without a window and checks each of them.
+//!
+//! Runs as part of `cargo test`, so it needs a Vulkan device but no display.
+
+mod common;
+
+use common::{check_golden, dominant, pixel, quad};
+
+use vulkan_video::{VulkanRenderer, Camera, InstanceData, RendererBuffer, WorldLight, golden, vk};
+use vulkan_video::renderer::hdr::{Tonemapper, Tonemapping};
+use vulkan_video::renderer::material::RendererMaterials;
+use vulkan_video::renderer::mesh::IndirectCommands;
+use vulkan_video::scene::{LightKind, Material};
+use vulkan_video::glam::{Mat4, Vec3, Vec4};
+
+use anyhow::Result;
+
+const EXTENT: vk::Extent2D = vk::Extent2D { width: 64, height: 64 };
+
+/// Quads along each side of the grid.
+const GRID: u32 = 100;
+
+const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+const GREEN: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
+const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+
+/// A half of the screen, `-1.0` for the left one and `1.0` for the right one.
+fn half(side: f32, color: [f32; 4]) -> InstanceData {
+    let model = Mat4::from_translation(Vec3::new(side * 0.5, 0.0, 0.0)) * Mat4::from_scale(Vec3::new(1.0, 2.0, 1.0));
+
+    InstanceData::new(model, Vec4::from(color))
+}
+
+fn main() -> Result<()> {
+    let mut renderer = VulkanRenderer::headless_with_config(EXTENT, golden::config()?)?;
+
+    // the checks are on which channel is brightest, so the colors can be as bright as they like:
+    renderer.set_tonemapping(Tonemapping {
+        tonemapper: Tonemapper::None,
+        ..Default::default()
+    });
+
+    let mut quad = quad(&renderer, [-0.5; 2], [0.5; 2])?;
+
+    let white = Material {
+        metallic_factor: 0.0,
+        ..Default::default()
+    };
+
+    let mut materials = RendererMaterials::new(
+        &renderer.main_device,
+        &renderer.command_pools,
+        renderer.material_pipelines.set_layout,
+        &[white],
+        &[],
+    )?;
+
+    let sun = WorldLight {
+        kind: LightKind::Directional,
+        position: Vec3::ZERO,
+        direction: -Vec3::Z,
+        color: Vec3::ONE,
+        intensity: 3.0,
+        range: None,
+        casts_shadows: false,
+    };
+
+    let mut camera = Camera::orthographic(2.0, 1.0, 0.1, 10.0);
+    camera.position = Vec3::new(0.0, 0.0, 2.0);
+    camera.look_at(Vec3::ZERO, Vec3::Y);
+
+    // a grid of queued quads, red on the left and blue on the right:
+    let cell = 2.0 / GRID as f32;
+
+    let frame = renderer.begin_frame()?;
+
+    renderer.set_camera(&frame, &camera)?;
+    renderer.set_lights(&frame, &[sun])?;
+
+    for y in 0..GRID {
+        for x in 0..GRID {
+            let position = Vec3::new(
+                -1.0 + (x as f32 + 0.5) * cell,
+                -1.0 + (y as f32 + 0.5) * cell,
+                0.0,
+            );
+
+            let model = Mat4::from_translation(position) * Mat4::from_scale(Vec3::splat(cell));
+            let color = if x < GRID / 2 { RED } else { BLUE };
+
+            renderer.queue_material_mesh(&quad, InstanceData::new(model, Vec4::from(color)), &materials, Some(0));
+        }
+    }
+
+    renderer.end_frame(frame)?;
+
+    let stats = renderer.batches.stats;
+
+    println!("instancing: {} queued draws in {} batches", stats.draws, stats.batches);
+
+    assert_eq!(stats.draws, (GRID * GRID) as usize, "not every draw was queued");
+    assert_eq!(stats.batches, 1, "the queued draws weren't merged");
+
+    let pixels = renderer.read_pixels()?;
+
+    check_golden("instancing_grid", EXTENT, &pixels)?;
+
+    let left = pixel(&pixels, EXTENT, 16, 32);
+    let right = pixel(&pixels, EXTENT, 48, 32);
+
+    println!("instancing: queued left {:?}, right {:?}", left, right);
+
+    assert_eq!(dominant(left), Some(0), "the left of the grid isn't red");
+    assert_eq!(dominant(right), Some(2), "the right of the grid isn't blue");
+
+    // two halves from a buffer of instances, green on the left and red on the right:
+    let mut instances = RendererBuffer::with_data(
+        &renderer.main_device,
+        "halves",
+        vk::BufferUsageFlags::VERTEX_BUFFER,
+        &[half(-1.0, GREEN), half(1.0, RED)],
+    )?;
+
+    let frame = renderer.begin_frame()?;
+
+    renderer.set_camera(&frame, &camera)?;
+    renderer.set_lights(&frame, &[sun])?;
+
+    renderer.draw_material_mesh_instanced(&frame, &quad, &materials, Some(0), &instances, 0..2);
+
+    renderer.end_frame(frame)?;
+
+    let pixels = renderer.read_pixels()?;
+
+    check_golden("instancing_halves", EXTENT, &pixels)?;
+
+    let left = pixel(&pixels, EXTENT, 16, 32);
+    let right = pixel(&pixels, EXTENT, 48, 32);
+
+    println!("instancing: instanced left {:?}, right {:?}", left, right);
+
+    assert_eq!(dominant(left), Some(1), "the instanced left half isn't green");
+    assert_eq!(dominant(right), Some(0), "the instanced right half isn't red");
+
+    // the same from indirect commands, one per half where they can start at an instance:
+    let half_command = |first_instance: u32, instance_count: u32| vk::DrawIndexedIndirectCommand {
+        index_count: quad.index_count,
+        instance_count,
+        first_index: 0,
+        vertex_offset: 0,
+        first_instance,
+    };
+
+    let first_instance = renderer.main_device.features.draw_indirect_first_instance == vk::TRUE;
+
+    let commands = if first_instance {
+        vec![half_command(0, 1), half_command(1, 1)]
+    } else {
+        vec![half_command(0, 2)]
+    };
+
+    let mut command_buffer = RendererBuffer::with_data(
+        &renderer.main_device,
+        "indirect commands",
+        vk::BufferUsageFlags::INDIRECT_BUFFER,
+        &commands,
+    )?;
+
+    let frame = renderer.begin_frame()?;
+
+    renderer.set_camera(&frame, &camera)?;
+    renderer.set_lights(&frame, &[sun])?;
+
+    renderer.draw_material_mesh_indirect(&frame, &quad, &materials, Some(0), &instances, &IndirectCommands {
+        buffer: &command_buffer,
+        offset: 0,
+        draw_count: commands.len() as u32,
+        count: None,
+    })?;
+
+    renderer.end_frame(frame)?;
+
+    let pixels = renderer.read_pixels()?;
+
+    check_golden("instancing_halves", EXTENT, &pixels)?;
+
+    let left = pixel(&pixels, EXTENT, 16, 32);
+    let right = pixel(&pixels, EXTENT, 48, 32);
+
+    println!("instancing: indirect left {:?}, right {:?}", left, right);
+
+    assert_eq!(dominant(left), Some(1), "the indirect left half isn't green");
+    assert_eq!(dominant(right), Some(0), "the indirect right half isn't red");
+
+    // and with the count in a buffer, which leaves out the right half:
+    let mut count_buffer = RendererBuffer::with_data(
+        &renderer.main_device,
+        "indirect count",
+        vk::BufferUsageFlags::INDIRECT_BUFFER,
+        &[1u32],
+    )?;
+
+    if renderer.main_device.draw_indirect_count.is_some() && first_instance {
+        let frame = renderer.begin_frame()?;
+
+        renderer.set_camera(&frame, &camera)?;
+        renderer.set_lights(&frame, &[sun])?;
+
+        renderer.draw_material_mesh_indirect(&frame, &quad, &materials, Some(0), &instances, &IndirectCommands {
+            buffer: &command_buffer,
+            offset: 0,
+            draw_count: commands.len() as u32,
+            count: Some((&count_buffer, 0)),
+        })?;
+
+        renderer.end_frame(frame)?;
+
+        let pixels = renderer.read_pixels()?;
+
+        check_golden("instancing_counted", EXTENT, &pixels)?;
+
+        let left = pixel(&pixels, EXTENT, 16, 32);
+        let right = pixel(&pixels, EXTENT, 48, 32);
+
+        println!("instancing: counted left {:?}, right {:?}", left, right);
+
+        assert_eq!(dominant(left), Some(1), "the counted left half isn't green");
+        assert_eq!(dominant(right), None, "more commands were drawn than counted");
+    } else {
+        println!("instancing: no VK_KHR_draw_indirect_count, skipping the counted draw");
+    }
+
+    unsafe {
+        renderer.main_device.logical_device.device_wait_idle()?;
+
+        count_buffer.cleanup(&renderer.main_device);
+        command_buffer.cleanup(&renderer.main_device);
+        instances.cleanup(&renderer.main_device);
+        materials.cleanup(&renderer.main_device);
+        quad.cleanup(&renderer.main_device);
+    };
+
+    Ok(())
+}
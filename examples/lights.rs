@@ -0,0 +1,115 @@
+//! Lights a white wall with 200 small point lights, red ones on the left and green ones on the right,
+//! without a window and checks that each stays in its own corner.
+//!
+//! Runs as part of `cargo test`, so it needs a Vulkan device but no display.
+
+mod common;
+
+use common::{check_golden, pixel};
+
+use vulkan_video::{VulkanRenderer, Camera, Mesh, Vertex, WorldLight, golden, vk};
+use vulkan_video::renderer::hdr::{Tonemapper, Tonemapping};
+use vulkan_video::renderer::material::RendererMaterials;
+use vulkan_video::scene::{LightKind, Material};
+use vulkan_video::glam::{Mat4, Vec3};
+
+use anyhow::Result;
+
+const EXTENT: vk::Extent2D = vk::Extent2D { width: 64, height: 64 };
+
+fn main() -> Result<()> {
+    let mut renderer = VulkanRenderer::headless_with_config(EXTENT, golden::config()?)?;
+
+    // the checks are on the colors as shaded, not as tonemapped:
+    renderer.set_tonemapping(Tonemapping {
+        tonemapper: Tonemapper::None,
+        ..Default::default()
+    });
+
+    let corner = |x: f32, y: f32| Vertex {
+        position: [x, y, 0.0],
+        normal: [0.0, 0.0, 1.0],
+        tangent: [1.0, 0.0, 0.0, 1.0],
+        color: [1.0; 4],
+        ..Default::default()
+    };
+
+    let vertices = [corner(-1.0, -1.0), corner(1.0, -1.0), corner(1.0, 1.0), corner(-1.0, 1.0)];
+    let indices = [0, 1, 2, 2, 3, 0];
+
+    let mut wall = Mesh::new(&renderer.main_device, &vertices, &indices)?;
+
+    let white = Material {
+        metallic_factor: 0.0,
+        ..Default::default()
+    };
+
+    let mut materials = RendererMaterials::new(
+        &renderer.main_device,
+        &renderer.command_pools,
+        renderer.material_pipelines.set_layout,
+        &[white],
+        &[],
+    )?;
+
+    // a 20 by 10 grid over the bottom half of the wall, hovering just in front of it:
+    let mut lights = Vec::new();
+
+    for i in 0..20 {
+        for j in 0..10 {
+            let x = -0.95 + i as f32 * 0.1;
+            let y = -0.95 + j as f32 * 0.1;
+
+            lights.push(WorldLight {
+                kind: LightKind::Point,
+                position: Vec3::new(x, y, 0.1),
+                direction: Vec3::ZERO,
+                color: if x < 0.0 { Vec3::X } else { Vec3::Y },
+                intensity: 0.05,
+                range: Some(0.25),
+                casts_shadows: false,
+            });
+        }
+    }
+
+    let mut camera = Camera::orthographic(2.0, 1.0, 0.1, 10.0);
+    camera.position = Vec3::new(0.0, 0.0, 2.0);
+    camera.look_at(Vec3::ZERO, Vec3::Y);
+
+    let frame = renderer.begin_frame()?;
+
+    renderer.set_camera(&frame, &camera)?;
+    renderer.set_lights(&frame, &lights)?;
+
+    renderer.begin_label(&frame, "wall");
+
+    renderer.draw_material_mesh(&frame, &wall, Mat4::IDENTITY, &materials, Some(0));
+
+    renderer.end_label(&frame);
+
+    renderer.end_frame(frame)?;
+
+    let pixels = renderer.read_pixels()?;
+
+    check_golden("lights", EXTENT, &pixels)?;
+
+    let left = pixel(&pixels, EXTENT, EXTENT.width / 4, EXTENT.height * 3 / 4);
+    let right = pixel(&pixels, EXTENT, EXTENT.width * 3 / 4, EXTENT.height * 3 / 4);
+    let top = pixel(&pixels, EXTENT, EXTENT.width / 2, EXTENT.height / 8);
+
+    println!("lights: left {:?}, right {:?}, top {:?}", left, right, top);
+
+    // every light is out of range of the other side and of the top of the wall:
+    assert!(left[0] > 200 && left[1] < 16, "left side isn't lit red");
+    assert!(right[1] > 200 && right[0] < 16, "right side isn't lit green");
+    assert!(top[0] < 16 && top[1] < 16, "top of the wall is lit");
+
+    unsafe {
+        renderer.main_device.logical_device.device_wait_idle()?;
+
+        materials.cleanup(&renderer.main_device);
+        wall.cleanup(&renderer.main_device);
+    };
+
+    Ok(())
+}
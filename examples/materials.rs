@@ -0,0 +1,129 @@
+//! Draws three quads with opaque, masked and blended PBR materials without a window and checks the result.
+//!
+//! Runs as part of `cargo test`, so it needs a Vulkan device but no display.
+
+mod common;
+
+use common::{check_golden, pixel};
+
+use vulkan_video::{VulkanRenderer, Camera, Scene, SceneResources, Vertex, golden, vk};
+use vulkan_video::renderer::hdr::{Tonemapper, Tonemapping};
+use vulkan_video::scene::{AlphaMode, Material, MeshData, Node, Primitive};
+use vulkan_video::glam::{Mat4, Vec3, Vec4};
+
+use anyhow::Result;
+
+const EXTENT: vk::Extent2D = vk::Extent2D { width: 64, height: 64 };
+
+fn quad(material: usize) -> Primitive {
+    let corner = |x: f32, y: f32| Vertex {
+        position: [x, y, 0.0],
+        normal: [0.0, 0.0, 1.0],
+        tangent: [1.0, 0.0, 0.0, 1.0],
+        uv0: [x + 0.5, 0.5 - y],
+        color: [1.0; 4],
+        ..Default::default()
+    };
+
+    Primitive {
+        vertices: vec![corner(-0.25, -0.25), corner(0.25, -0.25), corner(0.25, 0.25), corner(-0.25, 0.25)],
+        indices: vec![0, 1, 2, 2, 3, 0],
+        material: Some(material),
+    }
+}
+
+fn main() -> Result<()> {
+    // black surfaces that glow, so only the emissive color and alpha matter:
+    let glowing = |name: &str, emissive: Vec3, alpha: f32, alpha_mode: AlphaMode| Material {
+        name: name.to_string(),
+        base_color_factor: Vec4::new(0.0, 0.0, 0.0, alpha),
+        metallic_factor: 0.0,
+        emissive_factor: emissive,
+        alpha_mode,
+        ..Default::default()
+    };
+
+    let materials = vec![
+        glowing("opaque", Vec3::X, 0.2, AlphaMode::Opaque),
+        glowing("mask", Vec3::X, 0.2, AlphaMode::Mask),
+        glowing("blend", Vec3::Y, 0.5, AlphaMode::Blend),
+    ];
+
+    let nodes = [-0.7, 0.0, 0.7].iter()
+        .enumerate()
+        .map(|(i, &x)| Node {
+            name: materials[i].name.clone(),
+            transform: Mat4::from_translation(Vec3::new(x, 0.0, 0.0)),
+            children: vec![],
+            mesh: Some(i),
+            camera: None,
+            light: None,
+        })
+        .collect();
+
+    let meshes = (0..materials.len())
+        .map(|i| MeshData {
+            name: materials[i].name.clone(),
+            primitives: vec![quad(i)],
+        })
+        .collect();
+
+    let scene = Scene {
+        nodes,
+        roots: vec![0, 1, 2],
+        meshes,
+        materials,
+        ..Default::default()
+    };
+
+    let mut renderer = VulkanRenderer::headless_with_config(EXTENT, golden::config()?)?;
+
+    // the checks are on the colors as shaded, not as tonemapped:
+    renderer.set_tonemapping(Tonemapping {
+        tonemapper: Tonemapper::None,
+        ..Default::default()
+    });
+
+    let mut resources = SceneResources::new(&renderer, &scene)?;
+
+    let mut camera = Camera::orthographic(2.0, 1.0, 0.1, 10.0);
+    camera.position = Vec3::new(0.0, 0.0, 2.0);
+    camera.look_at(Vec3::ZERO, Vec3::Y);
+
+    let frame = renderer.begin_frame()?;
+
+    renderer.set_camera(&frame, &camera)?;
+
+    renderer.begin_label(&frame, "materials");
+
+    resources.draw(&renderer, &frame, &scene, Mat4::IDENTITY);
+
+    renderer.end_label(&frame);
+
+    renderer.end_frame(frame)?;
+
+    let pixels = renderer.read_pixels()?;
+
+    check_golden("materials", EXTENT, &pixels)?;
+
+    let y = EXTENT.height / 2;
+
+    let opaque = pixel(&pixels, EXTENT, 10, y);
+    let mask = pixel(&pixels, EXTENT, 32, y);
+    let blend = pixel(&pixels, EXTENT, 54, y);
+
+    println!("materials: opaque {:?}, mask {:?}, blend {:?}", opaque, mask, blend);
+
+    // opaque ignores alpha, mask throws away what's under the cutoff, blend mixes with the clear color:
+    assert!(opaque[0] > 250 && opaque[1] < 16, "opaque quad isn't red");
+    assert_eq!(mask, [0, 0, 0, 255], "masked quad wasn't discarded");
+    assert!((100..160).contains(&blend[1]) && blend[0] < 16, "blended quad isn't half green");
+
+    unsafe {
+        renderer.main_device.logical_device.device_wait_idle()?;
+
+        resources.cleanup(&renderer.main_device);
+    };
+
+    Ok(())
+}
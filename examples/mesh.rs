@@ -0,0 +1,62 @@
+//! Draws an indexed quad through the mesh pipeline without a window and checks the result.
+//!
+//! Runs as part of `cargo test`, so it needs a Vulkan device but no display.
+
+mod common;
+
+use common::{check_golden};
+
+use vulkan_video::{VulkanRenderer, Camera, Mesh, Vertex, golden, vk};
+use vulkan_video::glam::{Mat4, Vec3};
+
+use anyhow::Result;
+
+const EXTENT: vk::Extent2D = vk::Extent2D { width: 64, height: 64 };
+
+fn main() -> Result<()> {
+    let mut renderer = VulkanRenderer::headless_with_config(EXTENT, golden::config()?)?;
+
+    let vertices = [
+        Vertex { position: [-0.5, -0.5, 0.0], color: [1.0, 0.0, 0.0, 1.0], ..Default::default() },
+        Vertex { position: [0.5, -0.5, 0.0], color: [0.0, 1.0, 0.0, 1.0], ..Default::default() },
+        Vertex { position: [0.5, 0.5, 0.0], color: [0.0, 0.0, 1.0, 1.0], ..Default::default() },
+        Vertex { position: [-0.5, 0.5, 0.0], color: [1.0, 1.0, 1.0, 1.0], ..Default::default() },
+    ];
+
+    let indices = [0, 1, 2, 2, 3, 0];
+
+    let mut mesh = Mesh::new(&renderer.main_device, &vertices, &indices)?;
+
+    let mut camera = Camera::perspective(60f32.to_radians(), 1.0, 0.1, 100.0);
+    camera.position = Vec3::new(0.0, 0.0, 2.0);
+    camera.look_at(Vec3::ZERO, Vec3::Y);
+
+    let frame = renderer.begin_frame()?;
+
+    renderer.set_camera(&frame, &camera)?;
+
+    renderer.begin_label(&frame, "quad");
+
+    renderer.draw_mesh(&frame, &mesh, Mat4::IDENTITY);
+
+    renderer.end_label(&frame);
+
+    renderer.end_frame(frame)?;
+
+    let pixels = renderer.read_pixels()?;
+
+    check_golden("mesh", EXTENT, &pixels)?;
+
+    let center = ((EXTENT.height / 2 * EXTENT.width + EXTENT.width / 2) * 4) as usize;
+
+    assert_ne!(&pixels[center..center + 3], &[0, 0, 0], "quad wasn't drawn");
+    assert_eq!(&pixels[..4], &[0, 0, 0, 255], "corner isn't the clear color");
+
+    unsafe {
+        renderer.main_device.logical_device.device_wait_idle()?;
+
+        mesh.cleanup(&renderer.main_device);
+    };
+
+    Ok(())
+}
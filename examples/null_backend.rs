@@ -0,0 +1,582 @@
+//! Records queued draws, indirect draws, an image upload and a whole frame through the null backend and checks the
+//! commands.
+//!
+//! Doesn't need a Vulkan device, the null backend makes up handles and keeps buffers on the CPU. Runs as part of
+//! `cargo test`.
+
+use vulkan_video::{Camera, Frame, InstanceData, Mesh, RenderTarget, RendererBuffer, RendererImage, RendererPipeline, Vertex, vk};
+use vulkan_video::renderer::backend::{Backend, Command, ImageTransition, NullBackend};
+use vulkan_video::renderer::batches::RendererBatches;
+use vulkan_video::renderer::frame::FramePasses;
+use vulkan_video::renderer::offscreen::RendererOffscreen;
+use vulkan_video::renderer::hdr::RendererHdr;
+use vulkan_video::renderer::post::RendererPostProcess;
+use vulkan_video::renderer::lights::{ClusteredLights, CLUSTER_GRID};
+use vulkan_video::renderer::culling::{CullingSettings, RendererCulling};
+use vulkan_video::renderer::screenshot::RendererScreenshots;
+use vulkan_video::renderer::material::{MaterialKey, MaterialPipelines};
+use vulkan_video::renderer::mesh::IndirectCommands;
+use vulkan_video::renderer::recorder::Recorder;
+use vulkan_video::scene::AlphaMode;
+use vulkan_video::glam::{Mat4, Vec3, Vec4};
+
+use gpu_allocator::vulkan::Allocation;
+
+use std::mem;
+use std::path::Path;
+
+use anyhow::Result;
+
+fn quad(backend: &NullBackend) -> Result<Mesh> {
+    let corner = |x: f32, y: f32| Vertex {
+        position: [x, y, 0.0],
+        normal: [0.0, 0.0, 1.0],
+        ..Default::default()
+    };
+
+    let vertices = [corner(-1.0, -1.0), corner(1.0, -1.0), corner(1.0, 1.0), corner(-1.0, 1.0)];
+    let indices = [0, 1, 2, 2, 3, 0];
+
+    Mesh::new(backend, &vertices, &indices)
+}
+
+fn frame(backend: &NullBackend) -> Frame {
+    Frame {
+        command_buffer: backend.handle(),
+        image_index: 0,
+        slot: 0,
+        extent: vk::Extent2D { width: 64, height: 64 },
+    }
+}
+
+/// Begins, ends and submits one frame into an offscreen target with the passes around the render pass made up,
+/// occlusion culling on and a screenshot asked for, and checks everything the frame recorded.
+fn whole_frame() -> Result<()> {
+    let backend = NullBackend::new();
+
+    let extent = vk::Extent2D { width: 64, height: 64 };
+
+    let pipeline = || RendererPipeline {
+        pipeline: backend.handle(),
+        pipeline_layout: backend.handle(),
+    };
+
+    let image = |format: vk::Format, extent: vk::Extent2D| RendererImage {
+        image: backend.handle(),
+        image_view: backend.handle(),
+        format,
+        extent,
+        depth: 1,
+        layers: 1,
+        mip_levels: 1,
+        samples: vk::SampleCountFlags::TYPE_1,
+        ..Default::default()
+    };
+
+    let target = RenderTarget::Offscreen(RendererOffscreen {
+        color: image(RendererOffscreen::FORMAT, extent),
+        framebuffers: vec![backend.handle()],
+        extent,
+        may_begin_drawing: vec![backend.handle(), backend.handle()],
+        image_count: 1,
+        current_image: 0,
+    });
+
+    let depth = image(vk::Format::D32_SFLOAT, extent);
+
+    let hdr = RendererHdr {
+        framebuffer: backend.handle(),
+        render_pass: backend.handle(),
+        pipeline: pipeline(),
+        descriptor_sets: [backend.handle(), backend.handle()],
+        ..Default::default()
+    };
+
+    // no effects, the frame is tonemapped from the first HDR target:
+    let mut post = RendererPostProcess::default();
+
+    let lights = ClusteredLights {
+        descriptor_sets: vec![backend.handle(), backend.handle()],
+        pipeline: pipeline(),
+        ..Default::default()
+    };
+
+    // a pyramid of one level, half the depth buffer:
+    let mut culling = RendererCulling {
+        settings: CullingSettings {
+            frustum: true,
+            occlusion: true,
+        },
+        pyramid: image(vk::Format::R32_SFLOAT, vk::Extent2D { width: 32, height: 32 }),
+        pyramid_descriptor_sets: vec![backend.handle()],
+        pyramid_pipeline: pipeline(),
+        ..Default::default()
+    };
+
+    culling.begin();
+    culling.set_camera(&Camera::orthographic(2.0, 1.0, 0.1, 10.0));
+
+    let mut screenshots = RendererScreenshots::new(2);
+
+    screenshots.request(&target, Path::new("null_backend"))?;
+
+    let command_buffers = [backend.handle(), backend.handle()];
+    let camera_sets = [backend.handle(), backend.handle()];
+
+    let mut passes = FramePasses {
+        device: &backend,
+        queue: backend.handle(),
+        render_pass: backend.handle(),
+        clear_color: [0.0, 0.0, 0.0, 1.0],
+        reverse_z: false,
+        command_buffers: &command_buffers,
+        camera_sets: &camera_sets,
+        target: &target,
+        depth: &depth,
+        hdr: &hdr,
+        post: &mut post,
+        lights: &lights,
+        culling: &mut culling,
+        text: None,
+        capture: None,
+        screenshots: &mut screenshots,
+        overlay: None,
+    };
+
+    let frame = Frame {
+        command_buffer: backend.handle(),
+        image_index: 0,
+        slot: 0,
+        extent,
+    };
+
+    // what the shadow and culling passes would have recorded:
+    let first: vk::CommandBuffer = backend.handle();
+
+    let command_buffer = passes.begin(frame.slot)?;
+
+    assert_eq!(command_buffer, command_buffers[0], "the frame isn't recorded into its slot's command buffer");
+
+    passes.end(&frame, &[frame.command_buffer])?;
+    passes.submit(&frame, &[first])?;
+
+    assert!(!passes.present(&frame)?, "an offscreen target has nothing to present");
+
+    let render_pass = passes.render_pass;
+
+    drop(passes);
+
+    // push constants are checked by the examples of the passes, here only where they go:
+    let commands: Vec<Command> = backend.commands(command_buffer)
+        .into_iter()
+        .map(|command| match command {
+            Command::PushConstants { layout, stages, offset, .. } => Command::PushConstants {
+                layout,
+                stages,
+                offset,
+                constants: vec![],
+            },
+            command => command
+        })
+        .collect();
+
+    let readback = commands.iter()
+        .find_map(|command| match command {
+            Command::CopyImageToBuffer { buffer, .. } => Some(*buffer),
+            _ => None
+        })
+        .expect("the screenshot wasn't copied");
+
+    let color = target.image(0);
+    let framebuffer = target.framebuffers()[0];
+
+    let transition = |image, old_layout, new_layout| vec![ImageTransition {
+        image,
+        old_layout,
+        new_layout,
+    }];
+
+    let barrier = |src_stage, dst_stage, memory_barriers, images| Command::Barrier {
+        src_stage,
+        dst_stage,
+        memory_barriers,
+        buffers: vec![],
+        images,
+    };
+
+    let push_constants = |pipeline: &RendererPipeline, stages| Command::PushConstants {
+        layout: pipeline.pipeline_layout,
+        stages,
+        offset: 0,
+        constants: vec![],
+    };
+
+    let expected = vec![
+        Command::BeginCommandBuffer {
+            flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+        },
+        // binning the lights:
+        Command::BindPipeline {
+            bind_point: vk::PipelineBindPoint::COMPUTE,
+            pipeline: lights.pipeline.pipeline,
+        },
+        Command::BindDescriptorSets {
+            bind_point: vk::PipelineBindPoint::COMPUTE,
+            layout: lights.pipeline.pipeline_layout,
+            first_set: 0,
+            descriptor_sets: vec![camera_sets[0], lights.descriptor_sets[0]],
+        },
+        Command::Dispatch {
+            x: CLUSTER_GRID[0].div_ceil(4),
+            y: CLUSTER_GRID[1].div_ceil(4),
+            z: CLUSTER_GRID[2].div_ceil(4),
+        },
+        barrier(vk::PipelineStageFlags::COMPUTE_SHADER, vk::PipelineStageFlags::FRAGMENT_SHADER, 1, vec![]),
+        // the render pass, continued by the frame's secondary command buffers:
+        Command::BeginRenderPass {
+            render_pass,
+            framebuffer: hdr.framebuffer,
+            contents: vk::SubpassContents::SECONDARY_COMMAND_BUFFERS,
+        },
+        Command::ExecuteCommands {
+            command_buffers: vec![frame.command_buffer],
+        },
+        Command::EndRenderPass,
+        // the depth pyramid:
+        barrier(
+            vk::PipelineStageFlags::LATE_FRAGMENT_TESTS | vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            0,
+            transition(
+                depth.image,
+                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            ),
+        ),
+        Command::BindPipeline {
+            bind_point: vk::PipelineBindPoint::COMPUTE,
+            pipeline: culling.pyramid_pipeline.pipeline,
+        },
+        Command::BindDescriptorSets {
+            bind_point: vk::PipelineBindPoint::COMPUTE,
+            layout: culling.pyramid_pipeline.pipeline_layout,
+            first_set: 0,
+            descriptor_sets: vec![culling.pyramid_descriptor_sets[0]],
+        },
+        push_constants(&culling.pyramid_pipeline, vk::ShaderStageFlags::COMPUTE),
+        Command::Dispatch {
+            x: 4,
+            y: 4,
+            z: 1,
+        },
+        barrier(vk::PipelineStageFlags::COMPUTE_SHADER, vk::PipelineStageFlags::COMPUTE_SHADER, 1, vec![]),
+        // tonemapping into the target:
+        Command::BeginRenderPass {
+            render_pass: hdr.render_pass,
+            framebuffer,
+            contents: vk::SubpassContents::INLINE,
+        },
+        Command::BindPipeline {
+            bind_point: vk::PipelineBindPoint::GRAPHICS,
+            pipeline: hdr.pipeline.pipeline,
+        },
+        Command::BindDescriptorSets {
+            bind_point: vk::PipelineBindPoint::GRAPHICS,
+            layout: hdr.pipeline.pipeline_layout,
+            first_set: 0,
+            descriptor_sets: vec![hdr.descriptor_sets[0]],
+        },
+        push_constants(&hdr.pipeline, vk::ShaderStageFlags::FRAGMENT),
+        Command::Draw {
+            vertex_count: 3,
+            instance_count: 1,
+            first_vertex: 0,
+            first_instance: 0,
+        },
+        Command::EndRenderPass,
+        // the screenshot:
+        barrier(
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::TRANSFER,
+            0,
+            transition(color, target.final_layout(), vk::ImageLayout::TRANSFER_SRC_OPTIMAL),
+        ),
+        Command::CopyImageToBuffer {
+            image: color,
+            layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            buffer: readback,
+        },
+        barrier(
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::HOST | vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            1,
+            transition(color, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, target.final_layout()),
+        ),
+        Command::EndCommandBuffer,
+    ];
+
+    assert_eq!(commands, expected);
+
+    // the fence is reset right before, a frame that fails earlier leaves it signalled:
+    assert_eq!(backend.queue_commands(), vec![
+        Command::ResetFences {
+            fences: vec![target.may_begin_drawing()[0]],
+        },
+        Command::Submit {
+            wait_semaphores: vec![],
+            wait_stages: vec![],
+            command_buffers: vec![first, command_buffer],
+            signal_semaphores: vec![],
+            fence: target.may_begin_drawing()[0],
+        },
+    ], "the frame wasn't submitted once with its fence reset, after the passes ahead of it");
+
+    unsafe {
+        screenshots.cleanup(&backend);
+    };
+
+    assert_eq!(backend.live_buffers(), 0, "the screenshot's buffer was left behind");
+
+    println!("null_backend: a frame of {} commands recorded as expected", expected.len());
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let backend = NullBackend::new();
+
+    let mut quad = quad(&backend)?;
+    let mut triangle = Mesh::new(&backend, &[Vertex::default(); 3], &[0, 1, 2])?;
+
+    let indices = backend.buffer_data(quad.index_buffer.buffer).unwrap();
+
+    assert_eq!(indices, RendererBuffer::bytes(&[0u32, 1, 2, 2, 3, 0]), "the indices weren't written");
+
+    // draws of two meshes with two materials, queued out of order and one of them blended:
+    let opaque = MaterialKey::ALL[0];
+    let blended = MaterialKey::ALL[4];
+
+    assert_eq!(blended.alpha_mode, AlphaMode::Blend);
+
+    let (first_set, second_set): (vk::DescriptorSet, vk::DescriptorSet) = (backend.handle(), backend.handle());
+
+    let instance = |x: f32| InstanceData::new(Mat4::from_translation(Vec3::new(x, 0.0, 0.0)), Vec4::ONE);
+
+    let mut batches = RendererBatches::new(&backend, 2)?;
+
+    batches.begin();
+
+    batches.queue(&quad, blended, second_set, instance(0.0));
+    batches.queue(&quad, opaque, first_set, instance(1.0));
+    batches.queue(&triangle, opaque, first_set, instance(2.0));
+    batches.queue(&quad, opaque, first_set, instance(3.0));
+    batches.queue_caster(&quad, Mat4::IDENTITY);
+
+    let (merged, casters) = batches.flush(&backend, 0)?;
+
+    println!("null_backend: {:?}", batches.stats);
+
+    assert_eq!(merged.len(), 3, "the quads with the same material weren't merged");
+    assert_eq!(casters.len(), 1);
+    assert_eq!(merged.last().and_then(|batch| batch.material).map(|(key, _)| key), Some(blended), "blended isn't last");
+    assert_eq!(casters[0].instances, 4..5, "casters don't come after the draws");
+
+    // the instances of each batch are next to each other, in the order they were queued:
+    let instance_buffer = batches.instance_buffers[0].buffer;
+
+    let written = backend.buffer_data(instance_buffer).unwrap();
+
+    let quads = merged.iter()
+        .find(|batch| batch.vertex_buffer == quad.vertex_buffer.buffer && batch.instances.len() == 2)
+        .unwrap();
+
+    let stride = mem::size_of::<InstanceData>();
+    let start = quads.instances.start as usize * stride;
+
+    assert_eq!(
+        &written[start..start + 2 * stride],
+        RendererBuffer::bytes(&[instance(1.0), instance(3.0)]),
+        "the merged instances aren't in the order they were queued",
+    );
+
+    // recorded with made up pipelines and per frame sets:
+    let pipeline = |backend: &NullBackend| RendererPipeline {
+        pipeline: backend.handle(),
+        pipeline_layout: backend.handle(),
+    };
+
+    let mesh_pipeline = pipeline(&backend);
+
+    let material_pipelines = MaterialPipelines {
+        set_layout: backend.handle(),
+        pipelines: MaterialKey::ALL.iter().map(|_| pipeline(&backend)).collect(),
+    };
+
+    let frame_sets = |backend: &NullBackend| -> Vec<vk::DescriptorSet> { vec![backend.handle(), backend.handle()] };
+
+    let (camera_sets, light_sets, shadow_sets) = (frame_sets(&backend), frame_sets(&backend), frame_sets(&backend));
+
+    let recorder = Recorder {
+        device: &backend,
+        mesh_pipeline: &mesh_pipeline,
+        material_pipelines: &material_pipelines,
+        camera_sets: &camera_sets,
+        light_sets: &light_sets,
+        shadow_sets: &shadow_sets,
+        single_instance: batches.single_instance.buffer,
+    };
+
+    let frame = frame(&backend);
+
+    for batch in merged.iter().chain(&casters) {
+        recorder.draw_batch(&frame, batch, instance_buffer);
+    }
+
+    let commands = backend.commands(frame.command_buffer);
+
+    // six commands for each batch with a material, nothing for the casters:
+    assert_eq!(commands.len(), 6 * merged.len(), "{:#?}", commands);
+
+    for (batch, commands) in merged.iter().zip(commands.chunks(6)) {
+        let (key, descriptor_set) = batch.material.unwrap();
+        let expected_pipeline = material_pipelines.get(key);
+
+        assert_eq!(commands[0], Command::BindPipeline {
+            bind_point: vk::PipelineBindPoint::GRAPHICS,
+            pipeline: expected_pipeline.pipeline,
+        });
+
+        assert_eq!(commands[1], Command::BindDescriptorSets {
+            bind_point: vk::PipelineBindPoint::GRAPHICS,
+            layout: expected_pipeline.pipeline_layout,
+            first_set: 0,
+            descriptor_sets: vec![camera_sets[0], descriptor_set, light_sets[0], shadow_sets[0]],
+        });
+
+        assert_eq!(commands[2], Command::PushConstants {
+            layout: expected_pipeline.pipeline_layout,
+            stages: vk::ShaderStageFlags::VERTEX,
+            offset: 0,
+            constants: RendererBuffer::bytes(&Mat4::IDENTITY.to_cols_array()).to_vec(),
+        });
+
+        assert_eq!(commands[3], Command::BindVertexBuffers {
+            first_binding: 0,
+            buffers: vec![batch.vertex_buffer, instance_buffer],
+            offsets: vec![0, 0],
+        });
+
+        assert_eq!(commands[5], Command::DrawIndexed {
+            index_count: batch.index_count,
+            instance_count: batch.instances.len() as u32,
+            first_index: 0,
+            vertex_offset: 0,
+            first_instance: batch.instances.start,
+        });
+    }
+
+    // indirect draws, one at a time without multiDrawIndirect:
+    let mut indirect = RendererBuffer::with_data(
+        &backend,
+        "indirect commands",
+        vk::BufferUsageFlags::INDIRECT_BUFFER,
+        &[vk::DrawIndexedIndirectCommand::default(); 3],
+    )?;
+
+    let commands = IndirectCommands {
+        buffer: &indirect,
+        offset: 0,
+        draw_count: 3,
+        count: None,
+    };
+
+    let command_buffer = backend.handle();
+
+    quad.draw_indirect(&backend, command_buffer, instance_buffer, &commands)?;
+
+    let draws = |backend: &NullBackend| -> Vec<Command> {
+        backend.commands(command_buffer)
+            .into_iter()
+            .filter(|command| matches!(command, Command::DrawIndexedIndirect { .. }))
+            .collect()
+    };
+
+    assert_eq!(draws(&backend).len(), 3, "the indirect draws weren't split up without multiDrawIndirect");
+
+    let mut backend_with_features = NullBackend::new();
+    backend_with_features.features.multi_draw_indirect = vk::TRUE;
+
+    quad.draw_indirect(&backend_with_features, command_buffer, instance_buffer, &commands)?;
+
+    assert_eq!(draws(&backend_with_features).len(), 1, "the indirect draws were split up with multiDrawIndirect");
+
+    // and a count from a buffer fails without the extension:
+    let counted = IndirectCommands {
+        count: Some((&indirect, 0)),
+        ..commands
+    };
+
+    assert!(quad.draw_indirect(&backend, command_buffer, instance_buffer, &counted).is_err());
+
+    // an upload leaves the image ready to be sampled:
+    let image = RendererImage {
+        image: backend.handle(),
+        image_view: backend.handle(),
+        allocation: Allocation::default(),
+        format: vk::Format::R8G8B8A8_UNORM,
+        extent: vk::Extent2D { width: 4, height: 4 },
+        depth: 1,
+        layers: 1,
+        mip_levels: 1,
+        samples: vk::SampleCountFlags::TYPE_1,
+    };
+
+    let staging = backend.handle();
+    let upload_command_buffer = backend.handle();
+
+    image.record_upload(&backend, upload_command_buffer, staging);
+
+    let transition = |old_layout, new_layout| vec![ImageTransition {
+        image: image.image,
+        old_layout,
+        new_layout,
+    }];
+
+    assert_eq!(backend.commands(upload_command_buffer), vec![
+        Command::Barrier {
+            src_stage: vk::PipelineStageFlags::TOP_OF_PIPE,
+            dst_stage: vk::PipelineStageFlags::TRANSFER,
+            memory_barriers: 0,
+            buffers: vec![],
+            images: transition(vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL),
+        },
+        Command::CopyBufferToImage {
+            buffer: staging,
+            image: image.image,
+            layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        },
+        Command::Barrier {
+            src_stage: vk::PipelineStageFlags::TRANSFER,
+            dst_stage: vk::PipelineStageFlags::FRAGMENT_SHADER,
+            memory_barriers: 0,
+            buffers: vec![],
+            images: transition(vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+        },
+    ]);
+
+    backend.wait_idle()?;
+
+    unsafe {
+        backend.destroy_buffer(&mut indirect);
+
+        batches.cleanup(&backend);
+        triangle.cleanup(&backend);
+        quad.cleanup(&backend);
+    };
+
+    assert_eq!(backend.live_buffers(), 0, "buffers were left behind");
+
+    println!("null_backend: {} batches and an upload recorded as expected", merged.len());
+
+    whole_frame()
+}
@@ -0,0 +1,113 @@
+//! Loads OBJ data from memory and checks the resulting meshes and materials. Doesn't need a GPU at all.
+//!
+//! Runs as part of `cargo test`.
+
+use vulkan_video::scene::AlphaMode;
+use vulkan_video::scene::obj::{self, Normals};
+use vulkan_video::glam::Vec4;
+
+use anyhow::Result;
+
+const CUBE: &str = "
+mtllib cube.mtl
+o cube
+v -1 -1 -1
+v  1 -1 -1
+v  1  1 -1
+v -1  1 -1
+v -1 -1  1
+v  1 -1  1
+v  1  1  1
+v -1  1  1
+usemtl red
+s off
+f 1 4 3 2
+f 5 6 7 8
+f 1 2 6 5
+usemtl glass
+f 4 8 7 3
+f 1 5 8 4
+f 2 3 7 6
+";
+
+const MATERIALS: &str = "
+newmtl red
+Kd 1 0 0
+
+newmtl glass
+Kd 0.5 0.5 1
+d 0.25
+";
+
+const TEXTURED: &str = "
+o quad
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+f 1/1/1 2/2/1 3/3/1 4/4/1
+";
+
+fn main() -> Result<()> {
+    // flat: every side gets its own 4 corners, shared by its two triangles:
+    let scene = obj::from_str(CUBE, Some(MATERIALS), Normals::SmoothingGroups)?;
+
+    assert_eq!(scene.meshes.len(), 1);
+    assert_eq!(scene.meshes[0].name, "cube");
+
+    let primitives = &scene.meshes[0].primitives;
+
+    assert_eq!(primitives.len(), 2, "one primitive per material");
+
+    for primitive in primitives {
+        assert_eq!(primitive.vertices.len(), 12);
+        assert_eq!(primitive.indices.len(), 18);
+    }
+
+    let normal = primitives[0].vertices[0].normal;
+    assert_eq!(normal, [0.0, 0.0, -1.0], "first face faces -z");
+
+    let red = &scene.materials[primitives[0].material.unwrap()];
+    let glass = &scene.materials[primitives[1].material.unwrap()];
+
+    assert_eq!(red.name, "red");
+    assert_eq!(red.base_color_factor, Vec4::new(1.0, 0.0, 0.0, 1.0));
+    assert_eq!(red.alpha_mode, AlphaMode::Opaque);
+
+    assert_eq!(glass.base_color_factor, Vec4::new(0.5, 0.5, 1.0, 0.25));
+    assert_eq!(glass.alpha_mode, AlphaMode::Blend);
+
+    // smooth: corners are shared between sides, as long as they use the same material:
+    let scene = obj::from_str(CUBE, None, Normals::Smooth)?;
+
+    let vertex_count: usize = scene.meshes[0].primitives.iter()
+        .map(|primitive| primitive.vertices.len())
+        .sum();
+
+    assert_eq!(vertex_count, 8 + 8);
+    assert_eq!(scene.materials.len(), 2, "unknown materials still get a default one");
+
+    // given normals and UVs are kept, with v flipped for Vulkan:
+    let scene = obj::from_str(TEXTURED, None, Normals::SmoothingGroups)?;
+
+    let quad = &scene.meshes[0].primitives[0];
+
+    assert_eq!(quad.vertices.len(), 4);
+    assert_eq!(quad.indices, [0, 1, 2, 0, 2, 3]);
+    assert_eq!(quad.vertices[0].uv0, [0.0, 1.0]);
+    assert!(quad.vertices.iter().all(|vertex| vertex.normal == [0.0, 0.0, 1.0]));
+    assert_eq!(quad.vertices[0].tangent, [1.0, 0.0, 0.0, 1.0]);
+
+    // broken files are errors that point at the line:
+    let broken = obj::from_str("v 0 0 0\nf 1 2 3\n", None, Normals::Flat);
+
+    let message = format!("{:#}", broken.err().expect("out of range indices were accepted"));
+    assert!(message.contains("Line 2"), "unexpected error: {}", message);
+
+    Ok(())
+}
@@ -0,0 +1,139 @@
+//! Records a frame's draws on worker threads into secondary command buffers without a window, with the first
+//! worker finishing last, and checks that they're executed in the order they were handed out.
+//!
+//! Runs as part of `cargo test`, so it needs a Vulkan device but no display.
+
+mod common;
+
+use common::{check_golden, dominant, pixel, quad};
+
+use vulkan_video::{VulkanRenderer, Camera, Frame, InstanceData, WorldLight, golden, vk};
+use vulkan_video::renderer::hdr::{Tonemapper, Tonemapping};
+use vulkan_video::renderer::material::RendererMaterials;
+use vulkan_video::renderer::recorder::Recorder;
+use vulkan_video::scene::{LightKind, Material};
+use vulkan_video::glam::{Mat4, Vec3, Vec4};
+
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Result;
+
+const EXTENT: vk::Extent2D = vk::Extent2D { width: 64, height: 64 };
+
+const RED: usize = 0;
+const GREEN: usize = 1;
+const BLUE: usize = 2;
+
+/// The strip each worker's quad starts at and its material, later ones cover what earlier ones drew.
+const JOBS: [(u32, usize); 3] = [(1, GREEN), (2, BLUE), (3, GREEN)];
+
+/// Covers the screen from the left edge of one of its four strips to the right edge, all at the same depth.
+fn from_strip(strip: u32) -> Mat4 {
+    let left = -1.0 + 0.5 * strip as f32;
+
+    Mat4::from_translation(Vec3::new((left + 1.0) * 0.5, 0.0, 0.0)) * Mat4::from_scale(Vec3::new(1.0 - left, 2.0, 1.0))
+}
+
+fn main() -> Result<()> {
+    let mut renderer = VulkanRenderer::headless_with_config(EXTENT, golden::config()?)?;
+
+    // the checks are on which channel is brightest, so the colors can be as bright as they like:
+    renderer.set_tonemapping(Tonemapping {
+        tonemapper: Tonemapper::None,
+        ..Default::default()
+    });
+
+    let mut quad = quad(&renderer, [-0.5; 2], [0.5; 2])?;
+
+    let material = |color: Vec4| Material {
+        base_color_factor: color,
+        metallic_factor: 0.0,
+        ..Default::default()
+    };
+
+    let mut materials = RendererMaterials::new(
+        &renderer.main_device,
+        &renderer.command_pools,
+        renderer.material_pipelines.set_layout,
+        &[
+            material(Vec4::new(1.0, 0.0, 0.0, 1.0)),
+            material(Vec4::new(0.0, 1.0, 0.0, 1.0)),
+            material(Vec4::new(0.0, 0.0, 1.0, 1.0)),
+        ],
+        &[],
+    )?;
+
+    let sun = WorldLight {
+        kind: LightKind::Directional,
+        position: Vec3::ZERO,
+        direction: -Vec3::Z,
+        color: Vec3::ONE,
+        intensity: 3.0,
+        range: None,
+        casts_shadows: false,
+    };
+
+    let mut camera = Camera::orthographic(2.0, 1.0, 0.1, 10.0);
+    camera.position = Vec3::new(0.0, 0.0, 2.0);
+    camera.look_at(Vec3::ZERO, Vec3::Y);
+
+    // a few frames, so the pools are reused and any ordering that's left to chance gets the chance to show:
+    for _ in 0..8 {
+        let frame = renderer.begin_frame()?;
+
+        renderer.set_camera(&frame, &camera)?;
+        renderer.set_lights(&frame, &[sun])?;
+
+        // drawn on the frame itself, which goes first:
+        renderer.draw_material_mesh(&frame, &quad, from_strip(0), &materials, Some(RED));
+
+        let jobs: Vec<_> = JOBS.iter()
+            .enumerate()
+            .map(|(index, &(strip, color))| {
+                let quad = &quad;
+                let materials = &materials;
+
+                move |recorder: &Recorder, frame: &Frame| {
+                    // the first worker is the last to finish:
+                    if index == 0 {
+                        thread::sleep(Duration::from_millis(10));
+                    }
+
+                    recorder.draw_material_mesh(frame, quad, from_strip(strip), materials, Some(color));
+
+                    Ok(())
+                }
+            })
+            .collect();
+
+        renderer.record_parallel(&frame, jobs)?;
+
+        // queued draws go after every worker's:
+        renderer.queue_material_mesh(&quad, InstanceData::new(from_strip(3), Vec4::ONE), &materials, Some(RED));
+
+        renderer.end_frame(frame)?;
+
+        let pixels = renderer.read_pixels()?;
+
+        // every frame draws the same, whichever worker finishes first:
+        check_golden("parallel", EXTENT, &pixels)?;
+
+        let strips: Vec<Option<usize>> = (0..4)
+            .map(|strip| dominant(pixel(&pixels, EXTENT, strip * 16 + 8, 32)))
+            .collect();
+
+        assert_eq!(strips, [Some(RED), Some(GREEN), Some(BLUE), Some(RED)], "the secondary command buffers ran out of order");
+    }
+
+    println!("parallel: {} worker threads recorded 8 frames in order", JOBS.len());
+
+    unsafe {
+        renderer.main_device.logical_device.device_wait_idle()?;
+
+        materials.cleanup(&renderer.main_device);
+        quad.cleanup(&renderer.main_device);
+    };
+
+    Ok(())
+}
@@ -0,0 +1,187 @@
+//! Draws a glowing left half next to a grey right half through different post-process stacks without a window,
+//! and checks what each of them does.
+//!
+//! Runs as part of `cargo test`, so it needs a Vulkan device but no display.
+
+mod common;
+
+use common::{check_golden, pixel, quad};
+
+use vulkan_video::{VulkanRenderer, Camera, Mesh, golden, vk};
+use vulkan_video::renderer::hdr::{Tonemapper, Tonemapping};
+use vulkan_video::renderer::material::RendererMaterials;
+use vulkan_video::renderer::post::{
+    Bloom, ChromaticAberration, ColorGrade, Effect, FilmGrain, Fxaa, Lut, PostEffect, Vignette,
+};
+use vulkan_video::scene::Material;
+use vulkan_video::glam::{Mat4, Vec3, Vec4};
+
+use anyhow::Result;
+
+const EXTENT: vk::Extent2D = vk::Extent2D { width: 32, height: 32 };
+
+struct Scene {
+    glowing: Mesh,
+    grey: Mesh,
+    materials: RendererMaterials,
+    camera: Camera,
+}
+
+impl Scene {
+    /// Renders a frame and checks it against the golden called `name`.
+    fn render(&self, renderer: &mut VulkanRenderer, name: &str) -> Result<Vec<u8>> {
+        let frame = renderer.begin_frame()?;
+
+        renderer.set_camera(&frame, &self.camera)?;
+
+        renderer.draw_material_mesh(&frame, &self.glowing, Mat4::IDENTITY, &self.materials, Some(0));
+        renderer.draw_material_mesh(&frame, &self.grey, Mat4::IDENTITY, &self.materials, Some(1));
+
+        renderer.end_frame(frame)?;
+
+        let pixels = renderer.read_pixels()?;
+
+        check_golden(name, EXTENT, &pixels)?;
+
+        Ok(pixels)
+    }
+}
+
+fn main() -> Result<()> {
+    let mut renderer = VulkanRenderer::headless_with_config(EXTENT, golden::config()?)?;
+
+    // the checks are on the colors as the effects leave them, not as tonemapped:
+    renderer.set_tonemapping(Tonemapping {
+        tonemapper: Tonemapper::None,
+        ..Default::default()
+    });
+
+    // black, so only the glow makes it to the screen:
+    let emissive = |glow: f32| Material {
+        base_color_factor: Vec4::new(0.0, 0.0, 0.0, 1.0),
+        metallic_factor: 0.0,
+        emissive_factor: Vec3::splat(glow),
+        ..Default::default()
+    };
+
+    let materials = RendererMaterials::new(
+        &renderer.main_device,
+        &renderer.command_pools,
+        renderer.material_pipelines.set_layout,
+        &[emissive(4.0), emissive(0.5)],
+        &[],
+    )?;
+
+    let mut camera = Camera::orthographic(2.0, 1.0, 0.1, 10.0);
+    camera.position = Vec3::new(0.0, 0.0, 2.0);
+    camera.look_at(Vec3::ZERO, Vec3::Y);
+
+    let mut scene = Scene {
+        glowing: quad(&renderer, [-1.0, -1.0], [0.0, 1.0])?,
+        grey: quad(&renderer, [0.0, -1.0], [1.0, 1.0])?,
+        materials,
+        camera,
+    };
+
+    // without effects, grey is 0.5:
+    let pixels = scene.render(&mut renderer, "post_plain")?;
+
+    let plain = pixel(&pixels, EXTENT, 24, 16);
+    let corner = pixel(&pixels, EXTENT, 31, 0);
+
+    println!("post: without effects {:?}, corner {:?}", plain, corner);
+
+    assert!(plain[0].abs_diff(128) <= 2, "grey isn't 0.5 without effects");
+    assert!(corner[0].abs_diff(128) <= 2, "the corner isn't grey without effects");
+
+    // vignette, darkening the corners by half:
+    renderer.set_post_effects(vec![
+        PostEffect::new(Effect::Vignette(Vignette {
+            intensity: 0.5,
+            radius: 0.0,
+        })),
+    ]);
+
+    let pixels = scene.render(&mut renderer, "post_vignette")?;
+
+    let corner = pixel(&pixels, EXTENT, 31, 0);
+
+    println!("post: vignette corner {:?}", corner);
+
+    assert!(corner[0].abs_diff(64) <= 4, "the vignette doesn't darken the corner by half");
+
+    // and toggled off again:
+    renderer.post.effects[0].enabled = false;
+
+    let pixels = scene.render(&mut renderer, "post_plain")?;
+
+    assert!(pixel(&pixels, EXTENT, 31, 0)[0].abs_diff(128) <= 2, "the disabled vignette still darkens");
+
+    // bloom, the glow spills over the edge but doesn't reach the far side:
+    renderer.set_post_effects(vec![PostEffect::new(Effect::Bloom(Bloom::default()))]);
+
+    let pixels = scene.render(&mut renderer, "post_bloom")?;
+
+    let near = pixel(&pixels, EXTENT, 17, 16);
+    let far = pixel(&pixels, EXTENT, 31, 16);
+
+    println!("post: bloom near the edge {:?}, far from it {:?}", near, far);
+
+    assert!(near[0] > 200, "the glow doesn't bloom");
+    assert!(far[0].abs_diff(128) <= 2, "bloom reaches too far");
+
+    // a LUT that halves everything:
+    renderer.set_lut(&Lut::from_fn(17, |color| color * 0.5))?;
+
+    renderer.set_post_effects(vec![PostEffect::new(Effect::ColorGrade(ColorGrade::default()))]);
+
+    let pixels = scene.render(&mut renderer, "post_lut")?;
+
+    let graded = pixel(&pixels, EXTENT, 24, 16);
+
+    println!("post: graded {:?}", graded);
+
+    assert!(graded[0].abs_diff(64) <= 3, "the LUT doesn't halve grey");
+    assert!(graded[0].abs_diff(graded[2]) <= 1, "the LUT tints grey");
+
+    // film grain, noisy but as bright on average:
+    renderer.set_post_effects(vec![PostEffect::new(Effect::FilmGrain(FilmGrain { intensity: 0.2 }))]);
+
+    let pixels = scene.render(&mut renderer, "post_film_grain")?;
+
+    let grain: Vec<u8> = (16..EXTENT.width)
+        .flat_map(|x| (0..EXTENT.height).map(move |y| (x, y)))
+        .map(|(x, y)| pixel(&pixels, EXTENT, x, y)[0])
+        .collect();
+
+    let average = grain.iter().map(|value| *value as f32).sum::<f32>() / grain.len() as f32;
+
+    println!("post: grain averages {}", average);
+
+    assert!((average - 128.0).abs() <= 3.0, "grain changes the brightness");
+    assert!(grain.iter().any(|value| *value != grain[0]), "there's no grain");
+
+    // antialiasing and aberration leave flat areas alone, after an even number of passes this time:
+    renderer.set_post_effects(vec![
+        PostEffect::new(Effect::Fxaa(Fxaa::default())),
+        PostEffect::new(Effect::ChromaticAberration(ChromaticAberration::default())),
+    ]);
+
+    let pixels = scene.render(&mut renderer, "post_fxaa_aberration")?;
+
+    let flat = pixel(&pixels, EXTENT, 24, 16);
+
+    println!("post: fxaa and aberration {:?}", flat);
+
+    assert!(flat[0].abs_diff(128) <= 2 && flat[2].abs_diff(128) <= 2, "flat grey changed");
+
+    unsafe {
+        renderer.main_device.logical_device.device_wait_idle()?;
+
+        scene.materials.cleanup(&renderer.main_device);
+        scene.grey.cleanup(&renderer.main_device);
+        scene.glowing.cleanup(&renderer.main_device);
+    };
+
+    Ok(())
+}
@@ -0,0 +1,237 @@
+//! Captures a quad stepping across the screen without a window, into a Y4M video and into PNG images, then reads
+//! both back and checks every frame. The first image is checked against a golden image too.
+//!
+//! `--record out.y4m` or `--record out.png` captures into that instead, `--frames` and `--fps` like the viewer.
+//! Runs as part of `cargo test`, so it needs a Vulkan device but no display.
+
+mod common;
+
+use common::{check_golden, quad};
+
+use vulkan_video::{VulkanRenderer, Camera, Mesh, golden, vk};
+use vulkan_video::renderer::capture::{CaptureFormat, CaptureSettings};
+use vulkan_video::renderer::hdr::{Tonemapper, Tonemapping};
+use vulkan_video::renderer::material::RendererMaterials;
+use vulkan_video::scene::Material;
+use vulkan_video::glam::{Mat4, Vec3, Vec4};
+
+use std::fs;
+
+use anyhow::{Context, Result};
+
+const EXTENT: vk::Extent2D = vk::Extent2D { width: 64, height: 64 };
+
+/// The quad steps through this many strips, one per frame.
+const STRIPS: u32 = 4;
+
+/// Pure red and black, in BT.601 with limited range.
+const RED_YUV: [u8; 3] = [81, 90, 240];
+const BLACK_YUV: [u8; 3] = [16, 128, 128];
+
+/// The strip the quad covers in frame `index`.
+fn strip(index: u32) -> u32 {
+    index % STRIPS
+}
+
+/// Whether the pixel at `x` in frame `index` shows the quad, the pixels next to its edges are left out.
+fn inside(index: u32, x: u32) -> Option<bool> {
+    let width = EXTENT.width / STRIPS;
+    let offset = x % width;
+
+    if offset < 2 || offset >= width - 2 {
+        None
+    } else {
+        Some(x / width == strip(index))
+    }
+}
+
+struct Scene {
+    quad: Mesh,
+    materials: RendererMaterials,
+    camera: Camera,
+}
+
+impl Scene {
+    /// Renders frames until the capture has all of them.
+    fn record(&self, renderer: &mut VulkanRenderer, settings: CaptureSettings) -> Result<u32> {
+        renderer.start_capture(settings)?;
+
+        let mut index = 0;
+
+        while !renderer.capture_done() {
+            let frame = renderer.begin_frame()?;
+
+            renderer.set_camera(&frame, &self.camera)?;
+
+            let width = 2.0 / STRIPS as f32;
+            let x = -1.0 + (strip(index) as f32 + 0.5) * width;
+
+            let model = Mat4::from_translation(Vec3::new(x, 0.0, 0.0)) * Mat4::from_scale(Vec3::new(width, 2.0, 1.0));
+
+            renderer.draw_material_mesh(&frame, &self.quad, model, &self.materials, Some(0));
+
+            renderer.end_frame(frame)?;
+
+            index += 1;
+        }
+
+        renderer.stop_capture()
+    }
+}
+
+/// Reads a Y4M file back and checks its header and every frame.
+fn check_y4m(settings: &CaptureSettings, frames: u32) -> Result<()> {
+    let bytes = fs::read(&settings.path)?;
+
+    let header_end = bytes.iter()
+        .position(|&byte| byte == b'\n')
+        .context("The video has no header")?;
+
+    let header = String::from_utf8_lossy(&bytes[..header_end]);
+
+    println!("record: {}", header);
+
+    let expected = format!("YUV4MPEG2 W{} H{} F{}:1", EXTENT.width, EXTENT.height, settings.fps);
+
+    assert!(header.starts_with(&expected), "the header is {:?}", header);
+    assert!(header.contains("C420"), "the video isn't 4:2:0");
+
+    let luma = (EXTENT.width * EXTENT.height) as usize;
+    let chroma = luma / 4;
+
+    let frame_size = b"FRAME\n".len() + luma + chroma * 2;
+
+    let body = &bytes[header_end + 1..];
+
+    assert_eq!(body.len(), frame_size * frames as usize, "the video doesn't hold {} frames", frames);
+
+    for (index, frame) in body.chunks_exact(frame_size).enumerate() {
+        assert!(frame.starts_with(b"FRAME\n"), "frame {} has no marker", index);
+
+        let (y, u, v) = (&frame[6..6 + luma], &frame[6 + luma..6 + luma + chroma], &frame[6 + luma + chroma..]);
+
+        for x in 0..EXTENT.width {
+            let expected = match inside(index as u32, x) {
+                None => continue,
+                Some(true) => RED_YUV,
+                Some(false) => BLACK_YUV,
+            };
+
+            let row = 32;
+            let chroma_index = (row / 2 * EXTENT.width / 2 + x / 2) as usize;
+
+            let got = [y[(row * EXTENT.width + x) as usize], u[chroma_index], v[chroma_index]];
+
+            let close = got.iter()
+                .zip(expected)
+                .all(|(got, expected)| got.abs_diff(expected) <= 2);
+
+            assert!(close, "frame {} is {:?} at {}, not {:?}", index, got, x, expected);
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the PNG images back and checks every one.
+fn check_png(settings: &CaptureSettings, frames: u32) -> Result<()> {
+    for index in 0..frames {
+        let path = settings.png_path(index);
+
+        let image = image::open(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?
+            .to_rgba8();
+
+        assert_eq!(image.dimensions(), (EXTENT.width, EXTENT.height), "{} has the wrong size", path.display());
+
+        for x in 0..EXTENT.width {
+            let expected = match inside(index, x) {
+                None => continue,
+                Some(true) => [255, 0, 0, 255],
+                Some(false) => [0, 0, 0, 255],
+            };
+
+            let got = image.get_pixel(x, 32).0;
+
+            assert_eq!(got, expected, "{} is wrong at {}", path.display(), x);
+        }
+
+        if index == 0 {
+            check_golden("record", EXTENT, image.as_raw())?;
+        }
+    }
+
+    println!("record: {} images, starting with {}", frames, settings.png_path(0).display());
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+
+    let directory = std::env::temp_dir().join("vulkan-video-record");
+
+    let captures = match CaptureSettings::from_args(&mut args)? {
+        Some(settings) => vec![settings],
+        None => vec![
+            CaptureSettings::new(directory.join("record.y4m")),
+            CaptureSettings::new(directory.join("record.png")),
+        ],
+    };
+
+    let mut renderer = VulkanRenderer::headless_with_config(EXTENT, golden::config()?)?;
+
+    // pure colors, so they come out exactly:
+    renderer.set_tonemapping(Tonemapping {
+        tonemapper: Tonemapper::None,
+        ..Default::default()
+    });
+
+    // unlit and black but for what it emits, there are no lights:
+    let red = Material {
+        base_color_factor: Vec4::new(0.0, 0.0, 0.0, 1.0),
+        emissive_factor: Vec3::X,
+        metallic_factor: 0.0,
+        ..Default::default()
+    };
+
+    let materials = RendererMaterials::new(
+        &renderer.main_device,
+        &renderer.command_pools,
+        renderer.material_pipelines.set_layout,
+        &[red],
+        &[],
+    )?;
+
+    let mut camera = Camera::orthographic(2.0, 1.0, 0.1, 10.0);
+    camera.position = Vec3::new(0.0, 0.0, 2.0);
+    camera.look_at(Vec3::ZERO, Vec3::Y);
+
+    let mut scene = Scene {
+        quad: quad(&renderer, [-0.5; 2], [0.5; 2])?,
+        materials,
+        camera,
+    };
+
+    for mut settings in captures {
+        let frames = *settings.frames.get_or_insert(2 * STRIPS);
+
+        let written = scene.record(&mut renderer, settings.clone())?;
+
+        assert_eq!(written, frames, "not every frame was written");
+
+        match settings.format()? {
+            CaptureFormat::Y4m => check_y4m(&settings, frames)?,
+            CaptureFormat::Png => check_png(&settings, frames)?,
+        }
+    }
+
+    unsafe {
+        renderer.main_device.logical_device.device_wait_idle()?;
+
+        scene.materials.cleanup(&renderer.main_device);
+        scene.quad.cleanup(&renderer.main_device);
+    };
+
+    Ok(())
+}
@@ -0,0 +1,169 @@
+//! Loads a small glTF scene from memory, checks what the loader made of it, then draws it without a window.
+//!
+//! The scene exercises the awkward parts: a sparse accessor, two UV sets, missing normals and tangents,
+//! an embedded PNG, a camera and a light. Runs as part of `cargo test`.
+
+mod common;
+
+use common::{check_golden};
+
+use vulkan_video::{VulkanRenderer, SceneResources, golden, vk};
+use vulkan_video::scene::{gltf, AlphaMode, LightKind};
+use vulkan_video::glam::{Mat4, Vec3, Vec4Swizzles};
+
+use anyhow::Result;
+
+const EXTENT: vk::Extent2D = vk::Extent2D { width: 64, height: 64 };
+
+const SCENE: &str = r#"{
+    "asset": { "version": "2.0" },
+    "extensionsUsed": ["KHR_lights_punctual"],
+    "extensions": {
+        "KHR_lights_punctual": {
+            "lights": [{ "name": "sun", "type": "directional", "color": [1.0, 0.9, 0.8], "intensity": 3.0 }]
+        }
+    },
+    "scene": 0,
+    "scenes": [{ "nodes": [0, 2, 3] }],
+    "nodes": [
+        { "name": "root", "translation": [0.0, 0.0, -1.0], "children": [1] },
+        { "name": "quad", "mesh": 0 },
+        { "name": "eye", "camera": 0, "translation": [0.0, 0.0, 1.0] },
+        { "name": "sun", "extensions": { "KHR_lights_punctual": { "light": 0 } } }
+    ],
+    "cameras": [{ "type": "perspective", "perspective": { "yfov": 1.0, "aspectRatio": 1.0, "znear": 0.1 } }],
+    "meshes": [{
+        "name": "quad",
+        "primitives": [{
+            "attributes": { "POSITION": 0, "TEXCOORD_0": 1, "TEXCOORD_1": 2 },
+            "indices": 3,
+            "material": 0
+        }]
+    }],
+    "materials": [{
+        "name": "orange",
+        "pbrMetallicRoughness": {
+            "baseColorFactor": [1.0, 0.5, 0.25, 1.0],
+            "baseColorTexture": { "index": 0, "texCoord": 1 },
+            "metallicFactor": 0.0
+        },
+        "alphaMode": "MASK",
+        "doubleSided": true
+    }],
+    "textures": [{ "source": 0, "sampler": 0 }],
+    "samplers": [{ "magFilter": 9728, "wrapS": 33071 }],
+    "images": [{ "uri": "data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR4nGP43+DwHwAHAAK/K9fH4gAAAABJRU5ErkJggg==" }],
+    "accessors": [
+        {
+            "bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3",
+            "min": [-0.5, -0.5, 0.0], "max": [0.5, 0.75, 0.0],
+            "sparse": {
+                "count": 1,
+                "indices": { "bufferView": 4, "componentType": 5123 },
+                "values": { "bufferView": 5 }
+            }
+        },
+        { "bufferView": 1, "componentType": 5126, "count": 4, "type": "VEC2" },
+        { "bufferView": 2, "componentType": 5126, "count": 4, "type": "VEC2" },
+        { "bufferView": 3, "componentType": 5123, "count": INDEX_COUNT, "type": "SCALAR" }
+    ],
+    "bufferViews": [
+        { "buffer": 0, "byteOffset": 0, "byteLength": 48 },
+        { "buffer": 0, "byteOffset": 48, "byteLength": 32 },
+        { "buffer": 0, "byteOffset": 80, "byteLength": 32 },
+        { "buffer": 0, "byteOffset": 112, "byteLength": 12 },
+        { "buffer": 0, "byteOffset": 124, "byteLength": 2 },
+        { "buffer": 0, "byteOffset": 128, "byteLength": 12 }
+    ],
+    "buffers": [{
+        "byteLength": 140,
+        "uri": "data:application/octet-stream;base64,AAAAvwAAAL8AAAAAAAAAPwAAAL8AAAAAAAAAPwAAAD8AAAAAAAAAvwAAAD8AAAAAAAAAAAAAgD8AAIA/AACAPwAAgD8AAAAAAAAAAAAAAAAAAIA+AABAPwAAgD4AAEA/AACAPgAAQD8AAIA+AABAPwAAAQACAAIAAwAAAAIAAAAAAAA/AABAPwAAAAA="
+    }]
+}"#;
+
+fn main() -> Result<()> {
+    // an index accessor reaching past its buffer view has to be an error, not a panic:
+    let broken = gltf::from_slice(SCENE.replace("INDEX_COUNT", "7").as_bytes());
+    assert!(broken.is_err(), "out of bounds indices were accepted");
+
+    let scene = gltf::from_slice(SCENE.replace("INDEX_COUNT", "6").as_bytes())?;
+
+    let primitive = &scene.meshes[0].primitives[0];
+
+    // without normals every triangle gets its own vertices:
+    assert_eq!(primitive.vertices.len(), 6);
+    assert_eq!(primitive.indices, [0, 1, 2, 3, 4, 5]);
+    assert!(primitive.vertices.iter().all(|vertex| vertex.normal == [0.0, 0.0, 1.0]));
+
+    assert!(primitive.vertices.iter().any(|vertex| vertex.position == [0.5, 0.75, 0.0]), "sparse position wasn't applied");
+    assert!(primitive.vertices.iter().all(|vertex| vertex.uv1 == [0.25, 0.75]));
+
+    // u runs along +x and v along -y, which is how normal maps expect it:
+    assert_eq!(primitive.vertices[0].tangent, [1.0, 0.0, 0.0, 1.0]);
+
+    let material = &scene.materials[primitive.material.unwrap()];
+
+    assert_eq!(material.alpha_mode, AlphaMode::Mask);
+    assert!(material.double_sided);
+    assert_eq!(material.base_color_texture.map(|texture| texture.uv_set), Some(1));
+
+    let texture = &scene.textures[0];
+
+    assert!(texture.srgb);
+    assert_eq!(texture.pixels, [255, 128, 64, 255]);
+    assert_eq!(texture.sampler.mag_filter, vk::Filter::NEAREST);
+    assert_eq!(texture.sampler.address_mode_u, vk::SamplerAddressMode::CLAMP_TO_EDGE);
+
+    let world = scene.world_transforms();
+
+    assert_eq!(world[1].map(|transform| transform.w_axis.xyz()), Some(Vec3::new(0.0, 0.0, -1.0)));
+
+    assert_eq!(scene.lights[0].kind, LightKind::Directional);
+    assert_eq!(scene.lights[0].intensity, 3.0);
+
+    let lights = scene.world_lights();
+
+    assert_eq!(lights.len(), 1);
+    assert_eq!(lights[0].direction, Vec3::new(0.0, 0.0, -1.0), "lights shine down their node's -z");
+
+    let camera = match scene.camera(2, 1.0) {
+        None => panic!("the eye node has no camera"),
+        Some(camera) => camera
+    };
+
+    assert_eq!(camera.position, Vec3::new(0.0, 0.0, 1.0));
+
+    let mut renderer = VulkanRenderer::headless_with_config(EXTENT, golden::config()?)?;
+
+    let mut resources = SceneResources::new(&renderer, &scene)?;
+
+    let frame = renderer.begin_frame()?;
+
+    renderer.set_camera(&frame, &camera)?;
+    renderer.set_lights(&frame, &lights)?;
+
+    renderer.begin_label(&frame, "scene");
+
+    resources.draw(&renderer, &frame, &scene, Mat4::IDENTITY);
+
+    renderer.end_label(&frame);
+
+    renderer.end_frame(frame)?;
+
+    let pixels = renderer.read_pixels()?;
+
+    check_golden("scene", EXTENT, &pixels)?;
+
+    let center = ((EXTENT.height / 2 * EXTENT.width + EXTENT.width / 2) * 4) as usize;
+
+    assert_ne!(&pixels[center..center + 3], &[0, 0, 0], "scene wasn't drawn");
+    assert_eq!(&pixels[..4], &[0, 0, 0, 255], "corner isn't the clear color");
+
+    unsafe {
+        renderer.main_device.logical_device.device_wait_idle()?;
+
+        resources.cleanup(&renderer.main_device);
+    };
+
+    Ok(())
+}
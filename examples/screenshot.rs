@@ -0,0 +1,67 @@
+//! Takes a screenshot of the triangle without a window, then checks it against the pixels read back directly.
+//!
+//! The offscreen target holds linear colors, so the screenshot has to come out sRGB encoded. Runs as part of
+//! `cargo test`, so it needs a Vulkan device but no display.
+
+mod common;
+
+use common::{check_golden};
+
+use vulkan_video::{VulkanRenderer, golden, vk};
+
+use anyhow::{Context, Result};
+
+const EXTENT: vk::Extent2D = vk::Extent2D { width: 64, height: 64 };
+
+fn encode_srgb(linear: u8) -> u8 {
+    let linear = linear as f32 / 255.0;
+
+    let encoded = if linear <= 0.0031308 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    };
+
+    (encoded * 255.0).round() as u8
+}
+
+fn main() -> Result<()> {
+    let mut renderer = VulkanRenderer::headless_with_config(EXTENT, golden::config()?)?;
+
+    // the extension follows the target, whatever was asked for:
+    let path = renderer.capture_screenshot(std::env::temp_dir().join("vulkan-video-screenshot").join("triangle.jpg"))?;
+
+    assert_eq!(path.extension().and_then(|extension| extension.to_str()), Some("png"));
+
+    assert!(renderer.capture_screenshot(&path).is_err(), "two screenshots were asked of one frame");
+
+    renderer.draw_frame()?;
+
+    let saved = renderer.finish_screenshots()?;
+
+    assert_eq!(saved, vec![path.clone()], "the screenshot wasn't saved where it was said to be");
+
+    let pixels = renderer.read_pixels()?;
+
+    // the same triangle as the headless example:
+    check_golden("triangle", EXTENT, &pixels)?;
+
+    let screenshot = image::open(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?
+        .to_rgba8();
+
+    assert_eq!(screenshot.dimensions(), (EXTENT.width, EXTENT.height));
+
+    for (index, (got, read)) in screenshot.pixels().zip(pixels.chunks_exact(4)).enumerate() {
+        let expected = [encode_srgb(read[0]), encode_srgb(read[1]), encode_srgb(read[2]), 255];
+
+        assert_eq!(got.0, expected, "the screenshot is wrong at {}", index);
+    }
+
+    // the triangle is in there:
+    assert_ne!(&screenshot.get_pixel(EXTENT.width / 2, EXTENT.height * 3 / 8).0[..3], &[0, 0, 0]);
+
+    println!("screenshot: {}", path.display());
+
+    Ok(())
+}
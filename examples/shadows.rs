@@ -0,0 +1,113 @@
+//! Lights a white floor with a sun straight above it, with a hidden caster over the left half,
+//! without a window and checks that only the left half is in shadow.
+//!
+//! Runs as part of `cargo test`, so it needs a Vulkan device but no display.
+
+mod common;
+
+use common::{check_golden, pixel};
+
+use vulkan_video::{VulkanRenderer, Camera, Mesh, Vertex, WorldLight, golden, vk};
+use vulkan_video::renderer::material::RendererMaterials;
+use vulkan_video::renderer::shadows::ShadowSettings;
+use vulkan_video::scene::{LightKind, Material};
+use vulkan_video::glam::{Mat4, Vec3};
+
+use anyhow::Result;
+
+const EXTENT: vk::Extent2D = vk::Extent2D { width: 64, height: 64 };
+
+/// A quad facing up, from `left` to `right` along x and across the whole floor along z.
+fn horizontal_quad(renderer: &VulkanRenderer, left: f32, right: f32, height: f32) -> Result<Mesh> {
+    let corner = |x: f32, z: f32| Vertex {
+        position: [x, height, z],
+        normal: [0.0, 1.0, 0.0],
+        tangent: [1.0, 0.0, 0.0, 1.0],
+        color: [1.0; 4],
+        ..Default::default()
+    };
+
+    let vertices = [corner(left, 1.5), corner(right, 1.5), corner(right, -1.5), corner(left, -1.5)];
+    let indices = [0, 1, 2, 2, 3, 0];
+
+    Mesh::new(&renderer.main_device, &vertices, &indices)
+}
+
+fn main() -> Result<()> {
+    let mut renderer = VulkanRenderer::headless_with_config(EXTENT, golden::config()?)?;
+
+    // smaller than the default, which also goes through creating the maps again:
+    renderer.set_shadow_settings(ShadowSettings {
+        resolution: 512,
+        ..Default::default()
+    })?;
+
+    let mut floor = horizontal_quad(&renderer, -1.5, 1.5, 0.0)?;
+    let mut caster = horizontal_quad(&renderer, -1.5, 0.0, 0.5)?;
+
+    let white = Material {
+        metallic_factor: 0.0,
+        ..Default::default()
+    };
+
+    let mut materials = RendererMaterials::new(
+        &renderer.main_device,
+        &renderer.command_pools,
+        renderer.material_pipelines.set_layout,
+        &[white],
+        &[],
+    )?;
+
+    let sun = WorldLight {
+        kind: LightKind::Directional,
+        position: Vec3::ZERO,
+        direction: -Vec3::Y,
+        color: Vec3::ONE,
+        intensity: 3.0,
+        range: None,
+        casts_shadows: true,
+    };
+
+    // looking straight down, with +x to the right:
+    let mut camera = Camera::orthographic(2.0, 1.0, 0.1, 10.0);
+    camera.position = Vec3::new(0.0, 2.0, 0.0);
+    camera.look_at(Vec3::ZERO, -Vec3::Z);
+
+    let frame = renderer.begin_frame()?;
+
+    renderer.set_camera(&frame, &camera)?;
+    renderer.set_lights(&frame, &[sun])?;
+
+    // the caster only shows up in the shadow maps:
+    renderer.draw_shadow_caster(&caster, Mat4::IDENTITY);
+
+    renderer.begin_label(&frame, "floor");
+
+    renderer.draw_material_mesh(&frame, &floor, Mat4::IDENTITY, &materials, Some(0));
+
+    renderer.end_label(&frame);
+
+    renderer.end_frame(frame)?;
+
+    let pixels = renderer.read_pixels()?;
+
+    check_golden("shadows", EXTENT, &pixels)?;
+
+    let left = pixel(&pixels, EXTENT, EXTENT.width / 4, EXTENT.height / 2);
+    let right = pixel(&pixels, EXTENT, EXTENT.width * 3 / 4, EXTENT.height / 2);
+
+    println!("shadows: left {:?}, right {:?}", left, right);
+
+    assert!(left[0] < 40, "left side isn't in shadow");
+    assert!(right[0] > 100, "right side isn't lit");
+
+    unsafe {
+        renderer.main_device.logical_device.device_wait_idle()?;
+
+        materials.cleanup(&renderer.main_device);
+        caster.cleanup(&renderer.main_device);
+        floor.cleanup(&renderer.main_device);
+    };
+
+    Ok(())
+}
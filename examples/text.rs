@@ -0,0 +1,149 @@
+//! Text drawn over the triangle without a window, in screen space and in the world, checked against the same frame
+//! without it.
+//!
+//! Both texts have to change the pixels within their boxes and nothing else. The text on screen is drawn after
+//! tonemapping, so its white stays white. Runs as part of `cargo test`, so it needs a Vulkan device but no display.
+//!
+//! The font is the first argument, or `VULKAN_VIDEO_FONT`, or Inter from `assets/fonts`. The frame with Inter is
+//! checked against a golden image too, another font changes it.
+
+mod common;
+
+use common::{check_golden};
+
+use vulkan_video::{Camera, Font, TextLayout, TextStyle, VulkanRenderer, golden, vk};
+use vulkan_video::text::atlas::{SDF_SIZE, SDF_SPREAD};
+use vulkan_video::text::FontId;
+use vulkan_video::glam::{Mat4, Vec3};
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+const EXTENT: vk::Extent2D = vk::Extent2D { width: 128, height: 128 };
+
+/// The font that comes with the crate, relative to its root.
+const FONT: &str = "assets/fonts/Inter-Regular.ttf";
+
+/// Where the text on screen goes, in pixels.
+const SCREEN: [f32; 2] = [6.0, 4.0];
+
+/// The font and whether it's the one that comes with the crate.
+fn font() -> Result<(Font, bool)> {
+    let path = env::args().nth(1)
+        .or_else(|| env::var("VULKAN_VIDEO_FONT").ok())
+        .map(PathBuf::from);
+
+    let bundled = path.is_none();
+
+    let font = Font::from_file(path.unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join(FONT)))?;
+
+    Ok((font, bundled))
+}
+
+/// Left, top, right and bottom of where `layout` can change pixels, with the spread around its glyphs.
+fn bounds(layout: &TextLayout, left: f32, top: f32, pixels_per_unit: f32) -> [f32; 4] {
+    let spread = SDF_SPREAD as f32 * layout.size / SDF_SIZE * pixels_per_unit + 1.0;
+
+    [
+        left - spread,
+        top - spread,
+        left + layout.width * pixels_per_unit + spread,
+        top + layout.height * pixels_per_unit + spread,
+    ]
+}
+
+fn draw(renderer: &mut VulkanRenderer, texts: Option<(FontId, &TextLayout, &TextLayout, Mat4)>) -> Result<Vec<u8>> {
+    // two world units high, the world's origin in the middle of the frame:
+    let mut camera = Camera::orthographic(2.0, 1.0, 0.1, 10.0);
+    camera.position = Vec3::new(0.0, 0.0, 2.0);
+    camera.look_at(Vec3::ZERO, Vec3::Y);
+
+    let frame = renderer.begin_frame()?;
+
+    renderer.set_camera(&frame, &camera)?;
+
+    renderer.draw_triangle(&frame);
+
+    if let Some((font, screen, world, model)) = texts {
+        renderer.draw_text(font, screen, SCREEN, [255; 4])?;
+        renderer.draw_world_text(font, world, model, [255, 255, 0, 255])?;
+    }
+
+    renderer.end_frame(frame)?;
+
+    renderer.read_pixels()
+}
+
+fn main() -> Result<()> {
+    let mut renderer = VulkanRenderer::headless_with_config(EXTENT, golden::config()?)?;
+
+    let (font, bundled) = font()?;
+    let id = renderer.add_font(font.clone())?;
+
+    let screen = TextLayout::new(&font, "Hi", &TextStyle {
+        size: 24.0,
+        ..Default::default()
+    });
+
+    // in world units, centered under the middle of the frame:
+    let world = TextLayout::new(&font, "Hey", &TextStyle {
+        size: 0.5,
+        ..Default::default()
+    });
+
+    let model = Mat4::from_translation(Vec3::new(-world.width / 2.0, -0.3, 0.0));
+
+    let without = draw(&mut renderer, None)?;
+    let with = draw(&mut renderer, Some((id, &screen, &world, model)))?;
+
+    let screen_bounds = bounds(&screen, SCREEN[0], SCREEN[1], 1.0);
+
+    // world units to pixels, with world Y up and pixel Y down:
+    let pixels_per_unit = EXTENT.height as f32 / 2.0;
+
+    let world_bounds = bounds(
+        &world,
+        (1.0 - world.width / 2.0) * pixels_per_unit,
+        (1.0 + 0.3) * pixels_per_unit,
+        pixels_per_unit,
+    );
+
+    let inside = |[left, top, right, bottom]: [f32; 4], x: u32, y: u32| {
+        let (x, y) = (x as f32 + 0.5, y as f32 + 0.5);
+
+        x >= left && x <= right && y >= top && y <= bottom
+    };
+
+    let mut screen_changed = 0;
+    let mut world_changed = 0;
+    let mut white = false;
+
+    for (index, (got, expected)) in with.chunks_exact(4).zip(without.chunks_exact(4)).enumerate() {
+        let (x, y) = (index as u32 % EXTENT.width, index as u32 / EXTENT.width);
+
+        if inside(screen_bounds, x, y) {
+            if got != expected {
+                screen_changed += 1;
+                white |= got[..3].iter().all(|&channel| channel >= 250);
+            }
+        } else if inside(world_bounds, x, y) {
+            world_changed += (got != expected) as usize;
+        } else {
+            assert_eq!(got, expected, "text was drawn outside of its box at {}, {}", x, y);
+        }
+    }
+
+    assert!(screen_changed > 0, "the text on screen wasn't drawn");
+    assert!(world_changed > 0, "the text in the world wasn't drawn");
+    assert!(white, "the text on screen isn't white, it was tonemapped");
+
+    if bundled {
+        check_golden("text", EXTENT, &with)?;
+    }
+
+    println!("text: {} pixels on screen and {} in the world changed", screen_changed, world_changed);
+
+    Ok(())
+}
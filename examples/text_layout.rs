@@ -0,0 +1,174 @@
+//! Lays text out with kerning, wrapping and alignment, rasterizes its glyphs into the SDF atlas and batches them,
+//! checking each step. Doesn't need a GPU at all.
+//!
+//! The font is the first argument, or `VULKAN_VIDEO_FONT`, or Inter from `assets/fonts`, which kerns its pairs in
+//! `GPOS` rather than in a `kern` table. Runs as part of `cargo test`.
+
+use vulkan_video::text::{Align, SdfAtlas, TextBatch};
+use vulkan_video::text::atlas::{SDF_SIZE, SDF_SPREAD};
+use vulkan_video::{Font, TextLayout, TextStyle};
+use vulkan_video::glam::Mat4;
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+/// The font that comes with the crate, relative to its root.
+const FONT: &str = "assets/fonts/Inter-Regular.ttf";
+
+const TEXT: &str = "The quick brown fox jumps over the lazy dog, then AVAST WAVES";
+
+fn font() -> Result<Font> {
+    let path = env::args().nth(1)
+        .or_else(|| env::var("VULKAN_VIDEO_FONT").ok())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join(FONT));
+
+    Font::from_file(path)
+}
+
+fn main() -> Result<()> {
+    let font = font()?;
+
+    let style = TextStyle {
+        size: 20.0,
+        ..Default::default()
+    };
+
+    // without a width, there's a line for each paragraph:
+    let layout = TextLayout::new(&font, "first\n\nthird", &style);
+
+    assert_eq!(layout.lines.len(), 3);
+    assert!(layout.lines[1].glyphs.is_empty());
+    assert_eq!(layout.lines[1].width, 0.0);
+
+    let line_height = font.line_height(style.size);
+
+    assert!((layout.lines[2].baseline - layout.lines[0].baseline - 2.0 * line_height).abs() < 1e-3);
+    assert!((layout.height - (font.ascent(style.size) - font.descent(style.size) + 2.0 * line_height)).abs() < 1e-3);
+
+    // pairs the font kerns come out narrower than their advances:
+    let kerned = TextLayout::new(&font, "AV", &style);
+
+    let a = font.glyph_id('A');
+    let v = font.glyph_id('V');
+
+    let advances = font.advance(a, style.size) + font.advance(v, style.size);
+    let kerning = font.kerning(a, v, style.size);
+
+    assert!((kerned.width - (advances + kerning)).abs() < 1e-3);
+    assert!((kerned.glyphs[1].position[0] - (font.advance(a, style.size) + kerning)).abs() < 1e-3);
+
+    assert!(kerning < 0.0, "AV isn't kerned");
+
+    // wrapped at a width, lines break between words:
+    let max_width = 160.0;
+
+    for align in [Align::Left, Align::Center, Align::Right] {
+        let wrapped = TextLayout::new(&font, TEXT, &TextStyle {
+            max_width: Some(max_width),
+            align,
+            ..style
+        });
+
+        assert!(wrapped.lines.len() > 2, "{} lines", wrapped.lines.len());
+
+        let mut words = vec![];
+
+        for line in &wrapped.lines {
+            let text: String = wrapped.glyphs[line.glyphs.clone()].iter().map(|glyph| glyph.character).collect();
+
+            assert!(line.width <= max_width, "'{}' is {} wide", text, line.width);
+            assert_eq!(text.trim(), text, "a line starts or ends with spaces");
+
+            let start = wrapped.glyphs[line.glyphs.start].position[0];
+
+            assert!((start - line.x).abs() < 1e-3);
+
+            let expected = match align {
+                Align::Left => 0.0,
+                Align::Center => (max_width - line.width) / 2.0,
+                Align::Right => max_width - line.width,
+            };
+
+            assert!((line.x - expected).abs() < 1e-3, "{:?} put '{}' at {}", align, text, line.x);
+
+            words.extend(text.split_whitespace().map(str::to_owned));
+        }
+
+        // no word was broken or lost:
+        assert_eq!(words, TEXT.split_whitespace().collect::<Vec<_>>());
+    }
+
+    // a word longer than a line is broken where it overflows:
+    let long = TextLayout::new(&font, "Supercalifragilisticexpialidocious", &TextStyle {
+        max_width: Some(100.0),
+        ..style
+    });
+
+    assert!(long.lines.len() > 1);
+    assert!(long.lines.iter().all(|line| line.width <= 100.0 && !line.glyphs.is_empty()));
+    assert_eq!(long.glyphs.len(), "Supercalifragilisticexpialidocious".len());
+
+    // the atlas rasterizes each glyph once, spaces have nothing to rasterize:
+    let mut atlas = SdfAtlas::new(512, 512);
+
+    let id = atlas.add_font(font.clone());
+
+    assert_eq!(atlas.glyph(id, font.glyph_id(' '))?, None);
+
+    let o = atlas.glyph(id, font.glyph_id('o'))?.expect("'o' has an outline");
+
+    let version = atlas.version;
+
+    assert_eq!(atlas.glyph(id, font.glyph_id('o'))?, Some(o));
+    assert_eq!(atlas.version, version, "'o' was rasterized again");
+
+    // the ring of an 'o' is inside, its hole and the spread around it are outside:
+    let pixel = |u: f32, v: f32| {
+        let x = (u * atlas.width as f32) as usize;
+        let y = (v * atlas.height as f32) as usize;
+
+        atlas.pixels[y * atlas.width as usize + x]
+    };
+
+    let [left, top, right, bottom] = o.uv;
+    let middle = (top + bottom) / 2.0;
+
+    let stroke = left + (SDF_SPREAD as f32 + 1.0) / atlas.width as f32;
+
+    assert!(pixel(left + 0.5 / atlas.width as f32, top + 0.5 / atlas.height as f32) < 64, "the spread around the 'o' is inside it");
+    assert!(pixel(stroke, middle) > 128, "the ring of the 'o' is outside it");
+    assert!(pixel((left + right) / 2.0, middle) < 128, "the hole of the 'o' is inside it");
+
+    assert!(o.size[0] > 2.0 * SDF_SPREAD as f32 && o.size[1] < SDF_SIZE + 2.0 * SDF_SPREAD as f32);
+
+    // a quad for each glyph with an outline, within the layout's box:
+    let mut batch = TextBatch::new();
+
+    let layout = TextLayout::new(&font, "Hello, world", &style);
+
+    batch.push(&mut atlas, id, &layout, Mat4::IDENTITY, [255; 4])?;
+
+    let visible = layout.glyphs.iter().filter(|glyph| !glyph.character.is_whitespace()).count();
+
+    assert_eq!(batch.vertices.len(), visible * 4);
+    assert_eq!(batch.indices.len(), visible * 6);
+    assert!(batch.indices.iter().all(|&index| (index as usize) < batch.vertices.len()));
+
+    // the spread around the glyphs reaches past the box, but not by more than it is wide:
+    let spread = SDF_SPREAD as f32 * style.size / SDF_SIZE + 1.0;
+
+    for vertex in &batch.vertices {
+        let [x, y, z] = vertex.position;
+
+        assert!(x >= -spread && x <= layout.width + spread, "x {} is outside of the layout", x);
+        assert!(y >= -spread && y <= layout.height + spread, "y {} is outside of the layout", y);
+        assert_eq!(z, 0.0);
+    }
+
+    println!("text layout: {} lines of {:.1} by {:.1}, {} glyphs in the atlas", layout.lines.len(), layout.width, layout.height, atlas.glyph_count());
+
+    Ok(())
+}
@@ -0,0 +1,98 @@
+//! Draws a wall that glows four times brighter than white through each tonemapper without a window
+//! and checks what comes out.
+//!
+//! Runs as part of `cargo test`, so it needs a Vulkan device but no display.
+
+mod common;
+
+use common::{check_golden};
+
+use vulkan_video::{VulkanRenderer, Camera, Mesh, Vertex, golden, vk};
+use vulkan_video::renderer::hdr::{Tonemapper, Tonemapping};
+use vulkan_video::renderer::material::RendererMaterials;
+use vulkan_video::scene::Material;
+use vulkan_video::glam::{Mat4, Vec3, Vec4};
+
+use anyhow::Result;
+
+const EXTENT: vk::Extent2D = vk::Extent2D { width: 16, height: 16 };
+
+fn main() -> Result<()> {
+    let mut renderer = VulkanRenderer::headless_with_config(EXTENT, golden::config()?)?;
+
+    let corner = |x: f32, y: f32| Vertex {
+        position: [x, y, 0.0],
+        normal: [0.0, 0.0, 1.0],
+        tangent: [1.0, 0.0, 0.0, 1.0],
+        color: [1.0; 4],
+        ..Default::default()
+    };
+
+    let vertices = [corner(-1.0, -1.0), corner(1.0, -1.0), corner(1.0, 1.0), corner(-1.0, 1.0)];
+    let indices = [0, 1, 2, 2, 3, 0];
+
+    let mut wall = Mesh::new(&renderer.main_device, &vertices, &indices)?;
+
+    // black, so only the glow makes it to the screen:
+    let glowing = Material {
+        base_color_factor: Vec4::new(0.0, 0.0, 0.0, 1.0),
+        metallic_factor: 0.0,
+        emissive_factor: Vec3::splat(4.0),
+        ..Default::default()
+    };
+
+    let mut materials = RendererMaterials::new(
+        &renderer.main_device,
+        &renderer.command_pools,
+        renderer.material_pipelines.set_layout,
+        &[glowing],
+        &[],
+    )?;
+
+    let mut camera = Camera::orthographic(2.0, 1.0, 0.1, 10.0);
+    camera.position = Vec3::new(0.0, 0.0, 2.0);
+    camera.look_at(Vec3::ZERO, Vec3::Y);
+
+    // what 4.0 comes out as, worked out on the CPU from the same curves:
+    let expected = [
+        (Tonemapper::None, 1.0, 255),
+        (Tonemapper::Reinhard, 1.0, 204),
+        (Tonemapper::Reinhard, 0.25, 128),
+        (Tonemapper::Aces, 1.0, 232),
+        (Tonemapper::AgX, 1.0, 219),
+    ];
+
+    for (tonemapper, exposure, value) in expected {
+        renderer.set_tonemapping(Tonemapping {
+            tonemapper,
+            exposure,
+            ..Default::default()
+        });
+
+        let frame = renderer.begin_frame()?;
+
+        renderer.set_camera(&frame, &camera)?;
+
+        renderer.draw_material_mesh(&frame, &wall, Mat4::IDENTITY, &materials, Some(0));
+
+        renderer.end_frame(frame)?;
+
+        let pixels = renderer.read_pixels()?;
+
+        check_golden(&format!("tonemap_{:?}_{}", tonemapper, exposure).to_lowercase(), EXTENT, &pixels)?;
+
+        println!("tonemap: {:?} at exposure {} gives {:?}", tonemapper, exposure, &pixels[..4]);
+
+        assert!(pixels[0].abs_diff(value) <= 3, "{:?} at exposure {} isn't {}", tonemapper, exposure, value);
+        assert!(pixels[0].abs_diff(pixels[1]) <= 1 && pixels[0].abs_diff(pixels[2]) <= 1, "{:?} tints white", tonemapper);
+    }
+
+    unsafe {
+        renderer.main_device.logical_device.device_wait_idle()?;
+
+        materials.cleanup(&renderer.main_device);
+        wall.cleanup(&renderer.main_device);
+    };
+
+    Ok(())
+}
@@ -0,0 +1,19 @@
+use vulkan_video::{App, AppRunner, Frame, VulkanRenderer};
+
+use anyhow::Result;
+
+struct Triangle;
+
+impl App for Triangle {
+    fn render(&mut self, renderer: &mut VulkanRenderer, frame: &Frame, _alpha: f32) -> Result<()> {
+        renderer.draw_triangle(frame);
+
+        Ok(())
+    }
+}
+
+fn main() -> Result<()> {
+    let renderer = VulkanRenderer::new()?;
+
+    AppRunner::new(renderer)?.run(Triangle)
+}
@@ -0,0 +1,380 @@
+//! Runs video through a chain of compute shaders on the GPU, from Y4M or raw I420 and NV12 into Y4M.
+//!
+//! `video_filter in.y4m out.y4m --scale 640x360 --blur 1.5 --sharpen 0.5 --saturation 0` runs the filters in the
+//! order they're given, `--raw i420 --size 1920x1080 --fps 30` reads raw frames instead. Without any arguments it
+//! filters frames it makes up and checks what comes out, the frame through every filter against a golden image too.
+//! That runs as part of `cargo test`: it needs a Vulkan device but no display.
+
+mod common;
+
+use common::{check_golden};
+
+use vulkan_video::{VulkanRenderer, golden, vk};
+use vulkan_video::renderer::video_filters::{RendererVideoFilters, VideoFilter};
+use vulkan_video::video::{RawFormat, Yuv420};
+use vulkan_video::video::y4m::{Y4mReader, Y4mWriter};
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read};
+
+use anyhow::{Context, Result};
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 48;
+
+const USAGE: &str = "usage: video_filter [in.y4m out.y4m [--raw i420|nv12 --size WxH --fps N] \
+    [--scale WxH] [--blur SIGMA] [--sharpen AMOUNT] [--saturation AMOUNT]...]";
+
+/// A gray ramp on the left and red on the right, split where a chroma sample ends.
+fn ramp_frame() -> Result<Yuv420> {
+    let mut frame = Yuv420::new(WIDTH, HEIGHT)?;
+
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            frame.y[(y * WIDTH + x) as usize] = if x < WIDTH / 2 { 16 + x as u8 * 6 } else { 81 };
+        }
+    }
+
+    let (chroma_width, _) = Yuv420::chroma_size(WIDTH, HEIGHT);
+
+    for (index, (u, v)) in frame.u.iter_mut().zip(&mut frame.v).enumerate() {
+        if index as u32 % chroma_width >= chroma_width / 2 {
+            (*u, *v) = (90, 240);
+        }
+    }
+
+    Ok(frame)
+}
+
+/// Dark gray on the left and light gray on the right.
+fn step_frame() -> Result<Yuv420> {
+    let mut frame = Yuv420::new(WIDTH, HEIGHT)?;
+
+    for (index, y) in frame.y.iter_mut().enumerate() {
+        *y = if (index as u32 % WIDTH) < WIDTH / 2 { 60 } else { 180 };
+    }
+
+    Ok(frame)
+}
+
+/// The frame as RGBA, from BT.601 with limited range, to be checked against a golden image.
+fn rgba(frame: &Yuv420) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity((frame.width * frame.height * 4) as usize);
+
+    for y in 0..frame.height {
+        for x in 0..frame.width {
+            let [luma, u, v] = frame.pixel(x, y);
+
+            let luma = (luma as f32 - 16.0) * 255.0 / 219.0;
+            let (u, v) = ((u as f32 - 128.0) * 255.0 / 224.0, (v as f32 - 128.0) * 255.0 / 224.0);
+
+            let rgb = [luma + 1.402 * v, luma - 0.344136 * u - 0.714136 * v, luma + 1.772 * u];
+
+            pixels.extend(rgb.map(|channel| channel.round().clamp(0.0, 255.0) as u8));
+            pixels.push(255);
+        }
+    }
+
+    pixels
+}
+
+fn close(got: u8, expected: u8, what: &str) {
+    assert!(got.abs_diff(expected) <= 1, "{} is {}, not {}", what, got, expected);
+}
+
+/// Makes filters for `filters`, runs `frame` through them a few times and checks they come out the same every time.
+fn filter(renderer: &VulkanRenderer, frame: &Yuv420, filters: &[VideoFilter]) -> Result<Yuv420> {
+    let extent = vk::Extent2D {
+        width: frame.width,
+        height: frame.height,
+    };
+
+    let mut video_filters = RendererVideoFilters::new(&renderer.main_device, extent, filters)?;
+
+    let mut outputs = vec![];
+
+    for _ in 0..3 {
+        outputs.push(video_filters.process(&renderer.main_device, &renderer.command_pools, frame));
+    }
+
+    unsafe {
+        video_filters.cleanup(&renderer.main_device);
+    };
+
+    let outputs = outputs.into_iter().collect::<Result<Vec<_>>>()?;
+
+    assert!(outputs.windows(2).all(|pair| pair[0] == pair[1]), "{:?} doesn't filter the same every time", filters);
+
+    Ok(outputs.into_iter().next().unwrap())
+}
+
+fn check_files() -> Result<()> {
+    let frame = ramp_frame()?;
+
+    // an NTSC rate, which isn't whole:
+    let mut writer = Y4mWriter::with_frame_rate(vec![], WIDTH, HEIGHT, (30000, 1001))?;
+
+    writer.write_frame(&frame)?;
+    writer.write_frame(&step_frame()?)?;
+
+    let bytes = writer.finish()?;
+
+    let mut reader = Y4mReader::new(bytes.as_slice())?;
+
+    assert_eq!((reader.width, reader.height, reader.frame_rate), (WIDTH, HEIGHT, (30000, 1001)));
+
+    assert_eq!(reader.read_frame()?.as_ref(), Some(&frame));
+    assert_eq!(reader.read_frame()?, Some(step_frame()?));
+    assert_eq!(reader.read_frame()?, None);
+
+    for format in [RawFormat::I420, RawFormat::Nv12] {
+        let raw = frame.to_raw(format);
+
+        assert_eq!(raw.len(), Yuv420::frame_size(WIDTH, HEIGHT)?);
+        assert_eq!(Yuv420::from_raw(WIDTH, HEIGHT, format, &raw)?, frame, "{:?} doesn't round trip", format);
+    }
+
+    assert!(Y4mReader::new(&b"YUV4MPEG2 W8 H8 C444\n"[..]).is_err(), "4:4:4 was read as 4:2:0");
+    assert!(Y4mReader::new(&b"YUV4MPEG2 W8 H8 C420p10\n"[..]).is_err(), "10-bit was read as 8-bit");
+    assert!(Y4mReader::new(&b"YUV4MPEG2 W8 H8 C420paldv\n"[..]).is_ok(), "PAL DV siting wasn't read");
+
+    // odd and hostile headers don't crash the reader, unknown tags are skipped and frames too big are refused:
+    assert!(Y4mReader::new("YUV4MPEG2 W8 H8 \u{e9}x\n".as_bytes()).is_ok(), "a tag that isn't ASCII wasn't skipped");
+    assert!(Y4mReader::new(&b"YUV4MPEG2 W65536 H65536\n"[..]).is_err(), "frames too big were accepted");
+    assert!(Y4mReader::new(&b"YUV4MPEG2 W16384 H1\n"[..]).is_ok(), "the widest frames were refused");
+
+    println!("video_filter: Y4M and raw frames round trip");
+
+    Ok(())
+}
+
+fn check_filters() -> Result<()> {
+    let renderer = VulkanRenderer::headless_with_config(vk::Extent2D { width: 16, height: 16 }, golden::config()?)?;
+
+    let ramp = ramp_frame()?;
+    let step = step_frame()?;
+
+    // nothing but the conversions, away from where chroma is interpolated across the edge:
+    let output = filter(&renderer, &ramp, &[])?;
+
+    for x in (0..WIDTH).filter(|x| !(WIDTH / 2 - 3..WIDTH / 2 + 3).contains(x)) {
+        let [y, u, v] = output.pixel(x, 7);
+        let [expected_y, expected_u, expected_v] = ramp.pixel(x, 7);
+
+        close(y, expected_y, &format!("Y at {}", x));
+        close(u, expected_u, &format!("U at {}", x));
+        close(v, expected_v, &format!("V at {}", x));
+    }
+
+    // luma stays as it is and chroma goes away, everywhere:
+    let output = filter(&renderer, &ramp, &[VideoFilter::saturation(0.0)])?;
+
+    for x in 0..WIDTH {
+        let [y, u, v] = output.pixel(x, 7);
+
+        close(y, ramp.pixel(x, 7)[0], &format!("grayscale Y at {}", x));
+        close(u, 128, &format!("grayscale U at {}", x));
+        close(v, 128, &format!("grayscale V at {}", x));
+    }
+
+    // every pixel is the average of two from the ramp:
+    let output = filter(&renderer, &ramp, &[VideoFilter::Scale { width: WIDTH / 2, height: HEIGHT / 2 }])?;
+
+    assert_eq!((output.width, output.height), (WIDTH / 2, HEIGHT / 2));
+
+    for x in 0..WIDTH / 4 - 2 {
+        close(output.pixel(x, 5)[0], 16 + (x * 12 + 3) as u8, &format!("scaled Y at {}", x));
+    }
+
+    for x in WIDTH / 4 + 2..WIDTH / 2 {
+        for (got, expected) in output.pixel(x, 5).into_iter().zip([81, 90, 240]) {
+            close(got, expected, &format!("scaled red at {}", x));
+        }
+    }
+
+    // the step softens, away from it nothing changes:
+    let edge = WIDTH / 2;
+
+    let output = filter(&renderer, &step, &[VideoFilter::Blur { sigma: 1.5 }])?;
+
+    let (dark, light) = (output.pixel(edge - 1, 9)[0], output.pixel(edge, 9)[0]);
+
+    assert!(60 < dark && dark < 120 && 120 < light && light < 180, "the blurred step is {} and {}", dark, light);
+
+    close(output.pixel(edge - 8, 9)[0], 60, "blurred dark gray");
+    close(output.pixel(edge + 8, 9)[0], 180, "blurred light gray");
+
+    // the step overshoots on both sides:
+    let sharpen = VideoFilter::Sharpen { amount: 1.0 };
+
+    let output = filter(&renderer, &step, &[sharpen])?;
+
+    let (dark, light) = (output.pixel(edge - 1, 9)[0], output.pixel(edge, 9)[0]);
+
+    assert!(dark < 50 && light > 190, "the sharpened step is {} and {}", dark, light);
+
+    close(output.pixel(edge - 4, 9)[0], 60, "sharpened dark gray");
+    close(output.pixel(edge + 4, 9)[0], 180, "sharpened light gray");
+
+    // a shader of our own, which happens to be the one sharpening uses:
+    let custom = VideoFilter::Custom {
+        code: vk_shader_macros::include_glsl!("./shaders/video_sharpen.comp").to_vec(),
+        constants: 1.0f32.to_ne_bytes().to_vec(),
+    };
+
+    assert_eq!(filter(&renderer, &step, &[custom])?, output, "the custom filter doesn't sharpen");
+
+    // all at once, into a size that isn't a whole number of blocks:
+    let output = filter(&renderer, &ramp, &[
+        VideoFilter::Scale { width: 37, height: 21 },
+        VideoFilter::Blur { sigma: 0.8 },
+        VideoFilter::Sharpen { amount: 0.5 },
+        VideoFilter::saturation(0.5),
+    ])?;
+
+    assert_eq!((output.width, output.height), (37, 21));
+    assert_eq!((output.u.len(), output.v.len()), (19 * 11, 19 * 11));
+
+    check_golden("video_filter", vk::Extent2D { width: output.width, height: output.height }, &rgba(&output))?;
+
+    assert!(RendererVideoFilters::new(&renderer.main_device, vk::Extent2D { width: 8, height: 8 }, &[
+        VideoFilter::Blur { sigma: 0.0 }
+    ]).is_err(), "a blur without a sigma was made");
+
+    println!("video_filter: scaling, blurring, sharpening, color and custom filters check out");
+
+    Ok(())
+}
+
+/// Splits `WxH`.
+fn size(value: &str) -> Result<(u32, u32)> {
+    value.split_once('x')
+        .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+        .with_context(|| format!("{} isn't a size like 640x360", value))
+}
+
+/// Where frames come from.
+enum Input {
+    Y4m(Y4mReader<BufReader<File>>),
+    Raw(BufReader<File>, RawFormat, u32, u32),
+}
+
+impl Input {
+    fn read_frame(&mut self) -> Result<Option<Yuv420>> {
+        match self {
+            Input::Y4m(reader) => reader.read_frame(),
+            Input::Raw(reader, format, width, height) => {
+                let mut bytes = vec![0; Yuv420::frame_size(*width, *height)?];
+
+                match reader.read_exact(&mut bytes) {
+                    Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+                    Err(err) => Err(err.into()),
+                    Ok(()) => Ok(Some(Yuv420::from_raw(*width, *height, *format, &bytes)?)),
+                }
+            }
+        }
+    }
+}
+
+fn run(args: Vec<String>) -> Result<()> {
+    let (input_path, output_path) = match (args.first(), args.get(1)) {
+        (Some(input), Some(output)) => (input.clone(), output.clone()),
+        _ => anyhow::bail!("{}", USAGE),
+    };
+
+    let mut raw = None;
+    let mut raw_size = None;
+    let mut fps = 30;
+    let mut filters = vec![];
+
+    for pair in args[2..].chunks(2) {
+        let (flag, value) = match pair {
+            [flag, value] => (flag.as_str(), value.as_str()),
+            _ => anyhow::bail!("{} needs a value\n{}", pair[0], USAGE),
+        };
+
+        let number = || value.parse::<f32>().with_context(|| format!("{} {} isn't a number", flag, value));
+
+        match flag {
+            "--raw" => raw = Some(match value {
+                "i420" => RawFormat::I420,
+                "nv12" => RawFormat::Nv12,
+                _ => anyhow::bail!("--raw takes i420 or nv12, not {}", value),
+            }),
+            "--size" => raw_size = Some(size(value)?),
+            "--fps" => fps = value.parse().with_context(|| format!("--fps {} isn't a frame rate", value))?,
+            "--scale" => {
+                let (width, height) = size(value)?;
+
+                filters.push(VideoFilter::Scale { width, height });
+            },
+            "--blur" => filters.push(VideoFilter::Blur { sigma: number()? }),
+            "--sharpen" => filters.push(VideoFilter::Sharpen { amount: number()? }),
+            "--saturation" => filters.push(VideoFilter::saturation(number()?)),
+            _ => anyhow::bail!("Don't know {}\n{}", flag, USAGE),
+        }
+    }
+
+    let file = File::open(&input_path)
+        .with_context(|| format!("Failed to open {}", input_path))?;
+
+    let (mut input, width, height, frame_rate) = match (raw, raw_size) {
+        (None, _) => {
+            let reader = Y4mReader::new(BufReader::new(file))?;
+
+            let (width, height, frame_rate) = (reader.width, reader.height, reader.frame_rate);
+
+            (Input::Y4m(reader), width, height, frame_rate)
+        },
+        (Some(format), Some((width, height))) => (Input::Raw(BufReader::new(file), format, width, height), width, height, (fps, 1)),
+        (Some(_), None) => anyhow::bail!("Raw frames need a --size"),
+    };
+
+    let renderer = VulkanRenderer::headless(vk::Extent2D { width: 16, height: 16 })?;
+
+    let mut video_filters = RendererVideoFilters::new(&renderer.main_device, vk::Extent2D { width, height }, &filters)?;
+
+    let output = File::create(&output_path)
+        .with_context(|| format!("Failed to create {}", output_path))?;
+
+    let written = (|| -> Result<u32> {
+        let mut writer = Y4mWriter::with_frame_rate(
+            BufWriter::new(output),
+            video_filters.output.width,
+            video_filters.output.height,
+            frame_rate,
+        )?;
+
+        while let Some(frame) = input.read_frame()? {
+            writer.write_frame(&video_filters.process(&renderer.main_device, &renderer.command_pools, &frame)?)?;
+        }
+
+        let frames = writer.frames;
+
+        writer.finish()?;
+
+        Ok(frames)
+    })();
+
+    unsafe {
+        video_filters.cleanup(&renderer.main_device);
+    };
+
+    println!(
+        "video_filter: {} frames of {}x{} through {} filters into {}",
+        written?, width, height, filters.len(), output_path,
+    );
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if !args.is_empty() {
+        return run(args);
+    }
+
+    check_files()?;
+    check_filters()
+}
@@ -0,0 +1,168 @@
+//! Shows a model file with an orbit camera: `cargo run --example viewer -- path/to/model.gltf`.
+//! `C` switches to the cameras stored in the file and back, `1` to `4` toggle bloom, FXAA, the vignette and film grain,
+//! `O` toggles occlusion culling, `F11` fullscreen and `F12` saves a screenshot into `screenshots/`.
+//!
+//! `--record out.y4m` records what's shown into a video, or `--record out.png` into numbered images. `--frames 300`
+//! stops after that many frames and `--fps 30` sets the video's frame rate.
+//!
+//! The renderer is set up by `--config viewer.toml` and the other `RendererConfig` flags, `--hdr` presents in HDR10
+//! and `--window-width 1280` sizes the window. A model that isn't where it's said to be is looked for in `assets`.
+
+use vulkan_video::{App, AppRunner, Camera, Frame, Input, RendererConfig, Scene, SceneResources, VulkanRenderer, WorldLight};
+use vulkan_video::scene::LightKind;
+use vulkan_video::renderer::post::{Bloom, Effect, FilmGrain, Fxaa, PostEffect, Vignette};
+use vulkan_video::renderer::capture::CaptureSettings;
+use vulkan_video::camera::controller::OrbitController;
+use vulkan_video::glam::{Mat4, Vec3};
+
+use winit::event::VirtualKeyCode;
+
+use anyhow::Result;
+
+struct Viewer {
+    scene: Scene,
+    resources: Option<SceneResources>,
+    camera: Camera,
+    orbit: OrbitController,
+    /// Index into the nodes with a camera, `None` while orbiting.
+    scene_camera: Option<usize>,
+    lights: Vec<WorldLight>,
+}
+
+impl App for Viewer {
+    fn init(&mut self, renderer: &mut VulkanRenderer) -> Result<()> {
+        self.resources = Some(SceneResources::new(renderer, &self.scene)?);
+
+        let extent = renderer.target.extent();
+        self.camera.aspect = extent.width as f32 / extent.height as f32;
+
+        renderer.set_post_effects(vec![
+            PostEffect::new(Effect::Bloom(Bloom::default())),
+            PostEffect::new(Effect::Fxaa(Fxaa::default())),
+            PostEffect::new(Effect::Vignette(Vignette::default())),
+            PostEffect::new(Effect::FilmGrain(FilmGrain::default())),
+        ]);
+
+        Ok(())
+    }
+
+    fn update(&mut self, renderer: &mut VulkanRenderer, input: &mut Input, dt: f32) {
+        let camera_nodes: Vec<usize> = (0..self.scene.nodes.len())
+            .filter(|&node| self.scene.nodes[node].camera.is_some())
+            .collect();
+
+        if input.key_pressed(VirtualKeyCode::C) {
+            self.scene_camera = match self.scene_camera {
+                None if !camera_nodes.is_empty() => Some(0),
+                Some(index) if index + 1 < camera_nodes.len() => Some(index + 1),
+                _ => None,
+            };
+        }
+
+        let toggles = [VirtualKeyCode::Key1, VirtualKeyCode::Key2, VirtualKeyCode::Key3, VirtualKeyCode::Key4];
+
+        for (effect, key) in renderer.post.effects.iter_mut().zip(toggles) {
+            if input.key_pressed(key) {
+                effect.enabled = !effect.enabled;
+            }
+        }
+
+        if input.key_pressed(VirtualKeyCode::O) {
+            renderer.culling.settings.occlusion = !renderer.culling.settings.occlusion;
+        }
+
+        match self.scene_camera {
+            None => self.orbit.update(&mut self.camera, input, dt),
+            Some(index) => {
+                if let Some(mut camera) = self.scene.camera(camera_nodes[index], self.camera.aspect) {
+                    camera.reverse_z = self.camera.reverse_z;
+
+                    self.camera = camera;
+                }
+            }
+        }
+    }
+
+    fn render(&mut self, renderer: &mut VulkanRenderer, frame: &Frame, _alpha: f32) -> Result<()> {
+        renderer.set_camera(frame, &self.camera)?;
+        renderer.set_lights(frame, &self.lights)?;
+
+        if let Some(resources) = &self.resources {
+            resources.draw_shadow_casters(renderer, &self.scene, Mat4::IDENTITY);
+            resources.queue(renderer, &self.scene, Mat4::IDENTITY);
+        }
+
+        Ok(())
+    }
+
+    fn shutdown(&mut self, renderer: &mut VulkanRenderer) {
+        if let Some(mut resources) = self.resources.take() {
+            unsafe {
+                renderer.main_device.logical_device.device_wait_idle().unwrap();
+
+                resources.cleanup(&renderer.main_device);
+            };
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+
+    let capture = CaptureSettings::from_args(&mut args)?;
+
+    let config = RendererConfig::load(&mut args)?;
+
+    let path = match args.iter().find(|arg| !arg.starts_with("--")) {
+        None => anyhow::bail!(
+            "Usage: viewer [--config <file>] [--hdr] [--<setting> <value>] \
+            [--record <out.y4m or out.png> [--frames <count>] [--fps <rate>]] <model file>"
+        ),
+        Some(path) => config.asset(path)
+    };
+
+    let scene = Scene::load(&path)?;
+
+    println!(
+        "{}: {} nodes, {} meshes, {} materials, {} textures, {} cameras, {} lights",
+        path.display(),
+        scene.nodes.len(),
+        scene.meshes.len(),
+        scene.materials.len(),
+        scene.textures.len(),
+        scene.cameras.len(),
+        scene.lights.len(),
+    );
+
+    let mut lights = scene.world_lights();
+
+    // files without lights get a sun, so there's something to see:
+    if lights.is_empty() {
+        lights.push(WorldLight {
+            kind: LightKind::Directional,
+            position: Vec3::ZERO,
+            direction: Vec3::new(-0.3, -0.8, -0.5).normalize(),
+            color: Vec3::ONE,
+            intensity: 3.0,
+            range: None,
+            casts_shadows: true,
+        });
+    }
+
+    let mut renderer = VulkanRenderer::with_config(config)?;
+
+    if let Some(capture) = capture {
+        renderer.start_capture(capture)?;
+    }
+
+    let viewer = Viewer {
+        scene,
+        resources: None,
+        camera: Camera::perspective(60f32.to_radians(), 1.0, 0.01, f32::INFINITY),
+        orbit: OrbitController::new(Vec3::ZERO, 3.0),
+        scene_camera: None,
+        lights,
+    };
+
+    AppRunner::new(renderer)?.run(viewer)
+}
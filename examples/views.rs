@@ -0,0 +1,62 @@
+//! The triangle drawn into offscreen views of other sizes next to the renderer's own image, the headless
+//! counterpart of the `windows` example. The views share the device and resources, and each of them is checked
+//! against a golden image.
+//!
+//! Runs as part of `cargo test`, so it needs a Vulkan device but no display.
+
+mod common;
+
+use common::{check_golden, pixel};
+
+use vulkan_video::{VulkanRenderer, golden, vk};
+
+use anyhow::Result;
+
+const EXTENT: vk::Extent2D = vk::Extent2D { width: 64, height: 64 };
+
+const VIEWS: [vk::Extent2D; 2] = [
+    vk::Extent2D { width: 96, height: 48 },
+    vk::Extent2D { width: 32, height: 64 },
+];
+
+fn main() -> Result<()> {
+    let mut renderer = VulkanRenderer::headless_with_config(EXTENT, golden::config()?)?;
+
+    let views = VIEWS.iter()
+        .map(|&extent| renderer.create_offscreen_view(extent))
+        .collect::<Result<Vec<usize>>>()?;
+
+    // a few rounds, so that the frames wait for each other's per frame resources:
+    for _ in 0..3 {
+        renderer.draw_frame()?;
+
+        for &view in &views {
+            let frame = renderer.begin_view_frame(view)?;
+
+            renderer.draw_triangle(&frame);
+
+            renderer.end_frame(frame)?;
+        }
+    }
+
+    // the renderer's own image is what it would be without the views:
+    check_golden("triangle", EXTENT, &renderer.read_pixels()?)?;
+
+    for (index, (&view, extent)) in views.iter().zip(VIEWS).enumerate() {
+        let pixels = renderer.read_view_pixels(view)?;
+
+        assert_eq!(pixels.len(), (extent.width * extent.height * 4) as usize, "view {} has the wrong size", index);
+
+        // the triangle covers the upper middle of every view, the corners keep the clear color:
+        let inside = pixel(&pixels, extent, extent.width / 2, extent.height * 3 / 8);
+
+        assert_ne!(&inside[..3], &[0, 0, 0], "the triangle wasn't drawn into view {}", index);
+        assert_eq!(pixel(&pixels, extent, 0, 0), [0, 0, 0, 255], "the corner of view {} isn't the clear color", index);
+
+        check_golden(&format!("views_{}", index), extent, &pixels)?;
+    }
+
+    println!("views: the triangle in {} views besides the renderer's own", views.len());
+
+    Ok(())
+}
@@ -0,0 +1,77 @@
+//! The triangle in as many windows as you like, drawn with one device. `N` opens another window, every other one
+//! only draws ten frames a second, and closing one of them leaves the rest.
+
+use vulkan_video::{App, AppRunner, Frame, Input, VulkanRenderer};
+use vulkan_video::renderer::config::WindowConfig;
+
+use winit::event::VirtualKeyCode;
+use winit::event_loop::EventLoopWindowTarget;
+use winit::window::WindowId;
+
+use std::time::Duration;
+
+use anyhow::Result;
+
+#[derive(Default)]
+struct Windows {
+    opened: u32,
+    open_another: bool,
+}
+
+impl App for Windows {
+    fn update(&mut self, _renderer: &mut VulkanRenderer, input: &mut Input, _dt: f32) {
+        if input.key_pressed(VirtualKeyCode::N) {
+            self.open_another = true;
+        }
+    }
+
+    fn update_windows(&mut self, renderer: &mut VulkanRenderer, event_loop: &EventLoopWindowTarget<()>) -> Result<()> {
+        if !self.open_another {
+            return Ok(());
+        }
+
+        self.open_another = false;
+        self.opened += 1;
+
+        let config = WindowConfig {
+            title: format!("Window {}", self.opened),
+            width: 400,
+            height: 300,
+            ..Default::default()
+        };
+
+        let id = renderer.create_window(event_loop, config)?;
+
+        if self.opened.is_multiple_of(2) {
+            if let Some(view) = renderer.view_mut(id) {
+                view.frame_interval = Duration::from_millis(100);
+            }
+        }
+
+        println!("Opened {:?}, {} windows besides the first", id, renderer.views.len());
+
+        Ok(())
+    }
+
+    fn render(&mut self, renderer: &mut VulkanRenderer, frame: &Frame, _alpha: f32) -> Result<()> {
+        renderer.draw_triangle(frame);
+
+        Ok(())
+    }
+
+    fn render_window(&mut self, renderer: &mut VulkanRenderer, _window: WindowId, frame: &Frame, _alpha: f32) -> Result<()> {
+        renderer.draw_triangle(frame);
+
+        Ok(())
+    }
+
+    fn window_closed(&mut self, renderer: &mut VulkanRenderer, window: WindowId) {
+        println!("Closed {:?}, {} windows besides the first", window, renderer.views.len());
+    }
+}
+
+fn main() -> Result<()> {
+    let renderer = VulkanRenderer::new()?;
+
+    AppRunner::new(renderer)?.run(Windows::default())
+}
@@ -0,0 +1,61 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform Effect {
+    vec2 texel_size;
+    uint frame;
+    uint pass;
+    // threshold, intensity, radius:
+    vec4 params;
+} effect;
+
+layout(location = 0) in vec2 i_uv;
+
+layout(location = 0) out vec4 o_color;
+
+// the first pass keeps what's bright and blurs it sideways, the second blurs it downwards and is added to the frame:
+const uint PASS_PREFILTER = 0u;
+const uint PASS_COMPOSITE = 1u;
+
+// a 9 tap gaussian, from the middle out:
+const float WEIGHTS[5] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+
+// fades in over half the threshold below it, so highlights don't pop in and out of the bloom:
+vec3 prefilter(vec3 color) {
+    float threshold = effect.params.x;
+    float knee = threshold * 0.5;
+
+    float brightness = max(color.r, max(color.g, color.b));
+
+    float soft = clamp(brightness - threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee + 0.0001);
+
+    return color * max(soft, brightness - threshold) / max(brightness, 0.0001);
+}
+
+void main() {
+    vec2 direction = effect.pass == PASS_PREFILTER
+        ? vec2(effect.texel_size.x, 0.0)
+        : vec2(0.0, effect.texel_size.y);
+
+    direction *= effect.params.z;
+
+    vec3 sum = vec3(0.0);
+
+    for (int i = -4; i <= 4; i++) {
+        vec3 color = texture(source, i_uv + direction * float(i)).rgb;
+
+        if (effect.pass == PASS_PREFILTER) {
+            color = prefilter(color);
+        }
+
+        sum += color * WEIGHTS[abs(i)];
+    }
+
+    if (effect.pass == PASS_COMPOSITE) {
+        sum *= effect.params.y;
+    }
+
+    o_color = vec4(sum, 1.0);
+}
@@ -0,0 +1,26 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform Effect {
+    vec2 texel_size;
+    uint frame;
+    uint pass;
+    // strength:
+    vec4 params;
+} effect;
+
+layout(location = 0) in vec2 i_uv;
+
+layout(location = 0) out vec4 o_color;
+
+void main() {
+    // nothing in the middle, growing towards the edges where red and blue are apart by the strength:
+    vec2 offset = (i_uv - 0.5) * effect.params.x;
+
+    float red = texture(source, i_uv - offset).r;
+    float green = texture(source, i_uv).g;
+    float blue = texture(source, i_uv + offset).b;
+
+    o_color = vec4(red, green, blue, 1.0);
+}
@@ -0,0 +1,42 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform Effect {
+    vec2 texel_size;
+    uint frame;
+    uint pass;
+    // intensity:
+    vec4 params;
+} effect;
+
+layout(location = 0) in vec2 i_uv;
+
+layout(location = 0) out vec4 o_color;
+
+// Jarzynski and Olano's pcg3d, good enough noise without any textures:
+uvec3 pcg3d(uvec3 v) {
+    v = v * 1664525u + 1013904223u;
+
+    v.x += v.y * v.z;
+    v.y += v.z * v.x;
+    v.z += v.x * v.y;
+
+    v ^= v >> 16u;
+
+    v.x += v.y * v.z;
+    v.y += v.z * v.x;
+    v.z += v.x * v.y;
+
+    return v;
+}
+
+void main() {
+    vec3 color = texture(source, i_uv).rgb;
+
+    // new grain every frame, from -0.5 to 0.5 so brightness stays the same on average:
+    float noise = float(pcg3d(uvec3(gl_FragCoord.xy, effect.frame)).x) / 4294967295.0 - 0.5;
+
+    // like on film, the grain is as strong as the light that hit it:
+    o_color = vec4(max(color * (1.0 + noise * effect.params.x), 0.0), 1.0);
+}
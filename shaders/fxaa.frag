@@ -0,0 +1,72 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform Effect {
+    vec2 texel_size;
+    uint frame;
+    uint pass;
+    // edge threshold, minimum edge threshold, longest blur in texels:
+    vec4 params;
+} effect;
+
+layout(location = 0) in vec2 i_uv;
+
+layout(location = 0) out vec4 o_color;
+
+const float REDUCE_MUL = 1.0 / 8.0;
+const float REDUCE_MIN = 1.0 / 128.0;
+
+// the frame isn't tonemapped yet, so luma is compressed to keep highlights from counting as edges everywhere:
+float luma(vec3 color) {
+    float l = dot(color, vec3(0.2126, 0.7152, 0.0722));
+
+    return sqrt(l / (1.0 + l));
+}
+
+vec3 fetch(vec2 offset) {
+    return texture(source, i_uv + offset * effect.texel_size).rgb;
+}
+
+void main() {
+    vec3 middle = fetch(vec2(0.0));
+
+    float luma_m = luma(middle);
+    float luma_nw = luma(fetch(vec2(-1.0, -1.0)));
+    float luma_ne = luma(fetch(vec2(1.0, -1.0)));
+    float luma_sw = luma(fetch(vec2(-1.0, 1.0)));
+    float luma_se = luma(fetch(vec2(1.0, 1.0)));
+
+    float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    // flat enough to leave alone:
+    if (luma_max - luma_min < max(effect.params.y, luma_max * effect.params.x)) {
+        o_color = vec4(middle, 1.0);
+
+        return;
+    }
+
+    // blur along the edge, which runs across the steepest change in luma:
+    vec2 direction = vec2(
+        (luma_sw + luma_se) - (luma_nw + luma_ne),
+        (luma_nw + luma_sw) - (luma_ne + luma_se)
+    );
+
+    float reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+    float scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+
+    direction = clamp(direction * scale, vec2(-effect.params.z), vec2(effect.params.z));
+
+    vec3 near = 0.5 * (fetch(direction * (1.0 / 3.0 - 0.5)) + fetch(direction * (2.0 / 3.0 - 0.5)));
+    vec3 far = near * 0.5 + 0.25 * (fetch(direction * -0.5) + fetch(direction * 0.5));
+
+    // the wider blur crossed another edge:
+    float luma_far = luma(far);
+
+    if (luma_far < luma_min || luma_far > luma_max) {
+        o_color = vec4(near, 1.0);
+    } else {
+        o_color = vec4(far, 1.0);
+    }
+}
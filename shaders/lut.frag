@@ -0,0 +1,40 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+layout(set = 0, binding = 1) uniform sampler3D lut;
+
+layout(push_constant) uniform Effect {
+    vec2 texel_size;
+    uint frame;
+    uint pass;
+    // strength:
+    vec4 params;
+} effect;
+
+layout(location = 0) in vec2 i_uv;
+
+layout(location = 0) out vec4 o_color;
+
+// the LUT is indexed and filled in log2 space, from black up to 64, same as in post.rs:
+const float LUT_TOE = 1.0 / 1024.0;
+const float LUT_STOPS = 16.0;
+
+vec3 lut_encode(vec3 color) {
+    return clamp(log2(1.0 + max(color, 0.0) / LUT_TOE) / LUT_STOPS, 0.0, 1.0);
+}
+
+vec3 lut_decode(vec3 encoded) {
+    return LUT_TOE * (exp2(encoded * LUT_STOPS) - 1.0);
+}
+
+void main() {
+    vec3 color = texture(source, i_uv).rgb;
+
+    // through the middle of the first and last texels, so the ends of the range land on the ends of the LUT:
+    float size = float(textureSize(lut, 0).x);
+    vec3 coords = lut_encode(color) * (size - 1.0) / size + 0.5 / size;
+
+    vec3 graded = lut_decode(texture(lut, coords).rgb);
+
+    o_color = vec4(mix(color, graded, effect.params.x), 1.0);
+}
@@ -0,0 +1,23 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 projection;
+    mat4 view_projection;
+    vec4 position;
+} camera;
+
+layout(push_constant) uniform Model {
+    mat4 model;
+} model;
+
+layout(location = 0) in vec3 i_position;
+layout(location = 5) in vec4 i_color;
+
+layout(location = 0) out vec4 o_color;
+
+void main() {
+    gl_Position = camera.view_projection * model.model * vec4(i_position, 1.0);
+
+    o_color = i_color;
+}
@@ -0,0 +1,62 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform sampler2D font;
+
+layout(push_constant) uniform Overlay {
+    vec2 scale;
+    vec2 translate;
+    uint encoding;
+    // how many nits 1.0 is on HDR outputs:
+    float paper_white;
+} overlay;
+
+layout(location = 0) in vec2 i_uv;
+layout(location = 1) in vec4 i_color;
+
+layout(location = 0) out vec4 o_color;
+
+const uint ENCODING_LINEAR = 0u;
+const uint ENCODING_SRGB = 1u;
+const uint ENCODING_PQ = 2u;
+const uint ENCODING_SCRGB = 3u;
+
+const mat3 REC709_TO_REC2020 = mat3(
+    0.6274, 0.0691, 0.0164,
+    0.3293, 0.9195, 0.0880,
+    0.0433, 0.0114, 0.8956
+);
+
+vec3 decode_srgb(vec3 color) {
+    vec3 low = color / 12.92;
+    vec3 high = pow((color + 0.055) / 1.055, vec3(2.4));
+
+    return mix(high, low, lessThanEqual(color, vec3(0.04045)));
+}
+
+// SMPTE ST 2084, from nits:
+vec3 encode_pq(vec3 nits) {
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+
+    vec3 y = pow(clamp(nits / 10000.0, 0.0, 1.0), vec3(m1));
+
+    return pow((c1 + c2 * y) / (1.0 + c3 * y), vec3(m2));
+}
+
+void main() {
+    // the colors are sRGB encoded already, which is what the target wants unless it encodes on its own or is HDR:
+    vec4 color = i_color * texture(font, i_uv);
+
+    if (overlay.encoding == ENCODING_LINEAR) {
+        color.rgb = decode_srgb(color.rgb);
+    } else if (overlay.encoding == ENCODING_PQ) {
+        color.rgb = encode_pq(REC709_TO_REC2020 * decode_srgb(color.rgb) * overlay.paper_white);
+    } else if (overlay.encoding == ENCODING_SCRGB) {
+        color.rgb = decode_srgb(color.rgb) * overlay.paper_white / 80.0;
+    }
+
+    o_color = color;
+}
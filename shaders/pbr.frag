@@ -0,0 +1,344 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 projection;
+    mat4 view_projection;
+    vec4 position;
+} camera;
+
+layout(set = 1, binding = 0) uniform Material {
+    vec4 base_color_factor;
+    vec4 emissive_factor;
+    float metallic_factor;
+    float roughness_factor;
+    float normal_scale;
+    float occlusion_strength;
+    float alpha_cutoff;
+    uint alpha_mode;
+    // a bit per texture, set when it is read with the second UV set:
+    uint uv_sets;
+} material;
+
+layout(set = 1, binding = 1) uniform sampler2D base_color_texture;
+layout(set = 1, binding = 2) uniform sampler2D metallic_roughness_texture;
+layout(set = 1, binding = 3) uniform sampler2D normal_texture;
+layout(set = 1, binding = 4) uniform sampler2D occlusion_texture;
+layout(set = 1, binding = 5) uniform sampler2D emissive_texture;
+
+layout(set = 2, binding = 0) uniform Lighting {
+    // clusters along x, y and z, then how many lights fit in one:
+    uvec4 grid;
+    uint directional_count;
+    uint light_count;
+    vec2 tile_size;
+    vec2 screen_size;
+    float near;
+    float far;
+} lighting;
+
+struct Light {
+    vec3 position;
+    float range;
+    vec3 direction;
+    uint kind;
+    vec3 color;
+    float spot_scale;
+    float spot_offset;
+    // the shadow map layer, -1 without one:
+    int shadow;
+};
+
+layout(std430, set = 2, binding = 1) readonly buffer Lights {
+    Light lights[];
+};
+
+layout(std430, set = 2, binding = 2) readonly buffer ClusterCounts {
+    uint light_counts[];
+};
+
+layout(std430, set = 2, binding = 3) readonly buffer ClusterLights {
+    uint light_indices[];
+};
+
+layout(set = 3, binding = 0) uniform Shadows {
+    mat4 cascades[4];
+    mat4 spots[4];
+    vec4 cascade_splits;
+    vec4 cascade_texel_sizes;
+    vec4 spot_texel_scales;
+    uint cascade_count;
+    uint pcf_radius;
+    float normal_bias;
+    uint debug_cascades;
+} shadows;
+
+// the cascades, then a layer per spot light:
+layout(set = 3, binding = 1) uniform sampler2DArrayShadow shadow_map;
+
+layout(location = 0) in vec3 i_world_position;
+layout(location = 1) in vec3 i_normal;
+layout(location = 2) in vec4 i_tangent;
+layout(location = 3) in vec2 i_uv0;
+layout(location = 4) in vec2 i_uv1;
+layout(location = 5) in vec4 i_color;
+
+layout(location = 0) out vec4 o_color;
+
+const uint ALPHA_MODE_OPAQUE = 0u;
+const uint ALPHA_MODE_MASK = 1u;
+
+const uint BASE_COLOR = 0u;
+const uint METALLIC_ROUGHNESS = 1u;
+const uint NORMAL = 2u;
+const uint OCCLUSION = 3u;
+const uint EMISSIVE = 4u;
+
+const uint LIGHT_DIRECTIONAL = 0u;
+const uint LIGHT_SPOT = 2u;
+
+const uint MAX_CASCADES = 4u;
+
+const vec3 CASCADE_TINTS[4] = vec3[](
+    vec3(1.0, 0.2, 0.2),
+    vec3(0.2, 1.0, 0.2),
+    vec3(0.2, 0.2, 1.0),
+    vec3(1.0, 1.0, 0.2)
+);
+
+const float PI = 3.14159265359;
+
+// so that surfaces facing away from every light aren't pitch black:
+const vec3 AMBIENT = vec3(0.03);
+
+vec2 uv(uint texture_index) {
+    return (material.uv_sets & (1u << texture_index)) != 0u ? i_uv1 : i_uv0;
+}
+
+float distribution_ggx(float n_dot_h, float alpha) {
+    float alpha2 = alpha * alpha;
+    float d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+
+    return alpha2 / (PI * d * d);
+}
+
+// height correlated Smith, already divided by 4 n.l n.v:
+float visibility_smith(float n_dot_v, float n_dot_l, float alpha) {
+    float alpha2 = alpha * alpha;
+
+    float v = n_dot_l * sqrt(n_dot_v * n_dot_v * (1.0 - alpha2) + alpha2);
+    float l = n_dot_v * sqrt(n_dot_l * n_dot_l * (1.0 - alpha2) + alpha2);
+
+    return 0.5 / max(v + l, 1e-5);
+}
+
+vec3 fresnel_schlick(float v_dot_h, vec3 f0) {
+    return f0 + (1.0 - f0) * pow(1.0 - v_dot_h, 5.0);
+}
+
+// how much of the light coming from `l` leaves towards `v`, n.l included:
+vec3 brdf(vec3 n, vec3 v, vec3 l, vec3 base_color, float metallic, float roughness) {
+    vec3 h = normalize(v + l);
+
+    float n_dot_l = clamp(dot(n, l), 0.0, 1.0);
+    float n_dot_v = clamp(abs(dot(n, v)), 1e-4, 1.0);
+    float n_dot_h = clamp(dot(n, h), 0.0, 1.0);
+    float v_dot_h = clamp(dot(v, h), 0.0, 1.0);
+
+    float alpha = roughness * roughness;
+
+    vec3 f0 = mix(vec3(0.04), base_color, metallic);
+    vec3 f = fresnel_schlick(v_dot_h, f0);
+
+    vec3 specular = f * distribution_ggx(n_dot_h, alpha) * visibility_smith(n_dot_v, n_dot_l, alpha);
+    vec3 diffuse = (1.0 - f) * (1.0 - metallic) * base_color / PI;
+
+    return (diffuse + specular) * n_dot_l;
+}
+
+// the light arriving at the surface from `lights[i]`, and the direction it comes from in `l`:
+vec3 incoming_light(uint i, out vec3 l) {
+    if (lights[i].kind == LIGHT_DIRECTIONAL) {
+        l = -lights[i].direction;
+
+        return lights[i].color;
+    }
+
+    vec3 to_light = lights[i].position - i_world_position;
+    float distance2 = max(dot(to_light, to_light), 1e-4);
+
+    l = to_light * inversesqrt(distance2);
+
+    // inverse square, smoothly cut off at the range:
+    float range_ratio = distance2 / (lights[i].range * lights[i].range);
+    float attenuation = clamp(1.0 - range_ratio * range_ratio, 0.0, 1.0) / distance2;
+
+    if (lights[i].kind == LIGHT_SPOT) {
+        float cone = clamp(dot(lights[i].direction, -l) * lights[i].spot_scale + lights[i].spot_offset, 0.0, 1.0);
+
+        attenuation *= cone * cone;
+    }
+
+    return lights[i].color * attenuation;
+}
+
+float view_depth() {
+    return -(camera.view * vec4(i_world_position, 1.0)).z;
+}
+
+uint cluster_index() {
+    float depth = max(view_depth(), lighting.near);
+
+    float slice = floor(log(depth / lighting.near) / log(lighting.far / lighting.near) * float(lighting.grid.z));
+
+    uint z = uint(clamp(slice, 0.0, float(lighting.grid.z - 1u)));
+    uvec2 xy = min(uvec2(gl_FragCoord.xy / lighting.tile_size), lighting.grid.xy - 1u);
+
+    return xy.x + xy.y * lighting.grid.x + z * lighting.grid.x * lighting.grid.y;
+}
+
+// the cascade the fragment is in, `shadows.cascade_count` past the last one:
+uint cascade_index() {
+    float depth = view_depth();
+
+    for (uint i = 0u; i < shadows.cascade_count; i++) {
+        if (depth < shadows.cascade_splits[i]) {
+            return i;
+        }
+    }
+
+    return shadows.cascade_count;
+}
+
+// averages the depth comparisons around `uv`:
+float filter_shadow(vec2 uv, float layer, float depth) {
+    int radius = int(shadows.pcf_radius);
+    vec2 texel = 1.0 / vec2(textureSize(shadow_map, 0).xy);
+
+    float lit = 0.0;
+
+    for (int x = -radius; x <= radius; x++) {
+        for (int y = -radius; y <= radius; y++) {
+            lit += texture(shadow_map, vec4(uv + vec2(x, y) * texel, layer, depth));
+        }
+    }
+
+    float side = float(2 * radius + 1);
+
+    return lit / (side * side);
+}
+
+// how much of light `i` reaches the surface, 1 when nothing is in the way:
+float shadow(uint i, vec3 n, vec3 l) {
+    int map = lights[i].shadow;
+
+    if (map < 0) {
+        return 1.0;
+    }
+
+    // receivers are moved off the surface along the normal, the more the light grazes it the further:
+    float n_dot_l = clamp(dot(n, l), 0.0, 1.0);
+    float offset = sqrt(1.0 - n_dot_l * n_dot_l) * shadows.normal_bias;
+
+    vec4 clip;
+    float layer;
+
+    if (lights[i].kind == LIGHT_DIRECTIONAL) {
+        uint cascade = cascade_index();
+
+        if (cascade >= shadows.cascade_count) {
+            return 1.0;
+        }
+
+        vec3 position = i_world_position + n * offset * shadows.cascade_texel_sizes[cascade];
+
+        clip = shadows.cascades[cascade] * vec4(position, 1.0);
+        layer = float(cascade);
+    } else {
+        uint spot = uint(map) - MAX_CASCADES;
+
+        float texel_size = distance(lights[i].position, i_world_position) * shadows.spot_texel_scales[spot];
+        vec3 position = i_world_position + n * offset * texel_size;
+
+        clip = shadows.spots[spot] * vec4(position, 1.0);
+        layer = float(map);
+    }
+
+    vec3 ndc = clip.xyz / clip.w;
+
+    // nothing outside of the map was rendered into it:
+    if (clip.w <= 0.0 || ndc.z < 0.0 || ndc.z > 1.0) {
+        return 1.0;
+    }
+
+    return filter_shadow(ndc.xy * 0.5 + 0.5, layer, ndc.z);
+}
+
+vec3 surface_normal() {
+    vec3 n = normalize(i_normal);
+    vec3 t = normalize(i_tangent.xyz - n * dot(n, i_tangent.xyz));
+    vec3 b = cross(n, t) * i_tangent.w;
+
+    vec3 sampled = texture(normal_texture, uv(NORMAL)).xyz * 2.0 - 1.0;
+    sampled.xy *= material.normal_scale;
+
+    n = normalize(mat3(t, b, n) * sampled);
+
+    return gl_FrontFacing ? n : -n;
+}
+
+void main() {
+    vec4 base_color = material.base_color_factor * i_color * texture(base_color_texture, uv(BASE_COLOR));
+
+    if (material.alpha_mode == ALPHA_MODE_MASK && base_color.a < material.alpha_cutoff) {
+        discard;
+    }
+
+    if (material.alpha_mode == ALPHA_MODE_OPAQUE) {
+        base_color.a = 1.0;
+    }
+
+    // roughness is in green and metalness in blue:
+    vec4 metallic_roughness = texture(metallic_roughness_texture, uv(METALLIC_ROUGHNESS));
+
+    float metallic = clamp(material.metallic_factor * metallic_roughness.b, 0.0, 1.0);
+    float roughness = clamp(material.roughness_factor * metallic_roughness.g, 0.045, 1.0);
+
+    float occlusion = mix(1.0, texture(occlusion_texture, uv(OCCLUSION)).r, material.occlusion_strength);
+    vec3 emissive = material.emissive_factor.rgb * texture(emissive_texture, uv(EMISSIVE)).rgb;
+
+    vec3 n = surface_normal();
+    vec3 v = normalize(camera.position.xyz - i_world_position);
+
+    vec3 color = vec3(0.0);
+    vec3 l;
+
+    for (uint i = 0u; i < lighting.directional_count; i++) {
+        vec3 light = incoming_light(i, l) * shadow(i, n, l);
+
+        color += brdf(n, v, l, base_color.rgb, metallic, roughness) * light;
+    }
+
+    uint cluster = cluster_index();
+
+    for (uint i = 0u; i < light_counts[cluster]; i++) {
+        uint light_index = light_indices[cluster * lighting.grid.w + i];
+
+        vec3 light = incoming_light(light_index, l) * shadow(light_index, n, l);
+
+        color += brdf(n, v, l, base_color.rgb, metallic, roughness) * light;
+    }
+
+    color += AMBIENT * base_color.rgb * occlusion;
+    color += emissive;
+
+    if (shadows.debug_cascades != 0u) {
+        uint cascade = cascade_index();
+
+        if (cascade < shadows.cascade_count) {
+            color = mix(color, CASCADE_TINTS[cascade], 0.3);
+        }
+    }
+
+    o_color = vec4(color, base_color.a);
+}
@@ -0,0 +1,51 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 projection;
+    mat4 view_projection;
+    vec4 position;
+} camera;
+
+layout(push_constant) uniform Model {
+    mat4 model;
+} model;
+
+layout(location = 0) in vec3 i_position;
+layout(location = 1) in vec3 i_normal;
+layout(location = 2) in vec4 i_tangent;
+layout(location = 3) in vec2 i_uv0;
+layout(location = 4) in vec2 i_uv1;
+layout(location = 5) in vec4 i_color;
+
+// per instance, draws that aren't instanced get an identity matrix and white:
+layout(location = 6) in vec4 i_instance_model_0;
+layout(location = 7) in vec4 i_instance_model_1;
+layout(location = 8) in vec4 i_instance_model_2;
+layout(location = 9) in vec4 i_instance_model_3;
+layout(location = 10) in vec4 i_instance_color;
+
+layout(location = 0) out vec3 o_world_position;
+layout(location = 1) out vec3 o_normal;
+layout(location = 2) out vec4 o_tangent;
+layout(location = 3) out vec2 o_uv0;
+layout(location = 4) out vec2 o_uv1;
+layout(location = 5) out vec4 o_color;
+
+void main() {
+    mat4 model_matrix = model.model * mat4(i_instance_model_0, i_instance_model_1, i_instance_model_2, i_instance_model_3);
+
+    vec4 world_position = model_matrix * vec4(i_position, 1.0);
+
+    // keeps normals perpendicular to surfaces under non-uniform scaling:
+    mat3 normal_matrix = transpose(inverse(mat3(model_matrix)));
+
+    o_world_position = world_position.xyz;
+    o_normal = normalize(normal_matrix * i_normal);
+    o_tangent = vec4(normalize(mat3(model_matrix) * i_tangent.xyz), i_tangent.w);
+    o_uv0 = i_uv0;
+    o_uv1 = i_uv1;
+    o_color = i_color * i_instance_color;
+
+    gl_Position = camera.view_projection * world_position;
+}
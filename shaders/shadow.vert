@@ -0,0 +1,17 @@
+#version 450
+
+// the light's view projection and the model matrix, already multiplied together:
+layout(push_constant) uniform Caster {
+    mat4 light_model_view_projection;
+} caster;
+
+layout(location = 0) in vec3 i_position;
+
+layout(location = 6) in vec4 i_instance_model_0;
+layout(location = 7) in vec4 i_instance_model_1;
+layout(location = 8) in vec4 i_instance_model_2;
+layout(location = 9) in vec4 i_instance_model_3;
+
+void main() {
+    gl_Position = caster.light_model_view_projection * mat4(i_instance_model_0, i_instance_model_1, i_instance_model_2, i_instance_model_3) * vec4(i_position, 1.0);
+}
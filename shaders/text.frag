@@ -0,0 +1,73 @@
+#version 450
+
+// distances to the glyphs' outlines, 0.5 on them and more inside:
+layout(set = 0, binding = 0) uniform sampler2D atlas;
+
+layout(push_constant) uniform Text {
+    vec2 scale;
+    vec2 translate;
+    uint encoding;
+    // how many nits 1.0 is on HDR outputs:
+    float paper_white;
+} text;
+
+layout(location = 0) in vec2 i_uv;
+layout(location = 1) in vec4 i_color;
+
+layout(location = 0) out vec4 o_color;
+
+const uint ENCODING_LINEAR = 0u;
+const uint ENCODING_SRGB = 1u;
+const uint ENCODING_PQ = 2u;
+const uint ENCODING_SCRGB = 3u;
+
+const mat3 REC709_TO_REC2020 = mat3(
+    0.6274, 0.0691, 0.0164,
+    0.3293, 0.9195, 0.0880,
+    0.0433, 0.0114, 0.8956
+);
+
+vec3 decode_srgb(vec3 color) {
+    vec3 low = color / 12.92;
+    vec3 high = pow((color + 0.055) / 1.055, vec3(2.4));
+
+    return mix(high, low, lessThanEqual(color, vec3(0.04045)));
+}
+
+// SMPTE ST 2084, from nits:
+vec3 encode_pq(vec3 nits) {
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+
+    vec3 y = pow(clamp(nits / 10000.0, 0.0, 1.0), vec3(m1));
+
+    return pow((c1 + c2 * y) / (1.0 + c3 * y), vec3(m2));
+}
+
+void main() {
+    float field = texture(atlas, i_uv).r;
+
+    // about a pixel of antialiasing, however big the glyph is on screen:
+    float width = max(fwidth(field) * 0.5, 0.0001);
+    float coverage = smoothstep(0.5 - width, 0.5 + width, field);
+
+    if (coverage <= 0.0) {
+        discard;
+    }
+
+    vec4 color = vec4(i_color.rgb, i_color.a * coverage);
+
+    // linear for the HDR target world space text is drawn into, like the overlay's for the screen:
+    if (text.encoding == ENCODING_LINEAR) {
+        color.rgb = decode_srgb(color.rgb);
+    } else if (text.encoding == ENCODING_PQ) {
+        color.rgb = encode_pq(REC709_TO_REC2020 * decode_srgb(color.rgb) * text.paper_white);
+    } else if (text.encoding == ENCODING_SCRGB) {
+        color.rgb = decode_srgb(color.rgb) * text.paper_white / 80.0;
+    }
+
+    o_color = color;
+}
@@ -0,0 +1,23 @@
+#version 450
+
+layout(push_constant) uniform Text {
+    // from pixels to clip space:
+    vec2 scale;
+    vec2 translate;
+    uint encoding;
+    float paper_white;
+} text;
+
+layout(location = 0) in vec3 i_position;
+layout(location = 1) in vec2 i_uv;
+layout(location = 2) in vec4 i_color;
+
+layout(location = 0) out vec2 o_uv;
+layout(location = 1) out vec4 o_color;
+
+void main() {
+    o_uv = i_uv;
+    o_color = i_color;
+
+    gl_Position = vec4(i_position.xy * text.scale + text.translate, 0.0, 1.0);
+}
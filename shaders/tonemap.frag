@@ -0,0 +1,150 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform sampler2D hdr_color;
+
+layout(push_constant) uniform Tonemap {
+    uint tonemapper;
+    uint encoding;
+    float exposure;
+    // how many nits 1.0 is on HDR outputs, and the most they show:
+    float paper_white;
+    float peak_brightness;
+} tonemap;
+
+layout(location = 0) in vec2 i_uv;
+
+layout(location = 0) out vec4 o_color;
+
+const uint TONEMAPPER_NONE = 0u;
+const uint TONEMAPPER_REINHARD = 1u;
+const uint TONEMAPPER_ACES = 2u;
+const uint TONEMAPPER_AGX = 3u;
+
+const uint ENCODING_LINEAR = 0u;
+const uint ENCODING_SRGB = 1u;
+const uint ENCODING_PQ = 2u;
+const uint ENCODING_SCRGB = 3u;
+
+// Stephen Hill's fit of the ACES reference rendering and output transforms:
+const mat3 ACES_INPUT = mat3(
+    0.59719, 0.07600, 0.02840,
+    0.35458, 0.90834, 0.13383,
+    0.04823, 0.01566, 0.83777
+);
+
+const mat3 ACES_OUTPUT = mat3(
+     1.60475, -0.10208, -0.00327,
+    -0.53108,  1.10813, -0.07276,
+    -0.07367, -0.00605,  1.07602
+);
+
+// the AgX base look, as approximated by Benjamin Wrensch:
+const mat3 AGX_INSET = mat3(
+    0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+    0.0784335999999992, 0.878468636469772, 0.0784336,
+    0.0792237451477643, 0.0791661274605434, 0.879142973793104
+);
+
+const mat3 AGX_OUTSET = mat3(
+    1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+    -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+    -0.0990297440797205, -0.0989611768448433, 1.15107367264116
+);
+
+const float AGX_MIN_EV = -12.47393;
+const float AGX_MAX_EV = 4.026069;
+
+const mat3 REC709_TO_REC2020 = mat3(
+    0.6274, 0.0691, 0.0164,
+    0.3293, 0.9195, 0.0880,
+    0.0433, 0.0114, 0.8956
+);
+
+vec3 reinhard(vec3 color) {
+    return color / (1.0 + color);
+}
+
+vec3 aces(vec3 color) {
+    vec3 v = ACES_INPUT * color;
+
+    vec3 a = v * (v + 0.0245786) - 0.000090537;
+    vec3 b = v * (0.983729 * v + 0.4329510) + 0.238081;
+
+    return clamp(ACES_OUTPUT * (a / b), 0.0, 1.0);
+}
+
+vec3 agx(vec3 color) {
+    vec3 v = AGX_INSET * color;
+
+    v = clamp(log2(max(v, vec3(1e-10))), AGX_MIN_EV, AGX_MAX_EV);
+    v = (v - AGX_MIN_EV) / (AGX_MAX_EV - AGX_MIN_EV);
+
+    // the sigmoid, as a polynomial:
+    vec3 v2 = v * v;
+    vec3 v4 = v2 * v2;
+
+    v = 15.5 * v4 * v2 - 40.14 * v4 * v + 31.96 * v4 - 6.868 * v2 * v + 0.4298 * v2 + 0.1191 * v - 0.00232;
+
+    // the curve ends up display encoded, back to linear:
+    return pow(max(AGX_OUTSET * v, vec3(0.0)), vec3(2.2));
+}
+
+// stretches a curve that ends at 1.0 to end at white instead, darker colors stay about where they were.
+// Reinhard comes out as Reinhard with that white:
+vec3 expand(vec3 color, float white) {
+    color = clamp(color, 0.0, 1.0);
+
+    return color / (1.0 - color * (1.0 - 1.0 / white));
+}
+
+vec3 encode_srgb(vec3 color) {
+    color = clamp(color, 0.0, 1.0);
+
+    vec3 low = color * 12.92;
+    vec3 high = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+
+    return mix(high, low, lessThanEqual(color, vec3(0.0031308)));
+}
+
+// SMPTE ST 2084, from nits:
+vec3 encode_pq(vec3 nits) {
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+
+    vec3 y = pow(clamp(nits / 10000.0, 0.0, 1.0), vec3(m1));
+
+    return pow((c1 + c2 * y) / (1.0 + c3 * y), vec3(m2));
+}
+
+void main() {
+    vec3 color = texture(hdr_color, i_uv).rgb * tonemap.exposure;
+
+    if (tonemap.tonemapper == TONEMAPPER_REINHARD) {
+        color = reinhard(color);
+    } else if (tonemap.tonemapper == TONEMAPPER_ACES) {
+        color = aces(color);
+    } else if (tonemap.tonemapper == TONEMAPPER_AGX) {
+        color = agx(color);
+    }
+
+    // HDR outputs go past paper white, up to the display's peak:
+    bool hdr = tonemap.encoding == ENCODING_PQ || tonemap.encoding == ENCODING_SCRGB;
+
+    if (hdr && tonemap.tonemapper != TONEMAPPER_NONE) {
+        color = expand(color, max(tonemap.peak_brightness / tonemap.paper_white, 1.0));
+    }
+
+    if (tonemap.encoding == ENCODING_SRGB) {
+        color = encode_srgb(color);
+    } else if (tonemap.encoding == ENCODING_PQ) {
+        color = encode_pq(REC709_TO_REC2020 * max(color, vec3(0.0)) * tonemap.paper_white);
+    } else if (tonemap.encoding == ENCODING_SCRGB) {
+        // scRGB has 1.0 at 80 nits:
+        color = color * tonemap.paper_white / 80.0;
+    }
+
+    o_color = vec4(color, 1.0);
+}
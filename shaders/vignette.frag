@@ -0,0 +1,27 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform Effect {
+    vec2 texel_size;
+    uint frame;
+    uint pass;
+    // intensity, radius:
+    vec4 params;
+} effect;
+
+layout(location = 0) in vec2 i_uv;
+
+layout(location = 0) out vec4 o_color;
+
+void main() {
+    vec3 color = texture(source, i_uv).rgb;
+
+    // round on any aspect ratio, 0 in the middle and 1 in the corners:
+    vec2 aspect = vec2(effect.texel_size.y / effect.texel_size.x, 1.0);
+    float from_middle = length((i_uv - 0.5) * aspect) / length(0.5 * aspect);
+
+    float darkening = effect.params.x * smoothstep(effect.params.y, 1.0, from_middle);
+
+    o_color = vec4(color * (1.0 - darkening), 1.0);
+}
@@ -0,0 +1,361 @@
+use crate::renderer::VulkanRenderer;
+use crate::renderer::frame::Frame;
+use crate::renderer::diagnostics::DeviceLost;
+use crate::input::Input;
+use crate::debug_ui::DebugUi;
+
+use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use winit::event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget};
+use winit::window::WindowId;
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::Result;
+
+/// Hooks an application implements to be driven by an [`AppRunner`].
+///
+/// `update` runs once per rendered frame with the measured frame time, `fixed_update` runs
+/// zero or more times per frame with the runner's fixed timestep.
+pub trait App {
+    fn init(&mut self, _renderer: &mut VulkanRenderer) -> Result<()> {
+        Ok(())
+    }
+
+    fn update(&mut self, _renderer: &mut VulkanRenderer, _input: &mut Input, _dt: f32) {}
+
+    fn fixed_update(&mut self, _renderer: &mut VulkanRenderer, _input: &mut Input, _dt: f32) {}
+
+    /// Records the frame. `alpha` is how far the simulation is between the last two fixed updates,
+    /// for interpolating what was simulated.
+    fn render(&mut self, renderer: &mut VulkanRenderer, frame: &Frame, alpha: f32) -> Result<()>;
+
+    /// Adds the app's own windows to the debug UI, while it's shown. Runs after `update`, before the frame begins.
+    fn debug_ui(&mut self, _renderer: &mut VulkanRenderer, _ui: &imgui::Ui) {}
+
+    /// Opens and closes windows with [`VulkanRenderer::create_window`] and `destroy_window`, runs once per
+    /// iteration of the event loop before anything is drawn.
+    fn update_windows(&mut self, _renderer: &mut VulkanRenderer, _event_loop: &EventLoopWindowTarget<()>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Records the frame of a window opened with [`VulkanRenderer::create_window`], whenever its frame interval
+    /// has passed. `alpha` is the one of the renderer's window's last frame.
+    fn render_window(
+        &mut self,
+        _renderer: &mut VulkanRenderer,
+        _window: WindowId,
+        _frame: &Frame,
+        _alpha: f32
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// A window opened with `create_window` was closed by the user, the renderer already destroyed it.
+    fn window_closed(&mut self, _renderer: &mut VulkanRenderer, _window: WindowId) {}
+
+    fn shutdown(&mut self, _renderer: &mut VulkanRenderer) {}
+}
+
+/// Owns the winit event loop and drives an [`App`] with it.
+pub struct AppRunner {
+    pub renderer: VulkanRenderer,
+    pub input: Input,
+    pub fixed_timestep: Duration,
+    /// Longer frames are clamped to this, so that a hitch doesn't run the simulation for ages.
+    pub max_frame_time: Duration,
+    /// Takes a screenshot of the frame it's pressed in, `None` turns that off.
+    pub screenshot_key: Option<VirtualKeyCode>,
+    /// Where screenshots go, named after when they were taken.
+    pub screenshot_directory: PathBuf,
+    /// Switches between a window and borderless fullscreen, `None` turns that off.
+    pub fullscreen_key: Option<VirtualKeyCode>,
+    /// Drawn over the renderer's window, created with [`AppRunner::with_debug_ui`].
+    pub debug_ui: Option<DebugUi>,
+    /// Shows and hides the debug UI.
+    pub debug_ui_key: Option<VirtualKeyCode>,
+    event_loop: EventLoop<()>,
+}
+
+impl AppRunner {
+    pub fn new(mut renderer: VulkanRenderer) -> Result<AppRunner> {
+        let event_loop = renderer.acquire_event_loop()?;
+
+        Ok(AppRunner {
+            renderer,
+            input: Input::new(),
+            fixed_timestep: Duration::from_secs_f64(1.0 / 60.0),
+            max_frame_time: Duration::from_millis(250),
+            screenshot_key: Some(VirtualKeyCode::F12),
+            screenshot_directory: PathBuf::from("screenshots"),
+            fullscreen_key: Some(VirtualKeyCode::F11),
+            debug_ui: None,
+            debug_ui_key: Some(VirtualKeyCode::F1),
+            event_loop,
+        })
+    }
+
+    /// Draws the [`debug_ui`](crate::debug_ui) panels over every frame of the renderer's window, along with the
+    /// app's own from [`App::debug_ui`].
+    pub fn with_debug_ui(mut self) -> Result<AppRunner> {
+        self.debug_ui = Some(DebugUi::new(&mut self.renderer)?);
+
+        Ok(self)
+    }
+
+    pub fn run<A: App + 'static>(self, mut app: A) -> ! {
+        let AppRunner {
+            renderer,
+            mut input,
+            fixed_timestep,
+            max_frame_time,
+            screenshot_key,
+            screenshot_directory,
+            fullscreen_key,
+            mut debug_ui,
+            debug_ui_key,
+            event_loop,
+        } = self;
+
+        // winit never returns from `run`, so the renderer is dropped by hand when the loop is destroyed. Errors in the
+        // loop are reported and exit it instead of panicking, so that it happens then too:
+        let mut renderer = Some(renderer);
+
+        if let Err(err) = app.init(renderer.as_mut().unwrap()) {
+            panic!("Failed to initialize the app: {:?}", err);
+        }
+
+        let mut last_frame = Instant::now();
+        let mut accumulator = Duration::ZERO;
+        let mut alpha = 0.0;
+
+        event_loop.run(move |event, event_loop, control_flow| {
+            let renderer_ref = match renderer.as_mut() {
+                None => return,
+                Some(renderer) => renderer
+            };
+
+            let main_window = renderer_ref.window.as_ref().map(|window| window.window.id());
+
+            match event {
+                Event::WindowEvent {
+                    event: WindowEvent::CloseRequested,
+                    window_id,
+                } => {
+                    if Some(window_id) == main_window {
+                        *control_flow = ControlFlow::Exit;
+                    } else if let Err(err) = renderer_ref.destroy_window(window_id) {
+                        renderer_ref.report(err.context("Failed to close a window"));
+
+                        *control_flow = ControlFlow::Exit;
+                    } else {
+                        app.window_closed(renderer_ref, window_id);
+                    }
+                },
+                Event::WindowEvent { event, window_id } => {
+                    match &event {
+                        WindowEvent::Resized(_) => renderer_ref.window_resized(window_id),
+                        WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                            renderer_ref.scale_factor_changed(window_id, *scale_factor)
+                        },
+                        WindowEvent::KeyboardInput {
+                            input: KeyboardInput { state: ElementState::Pressed, virtual_keycode: Some(key), .. },
+                            ..
+                        } if Some(*key) == debug_ui_key => {
+                            if let Some(debug_ui) = &mut debug_ui {
+                                debug_ui.visible = !debug_ui.visible;
+                            }
+                        },
+                        _ => {}
+                    }
+
+                    // what went to the debug UI doesn't go to the app:
+                    let taken = Some(window_id) == main_window
+                        && debug_ui.as_mut().is_some_and(|debug_ui| debug_ui.handle_event(&event));
+
+                    if !taken {
+                        input.handle_window_event(&event)
+                    }
+                },
+                Event::DeviceEvent { event, .. } => input.handle_device_event(&event),
+                Event::MainEventsCleared => {
+                    if let Err(err) = app.update_windows(renderer_ref, event_loop) {
+                        renderer_ref.report(err.context("Failed to open or close windows"));
+
+                        *control_flow = ControlFlow::Exit;
+
+                        return;
+                    }
+
+                    if let Some(window) = &renderer_ref.window {
+                        window.window.request_redraw();
+                    }
+
+                    // the other windows are drawn as often as they ask for:
+                    let now = Instant::now();
+
+                    for view in renderer_ref.views.iter().filter(|view| view.due(now)) {
+                        if let Some(window) = &view.window {
+                            window.window.request_redraw();
+                        }
+                    }
+                },
+                Event::RedrawRequested(window_id) if Some(window_id) != main_window => {
+                    let minimized = renderer_ref.view(window_id)
+                        .and_then(|view| view.window.as_ref())
+                        .is_none_or(|window| window.is_minimized());
+
+                    if minimized {
+                        return;
+                    }
+
+                    if let Err(err) = Self::render_window(&mut app, renderer_ref, window_id, alpha) {
+                        match err.downcast_ref::<DeviceLost>() {
+                            Some(_) => if !Self::recover_device(renderer_ref, err) {
+                                // without a device there's nothing to draw with, what's left of the renderer goes:
+                                renderer.take();
+
+                                *control_flow = ControlFlow::Exit;
+                            },
+                            None => {
+                                renderer_ref.report(err.context(format!("Failed to draw a frame of {:?}", window_id)));
+
+                                *control_flow = ControlFlow::Exit;
+                            }
+                        }
+                    }
+                },
+                Event::RedrawRequested(_) => {
+                    let now = Instant::now();
+                    let frame_time = (now - last_frame).min(max_frame_time);
+                    last_frame = now;
+
+                    accumulator += frame_time;
+
+                    while accumulator >= fixed_timestep {
+                        app.fixed_update(renderer_ref, &mut input, fixed_timestep.as_secs_f32());
+
+                        accumulator -= fixed_timestep;
+                    }
+
+                    alpha = accumulator.as_secs_f32() / fixed_timestep.as_secs_f32();
+
+                    app.update(renderer_ref, &mut input, frame_time.as_secs_f32());
+
+                    if screenshot_key.is_some_and(|key| input.key_pressed(key)) {
+                        Self::screenshot(renderer_ref, &screenshot_directory);
+                    }
+
+                    if fullscreen_key.is_some_and(|key| input.key_pressed(key)) {
+                        if let Err(err) = renderer_ref.toggle_fullscreen() {
+                            renderer_ref.report(err.context("Failed to toggle fullscreen"));
+                        }
+                    }
+
+                    // a minimized window can't be drawn into, the app keeps running without frames:
+                    let minimized = renderer_ref.window.as_ref().is_some_and(|window| window.is_minimized());
+
+                    if minimized {
+                        input.end_frame();
+
+                        return;
+                    }
+
+                    if let Some(debug_ui) = &mut debug_ui {
+                        debug_ui.prepare(renderer_ref, frame_time, |ui, renderer| app.debug_ui(renderer, ui));
+                    }
+
+                    if let Err(err) = Self::render(&mut app, renderer_ref, debug_ui.as_mut(), alpha) {
+                        match err.downcast_ref::<DeviceLost>() {
+                            Some(_) => if !Self::recover_device(renderer_ref, err) {
+                                // without a device there's nothing to draw with, what's left of the renderer goes:
+                                renderer.take();
+
+                                *control_flow = ControlFlow::Exit;
+
+                                return;
+                            },
+                            None => {
+                                renderer_ref.report(err.context("Failed to draw a frame"));
+
+                                *control_flow = ControlFlow::Exit;
+
+                                return;
+                            }
+                        }
+                    }
+
+                    input.end_frame();
+
+                    // a capture that has all of its frames ends the app, the renderer writes them when it's dropped:
+                    if renderer_ref.capture_done() {
+                        *control_flow = ControlFlow::Exit;
+                    }
+                },
+                Event::LoopDestroyed => {
+                    app.shutdown(renderer_ref);
+
+                    renderer.take();
+                },
+                _ => {}
+            }
+        })
+    }
+
+    /// Reports a lost device and creates it again, `false` when that failed too and was reported.
+    fn recover_device(renderer: &mut VulkanRenderer, lost: anyhow::Error) -> bool {
+        renderer.report(lost);
+
+        match renderer.recreate_device() {
+            Err(err) => {
+                renderer.report(err.context("Failed to create the device again after it was lost"));
+
+                false
+            },
+            Ok(()) => true
+        }
+    }
+
+    /// Saves the frame that's about to be rendered, failing to is handed to the renderer's error hook.
+    fn screenshot(renderer: &mut VulkanRenderer, directory: &Path) {
+        let millis = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+
+        if let Err(err) = renderer.capture_screenshot(directory.join(format!("screenshot_{}", millis))) {
+            renderer.report(err.context("Failed to take a screenshot"));
+        }
+    }
+
+    fn render<A: App>(
+        app: &mut A,
+        renderer: &mut VulkanRenderer,
+        debug_ui: Option<&mut DebugUi>,
+        alpha: f32
+    ) -> Result<()> {
+        let frame = renderer.begin_frame()?;
+
+        let mut rendered = app.render(renderer, &frame, alpha);
+
+        if let (Ok(()), Some(debug_ui)) = (&rendered, debug_ui) {
+            rendered = debug_ui.draw(renderer, &frame);
+        }
+
+        // the frame is ended either way, so the next one can begin, the first error is the one returned:
+        let ended = renderer.end_frame(frame);
+
+        rendered.and(ended)
+    }
+
+    fn render_window<A: App>(app: &mut A, renderer: &mut VulkanRenderer, window: WindowId, alpha: f32) -> Result<()> {
+        let frame = renderer.begin_window_frame(window)?;
+
+        // the frame is ended either way, which hands the window its parts back:
+        let rendered = app.render_window(renderer, window, &frame, alpha);
+
+        let ended = renderer.end_frame(frame);
+
+        rendered.and(ended)
+    }
+}
@@ -0,0 +1,132 @@
+//! Cameras moved around by [`Input`].
+
+use crate::camera::Camera;
+use crate::input::Input;
+
+use winit::event::{MouseButton, VirtualKeyCode};
+
+use glam::{EulerRot, Quat, Vec3};
+
+use std::f32::consts::FRAC_PI_2;
+
+/// Keeps the camera from flipping over when looking straight up or down.
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+/// Moves freely with WASD, Space and LShift, and looks around with the mouse while the cursor
+/// is grabbed or the right mouse button is held.
+pub struct FlyController {
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Units per second.
+    pub speed: f32,
+    /// Radians per pixel of mouse movement.
+    pub sensitivity: f32,
+    pub boost: f32,
+}
+
+impl FlyController {
+    pub fn new() -> FlyController {
+        FlyController {
+            yaw: 0.0,
+            pitch: 0.0,
+            speed: 4.0,
+            sensitivity: 0.003,
+            boost: 4.0,
+        }
+    }
+
+    /// Picks up where the camera is currently looking.
+    pub fn from_camera(camera: &Camera) -> FlyController {
+        let (yaw, pitch, _) = camera.rotation.to_euler(EulerRot::YXZ);
+
+        FlyController {
+            yaw,
+            pitch,
+            ..Self::new()
+        }
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, input: &Input, dt: f32) {
+        if input.cursor_grabbed || input.button_held(MouseButton::Right) {
+            self.yaw -= input.mouse_delta.0 as f32 * self.sensitivity;
+            self.pitch -= input.mouse_delta.1 as f32 * self.sensitivity;
+
+            self.pitch = self.pitch.clamp(-MAX_PITCH, MAX_PITCH);
+        }
+
+        camera.rotation = Quat::from_euler(EulerRot::YXZ, self.yaw, self.pitch, 0.0);
+
+        let mut direction = Vec3::ZERO;
+
+        let axes = [
+            (VirtualKeyCode::W, camera.forward()),
+            (VirtualKeyCode::S, -camera.forward()),
+            (VirtualKeyCode::D, camera.right()),
+            (VirtualKeyCode::A, -camera.right()),
+            (VirtualKeyCode::Space, Vec3::Y),
+            (VirtualKeyCode::LShift, -Vec3::Y),
+        ];
+
+        for (key, axis) in axes {
+            if input.key_held(key) {
+                direction += axis;
+            }
+        }
+
+        let speed = if input.key_held(VirtualKeyCode::LControl) {
+            self.speed * self.boost
+        } else {
+            self.speed
+        };
+
+        camera.position += direction.normalize_or_zero() * speed * dt;
+    }
+}
+
+impl Default for FlyController {
+    fn default() -> FlyController {
+        Self::new()
+    }
+}
+
+/// Circles around a target while the left mouse button is dragged and zooms with the scroll wheel.
+pub struct OrbitController {
+    pub target: Vec3,
+    pub distance: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Radians per pixel of mouse movement.
+    pub sensitivity: f32,
+    /// How much of the distance one line of scrolling takes away.
+    pub zoom_speed: f32,
+    pub min_distance: f32,
+}
+
+impl OrbitController {
+    pub fn new(target: Vec3, distance: f32) -> OrbitController {
+        OrbitController {
+            target,
+            distance,
+            yaw: 0.0,
+            pitch: -0.4,
+            sensitivity: 0.005,
+            zoom_speed: 0.1,
+            min_distance: 0.1,
+        }
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, input: &Input, _dt: f32) {
+        if input.cursor_grabbed || input.button_held(MouseButton::Left) {
+            self.yaw -= input.mouse_delta.0 as f32 * self.sensitivity;
+            self.pitch -= input.mouse_delta.1 as f32 * self.sensitivity;
+
+            self.pitch = self.pitch.clamp(-MAX_PITCH, MAX_PITCH);
+        }
+
+        self.distance *= 1.0 - input.scroll.1 * self.zoom_speed;
+        self.distance = self.distance.max(self.min_distance);
+
+        camera.rotation = Quat::from_euler(EulerRot::YXZ, self.yaw, self.pitch, 0.0);
+        camera.position = self.target - camera.forward() * self.distance;
+    }
+}
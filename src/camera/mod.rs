@@ -0,0 +1,148 @@
+//! Cameras following Vulkan's clip space conventions: `y` points down and depth goes from 0 to 1.
+//!
+//! The world itself is right handed with `y` up, cameras look down their local `-z`.
+
+pub mod controller;
+
+use glam::{Mat4, Quat, Vec2, Vec3, Vec4};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    /// `far` may be infinite.
+    Perspective { fov_y: f32, near: f32, far: f32 },
+    /// `height` is how much of the world fits vertically, the width follows from the aspect ratio.
+    Orthographic { height: f32, near: f32, far: f32 },
+}
+
+#[derive(Clone, Debug)]
+pub struct Camera {
+    pub position: Vec3,
+    pub rotation: Quat,
+    pub projection: Projection,
+    pub aspect: f32,
+    /// Maps near to 1 and far to 0, which spreads depth precision a lot better. Pipelines have to agree on it.
+    pub reverse_z: bool,
+}
+
+/// What shaders get to see of a camera, laid out for std140.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct CameraUniform {
+    pub view: Mat4,
+    pub projection: Mat4,
+    pub view_projection: Mat4,
+    pub position: Vec4,
+}
+
+impl Camera {
+    pub fn perspective(fov_y: f32, aspect: f32, near: f32, far: f32) -> Camera {
+        Camera {
+            position: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            projection: Projection::Perspective { fov_y, near, far },
+            aspect,
+            reverse_z: false,
+        }
+    }
+
+    pub fn orthographic(height: f32, aspect: f32, near: f32, far: f32) -> Camera {
+        Camera {
+            position: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            projection: Projection::Orthographic { height, near, far },
+            aspect,
+            reverse_z: false,
+        }
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        self.rotation * -Vec3::Z
+    }
+
+    pub fn right(&self) -> Vec3 {
+        self.rotation * Vec3::X
+    }
+
+    pub fn up(&self) -> Vec3 {
+        self.rotation * Vec3::Y
+    }
+
+    pub fn look_at(&mut self, target: Vec3, up: Vec3) {
+        let view = Mat4::look_at_rh(self.position, target, up);
+
+        self.rotation = Quat::from_mat4(&view.inverse());
+    }
+
+    pub fn view(&self) -> Mat4 {
+        Mat4::from_rotation_translation(self.rotation, self.position).inverse()
+    }
+
+    pub fn projection(&self) -> Mat4 {
+        let mut projection = match self.projection {
+            Projection::Perspective { fov_y, near, far } => match (far.is_infinite(), self.reverse_z) {
+                (false, false) => Mat4::perspective_rh(fov_y, self.aspect, near, far),
+                (false, true) => Mat4::perspective_rh(fov_y, self.aspect, far, near),
+                (true, false) => Mat4::perspective_infinite_rh(fov_y, self.aspect, near),
+                (true, true) => Mat4::perspective_infinite_reverse_rh(fov_y, self.aspect, near),
+            },
+            Projection::Orthographic { height, near, far } => {
+                let half_height = height / 2.0;
+                let half_width = half_height * self.aspect;
+
+                let (near, far) = if self.reverse_z { (far, near) } else { (near, far) };
+
+                Mat4::orthographic_rh(-half_width, half_width, -half_height, half_height, near, far)
+            },
+        };
+
+        // glam's projections have y pointing up in clip space, Vulkan's points down:
+        projection.y_axis.y = -projection.y_axis.y;
+
+        projection
+    }
+
+    pub fn view_projection(&self) -> Mat4 {
+        self.projection() * self.view()
+    }
+
+    /// The corners of the part of the view frustum between two view space depths, in world space.
+    ///
+    /// The first four are at `near`, the last four at `far`.
+    pub fn frustum_corners(&self, near: f32, far: f32) -> [Vec3; 8] {
+        let inverse_projection = self.projection().inverse();
+        let world = self.view().inverse();
+
+        let mut corners = [Vec3::ZERO; 8];
+
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let ndc = Vec2::new(
+                if i & 1 == 0 { -1.0 } else { 1.0 },
+                if i & 2 == 0 { -1.0 } else { 1.0 },
+            );
+
+            let depth = if i & 4 == 0 { near } else { far };
+
+            // two points on the ray through the corner, away from the planes since either can be at infinity:
+            let start = inverse_projection.project_point3(ndc.extend(0.25));
+            let end = inverse_projection.project_point3(ndc.extend(0.75));
+
+            let point = start + (end - start) * ((-depth - start.z) / (end.z - start.z));
+
+            *corner = world.transform_point3(point);
+        }
+
+        corners
+    }
+
+    pub fn uniform(&self) -> CameraUniform {
+        let view = self.view();
+        let projection = self.projection();
+
+        CameraUniform {
+            view,
+            projection,
+            view_projection: projection * view,
+            position: self.position.extend(1.0),
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! Dear ImGui on top of the renderer's frames, for tweaking settings and looking at stats while an app runs.
+//!
+//! [`DebugUi`] owns the ImGui context. winit's events are fed into it with [`DebugUi::handle_event`], the UI is
+//! built with [`DebugUi::prepare`] before the frame begins and handed to the renderer's
+//! [`overlay`](crate::renderer::overlay) with [`DebugUi::draw`] while it's recorded. Settings are changed while no
+//! frame is being recorded, so the [`panels`] can do anything the renderer allows between frames.
+//!
+//! An [`AppRunner`](crate::AppRunner) does all of that when it's created with a debug UI, apps add windows of
+//! their own in [`App::debug_ui`](crate::App::debug_ui).
+
+pub mod panels;
+
+use panels::{FrameTimes, Panels};
+
+use crate::input::PIXELS_PER_LINE;
+use crate::renderer::VulkanRenderer;
+use crate::renderer::frame::Frame;
+use crate::renderer::overlay::{OverlayDraw, OverlayDrawData, OverlayVertex};
+
+use ash::vk;
+
+use imgui::{BackendFlags, DrawCmd, DrawData, Key, MouseButton as ImguiButton, TextureId};
+
+use winit::event::{ElementState, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+
+use std::time::Duration;
+
+use anyhow::Result;
+
+/// The size of the default font at a scale factor of 1, in pixels.
+const FONT_SIZE: f32 = 13.0;
+
+pub struct DebugUi {
+    pub context: imgui::Context,
+    /// Nothing is drawn or fed into ImGui while it's hidden, the frame times are still kept.
+    pub visible: bool,
+    /// Which of the built-in panels are shown.
+    pub panels: Panels,
+    pub frame_times: FrameTimes,
+    /// Physical pixels per logical one, ImGui lays itself out in logical ones.
+    scale_factor: f64,
+    /// Built by `prepare`, waiting for the frame.
+    draw_data: Option<OverlayDrawData>,
+}
+
+impl DebugUi {
+    /// Creates the context and the renderer's overlay with the font atlas, rasterized for the window's DPI.
+    pub fn new(renderer: &mut VulkanRenderer) -> Result<DebugUi> {
+        let scale_factor = renderer.scale_factor();
+
+        let mut context = imgui::Context::create();
+
+        // nothing is saved between runs:
+        context.set_ini_filename(None);
+        context.set_platform_name(Some(format!("vulkan-video {}", env!("CARGO_PKG_VERSION"))));
+
+        // draws with more than 64K vertices are split with vertex offsets, which the overlay has:
+        context.io_mut().backend_flags.insert(BackendFlags::RENDERER_HAS_VTX_OFFSET);
+
+        // the font is rasterized at the window's DPI and scaled back, so text stays sharp:
+        context.fonts().add_font(&[imgui::FontSource::DefaultFontData {
+            config: Some(imgui::FontConfig {
+                size_pixels: FONT_SIZE * scale_factor as f32,
+                ..Default::default()
+            }),
+        }]);
+
+        context.io_mut().font_global_scale = 1.0 / scale_factor as f32;
+
+        let font = context.fonts().build_rgba32_texture();
+
+        let font_extent = vk::Extent2D {
+            width: font.width,
+            height: font.height,
+        };
+
+        renderer.create_overlay(font_extent, font.data.to_vec())?;
+
+        context.fonts().tex_id = TextureId::new(0);
+
+        Ok(DebugUi {
+            context,
+            visible: true,
+            panels: Panels::default(),
+            frame_times: FrameTimes::new(),
+            scale_factor,
+            draw_data: None,
+        })
+    }
+
+    /// Feeds an event of the renderer's window into ImGui. Returns whether ImGui took it, the app shouldn't react
+    /// to mouse or keyboard input that went to the UI.
+    pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        if !self.visible {
+            return false;
+        }
+
+        let io = self.context.io_mut();
+
+        match event {
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                self.scale_factor = *scale_factor;
+
+                false
+            },
+            WindowEvent::ModifiersChanged(modifiers) => {
+                io.add_key_event(Key::ModCtrl, modifiers.ctrl());
+                io.add_key_event(Key::ModShift, modifiers.shift());
+                io.add_key_event(Key::ModAlt, modifiers.alt());
+                io.add_key_event(Key::ModSuper, modifiers.logo());
+
+                io.want_capture_keyboard
+            },
+            WindowEvent::KeyboardInput { input, .. } => {
+                if let Some(key) = input.virtual_keycode.and_then(Self::key) {
+                    io.add_key_event(key, input.state == ElementState::Pressed);
+                }
+
+                io.want_capture_keyboard
+            },
+            WindowEvent::ReceivedCharacter(character) => {
+                if !character.is_control() {
+                    io.add_input_character(*character);
+                }
+
+                io.want_capture_keyboard
+            },
+            WindowEvent::CursorMoved { position, .. } => {
+                let position = position.to_logical::<f32>(self.scale_factor);
+
+                io.add_mouse_pos_event([position.x, position.y]);
+
+                io.want_capture_mouse
+            },
+            WindowEvent::CursorLeft { .. } => {
+                io.add_mouse_pos_event([-f32::MAX, -f32::MAX]);
+
+                false
+            },
+            WindowEvent::MouseInput { button, state, .. } => {
+                let button = match button {
+                    MouseButton::Left => ImguiButton::Left,
+                    MouseButton::Right => ImguiButton::Right,
+                    MouseButton::Middle => ImguiButton::Middle,
+                    MouseButton::Other(_) => return false,
+                };
+
+                io.add_mouse_button_event(button, *state == ElementState::Pressed);
+
+                io.want_capture_mouse
+            },
+            WindowEvent::MouseWheel { delta, .. } => {
+                let [x, y] = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => [*x, *y],
+                    MouseScrollDelta::PixelDelta(position) => [
+                        position.x as f32 / PIXELS_PER_LINE,
+                        position.y as f32 / PIXELS_PER_LINE,
+                    ],
+                };
+
+                io.add_mouse_wheel_event([x, y]);
+
+                io.want_capture_mouse
+            },
+            _ => false,
+        }
+    }
+
+    /// Builds the UI for the coming frame: the built-in panels, then what `build` adds.
+    ///
+    /// Runs before the frame begins, so `build` and the panels can change settings that can't change while a frame
+    /// is recorded. `dt` goes into the frame times even while the UI is hidden.
+    pub fn prepare<F: FnOnce(&imgui::Ui, &mut VulkanRenderer)>(
+        &mut self,
+        renderer: &mut VulkanRenderer,
+        dt: Duration,
+        build: F
+    ) {
+        self.frame_times.push(dt);
+
+        self.draw_data = None;
+
+        if !self.visible {
+            return;
+        }
+
+        let extent = renderer.target.extent();
+
+        let io = self.context.io_mut();
+
+        io.display_size = [
+            (extent.width as f64 / self.scale_factor) as f32,
+            (extent.height as f64 / self.scale_factor) as f32,
+        ];
+        io.display_framebuffer_scale = [self.scale_factor as f32; 2];
+        io.update_delta_time(dt);
+
+        let ui = self.context.new_frame();
+
+        self.panels.draw(ui, renderer, &self.frame_times);
+
+        build(ui, renderer);
+
+        self.draw_data = Some(Self::overlay_draw_data(self.context.render()));
+    }
+
+    /// Hands what `prepare` built to the renderer, to be drawn over the frame.
+    pub fn draw(&mut self, renderer: &mut VulkanRenderer, frame: &Frame) -> Result<()> {
+        match self.draw_data.take() {
+            None => Ok(()),
+            Some(data) => renderer.draw_overlay(frame, data)
+        }
+    }
+
+    /// ImGui's draw lists, one after the other in a single vertex and index buffer.
+    pub fn overlay_draw_data(draw_data: &DrawData) -> OverlayDrawData {
+        let mut data = OverlayDrawData {
+            vertices: Vec::with_capacity(draw_data.total_vtx_count as usize),
+            indices: Vec::with_capacity(draw_data.total_idx_count as usize),
+            draws: vec![],
+            origin: draw_data.display_pos,
+            size: draw_data.display_size,
+            scale: draw_data.framebuffer_scale,
+        };
+
+        for draw_list in draw_data.draw_lists() {
+            let first_vertex = data.vertices.len();
+            let first_index = data.indices.len();
+
+            data.vertices.extend(draw_list.vtx_buffer().iter().map(|vertex| OverlayVertex {
+                position: vertex.pos,
+                uv: vertex.uv,
+                color: vertex.col,
+            }));
+
+            data.indices.extend_from_slice(draw_list.idx_buffer());
+
+            for command in draw_list.commands() {
+                match command {
+                    // only the font atlas is uploaded, images of other textures aren't drawn:
+                    DrawCmd::Elements { count, cmd_params } if cmd_params.texture_id.id() == 0 => {
+                        data.draws.push(OverlayDraw {
+                            clip_rect: cmd_params.clip_rect,
+                            first_index: (first_index + cmd_params.idx_offset) as u32,
+                            index_count: count as u32,
+                            vertex_offset: (first_vertex + cmd_params.vtx_offset) as i32,
+                        });
+                    },
+                    // the overlay's state is the same for every draw, and callbacks can't record into its pass:
+                    _ => {}
+                }
+            }
+        }
+
+        data
+    }
+
+    fn key(key: VirtualKeyCode) -> Option<Key> {
+        let key = match key {
+            VirtualKeyCode::Tab => Key::Tab,
+            VirtualKeyCode::Left => Key::LeftArrow,
+            VirtualKeyCode::Right => Key::RightArrow,
+            VirtualKeyCode::Up => Key::UpArrow,
+            VirtualKeyCode::Down => Key::DownArrow,
+            VirtualKeyCode::PageUp => Key::PageUp,
+            VirtualKeyCode::PageDown => Key::PageDown,
+            VirtualKeyCode::Home => Key::Home,
+            VirtualKeyCode::End => Key::End,
+            VirtualKeyCode::Insert => Key::Insert,
+            VirtualKeyCode::Delete => Key::Delete,
+            VirtualKeyCode::Back => Key::Backspace,
+            VirtualKeyCode::Space => Key::Space,
+            VirtualKeyCode::Return => Key::Enter,
+            VirtualKeyCode::NumpadEnter => Key::KeypadEnter,
+            VirtualKeyCode::Escape => Key::Escape,
+            VirtualKeyCode::LControl => Key::LeftCtrl,
+            VirtualKeyCode::LShift => Key::LeftShift,
+            VirtualKeyCode::LAlt => Key::LeftAlt,
+            VirtualKeyCode::LWin => Key::LeftSuper,
+            VirtualKeyCode::RControl => Key::RightCtrl,
+            VirtualKeyCode::RShift => Key::RightShift,
+            VirtualKeyCode::RAlt => Key::RightAlt,
+            VirtualKeyCode::RWin => Key::RightSuper,
+            // the shortcuts ImGui's text fields know:
+            VirtualKeyCode::A => Key::A,
+            VirtualKeyCode::C => Key::C,
+            VirtualKeyCode::V => Key::V,
+            VirtualKeyCode::X => Key::X,
+            VirtualKeyCode::Y => Key::Y,
+            VirtualKeyCode::Z => Key::Z,
+            _ => return None,
+        };
+
+        Some(key)
+    }
+}
@@ -0,0 +1,275 @@
+//! The debug UI's built-in windows: frame timing, GPU memory and the render settings.
+
+use crate::renderer::VulkanRenderer;
+use crate::renderer::config::{PresentMode, WindowMode};
+use crate::renderer::hdr::Tonemapper;
+use crate::renderer::post::Effect;
+use crate::renderer::shadows::MAX_CASCADES;
+
+use imgui::{Condition, TreeNodeFlags, Ui};
+
+use std::collections::VecDeque;
+use std::ffi;
+use std::time::Duration;
+
+/// How many frames the timing panel looks back on.
+const FRAME_HISTORY: usize = 240;
+
+const MIB: f64 = 1024.0 * 1024.0;
+
+/// The lengths of the last frames, in milliseconds.
+pub struct FrameTimes {
+    pub times: VecDeque<f32>,
+}
+
+impl FrameTimes {
+    pub fn new() -> FrameTimes {
+        FrameTimes {
+            times: VecDeque::with_capacity(FRAME_HISTORY),
+        }
+    }
+
+    pub fn push(&mut self, dt: Duration) {
+        if self.times.len() == FRAME_HISTORY {
+            self.times.pop_front();
+        }
+
+        self.times.push_back(dt.as_secs_f32() * 1000.0);
+    }
+
+    pub fn average(&self) -> f32 {
+        if self.times.is_empty() {
+            return 0.0;
+        }
+
+        self.times.iter().sum::<f32>() / self.times.len() as f32
+    }
+
+    pub fn min(&self) -> f32 {
+        self.times.iter().copied().reduce(f32::min).unwrap_or(0.0)
+    }
+
+    pub fn max(&self) -> f32 {
+        self.times.iter().copied().reduce(f32::max).unwrap_or(0.0)
+    }
+}
+
+impl Default for FrameTimes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Which of the built-in windows are shown, all of them to begin with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Panels {
+    pub frame_timing: bool,
+    pub gpu_memory: bool,
+    pub render_settings: bool,
+}
+
+impl Default for Panels {
+    fn default() -> Self {
+        Panels {
+            frame_timing: true,
+            gpu_memory: true,
+            render_settings: true,
+        }
+    }
+}
+
+impl Panels {
+    /// Draws the shown panels, changing the renderer's settings as they're edited. Settings that fail to apply
+    /// are left as they were, with the error handed to the renderer's error hook.
+    pub fn draw(&mut self, ui: &Ui, renderer: &mut VulkanRenderer, frame_times: &FrameTimes) {
+        if self.frame_timing {
+            ui.window("Frame timing")
+                .position([10.0, 10.0], Condition::FirstUseEver)
+                .always_auto_resize(true)
+                .opened(&mut self.frame_timing)
+                .build(|| Self::frame_timing(ui, renderer, frame_times));
+        }
+
+        if self.gpu_memory {
+            ui.window("GPU memory")
+                .position([10.0, 170.0], Condition::FirstUseEver)
+                .always_auto_resize(true)
+                .opened(&mut self.gpu_memory)
+                .build(|| Self::gpu_memory(ui, renderer));
+        }
+
+        if self.render_settings {
+            ui.window("Render settings")
+                .position([300.0, 10.0], Condition::FirstUseEver)
+                .size([320.0, 480.0], Condition::FirstUseEver)
+                .opened(&mut self.render_settings)
+                .build(|| Self::render_settings(ui, renderer));
+        }
+    }
+
+    fn frame_timing(ui: &Ui, renderer: &VulkanRenderer, frame_times: &FrameTimes) {
+        let average = frame_times.average();
+
+        let fps = if average > 0.0 {
+            1000.0 / average
+        } else {
+            0.0
+        };
+
+        ui.text(format!("{:.1} fps, {:.2} ms", fps, average));
+        ui.text(format!("min {:.2} ms, max {:.2} ms", frame_times.min(), frame_times.max()));
+        ui.text(format!("{} frames submitted", renderer.diagnostics.submitted_frames));
+
+        let times: Vec<f32> = frame_times.times.iter().copied().collect();
+
+        ui.plot_lines("##frame times", &times)
+            .scale_min(0.0)
+            .scale_max(frame_times.max().max(1000.0 / 30.0))
+            .graph_size([FRAME_HISTORY as f32, 60.0])
+            .build();
+    }
+
+    fn gpu_memory(ui: &Ui, renderer: &VulkanRenderer) {
+        let device = &renderer.main_device;
+
+        let name = unsafe {
+            ffi::CStr::from_ptr(device.properties.device_name.as_ptr())
+        };
+
+        ui.text(name.to_string_lossy());
+
+        let usage = device.memory_usage();
+
+        ui.text(format!("{} allocations", usage.allocations));
+        ui.text(format!("{:.1} MiB device local", usage.device_local as f64 / MIB));
+        ui.text(format!("{:.1} MiB host visible", usage.host_visible as f64 / MIB));
+
+        ui.separator();
+
+        for (i, (size, device_local)) in device.memory_heaps().into_iter().enumerate() {
+            let kind = if device_local {
+                "device local"
+            } else {
+                "host"
+            };
+
+            ui.text(format!("heap {}: {:.0} MiB, {}", i, size as f64 / MIB, kind));
+        }
+    }
+
+    fn render_settings(ui: &Ui, renderer: &mut VulkanRenderer) {
+        if ui.collapsing_header("Tonemapping", TreeNodeFlags::DEFAULT_OPEN) {
+            let mut tonemapping = renderer.hdr.tonemapping;
+
+            let tonemappers = [Tonemapper::None, Tonemapper::Reinhard, Tonemapper::Aces, Tonemapper::AgX];
+            let names = ["None", "Reinhard", "ACES", "AgX"];
+
+            let mut current = tonemappers.iter().position(|&tonemapper| tonemapper == tonemapping.tonemapper).unwrap_or(0);
+
+            if ui.combo_simple_string("Tonemapper", &mut current, &names) {
+                tonemapping.tonemapper = tonemappers[current];
+            }
+
+            ui.slider("Exposure", 0.0, 8.0, &mut tonemapping.exposure);
+
+            // only HDR outputs go past it:
+            if renderer.hdr.encoding.is_hdr() {
+                ui.slider("Paper white", 80.0, 1000.0, &mut tonemapping.paper_white);
+                ui.slider("Peak brightness", 80.0, 10000.0, &mut tonemapping.peak_brightness);
+            }
+
+            renderer.set_tonemapping(tonemapping);
+        }
+
+        if ui.collapsing_header("Post effects", TreeNodeFlags::DEFAULT_OPEN) {
+            if renderer.post.effects.is_empty() {
+                ui.text_disabled("none");
+            }
+
+            for (i, post_effect) in renderer.post.effects.iter_mut().enumerate() {
+                let name = match post_effect.effect {
+                    Effect::Bloom(_) => "Bloom",
+                    Effect::Fxaa(_) => "FXAA",
+                    Effect::Vignette(_) => "Vignette",
+                    Effect::ChromaticAberration(_) => "Chromatic aberration",
+                    Effect::FilmGrain(_) => "Film grain",
+                    Effect::ColorGrade(_) => "Color grade",
+                };
+
+                // the same effect can be in the stack more than once:
+                ui.checkbox(format!("{}##{}", name, i), &mut post_effect.enabled);
+            }
+        }
+
+        if ui.collapsing_header("Culling", TreeNodeFlags::empty()) {
+            let mut culling = renderer.culling.settings;
+
+            let frustum = ui.checkbox("Frustum", &mut culling.frustum);
+            let occlusion = ui.checkbox("Occlusion", &mut culling.occlusion);
+
+            if frustum || occlusion {
+                renderer.set_culling(culling);
+            }
+        }
+
+        if ui.collapsing_header("Shadows", TreeNodeFlags::empty()) {
+            let mut shadows = renderer.shadows.settings;
+
+            let changed = [
+                ui.slider("Cascades", 1, MAX_CASCADES as u32, &mut shadows.cascade_count),
+                ui.slider("Max distance", 1.0, 500.0, &mut shadows.max_distance),
+                ui.slider("Split lambda", 0.0, 1.0, &mut shadows.split_lambda),
+                ui.slider("Depth bias", 0.0, 8.0, &mut shadows.depth_bias_constant),
+                ui.slider("Slope bias", 0.0, 8.0, &mut shadows.depth_bias_slope),
+                ui.slider("Normal bias", 0.0, 8.0, &mut shadows.normal_bias),
+                ui.slider("PCF radius", 0, 4, &mut shadows.pcf_radius),
+                ui.checkbox("Show cascades", &mut shadows.debug_cascades),
+            ];
+
+            if changed.contains(&true) {
+                if let Err(err) = renderer.set_shadow_settings(shadows) {
+                    renderer.report(err.context("Failed to change the shadow settings"));
+                }
+            }
+        }
+
+        if ui.collapsing_header("Presentation", TreeNodeFlags::empty()) {
+            ui.text(format!("{:?}, reverse Z {}", renderer.hdr.encoding, if renderer.reverse_z { "on" } else { "off" }));
+
+            let mut clear_color = renderer.config.clear_color;
+
+            if ui.color_edit4("Clear color", &mut clear_color) {
+                renderer.config.clear_color = clear_color;
+            }
+
+            // headless renderers have nothing to present to:
+            if renderer.window.is_some() {
+                Self::window_settings(ui, renderer);
+            }
+        }
+    }
+
+    fn window_settings(ui: &Ui, renderer: &mut VulkanRenderer) {
+        let present_modes = [PresentMode::Fifo, PresentMode::FifoRelaxed, PresentMode::Mailbox, PresentMode::Immediate];
+        let names = ["FIFO", "FIFO relaxed", "Mailbox", "Immediate"];
+
+        let mut current = present_modes.iter().position(|&mode| mode == renderer.config.present_mode).unwrap_or(0);
+
+        if ui.combo_simple_string("Present mode", &mut current, &names) {
+            if let Err(err) = renderer.set_present_mode(present_modes[current]) {
+                renderer.report(err.context("Failed to change the present mode"));
+            }
+        }
+
+        let window_modes = [WindowMode::Windowed, WindowMode::Borderless, WindowMode::Exclusive];
+        let names = ["Windowed", "Borderless", "Exclusive"];
+
+        let mut current = window_modes.iter().position(|&mode| mode == renderer.config.window.mode).unwrap_or(0);
+
+        if ui.combo_simple_string("Window mode", &mut current, &names) {
+            if let Err(err) = renderer.set_window_mode(window_modes[current]) {
+                renderer.report(err.context("Failed to change the window mode"));
+            }
+        }
+    }
+}
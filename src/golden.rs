@@ -0,0 +1,269 @@
+//! Golden images: checking that frames still look like they did, so that rendering changes show up in CI.
+//!
+//! Goldens are PNGs of frames as they're read back from an offscreen target, under `goldens/` in the crate. They're
+//! rendered on lavapipe, which renderers set up by [`config`] pick unless `VULKAN_VIDEO_DEVICE` asks for another
+//! device, so that CI without a GPU gets the same pixels.
+//! A frame passes when few enough of its pixels are off by more than a tolerance and its SSIM against the golden
+//! stays high, which lets through what rasterizers disagree on along edges but not changes anyone would see.
+//!
+//! When a frame fails, it's written next to an image of where it differs into `target/golden/`.
+//! `VULKAN_VIDEO_BLESS=1` saves frames as the new goldens instead of checking them. A frame without a golden yet
+//! fails, and is written into `target/golden/` to be looked at before it's blessed.
+
+use crate::RendererConfig;
+
+use ash::vk;
+
+use image::RgbaImage;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+/// What goldens are rendered on, lavapipe's device is called `llvmpipe`.
+pub const DEVICE: &str = "llvmpipe";
+
+/// Where goldens are, where failures go, and how close frames have to be.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GoldenSettings {
+    pub directory: PathBuf,
+    pub failures: PathBuf,
+    /// How far a channel can be off before its pixel counts as different.
+    pub tolerance: u8,
+    /// How many pixels can be different, as a fraction of all of them.
+    pub max_different: f32,
+    /// The lowest SSIM that passes, between luma of the frame and the golden.
+    pub min_ssim: f32,
+    /// Saves frames as the new goldens instead of checking them.
+    pub bless: bool,
+}
+
+impl Default for GoldenSettings {
+    fn default() -> Self {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+
+        GoldenSettings {
+            directory: root.join("goldens"),
+            failures: root.join("target").join("golden"),
+            tolerance: 8,
+            max_different: 0.01,
+            min_ssim: 0.97,
+            bless: false,
+        }
+    }
+}
+
+impl GoldenSettings {
+    /// The defaults, blessing when `VULKAN_VIDEO_BLESS` is set to anything but `0`.
+    pub fn from_env() -> GoldenSettings {
+        let bless = env::var("VULKAN_VIDEO_BLESS")
+            .map(|bless| !bless.is_empty() && bless != "0")
+            .unwrap_or(false);
+
+        GoldenSettings {
+            bless,
+            ..Default::default()
+        }
+    }
+
+    pub fn golden_path(&self, name: &str) -> PathBuf {
+        self.directory.join(format!("{}.png", name))
+    }
+
+    /// Checks RGBA `pixels` of `extent` against the golden called `name`, or saves them as it when blessing and
+    /// returns where.
+    pub fn check(&self, name: &str, extent: vk::Extent2D, pixels: &[u8]) -> Result<Option<PathBuf>> {
+        let actual = match RgbaImage::from_raw(extent.width, extent.height, pixels.to_vec()) {
+            None => anyhow::bail!("{} bytes aren't a {}x{} RGBA frame", pixels.len(), extent.width, extent.height),
+            Some(actual) => actual
+        };
+
+        let path = self.golden_path(name);
+
+        if self.bless {
+            fs::create_dir_all(&self.directory)
+                .with_context(|| format!("Failed to create {}", self.directory.display()))?;
+
+            actual.save(&path)
+                .with_context(|| format!("Failed to bless {}", path.display()))?;
+
+            return Ok(Some(path));
+        }
+
+        if !path.exists() {
+            let new = self.write_failure(name, "new", &actual)?;
+
+            anyhow::bail!(
+                "There's no golden for {} yet, the frame is in {}. It's blessed with VULKAN_VIDEO_BLESS=1",
+                name, new.display(),
+            );
+        }
+
+        let expected = image::open(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?
+            .to_rgba8();
+
+        if expected.dimensions() != actual.dimensions() {
+            let actual_path = self.write_failure(name, "actual", &actual)?;
+
+            anyhow::bail!(
+                "{} is {:?} but its golden is {:?}, the frame is in {}",
+                name, actual.dimensions(), expected.dimensions(), actual_path.display(),
+            );
+        }
+
+        let comparison = Comparison::new(&expected, &actual, self.tolerance);
+
+        let pixel_count = (extent.width * extent.height) as f32;
+
+        if comparison.different as f32 <= self.max_different * pixel_count && comparison.ssim >= self.min_ssim {
+            return Ok(None);
+        }
+
+        let actual_path = self.write_failure(name, "actual", &actual)?;
+        let diff_path = self.write_failure(name, "diff", &comparison.diff)?;
+
+        anyhow::bail!(
+            "{} doesn't match its golden: {} pixels ({:.2}%) are off by more than {}, by up to {}, and the SSIM is {:.4}. \
+            The frame is in {} and where it differs in {}",
+            name,
+            comparison.different,
+            comparison.different as f32 / pixel_count * 100.0,
+            self.tolerance,
+            comparison.max_difference,
+            comparison.ssim,
+            actual_path.display(),
+            diff_path.display(),
+        )
+    }
+
+    fn write_failure(&self, name: &str, kind: &str, image: &RgbaImage) -> Result<PathBuf> {
+        fs::create_dir_all(&self.failures)
+            .with_context(|| format!("Failed to create {}", self.failures.display()))?;
+
+        let path = self.failures.join(format!("{}.{}.png", name, kind));
+
+        image.save(&path)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+
+        Ok(path)
+    }
+}
+
+/// How a frame differs from its golden.
+pub struct Comparison {
+    /// Pixels with a channel that's off by more than the tolerance.
+    pub different: u32,
+    /// How far the channel that's furthest off is.
+    pub max_difference: u8,
+    /// The mean structural similarity of their luma over 8x8 windows, 1 when they're the same.
+    pub ssim: f32,
+    /// The golden darkened, with the pixels that are different in red, brighter the further off they are.
+    pub diff: RgbaImage,
+}
+
+impl Comparison {
+    /// Compares two images of the same size.
+    pub fn new(expected: &RgbaImage, actual: &RgbaImage, tolerance: u8) -> Comparison {
+        let mut different = 0;
+        let mut max_difference = 0;
+
+        let mut diff = RgbaImage::new(expected.width(), expected.height());
+
+        for ((expected, actual), diff) in expected.pixels().zip(actual.pixels()).zip(diff.pixels_mut()) {
+            let difference = expected.0.iter()
+                .zip(actual.0)
+                .map(|(expected, actual)| expected.abs_diff(actual))
+                .max()
+                .unwrap_or(0);
+
+            max_difference = max_difference.max(difference);
+
+            diff.0 = if difference > tolerance {
+                different += 1;
+
+                [128 + difference / 2, 0, 0, 255]
+            } else {
+                let gray = (Self::luma(expected.0) / 3.0) as u8;
+
+                [gray, gray, gray, 255]
+            };
+        }
+
+        Comparison {
+            different,
+            max_difference,
+            ssim: Self::ssim(expected, actual),
+            diff,
+        }
+    }
+
+    fn luma(pixel: [u8; 4]) -> f32 {
+        0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32
+    }
+
+    /// SSIM over windows that overlap by half, smaller images are one window.
+    fn ssim(expected: &RgbaImage, actual: &RgbaImage) -> f32 {
+        const C1: f32 = (0.01 * 255.0) * (0.01 * 255.0);
+        const C2: f32 = (0.03 * 255.0) * (0.03 * 255.0);
+
+        let (width, height) = expected.dimensions();
+
+        let window = 8.min(width).min(height);
+        let step = (window / 2).max(1);
+
+        let mut total = 0.0;
+        let mut windows = 0;
+
+        for top in (0..=height - window).step_by(step as usize) {
+            for left in (0..=width - window).step_by(step as usize) {
+                let lumas: Vec<(f32, f32)> = (top..top + window)
+                    .flat_map(|y| (left..left + window).map(move |x| (x, y)))
+                    .map(|(x, y)| (Self::luma(expected.get_pixel(x, y).0), Self::luma(actual.get_pixel(x, y).0)))
+                    .collect();
+
+                let count = lumas.len() as f32;
+
+                let mean_expected = lumas.iter().map(|(expected, _)| expected).sum::<f32>() / count;
+                let mean_actual = lumas.iter().map(|(_, actual)| actual).sum::<f32>() / count;
+
+                let (mut variance_expected, mut variance_actual, mut covariance) = (0.0, 0.0, 0.0);
+
+                for (expected, actual) in &lumas {
+                    variance_expected += (expected - mean_expected) * (expected - mean_expected);
+                    variance_actual += (actual - mean_actual) * (actual - mean_actual);
+                    covariance += (expected - mean_expected) * (actual - mean_actual);
+                }
+
+                let (variance_expected, variance_actual, covariance) =
+                    (variance_expected / count, variance_actual / count, covariance / count);
+
+                total += ((2.0 * mean_expected * mean_actual + C1) * (2.0 * covariance + C2))
+                    / ((mean_expected * mean_expected + mean_actual * mean_actual + C1) * (variance_expected + variance_actual + C2));
+
+                windows += 1;
+            }
+        }
+
+        total / windows as f32
+    }
+}
+
+/// Renderer settings from [`RendererConfig::from_env`], on [`DEVICE`] unless they pick a device of their own.
+pub fn config() -> Result<RendererConfig> {
+    let mut config = RendererConfig::from_env()?;
+
+    if config.device.is_none() {
+        config.device = Some(DEVICE.to_owned());
+    }
+
+    Ok(config)
+}
+
+/// Checks a frame against its golden with the settings from [`GoldenSettings::from_env`], returns where it was saved
+/// when blessing.
+pub fn check(name: &str, extent: vk::Extent2D, pixels: &[u8]) -> Result<Option<PathBuf>> {
+    GoldenSettings::from_env().check(name, extent, pixels)
+}
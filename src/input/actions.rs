@@ -0,0 +1,125 @@
+//! Named actions bound to keys and mouse buttons.
+//!
+//! Bindings can be loaded from a TOML file like this one:
+//!
+//! ```toml
+//! [actions]
+//! jump = [{ key = "Space" }]
+//! fire = [{ mouse = "Left" }, { key = "LControl" }]
+//! back = [{ mouse = 4 }]
+//! ```
+//!
+//! Mouse buttons past the middle one are numbers.
+
+use winit::event::{MouseButton, VirtualKeyCode};
+
+use serde::{Deserialize, Serialize, Serializer};
+use serde::ser::SerializeMap;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(try_from = "BindingTable")]
+pub enum Binding {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+}
+
+// toml can't write enum variants that hold something, so bindings are written as the tables they're read from:
+impl Serialize for Binding {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(1))?;
+
+        match self {
+            Binding::Key(key) => map.serialize_entry("key", key)?,
+            Binding::Mouse(MouseButton::Other(button)) => map.serialize_entry("mouse", button)?,
+            Binding::Mouse(button) => map.serialize_entry("mouse", button)?,
+        }
+
+        map.end()
+    }
+}
+
+/// A binding as it's written, with other mouse buttons as numbers. It's read as a struct rather than as an enum,
+/// which toml can't read from the `[[actions.name]]` tables it writes.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BindingTable {
+    key: Option<VirtualKeyCode>,
+    mouse: Option<MouseTable>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MouseTable {
+    Other(u16),
+    Button(MouseButton),
+}
+
+impl TryFrom<BindingTable> for Binding {
+    type Error = String;
+
+    fn try_from(table: BindingTable) -> std::result::Result<Binding, String> {
+        match (table.key, table.mouse) {
+            (Some(key), None) => Ok(Binding::Key(key)),
+            (None, Some(MouseTable::Other(button))) => Ok(Binding::Mouse(MouseButton::Other(button))),
+            (None, Some(MouseTable::Button(button))) => Ok(Binding::Mouse(button)),
+            _ => Err("a binding has either a key or a mouse button".to_owned()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ActionMap {
+    #[serde(default)]
+    actions: HashMap<String, Vec<Binding>>,
+}
+
+impl ActionMap {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<ActionMap> {
+        let path = path.as_ref();
+
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read action bindings from {}", path.display()))?;
+
+        Self::from_toml(&source)
+            .with_context(|| format!("Failed to parse action bindings in {}", path.display()))
+    }
+
+    pub fn from_toml(source: &str) -> Result<ActionMap> {
+        Ok(toml::from_str(source)?)
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+
+    pub fn bind(&mut self, action: &str, binding: Binding) {
+        let bindings = self.actions.entry(action.to_owned()).or_default();
+
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn unbind(&mut self, action: &str, binding: Binding) {
+        if let Some(bindings) = self.actions.get_mut(action) {
+            bindings.retain(|bound| *bound != binding);
+        }
+    }
+
+    pub fn bindings(&self, action: &str) -> &[Binding] {
+        match self.actions.get(action) {
+            None => &[],
+            Some(bindings) => bindings
+        }
+    }
+
+    pub fn actions(&self) -> impl Iterator<Item = &str> {
+        self.actions.keys().map(|action| action.as_str())
+    }
+}
@@ -0,0 +1,251 @@
+//! Keyboard and mouse state collected from winit events.
+//!
+//! Everything that happened since the last [`Input::end_frame`] is kept: keys and buttons that went
+//! down (`pressed`) or up (`released`) this frame, and the ones that are still down (`held`).
+//! The event handlers are plain methods, so synthetic events can be fed without a window.
+
+pub mod actions;
+
+use actions::{ActionMap, Binding};
+
+use crate::renderer::window::RendererWindow;
+
+use winit::event::{DeviceEvent, ElementState, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+
+use std::collections::HashSet;
+use std::hash::Hash;
+
+use anyhow::Result;
+
+/// How many pixels of a touchpad scroll make up one line of a mouse wheel.
+pub const PIXELS_PER_LINE: f32 = 20.0;
+
+pub struct ButtonState<T> {
+    held: HashSet<T>,
+    pressed: HashSet<T>,
+    released: HashSet<T>,
+}
+
+impl<T: Copy + Eq + Hash> ButtonState<T> {
+    fn new() -> ButtonState<T> {
+        ButtonState {
+            held: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+        }
+    }
+
+    fn update(&mut self, button: T, state: ElementState) {
+        match state {
+            // repeated presses of something that's already held don't count:
+            ElementState::Pressed => if self.held.insert(button) {
+                self.pressed.insert(button);
+            },
+            ElementState::Released => if self.held.remove(&button) {
+                self.released.insert(button);
+            },
+        }
+    }
+
+    fn release_all(&mut self) {
+        self.released.extend(self.held.drain());
+    }
+
+    fn end_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+    }
+
+    pub fn pressed(&self, button: T) -> bool {
+        self.pressed.contains(&button)
+    }
+
+    pub fn released(&self, button: T) -> bool {
+        self.released.contains(&button)
+    }
+
+    pub fn held(&self, button: T) -> bool {
+        self.held.contains(&button)
+    }
+}
+
+pub struct Input {
+    pub keys: ButtonState<VirtualKeyCode>,
+    pub mouse_buttons: ButtonState<MouseButton>,
+    pub mouse_position: Option<(f64, f64)>,
+    pub mouse_delta: (f64, f64),
+    /// In lines, positive `y` scrolls up.
+    pub scroll: (f32, f32),
+    pub text: String,
+    pub focused: bool,
+    pub cursor_grabbed: bool,
+    pub cursor_visible: bool,
+    pub actions: ActionMap,
+}
+
+impl Input {
+    pub fn new() -> Input {
+        Self::with_actions(ActionMap::default())
+    }
+
+    pub fn with_actions(actions: ActionMap) -> Input {
+        Input {
+            keys: ButtonState::new(),
+            mouse_buttons: ButtonState::new(),
+            mouse_position: None,
+            mouse_delta: (0.0, 0.0),
+            scroll: (0.0, 0.0),
+            text: String::new(),
+            focused: true,
+            cursor_grabbed: false,
+            cursor_visible: true,
+            actions,
+        }
+    }
+
+    pub fn handle_window_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput { input, .. } => {
+                if let Some(key) = input.virtual_keycode {
+                    self.key(key, input.state);
+                }
+            },
+            WindowEvent::MouseInput { button, state, .. } => self.mouse_button(*button, *state),
+            WindowEvent::CursorMoved { position, .. } => self.cursor_moved(position.x, position.y),
+            WindowEvent::CursorLeft { .. } => self.mouse_position = None,
+            WindowEvent::MouseWheel { delta, .. } => match delta {
+                MouseScrollDelta::LineDelta(x, y) => self.scrolled(*x, *y),
+                MouseScrollDelta::PixelDelta(position) => self.scrolled(
+                    position.x as f32 / PIXELS_PER_LINE,
+                    position.y as f32 / PIXELS_PER_LINE,
+                ),
+            },
+            WindowEvent::ReceivedCharacter(character) => self.character(*character),
+            WindowEvent::Focused(focused) => self.focus(*focused),
+            _ => {}
+        }
+    }
+
+    /// Raw mouse motion keeps coming in while the cursor is grabbed, unlike `CursorMoved`.
+    pub fn handle_device_event(&mut self, event: &DeviceEvent) {
+        if let DeviceEvent::MouseMotion { delta } = event {
+            self.mouse_motion(delta.0, delta.1);
+        }
+    }
+
+    pub fn key(&mut self, key: VirtualKeyCode, state: ElementState) {
+        self.keys.update(key, state);
+    }
+
+    pub fn mouse_button(&mut self, button: MouseButton, state: ElementState) {
+        self.mouse_buttons.update(button, state);
+    }
+
+    pub fn cursor_moved(&mut self, x: f64, y: f64) {
+        self.mouse_position = Some((x, y));
+    }
+
+    pub fn mouse_motion(&mut self, dx: f64, dy: f64) {
+        self.mouse_delta.0 += dx;
+        self.mouse_delta.1 += dy;
+    }
+
+    pub fn scrolled(&mut self, x: f32, y: f32) {
+        self.scroll.0 += x;
+        self.scroll.1 += y;
+    }
+
+    pub fn character(&mut self, character: char) {
+        if !character.is_control() {
+            self.text.push(character);
+        }
+    }
+
+    /// Losing focus releases everything, the matching release events would never arrive.
+    pub fn focus(&mut self, focused: bool) {
+        self.focused = focused;
+
+        if !focused {
+            self.keys.release_all();
+            self.mouse_buttons.release_all();
+        }
+    }
+
+    /// Forgets what happened this frame, called once all of it was handled.
+    pub fn end_frame(&mut self) {
+        self.keys.end_frame();
+        self.mouse_buttons.end_frame();
+
+        self.mouse_delta = (0.0, 0.0);
+        self.scroll = (0.0, 0.0);
+        self.text.clear();
+    }
+
+    pub fn key_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.keys.pressed(key)
+    }
+
+    pub fn key_released(&self, key: VirtualKeyCode) -> bool {
+        self.keys.released(key)
+    }
+
+    pub fn key_held(&self, key: VirtualKeyCode) -> bool {
+        self.keys.held(key)
+    }
+
+    pub fn button_pressed(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.pressed(button)
+    }
+
+    pub fn button_released(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.released(button)
+    }
+
+    pub fn button_held(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.held(button)
+    }
+
+    pub fn action_pressed(&self, action: &str) -> bool {
+        self.actions.bindings(action).iter().any(|binding| match binding {
+            Binding::Key(key) => self.key_pressed(*key),
+            Binding::Mouse(button) => self.button_pressed(*button),
+        })
+    }
+
+    pub fn action_released(&self, action: &str) -> bool {
+        self.actions.bindings(action).iter().any(|binding| match binding {
+            Binding::Key(key) => self.key_released(*key),
+            Binding::Mouse(button) => self.button_released(*button),
+        })
+    }
+
+    pub fn action_held(&self, action: &str) -> bool {
+        self.actions.bindings(action).iter().any(|binding| match binding {
+            Binding::Key(key) => self.key_held(*key),
+            Binding::Mouse(button) => self.button_held(*button),
+        })
+    }
+
+    /// Confines the cursor to the window and hides it, or gives it back.
+    pub fn set_cursor_grab(&mut self, window: &RendererWindow, grab: bool) -> Result<()> {
+        window.window.set_cursor_grab(grab)?;
+
+        self.cursor_grabbed = grab;
+
+        self.set_cursor_visible(window, !grab);
+
+        Ok(())
+    }
+
+    pub fn set_cursor_visible(&mut self, window: &RendererWindow, visible: bool) {
+        window.window.set_cursor_visible(visible);
+
+        self.cursor_visible = visible;
+    }
+}
+
+impl Default for Input {
+    fn default() -> Input {
+        Self::new()
+    }
+}
@@ -0,0 +1,38 @@
+//! A `Vulkan` engine built on `ash`.
+//!
+//! [`VulkanRenderer`] is the entry point: create it with [`VulkanRenderer::new`] for a window or
+//! [`VulkanRenderer::headless`] for an offscreen image, then record frames between
+//! [`VulkanRenderer::begin_frame`] and [`VulkanRenderer::end_frame`]. Or implement [`App`] and
+//! let an [`AppRunner`] own the event loop.
+
+pub mod renderer;
+pub mod app;
+pub mod input;
+pub mod camera;
+pub mod scene;
+pub mod video;
+pub mod golden;
+pub mod debug_ui;
+pub mod text;
+
+pub use app::{App, AppRunner};
+pub use input::Input;
+pub use camera::Camera;
+pub use scene::{Scene, SceneResources};
+pub use renderer::VulkanRenderer;
+pub use renderer::config::RendererConfig;
+pub use renderer::frame::Frame;
+pub use renderer::target::RenderTarget;
+pub use renderer::buffer::RendererBuffer;
+pub use renderer::image::RendererImage;
+pub use renderer::mesh::{InstanceData, Mesh, Vertex};
+pub use renderer::texture::Texture;
+pub use renderer::lights::WorldLight;
+pub use renderer::pipeline::RendererPipeline;
+pub use renderer::diagnostics::DeviceLost;
+pub use debug_ui::DebugUi;
+pub use text::{Font, TextLayout, TextStyle};
+
+pub use ash::vk;
+pub use glam;
+pub use imgui;
@@ -0,0 +1,1216 @@
+//! What the engine asks of the GPU every frame, behind a trait so the logic around it can run without one.
+//!
+//! `RendererDevice` implements [`Backend`] by calling ash. [`NullBackend`] creates nothing and records the commands
+//! it's given instead, so code that's generic over the backend, like meshes, queued draws, the [`Recorder`] and
+//! the passes of a whole frame in [`FramePasses`], can be checked by what it records without a Vulkan instance.
+//!
+//! The trait covers buffers, fences, acquiring, submitting and presenting, and recording commands: what drawing a
+//! scene and a frame does. Images, samplers, pipelines, descriptor sets, render passes and framebuffers are created
+//! once up front and stay on `RendererDevice`, which is why `VulkanRenderer` itself isn't generic over the backend.
+//! Tests make those up from handles of [`NullBackend::handle`] instead, see the `null_backend` example.
+//!
+//! [`Recorder`]: crate::renderer::recorder::Recorder
+//! [`FramePasses`]: crate::renderer::frame::FramePasses
+
+use ash::vk;
+use ash::vk::Handle;
+use ash::prelude::VkResult;
+
+use crate::renderer::device::RendererDevice;
+use crate::renderer::buffer::RendererBuffer;
+use crate::renderer::swapchain::RendererSwapchain;
+
+use gpu_allocator::MemoryLocation;
+use gpu_allocator::vulkan::Allocation;
+
+use std::collections::HashMap;
+use std::slice;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::Result;
+
+/// Device, buffer and command recording operations, see the [module](self) for what's left out.
+///
+/// The commands take the same arguments as the ash ones they're named after, they're recorded into
+/// `command_buffer` and the caller makes sure the handles stay alive until it has executed.
+pub trait Backend: Sync {
+    /// The optional features that were enabled, see [`RendererDevice::features`].
+    fn features(&self) -> vk::PhysicalDeviceFeatures;
+
+    fn wait_idle(&self) -> Result<()>;
+
+    fn create_buffer(
+        &self,
+        name: &str,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        location: MemoryLocation
+    ) -> Result<RendererBuffer>;
+
+    /// Writes `bytes` at `offset` into a host visible buffer.
+    fn write_buffer(&self, buffer: &mut RendererBuffer, offset: usize, bytes: &[u8]) -> Result<()>;
+
+    /// Destroys a buffer the backend created, it mustn't be in use anymore.
+    unsafe fn destroy_buffer(&self, buffer: &mut RendererBuffer);
+
+    /// Waits for all of `fences`. Errors are Vulkan's, so that a lost device can be told apart.
+    fn wait_for_fences(&self, fences: &[vk::Fence], timeout: u64) -> VkResult<()>;
+
+    /// Errors are Vulkan's, it's reset right before the frame is submitted.
+    fn reset_fences(&self, fences: &[vk::Fence]) -> VkResult<()>;
+
+    /// The index of the next image of `swapchain`, which can be drawn into once `semaphore` is signalled, and
+    /// whether the swapchain is suboptimal.
+    fn acquire_next_image(&self, swapchain: &RendererSwapchain, semaphore: vk::Semaphore) -> VkResult<(u32, bool)>;
+
+    fn queue_submit(&self, queue: vk::Queue, submits: &[vk::SubmitInfo], fence: vk::Fence) -> VkResult<()>;
+
+    /// Presents the image at `image_index` of `swapchain` once `wait_semaphores` are signalled, returns whether the
+    /// swapchain is suboptimal.
+    fn queue_present(
+        &self,
+        queue: vk::Queue,
+        swapchain: &RendererSwapchain,
+        image_index: u32,
+        wait_semaphores: &[vk::Semaphore]
+    ) -> VkResult<bool>;
+
+    fn begin_command_buffer(&self, command_buffer: vk::CommandBuffer, begin_info: &vk::CommandBufferBeginInfo) -> Result<()>;
+
+    fn end_command_buffer(&self, command_buffer: vk::CommandBuffer) -> Result<()>;
+
+    fn cmd_execute_commands(&self, command_buffer: vk::CommandBuffer, secondary_command_buffers: &[vk::CommandBuffer]);
+
+    fn cmd_pipeline_barrier(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        src_stage: vk::PipelineStageFlags,
+        dst_stage: vk::PipelineStageFlags,
+        memory_barriers: &[vk::MemoryBarrier],
+        buffer_barriers: &[vk::BufferMemoryBarrier],
+        image_barriers: &[vk::ImageMemoryBarrier]
+    );
+
+    fn cmd_copy_buffer_to_image(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        image: vk::Image,
+        layout: vk::ImageLayout,
+        regions: &[vk::BufferImageCopy]
+    );
+
+    fn cmd_copy_image_to_buffer(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image: vk::Image,
+        layout: vk::ImageLayout,
+        buffer: vk::Buffer,
+        regions: &[vk::BufferImageCopy]
+    );
+
+    fn cmd_blit_image(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        src: (vk::Image, vk::ImageLayout),
+        dst: (vk::Image, vk::ImageLayout),
+        regions: &[vk::ImageBlit],
+        filter: vk::Filter
+    );
+
+    fn cmd_fill_buffer(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+        data: u32
+    );
+
+    fn cmd_begin_render_pass(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        begin_info: &vk::RenderPassBeginInfo,
+        contents: vk::SubpassContents
+    );
+
+    fn cmd_end_render_pass(&self, command_buffer: vk::CommandBuffer);
+
+    fn cmd_set_viewport(&self, command_buffer: vk::CommandBuffer, first_viewport: u32, viewports: &[vk::Viewport]);
+
+    fn cmd_set_scissor(&self, command_buffer: vk::CommandBuffer, first_scissor: u32, scissors: &[vk::Rect2D]);
+
+    fn cmd_set_depth_bias(&self, command_buffer: vk::CommandBuffer, constant_factor: f32, clamp: f32, slope_factor: f32);
+
+    fn cmd_bind_pipeline(&self, command_buffer: vk::CommandBuffer, bind_point: vk::PipelineBindPoint, pipeline: vk::Pipeline);
+
+    fn cmd_bind_descriptor_sets(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        bind_point: vk::PipelineBindPoint,
+        layout: vk::PipelineLayout,
+        first_set: u32,
+        descriptor_sets: &[vk::DescriptorSet],
+        dynamic_offsets: &[u32]
+    );
+
+    fn cmd_push_constants(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        layout: vk::PipelineLayout,
+        stages: vk::ShaderStageFlags,
+        offset: u32,
+        constants: &[u8]
+    );
+
+    fn cmd_bind_vertex_buffers(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        first_binding: u32,
+        buffers: &[vk::Buffer],
+        offsets: &[vk::DeviceSize]
+    );
+
+    fn cmd_bind_index_buffer(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        index_type: vk::IndexType
+    );
+
+    fn cmd_draw(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        vertex_count: u32,
+        instance_count: u32,
+        first_vertex: u32,
+        first_instance: u32
+    );
+
+    fn cmd_draw_indexed(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        index_count: u32,
+        instance_count: u32,
+        first_index: u32,
+        vertex_offset: i32,
+        first_instance: u32
+    );
+
+    fn cmd_draw_indexed_indirect(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        draw_count: u32,
+        stride: u32
+    );
+
+    /// Like `cmd_draw_indexed_indirect`, with the draw count read from `count`, a buffer and an offset.
+    ///
+    /// Fails when the device doesn't have `VK_KHR_draw_indirect_count`.
+    fn cmd_draw_indexed_indirect_count(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        commands: (vk::Buffer, vk::DeviceSize),
+        count: (vk::Buffer, vk::DeviceSize),
+        max_draw_count: u32,
+        stride: u32
+    ) -> Result<()>;
+
+    fn cmd_dispatch(&self, command_buffer: vk::CommandBuffer, x: u32, y: u32, z: u32);
+}
+
+impl Backend for RendererDevice {
+    fn features(&self) -> vk::PhysicalDeviceFeatures {
+        self.features
+    }
+
+    fn wait_idle(&self) -> Result<()> {
+        unsafe {
+            self.logical_device.device_wait_idle()?;
+        };
+
+        Ok(())
+    }
+
+    fn create_buffer(
+        &self,
+        name: &str,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        location: MemoryLocation
+    ) -> Result<RendererBuffer> {
+        RendererBuffer::new(self, name, size, usage, location)
+    }
+
+    fn write_buffer(&self, buffer: &mut RendererBuffer, offset: usize, bytes: &[u8]) -> Result<()> {
+        buffer.write_at(offset, bytes)
+    }
+
+    unsafe fn destroy_buffer(&self, buffer: &mut RendererBuffer) {
+        buffer.cleanup(self);
+    }
+
+    fn wait_for_fences(&self, fences: &[vk::Fence], timeout: u64) -> VkResult<()> {
+        unsafe {
+            self.logical_device.wait_for_fences(fences, true, timeout)
+        }
+    }
+
+    fn reset_fences(&self, fences: &[vk::Fence]) -> VkResult<()> {
+        unsafe {
+            self.logical_device.reset_fences(fences)
+        }
+    }
+
+    fn acquire_next_image(&self, swapchain: &RendererSwapchain, semaphore: vk::Semaphore) -> VkResult<(u32, bool)> {
+        unsafe {
+            swapchain.swapchain_loader.acquire_next_image(swapchain.swapchain, u64::MAX, semaphore, vk::Fence::null())
+        }
+    }
+
+    fn queue_submit(&self, queue: vk::Queue, submits: &[vk::SubmitInfo], fence: vk::Fence) -> VkResult<()> {
+        unsafe {
+            self.logical_device.queue_submit(queue, submits, fence)
+        }
+    }
+
+    fn queue_present(
+        &self,
+        queue: vk::Queue,
+        swapchain: &RendererSwapchain,
+        image_index: u32,
+        wait_semaphores: &[vk::Semaphore]
+    ) -> VkResult<bool> {
+        let swapchains = [swapchain.swapchain];
+        let indices = [image_index];
+
+        let present_info = vk::PresentInfoKHR::builder()
+            .wait_semaphores(wait_semaphores)
+            .swapchains(&swapchains)
+            .image_indices(&indices);
+
+        unsafe {
+            swapchain.swapchain_loader.queue_present(queue, &present_info)
+        }
+    }
+
+    fn begin_command_buffer(&self, command_buffer: vk::CommandBuffer, begin_info: &vk::CommandBufferBeginInfo) -> Result<()> {
+        unsafe {
+            self.logical_device.begin_command_buffer(command_buffer, begin_info)?;
+        };
+
+        Ok(())
+    }
+
+    fn end_command_buffer(&self, command_buffer: vk::CommandBuffer) -> Result<()> {
+        unsafe {
+            self.logical_device.end_command_buffer(command_buffer)?;
+        };
+
+        Ok(())
+    }
+
+    fn cmd_execute_commands(&self, command_buffer: vk::CommandBuffer, secondary_command_buffers: &[vk::CommandBuffer]) {
+        unsafe {
+            self.logical_device.cmd_execute_commands(command_buffer, secondary_command_buffers);
+        };
+    }
+
+    fn cmd_pipeline_barrier(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        src_stage: vk::PipelineStageFlags,
+        dst_stage: vk::PipelineStageFlags,
+        memory_barriers: &[vk::MemoryBarrier],
+        buffer_barriers: &[vk::BufferMemoryBarrier],
+        image_barriers: &[vk::ImageMemoryBarrier]
+    ) {
+        unsafe {
+            self.logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                src_stage,
+                dst_stage,
+                vk::DependencyFlags::empty(),
+                memory_barriers,
+                buffer_barriers,
+                image_barriers,
+            );
+        };
+    }
+
+    fn cmd_copy_buffer_to_image(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        image: vk::Image,
+        layout: vk::ImageLayout,
+        regions: &[vk::BufferImageCopy]
+    ) {
+        unsafe {
+            self.logical_device.cmd_copy_buffer_to_image(command_buffer, buffer, image, layout, regions);
+        };
+    }
+
+    fn cmd_copy_image_to_buffer(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image: vk::Image,
+        layout: vk::ImageLayout,
+        buffer: vk::Buffer,
+        regions: &[vk::BufferImageCopy]
+    ) {
+        unsafe {
+            self.logical_device.cmd_copy_image_to_buffer(command_buffer, image, layout, buffer, regions);
+        };
+    }
+
+    fn cmd_blit_image(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        src: (vk::Image, vk::ImageLayout),
+        dst: (vk::Image, vk::ImageLayout),
+        regions: &[vk::ImageBlit],
+        filter: vk::Filter
+    ) {
+        unsafe {
+            self.logical_device.cmd_blit_image(command_buffer, src.0, src.1, dst.0, dst.1, regions, filter);
+        };
+    }
+
+    fn cmd_fill_buffer(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+        data: u32
+    ) {
+        unsafe {
+            self.logical_device.cmd_fill_buffer(command_buffer, buffer, offset, size, data);
+        };
+    }
+
+    fn cmd_begin_render_pass(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        begin_info: &vk::RenderPassBeginInfo,
+        contents: vk::SubpassContents
+    ) {
+        unsafe {
+            self.logical_device.cmd_begin_render_pass(command_buffer, begin_info, contents);
+        };
+    }
+
+    fn cmd_end_render_pass(&self, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            self.logical_device.cmd_end_render_pass(command_buffer);
+        };
+    }
+
+    fn cmd_set_viewport(&self, command_buffer: vk::CommandBuffer, first_viewport: u32, viewports: &[vk::Viewport]) {
+        unsafe {
+            self.logical_device.cmd_set_viewport(command_buffer, first_viewport, viewports);
+        };
+    }
+
+    fn cmd_set_scissor(&self, command_buffer: vk::CommandBuffer, first_scissor: u32, scissors: &[vk::Rect2D]) {
+        unsafe {
+            self.logical_device.cmd_set_scissor(command_buffer, first_scissor, scissors);
+        };
+    }
+
+    fn cmd_set_depth_bias(&self, command_buffer: vk::CommandBuffer, constant_factor: f32, clamp: f32, slope_factor: f32) {
+        unsafe {
+            self.logical_device.cmd_set_depth_bias(command_buffer, constant_factor, clamp, slope_factor);
+        };
+    }
+
+    fn cmd_bind_pipeline(&self, command_buffer: vk::CommandBuffer, bind_point: vk::PipelineBindPoint, pipeline: vk::Pipeline) {
+        unsafe {
+            self.logical_device.cmd_bind_pipeline(command_buffer, bind_point, pipeline);
+        };
+    }
+
+    fn cmd_bind_descriptor_sets(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        bind_point: vk::PipelineBindPoint,
+        layout: vk::PipelineLayout,
+        first_set: u32,
+        descriptor_sets: &[vk::DescriptorSet],
+        dynamic_offsets: &[u32]
+    ) {
+        unsafe {
+            self.logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                bind_point,
+                layout,
+                first_set,
+                descriptor_sets,
+                dynamic_offsets,
+            );
+        };
+    }
+
+    fn cmd_push_constants(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        layout: vk::PipelineLayout,
+        stages: vk::ShaderStageFlags,
+        offset: u32,
+        constants: &[u8]
+    ) {
+        unsafe {
+            self.logical_device.cmd_push_constants(command_buffer, layout, stages, offset, constants);
+        };
+    }
+
+    fn cmd_bind_vertex_buffers(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        first_binding: u32,
+        buffers: &[vk::Buffer],
+        offsets: &[vk::DeviceSize]
+    ) {
+        unsafe {
+            self.logical_device.cmd_bind_vertex_buffers(command_buffer, first_binding, buffers, offsets);
+        };
+    }
+
+    fn cmd_bind_index_buffer(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        index_type: vk::IndexType
+    ) {
+        unsafe {
+            self.logical_device.cmd_bind_index_buffer(command_buffer, buffer, offset, index_type);
+        };
+    }
+
+    fn cmd_draw(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        vertex_count: u32,
+        instance_count: u32,
+        first_vertex: u32,
+        first_instance: u32
+    ) {
+        unsafe {
+            self.logical_device.cmd_draw(command_buffer, vertex_count, instance_count, first_vertex, first_instance);
+        };
+    }
+
+    fn cmd_draw_indexed(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        index_count: u32,
+        instance_count: u32,
+        first_index: u32,
+        vertex_offset: i32,
+        first_instance: u32
+    ) {
+        unsafe {
+            self.logical_device.cmd_draw_indexed(
+                command_buffer,
+                index_count,
+                instance_count,
+                first_index,
+                vertex_offset,
+                first_instance,
+            );
+        };
+    }
+
+    fn cmd_draw_indexed_indirect(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        draw_count: u32,
+        stride: u32
+    ) {
+        unsafe {
+            self.logical_device.cmd_draw_indexed_indirect(command_buffer, buffer, offset, draw_count, stride);
+        };
+    }
+
+    fn cmd_draw_indexed_indirect_count(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        commands: (vk::Buffer, vk::DeviceSize),
+        count: (vk::Buffer, vk::DeviceSize),
+        max_draw_count: u32,
+        stride: u32
+    ) -> Result<()> {
+        let draw_indirect_count = match &self.draw_indirect_count {
+            None => anyhow::bail!("Drawing with a count from a buffer needs VK_KHR_draw_indirect_count"),
+            Some(draw_indirect_count) => draw_indirect_count
+        };
+
+        unsafe {
+            draw_indirect_count.cmd_draw_indexed_indirect_count(
+                command_buffer,
+                commands.0,
+                commands.1,
+                count.0,
+                count.1,
+                max_draw_count,
+                stride,
+            );
+        };
+
+        Ok(())
+    }
+
+    fn cmd_dispatch(&self, command_buffer: vk::CommandBuffer, x: u32, y: u32, z: u32) {
+        unsafe {
+            self.logical_device.cmd_dispatch(command_buffer, x, y, z);
+        };
+    }
+}
+
+/// An image changing layout in a barrier.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageTransition {
+    pub image: vk::Image,
+    pub old_layout: vk::ImageLayout,
+    pub new_layout: vk::ImageLayout,
+}
+
+/// A command as [`NullBackend`] recorded it, with what there is to check about it.
+///
+/// What goes to a queue rather than into a command buffer is recorded too, as if into a null command buffer.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    WaitForFences {
+        fences: Vec<vk::Fence>,
+    },
+    ResetFences {
+        fences: Vec<vk::Fence>,
+    },
+    AcquireNextImage {
+        swapchain: vk::SwapchainKHR,
+        semaphore: vk::Semaphore,
+    },
+    /// One for each `vk::SubmitInfo`.
+    Submit {
+        wait_semaphores: Vec<vk::Semaphore>,
+        wait_stages: Vec<vk::PipelineStageFlags>,
+        command_buffers: Vec<vk::CommandBuffer>,
+        signal_semaphores: Vec<vk::Semaphore>,
+        fence: vk::Fence,
+    },
+    Present {
+        swapchain: vk::SwapchainKHR,
+        image_index: u32,
+        wait_semaphores: Vec<vk::Semaphore>,
+    },
+    BeginCommandBuffer {
+        flags: vk::CommandBufferUsageFlags,
+    },
+    EndCommandBuffer,
+    ExecuteCommands {
+        command_buffers: Vec<vk::CommandBuffer>,
+    },
+    Barrier {
+        src_stage: vk::PipelineStageFlags,
+        dst_stage: vk::PipelineStageFlags,
+        memory_barriers: usize,
+        buffers: Vec<vk::Buffer>,
+        images: Vec<ImageTransition>,
+    },
+    CopyBufferToImage {
+        buffer: vk::Buffer,
+        image: vk::Image,
+        layout: vk::ImageLayout,
+    },
+    CopyImageToBuffer {
+        image: vk::Image,
+        layout: vk::ImageLayout,
+        buffer: vk::Buffer,
+    },
+    BlitImage {
+        src: (vk::Image, vk::ImageLayout),
+        dst: (vk::Image, vk::ImageLayout),
+        filter: vk::Filter,
+    },
+    FillBuffer {
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+        data: u32,
+    },
+    BeginRenderPass {
+        render_pass: vk::RenderPass,
+        framebuffer: vk::Framebuffer,
+        contents: vk::SubpassContents,
+    },
+    EndRenderPass,
+    /// With the x, y, width and height of each viewport.
+    SetViewport {
+        first_viewport: u32,
+        viewports: Vec<[f32; 4]>,
+    },
+    SetScissor {
+        first_scissor: u32,
+        scissors: Vec<vk::Rect2D>,
+    },
+    SetDepthBias {
+        constant_factor: f32,
+        clamp: f32,
+        slope_factor: f32,
+    },
+    BindPipeline {
+        bind_point: vk::PipelineBindPoint,
+        pipeline: vk::Pipeline,
+    },
+    BindDescriptorSets {
+        bind_point: vk::PipelineBindPoint,
+        layout: vk::PipelineLayout,
+        first_set: u32,
+        descriptor_sets: Vec<vk::DescriptorSet>,
+    },
+    PushConstants {
+        layout: vk::PipelineLayout,
+        stages: vk::ShaderStageFlags,
+        offset: u32,
+        constants: Vec<u8>,
+    },
+    BindVertexBuffers {
+        first_binding: u32,
+        buffers: Vec<vk::Buffer>,
+        offsets: Vec<vk::DeviceSize>,
+    },
+    BindIndexBuffer {
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        index_type: vk::IndexType,
+    },
+    Draw {
+        vertex_count: u32,
+        instance_count: u32,
+        first_vertex: u32,
+        first_instance: u32,
+    },
+    DrawIndexed {
+        index_count: u32,
+        instance_count: u32,
+        first_index: u32,
+        vertex_offset: i32,
+        first_instance: u32,
+    },
+    DrawIndexedIndirect {
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        draw_count: u32,
+        stride: u32,
+    },
+    DrawIndexedIndirectCount {
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        count_buffer: vk::Buffer,
+        count_offset: vk::DeviceSize,
+        max_draw_count: u32,
+        stride: u32,
+    },
+    Dispatch {
+        x: u32,
+        y: u32,
+        z: u32,
+    },
+}
+
+/// A backend without a GPU, that hands out made up handles and records commands.
+///
+/// Buffers it creates are kept as bytes on the CPU, so what's written into them can be read back with
+/// `buffer_data`.
+pub struct NullBackend {
+    /// What `features` returns, nothing by default.
+    pub features: vk::PhysicalDeviceFeatures,
+    /// Whether `cmd_draw_indexed_indirect_count` works, like a device with `VK_KHR_draw_indirect_count`.
+    pub draw_indirect_count: bool,
+    next_handle: AtomicU64,
+    buffers: Mutex<HashMap<vk::Buffer, Vec<u8>>>,
+    commands: Mutex<Vec<(vk::CommandBuffer, Command)>>,
+}
+
+impl Default for NullBackend {
+    fn default() -> Self {
+        NullBackend {
+            features: vk::PhysicalDeviceFeatures::default(),
+            draw_indirect_count: false,
+            next_handle: AtomicU64::new(1),
+            buffers: Mutex::new(HashMap::new()),
+            commands: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl NullBackend {
+    pub fn new() -> NullBackend {
+        Self::default()
+    }
+
+    /// A handle of any kind that no other call returns, for pipelines, descriptor sets and the like.
+    pub fn handle<T: Handle>(&self) -> T {
+        T::from_raw(self.next_handle.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// The commands recorded into `command_buffer` so far, in order.
+    pub fn commands(&self, command_buffer: vk::CommandBuffer) -> Vec<Command> {
+        self.commands.lock().unwrap()
+            .iter()
+            .filter(|(recorded_into, _)| *recorded_into == command_buffer)
+            .map(|(_, command)| command.clone())
+            .collect()
+    }
+
+    /// What went to a queue so far: fences waited for and reset, images acquired, submits and presents, in order.
+    pub fn queue_commands(&self) -> Vec<Command> {
+        self.commands(vk::CommandBuffer::null())
+    }
+
+    /// Every command recorded so far with the command buffer it went into, and forgets them.
+    pub fn take_commands(&self) -> Vec<(vk::CommandBuffer, Command)> {
+        std::mem::take(&mut *self.commands.lock().unwrap())
+    }
+
+    /// What a buffer from `create_buffer` holds, `None` once it's destroyed.
+    pub fn buffer_data(&self, buffer: vk::Buffer) -> Option<Vec<u8>> {
+        self.buffers.lock().unwrap().get(&buffer).cloned()
+    }
+
+    /// How many of the buffers it created haven't been destroyed yet.
+    pub fn live_buffers(&self) -> usize {
+        self.buffers.lock().unwrap().len()
+    }
+
+    fn record(&self, command_buffer: vk::CommandBuffer, command: Command) {
+        self.commands.lock().unwrap().push((command_buffer, command));
+    }
+
+    fn record_queue(&self, command: Command) {
+        self.record(vk::CommandBuffer::null(), command);
+    }
+
+    /// The array behind a pointer and a count of a Vulkan struct, which may be null when the count is 0.
+    unsafe fn array<'a, T>(pointer: *const T, count: u32) -> &'a [T] {
+        match count {
+            0 => &[],
+            count => slice::from_raw_parts(pointer, count as usize)
+        }
+    }
+}
+
+impl Backend for NullBackend {
+    fn features(&self) -> vk::PhysicalDeviceFeatures {
+        self.features
+    }
+
+    fn wait_idle(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn create_buffer(
+        &self,
+        _name: &str,
+        size: vk::DeviceSize,
+        _usage: vk::BufferUsageFlags,
+        _location: MemoryLocation
+    ) -> Result<RendererBuffer> {
+        let buffer = self.handle();
+
+        self.buffers.lock().unwrap().insert(buffer, vec![0; size as usize]);
+
+        Ok(RendererBuffer {
+            buffer,
+            allocation: Allocation::default(),
+            size,
+        })
+    }
+
+    fn write_buffer(&self, buffer: &mut RendererBuffer, offset: usize, bytes: &[u8]) -> Result<()> {
+        let mut buffers = self.buffers.lock().unwrap();
+
+        let data = match buffers.get_mut(&buffer.buffer) {
+            None => anyhow::bail!("{:?} isn't a buffer of this backend", buffer.buffer),
+            Some(data) => data
+        };
+
+        if offset + bytes.len() > data.len() {
+            anyhow::bail!("Writing {} bytes at {} overflows a buffer of {} bytes", bytes.len(), offset, data.len());
+        }
+
+        data[offset..offset + bytes.len()].copy_from_slice(bytes);
+
+        Ok(())
+    }
+
+    unsafe fn destroy_buffer(&self, buffer: &mut RendererBuffer) {
+        self.buffers.lock().unwrap().remove(&buffer.buffer);
+
+        buffer.buffer = vk::Buffer::null();
+    }
+
+    fn wait_for_fences(&self, fences: &[vk::Fence], _timeout: u64) -> VkResult<()> {
+        self.record_queue(Command::WaitForFences {
+            fences: fences.to_vec(),
+        });
+
+        Ok(())
+    }
+
+    fn reset_fences(&self, fences: &[vk::Fence]) -> VkResult<()> {
+        self.record_queue(Command::ResetFences {
+            fences: fences.to_vec(),
+        });
+
+        Ok(())
+    }
+
+    /// Always the first image, which is never suboptimal.
+    fn acquire_next_image(&self, swapchain: &RendererSwapchain, semaphore: vk::Semaphore) -> VkResult<(u32, bool)> {
+        self.record_queue(Command::AcquireNextImage {
+            swapchain: swapchain.swapchain,
+            semaphore,
+        });
+
+        Ok((0, false))
+    }
+
+    fn queue_submit(&self, _queue: vk::Queue, submits: &[vk::SubmitInfo], fence: vk::Fence) -> VkResult<()> {
+        for submit in submits {
+            let command = unsafe {
+                Command::Submit {
+                    wait_semaphores: Self::array(submit.p_wait_semaphores, submit.wait_semaphore_count).to_vec(),
+                    wait_stages: Self::array(submit.p_wait_dst_stage_mask, submit.wait_semaphore_count).to_vec(),
+                    command_buffers: Self::array(submit.p_command_buffers, submit.command_buffer_count).to_vec(),
+                    signal_semaphores: Self::array(submit.p_signal_semaphores, submit.signal_semaphore_count).to_vec(),
+                    fence,
+                }
+            };
+
+            self.record_queue(command);
+        }
+
+        Ok(())
+    }
+
+    fn queue_present(
+        &self,
+        _queue: vk::Queue,
+        swapchain: &RendererSwapchain,
+        image_index: u32,
+        wait_semaphores: &[vk::Semaphore]
+    ) -> VkResult<bool> {
+        self.record_queue(Command::Present {
+            swapchain: swapchain.swapchain,
+            image_index,
+            wait_semaphores: wait_semaphores.to_vec(),
+        });
+
+        Ok(false)
+    }
+
+    fn begin_command_buffer(&self, command_buffer: vk::CommandBuffer, begin_info: &vk::CommandBufferBeginInfo) -> Result<()> {
+        self.record(command_buffer, Command::BeginCommandBuffer {
+            flags: begin_info.flags,
+        });
+
+        Ok(())
+    }
+
+    fn end_command_buffer(&self, command_buffer: vk::CommandBuffer) -> Result<()> {
+        self.record(command_buffer, Command::EndCommandBuffer);
+
+        Ok(())
+    }
+
+    fn cmd_execute_commands(&self, command_buffer: vk::CommandBuffer, secondary_command_buffers: &[vk::CommandBuffer]) {
+        self.record(command_buffer, Command::ExecuteCommands {
+            command_buffers: secondary_command_buffers.to_vec(),
+        });
+    }
+
+    fn cmd_pipeline_barrier(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        src_stage: vk::PipelineStageFlags,
+        dst_stage: vk::PipelineStageFlags,
+        memory_barriers: &[vk::MemoryBarrier],
+        buffer_barriers: &[vk::BufferMemoryBarrier],
+        image_barriers: &[vk::ImageMemoryBarrier]
+    ) {
+        self.record(command_buffer, Command::Barrier {
+            src_stage,
+            dst_stage,
+            memory_barriers: memory_barriers.len(),
+            buffers: buffer_barriers.iter().map(|barrier| barrier.buffer).collect(),
+            images: image_barriers.iter()
+                .map(|barrier| ImageTransition {
+                    image: barrier.image,
+                    old_layout: barrier.old_layout,
+                    new_layout: barrier.new_layout,
+                })
+                .collect(),
+        });
+    }
+
+    fn cmd_copy_buffer_to_image(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        image: vk::Image,
+        layout: vk::ImageLayout,
+        _regions: &[vk::BufferImageCopy]
+    ) {
+        self.record(command_buffer, Command::CopyBufferToImage {
+            buffer,
+            image,
+            layout,
+        });
+    }
+
+    fn cmd_copy_image_to_buffer(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image: vk::Image,
+        layout: vk::ImageLayout,
+        buffer: vk::Buffer,
+        _regions: &[vk::BufferImageCopy]
+    ) {
+        self.record(command_buffer, Command::CopyImageToBuffer {
+            image,
+            layout,
+            buffer,
+        });
+    }
+
+    fn cmd_blit_image(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        src: (vk::Image, vk::ImageLayout),
+        dst: (vk::Image, vk::ImageLayout),
+        _regions: &[vk::ImageBlit],
+        filter: vk::Filter
+    ) {
+        self.record(command_buffer, Command::BlitImage {
+            src,
+            dst,
+            filter,
+        });
+    }
+
+    fn cmd_fill_buffer(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+        data: u32
+    ) {
+        self.record(command_buffer, Command::FillBuffer {
+            buffer,
+            offset,
+            size,
+            data,
+        });
+    }
+
+    fn cmd_begin_render_pass(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        begin_info: &vk::RenderPassBeginInfo,
+        contents: vk::SubpassContents
+    ) {
+        self.record(command_buffer, Command::BeginRenderPass {
+            render_pass: begin_info.render_pass,
+            framebuffer: begin_info.framebuffer,
+            contents,
+        });
+    }
+
+    fn cmd_end_render_pass(&self, command_buffer: vk::CommandBuffer) {
+        self.record(command_buffer, Command::EndRenderPass);
+    }
+
+    fn cmd_set_viewport(&self, command_buffer: vk::CommandBuffer, first_viewport: u32, viewports: &[vk::Viewport]) {
+        self.record(command_buffer, Command::SetViewport {
+            first_viewport,
+            viewports: viewports.iter()
+                .map(|viewport| [viewport.x, viewport.y, viewport.width, viewport.height])
+                .collect(),
+        });
+    }
+
+    fn cmd_set_scissor(&self, command_buffer: vk::CommandBuffer, first_scissor: u32, scissors: &[vk::Rect2D]) {
+        self.record(command_buffer, Command::SetScissor {
+            first_scissor,
+            scissors: scissors.to_vec(),
+        });
+    }
+
+    fn cmd_set_depth_bias(&self, command_buffer: vk::CommandBuffer, constant_factor: f32, clamp: f32, slope_factor: f32) {
+        self.record(command_buffer, Command::SetDepthBias {
+            constant_factor,
+            clamp,
+            slope_factor,
+        });
+    }
+
+    fn cmd_bind_pipeline(&self, command_buffer: vk::CommandBuffer, bind_point: vk::PipelineBindPoint, pipeline: vk::Pipeline) {
+        self.record(command_buffer, Command::BindPipeline {
+            bind_point,
+            pipeline,
+        });
+    }
+
+    fn cmd_bind_descriptor_sets(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        bind_point: vk::PipelineBindPoint,
+        layout: vk::PipelineLayout,
+        first_set: u32,
+        descriptor_sets: &[vk::DescriptorSet],
+        _dynamic_offsets: &[u32]
+    ) {
+        self.record(command_buffer, Command::BindDescriptorSets {
+            bind_point,
+            layout,
+            first_set,
+            descriptor_sets: descriptor_sets.to_vec(),
+        });
+    }
+
+    fn cmd_push_constants(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        layout: vk::PipelineLayout,
+        stages: vk::ShaderStageFlags,
+        offset: u32,
+        constants: &[u8]
+    ) {
+        self.record(command_buffer, Command::PushConstants {
+            layout,
+            stages,
+            offset,
+            constants: constants.to_vec(),
+        });
+    }
+
+    fn cmd_bind_vertex_buffers(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        first_binding: u32,
+        buffers: &[vk::Buffer],
+        offsets: &[vk::DeviceSize]
+    ) {
+        self.record(command_buffer, Command::BindVertexBuffers {
+            first_binding,
+            buffers: buffers.to_vec(),
+            offsets: offsets.to_vec(),
+        });
+    }
+
+    fn cmd_bind_index_buffer(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        index_type: vk::IndexType
+    ) {
+        self.record(command_buffer, Command::BindIndexBuffer {
+            buffer,
+            offset,
+            index_type,
+        });
+    }
+
+    fn cmd_draw(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        vertex_count: u32,
+        instance_count: u32,
+        first_vertex: u32,
+        first_instance: u32
+    ) {
+        self.record(command_buffer, Command::Draw {
+            vertex_count,
+            instance_count,
+            first_vertex,
+            first_instance,
+        });
+    }
+
+    fn cmd_draw_indexed(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        index_count: u32,
+        instance_count: u32,
+        first_index: u32,
+        vertex_offset: i32,
+        first_instance: u32
+    ) {
+        self.record(command_buffer, Command::DrawIndexed {
+            index_count,
+            instance_count,
+            first_index,
+            vertex_offset,
+            first_instance,
+        });
+    }
+
+    fn cmd_draw_indexed_indirect(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        draw_count: u32,
+        stride: u32
+    ) {
+        self.record(command_buffer, Command::DrawIndexedIndirect {
+            buffer,
+            offset,
+            draw_count,
+            stride,
+        });
+    }
+
+    fn cmd_draw_indexed_indirect_count(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        commands: (vk::Buffer, vk::DeviceSize),
+        count: (vk::Buffer, vk::DeviceSize),
+        max_draw_count: u32,
+        stride: u32
+    ) -> Result<()> {
+        if !self.draw_indirect_count {
+            anyhow::bail!("Drawing with a count from a buffer needs VK_KHR_draw_indirect_count");
+        }
+
+        self.record(command_buffer, Command::DrawIndexedIndirectCount {
+            buffer: commands.0,
+            offset: commands.1,
+            count_buffer: count.0,
+            count_offset: count.1,
+            max_draw_count,
+            stride,
+        });
+
+        Ok(())
+    }
+
+    fn cmd_dispatch(&self, command_buffer: vk::CommandBuffer, x: u32, y: u32, z: u32) {
+        self.record(command_buffer, Command::Dispatch {
+            x,
+            y,
+            z,
+        });
+    }
+}
@@ -0,0 +1,246 @@
+//! Merges queued draws that share a mesh and a material into one instanced draw.
+//!
+//! Draws are queued while a frame is recorded and merged when it ends, so what each one costs on the CPU is its
+//! `InstanceData` and a sort. The instances of the whole frame end up in one buffer, and every run of draws with
+//! the same mesh and material becomes one `cmd_draw_indexed` over its part of it. Shadow casters are merged the
+//! same way, by mesh.
+
+use ash::vk;
+
+use crate::renderer::backend::Backend;
+use crate::renderer::buffer::RendererBuffer;
+use crate::renderer::material::MaterialKey;
+use crate::renderer::mesh::{Bounds, InstanceData, Mesh};
+use crate::scene::AlphaMode;
+
+use gpu_allocator::MemoryLocation;
+
+use glam::{Mat4, Vec4};
+
+use std::mem;
+use std::ops::Range;
+
+use anyhow::Result;
+
+/// Instance buffers start out with room for this many and double when they run out.
+const MIN_INSTANCES: usize = 1024;
+
+/// A queued draw, with the bounds of its mesh.
+type Queued = (BatchKey, InstanceData, Bounds);
+
+/// What queued draws have to share to be merged, ordered so blended materials come last.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct BatchKey {
+    blend: bool,
+    /// `MaterialKey::index`, unused for shadow casters.
+    pipeline: usize,
+    descriptor_set: vk::DescriptorSet,
+    vertex_buffer: vk::Buffer,
+    index_buffer: vk::Buffer,
+    index_count: u32,
+}
+
+impl BatchKey {
+    fn new(mesh: &Mesh, material: Option<(MaterialKey, vk::DescriptorSet)>) -> BatchKey {
+        let (blend, pipeline, descriptor_set) = match material {
+            None => (false, 0, vk::DescriptorSet::null()),
+            Some((key, descriptor_set)) => (key.alpha_mode == AlphaMode::Blend, key.index(), descriptor_set)
+        };
+
+        BatchKey {
+            blend,
+            pipeline,
+            descriptor_set,
+            vertex_buffer: mesh.vertex_buffer.buffer,
+            index_buffer: mesh.index_buffer.buffer,
+            index_count: mesh.index_count,
+        }
+    }
+}
+
+/// A run of queued draws that became one instanced draw.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Batch {
+    pub vertex_buffer: vk::Buffer,
+    pub index_buffer: vk::Buffer,
+    pub index_count: u32,
+    /// The pipeline and material descriptor set to draw with, shadow casters have neither.
+    pub material: Option<(MaterialKey, vk::DescriptorSet)>,
+    /// Where the batch's instances are in the frame's instance buffer.
+    pub instances: Range<u32>,
+    /// The bounds of the mesh, which every instance shares.
+    pub bounds: Bounds,
+}
+
+/// How much merging the last frame that ended got done.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BatchStats {
+    pub draws: usize,
+    pub batches: usize,
+    pub casters: usize,
+    pub caster_batches: usize,
+}
+
+pub struct RendererBatches {
+    /// One instance with an identity matrix and white, bound for draws that aren't instanced.
+    pub single_instance: RendererBuffer,
+    /// One per frame in flight, replaced by a bigger one when a frame queues more instances than fit.
+    pub instance_buffers: Vec<RendererBuffer>,
+    pub stats: BatchStats,
+    draws: Vec<Queued>,
+    casters: Vec<Queued>,
+}
+
+impl RendererBatches {
+    pub fn new<B: Backend>(backend: &B, count: usize) -> Result<RendererBatches> {
+        let single_instance = RendererBuffer::with_data(
+            backend,
+            "single instance",
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            &[InstanceData::default()],
+        )?;
+
+        let mut batches = RendererBatches {
+            single_instance,
+            instance_buffers: Vec::with_capacity(count),
+            stats: BatchStats::default(),
+            draws: Vec::new(),
+            casters: Vec::new(),
+        };
+
+        for _ in 0..count {
+            match Self::create_instance_buffer(backend, MIN_INSTANCES) {
+                Err(err) => {
+                    unsafe {
+                        batches.cleanup(backend);
+                    };
+
+                    return Err(err);
+                },
+                Ok(buffer) => batches.instance_buffers.push(buffer)
+            }
+        }
+
+        Ok(batches)
+    }
+
+    fn create_instance_buffer<B: Backend>(backend: &B, instances: usize) -> Result<RendererBuffer> {
+        backend.create_buffer(
+            "instances",
+            (instances * mem::size_of::<InstanceData>()) as vk::DeviceSize,
+            // storage too, culling reads it:
+            vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::STORAGE_BUFFER,
+            MemoryLocation::CpuToGpu,
+        )
+    }
+
+    /// Starts a frame without anything queued.
+    pub fn begin(&mut self) {
+        self.draws.clear();
+        self.casters.clear();
+    }
+
+    /// Queues an instance of `mesh` drawn with the material in `descriptor_set`, through the pipeline for `key`.
+    pub fn queue(&mut self, mesh: &Mesh, key: MaterialKey, descriptor_set: vk::DescriptorSet, instance: InstanceData) {
+        self.draws.push((BatchKey::new(mesh, Some((key, descriptor_set))), instance, mesh.bounds));
+    }
+
+    /// Queues an instance of `mesh` rendered into the shadow maps.
+    pub fn queue_caster(&mut self, mesh: &Mesh, model: Mat4) {
+        self.casters.push((BatchKey::new(mesh, None), InstanceData::new(model, Vec4::ONE), mesh.bounds));
+    }
+
+    /// Merges what was queued and writes the instances into the buffer of the frame in `slot`.
+    ///
+    /// Returns the batches to draw and the batches of shadow casters, in that order.
+    pub fn flush<B: Backend>(&mut self, backend: &B, slot: usize) -> Result<(Vec<Batch>, Vec<Batch>)> {
+        // stable, so instances keep the order they were queued in:
+        self.draws.sort_by_key(|(key, _, _)| *key);
+        self.casters.sort_by_key(|(key, _, _)| *key);
+
+        let instance_count = self.draws.len() + self.casters.len();
+
+        let capacity = self.instance_buffers[slot].size as usize / mem::size_of::<InstanceData>();
+
+        // the frame in this slot has finished, so its buffer can be replaced:
+        if instance_count > capacity {
+            let buffer = Self::create_instance_buffer(backend, instance_count.next_power_of_two())?;
+
+            unsafe {
+                backend.destroy_buffer(&mut self.instance_buffers[slot]);
+            };
+
+            self.instance_buffers[slot] = buffer;
+        }
+
+        let instances: Vec<InstanceData> = self.draws.iter()
+            .chain(&self.casters)
+            .map(|(_, instance, _)| *instance)
+            .collect();
+
+        backend.write_buffer(&mut self.instance_buffers[slot], 0, RendererBuffer::bytes(&instances))?;
+
+        let batches = Self::merge(&self.draws, 0);
+        let caster_batches = Self::merge(&self.casters, self.draws.len() as u32);
+
+        self.stats = BatchStats {
+            draws: self.draws.len(),
+            batches: batches.len(),
+            casters: self.casters.len(),
+            caster_batches: caster_batches.len(),
+        };
+
+        self.draws.clear();
+        self.casters.clear();
+
+        Ok((batches, caster_batches))
+    }
+
+    /// Turns runs of the same key into batches, with instances counted from `first_instance`.
+    fn merge(queued: &[Queued], first_instance: u32) -> Vec<Batch> {
+        let mut batches: Vec<Batch> = Vec::new();
+
+        let mut previous = None;
+
+        for (i, (key, _, bounds)) in queued.iter().enumerate() {
+            let instance = first_instance + i as u32;
+
+            if previous == Some(key) {
+                if let Some(batch) = batches.last_mut() {
+                    batch.instances.end = instance + 1;
+                }
+
+                continue;
+            }
+
+            let material = if key.descriptor_set == vk::DescriptorSet::null() {
+                None
+            } else {
+                Some((MaterialKey::ALL[key.pipeline], key.descriptor_set))
+            };
+
+            batches.push(Batch {
+                vertex_buffer: key.vertex_buffer,
+                index_buffer: key.index_buffer,
+                index_count: key.index_count,
+                material,
+                instances: instance..instance + 1,
+                bounds: *bounds,
+            });
+
+            previous = Some(key);
+        }
+
+        batches
+    }
+
+    pub unsafe fn cleanup<B: Backend>(&mut self, backend: &B) {
+        for buffer in &mut self.instance_buffers {
+            backend.destroy_buffer(buffer);
+        }
+
+        self.instance_buffers.clear();
+
+        backend.destroy_buffer(&mut self.single_instance);
+    }
+}
@@ -0,0 +1,170 @@
+use ash::vk;
+
+use crate::renderer::device::RendererDevice;
+use crate::renderer::command_pools::CommandPools;
+use crate::renderer::backend::Backend;
+
+use gpu_allocator::MemoryLocation;
+use gpu_allocator::vulkan::{Allocation, AllocationCreateDesc};
+
+use std::{mem, slice};
+
+use anyhow::Result;
+
+pub struct RendererBuffer {
+    pub buffer: vk::Buffer,
+    pub allocation: Allocation,
+    pub size: vk::DeviceSize,
+}
+
+impl RendererBuffer {
+    pub fn new(
+        device: &RendererDevice,
+        name: &str,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        location: MemoryLocation
+    ) -> Result<RendererBuffer> {
+        let buffer_info = vk::BufferCreateInfo::builder()
+            .size(size)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        let buffer = unsafe {
+            device.logical_device.create_buffer(&buffer_info, None)?
+        };
+
+        let requirements = unsafe {
+            device.logical_device.get_buffer_memory_requirements(buffer)
+        };
+
+        let allocation = device.allocate(&AllocationCreateDesc {
+            name,
+            requirements,
+            location,
+            linear: true,
+        })?;
+
+        unsafe {
+            device.logical_device.bind_buffer_memory(buffer, allocation.memory(), allocation.offset())?
+        };
+
+        Ok(RendererBuffer {
+            buffer,
+            allocation,
+            size,
+        })
+    }
+
+    /// Creates a host visible buffer that already holds `data`.
+    pub fn with_data<B: Backend, T: Copy>(
+        backend: &B,
+        name: &str,
+        usage: vk::BufferUsageFlags,
+        data: &[T]
+    ) -> Result<RendererBuffer> {
+        let size = mem::size_of_val(data) as vk::DeviceSize;
+
+        let mut buffer = backend.create_buffer(name, size, usage, MemoryLocation::CpuToGpu)?;
+
+        if let Err(err) = backend.write_buffer(&mut buffer, 0, Self::bytes(data)) {
+            unsafe {
+                backend.destroy_buffer(&mut buffer);
+            };
+
+            return Err(err);
+        }
+
+        Ok(buffer)
+    }
+
+    /// The bytes of `data`, as they're written into buffers.
+    pub fn bytes<T: Copy>(data: &[T]) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts(data.as_ptr() as *const u8, mem::size_of_val(data))
+        }
+    }
+
+    /// Creates a device local buffer holding `data`, copied over from a temporary staging buffer.
+    pub fn device_local<T: Copy>(
+        device: &RendererDevice,
+        command_pools: &CommandPools,
+        name: &str,
+        usage: vk::BufferUsageFlags,
+        data: &[T]
+    ) -> Result<RendererBuffer> {
+        let size = mem::size_of_val(data) as vk::DeviceSize;
+
+        let mut staging = Self::with_data(device, "staging", vk::BufferUsageFlags::TRANSFER_SRC, data)?;
+
+        let buffer = Self::new(
+            device,
+            name,
+            size,
+            usage | vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuOnly
+        );
+
+        let result = buffer.and_then(|mut buffer| {
+            let copied = command_pools.one_time_submit(device, |command_buffer| {
+                let region = vk::BufferCopy::builder()
+                    .size(size);
+
+                unsafe {
+                    device.logical_device.cmd_copy_buffer(command_buffer, staging.buffer, buffer.buffer, &[region.build()]);
+                };
+            });
+
+            match copied {
+                Err(err) => {
+                    unsafe {
+                        buffer.cleanup(device);
+                    };
+
+                    Err(err)
+                },
+                Ok(()) => Ok(buffer)
+            }
+        });
+
+        unsafe {
+            staging.cleanup(device);
+        };
+
+        result
+    }
+
+    pub fn write<T: Copy>(&mut self, data: &[T]) -> Result<()> {
+        self.write_at(0, data)
+    }
+
+    pub fn write_at<T: Copy>(&mut self, offset: usize, data: &[T]) -> Result<()> {
+        let bytes = Self::bytes(data);
+
+        let mapped = match self.allocation.mapped_slice_mut() {
+            None => anyhow::bail!("Buffer is not host visible"),
+            Some(mapped) => mapped
+        };
+
+        if offset + bytes.len() > mapped.len() {
+            anyhow::bail!("Writing {} bytes at {} overflows a buffer of {} bytes", bytes.len(), offset, mapped.len());
+        }
+
+        mapped[offset..offset + bytes.len()].copy_from_slice(bytes);
+
+        Ok(())
+    }
+
+    pub fn read(&self) -> Result<&[u8]> {
+        match self.allocation.mapped_slice() {
+            None => anyhow::bail!("Buffer is not host visible"),
+            Some(mapped) => Ok(&mapped[..self.size as usize])
+        }
+    }
+
+    pub unsafe fn cleanup(&mut self, device: &RendererDevice) {
+        device.logical_device.destroy_buffer(self.buffer, None);
+
+        device.free(mem::take(&mut self.allocation)).unwrap();
+    }
+}
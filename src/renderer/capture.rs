@@ -0,0 +1,769 @@
+//! Recording what's rendered, as numbered PNG images or a Y4M video.
+//!
+//! When a frame ends, the target's image is blitted into an RGBA image of the capture's own, which takes care of
+//! BGRA swapchains. A compute pass then converts that into RGBA or YUV 4:2:0 rows in a host-visible buffer of the
+//! frame in flight. The buffer is read once the frame has finished, so the frame never waits for it, and a thread of
+//! its own encodes the frames and writes them out in order.
+//!
+//! Captures are sRGB encoded like the frame shows up on screen, frames of offscreen targets too. HDR10 and scRGB
+//! swapchains are captured as they're stored, which won't look right as 8-bit sRGB.
+
+use ash::vk;
+
+use crate::renderer::device::RendererDevice;
+use crate::renderer::backend::Backend;
+use crate::renderer::buffer::RendererBuffer;
+use crate::renderer::image::RendererImage;
+use crate::renderer::pipeline::RendererPipeline;
+use crate::renderer::target::RenderTarget;
+use crate::renderer::hdr::OutputEncoding;
+use crate::video::Yuv420;
+use crate::video::y4m::Y4mWriter;
+
+use gpu_allocator::MemoryLocation;
+
+use std::{fs, mem, slice, thread};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread::JoinHandle;
+
+use anyhow::{Context, Result};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureFormat {
+    /// An image per frame, numbered.
+    Png,
+    /// One uncompressed YUV 4:2:0 video.
+    Y4m,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CaptureSettings {
+    /// Ends in `.y4m` for a video, or in `.png` for images next to it: `out.png` becomes `out_00000.png` and on.
+    pub path: PathBuf,
+    /// Stops after this many frames, or runs until the capture is stopped.
+    pub frames: Option<u32>,
+    /// What the video says its frame rate is. Every frame is captured, however long it took to render.
+    pub fps: u32,
+}
+
+impl CaptureSettings {
+    pub fn new(path: impl Into<PathBuf>) -> CaptureSettings {
+        CaptureSettings {
+            path: path.into(),
+            frames: None,
+            fps: 60,
+        }
+    }
+
+    /// Takes `--record <path>`, `--frames <count>` and `--fps <rate>` out of `args`, `None` without `--record`.
+    pub fn from_args(args: &mut Vec<String>) -> Result<Option<CaptureSettings>> {
+        let mut settings = None;
+        let mut frames = None;
+        let mut fps = None;
+
+        let mut i = 0;
+
+        while i < args.len() {
+            let flag = args[i].as_str();
+
+            if !matches!(flag, "--record" | "--frames" | "--fps") {
+                i += 1;
+
+                continue;
+            }
+
+            let value = match args.get(i + 1) {
+                None => anyhow::bail!("{} needs a value", flag),
+                Some(value) => value.clone()
+            };
+
+            match flag {
+                "--record" => settings = Some(CaptureSettings::new(value)),
+                "--frames" => frames = Some(value.parse().with_context(|| format!("--frames {} isn't a count", value))?),
+                _ => fps = Some(value.parse().with_context(|| format!("--fps {} isn't a frame rate", value))?),
+            }
+
+            args.drain(i..i + 2);
+        }
+
+        let mut settings = match settings {
+            None if frames.is_some() || fps.is_some() => anyhow::bail!("--frames and --fps need --record"),
+            None => return Ok(None),
+            Some(settings) => settings
+        };
+
+        settings.frames = frames;
+        settings.fps = fps.unwrap_or(settings.fps);
+
+        Ok(Some(settings))
+    }
+
+    /// Which format `path` asks for.
+    pub fn format(&self) -> Result<CaptureFormat> {
+        let extension = self.path.extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("y4m") => Ok(CaptureFormat::Y4m),
+            Some("png") => Ok(CaptureFormat::Png),
+            _ => anyhow::bail!("Don't know how to capture to {}, it has to end in .y4m or .png", self.path.display()),
+        }
+    }
+
+    /// Where the PNG image of frame `index` goes.
+    pub fn png_path(&self, index: u32) -> PathBuf {
+        let stem = self.path.file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        self.path.with_file_name(format!("{}_{:05}.png", stem, index))
+    }
+}
+
+/// How the pass packs a frame into words, as the shader reads it.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct CaptureConstants {
+    size: [i32; 2],
+    /// Words per row of RGBA or Y, U and V rows are half as long.
+    stride: u32,
+    u_offset: u32,
+    v_offset: u32,
+    yuv: u32,
+    encode: u32,
+}
+
+impl CaptureConstants {
+    /// How a frame of `extent` is packed without sRGB encoding, and how many words that takes.
+    pub(crate) fn layout(format: CaptureFormat, extent: vk::Extent2D) -> (CaptureConstants, u32) {
+        let blocks_wide = extent.width.div_ceil(8);
+        let rows = extent.height.div_ceil(2) * 2;
+
+        // an RGBA word per pixel, or a Y byte per pixel and U and V bytes per 2x2 pixels:
+        match format {
+            CaptureFormat::Png => {
+                let constants = CaptureConstants {
+                    size: [extent.width as i32, extent.height as i32],
+                    stride: blocks_wide * 8,
+                    u_offset: 0,
+                    v_offset: 0,
+                    yuv: 0,
+                    encode: 0,
+                };
+
+                (constants, constants.stride * rows)
+            },
+            CaptureFormat::Y4m => {
+                let stride = blocks_wide * 2;
+                let chroma_words = stride / 2 * rows / 2;
+
+                let constants = CaptureConstants {
+                    size: [extent.width as i32, extent.height as i32],
+                    stride,
+                    u_offset: stride * rows,
+                    v_offset: stride * rows + chroma_words,
+                    yuv: 1,
+                    encode: 0,
+                };
+
+                (constants, constants.v_offset + chroma_words)
+            }
+        }
+    }
+
+    /// Copies `height` rows of `width` bytes out of rows that are `row` bytes long.
+    fn rows(bytes: &[u8], offset: usize, row: usize, width: usize, height: usize) -> Vec<u8> {
+        (0..height)
+            .flat_map(|y| &bytes[offset + y * row..offset + y * row + width])
+            .copied()
+            .collect()
+    }
+
+    /// Strips the padding off RGBA words the pass wrote.
+    pub(crate) fn unpack_rgba(&self, bytes: &[u8]) -> Vec<u8> {
+        let [width, height] = self.size;
+
+        Self::rows(bytes, 0, self.stride as usize * 4, width as usize * 4, height as usize)
+    }
+
+    /// Strips the padding off YUV planes the pass wrote.
+    pub(crate) fn unpack_yuv(&self, bytes: &[u8]) -> Yuv420 {
+        let (width, height) = (self.size[0] as u32, self.size[1] as u32);
+
+        let (chroma_width, chroma_height) = Yuv420::chroma_size(width, height);
+
+        let row = self.stride as usize * 4;
+
+        let plane = |offset: u32| {
+            Self::rows(bytes, offset as usize * 4, row / 2, chroma_width as usize, chroma_height as usize)
+        };
+
+        Yuv420 {
+            width,
+            height,
+            y: Self::rows(bytes, 0, row, width as usize, height as usize),
+            u: plane(self.u_offset),
+            v: plane(self.v_offset),
+        }
+    }
+}
+
+/// A frame on its way to the writer thread.
+enum Captured {
+    Rgba(Vec<u8>),
+    Yuv(Yuv420),
+}
+
+/// Sends frames to the writer thread with their number.
+type FrameSender = Sender<(u32, Captured)>;
+
+/// The capture pass, the buffers it writes per frame in flight, and the thread that writes what they hold.
+pub struct RendererCapture {
+    pub settings: CaptureSettings,
+    pub format: CaptureFormat,
+    pub extent: vk::Extent2D,
+    /// What the target's image is blitted into, sRGB when the target is.
+    pub image: RendererImage,
+    pub sampler: vk::Sampler,
+    pub set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    /// One per frame in flight, like the buffers.
+    pub descriptor_sets: Vec<vk::DescriptorSet>,
+    /// What the pass writes, padded to whole blocks of 8x2 pixels.
+    pub readback: Vec<RendererBuffer>,
+    pub pipeline: RendererPipeline,
+    /// How many frames were captured so far, counting the ones that haven't finished yet.
+    pub captured: u32,
+    constants: CaptureConstants,
+    /// The number of the frame each frame in flight captured, until it has been read.
+    pending: Vec<Option<u32>>,
+    sender: Option<FrameSender>,
+    writer: Option<JoinHandle<Result<u32>>>,
+}
+
+impl RendererCapture {
+    /// Starts capturing the frames of `target`, which needs `TRANSFER_SRC` usage.
+    pub fn new(
+        device: &RendererDevice,
+        settings: CaptureSettings,
+        target: &RenderTarget,
+        count: usize
+    ) -> Result<RendererCapture> {
+        let format = settings.format()?;
+
+        if !target.usage().contains(vk::ImageUsageFlags::TRANSFER_SRC) {
+            anyhow::bail!("The target's images can't be copied from, so they can't be captured");
+        }
+
+        let extent = target.extent();
+
+        let (constants, words) = CaptureConstants::layout(format, extent);
+
+        // sRGB formats are decoded when they're sampled, offscreen targets hold linear colors:
+        let encode = target.output_encoding() == OutputEncoding::Linear;
+
+        let image_format = match target.format() {
+            vk::Format::B8G8R8A8_SRGB
+            | vk::Format::R8G8B8A8_SRGB
+            | vk::Format::A8B8G8R8_SRGB_PACK32 => vk::Format::R8G8B8A8_SRGB,
+            _ => vk::Format::R8G8B8A8_UNORM,
+        };
+
+        let (sender, writer) = Self::spawn_writer(&settings, format, extent)?;
+
+        let mut capture = RendererCapture {
+            settings,
+            format,
+            extent,
+            image: RendererImage {
+                image: vk::Image::null(),
+                image_view: vk::ImageView::null(),
+                allocation: Default::default(),
+                format: image_format,
+                extent,
+                depth: 1,
+                layers: 1,
+                mip_levels: 1,
+                samples: vk::SampleCountFlags::TYPE_1,
+            },
+            sampler: vk::Sampler::null(),
+            set_layout: vk::DescriptorSetLayout::null(),
+            descriptor_pool: vk::DescriptorPool::null(),
+            descriptor_sets: Vec::with_capacity(count),
+            readback: Vec::with_capacity(count),
+            pipeline: RendererPipeline {
+                pipeline: vk::Pipeline::null(),
+                pipeline_layout: vk::PipelineLayout::null(),
+            },
+            captured: 0,
+            constants: CaptureConstants {
+                encode: encode as u32,
+                ..constants
+            },
+            pending: vec![None; count],
+            sender: Some(sender),
+            writer: Some(writer),
+        };
+
+        if let Err(err) = capture.create(device, count, words as vk::DeviceSize * 4) {
+            unsafe {
+                capture.cleanup(device);
+            };
+
+            return Err(err);
+        }
+
+        Ok(capture)
+    }
+
+    fn create(&mut self, device: &RendererDevice, count: usize, size: vk::DeviceSize) -> Result<()> {
+        self.image = RendererImage::new(
+            device,
+            "capture",
+            self.extent,
+            self.image.format,
+            vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+        )?;
+
+        for _ in 0..count {
+            self.readback.push(RendererBuffer::new(
+                device,
+                "capture readback",
+                size,
+                vk::BufferUsageFlags::STORAGE_BUFFER,
+                MemoryLocation::GpuToCpu,
+            )?);
+        }
+
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::NEAREST)
+            .min_filter(vk::Filter::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+
+        self.sampler = unsafe {
+            device.logical_device.create_sampler(&sampler_info, None)?
+        };
+
+        // descriptors:
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .build(),
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .build(),
+        ];
+
+        let set_layout_info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .bindings(&bindings);
+
+        self.set_layout = unsafe {
+            device.logical_device.create_descriptor_set_layout(&set_layout_info, None)?
+        };
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: count as u32,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: count as u32,
+            },
+        ];
+
+        let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(count as u32)
+            .pool_sizes(&pool_sizes);
+
+        self.descriptor_pool = unsafe {
+            device.logical_device.create_descriptor_pool(&pool_info, None)?
+        };
+
+        let set_layouts = vec![self.set_layout; count];
+
+        let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(self.descriptor_pool)
+            .set_layouts(&set_layouts);
+
+        self.descriptor_sets = unsafe {
+            device.logical_device.allocate_descriptor_sets(&allocate_info)?
+        };
+
+        for (descriptor_set, readback) in self.descriptor_sets.iter().zip(&self.readback) {
+            let image_infos = [
+                vk::DescriptorImageInfo {
+                    sampler: self.sampler,
+                    image_view: self.image.image_view,
+                    image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                }
+            ];
+
+            let buffer_infos = [
+                vk::DescriptorBufferInfo {
+                    buffer: readback.buffer,
+                    offset: 0,
+                    range: readback.size,
+                }
+            ];
+
+            let writes = [
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(*descriptor_set)
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(&image_infos)
+                    .build(),
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(*descriptor_set)
+                    .dst_binding(1)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .buffer_info(&buffer_infos)
+                    .build(),
+            ];
+
+            unsafe {
+                device.logical_device.update_descriptor_sets(&writes, &[]);
+            };
+        }
+
+        // pipeline:
+        let push_constant_ranges = [
+            vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::COMPUTE,
+                offset: 0,
+                size: mem::size_of::<CaptureConstants>() as u32,
+            }
+        ];
+
+        self.pipeline = RendererPipeline::compute(
+            device,
+            vk_shader_macros::include_glsl!("./shaders/capture.comp"),
+            &[self.set_layout],
+            &push_constant_ranges,
+        )?;
+
+        Ok(())
+    }
+
+    /// Opens the file or checks the directory up front, so that the capture fails to start instead of later.
+    fn spawn_writer(
+        settings: &CaptureSettings,
+        format: CaptureFormat,
+        extent: vk::Extent2D
+    ) -> Result<(FrameSender, JoinHandle<Result<u32>>)> {
+        if let Some(parent) = settings.path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {} for the capture", parent.display()))?;
+        }
+
+        let mut y4m = match format {
+            CaptureFormat::Png => None,
+            CaptureFormat::Y4m => {
+                let file = File::create(&settings.path)
+                    .with_context(|| format!("Failed to create {}", settings.path.display()))?;
+
+                Some(Y4mWriter::new(BufWriter::new(file), extent.width, extent.height, settings.fps)?)
+            }
+        };
+
+        let settings = settings.clone();
+
+        let (sender, receiver) = mpsc::channel::<(u32, Captured)>();
+
+        let writer = thread::spawn(move || {
+            let mut written = 0;
+
+            // frames come in the order they were captured, until the capture stops:
+            for (index, captured) in receiver {
+                match (captured, &mut y4m) {
+                    (Captured::Yuv(frame), Some(y4m)) => y4m.write_frame(&frame)?,
+                    (Captured::Rgba(pixels), _) => Self::write_png(&settings.png_path(index), extent, &pixels)?,
+                    (Captured::Yuv(_), None) => anyhow::bail!("A YUV frame was captured for PNG images"),
+                }
+
+                written += 1;
+            }
+
+            if let Some(y4m) = y4m {
+                y4m.finish()?;
+            }
+
+            Ok(written)
+        });
+
+        Ok((sender, writer))
+    }
+
+    fn write_png(path: &Path, extent: vk::Extent2D, pixels: &[u8]) -> Result<()> {
+        image::save_buffer(path, pixels, extent.width, extent.height, image::ColorType::Rgba8)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Whether as many frames were captured as the settings ask for.
+    pub fn is_done(&self) -> bool {
+        match self.settings.frames {
+            None => false,
+            Some(frames) => self.captured >= frames
+        }
+    }
+
+    /// Records copying `image`, the target's image of the frame in `slot`, and converting it into the frame's buffer.
+    ///
+    /// `layout` is the one the image is in and is left in. Nothing is recorded once the capture is done.
+    pub fn record<B: Backend>(
+        &mut self,
+        backend: &B,
+        command_buffer: vk::CommandBuffer,
+        slot: usize,
+        image: vk::Image,
+        layout: vk::ImageLayout
+    ) {
+        if self.is_done() {
+            return;
+        }
+
+        let color = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+
+        let barrier = |image: vk::Image, old_layout, new_layout, src_access_mask, dst_access_mask| {
+            vk::ImageMemoryBarrier::builder()
+                .src_access_mask(src_access_mask)
+                .dst_access_mask(dst_access_mask)
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(image)
+                .subresource_range(color)
+                .build()
+        };
+
+        // the copy is overwritten once the previous frame's pass has read it:
+        let to_copy = [
+            barrier(
+                image,
+                layout,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                vk::AccessFlags::TRANSFER_READ,
+            ),
+            barrier(
+                self.image.image,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::AccessFlags::empty(),
+                vk::AccessFlags::TRANSFER_WRITE,
+            ),
+        ];
+
+        let copied = [
+            barrier(
+                image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                layout,
+                vk::AccessFlags::TRANSFER_READ,
+                vk::AccessFlags::empty(),
+            ),
+            barrier(
+                self.image.image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::AccessFlags::SHADER_READ,
+            ),
+        ];
+
+        let corner = vk::Offset3D {
+            x: self.extent.width as i32,
+            y: self.extent.height as i32,
+            z: 1,
+        };
+
+        let subresource = vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+
+        // a blit rather than a copy, it swizzles BGRA into RGBA:
+        let blit = vk::ImageBlit::builder()
+            .src_subresource(subresource)
+            .src_offsets([vk::Offset3D::default(), corner])
+            .dst_subresource(subresource)
+            .dst_offsets([vk::Offset3D::default(), corner]);
+
+        let converted = vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::HOST_READ);
+
+        let constants_bytes = unsafe {
+            slice::from_raw_parts(&self.constants as *const CaptureConstants as *const u8, mem::size_of::<CaptureConstants>())
+        };
+
+        backend.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::TRANSFER,
+            &[],
+            &[],
+            &to_copy,
+        );
+
+        backend.cmd_blit_image(
+            command_buffer,
+            (image, vk::ImageLayout::TRANSFER_SRC_OPTIMAL),
+            (self.image.image, vk::ImageLayout::TRANSFER_DST_OPTIMAL),
+            &[blit.build()],
+            vk::Filter::NEAREST,
+        );
+
+        backend.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            &[],
+            &[],
+            &copied,
+        );
+
+        backend.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, self.pipeline.pipeline);
+
+        backend.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            self.pipeline.pipeline_layout,
+            0,
+            &[self.descriptor_sets[slot]],
+            &[],
+        );
+
+        backend.cmd_push_constants(
+            command_buffer,
+            self.pipeline.pipeline_layout,
+            vk::ShaderStageFlags::COMPUTE,
+            0,
+            constants_bytes,
+        );
+
+        // 8x8 invocations per group as in the shader, each covering 8x2 pixels:
+        backend.cmd_dispatch(
+            command_buffer,
+            self.extent.width.div_ceil(8).div_ceil(8),
+            self.extent.height.div_ceil(2).div_ceil(8),
+            1,
+        );
+
+        backend.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::HOST,
+            &[converted.build()],
+            &[],
+            &[],
+        );
+
+        self.pending[slot] = Some(self.captured);
+
+        self.captured += 1;
+    }
+
+    /// Hands what the frame in `slot` captured to the writer, after its fence was waited for.
+    pub fn frame_finished(&mut self, slot: usize) -> Result<()> {
+        let index = match self.pending[slot].take() {
+            None => return Ok(()),
+            Some(index) => index
+        };
+
+        let captured = self.unpack(slot)?;
+
+        let sent = match &self.sender {
+            None => false,
+            Some(sender) => sender.send((index, captured)).is_ok()
+        };
+
+        // the writer only hangs up when it failed, which joining it reports:
+        if !sent {
+            self.join_writer()?;
+
+            anyhow::bail!("The capture stopped writing frames");
+        }
+
+        Ok(())
+    }
+
+    /// Strips the padding off what the frame in `slot` captured.
+    fn unpack(&self, slot: usize) -> Result<Captured> {
+        let bytes = self.readback[slot].read()?;
+
+        Ok(match self.format {
+            CaptureFormat::Png => Captured::Rgba(self.constants.unpack_rgba(bytes)),
+            CaptureFormat::Y4m => Captured::Yuv(self.constants.unpack_yuv(bytes)),
+        })
+    }
+
+    fn join_writer(&mut self) -> Result<u32> {
+        self.sender.take();
+
+        match self.writer.take() {
+            None => Ok(0),
+            Some(writer) => match writer.join() {
+                Err(_) => anyhow::bail!("The capture's writer panicked"),
+                Ok(written) => written
+            }
+        }
+    }
+
+    /// Hands every frame that's left to the writer and waits for it to write them, returns how many it wrote.
+    ///
+    /// Every captured frame has to have finished.
+    pub fn finish(&mut self) -> Result<u32> {
+        let mut slots: Vec<(usize, u32)> = self.pending.iter()
+            .enumerate()
+            .filter_map(|(slot, index)| index.map(|index| (slot, index)))
+            .collect();
+
+        slots.sort_by_key(|&(_, index)| index);
+
+        for (slot, _) in slots {
+            self.frame_finished(slot)?;
+        }
+
+        self.join_writer()
+    }
+
+    /// Stops the writer without waiting for it, frames that weren't handed to it yet are lost.
+    pub unsafe fn cleanup(&mut self, device: &RendererDevice) {
+        self.sender.take();
+
+        device.logical_device.destroy_descriptor_pool(self.descriptor_pool, None);
+        device.logical_device.destroy_descriptor_set_layout(self.set_layout, None);
+        device.logical_device.destroy_sampler(self.sampler, None);
+
+        self.pipeline.cleanup(&device.logical_device);
+
+        for readback in &mut self.readback {
+            readback.cleanup(device);
+        }
+
+        self.image.cleanup(device);
+    }
+}
@@ -6,11 +6,27 @@ use anyhow::Result;
 
 pub struct CommandPools {
     pub graphics: vk::CommandPool,
+    /// Pools for recording a frame's secondary command buffers, the first for the main thread and one for each
+    /// worker after it.
+    pub threads: Vec<ThreadCommandPools>,
+    graphics_queue_family: u32,
+    frame_count: usize,
+}
+
+/// The command pools of one recording thread, one for each frame in flight.
+///
+/// A pool is only reset once its frame has finished, and is never used by two threads at once.
+pub struct ThreadCommandPools {
+    pub pools: Vec<vk::CommandPool>,
+    /// The secondary command buffers allocated from each pool, reused after the pool is reset.
+    pub secondary: Vec<Vec<vk::CommandBuffer>>,
+    used: Vec<usize>,
 }
 
 impl CommandPools {
     pub fn new(
-        device: &RendererDevice
+        device: &RendererDevice,
+        frame_count: usize
     ) -> Result<CommandPools> {
         let graphics_queue_family = match device.queue_family(vk::QueueFlags::GRAPHICS) {
             None => panic!("No graphics queue family found, don't know what to do!"),
@@ -25,9 +41,72 @@ impl CommandPools {
             device.logical_device.create_command_pool(&graphics_command_pool_info, None)?
         };
 
-        Ok(CommandPools {
+        let mut command_pools = CommandPools {
             graphics: graphics_command_pool,
-        })
+            threads: vec![],
+            graphics_queue_family: graphics_queue_family.index,
+            frame_count,
+        };
+
+        // the main thread's:
+        if let Err(e) = command_pools.add_threads(device, 1) {
+            unsafe {
+                command_pools.cleanup(device);
+            };
+
+            return Err(e);
+        }
+
+        Ok(command_pools)
+    }
+
+    /// Makes sure there are pools for at least `count` threads.
+    pub fn add_threads(&mut self, device: &RendererDevice, count: usize) -> Result<()> {
+        while self.threads.len() < count {
+            let mut thread = ThreadCommandPools {
+                pools: Vec::with_capacity(self.frame_count),
+                secondary: vec![vec![]; self.frame_count],
+                used: vec![0; self.frame_count],
+            };
+
+            let pool_info = vk::CommandPoolCreateInfo::builder()
+                .queue_family_index(self.graphics_queue_family)
+                .flags(vk::CommandPoolCreateFlags::TRANSIENT);
+
+            for _ in 0..self.frame_count {
+                let created = unsafe {
+                    device.logical_device.create_command_pool(&pool_info, None)
+                };
+
+                match created {
+                    Ok(pool) => thread.pools.push(pool),
+                    Err(e) => {
+                        unsafe {
+                            thread.cleanup(device);
+                        };
+
+                        return Err(e.into());
+                    }
+                }
+            }
+
+            self.threads.push(thread);
+        }
+
+        Ok(())
+    }
+
+    /// Resets every thread's pool for the frame in `slot`, which has to have finished.
+    pub fn reset_frame(&mut self, device: &RendererDevice, slot: usize) -> Result<()> {
+        for thread in &mut self.threads {
+            unsafe {
+                device.logical_device.reset_command_pool(thread.pools[slot], vk::CommandPoolResetFlags::empty())?;
+            };
+
+            thread.used[slot] = 0;
+        }
+
+        Ok(())
     }
 
     pub fn create_command_buffers(
@@ -44,7 +123,84 @@ impl CommandPools {
         }
     }
 
+    /// Records and submits a command buffer on the graphics queue, then waits for it to finish.
+    pub fn one_time_submit<F: FnOnce(vk::CommandBuffer)>(
+        &self,
+        device: &RendererDevice,
+        record: F
+    ) -> Result<()> {
+        let graphics_queue = match device.queue_family(vk::QueueFlags::GRAPHICS) {
+            None => panic!("No graphics queue family found, don't know what to do!"),
+            Some(qf) => qf.queues[0]
+        };
+
+        let command_buffer = Self::create_command_buffers(device, self.graphics, 1)?[0];
+
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+        unsafe {
+            device.logical_device.begin_command_buffer(command_buffer, &begin_info)?;
+        };
+
+        record(command_buffer);
+
+        let command_buffers = [command_buffer];
+
+        let submit_info = [
+            vk::SubmitInfo::builder()
+                .command_buffers(&command_buffers)
+                .build()
+        ];
+
+        unsafe {
+            device.logical_device.end_command_buffer(command_buffer)?;
+
+            device.logical_device.queue_submit(graphics_queue, &submit_info, vk::Fence::null())?;
+            device.logical_device.queue_wait_idle(graphics_queue)?;
+
+            device.logical_device.free_command_buffers(self.graphics, &command_buffers);
+        };
+
+        Ok(())
+    }
+
     pub unsafe fn cleanup(&self, device: &RendererDevice) {
+        for thread in &self.threads {
+            thread.cleanup(device);
+        }
+
         device.logical_device.destroy_command_pool(self.graphics, None);
     }
+}
+
+impl ThreadCommandPools {
+    /// Hands out a secondary command buffer for the frame in `slot`, allocating one when all of them are in use.
+    pub fn secondary(&mut self, device: &RendererDevice, slot: usize) -> Result<vk::CommandBuffer> {
+        if self.used[slot] == self.secondary[slot].len() {
+            let allocate_info = vk::CommandBufferAllocateInfo::builder()
+                .command_pool(self.pools[slot])
+                .level(vk::CommandBufferLevel::SECONDARY)
+                .command_buffer_count(1);
+
+            let allocated = unsafe {
+                device.logical_device.allocate_command_buffers(&allocate_info)?
+            };
+
+            self.secondary[slot].extend(allocated);
+        }
+
+        let command_buffer = self.secondary[slot][self.used[slot]];
+
+        self.used[slot] += 1;
+
+        Ok(command_buffer)
+    }
+
+    /// Destroys the pools, which frees their command buffers with them.
+    pub unsafe fn cleanup(&self, device: &RendererDevice) {
+        for pool in &self.pools {
+            device.logical_device.destroy_command_pool(*pool, None);
+        }
+    }
 }
\ No newline at end of file
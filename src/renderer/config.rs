@@ -0,0 +1,479 @@
+//! Renderer settings, loaded from a TOML or RON file and overridden by environment variables and flags.
+//!
+//! Every field has a default, so a file only needs what it changes:
+//!
+//! ```toml
+//! present_mode = "mailbox"
+//! clear_color = [0.1, 0.1, 0.1, 1.0]
+//!
+//! [window]
+//! width = 1920
+//! height = 1080
+//! mode = "borderless"
+//! ```
+//!
+//! RON files have the same fields, like `(present_mode: mailbox, window: (width: 1920, height: 1080))`.
+//!
+//! A setting is overridden by `VULKAN_VIDEO_<KEY>` in the environment and by `--<key> <value>` on the command
+//! line, in that order, where the key is its path in the file: `window.width` is `VULKAN_VIDEO_WINDOW_WIDTH` and
+//! `--window-width`. Values are written like they would be in a TOML file, with the quotes around strings left out.
+
+use ash::vk;
+
+use serde::{Deserialize, Serialize};
+
+use winit::window::CursorIcon;
+
+use std::env;
+use std::ffi;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+/// Where the window is shown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowMode {
+    Windowed,
+    /// A borderless window covering the monitor, `fullscreen` in files too.
+    #[serde(alias = "fullscreen")]
+    Borderless,
+    /// Takes the monitor over and switches it to the video mode closest to the window's size.
+    Exclusive,
+}
+
+/// How frames are presented, `Fifo` is used where the surface doesn't have the one asked for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PresentMode {
+    /// Waits for vertical blank, always available.
+    Fifo,
+    /// Like `Fifo`, but a late frame is shown right away and can tear.
+    FifoRelaxed,
+    /// Waits for vertical blank, but replaces the queued frame with newer ones.
+    Mailbox,
+    /// Doesn't wait, and can tear.
+    Immediate,
+}
+
+impl From<PresentMode> for vk::PresentModeKHR {
+    fn from(present_mode: PresentMode) -> Self {
+        match present_mode {
+            PresentMode::Fifo => vk::PresentModeKHR::FIFO,
+            PresentMode::FifoRelaxed => vk::PresentModeKHR::FIFO_RELAXED,
+            PresentMode::Mailbox => vk::PresentModeKHR::MAILBOX,
+            PresentMode::Immediate => vk::PresentModeKHR::IMMEDIATE,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowConfig {
+    pub title: String,
+    /// The size of what's rendered into, in logical pixels, so it's rendered at a higher resolution on monitors
+    /// with a higher DPI. The video mode's resolution in physical pixels in `Exclusive` mode.
+    pub width: u32,
+    pub height: u32,
+    /// Where the window's top left corner goes in logical pixels, where the platform likes without it.
+    pub position: Option<[i32; 2]>,
+    pub resizable: bool,
+    /// The title bar and borders.
+    pub decorations: bool,
+    pub mode: WindowMode,
+    /// Which of the monitors fullscreen modes go on, the primary one without it.
+    pub monitor: Option<usize>,
+    /// The refresh rate in Hz the video mode is picked by in `Exclusive` mode, the highest without it.
+    pub refresh_rate: Option<u16>,
+    /// A PNG or JPEG the window is shown with.
+    pub icon: Option<PathBuf>,
+    pub cursor_visible: bool,
+    /// Keeps the cursor in the window, for mouse look.
+    pub cursor_grab: bool,
+    pub cursor_icon: CursorIcon,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        WindowConfig {
+            title: "Vulkan Engine".to_owned(),
+            width: 800,
+            height: 600,
+            position: None,
+            resizable: true,
+            decorations: true,
+            mode: WindowMode::Windowed,
+            monitor: None,
+            refresh_rate: None,
+            icon: None,
+            cursor_visible: true,
+            cursor_grab: false,
+            cursor_icon: CursorIcon::Default,
+        }
+    }
+}
+
+impl WindowConfig {
+    /// Checks the settings for values that can't work, with what's wrong.
+    pub fn validate(&self) -> Result<()> {
+        if self.width == 0 || self.height == 0 {
+            anyhow::bail!("The window can't be {}x{}", self.width, self.height);
+        }
+
+        if self.refresh_rate == Some(0) {
+            anyhow::bail!("The refresh rate can't be 0 Hz, leave it out for the highest");
+        }
+
+        if self.title.contains('\0') {
+            anyhow::bail!("window.title can't have a NUL in it");
+        }
+
+        Ok(())
+    }
+
+    /// Which of the video modes, as their size, refresh rate and bit depth, `Exclusive` mode switches to.
+    ///
+    /// The ones with the window's size come first, then the refresh rate closest to `refresh_rate` or the highest,
+    /// then the highest bit depth. Without one of the window's size, the largest is picked.
+    pub fn pick_video_mode(&self, modes: &[([u32; 2], u16, u16)]) -> Option<usize> {
+        let size = [self.width, self.height];
+
+        let key = |&([width, height], refresh_rate, bit_depth): &([u32; 2], u16, u16)| {
+            let refresh_rate_distance = match self.refresh_rate {
+                None => u16::MAX - refresh_rate,
+                Some(wanted) => wanted.abs_diff(refresh_rate)
+            };
+
+            (
+                [width, height] != size,
+                u64::MAX - width as u64 * height as u64,
+                refresh_rate_distance,
+                u16::MAX - bit_depth,
+            )
+        };
+
+        (0..modes.len()).min_by_key(|&i| key(&modes[i]))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RendererConfig {
+    /// The application name the driver is told about.
+    pub app_name: String,
+    /// The Vulkan version asked for, `"1.1"` or newer.
+    pub api_version: String,
+    /// Picks the first device with this in its name, e.g. `llvmpipe` for lavapipe. A discrete GPU is preferred
+    /// without it.
+    pub device: Option<String>,
+    pub present_mode: PresentMode,
+    /// Samples per pixel of the scene, 1 renders without MSAA. The device has to support it for color and depth.
+    pub samples: u32,
+    /// Enables `VK_LAYER_KHRONOS_validation`, which has to be installed.
+    pub validation: bool,
+    /// How many swapchain images frames are recorded into ahead of the one being shown, at least what the surface
+    /// needs. Headless renderers always have one.
+    pub frames_in_flight: u32,
+    /// What the scene is cleared to before drawing, in linear RGBA.
+    pub clear_color: [f32; 4],
+    /// Where `asset` looks for relative paths.
+    pub assets: PathBuf,
+    /// Presents in HDR10 when the window's surface offers it, see [`super::VulkanRenderer::new_hdr`].
+    pub hdr: bool,
+    /// Last, TOML has tables after the plain values.
+    pub window: WindowConfig,
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        RendererConfig {
+            app_name: "Vulkan App".to_owned(),
+            api_version: "1.1".to_owned(),
+            device: None,
+            present_mode: PresentMode::Fifo,
+            samples: 1,
+            validation: true,
+            frames_in_flight: 3,
+            clear_color: [0.0, 0.0, 0.0, 1.0],
+            assets: PathBuf::from("assets"),
+            hdr: false,
+            window: WindowConfig::default(),
+        }
+    }
+}
+
+impl RendererConfig {
+    /// Every setting that can be overridden, by its path in the file.
+    pub const KEYS: [&'static str; 23] = [
+        "app_name",
+        "api_version",
+        "window.title",
+        "window.width",
+        "window.height",
+        "window.position",
+        "window.resizable",
+        "window.decorations",
+        "window.mode",
+        "window.monitor",
+        "window.refresh_rate",
+        "window.icon",
+        "window.cursor_visible",
+        "window.cursor_grab",
+        "window.cursor_icon",
+        "device",
+        "present_mode",
+        "samples",
+        "validation",
+        "frames_in_flight",
+        "clear_color",
+        "assets",
+        "hdr",
+    ];
+
+    /// Reads a `.toml` or `.ron` file, and checks it.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<RendererConfig> {
+        let path = path.as_ref();
+
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read the renderer config from {}", path.display()))?;
+
+        let extension = path.extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+
+        let config = match extension.as_deref() {
+            Some("toml") => Self::from_toml(&source),
+            Some("ron") => Self::from_ron(&source),
+            _ => anyhow::bail!("Don't know how to read {}, it has to end in .toml or .ron", path.display()),
+        };
+
+        config.with_context(|| format!("Failed to parse the renderer config in {}", path.display()))
+    }
+
+    pub fn from_toml(source: &str) -> Result<RendererConfig> {
+        let config: RendererConfig = toml::from_str(source)?;
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    pub fn from_ron(source: &str) -> Result<RendererConfig> {
+        let config: RendererConfig = ron::from_str(source)?;
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+
+    pub fn to_ron(&self) -> Result<String> {
+        Ok(ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())?)
+    }
+
+    /// The defaults with what the environment overrides, how `VulkanRenderer::new` and `headless` are set up.
+    pub fn from_env() -> Result<RendererConfig> {
+        let mut config = RendererConfig::default();
+
+        config.apply_env()?;
+
+        Ok(config)
+    }
+
+    /// Everything in order: the file from `--config` or `VULKAN_VIDEO_CONFIG` if there's one, the environment,
+    /// then the flags in `args`, which are taken out of it.
+    pub fn load(args: &mut Vec<String>) -> Result<RendererConfig> {
+        let mut path = env::var_os("VULKAN_VIDEO_CONFIG").map(PathBuf::from);
+
+        if let Some(i) = args.iter().position(|arg| arg == "--config") {
+            let value = match args.get(i + 1) {
+                None => anyhow::bail!("--config needs a value"),
+                Some(value) => value.clone()
+            };
+
+            path = Some(PathBuf::from(value));
+
+            args.drain(i..i + 2);
+        }
+
+        let mut config = match path {
+            None => RendererConfig::default(),
+            Some(path) => Self::from_file(path)?
+        };
+
+        config.apply_env()?;
+        config.apply_args(args)?;
+
+        Ok(config)
+    }
+
+    /// Overrides settings with the `VULKAN_VIDEO_<KEY>` variables that are set.
+    pub fn apply_env(&mut self) -> Result<()> {
+        for key in Self::KEYS {
+            let name = format!("VULKAN_VIDEO_{}", key.replace('.', "_").to_ascii_uppercase());
+
+            if let Ok(value) = env::var(&name) {
+                self.set(key, &value).with_context(|| format!("{} is invalid", name))?;
+            }
+        }
+
+        self.validate()
+    }
+
+    /// Overrides settings with the `--<key> <value>` flags in `args`, and takes them out of it.
+    ///
+    /// A flag for a setting that's on or off can be given without a value, to turn it on.
+    pub fn apply_args(&mut self, args: &mut Vec<String>) -> Result<()> {
+        let mut i = 0;
+
+        while i < args.len() {
+            let key = match Self::KEYS.iter().find(|key| args[i] == Self::flag(key)) {
+                None => {
+                    i += 1;
+
+                    continue;
+                },
+                Some(key) => *key
+            };
+
+            let value = args.get(i + 1).cloned();
+
+            let (value, taken) = match value {
+                Some(value) if !Self::is_switch(key) || value == "true" || value == "false" => (value, 2),
+                _ if Self::is_switch(key) => ("true".to_owned(), 1),
+                _ => anyhow::bail!("{} needs a value", args[i])
+            };
+
+            self.set(key, &value).with_context(|| format!("{} {} is invalid", args[i], value))?;
+
+            args.drain(i..i + taken);
+        }
+
+        self.validate()
+    }
+
+    /// The flag that overrides `key`, `--window-width` for `window.width`.
+    pub fn flag(key: &str) -> String {
+        format!("--{}", key.replace(['.', '_'], "-"))
+    }
+
+    fn is_switch(key: &str) -> bool {
+        matches!(
+            key,
+            "validation" | "hdr" | "window.resizable" | "window.decorations" | "window.cursor_visible" | "window.cursor_grab"
+        )
+    }
+
+    /// Sets the setting at `key` to `value`, written like it would be in a TOML file or as a bare string.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        if !Self::KEYS.contains(&key) {
+            anyhow::bail!("There's no setting called {}, there are {}", key, Self::KEYS.join(", "));
+        }
+
+        let parsed = toml::from_str::<toml::value::Table>(&format!("value = {}", value))
+            .ok()
+            .and_then(|mut table| table.remove("value"));
+
+        let mut root = toml::Value::try_from(&*self)?;
+
+        let (parents, name) = match key.rsplit_once('.') {
+            None => (None, key),
+            Some((parents, name)) => (Some(parents), name)
+        };
+
+        let mut table = match root.as_table_mut() {
+            None => anyhow::bail!("The config isn't a table"),
+            Some(table) => table
+        };
+
+        for parent in parents.into_iter().flat_map(|parents| parents.split('.')) {
+            table = match table.get_mut(parent).and_then(|parent| parent.as_table_mut()) {
+                None => anyhow::bail!("There's no {} table", parent),
+                Some(table) => table
+            };
+        }
+
+        // a bare word that isn't TOML on its own, or a version like `1.2` for a string setting, is taken as a string,
+        // so `mailbox` works without quotes:
+        let value = match (parsed, table.get(name)) {
+            (Some(parsed), Some(toml::Value::String(_))) if !parsed.is_str() => toml::Value::String(value.to_owned()),
+            (Some(parsed), _) => parsed,
+            (None, _) => toml::Value::String(value.to_owned())
+        };
+
+        table.insert(name.to_owned(), value);
+
+        *self = root.try_into()?;
+
+        Ok(())
+    }
+
+    /// Checks the settings for values that can't work, with what's wrong.
+    pub fn validate(&self) -> Result<()> {
+        self.window.validate()?;
+
+        let api_version = self.vk_api_version()?;
+
+        if api_version < vk::API_VERSION_1_1 {
+            anyhow::bail!("The renderer needs Vulkan 1.1 or newer, not {}", self.api_version);
+        }
+
+        // Vulkan's sample counts are powers of two up to 64:
+        if !self.samples.is_power_of_two() || self.samples > 64 {
+            anyhow::bail!("samples has to be 1, 2, 4, 8, 16, 32 or 64, not {}", self.samples);
+        }
+
+        if !(1..=8).contains(&self.frames_in_flight) {
+            anyhow::bail!("There have to be 1 to 8 frames in flight, not {}", self.frames_in_flight);
+        }
+
+        if self.clear_color.iter().any(|channel| !channel.is_finite()) {
+            anyhow::bail!("The clear color {:?} isn't a color", self.clear_color);
+        }
+
+        if self.app_name.contains('\0') {
+            anyhow::bail!("app_name can't have a NUL in it");
+        }
+
+        if self.device.as_deref() == Some("") {
+            anyhow::bail!("device can't be empty, leave it out to let the renderer pick one");
+        }
+
+        Ok(())
+    }
+
+    /// `api_version` as Vulkan packs it.
+    pub fn vk_api_version(&self) -> Result<u32> {
+        let parsed = self.api_version.split_once('.')
+            .and_then(|(major, minor)| Some((major.parse().ok()?, minor.parse().ok()?)));
+
+        match parsed {
+            None => anyhow::bail!("The API version {} isn't like 1.2", self.api_version),
+            Some((major, minor)) => Ok(vk::make_api_version(0, major, minor, 0))
+        }
+    }
+
+    /// `samples` as Vulkan's flag for it, the bit of each count is the count.
+    pub fn sample_count(&self) -> vk::SampleCountFlags {
+        vk::SampleCountFlags::from_raw(self.samples)
+    }
+
+    pub fn app_name(&self) -> Result<ffi::CString> {
+        Ok(ffi::CString::new(self.app_name.as_str())?)
+    }
+
+    /// Where an asset is, `path` itself if it's absolute or there's something there, in `assets` otherwise.
+    pub fn asset<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        let path = path.as_ref();
+
+        if path.is_absolute() || path.exists() {
+            return path.to_owned();
+        }
+
+        self.assets.join(path)
+    }
+}
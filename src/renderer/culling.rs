@@ -0,0 +1,1007 @@
+//! Frustum and occlusion culling of queued draws, on the GPU.
+//!
+//! When a frame ends, a compute pass tests every instance in its [`batches`](crate::renderer::batches) against the
+//! camera frustum, with the bounding sphere of its mesh and then its box. What's left is tested against a depth
+//! pyramid: mip levels of the previous frame's depth buffer where every texel keeps the farthest depth of those it
+//! covers, so a box can be checked against a few texels of whichever level it spans about two of. The instances
+//! that pass are packed together per batch, and every batch is drawn with an indirect command whose instance count
+//! the pass filled in.
+//!
+//! The pyramid is from the frame before, so what comes out from behind something shows up a frame late. That's why
+//! occlusion culling is off by default.
+
+use ash::vk;
+
+use crate::renderer::device::RendererDevice;
+use crate::renderer::backend::Backend;
+use crate::renderer::command_pools::CommandPools;
+use crate::renderer::buffer::RendererBuffer;
+use crate::renderer::image::RendererImage;
+use crate::renderer::pipeline::RendererPipeline;
+use crate::renderer::batches::Batch;
+use crate::renderer::mesh::InstanceData;
+use crate::camera::Camera;
+
+use gpu_allocator::MemoryLocation;
+
+use glam::{Mat4, Vec4};
+
+use std::{mem, slice};
+
+use anyhow::Result;
+
+/// Buffers start out with room for this many instances and double when they run out.
+const MIN_INSTANCES: usize = 1024;
+
+/// Buffers start out with room for this many batches and double when they run out.
+const MIN_BATCHES: usize = 64;
+
+/// Levels of a pyramid for a depth buffer up to 65536 texels wide.
+const MAX_LEVELS: u32 = 16;
+
+const FRUSTUM: u32 = 1;
+const OCCLUSION: u32 = 2;
+const REVERSE_Z: u32 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CullingSettings {
+    pub frustum: bool,
+    /// Against the previous frame's depth, see [`culling`](crate::renderer::culling) for why that's off by default.
+    pub occlusion: bool,
+}
+
+impl Default for CullingSettings {
+    fn default() -> Self {
+        CullingSettings {
+            frustum: true,
+            occlusion: false,
+        }
+    }
+}
+
+/// What a culling pass did, laid out as the shader writes it.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CullingStats {
+    pub tested: u32,
+    pub frustum_culled: u32,
+    pub occlusion_culled: u32,
+    pub drawn: u32,
+}
+
+/// What the culling pass tests against, laid out for std140.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CullingUniform {
+    pub view_projection: Mat4,
+    /// The camera the depth pyramid was built with.
+    pub pyramid_view_projection: Mat4,
+    /// Left, right, bottom, top and near, facing inwards. Infinite far planes are why there's no far one.
+    pub planes: [Vec4; 5],
+    pub pyramid_size: [f32; 2],
+    pub instance_count: u32,
+    /// Which tests run and whether depth is reversed.
+    pub flags: u32,
+}
+
+/// A batch as the culling pass reads it, which begins with the command it's drawn with.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct BatchData {
+    command: vk::DrawIndexedIndirectCommand,
+    /// Where the batch's instances start, in the queued ones and in the visible ones.
+    first: u32,
+    padding: [u32; 2],
+    sphere: Vec4,
+    box_min: Vec4,
+    box_max: Vec4,
+}
+
+/// The size of each level of the pyramid, and what it's reduced from.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct PyramidLevel {
+    source_size: [i32; 2],
+    size: [i32; 2],
+    reverse_z: u32,
+}
+
+/// The culling pass, the buffers it reads and writes per frame in flight, and the depth pyramid.
+#[derive(Default)]
+pub struct RendererCulling {
+    pub settings: CullingSettings,
+    /// Of the last frame to finish that was culled.
+    pub stats: CullingStats,
+    /// Starts at the largest power of two that fits into the depth buffer, so every level halves exactly.
+    pub pyramid: RendererImage,
+    /// One per level, to write into and to reduce the next level from.
+    pub level_views: Vec<vk::ImageView>,
+    /// Nearest, the pyramid's texels are never blended.
+    pub sampler: vk::Sampler,
+    pub set_layout: vk::DescriptorSetLayout,
+    pub pyramid_set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    /// One per frame in flight, like the buffers.
+    pub descriptor_sets: Vec<vk::DescriptorSet>,
+    /// One per level of the pyramid.
+    pub pyramid_descriptor_sets: Vec<vk::DescriptorSet>,
+    pub uniforms: Vec<RendererBuffer>,
+    /// `BatchData` per batch, the draw commands are read from it.
+    pub batches: Vec<RendererBuffer>,
+    /// The index of its batch, per queued instance.
+    pub instance_batches: Vec<RendererBuffer>,
+    /// The instances that passed, at the same offset as their batch's queued ones.
+    pub visible: Vec<RendererBuffer>,
+    pub stats_buffers: Vec<RendererBuffer>,
+    /// The pass runs in its own command buffer, submitted ahead of the frame's.
+    pub command_buffers: Vec<vk::CommandBuffer>,
+    pub pipeline: RendererPipeline,
+    pub pyramid_pipeline: RendererPipeline,
+    /// Reduces a multisampled depth buffer into the first level, null when the depth buffer isn't.
+    pub multisampled_pyramid_pipeline: RendererPipeline,
+    /// What the frame being recorded writes to its uniform buffer when it ends.
+    pub uniform: CullingUniform,
+    /// The camera of the frame being recorded.
+    camera: Option<Camera>,
+    /// The camera the pyramid was built with, if it has been.
+    pyramid_camera: Option<Mat4>,
+    /// Which frames in flight ran the pass, so their stats can be read once they finish.
+    culled: Vec<bool>,
+}
+
+impl RendererCulling {
+    /// `depth` is the depth buffer the pyramid is built from, it needs `SAMPLED` usage.
+    pub fn new(
+        device: &RendererDevice,
+        command_pools: &CommandPools,
+        count: usize,
+        depth: &RendererImage
+    ) -> Result<RendererCulling> {
+        let mut culling = RendererCulling {
+            settings: CullingSettings::default(),
+            stats: CullingStats::default(),
+            pyramid: RendererImage {
+                image: vk::Image::null(),
+                image_view: vk::ImageView::null(),
+                allocation: Default::default(),
+                format: vk::Format::R32_SFLOAT,
+                extent: vk::Extent2D::default(),
+                depth: 1,
+                layers: 1,
+                mip_levels: 1,
+                samples: vk::SampleCountFlags::TYPE_1,
+            },
+            level_views: Vec::new(),
+            sampler: vk::Sampler::null(),
+            set_layout: vk::DescriptorSetLayout::null(),
+            pyramid_set_layout: vk::DescriptorSetLayout::null(),
+            descriptor_pool: vk::DescriptorPool::null(),
+            descriptor_sets: Vec::with_capacity(count),
+            pyramid_descriptor_sets: Vec::new(),
+            uniforms: Vec::with_capacity(count),
+            batches: Vec::with_capacity(count),
+            instance_batches: Vec::with_capacity(count),
+            visible: Vec::with_capacity(count),
+            stats_buffers: Vec::with_capacity(count),
+            command_buffers: Vec::with_capacity(count),
+            pipeline: RendererPipeline {
+                pipeline: vk::Pipeline::null(),
+                pipeline_layout: vk::PipelineLayout::null(),
+            },
+            pyramid_pipeline: RendererPipeline {
+                pipeline: vk::Pipeline::null(),
+                pipeline_layout: vk::PipelineLayout::null(),
+            },
+            multisampled_pyramid_pipeline: RendererPipeline {
+                pipeline: vk::Pipeline::null(),
+                pipeline_layout: vk::PipelineLayout::null(),
+            },
+            uniform: CullingUniform::default(),
+            camera: None,
+            pyramid_camera: None,
+            culled: vec![false; count],
+        };
+
+        if let Err(err) = culling.create(device, command_pools, count, depth) {
+            unsafe {
+                culling.cleanup(device);
+            };
+
+            return Err(err);
+        }
+
+        Ok(culling)
+    }
+
+    fn create(
+        &mut self,
+        device: &RendererDevice,
+        command_pools: &CommandPools,
+        count: usize,
+        depth: &RendererImage
+    ) -> Result<()> {
+        // buffers:
+        for _ in 0..count {
+            self.uniforms.push(RendererBuffer::new(
+                device,
+                "culling",
+                mem::size_of::<CullingUniform>() as vk::DeviceSize,
+                vk::BufferUsageFlags::UNIFORM_BUFFER,
+                MemoryLocation::CpuToGpu,
+            )?);
+
+            self.batches.push(Self::create_batches(device, MIN_BATCHES)?);
+            self.instance_batches.push(Self::create_instance_batches(device, MIN_INSTANCES)?);
+            self.visible.push(Self::create_visible(device, MIN_INSTANCES)?);
+
+            self.stats_buffers.push(RendererBuffer::new(
+                device,
+                "culling stats",
+                mem::size_of::<CullingStats>() as vk::DeviceSize,
+                vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+                MemoryLocation::GpuToCpu,
+            )?);
+        }
+
+        // pyramid:
+        let extent = vk::Extent2D {
+            width: Self::previous_power_of_two(depth.extent.width),
+            height: Self::previous_power_of_two(depth.extent.height),
+        };
+
+        let levels = (u32::BITS - extent.width.max(extent.height).leading_zeros()).min(MAX_LEVELS);
+
+        self.pyramid = RendererImage::mipmapped(
+            device,
+            "depth pyramid",
+            extent,
+            vk::Format::R32_SFLOAT,
+            vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED,
+            levels,
+        )?;
+
+        for level in 0..levels {
+            let subresource_range = vk::ImageSubresourceRange::builder()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .base_mip_level(level)
+                .level_count(1)
+                .base_array_layer(0)
+                .layer_count(1);
+
+            let image_view_info = vk::ImageViewCreateInfo::builder()
+                .image(self.pyramid.image)
+                .view_type(vk::ImageViewType::TYPE_2D)
+                .format(self.pyramid.format)
+                .subresource_range(*subresource_range);
+
+            self.level_views.push(unsafe {
+                device.logical_device.create_image_view(&image_view_info, None)?
+            });
+        }
+
+        self.to_general(device, command_pools)?;
+
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::NEAREST)
+            .min_filter(vk::Filter::NEAREST)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .max_lod(vk::LOD_CLAMP_NONE);
+
+        self.sampler = unsafe {
+            device.logical_device.create_sampler(&sampler_info, None)?
+        };
+
+        // descriptors:
+        let binding = |binding: u32, descriptor_type: vk::DescriptorType| {
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(binding)
+                .descriptor_type(descriptor_type)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .build()
+        };
+
+        let bindings = [
+            binding(0, vk::DescriptorType::UNIFORM_BUFFER),
+            binding(1, vk::DescriptorType::STORAGE_BUFFER),
+            binding(2, vk::DescriptorType::STORAGE_BUFFER),
+            binding(3, vk::DescriptorType::STORAGE_BUFFER),
+            binding(4, vk::DescriptorType::STORAGE_BUFFER),
+            binding(5, vk::DescriptorType::STORAGE_BUFFER),
+            binding(6, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+        ];
+
+        let set_layout_info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .bindings(&bindings);
+
+        self.set_layout = unsafe {
+            device.logical_device.create_descriptor_set_layout(&set_layout_info, None)?
+        };
+
+        let pyramid_bindings = [
+            binding(0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+            binding(1, vk::DescriptorType::STORAGE_IMAGE),
+        ];
+
+        let set_layout_info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .bindings(&pyramid_bindings);
+
+        self.pyramid_set_layout = unsafe {
+            device.logical_device.create_descriptor_set_layout(&set_layout_info, None)?
+        };
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: count as u32,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: count as u32 * 5,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: count as u32 + levels,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_IMAGE,
+                descriptor_count: levels,
+            },
+        ];
+
+        let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(count as u32 + levels)
+            .pool_sizes(&pool_sizes);
+
+        self.descriptor_pool = unsafe {
+            device.logical_device.create_descriptor_pool(&pool_info, None)?
+        };
+
+        let set_layouts = vec![self.set_layout; count];
+
+        let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(self.descriptor_pool)
+            .set_layouts(&set_layouts);
+
+        self.descriptor_sets = unsafe {
+            device.logical_device.allocate_descriptor_sets(&allocate_info)?
+        };
+
+        let set_layouts = vec![self.pyramid_set_layout; levels as usize];
+
+        let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(self.descriptor_pool)
+            .set_layouts(&set_layouts);
+
+        self.pyramid_descriptor_sets = unsafe {
+            device.logical_device.allocate_descriptor_sets(&allocate_info)?
+        };
+
+        // each level is reduced from the one before, the first one from the depth buffer:
+        for (level, &descriptor_set) in self.pyramid_descriptor_sets.iter().enumerate() {
+            let source_infos = [
+                match level {
+                    0 => vk::DescriptorImageInfo {
+                        sampler: self.sampler,
+                        image_view: depth.image_view,
+                        image_layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+                    },
+                    _ => vk::DescriptorImageInfo {
+                        sampler: self.sampler,
+                        image_view: self.level_views[level - 1],
+                        image_layout: vk::ImageLayout::GENERAL,
+                    }
+                }
+            ];
+
+            let level_infos = [
+                vk::DescriptorImageInfo {
+                    sampler: vk::Sampler::null(),
+                    image_view: self.level_views[level],
+                    image_layout: vk::ImageLayout::GENERAL,
+                }
+            ];
+
+            let writes = [
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_set)
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(&source_infos)
+                    .build(),
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_set)
+                    .dst_binding(1)
+                    .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                    .image_info(&level_infos)
+                    .build(),
+            ];
+
+            unsafe {
+                device.logical_device.update_descriptor_sets(&writes, &[]);
+            };
+        }
+
+        // pipelines:
+        self.pipeline = RendererPipeline::compute(
+            device,
+            vk_shader_macros::include_glsl!("./shaders/cull.comp"),
+            &[self.set_layout],
+            &[],
+        )?;
+
+        let push_constant_ranges = [
+            vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::COMPUTE,
+                offset: 0,
+                size: mem::size_of::<PyramidLevel>() as u32,
+            }
+        ];
+
+        self.pyramid_pipeline = RendererPipeline::compute(
+            device,
+            vk_shader_macros::include_glsl!("./shaders/depth_pyramid.comp"),
+            &[self.pyramid_set_layout],
+            &push_constant_ranges,
+        )?;
+
+        if depth.samples != vk::SampleCountFlags::TYPE_1 {
+            self.multisampled_pyramid_pipeline = RendererPipeline::compute(
+                device,
+                vk_shader_macros::include_glsl!("./shaders/depth_pyramid.comp", define: MULTISAMPLED),
+                &[self.pyramid_set_layout],
+                &push_constant_ranges,
+            )?;
+        }
+
+        self.command_buffers = CommandPools::create_command_buffers(device, command_pools.graphics, count as u32)?;
+
+        Ok(())
+    }
+
+    fn previous_power_of_two(size: u32) -> u32 {
+        if size.is_power_of_two() {
+            size
+        } else {
+            (size.next_power_of_two() / 2).max(1)
+        }
+    }
+
+    fn create_batches(device: &RendererDevice, batches: usize) -> Result<RendererBuffer> {
+        RendererBuffer::new(
+            device,
+            "culled batches",
+            (batches * mem::size_of::<BatchData>()) as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::INDIRECT_BUFFER,
+            MemoryLocation::CpuToGpu,
+        )
+    }
+
+    fn create_instance_batches(device: &RendererDevice, instances: usize) -> Result<RendererBuffer> {
+        RendererBuffer::new(
+            device,
+            "instance batches",
+            (instances * mem::size_of::<u32>()) as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            MemoryLocation::CpuToGpu,
+        )
+    }
+
+    fn create_visible(device: &RendererDevice, instances: usize) -> Result<RendererBuffer> {
+        RendererBuffer::new(
+            device,
+            "visible instances",
+            (instances * mem::size_of::<InstanceData>()) as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::VERTEX_BUFFER,
+            MemoryLocation::GpuOnly,
+        )
+    }
+
+    /// The pyramid stays in the general layout, it's written and sampled by turns.
+    fn to_general(&self, device: &RendererDevice, command_pools: &CommandPools) -> Result<()> {
+        command_pools.one_time_submit(device, |command_buffer| {
+            let to_general = vk::ImageMemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::empty())
+                .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+                .old_layout(vk::ImageLayout::UNDEFINED)
+                .new_layout(vk::ImageLayout::GENERAL)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(self.pyramid.image)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    base_mip_level: 0,
+                    level_count: self.pyramid.mip_levels,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                });
+
+            unsafe {
+                device.logical_device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[to_general.build()],
+                );
+            };
+        })
+    }
+
+    /// Replaces `buffer` with one made by `create` when it holds less than `size` bytes.
+    fn fit(
+        device: &RendererDevice,
+        buffer: &mut RendererBuffer,
+        size: usize,
+        element_size: usize,
+        create: fn(&RendererDevice, usize) -> Result<RendererBuffer>
+    ) -> Result<()> {
+        if buffer.size as usize >= size {
+            return Ok(());
+        }
+
+        let replacement = create(device, (size / element_size).next_power_of_two())?;
+
+        unsafe {
+            buffer.cleanup(device);
+        };
+
+        *buffer = replacement;
+
+        Ok(())
+    }
+
+    /// Starts a frame without a camera.
+    pub fn begin(&mut self) {
+        self.camera = None;
+    }
+
+    /// Draws are culled against what this camera sees.
+    pub fn set_camera(&mut self, camera: &Camera) {
+        self.camera = Some(camera.clone());
+    }
+
+    /// Reads the stats of the frame in `slot` when it ran the pass, after its fence was waited for.
+    pub fn frame_finished(&mut self, slot: usize) -> Result<()> {
+        if self.culled[slot] {
+            self.stats = self.read_stats(slot)?;
+
+            self.culled[slot] = false;
+        }
+
+        Ok(())
+    }
+
+    /// What the last pass of the frame in `slot` did, which has to have finished.
+    pub fn read_stats(&self, slot: usize) -> Result<CullingStats> {
+        let bytes = self.stats_buffers[slot].read()?;
+
+        let mut counts = bytes.chunks_exact(mem::size_of::<u32>())
+            .map(|chunk| u32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]));
+
+        Ok(CullingStats {
+            tested: counts.next().unwrap_or(0),
+            frustum_culled: counts.next().unwrap_or(0),
+            occlusion_culled: counts.next().unwrap_or(0),
+            drawn: counts.next().unwrap_or(0),
+        })
+    }
+
+    /// Left, right, bottom, top and near, from the rows of `view_projection`.
+    fn planes(view_projection: Mat4, reverse_z: bool) -> [Vec4; 5] {
+        let row = |i: usize| view_projection.row(i);
+
+        let near = if reverse_z { row(3) - row(2) } else { row(2) };
+
+        [row(3) + row(0), row(3) - row(0), row(3) + row(1), row(3) - row(1), near]
+            .map(|plane| plane / plane.truncate().length().max(f32::EPSILON))
+    }
+
+    /// Writes what the pass reads for `batches`, whose instances are in `instances`, and the buffers it needs.
+    ///
+    /// Returns whether the frame is culled, it isn't when culling is off, no camera was set or there's nothing to
+    /// draw. The pass of a culled frame is recorded with `record`, and its batches are drawn with `draw`.
+    pub fn prepare(
+        &mut self,
+        device: &RendererDevice,
+        slot: usize,
+        batches: &[Batch],
+        instances: &RendererBuffer
+    ) -> Result<bool> {
+        let camera = match &self.camera {
+            Some(camera) if !batches.is_empty() && (self.settings.frustum || self.settings.occlusion) => camera,
+            _ => return Ok(false)
+        };
+
+        let view_projection = camera.uniform().view_projection;
+
+        let occlusion = match self.pyramid_camera {
+            Some(pyramid_view_projection) if self.settings.occlusion => Some(pyramid_view_projection),
+            _ => None,
+        };
+
+        let flags = if self.settings.frustum { FRUSTUM } else { 0 }
+            | if occlusion.is_some() { OCCLUSION } else { 0 }
+            | if camera.reverse_z { REVERSE_Z } else { 0 };
+
+        // batches, with their instance counts zeroed for the pass to count up:
+        let batch_data: Vec<BatchData> = batches.iter()
+            .map(|batch| BatchData {
+                command: vk::DrawIndexedIndirectCommand {
+                    index_count: batch.index_count,
+                    instance_count: 0,
+                    first_index: 0,
+                    vertex_offset: 0,
+                    first_instance: 0,
+                },
+                first: batch.instances.start,
+                padding: [0; 2],
+                sphere: batch.bounds.center.extend(batch.bounds.radius),
+                box_min: batch.bounds.min.extend(1.0),
+                box_max: batch.bounds.max.extend(1.0),
+            })
+            .collect();
+
+        let instance_batches: Vec<u32> = batches.iter()
+            .enumerate()
+            .flat_map(|(index, batch)| batch.instances.clone().map(move |_| index as u32))
+            .collect();
+
+        let instance_count = instance_batches.len();
+
+        Self::fit(
+            device,
+            &mut self.batches[slot],
+            mem::size_of_val(batch_data.as_slice()),
+            mem::size_of::<BatchData>(),
+            Self::create_batches,
+        )?;
+
+        Self::fit(
+            device,
+            &mut self.instance_batches[slot],
+            mem::size_of_val(instance_batches.as_slice()),
+            mem::size_of::<u32>(),
+            Self::create_instance_batches,
+        )?;
+
+        Self::fit(
+            device,
+            &mut self.visible[slot],
+            instance_count * mem::size_of::<InstanceData>(),
+            mem::size_of::<InstanceData>(),
+            Self::create_visible,
+        )?;
+
+        self.batches[slot].write(&batch_data)?;
+        self.instance_batches[slot].write(&instance_batches)?;
+
+        self.uniform = CullingUniform {
+            view_projection,
+            pyramid_view_projection: occlusion.unwrap_or(Mat4::IDENTITY),
+            planes: Self::planes(view_projection, camera.reverse_z),
+            pyramid_size: [self.pyramid.extent.width as f32, self.pyramid.extent.height as f32],
+            instance_count: instance_count as u32,
+            flags,
+        };
+
+        self.uniforms[slot].write(slice::from_ref(&self.uniform))?;
+
+        self.write_descriptor_set(device, slot, instances);
+
+        Ok(true)
+    }
+
+    /// Records the pass of the frame in `slot` after `prepare`, returns the command buffer to submit ahead of the
+    /// frame's.
+    pub fn record<B: Backend>(&mut self, backend: &B, slot: usize) -> Result<vk::CommandBuffer> {
+        let instance_count = self.uniform.instance_count;
+
+        let command_buffer = self.command_buffers[slot];
+
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+        let cleared = vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+
+        // the commands and instances are read by the frame's draws, the stats by the host once it has finished:
+        let culled = vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(
+                vk::AccessFlags::INDIRECT_COMMAND_READ
+                    | vk::AccessFlags::VERTEX_ATTRIBUTE_READ
+                    | vk::AccessFlags::HOST_READ
+            );
+
+        backend.begin_command_buffer(command_buffer, &begin_info)?;
+
+        backend.cmd_fill_buffer(command_buffer, self.stats_buffers[slot].buffer, 0, vk::WHOLE_SIZE, 0);
+
+        backend.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            &[cleared.build()],
+            &[],
+            &[],
+        );
+
+        backend.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, self.pipeline.pipeline);
+
+        backend.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            self.pipeline.pipeline_layout,
+            0,
+            &[self.descriptor_sets[slot]],
+            &[],
+        );
+
+        // 64 invocations per group, as in the shader:
+        backend.cmd_dispatch(command_buffer, instance_count.div_ceil(64), 1, 1);
+
+        backend.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::DRAW_INDIRECT | vk::PipelineStageFlags::VERTEX_INPUT | vk::PipelineStageFlags::HOST,
+            &[culled.build()],
+            &[],
+            &[],
+        );
+
+        backend.end_command_buffer(command_buffer)?;
+
+        self.culled[slot] = true;
+
+        Ok(command_buffer)
+    }
+
+    fn write_descriptor_set(&self, device: &RendererDevice, slot: usize, instances: &RendererBuffer) {
+        let buffers = [
+            &self.uniforms[slot],
+            instances,
+            &self.instance_batches[slot],
+            &self.batches[slot],
+            &self.visible[slot],
+            &self.stats_buffers[slot],
+        ];
+
+        let buffer_infos: Vec<[vk::DescriptorBufferInfo; 1]> = buffers.iter()
+            .map(|buffer| [
+                vk::DescriptorBufferInfo {
+                    buffer: buffer.buffer,
+                    offset: 0,
+                    range: buffer.size,
+                }
+            ])
+            .collect();
+
+        let image_infos = [
+            vk::DescriptorImageInfo {
+                sampler: self.sampler,
+                image_view: self.pyramid.image_view,
+                image_layout: vk::ImageLayout::GENERAL,
+            }
+        ];
+
+        let mut writes: Vec<vk::WriteDescriptorSet> = buffer_infos.iter()
+            .enumerate()
+            .map(|(binding, buffer_info)| {
+                let descriptor_type = if binding == 0 {
+                    vk::DescriptorType::UNIFORM_BUFFER
+                } else {
+                    vk::DescriptorType::STORAGE_BUFFER
+                };
+
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(self.descriptor_sets[slot])
+                    .dst_binding(binding as u32)
+                    .descriptor_type(descriptor_type)
+                    .buffer_info(buffer_info)
+                    .build()
+            })
+            .collect();
+
+        writes.push(
+            vk::WriteDescriptorSet::builder()
+                .dst_set(self.descriptor_sets[slot])
+                .dst_binding(6)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(&image_infos)
+                .build()
+        );
+
+        unsafe {
+            device.logical_device.update_descriptor_sets(&writes, &[]);
+        };
+    }
+
+    /// Draws the visible instances of the batch at `index` of those the frame in `slot` was culled with.
+    pub fn draw<B: Backend>(&self, backend: &B, command_buffer: vk::CommandBuffer, slot: usize, index: usize, batch: &Batch) {
+        let first_instance = batch.instances.start as vk::DeviceSize * mem::size_of::<InstanceData>() as vk::DeviceSize;
+
+        let stride = mem::size_of::<BatchData>();
+
+        backend.cmd_bind_vertex_buffers(
+            command_buffer,
+            0,
+            &[batch.vertex_buffer, self.visible[slot].buffer],
+            &[0, first_instance],
+        );
+        backend.cmd_bind_index_buffer(command_buffer, batch.index_buffer, 0, vk::IndexType::UINT32);
+
+        backend.cmd_draw_indexed_indirect(
+            command_buffer,
+            self.batches[slot].buffer,
+            (index * stride) as vk::DeviceSize,
+            1,
+            stride as u32,
+        );
+    }
+
+    /// Builds the pyramid from `depth` after the frame's render pass, when occlusion culling is on.
+    ///
+    /// `depth` is left in the `DEPTH_STENCIL_READ_ONLY_OPTIMAL` layout.
+    pub fn record_pyramid<B: Backend>(&mut self, backend: &B, command_buffer: vk::CommandBuffer, depth: &RendererImage) {
+        let camera = match &self.camera {
+            Some(camera) if self.settings.occlusion => camera,
+            _ => {
+                self.pyramid_camera = None;
+
+                return;
+            }
+        };
+
+        // this frame's culling pass has to be done with the pyramid too, it's recorded in its own command buffer:
+        let to_read = vk::ImageMemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ)
+            .old_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+            .new_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(depth.image)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: RendererImage::aspect_mask(depth.format),
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            });
+
+        let reduced = vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ)
+            .build();
+
+        backend.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::LATE_FRAGMENT_TESTS | vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            &[],
+            &[],
+            &[to_read.build()],
+        );
+
+        // the pipelines' layouts are the same, the sets stay bound when they're switched:
+        let multisampled = self.multisampled_pyramid_pipeline.pipeline != vk::Pipeline::null();
+
+        let first_pipeline = if multisampled {
+            &self.multisampled_pyramid_pipeline
+        } else {
+            &self.pyramid_pipeline
+        };
+
+        backend.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            first_pipeline.pipeline,
+        );
+
+        let mut source_size = [depth.extent.width as i32, depth.extent.height as i32];
+
+        for (level, &descriptor_set) in self.pyramid_descriptor_sets.iter().enumerate() {
+            let size = [
+                (self.pyramid.extent.width >> level).max(1),
+                (self.pyramid.extent.height >> level).max(1),
+            ];
+
+            let constants = PyramidLevel {
+                source_size,
+                size: [size[0] as i32, size[1] as i32],
+                reverse_z: camera.reverse_z as u32,
+            };
+
+            let constant_bytes = unsafe {
+                slice::from_raw_parts(&constants as *const PyramidLevel as *const u8, mem::size_of::<PyramidLevel>())
+            };
+
+            backend.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pyramid_pipeline.pipeline_layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+
+            backend.cmd_push_constants(
+                command_buffer,
+                self.pyramid_pipeline.pipeline_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                constant_bytes,
+            );
+
+            // 8x8 invocations per group, as in the shader:
+            backend.cmd_dispatch(command_buffer, size[0].div_ceil(8), size[1].div_ceil(8), 1);
+
+            // the next level reads this one, and the next frame's culling pass all of them:
+            backend.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                &[reduced],
+                &[],
+                &[],
+            );
+
+            source_size = constants.size;
+
+            // the levels after the first are single sampled:
+            if level == 0 && multisampled {
+                backend.cmd_bind_pipeline(
+                    command_buffer,
+                    vk::PipelineBindPoint::COMPUTE,
+                    self.pyramid_pipeline.pipeline,
+                );
+            }
+        }
+
+        self.pyramid_camera = Some(camera.uniform().view_projection);
+    }
+
+    /// The command buffers go with the pool they were allocated from.
+    pub unsafe fn cleanup(&mut self, device: &RendererDevice) {
+        self.pipeline.cleanup(&device.logical_device);
+        self.pyramid_pipeline.cleanup(&device.logical_device);
+        self.multisampled_pyramid_pipeline.cleanup(&device.logical_device);
+
+        device.logical_device.destroy_descriptor_pool(self.descriptor_pool, None);
+        device.logical_device.destroy_descriptor_set_layout(self.set_layout, None);
+        device.logical_device.destroy_descriptor_set_layout(self.pyramid_set_layout, None);
+        device.logical_device.destroy_sampler(self.sampler, None);
+
+        for level_view in self.level_views.drain(..) {
+            device.logical_device.destroy_image_view(level_view, None);
+        }
+
+        self.pyramid.cleanup(device);
+
+        let buffers = self.uniforms.iter_mut()
+            .chain(&mut self.batches)
+            .chain(&mut self.instance_batches)
+            .chain(&mut self.visible)
+            .chain(&mut self.stats_buffers);
+
+        for buffer in buffers {
+            buffer.cleanup(device);
+        }
+
+        self.uniforms.clear();
+        self.batches.clear();
+        self.instance_batches.clear();
+        self.visible.clear();
+        self.stats_buffers.clear();
+        self.descriptor_sets.clear();
+        self.pyramid_descriptor_sets.clear();
+        self.command_buffers.clear();
+    }
+}
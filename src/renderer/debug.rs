@@ -51,6 +51,20 @@ impl RendererDebug {
         })
     }
 
+    /// NULs in `name` are left out, a label is only for tools to show.
+    pub unsafe fn cmd_begin_label(&self, command_buffer: vk::CommandBuffer, name: &str) {
+        let label_name = ffi::CString::new(name.replace('\0', "")).unwrap_or_default();
+
+        let label = vk::DebugUtilsLabelEXT::builder()
+            .label_name(&label_name);
+
+        self.debug_utils.cmd_begin_debug_utils_label(command_buffer, &label);
+    }
+
+    pub unsafe fn cmd_end_label(&self, command_buffer: vk::CommandBuffer) {
+        self.debug_utils.cmd_end_debug_utils_label(command_buffer);
+    }
+
     pub unsafe fn cleanup(&mut self) {
         self.debug_utils.destroy_debug_utils_messenger(self.debug_messenger, None);
     }
@@ -1,4 +1,11 @@
 use ash::vk;
+use ash::extensions::khr;
+
+use gpu_allocator::vulkan::{Allocation, AllocationCreateDesc, Allocator, AllocatorCreateDesc};
+
+use std::ffi;
+use std::mem::ManuallyDrop;
+use std::sync::Mutex;
 
 use anyhow::Result;
 
@@ -8,28 +15,67 @@ pub struct QueueFamily {
     pub queues: Vec<vk::Queue>,
 }
 
+/// What's allocated through [`RendererDevice::allocate`] and not freed yet.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub allocations: usize,
+    /// In bytes, of memory only the GPU sees.
+    pub device_local: u64,
+    /// In bytes, of memory the CPU maps to write or read.
+    pub host_visible: u64,
+}
+
 pub struct RendererDevice {
     pub physical_device: vk::PhysicalDevice,
+    /// Limits and the like, queried once.
+    pub properties: vk::PhysicalDeviceProperties,
+    /// The memory heaps and types, queried once.
+    pub memory_properties: vk::PhysicalDeviceMemoryProperties,
     pub logical_device: ash::Device,
     pub queue_families: Vec<QueueFamily>,
+    pub enabled_extensions: Vec<ffi::CString>,
+    /// The optional features that were enabled, the indirect drawing ones where the device has them.
+    pub features: vk::PhysicalDeviceFeatures,
+    /// Loaded when the device has `VK_KHR_draw_indirect_count`, for draws that read their count from a buffer.
+    pub draw_indirect_count: Option<khr::DrawIndirectCount>,
+    allocator: Mutex<ManuallyDrop<Allocator>>,
+    memory_usage: Mutex<MemoryUsage>,
 }
 
 impl RendererDevice {
-    fn used_extensions() -> Vec<*const i8> {
+    fn used_extensions() -> Vec<&'static ffi::CStr> {
         vec![
-            ash::extensions::khr::Swapchain::name().as_ptr()
+            ash::extensions::khr::Swapchain::name()
         ]
     }
 
+    // only one breadcrumb extension is enabled, checkpoints are preferred over buffer markers:
+    fn diagnostic_extensions() -> Vec<&'static ffi::CStr> {
+        vec![
+            vk::NvDeviceDiagnosticCheckpointsFn::name(),
+            vk::AmdBufferMarkerFn::name(),
+        ]
+    }
+
+    /// Creates the device on the first GPU with `name` in its name, or on one the renderer picks without it.
     pub fn new(
         instance: &ash::Instance,
         layer_pts: &Vec<*const i8>,
+        name: Option<&str>,
     ) -> Result<Option<RendererDevice>> {
-        let physical_device = match Self::pick_physical_device(instance)? {
+        let physical_device = match Self::pick_physical_device(instance, name)? {
             None => return Ok(None),
             Some(pd) => pd
         };
 
+        let properties = unsafe {
+            instance.get_physical_device_properties(physical_device)
+        };
+
+        let memory_properties = unsafe {
+            instance.get_physical_device_memory_properties(physical_device)
+        };
+
         let mut queue_families = Self::pick_queue_families(instance, physical_device);
 
         let priorities = [1.0f32];
@@ -45,11 +91,44 @@ impl RendererDevice {
             )
         }
 
-        let used_extensions = Self::used_extensions();
+        let available_extensions = Self::available_extensions(instance, physical_device)?;
+
+        let mut enabled_extensions: Vec<ffi::CString> = Self::used_extensions()
+            .into_iter()
+            .map(|name| name.to_owned())
+            .collect();
+
+        if let Some(name) = Self::diagnostic_extensions()
+            .into_iter()
+            .find(|name| available_extensions.iter().any(|available| available.as_c_str() == *name)) {
+            enabled_extensions.push(name.to_owned());
+        }
+
+        let has_draw_indirect_count = available_extensions.iter()
+            .any(|available| available.as_c_str() == khr::DrawIndirectCount::name());
+
+        if has_draw_indirect_count {
+            enabled_extensions.push(khr::DrawIndirectCount::name().to_owned());
+        }
+
+        let supported_features = unsafe {
+            instance.get_physical_device_features(physical_device)
+        };
+
+        let features = vk::PhysicalDeviceFeatures {
+            multi_draw_indirect: supported_features.multi_draw_indirect,
+            draw_indirect_first_instance: supported_features.draw_indirect_first_instance,
+            ..Default::default()
+        };
+
+        let enabled_extension_pts: Vec<*const i8> = enabled_extensions.iter()
+            .map(|name| name.as_ptr())
+            .collect();
 
         let device_create_info = vk::DeviceCreateInfo::builder()
             .queue_create_infos(&queue_infos)
-            .enabled_extension_names(&used_extensions)
+            .enabled_extension_names(&enabled_extension_pts)
+            .enabled_features(&features)
             .enabled_layer_names(layer_pts);
 
         let device = unsafe {
@@ -62,13 +141,104 @@ impl RendererDevice {
             };
         }
 
+        let draw_indirect_count = if has_draw_indirect_count {
+            Some(khr::DrawIndirectCount::new(instance, &device))
+        } else {
+            None
+        };
+
+        let allocator = Allocator::new(&AllocatorCreateDesc {
+            instance: instance.clone(),
+            device: device.clone(),
+            physical_device,
+            debug_settings: Default::default(),
+            buffer_device_address: false,
+        })?;
+
         Ok(Some(RendererDevice {
             physical_device,
+            properties,
+            memory_properties,
             logical_device: device,
             queue_families,
+            enabled_extensions,
+            features,
+            draw_indirect_count,
+            allocator: Mutex::new(ManuallyDrop::new(allocator)),
+            memory_usage: Mutex::new(MemoryUsage::default()),
         }))
     }
 
+    pub fn allocate(&self, desc: &AllocationCreateDesc) -> Result<Allocation> {
+        let allocation = self.allocator.lock().unwrap().allocate(desc)?;
+
+        self.count_allocation(&allocation, true);
+
+        Ok(allocation)
+    }
+
+    pub fn free(&self, allocation: Allocation) -> Result<()> {
+        // freeing an allocation that was taken out of its owner is a no-op:
+        if !allocation.is_null() {
+            self.count_allocation(&allocation, false);
+        }
+
+        self.allocator.lock().unwrap().free(allocation)?;
+
+        Ok(())
+    }
+
+    // host visible memory is always mapped by the allocator:
+    fn count_allocation(&self, allocation: &Allocation, allocated: bool) {
+        let mut usage = self.memory_usage.lock().unwrap();
+
+        let bytes = match allocation.mapped_ptr() {
+            None => &mut usage.device_local,
+            Some(_) => &mut usage.host_visible,
+        };
+
+        if allocated {
+            *bytes += allocation.size();
+            usage.allocations += 1;
+        } else {
+            *bytes -= allocation.size();
+            usage.allocations -= 1;
+        }
+    }
+
+    /// How much is allocated right now, for seeing what the renderer holds on to.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        *self.memory_usage.lock().unwrap()
+    }
+
+    /// The size of every memory heap, and whether it's the GPU's own memory.
+    pub fn memory_heaps(&self) -> Vec<(u64, bool)> {
+        let heaps = &self.memory_properties.memory_heaps[..self.memory_properties.memory_heap_count as usize];
+
+        heaps.iter()
+            .map(|heap| (heap.size, heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL)))
+            .collect()
+    }
+
+    pub fn extension_enabled(&self, name: &ffi::CStr) -> bool {
+        self.enabled_extensions.iter().any(|enabled| enabled.as_c_str() == name)
+    }
+
+    fn available_extensions(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice
+    ) -> Result<Vec<ffi::CString>> {
+        let props = unsafe {
+            instance.enumerate_device_extension_properties(physical_device)?
+        };
+
+        let names = props.iter()
+            .map(|prop| unsafe { ffi::CStr::from_ptr(prop.extension_name.as_ptr()) }.to_owned())
+            .collect();
+
+        Ok(names)
+    }
+
     pub fn queue_family(&self, flags: vk::QueueFlags) -> Option<&QueueFamily> {
         for queue_family in &self.queue_families {
             if queue_family.flags == flags {
@@ -80,13 +250,45 @@ impl RendererDevice {
     }
 
     fn pick_physical_device(
-        instance: &ash::Instance
+        instance: &ash::Instance,
+        name: Option<&str>
     ) -> Result<Option<vk::PhysicalDevice>>  {
         let physical_devices = unsafe {
             instance.enumerate_physical_devices()?
         };
 
-        let mut chosen = None;
+        // picked by name, e.g. `llvmpipe` for lavapipe:
+        if let Some(wanted) = name {
+            let wanted = wanted.to_lowercase();
+
+            let mut names = vec![];
+
+            for physical_device in physical_devices {
+                let props: vk::PhysicalDeviceProperties = unsafe {
+                    instance.get_physical_device_properties(physical_device)
+                };
+
+                let name = unsafe {
+                    ffi::CStr::from_ptr(props.device_name.as_ptr())
+                };
+
+                let name = name.to_string_lossy();
+
+                if name.to_lowercase().contains(&wanted) {
+                    return Ok(Some(physical_device));
+                }
+
+                names.push(name.into_owned());
+            }
+
+            anyhow::bail!(
+                "No device's name contains {}, which the config asks for, there's only {:?}",
+                wanted, names,
+            );
+        }
+
+        // anything goes when there's no discrete GPU, e.g. a software rasterizer on CI:
+        let mut chosen = physical_devices.first().copied();
 
         for physical_device in physical_devices {
             let props: vk::PhysicalDeviceProperties = unsafe {
@@ -133,7 +335,10 @@ impl RendererDevice {
         queue_families
     }
 
-    pub unsafe fn cleanup(&self) {
+    pub unsafe fn cleanup(&mut self) {
+        // the allocator frees its memory blocks when dropped, so it has to go before the device:
+        ManuallyDrop::drop(self.allocator.get_mut().unwrap());
+
         self.logical_device.destroy_device(None);
     }
 }
\ No newline at end of file
@@ -0,0 +1,362 @@
+use ash::vk;
+
+use crate::renderer::debug::RendererDebug;
+use crate::renderer::device::RendererDevice;
+
+use std::{ffi, fmt, mem, ptr};
+
+use anyhow::Result;
+
+pub enum Breadcrumb {
+    Begin(String),
+    End,
+}
+
+enum Breadcrumbs {
+    None,
+    Checkpoints(vk::NvDeviceDiagnosticCheckpointsFn),
+    BufferMarkers {
+        fp: vk::AmdBufferMarkerFn,
+        buffer: vk::Buffer,
+        memory: vk::DeviceMemory,
+        markers: *mut u32,
+    },
+}
+
+pub struct SubmittedFrame {
+    pub frame: u64,
+    pub slot: usize,
+}
+
+/// Keeps track of what was recorded and submitted, so that a lost device can be explained afterwards.
+///
+/// Every debug label is also written as a breadcrumb when `VK_NV_device_diagnostic_checkpoints`
+/// or `VK_AMD_buffer_marker` is enabled, which tells how far the GPU got before it died.
+pub struct RendererDiagnostics {
+    breadcrumbs: Breadcrumbs,
+    recorded: Vec<Vec<Breadcrumb>>,
+    pub submitted_frames: u64,
+    pub last_submitted: Option<SubmittedFrame>,
+}
+
+impl RendererDiagnostics {
+    pub fn new(
+        instance: &ash::Instance,
+        device: &RendererDevice,
+        command_buffer_count: usize
+    ) -> Result<RendererDiagnostics> {
+        let load = |name: &ffi::CStr| unsafe {
+            mem::transmute(instance.get_device_proc_addr(device.logical_device.handle(), name.as_ptr()))
+        };
+
+        let breadcrumbs = if device.extension_enabled(vk::NvDeviceDiagnosticCheckpointsFn::name()) {
+            Breadcrumbs::Checkpoints(vk::NvDeviceDiagnosticCheckpointsFn::load(load))
+        } else if device.extension_enabled(vk::AmdBufferMarkerFn::name()) {
+            // two markers per command buffer, the last one started and the last one finished:
+            let (buffer, memory, markers) = Self::create_marker_buffer(
+                instance,
+                device,
+                (command_buffer_count * 2 * mem::size_of::<u32>()) as vk::DeviceSize
+            )?;
+
+            Breadcrumbs::BufferMarkers {
+                fp: vk::AmdBufferMarkerFn::load(load),
+                buffer,
+                memory,
+                markers,
+            }
+        } else {
+            Breadcrumbs::None
+        };
+
+        let mut recorded = Vec::with_capacity(command_buffer_count);
+        recorded.resize_with(command_buffer_count, Vec::new);
+
+        Ok(RendererDiagnostics {
+            breadcrumbs,
+            recorded,
+            submitted_frames: 0,
+            last_submitted: None,
+        })
+    }
+
+    fn create_marker_buffer(
+        instance: &ash::Instance,
+        device: &RendererDevice,
+        size: vk::DeviceSize
+    ) -> Result<(vk::Buffer, vk::DeviceMemory, *mut u32)> {
+        let buffer_info = vk::BufferCreateInfo::builder()
+            .size(size)
+            .usage(vk::BufferUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        let buffer = unsafe {
+            device.logical_device.create_buffer(&buffer_info, None)?
+        };
+
+        let requirements = unsafe {
+            device.logical_device.get_buffer_memory_requirements(buffer)
+        };
+
+        let memory_props = unsafe {
+            instance.get_physical_device_memory_properties(device.physical_device)
+        };
+
+        let wanted = vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+
+        let memory_type_index = match (0..memory_props.memory_type_count).find(|&i| {
+            requirements.memory_type_bits & (1 << i) != 0
+                && memory_props.memory_types[i as usize].property_flags.contains(wanted)
+        }) {
+            None => anyhow::bail!("No host visible memory for breadcrumb markers"),
+            Some(i) => i
+        };
+
+        let allocate_info = vk::MemoryAllocateInfo::builder()
+            .allocation_size(requirements.size)
+            .memory_type_index(memory_type_index);
+
+        let (memory, markers) = unsafe {
+            let memory = device.logical_device.allocate_memory(&allocate_info, None)?;
+
+            device.logical_device.bind_buffer_memory(buffer, memory, 0)?;
+
+            let markers = device.logical_device.map_memory(memory, 0, size, vk::MemoryMapFlags::empty())? as *mut u32;
+
+            ptr::write_bytes(markers, 0, size as usize / mem::size_of::<u32>());
+
+            (memory, markers)
+        };
+
+        Ok((buffer, memory, markers))
+    }
+
+    pub fn begin_label(
+        &mut self,
+        debug: &RendererDebug,
+        slot: usize,
+        command_buffer: vk::CommandBuffer,
+        name: &str
+    ) {
+        unsafe {
+            debug.cmd_begin_label(command_buffer, name);
+        }
+
+        self.recorded[slot].push(Breadcrumb::Begin(name.to_owned()));
+
+        self.write_breadcrumb(slot, command_buffer);
+    }
+
+    pub fn end_label(
+        &mut self,
+        debug: &RendererDebug,
+        slot: usize,
+        command_buffer: vk::CommandBuffer
+    ) {
+        unsafe {
+            debug.cmd_end_label(command_buffer);
+        }
+
+        self.recorded[slot].push(Breadcrumb::End);
+
+        self.write_breadcrumb(slot, command_buffer);
+    }
+
+    /// Forgets what was recorded into a command buffer, used before it is filled again.
+    pub fn reset(&mut self, slot: usize) {
+        self.recorded[slot].clear();
+    }
+
+    fn write_breadcrumb(&self, slot: usize, command_buffer: vk::CommandBuffer) {
+        // markers start at 1, so that 0 means nothing was reached:
+        let marker = self.recorded[slot].len() as u32;
+
+        match &self.breadcrumbs {
+            Breadcrumbs::None => {},
+            Breadcrumbs::Checkpoints(fp) => unsafe {
+                let checkpoint = ((slot << 16) | marker as usize) as *const ffi::c_void;
+
+                (fp.cmd_set_checkpoint_nv)(command_buffer, checkpoint);
+            },
+            Breadcrumbs::BufferMarkers { fp, buffer, .. } => unsafe {
+                let offset = (slot * 2 * mem::size_of::<u32>()) as vk::DeviceSize;
+
+                (fp.cmd_write_buffer_marker_amd)(
+                    command_buffer,
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                    *buffer,
+                    offset,
+                    marker,
+                );
+                (fp.cmd_write_buffer_marker_amd)(
+                    command_buffer,
+                    vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                    *buffer,
+                    offset + mem::size_of::<u32>() as vk::DeviceSize,
+                    marker,
+                );
+            },
+        }
+    }
+
+    pub fn submitted(&mut self, slot: usize) {
+        if let Breadcrumbs::BufferMarkers { markers, .. } = &self.breadcrumbs {
+            unsafe {
+                ptr::write_bytes(markers.add(slot * 2), 0, 2);
+            }
+        }
+
+        self.last_submitted = Some(SubmittedFrame {
+            frame: self.submitted_frames,
+            slot,
+        });
+
+        self.submitted_frames += 1;
+    }
+
+    /// Collects everything known about the last submitted work. Only meaningful once the device is lost.
+    pub fn device_lost(&self, queue: vk::Queue, operation: &'static str) -> DeviceLost {
+        let (frame, slot) = match &self.last_submitted {
+            None => return DeviceLost {
+                operation,
+                frame: None,
+                slot: None,
+                recorded_labels: vec![],
+                active_labels: None,
+                breadcrumbs: vec![],
+            },
+            Some(submitted) => (submitted.frame, submitted.slot)
+        };
+
+        let recorded = &self.recorded[slot];
+
+        let recorded_labels = recorded.iter()
+            .filter_map(|breadcrumb| match breadcrumb {
+                Breadcrumb::Begin(name) => Some(name.clone()),
+                Breadcrumb::End => None,
+            })
+            .collect();
+
+        let mut breadcrumbs = vec![];
+
+        let finished = match &self.breadcrumbs {
+            Breadcrumbs::None => None,
+            Breadcrumbs::Checkpoints(fp) => unsafe {
+                let mut count = 0;
+                (fp.get_queue_checkpoint_data_nv)(queue, &mut count, ptr::null_mut());
+
+                let mut checkpoints = vec![vk::CheckpointDataNV::default(); count as usize];
+                (fp.get_queue_checkpoint_data_nv)(queue, &mut count, checkpoints.as_mut_ptr());
+
+                let mut finished = 0;
+
+                for checkpoint in &checkpoints[..count as usize] {
+                    let value = checkpoint.p_checkpoint_marker as usize;
+
+                    if value >> 16 != slot {
+                        continue;
+                    }
+
+                    let marker = (value & 0xffff) as u32;
+
+                    breadcrumbs.push(format!("{:?}: {}", checkpoint.stage, Self::describe(recorded, marker)));
+
+                    if checkpoint.stage == vk::PipelineStageFlags::BOTTOM_OF_PIPE {
+                        finished = finished.max(marker);
+                    }
+                }
+
+                Some(finished)
+            },
+            Breadcrumbs::BufferMarkers { markers, .. } => unsafe {
+                let started = *markers.add(slot * 2);
+                let finished = *markers.add(slot * 2 + 1);
+
+                breadcrumbs.push(format!("last started: {}", Self::describe(recorded, started)));
+                breadcrumbs.push(format!("last finished: {}", Self::describe(recorded, finished)));
+
+                Some(finished)
+            },
+        };
+
+        // replaying the recorded labels up to the last finished marker leaves the ones the GPU was inside of:
+        let active_labels = finished.map(|finished| {
+            let mut stack = vec![];
+
+            for breadcrumb in recorded.iter().take(finished as usize) {
+                match breadcrumb {
+                    Breadcrumb::Begin(name) => stack.push(name.clone()),
+                    Breadcrumb::End => { stack.pop(); },
+                }
+            }
+
+            stack
+        });
+
+        DeviceLost {
+            operation,
+            frame: Some(frame),
+            slot: Some(slot),
+            recorded_labels,
+            active_labels,
+            breadcrumbs,
+        }
+    }
+
+    fn describe(recorded: &[Breadcrumb], marker: u32) -> String {
+        if marker == 0 {
+            return "nothing".to_owned();
+        }
+
+        match recorded.get(marker as usize - 1) {
+            None => format!("unknown marker {}", marker),
+            Some(Breadcrumb::Begin(name)) => format!("begin '{}'", name),
+            Some(Breadcrumb::End) => "end of label".to_owned(),
+        }
+    }
+
+    pub unsafe fn cleanup(&self, device: &RendererDevice) {
+        if let Breadcrumbs::BufferMarkers { buffer, memory, .. } = &self.breadcrumbs {
+            device.logical_device.unmap_memory(*memory);
+            device.logical_device.destroy_buffer(*buffer, None);
+            device.logical_device.free_memory(*memory, None);
+        }
+    }
+}
+
+/// Returned (through `anyhow`) when the device was lost while submitting, presenting or waiting.
+#[derive(Debug)]
+pub struct DeviceLost {
+    pub operation: &'static str,
+    pub frame: Option<u64>,
+    pub slot: Option<usize>,
+    pub recorded_labels: Vec<String>,
+    pub active_labels: Option<Vec<String>>,
+    pub breadcrumbs: Vec<String>,
+}
+
+impl fmt::Display for DeviceLost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Device lost during {}", self.operation)?;
+
+        match (self.frame, self.slot) {
+            (Some(frame), Some(slot)) => writeln!(f, "  last submitted frame: {} (command buffer {})", frame, slot)?,
+            _ => writeln!(f, "  nothing was submitted yet")?,
+        }
+
+        writeln!(f, "  recorded labels: {:?}", self.recorded_labels)?;
+
+        match &self.active_labels {
+            None => writeln!(f, "  active labels: unknown, no breadcrumb extension available")?,
+            Some(labels) => writeln!(f, "  active labels: {:?}", labels)?,
+        }
+
+        for breadcrumb in &self.breadcrumbs {
+            writeln!(f, "  breadcrumb: {}", breadcrumb)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for DeviceLost {}
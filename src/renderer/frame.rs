@@ -0,0 +1,201 @@
+use ash::vk;
+use ash::prelude::VkResult;
+
+use crate::renderer::backend::Backend;
+use crate::renderer::device::RendererDevice;
+use crate::renderer::target::RenderTarget;
+use crate::renderer::image::RendererImage;
+use crate::renderer::hdr::RendererHdr;
+use crate::renderer::post::RendererPostProcess;
+use crate::renderer::lights::ClusteredLights;
+use crate::renderer::culling::RendererCulling;
+use crate::renderer::text::RendererText;
+use crate::renderer::capture::RendererCapture;
+use crate::renderer::screenshot::RendererScreenshots;
+use crate::renderer::overlay::RendererOverlay;
+
+use anyhow::Result;
+
+/// A frame that is being recorded, handed out by `VulkanRenderer::begin_frame`.
+///
+/// The render pass is already begun with the HDR framebuffer, `command_buffer` is a secondary command buffer that
+/// continues it.
+pub struct Frame {
+    pub command_buffer: vk::CommandBuffer,
+    pub image_index: u32,
+    pub slot: usize,
+    pub extent: vk::Extent2D,
+}
+
+/// What a frame's primary command buffer records around its render pass, and how it's submitted and presented.
+///
+/// Borrowed from a `VulkanRenderer` by `begin_frame` and `end_frame`. Like the [`Recorder`], it records through a
+/// [`Backend`], the renderer's device unless it's made by hand around another one.
+///
+/// [`Recorder`]: crate::renderer::recorder::Recorder
+pub struct FramePasses<'a, B: Backend = RendererDevice> {
+    pub device: &'a B,
+    pub queue: vk::Queue,
+    /// The scene render pass, drawing into `hdr`'s framebuffer.
+    pub render_pass: vk::RenderPass,
+    pub clear_color: [f32; 4],
+    pub reverse_z: bool,
+    /// The primary command buffer of each frame slot.
+    pub command_buffers: &'a [vk::CommandBuffer],
+    pub camera_sets: &'a [vk::DescriptorSet],
+    pub target: &'a RenderTarget,
+    pub depth: &'a RendererImage,
+    pub hdr: &'a RendererHdr,
+    pub post: &'a mut RendererPostProcess,
+    pub lights: &'a ClusteredLights,
+    pub culling: &'a mut RendererCulling,
+    pub text: Option<&'a RendererText>,
+    /// Only when the frame is of the renderer's own window.
+    pub capture: Option<&'a mut RendererCapture>,
+    pub screenshots: &'a mut RendererScreenshots,
+    pub overlay: Option<&'a mut RendererOverlay>,
+}
+
+impl<'a, B: Backend> FramePasses<'a, B> {
+    /// Begins the primary command buffer of `slot`, bins the lights and begins the render pass, which the frame's
+    /// secondary command buffers continue. Returns the primary command buffer.
+    pub fn begin(&self, slot: usize) -> Result<vk::CommandBuffer> {
+        let command_buffer = self.command_buffers[slot];
+
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+        let clear_values = [
+            vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: self.clear_color,
+                }
+            },
+            vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: if self.reverse_z { 0.0 } else { 1.0 },
+                    stencil: 0,
+                }
+            },
+        ];
+
+        let render_pass_begin_info = vk::RenderPassBeginInfo::builder()
+            .render_pass(self.render_pass)
+            .framebuffer(self.hdr.framebuffer)
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: self.target.extent(),
+            })
+            .clear_values(&clear_values);
+
+        self.device.begin_command_buffer(command_buffer, &begin_info)?;
+
+        // lights are binned before the render pass, compute can't run inside of it:
+        self.lights.record(self.device, command_buffer, slot, self.camera_sets[slot]);
+
+        self.device.cmd_begin_render_pass(
+            command_buffer,
+            &render_pass_begin_info,
+            vk::SubpassContents::SECONDARY_COMMAND_BUFFERS,
+        );
+
+        Ok(command_buffer)
+    }
+
+    /// Executes `secondary_command_buffers` and ends the render pass, then records what comes after it: the depth
+    /// pyramid, the post-process effects, tonemapping into the target, screen space text, the capture, a
+    /// screenshot and the overlay.
+    pub fn end(&mut self, frame: &Frame, secondary_command_buffers: &[vk::CommandBuffer]) -> Result<()> {
+        let command_buffer = self.command_buffers[frame.slot];
+        let framebuffer = self.target.framebuffers()[frame.image_index as usize];
+
+        self.device.cmd_execute_commands(command_buffer, secondary_command_buffers);
+
+        self.device.cmd_end_render_pass(command_buffer);
+
+        self.culling.record_pyramid(self.device, command_buffer, self.depth);
+
+        let source = self.post.record(self.device, command_buffer);
+
+        self.hdr.record(self.device, command_buffer, framebuffer, frame.extent, source);
+
+        if let Some(text) = self.text {
+            text.record_screen(self.device, command_buffer, framebuffer, frame.extent, self.hdr.tonemapping.paper_white);
+        }
+
+        if let Some(capture) = &mut self.capture {
+            capture.record(
+                self.device,
+                command_buffer,
+                frame.slot,
+                self.target.image(frame.image_index),
+                self.target.final_layout(),
+            );
+        }
+
+        self.screenshots.record(self.device, command_buffer, frame.slot, self.target, frame.image_index)?;
+
+        // the overlay goes over everything, after the frame was captured:
+        if let Some(overlay) = &mut self.overlay {
+            overlay.record(
+                self.device,
+                command_buffer,
+                frame.slot,
+                framebuffer,
+                frame.extent,
+                self.hdr.tonemapping.paper_white,
+            );
+        }
+
+        self.device.end_command_buffer(command_buffer)
+    }
+
+    /// Submits the frame's primary command buffer after `first`, signalling the fence of its slot. A swapchain's
+    /// image is waited for and its semaphore signalled for presenting.
+    ///
+    /// The fence is reset right before, a frame that failed earlier leaves it signalled for the next wait.
+    pub fn submit(&self, frame: &Frame, first: &[vk::CommandBuffer]) -> VkResult<()> {
+        let command_buffers: Vec<vk::CommandBuffer> = first.iter()
+            .copied()
+            .chain([self.command_buffers[frame.slot]])
+            .collect();
+
+        let (semaphores_available, semaphores_finished) = match self.target {
+            RenderTarget::Swapchain(swapchain) => (
+                vec![swapchain.image_available[frame.slot]],
+                vec![swapchain.rendering_finished[frame.slot]],
+            ),
+            RenderTarget::Offscreen(_) => (vec![], vec![]),
+        };
+
+        let waiting_stages = vec![vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT; semaphores_available.len()];
+
+        let submit_info = [
+            vk::SubmitInfo::builder()
+                .wait_semaphores(&semaphores_available)
+                .wait_dst_stage_mask(&waiting_stages)
+                .command_buffers(&command_buffers)
+                .signal_semaphores(&semaphores_finished)
+                .build()
+        ];
+
+        let fence = self.target.may_begin_drawing()[frame.slot];
+
+        self.device.reset_fences(&[fence])?;
+
+        self.device.queue_submit(self.queue, &submit_info, fence)
+    }
+
+    /// Presents the frame once it's rendered when the target is a swapchain, returns whether the swapchain is
+    /// suboptimal.
+    pub fn present(&self, frame: &Frame) -> VkResult<bool> {
+        match self.target {
+            RenderTarget::Swapchain(swapchain) => {
+                let semaphores_finished = [swapchain.rendering_finished[frame.slot]];
+
+                self.device.queue_present(self.queue, swapchain, frame.image_index, &semaphores_finished)
+            },
+            RenderTarget::Offscreen(_) => Ok(false)
+        }
+    }
+}
@@ -0,0 +1,461 @@
+//! The scene is rendered into a floating point target, then tonemapped into the swapchain image.
+//!
+//! There are two of those targets, so the [`post`](crate::renderer::post) effects can ping-pong between them on the
+//! way to tonemapping.
+//!
+//! The tonemapping pass is a fullscreen triangle. It scales the scene by the exposure, compresses it with one
+//! of the [`Tonemapper`]s and encodes it for whatever the target expects, see [`OutputEncoding`].
+
+use ash::vk;
+
+use crate::renderer::device::RendererDevice;
+use crate::renderer::backend::Backend;
+use crate::renderer::image::RendererImage;
+use crate::renderer::pipeline::{PipelineDesc, RendererPipeline};
+use crate::renderer::target::RenderTarget;
+
+use std::{mem, slice};
+
+use anyhow::Result;
+
+/// What the scene is rendered into.
+pub const HDR_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+/// How scene colors are compressed into what the output can show.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tonemapper {
+    /// Passes colors through, SDR outputs clip everything over 1.
+    None,
+    Reinhard,
+    /// A fit of the ACES filmic curve.
+    Aces,
+    /// The AgX base look, desaturates bright colors instead of skewing their hue.
+    AgX,
+}
+
+/// How the tonemapped colors are written, which depends on the target's format and color space.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputEncoding {
+    /// As they are, for `_SRGB` formats that encode on their own and for offscreen images that are read back.
+    #[default]
+    Linear,
+    /// Encoded by the shader, for `_UNORM` formats in the sRGB color space.
+    Srgb,
+    /// HDR10: Rec. 2020 primaries and the ST 2084 (PQ) curve.
+    Pq,
+    /// Linear with 1.0 at 80 nits, for extended sRGB surfaces.
+    ScRgb,
+}
+
+impl OutputEncoding {
+    pub fn new(format: vk::Format, color_space: vk::ColorSpaceKHR) -> OutputEncoding {
+        match color_space {
+            vk::ColorSpaceKHR::HDR10_ST2084_EXT => OutputEncoding::Pq,
+            vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT => OutputEncoding::ScRgb,
+            _ => match format {
+                vk::Format::B8G8R8A8_SRGB
+                | vk::Format::R8G8B8A8_SRGB
+                | vk::Format::A8B8G8R8_SRGB_PACK32 => OutputEncoding::Linear,
+                _ => OutputEncoding::Srgb,
+            },
+        }
+    }
+
+    /// Whether the output can show more than paper white.
+    pub fn is_hdr(self) -> bool {
+        matches!(self, OutputEncoding::Pq | OutputEncoding::ScRgb)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tonemapping {
+    pub tonemapper: Tonemapper,
+    /// Scene colors are multiplied by this before they're tonemapped.
+    pub exposure: f32,
+    /// How bright a tonemapped 1.0 is on HDR outputs, in nits.
+    pub paper_white: f32,
+    /// The brightest HDR outputs get, in nits. The tonemappers' curves are stretched to end there instead of at
+    /// paper white, `Tonemapper::None` goes past it.
+    pub peak_brightness: f32,
+}
+
+impl Default for Tonemapping {
+    fn default() -> Self {
+        Tonemapping {
+            tonemapper: Tonemapper::Aces,
+            exposure: 1.0,
+            paper_white: 203.0,
+            peak_brightness: 1000.0,
+        }
+    }
+}
+
+/// The tonemapping settings as the shader reads them.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct TonemapConstants {
+    tonemapper: u32,
+    encoding: u32,
+    exposure: f32,
+    paper_white: f32,
+    peak_brightness: f32,
+}
+
+/// The HDR color targets with the framebuffer the scene is rendered into, and the pass that tonemaps them.
+#[derive(Default)]
+pub struct RendererHdr {
+    /// The scene is rendered into the first, the second is only used by post-process effects.
+    pub targets: [RendererImage; 2],
+    /// What the scene is rendered into with MSAA, resolved into the first target at the end of the render pass. A
+    /// null image without it.
+    pub multisampled: RendererImage,
+    /// The first HDR color target and the depth buffer, for the scene render pass. With MSAA, the multisampled
+    /// target and depth buffer, then the first target they're resolved into.
+    pub framebuffer: vk::Framebuffer,
+    /// Renders into the target's images, which have framebuffers of their own.
+    pub render_pass: vk::RenderPass,
+    pub pipeline: RendererPipeline,
+    pub sampler: vk::Sampler,
+    pub set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    /// Samples the target with the same index.
+    pub descriptor_sets: [vk::DescriptorSet; 2],
+    pub encoding: OutputEncoding,
+    pub tonemapping: Tonemapping,
+}
+
+impl RendererHdr {
+    /// `scene_render_pass` has to have an `HDR_FORMAT` color attachment followed by a depth one, with `samples`
+    /// each. More than one needs a third attachment they're resolved into.
+    pub fn new(
+        device: &RendererDevice,
+        target: &RenderTarget,
+        scene_render_pass: vk::RenderPass,
+        depth_view: vk::ImageView,
+        samples: vk::SampleCountFlags
+    ) -> Result<RendererHdr> {
+        let usage = vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED;
+
+        let mut scene = RendererImage::new(device, "hdr scene", target.extent(), HDR_FORMAT, usage)?;
+
+        let swap = match RendererImage::new(device, "hdr swap", target.extent(), HDR_FORMAT, usage) {
+            Err(err) => {
+                unsafe {
+                    scene.cleanup(device);
+                };
+
+                return Err(err);
+            },
+            Ok(swap) => swap
+        };
+
+        let mut hdr = RendererHdr {
+            targets: [scene, swap],
+            multisampled: RendererImage::default(),
+            framebuffer: vk::Framebuffer::null(),
+            render_pass: vk::RenderPass::null(),
+            pipeline: RendererPipeline {
+                pipeline: vk::Pipeline::null(),
+                pipeline_layout: vk::PipelineLayout::null(),
+            },
+            sampler: vk::Sampler::null(),
+            set_layout: vk::DescriptorSetLayout::null(),
+            descriptor_pool: vk::DescriptorPool::null(),
+            descriptor_sets: [vk::DescriptorSet::null(); 2],
+            encoding: target.output_encoding(),
+            tonemapping: Tonemapping::default(),
+        };
+
+        if let Err(err) = hdr.create(device, target, scene_render_pass, depth_view, samples) {
+            unsafe {
+                hdr.cleanup(device);
+            };
+
+            return Err(err);
+        }
+
+        Ok(hdr)
+    }
+
+    fn create(
+        &mut self,
+        device: &RendererDevice,
+        target: &RenderTarget,
+        scene_render_pass: vk::RenderPass,
+        depth_view: vk::ImageView,
+        samples: vk::SampleCountFlags
+    ) -> Result<()> {
+        let extent = target.extent();
+
+        // scene framebuffer, the multisampled target is only ever written and resolved:
+        let attachments = if samples == vk::SampleCountFlags::TYPE_1 {
+            vec![self.targets[0].image_view, depth_view]
+        } else {
+            self.multisampled = RendererImage::multisampled(
+                device,
+                "hdr multisampled",
+                extent,
+                HDR_FORMAT,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+                samples,
+            )?;
+
+            vec![self.multisampled.image_view, depth_view, self.targets[0].image_view]
+        };
+
+        let framebuffer_info = vk::FramebufferCreateInfo::builder()
+            .render_pass(scene_render_pass)
+            .attachments(&attachments)
+            .width(extent.width)
+            .height(extent.height)
+            .layers(1);
+
+        self.framebuffer = unsafe {
+            device.logical_device.create_framebuffer(&framebuffer_info, None)?
+        };
+
+        // sampler, it's read texel for texel:
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::NEAREST)
+            .min_filter(vk::Filter::NEAREST)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+
+        self.sampler = unsafe {
+            device.logical_device.create_sampler(&sampler_info, None)?
+        };
+
+        // descriptors:
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .build()
+        ];
+
+        let set_layout_info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .bindings(&bindings);
+
+        self.set_layout = unsafe {
+            device.logical_device.create_descriptor_set_layout(&set_layout_info, None)?
+        };
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: 2,
+            }
+        ];
+
+        let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(2)
+            .pool_sizes(&pool_sizes);
+
+        self.descriptor_pool = unsafe {
+            device.logical_device.create_descriptor_pool(&pool_info, None)?
+        };
+
+        let set_layouts = [self.set_layout; 2];
+
+        let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(self.descriptor_pool)
+            .set_layouts(&set_layouts);
+
+        let descriptor_sets = unsafe {
+            device.logical_device.allocate_descriptor_sets(&allocate_info)?
+        };
+
+        for (i, target) in self.targets.iter().enumerate() {
+            self.descriptor_sets[i] = descriptor_sets[i];
+
+            let image_infos = [
+                vk::DescriptorImageInfo {
+                    sampler: self.sampler,
+                    image_view: target.image_view,
+                    image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                }
+            ];
+
+            let writes = [
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(self.descriptor_sets[i])
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(&image_infos)
+                    .build()
+            ];
+
+            unsafe {
+                device.logical_device.update_descriptor_sets(&writes, &[]);
+            };
+        }
+
+        // tonemapping:
+        self.render_pass = Self::create_render_pass(device, target.format(), target.final_layout())?;
+
+        let push_constant_ranges = [
+            vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                offset: 0,
+                size: mem::size_of::<TonemapConstants>() as u32,
+            }
+        ];
+
+        self.pipeline = RendererPipeline::from_desc(device, extent, self.render_pass, &PipelineDesc {
+            vert_code: vk_shader_macros::include_glsl!("./shaders/fullscreen.vert"),
+            frag_code: vk_shader_macros::include_glsl!("./shaders/tonemap.frag"),
+            set_layouts: &[self.set_layout],
+            push_constant_ranges: &push_constant_ranges,
+            ..Default::default()
+        })?;
+
+        Ok(())
+    }
+
+    /// Every pixel is written, so what was in the image before doesn't matter.
+    fn create_render_pass(
+        device: &RendererDevice,
+        format: vk::Format,
+        final_layout: vk::ImageLayout
+    ) -> Result<vk::RenderPass> {
+        let attachments = [
+            vk::AttachmentDescription::builder()
+                .format(format)
+                .load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .final_layout(final_layout)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .build(),
+        ];
+
+        let color_attachment_references = [vk::AttachmentReference {
+            attachment: 0,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        }];
+
+        let subpasses = [
+            vk::SubpassDescription::builder()
+                .color_attachments(&color_attachment_references)
+                .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+                .build()
+        ];
+
+        let subpass_dependencies = [
+            vk::SubpassDependency::builder()
+                .src_subpass(vk::SUBPASS_EXTERNAL)
+                .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                .dst_subpass(0)
+                .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .build()
+        ];
+
+        let render_pass_info = vk::RenderPassCreateInfo::builder()
+            .attachments(&attachments)
+            .subpasses(&subpasses)
+            .dependencies(&subpass_dependencies);
+
+        let render_pass = unsafe {
+            device.logical_device.create_render_pass(&render_pass_info, None)?
+        };
+
+        Ok(render_pass)
+    }
+
+    /// Records the tonemapping pass of the target at `source` into `framebuffer`, after the scene render pass
+    /// and the post-process effects.
+    pub fn record<B: Backend>(
+        &self,
+        backend: &B,
+        command_buffer: vk::CommandBuffer,
+        framebuffer: vk::Framebuffer,
+        extent: vk::Extent2D,
+        source: usize
+    ) {
+        let constants = TonemapConstants {
+            tonemapper: match self.tonemapping.tonemapper {
+                Tonemapper::None => 0,
+                Tonemapper::Reinhard => 1,
+                Tonemapper::Aces => 2,
+                Tonemapper::AgX => 3,
+            },
+            encoding: match self.encoding {
+                OutputEncoding::Linear => 0,
+                OutputEncoding::Srgb => 1,
+                OutputEncoding::Pq => 2,
+                OutputEncoding::ScRgb => 3,
+            },
+            exposure: self.tonemapping.exposure,
+            paper_white: self.tonemapping.paper_white,
+            peak_brightness: self.tonemapping.peak_brightness,
+        };
+
+        let constants_bytes = unsafe {
+            slice::from_raw_parts(&constants as *const TonemapConstants as *const u8, mem::size_of::<TonemapConstants>())
+        };
+
+        let render_pass_begin_info = vk::RenderPassBeginInfo::builder()
+            .render_pass(self.render_pass)
+            .framebuffer(framebuffer)
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent,
+            });
+
+        backend.cmd_begin_render_pass(
+            command_buffer,
+            &render_pass_begin_info,
+            vk::SubpassContents::INLINE,
+        );
+
+        backend.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            self.pipeline.pipeline,
+        );
+
+        backend.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            self.pipeline.pipeline_layout,
+            0,
+            &[self.descriptor_sets[source]],
+            &[],
+        );
+
+        backend.cmd_push_constants(
+            command_buffer,
+            self.pipeline.pipeline_layout,
+            vk::ShaderStageFlags::FRAGMENT,
+            0,
+            constants_bytes,
+        );
+
+        backend.cmd_draw(command_buffer, 3, 1, 0, 0);
+
+        backend.cmd_end_render_pass(command_buffer);
+    }
+
+    pub unsafe fn cleanup(&mut self, device: &RendererDevice) {
+        self.pipeline.cleanup(&device.logical_device);
+
+        device.logical_device.destroy_render_pass(self.render_pass, None);
+        device.logical_device.destroy_descriptor_pool(self.descriptor_pool, None);
+        device.logical_device.destroy_descriptor_set_layout(self.set_layout, None);
+        device.logical_device.destroy_sampler(self.sampler, None);
+        device.logical_device.destroy_framebuffer(self.framebuffer, None);
+
+        for target in &mut self.targets {
+            target.cleanup(device);
+        }
+
+        self.multisampled.cleanup(device);
+    }
+}
@@ -0,0 +1,305 @@
+use ash::vk;
+
+use crate::renderer::device::RendererDevice;
+use crate::renderer::command_pools::CommandPools;
+use crate::renderer::buffer::RendererBuffer;
+use crate::renderer::backend::Backend;
+
+use gpu_allocator::MemoryLocation;
+use gpu_allocator::vulkan::{Allocation, AllocationCreateDesc};
+
+use std::mem;
+
+use anyhow::Result;
+
+#[derive(Default)]
+pub struct RendererImage {
+    pub image: vk::Image,
+    pub image_view: vk::ImageView,
+    pub allocation: Allocation,
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+    /// Images deeper than 1 are 3D.
+    pub depth: u32,
+    /// Images with more than one layer are viewed as 2D arrays.
+    pub layers: u32,
+    /// The view covers every level.
+    pub mip_levels: u32,
+    /// Per texel, more than one only for multisampled attachments.
+    pub samples: vk::SampleCountFlags,
+}
+
+impl RendererImage {
+    pub fn new(
+        device: &RendererDevice,
+        name: &str,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags
+    ) -> Result<RendererImage> {
+        Self::layered(device, name, extent, format, usage, 1)
+    }
+
+    /// Like `new`, but with an array of `layers` images that are all the same size.
+    pub fn layered(
+        device: &RendererDevice,
+        name: &str,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        layers: u32
+    ) -> Result<RendererImage> {
+        Self::create(device, name, Self::extent_3d(extent, 1), format, usage, layers, 1, vk::SampleCountFlags::TYPE_1)
+    }
+
+    /// A 3D image, `extent` wide and high and `depth` deep.
+    pub fn volume(
+        device: &RendererDevice,
+        name: &str,
+        extent: vk::Extent2D,
+        depth: u32,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags
+    ) -> Result<RendererImage> {
+        Self::create(device, name, Self::extent_3d(extent, depth), format, usage, 1, 1, vk::SampleCountFlags::TYPE_1)
+    }
+
+    /// Like `new`, but with `mip_levels` levels that each have half the size of the one before.
+    pub fn mipmapped(
+        device: &RendererDevice,
+        name: &str,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        mip_levels: u32
+    ) -> Result<RendererImage> {
+        Self::create(device, name, Self::extent_3d(extent, 1), format, usage, 1, mip_levels, vk::SampleCountFlags::TYPE_1)
+    }
+
+    /// Like `new`, but with `samples` per texel, for attachments that are resolved or only read by shaders.
+    pub fn multisampled(
+        device: &RendererDevice,
+        name: &str,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        samples: vk::SampleCountFlags
+    ) -> Result<RendererImage> {
+        Self::create(device, name, Self::extent_3d(extent, 1), format, usage, 1, 1, samples)
+    }
+
+    fn extent_3d(extent: vk::Extent2D, depth: u32) -> vk::Extent3D {
+        vk::Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth,
+        }
+    }
+
+    fn create(
+        device: &RendererDevice,
+        name: &str,
+        extent: vk::Extent3D,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        layers: u32,
+        mip_levels: u32,
+        samples: vk::SampleCountFlags
+    ) -> Result<RendererImage> {
+        let depth = extent.depth;
+
+        let extent = vk::Extent2D {
+            width: extent.width,
+            height: extent.height,
+        };
+
+        let image_type = if depth > 1 {
+            vk::ImageType::TYPE_3D
+        } else {
+            vk::ImageType::TYPE_2D
+        };
+
+        let image_info = vk::ImageCreateInfo::builder()
+            .image_type(image_type)
+            .format(format)
+            .extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth,
+            })
+            .mip_levels(mip_levels)
+            .array_layers(layers)
+            .samples(samples)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+
+        let image = unsafe {
+            device.logical_device.create_image(&image_info, None)?
+        };
+
+        let requirements = unsafe {
+            device.logical_device.get_image_memory_requirements(image)
+        };
+
+        let allocation = device.allocate(&AllocationCreateDesc {
+            name,
+            requirements,
+            location: MemoryLocation::GpuOnly,
+            linear: false,
+        })?;
+
+        unsafe {
+            device.logical_device.bind_image_memory(image, allocation.memory(), allocation.offset())?
+        };
+
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(Self::aspect_mask(format))
+            .base_mip_level(0)
+            .level_count(mip_levels)
+            .base_array_layer(0)
+            .layer_count(layers);
+
+        let view_type = if depth > 1 {
+            vk::ImageViewType::TYPE_3D
+        } else if layers > 1 {
+            vk::ImageViewType::TYPE_2D_ARRAY
+        } else {
+            vk::ImageViewType::TYPE_2D
+        };
+
+        let image_view_info = vk::ImageViewCreateInfo::builder()
+            .image(image)
+            .view_type(view_type)
+            .format(format)
+            .subresource_range(*subresource_range);
+
+        let image_view = unsafe {
+            device.logical_device.create_image_view(&image_view_info, None)?
+        };
+
+        Ok(RendererImage {
+            image,
+            image_view,
+            allocation,
+            format,
+            extent,
+            depth,
+            layers,
+            mip_levels,
+            samples,
+        })
+    }
+
+    /// Fills the whole image with tightly packed `pixels` and leaves it ready to be sampled by fragment shaders.
+    ///
+    /// The image has to be created with `TRANSFER_DST` usage.
+    pub fn upload(
+        &self,
+        device: &RendererDevice,
+        command_pools: &CommandPools,
+        pixels: &[u8]
+    ) -> Result<()> {
+        let mut staging = RendererBuffer::with_data(device, "staging", vk::BufferUsageFlags::TRANSFER_SRC, pixels)?;
+
+        let result = command_pools.one_time_submit(device, |command_buffer| {
+            self.record_upload(device, command_buffer, staging.buffer);
+        });
+
+        unsafe {
+            staging.cleanup(device);
+        };
+
+        result
+    }
+
+    /// Records what `upload` does, copying the whole image from `staging` and leaving it ready to be sampled.
+    pub fn record_upload<B: Backend>(&self, backend: &B, command_buffer: vk::CommandBuffer, staging: vk::Buffer) {
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: Self::aspect_mask(self.format),
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+
+        let to_transfer = vk::ImageMemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::empty())
+            .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .old_layout(vk::ImageLayout::UNDEFINED)
+            .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(self.image)
+            .subresource_range(subresource_range);
+
+        let region = vk::BufferImageCopy::builder()
+            .image_subresource(vk::ImageSubresourceLayers {
+                aspect_mask: subresource_range.aspect_mask,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .image_extent(vk::Extent3D {
+                width: self.extent.width,
+                height: self.extent.height,
+                depth: self.depth,
+            });
+
+        let to_shader_read = vk::ImageMemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ)
+            .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(self.image)
+            .subresource_range(subresource_range);
+
+        backend.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::TRANSFER,
+            &[],
+            &[],
+            &[to_transfer.build()],
+        );
+
+        backend.cmd_copy_buffer_to_image(
+            command_buffer,
+            staging,
+            self.image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &[region.build()],
+        );
+
+        backend.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            &[],
+            &[],
+            &[to_shader_read.build()],
+        );
+    }
+
+    pub fn aspect_mask(format: vk::Format) -> vk::ImageAspectFlags {
+        match format {
+            vk::Format::D16_UNORM
+            | vk::Format::X8_D24_UNORM_PACK32
+            | vk::Format::D32_SFLOAT => vk::ImageAspectFlags::DEPTH,
+            vk::Format::D16_UNORM_S8_UINT
+            | vk::Format::D24_UNORM_S8_UINT
+            | vk::Format::D32_SFLOAT_S8_UINT => vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL,
+            _ => vk::ImageAspectFlags::COLOR,
+        }
+    }
+
+    pub unsafe fn cleanup(&mut self, device: &RendererDevice) {
+        device.logical_device.destroy_image_view(self.image_view, None);
+        device.logical_device.destroy_image(self.image, None);
+
+        device.free(mem::take(&mut self.allocation)).unwrap();
+    }
+}
@@ -0,0 +1,459 @@
+//! Clustered forward lighting.
+//!
+//! The view frustum is cut into a grid of clusters, tiles on screen and exponentially deeper slices.
+//! Every frame a compute pass bins the point and spot lights into the clusters they reach, so that a
+//! fragment only iterates the lights of its own cluster. Directional lights reach everything and are
+//! iterated by every fragment.
+
+use ash::vk;
+
+use crate::renderer::device::RendererDevice;
+use crate::renderer::backend::Backend;
+use crate::renderer::buffer::RendererBuffer;
+use crate::renderer::pipeline::RendererPipeline;
+use crate::renderer::shadows;
+use crate::scene::LightKind;
+use crate::camera::{Camera, Projection};
+
+use gpu_allocator::MemoryLocation;
+
+use glam::Vec3;
+
+use std::{mem, slice};
+
+use anyhow::Result;
+
+/// Clusters along x, y and z.
+pub const CLUSTER_GRID: [u32; 3] = [16, 9, 24];
+
+/// Lights past this many in one cluster are left out of it.
+pub const MAX_LIGHTS_PER_CLUSTER: u32 = 128;
+
+/// How many lights can be submitted per frame.
+pub const MAX_LIGHTS: usize = 1024;
+
+/// Where clusters end for cameras with an infinite far plane, the last slice still reaches past it.
+const INFINITE_FAR_FALLBACK: f32 = 1000.0;
+
+/// Lights without a range are binned as if they stopped where they'd add less than this.
+const RANGE_CUTOFF: f32 = 0.01;
+
+/// A light placed in the world, as submitted for a frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WorldLight {
+    pub kind: LightKind,
+    /// Ignored by directional lights.
+    pub position: Vec3,
+    /// Where the light shines to, ignored by point lights.
+    pub direction: Vec3,
+    pub color: Vec3,
+    /// Lux for directional lights, candela for the others.
+    pub intensity: f32,
+    /// Past this distance the light has no effect, infinite when missing.
+    pub range: Option<f32>,
+    /// Only directional and spot lights can, see [`shadows`](crate::renderer::shadows) for how many.
+    pub casts_shadows: bool,
+}
+
+impl WorldLight {
+    /// How far the light reaches, lights without a range stop where they'd add next to nothing.
+    pub fn reach(&self) -> f32 {
+        match self.range {
+            Some(range) => range,
+            None => ((self.color * self.intensity).max_element() / RANGE_CUTOFF).sqrt()
+        }
+    }
+}
+
+/// A light as the shaders read it, laid out for std430.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct LightData {
+    pub position: Vec3,
+    pub range: f32,
+    pub direction: Vec3,
+    /// 0 for directional, 1 for point and 2 for spot lights.
+    pub kind: u32,
+    /// Already multiplied by the intensity.
+    pub color: Vec3,
+    /// The cone falloff is `saturate(cos(angle) * spot_scale + spot_offset)` squared.
+    pub spot_scale: f32,
+    pub spot_offset: f32,
+    /// Which shadow map the light uses, -1 for none. Directional lights have cascades instead and use 0.
+    pub shadow: i32,
+    pub padding: [f32; 2],
+}
+
+impl LightData {
+    pub fn new(light: &WorldLight, shadow: Option<usize>) -> LightData {
+        let (kind, spot_scale, spot_offset) = match light.kind {
+            LightKind::Directional => (0, 0.0, 0.0),
+            LightKind::Point => (1, 0.0, 0.0),
+            LightKind::Spot { inner_cone_angle, outer_cone_angle } => {
+                let scale = 1.0 / (inner_cone_angle.cos() - outer_cone_angle.cos()).max(0.001);
+
+                (2, scale, -outer_cone_angle.cos() * scale)
+            },
+        };
+
+        LightData {
+            position: light.position,
+            range: light.reach(),
+            direction: light.direction.normalize_or_zero(),
+            kind,
+            color: light.color * light.intensity,
+            spot_scale,
+            spot_offset,
+            shadow: shadow.map_or(-1, |shadow| shadow as i32),
+            padding: [0.0; 2],
+        }
+    }
+}
+
+/// How the lights of a frame are laid out and how its clusters are shaped, laid out for std140.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LightingUniform {
+    /// Clusters along x, y and z, then how many lights fit in one.
+    pub grid: [u32; 4],
+    /// Directional lights come first in the light buffer and aren't binned.
+    pub directional_count: u32,
+    pub light_count: u32,
+    /// Size of a cluster on screen, in pixels.
+    pub tile_size: [f32; 2],
+    pub screen_size: [f32; 2],
+    /// The view space depth range the slices are spread over.
+    pub near: f32,
+    pub far: f32,
+}
+
+/// The per frame light buffers, the cluster grid they are binned into and the compute pass that does it.
+#[derive(Default)]
+pub struct ClusteredLights {
+    pub set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    /// One per frame in flight, like the buffers.
+    pub descriptor_sets: Vec<vk::DescriptorSet>,
+    pub uniforms: Vec<RendererBuffer>,
+    pub lights: Vec<RendererBuffer>,
+    pub cluster_counts: Vec<RendererBuffer>,
+    pub cluster_lights: Vec<RendererBuffer>,
+    pub pipeline: RendererPipeline,
+    /// What the frame being recorded writes to its uniform buffer when it ends.
+    pub uniform: LightingUniform,
+}
+
+impl ClusteredLights {
+    pub fn new(device: &RendererDevice, count: usize, camera_set_layout: vk::DescriptorSetLayout) -> Result<ClusteredLights> {
+        let mut clustered_lights = ClusteredLights {
+            set_layout: vk::DescriptorSetLayout::null(),
+            descriptor_pool: vk::DescriptorPool::null(),
+            descriptor_sets: Vec::with_capacity(count),
+            uniforms: Vec::with_capacity(count),
+            lights: Vec::with_capacity(count),
+            cluster_counts: Vec::with_capacity(count),
+            cluster_lights: Vec::with_capacity(count),
+            pipeline: RendererPipeline {
+                pipeline: vk::Pipeline::null(),
+                pipeline_layout: vk::PipelineLayout::null(),
+            },
+            uniform: LightingUniform::default(),
+        };
+
+        if let Err(err) = clustered_lights.create(device, count, camera_set_layout) {
+            unsafe {
+                clustered_lights.cleanup(device);
+            };
+
+            return Err(err);
+        }
+
+        Ok(clustered_lights)
+    }
+
+    fn create(&mut self, device: &RendererDevice, count: usize, camera_set_layout: vk::DescriptorSetLayout) -> Result<()> {
+        let cluster_count = (CLUSTER_GRID[0] * CLUSTER_GRID[1] * CLUSTER_GRID[2]) as vk::DeviceSize;
+
+        let uint_size = mem::size_of::<u32>() as vk::DeviceSize;
+
+        // buffers:
+        for _ in 0..count {
+            self.uniforms.push(RendererBuffer::new(
+                device,
+                "lighting",
+                mem::size_of::<LightingUniform>() as vk::DeviceSize,
+                vk::BufferUsageFlags::UNIFORM_BUFFER,
+                MemoryLocation::CpuToGpu,
+            )?);
+
+            self.lights.push(RendererBuffer::new(
+                device,
+                "lights",
+                (MAX_LIGHTS * mem::size_of::<LightData>()) as vk::DeviceSize,
+                vk::BufferUsageFlags::STORAGE_BUFFER,
+                MemoryLocation::CpuToGpu,
+            )?);
+
+            self.cluster_counts.push(RendererBuffer::new(
+                device,
+                "cluster light counts",
+                cluster_count * uint_size,
+                vk::BufferUsageFlags::STORAGE_BUFFER,
+                MemoryLocation::GpuOnly,
+            )?);
+
+            self.cluster_lights.push(RendererBuffer::new(
+                device,
+                "cluster lights",
+                cluster_count * MAX_LIGHTS_PER_CLUSTER as vk::DeviceSize * uint_size,
+                vk::BufferUsageFlags::STORAGE_BUFFER,
+                MemoryLocation::GpuOnly,
+            )?);
+        }
+
+        // descriptors:
+        let stages = vk::ShaderStageFlags::COMPUTE | vk::ShaderStageFlags::FRAGMENT;
+
+        let mut bindings = vec![
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(stages)
+                .build()
+        ];
+
+        for binding in 1..=3 {
+            bindings.push(
+                vk::DescriptorSetLayoutBinding::builder()
+                    .binding(binding)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .descriptor_count(1)
+                    .stage_flags(stages)
+                    .build()
+            );
+        }
+
+        let set_layout_info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .bindings(&bindings);
+
+        self.set_layout = unsafe {
+            device.logical_device.create_descriptor_set_layout(&set_layout_info, None)?
+        };
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: count as u32,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: count as u32 * 3,
+            },
+        ];
+
+        let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(count as u32)
+            .pool_sizes(&pool_sizes);
+
+        self.descriptor_pool = unsafe {
+            device.logical_device.create_descriptor_pool(&pool_info, None)?
+        };
+
+        let set_layouts = vec![self.set_layout; count];
+
+        let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(self.descriptor_pool)
+            .set_layouts(&set_layouts);
+
+        self.descriptor_sets = unsafe {
+            device.logical_device.allocate_descriptor_sets(&allocate_info)?
+        };
+
+        for slot in 0..count {
+            let buffers = [
+                &self.uniforms[slot],
+                &self.lights[slot],
+                &self.cluster_counts[slot],
+                &self.cluster_lights[slot],
+            ];
+
+            let buffer_infos: Vec<[vk::DescriptorBufferInfo; 1]> = buffers.iter()
+                .map(|buffer| [
+                    vk::DescriptorBufferInfo {
+                        buffer: buffer.buffer,
+                        offset: 0,
+                        range: buffer.size,
+                    }
+                ])
+                .collect();
+
+            let writes: Vec<vk::WriteDescriptorSet> = buffer_infos.iter()
+                .enumerate()
+                .map(|(binding, buffer_info)| {
+                    let descriptor_type = if binding == 0 {
+                        vk::DescriptorType::UNIFORM_BUFFER
+                    } else {
+                        vk::DescriptorType::STORAGE_BUFFER
+                    };
+
+                    vk::WriteDescriptorSet::builder()
+                        .dst_set(self.descriptor_sets[slot])
+                        .dst_binding(binding as u32)
+                        .descriptor_type(descriptor_type)
+                        .buffer_info(buffer_info)
+                        .build()
+                })
+                .collect();
+
+            unsafe {
+                device.logical_device.update_descriptor_sets(&writes, &[]);
+            };
+        }
+
+        // binning:
+        self.pipeline = RendererPipeline::compute(
+            device,
+            vk_shader_macros::include_glsl!("./shaders/lights.comp"),
+            &[camera_set_layout, self.set_layout],
+            &[],
+        )?;
+
+        Ok(())
+    }
+
+    /// Starts a frame without any lights, binned for a target of the given size.
+    pub fn begin(&mut self, extent: vk::Extent2D) {
+        let [x, y, z] = CLUSTER_GRID;
+
+        let tile_size = [
+            (extent.width as f32 / x as f32).ceil().max(1.0),
+            (extent.height as f32 / y as f32).ceil().max(1.0),
+        ];
+
+        self.uniform = LightingUniform {
+            grid: [x, y, z, MAX_LIGHTS_PER_CLUSTER],
+            directional_count: 0,
+            light_count: 0,
+            tile_size,
+            screen_size: [extent.width as f32, extent.height as f32],
+            ..self.uniform
+        };
+    }
+
+    /// Spreads the slices over the camera's depth range.
+    pub fn set_camera(&mut self, camera: &Camera) {
+        let (near, far) = match camera.projection {
+            Projection::Perspective { near, far, .. } => (near, far),
+            Projection::Orthographic { near, far, .. } => (near, far),
+        };
+
+        // the slices are spaced logarithmically, so they can't start at 0:
+        let near = near.max(0.001);
+
+        let far = if far.is_finite() {
+            far.max(near * 2.0)
+        } else {
+            INFINITE_FAR_FALLBACK.max(near * 2.0)
+        };
+
+        self.uniform.near = near;
+        self.uniform.far = far;
+    }
+
+    /// Replaces the lights of the frame in `slot`.
+    pub fn set_lights(&mut self, slot: usize, lights: &[WorldLight]) -> Result<()> {
+        if lights.len() > MAX_LIGHTS {
+            anyhow::bail!("{} lights were submitted, at most {} fit in a frame", lights.len(), MAX_LIGHTS);
+        }
+
+        let shadows = shadows::shadow_maps(lights);
+
+        // directional lights first, the binning pass skips them:
+        let mut data: Vec<LightData> = lights.iter()
+            .zip(&shadows)
+            .filter(|(light, _)| light.kind == LightKind::Directional)
+            .map(|(light, &shadow)| LightData::new(light, shadow))
+            .collect();
+
+        let directional_count = data.len();
+
+        data.extend(lights.iter()
+            .zip(&shadows)
+            .filter(|(light, _)| light.kind != LightKind::Directional)
+            .map(|(light, &shadow)| LightData::new(light, shadow)));
+
+        self.lights[slot].write(&data)?;
+
+        self.uniform.directional_count = directional_count as u32;
+        self.uniform.light_count = data.len() as u32;
+
+        Ok(())
+    }
+
+    /// Records the binning pass, it has to happen before the render pass begins.
+    pub fn record<B: Backend>(&self, backend: &B, command_buffer: vk::CommandBuffer, slot: usize, camera_set: vk::DescriptorSet) {
+        let pipeline = &self.pipeline;
+
+        // 4x4x4 invocations per group, as in the shader:
+        let group_count = |clusters: u32| clusters.div_ceil(4);
+
+        let barrier = vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ);
+
+        backend.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, pipeline.pipeline);
+
+        backend.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            pipeline.pipeline_layout,
+            0,
+            &[camera_set, self.descriptor_sets[slot]],
+            &[],
+        );
+
+        backend.cmd_dispatch(
+            command_buffer,
+            group_count(CLUSTER_GRID[0]),
+            group_count(CLUSTER_GRID[1]),
+            group_count(CLUSTER_GRID[2]),
+        );
+
+        backend.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            &[barrier.build()],
+            &[],
+            &[],
+        );
+    }
+
+    /// Writes the frame's uniform, right before it is submitted.
+    pub fn flush(&mut self, slot: usize) -> Result<()> {
+        self.uniforms[slot].write(slice::from_ref(&self.uniform))
+    }
+
+    pub unsafe fn cleanup(&mut self, device: &RendererDevice) {
+        self.pipeline.cleanup(&device.logical_device);
+
+        device.logical_device.destroy_descriptor_pool(self.descriptor_pool, None);
+        device.logical_device.destroy_descriptor_set_layout(self.set_layout, None);
+
+        let buffers = self.uniforms.iter_mut()
+            .chain(&mut self.lights)
+            .chain(&mut self.cluster_counts)
+            .chain(&mut self.cluster_lights);
+
+        for buffer in buffers {
+            buffer.cleanup(device);
+        }
+
+        self.uniforms.clear();
+        self.lights.clear();
+        self.cluster_counts.clear();
+        self.cluster_lights.clear();
+        self.descriptor_sets.clear();
+    }
+}
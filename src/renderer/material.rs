@@ -0,0 +1,442 @@
+use ash::vk;
+
+use crate::renderer::device::RendererDevice;
+use crate::renderer::command_pools::CommandPools;
+use crate::renderer::buffer::RendererBuffer;
+use crate::renderer::pipeline::RendererPipeline;
+use crate::renderer::texture::{SamplerDesc, Texture};
+use crate::scene::{AlphaMode, Material, TextureRef};
+
+use gpu_allocator::MemoryLocation;
+
+use glam::Vec4;
+
+use std::{mem, slice};
+
+use anyhow::Result;
+
+/// A material's factors as `pbr.frag` reads them from set 1, binding 0.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct MaterialParams {
+    pub base_color_factor: Vec4,
+    /// Only `xyz` is used.
+    pub emissive_factor: Vec4,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub normal_scale: f32,
+    pub occlusion_strength: f32,
+    pub alpha_cutoff: f32,
+    pub alpha_mode: u32,
+    /// One bit per texture, in binding order, set when it is read with the second UV set.
+    pub uv_sets: u32,
+    pub padding: u32,
+}
+
+impl MaterialParams {
+    pub fn new(material: &Material) -> MaterialParams {
+        let textures = [
+            material.base_color_texture,
+            material.metallic_roughness_texture,
+            material.normal_texture,
+            material.occlusion_texture,
+            material.emissive_texture,
+        ];
+
+        let uv_sets = textures.iter()
+            .enumerate()
+            .filter(|(_, texture)| matches!(texture, Some(TextureRef { uv_set: 1, .. })))
+            .fold(0, |bits, (i, _)| bits | 1 << i);
+
+        let alpha_mode = match material.alpha_mode {
+            AlphaMode::Opaque => 0,
+            AlphaMode::Mask => 1,
+            AlphaMode::Blend => 2,
+        };
+
+        MaterialParams {
+            base_color_factor: material.base_color_factor,
+            emissive_factor: material.emissive_factor.extend(0.0),
+            metallic_factor: material.metallic_factor,
+            roughness_factor: material.roughness_factor,
+            normal_scale: material.normal_scale,
+            occlusion_strength: material.occlusion_strength,
+            alpha_cutoff: material.alpha_cutoff,
+            alpha_mode,
+            uv_sets,
+            padding: 0,
+        }
+    }
+}
+
+/// What a material needs from its pipeline, materials with the same key share one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MaterialKey {
+    pub alpha_mode: AlphaMode,
+    pub double_sided: bool,
+}
+
+impl MaterialKey {
+    pub const ALL: [MaterialKey; 6] = [
+        MaterialKey { alpha_mode: AlphaMode::Opaque, double_sided: false },
+        MaterialKey { alpha_mode: AlphaMode::Opaque, double_sided: true },
+        MaterialKey { alpha_mode: AlphaMode::Mask, double_sided: false },
+        MaterialKey { alpha_mode: AlphaMode::Mask, double_sided: true },
+        MaterialKey { alpha_mode: AlphaMode::Blend, double_sided: false },
+        MaterialKey { alpha_mode: AlphaMode::Blend, double_sided: true },
+    ];
+
+    pub fn new(material: &Material) -> MaterialKey {
+        MaterialKey {
+            alpha_mode: material.alpha_mode,
+            double_sided: material.double_sided,
+        }
+    }
+
+    /// Where the key is in `ALL`.
+    pub fn index(self) -> usize {
+        let alpha_mode = match self.alpha_mode {
+            AlphaMode::Opaque => 0,
+            AlphaMode::Mask => 1,
+            AlphaMode::Blend => 2,
+        };
+
+        alpha_mode * 2 + self.double_sided as usize
+    }
+}
+
+/// The material descriptor set layout and a PBR pipeline for every `MaterialKey`, owned by the renderer.
+///
+/// The pipelines are created with the layouts of the per frame sets, the camera, lights and shadows in that order.
+pub struct MaterialPipelines {
+    pub set_layout: vk::DescriptorSetLayout,
+    /// Indexed by `MaterialKey::index`.
+    pub pipelines: Vec<RendererPipeline>,
+}
+
+impl MaterialPipelines {
+    /// How many textures a material binds, after its parameters.
+    pub const TEXTURE_COUNT: u32 = 5;
+
+    pub fn new(
+        device: &RendererDevice,
+        extent: vk::Extent2D,
+        render_pass: vk::RenderPass,
+        frame_set_layouts: [vk::DescriptorSetLayout; 3],
+        samples: vk::SampleCountFlags,
+        reverse_z: bool
+    ) -> Result<MaterialPipelines> {
+        let mut bindings = vec![
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .build()
+        ];
+
+        for binding in 1..=Self::TEXTURE_COUNT {
+            bindings.push(
+                vk::DescriptorSetLayoutBinding::builder()
+                    .binding(binding)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .descriptor_count(1)
+                    .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                    .build()
+            );
+        }
+
+        let set_layout_info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .bindings(&bindings);
+
+        let set_layout = unsafe {
+            device.logical_device.create_descriptor_set_layout(&set_layout_info, None)?
+        };
+
+        let mut material_pipelines = MaterialPipelines {
+            set_layout,
+            pipelines: Vec::with_capacity(MaterialKey::ALL.len()),
+        };
+
+        if let Err(err) = material_pipelines.rebuild(
+            device,
+            extent,
+            render_pass,
+            frame_set_layouts,
+            samples,
+            reverse_z
+        ) {
+            unsafe {
+                material_pipelines.cleanup(&device.logical_device);
+            };
+
+            return Err(err);
+        }
+
+        Ok(material_pipelines)
+    }
+
+    /// Creates the pipelines again, keeping the set layout so existing material descriptor sets stay usable.
+    pub fn rebuild(
+        &mut self,
+        device: &RendererDevice,
+        extent: vk::Extent2D,
+        render_pass: vk::RenderPass,
+        frame_set_layouts: [vk::DescriptorSetLayout; 3],
+        samples: vk::SampleCountFlags,
+        reverse_z: bool
+    ) -> Result<()> {
+        for pipeline in self.pipelines.drain(..) {
+            unsafe {
+                pipeline.cleanup(&device.logical_device);
+            };
+        }
+
+        let [camera_set_layout, lighting_set_layout, shadow_set_layout] = frame_set_layouts;
+
+        for key in MaterialKey::ALL {
+            self.pipelines.push(RendererPipeline::pbr(
+                device,
+                extent,
+                render_pass,
+                [camera_set_layout, self.set_layout, lighting_set_layout, shadow_set_layout],
+                key,
+                samples,
+                reverse_z,
+            )?);
+        }
+
+        Ok(())
+    }
+
+    pub fn get(&self, key: MaterialKey) -> &RendererPipeline {
+        &self.pipelines[key.index()]
+    }
+
+    pub unsafe fn cleanup(&self, device: &ash::Device) {
+        for pipeline in &self.pipelines {
+            pipeline.cleanup(device);
+        }
+
+        device.destroy_descriptor_set_layout(self.set_layout, None);
+    }
+}
+
+/// The GPU side of a list of materials: their parameters packed into one uniform buffer and a descriptor set each.
+///
+/// There is one more set than there are materials, at index `materials.len()`, for primitives without a material.
+/// Textures a material doesn't have are filled in with 1x1 ones that leave the factors as they are.
+pub struct RendererMaterials {
+    pub params: RendererBuffer,
+    /// Distance between two materials' parameters, rounded up to what the device allows as a uniform buffer offset.
+    pub stride: vk::DeviceSize,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_sets: Vec<vk::DescriptorSet>,
+    pub keys: Vec<MaterialKey>,
+    /// White, then a normal pointing straight out of the surface.
+    pub fallbacks: Vec<Texture>,
+}
+
+impl RendererMaterials {
+    pub fn new(
+        device: &RendererDevice,
+        command_pools: &CommandPools,
+        set_layout: vk::DescriptorSetLayout,
+        materials: &[Material],
+        textures: &[Texture]
+    ) -> Result<RendererMaterials> {
+        let alignment = device.properties.limits.min_uniform_buffer_offset_alignment.max(1);
+        let size = mem::size_of::<MaterialParams>() as vk::DeviceSize;
+
+        // the alignment is always a power of two:
+        let stride = (size + alignment - 1) & !(alignment - 1);
+
+        let count = materials.len() + 1;
+
+        let params = RendererBuffer::new(
+            device,
+            "material params",
+            stride * count as vk::DeviceSize,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            MemoryLocation::CpuToGpu,
+        )?;
+
+        let mut renderer_materials = RendererMaterials {
+            params,
+            stride,
+            descriptor_pool: vk::DescriptorPool::null(),
+            descriptor_sets: Vec::with_capacity(count),
+            keys: Vec::with_capacity(count),
+            fallbacks: Vec::with_capacity(2),
+        };
+
+        if let Err(err) = renderer_materials.upload(device, command_pools, set_layout, materials, textures) {
+            unsafe {
+                renderer_materials.cleanup(device);
+            };
+
+            return Err(err);
+        }
+
+        Ok(renderer_materials)
+    }
+
+    fn upload(
+        &mut self,
+        device: &RendererDevice,
+        command_pools: &CommandPools,
+        set_layout: vk::DescriptorSetLayout,
+        materials: &[Material],
+        textures: &[Texture]
+    ) -> Result<()> {
+        let default_material = Material::default();
+
+        let materials: Vec<&Material> = materials.iter()
+            .chain(Some(&default_material))
+            .collect();
+
+        // fallbacks:
+        let one_pixel = vk::Extent2D {
+            width: 1,
+            height: 1,
+        };
+
+        for (name, pixel) in [("white", [255, 255, 255, 255]), ("flat normal", [128, 128, 255, 255])] {
+            self.fallbacks.push(Texture::from_rgba8(
+                device,
+                command_pools,
+                name,
+                one_pixel,
+                &pixel,
+                false,
+                &SamplerDesc::default(),
+            )?);
+        }
+
+        // parameters:
+        for (i, material) in materials.iter().enumerate() {
+            let params = MaterialParams::new(material);
+
+            self.params.write_at(i * self.stride as usize, slice::from_ref(&params))?;
+
+            self.keys.push(MaterialKey::new(material));
+        }
+
+        // descriptor sets:
+        let count = materials.len() as u32;
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: count,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: count * MaterialPipelines::TEXTURE_COUNT,
+            },
+        ];
+
+        let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(count)
+            .pool_sizes(&pool_sizes);
+
+        self.descriptor_pool = unsafe {
+            device.logical_device.create_descriptor_pool(&pool_info, None)?
+        };
+
+        let set_layouts = vec![set_layout; materials.len()];
+
+        let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(self.descriptor_pool)
+            .set_layouts(&set_layouts);
+
+        self.descriptor_sets = unsafe {
+            device.logical_device.allocate_descriptor_sets(&allocate_info)?
+        };
+
+        for (i, material) in materials.iter().enumerate() {
+            let buffer_infos = [
+                vk::DescriptorBufferInfo {
+                    buffer: self.params.buffer,
+                    offset: i as vk::DeviceSize * self.stride,
+                    range: mem::size_of::<MaterialParams>() as vk::DeviceSize,
+                }
+            ];
+
+            let white = &self.fallbacks[0];
+            let flat_normal = &self.fallbacks[1];
+
+            let bound = [
+                (material.base_color_texture, white),
+                (material.metallic_roughness_texture, white),
+                (material.normal_texture, flat_normal),
+                (material.occlusion_texture, white),
+                (material.emissive_texture, white),
+            ];
+
+            let mut image_infos = Vec::with_capacity(bound.len());
+
+            for (texture_ref, fallback) in bound {
+                let texture = match texture_ref {
+                    None => fallback,
+                    Some(texture_ref) => match textures.get(texture_ref.texture) {
+                        None => anyhow::bail!("Material '{}' uses texture {}, but there are only {}", material.name, texture_ref.texture, textures.len()),
+                        Some(texture) => texture
+                    }
+                };
+
+                image_infos.push([
+                    vk::DescriptorImageInfo {
+                        sampler: texture.sampler,
+                        image_view: texture.image.image_view,
+                        image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    }
+                ]);
+            }
+
+            let mut writes = vec![
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(self.descriptor_sets[i])
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                    .buffer_info(&buffer_infos)
+                    .build()
+            ];
+
+            for (binding, image_info) in (1..).zip(&image_infos) {
+                writes.push(
+                    vk::WriteDescriptorSet::builder()
+                        .dst_set(self.descriptor_sets[i])
+                        .dst_binding(binding)
+                        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                        .image_info(image_info)
+                        .build()
+                );
+            }
+
+            unsafe {
+                device.logical_device.update_descriptor_sets(&writes, &[]);
+            };
+        }
+
+        Ok(())
+    }
+
+    /// The set to draw with, the default material's when there's none.
+    pub fn index(&self, material: Option<usize>) -> usize {
+        material.unwrap_or(self.descriptor_sets.len() - 1)
+    }
+
+    pub unsafe fn cleanup(&mut self, device: &RendererDevice) {
+        device.logical_device.destroy_descriptor_pool(self.descriptor_pool, None);
+
+        self.params.cleanup(device);
+
+        for texture in &mut self.fallbacks {
+            texture.cleanup(device);
+        }
+
+        self.descriptor_sets.clear();
+        self.fallbacks.clear();
+    }
+}
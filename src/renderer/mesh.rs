@@ -0,0 +1,323 @@
+use ash::vk;
+
+use crate::renderer::device::RendererDevice;
+use crate::renderer::buffer::RendererBuffer;
+use crate::renderer::command_pools::CommandPools;
+use crate::renderer::backend::Backend;
+
+use glam::{Mat4, Vec3, Vec4};
+
+use std::mem;
+use std::ops::Range;
+
+use anyhow::Result;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    /// `w` is the handedness of the bitangent, `cross(normal, tangent) * w`.
+    pub tangent: [f32; 4],
+    pub uv0: [f32; 2],
+    pub uv1: [f32; 2],
+    pub color: [f32; 4],
+}
+
+impl Vertex {
+    pub fn bindings() -> [vk::VertexInputBindingDescription; 1] {
+        [
+            vk::VertexInputBindingDescription {
+                binding: 0,
+                stride: mem::size_of::<Vertex>() as u32,
+                input_rate: vk::VertexInputRate::VERTEX,
+            }
+        ]
+    }
+
+    pub fn attributes() -> [vk::VertexInputAttributeDescription; 6] {
+        let attribute = |location: u32, format: vk::Format, offset: usize| vk::VertexInputAttributeDescription {
+            location,
+            binding: 0,
+            format,
+            offset: offset as u32,
+        };
+
+        let f32_size = mem::size_of::<f32>();
+
+        [
+            attribute(0, vk::Format::R32G32B32_SFLOAT, 0),
+            attribute(1, vk::Format::R32G32B32_SFLOAT, 3 * f32_size),
+            attribute(2, vk::Format::R32G32B32A32_SFLOAT, 6 * f32_size),
+            attribute(3, vk::Format::R32G32_SFLOAT, 10 * f32_size),
+            attribute(4, vk::Format::R32G32_SFLOAT, 12 * f32_size),
+            attribute(5, vk::Format::R32G32B32A32_SFLOAT, 14 * f32_size),
+        ]
+    }
+}
+
+/// What each instance of a mesh reads from the second vertex buffer binding.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InstanceData {
+    /// Applied after the model matrix of the draw.
+    pub model: Mat4,
+    /// Multiplies the vertex colors.
+    pub color: Vec4,
+}
+
+impl Default for InstanceData {
+    fn default() -> Self {
+        InstanceData {
+            model: Mat4::IDENTITY,
+            color: Vec4::ONE,
+        }
+    }
+}
+
+impl InstanceData {
+    pub fn new(model: Mat4, color: Vec4) -> InstanceData {
+        InstanceData {
+            model,
+            color,
+        }
+    }
+
+    pub fn bindings() -> [vk::VertexInputBindingDescription; 1] {
+        [
+            vk::VertexInputBindingDescription {
+                binding: 1,
+                stride: mem::size_of::<InstanceData>() as u32,
+                input_rate: vk::VertexInputRate::INSTANCE,
+            }
+        ]
+    }
+
+    /// The model matrix takes a location per column.
+    pub fn attributes() -> [vk::VertexInputAttributeDescription; 5] {
+        let attribute = |location: u32, offset: usize| vk::VertexInputAttributeDescription {
+            location,
+            binding: 1,
+            format: vk::Format::R32G32B32A32_SFLOAT,
+            offset: offset as u32,
+        };
+
+        let column_size = mem::size_of::<Vec4>();
+
+        [
+            attribute(6, 0),
+            attribute(7, column_size),
+            attribute(8, 2 * column_size),
+            attribute(9, 3 * column_size),
+            attribute(10, 4 * column_size),
+        ]
+    }
+}
+
+/// A sphere and a box around a mesh, in its own space.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Bounds {
+    pub center: Vec3,
+    pub radius: f32,
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Bounds {
+    /// The box around `vertices`, and the sphere around its center that reaches the furthest one.
+    pub fn new(vertices: &[Vertex]) -> Bounds {
+        if vertices.is_empty() {
+            return Bounds::default();
+        }
+
+        let (min, max) = vertices.iter()
+            .map(|vertex| Vec3::from(vertex.position))
+            .fold((Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)), |(min, max), position| {
+                (min.min(position), max.max(position))
+            });
+
+        let center = (min + max) * 0.5;
+
+        let radius = vertices.iter()
+            .map(|vertex| Vec3::from(vertex.position).distance(center))
+            .fold(0.0, f32::max);
+
+        Bounds {
+            center,
+            radius,
+            min,
+            max,
+        }
+    }
+}
+
+/// Draws that are read from a buffer instead of recorded, usually because the GPU wrote them.
+#[derive(Clone, Copy)]
+pub struct IndirectCommands<'a> {
+    /// Tightly packed `vk::DrawIndexedIndirectCommand`s, in a buffer with `INDIRECT_BUFFER` usage.
+    pub buffer: &'a RendererBuffer,
+    pub offset: vk::DeviceSize,
+    /// How many commands are drawn, or the most that are with `count`.
+    pub draw_count: u32,
+    /// A `u32` that says how many commands to draw, in a buffer with `INDIRECT_BUFFER` usage and at an offset.
+    ///
+    /// Needs `VK_KHR_draw_indirect_count`, see [`RendererDevice::draw_indirect_count`].
+    pub count: Option<(&'a RendererBuffer, vk::DeviceSize)>,
+}
+
+pub struct Mesh {
+    pub vertex_buffer: RendererBuffer,
+    pub index_buffer: RendererBuffer,
+    pub index_count: u32,
+    pub bounds: Bounds,
+}
+
+impl Mesh {
+    pub fn new<B: Backend>(backend: &B, vertices: &[Vertex], indices: &[u32]) -> Result<Mesh> {
+        let vertex_buffer = RendererBuffer::with_data(
+            backend,
+            "mesh vertices",
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            vertices
+        )?;
+
+        let index_buffer = RendererBuffer::with_data(
+            backend,
+            "mesh indices",
+            vk::BufferUsageFlags::INDEX_BUFFER,
+            indices
+        )?;
+
+        Ok(Mesh {
+            vertex_buffer,
+            index_buffer,
+            index_count: indices.len() as u32,
+            bounds: Bounds::new(vertices),
+        })
+    }
+
+    /// Like `new`, but the buffers live in device local memory and are filled through a staging buffer.
+    pub fn upload(
+        device: &RendererDevice,
+        command_pools: &CommandPools,
+        name: &str,
+        vertices: &[Vertex],
+        indices: &[u32]
+    ) -> Result<Mesh> {
+        let vertex_buffer = RendererBuffer::device_local(
+            device,
+            command_pools,
+            &format!("{} vertices", name),
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            vertices
+        )?;
+
+        let index_buffer = RendererBuffer::device_local(
+            device,
+            command_pools,
+            &format!("{} indices", name),
+            vk::BufferUsageFlags::INDEX_BUFFER,
+            indices
+        )?;
+
+        Ok(Mesh {
+            vertex_buffer,
+            index_buffer,
+            index_count: indices.len() as u32,
+            bounds: Bounds::new(vertices),
+        })
+    }
+
+    pub fn draw<B: Backend>(&self, backend: &B, command_buffer: vk::CommandBuffer) {
+        backend.cmd_bind_vertex_buffers(command_buffer, 0, &[self.vertex_buffer.buffer], &[0]);
+        backend.cmd_bind_index_buffer(command_buffer, self.index_buffer.buffer, 0, vk::IndexType::UINT32);
+
+        backend.cmd_draw_indexed(command_buffer, self.index_count, 1, 0, 0, 0);
+    }
+
+    /// Like `draw`, with `instances` of the `InstanceData` in `instance_buffer` bound to the second binding.
+    pub fn draw_instanced<B: Backend>(
+        &self,
+        backend: &B,
+        command_buffer: vk::CommandBuffer,
+        instance_buffer: vk::Buffer,
+        instances: Range<u32>
+    ) {
+        self.bind_instanced(backend, command_buffer, instance_buffer);
+
+        backend.cmd_draw_indexed(
+            command_buffer,
+            self.index_count,
+            instances.end - instances.start,
+            0,
+            0,
+            instances.start,
+        );
+    }
+
+    /// Binds the mesh and `instance_buffer` for draws that come from somewhere else, like an indirect buffer.
+    pub fn bind_instanced<B: Backend>(&self, backend: &B, command_buffer: vk::CommandBuffer, instance_buffer: vk::Buffer) {
+        backend.cmd_bind_vertex_buffers(
+            command_buffer,
+            0,
+            &[self.vertex_buffer.buffer, instance_buffer],
+            &[0, 0],
+        );
+        backend.cmd_bind_index_buffer(command_buffer, self.index_buffer.buffer, 0, vk::IndexType::UINT32);
+    }
+
+    /// Like `draw_instanced`, with the draws read from `commands`.
+    ///
+    /// Commands with a `first_instance` other than 0 need the device's `draw_indirect_first_instance` feature.
+    pub fn draw_indirect<B: Backend>(
+        &self,
+        backend: &B,
+        command_buffer: vk::CommandBuffer,
+        instance_buffer: vk::Buffer,
+        commands: &IndirectCommands
+    ) -> Result<()> {
+        let stride = mem::size_of::<vk::DrawIndexedIndirectCommand>() as u32;
+
+        self.bind_instanced(backend, command_buffer, instance_buffer);
+
+        match commands.count {
+            Some((count_buffer, count_offset)) => {
+                backend.cmd_draw_indexed_indirect_count(
+                    command_buffer,
+                    (commands.buffer.buffer, commands.offset),
+                    (count_buffer.buffer, count_offset),
+                    commands.draw_count,
+                    stride,
+                )?;
+            },
+            None if backend.features().multi_draw_indirect == vk::TRUE => {
+                backend.cmd_draw_indexed_indirect(
+                    command_buffer,
+                    commands.buffer.buffer,
+                    commands.offset,
+                    commands.draw_count,
+                    stride,
+                );
+            },
+            // one command at a time without multiDrawIndirect:
+            None => {
+                for i in 0..commands.draw_count {
+                    backend.cmd_draw_indexed_indirect(
+                        command_buffer,
+                        commands.buffer.buffer,
+                        commands.offset + (i * stride) as vk::DeviceSize,
+                        1,
+                        stride,
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub unsafe fn cleanup<B: Backend>(&mut self, backend: &B) {
+        backend.destroy_buffer(&mut self.vertex_buffer);
+        backend.destroy_buffer(&mut self.index_buffer);
+    }
+}
@@ -1,120 +1,2062 @@
+pub mod config;
 pub mod debug;
 pub mod device;
 pub mod window;
+pub mod view;
 pub mod swapchain;
 pub mod pipeline;
 pub mod shader;
 pub mod command_pools;
+pub mod diagnostics;
+pub mod backend;
+pub mod buffer;
+pub mod image;
+pub mod offscreen;
+pub mod target;
+pub mod frame;
+pub mod mesh;
+pub mod uniforms;
+pub mod texture;
+pub mod material;
+pub mod lights;
+pub mod shadows;
+pub mod hdr;
+pub mod post;
+pub mod overlay;
+pub mod text;
+pub mod batches;
+pub mod culling;
+pub mod recorder;
+pub mod capture;
+pub mod screenshot;
+pub mod video_filters;
 
+use config::{PresentMode, RendererConfig, WindowConfig, WindowMode};
 use debug::RendererDebug;
 use device::RendererDevice;
 use window::RendererWindow;
+use view::RendererView;
 use swapchain::RendererSwapchain;
 use pipeline::RendererPipeline;
 use command_pools::CommandPools;
+use diagnostics::RendererDiagnostics;
+use buffer::RendererBuffer;
+use offscreen::RendererOffscreen;
+use target::RenderTarget;
+use frame::{Frame, FramePasses};
+use image::RendererImage;
+use mesh::{IndirectCommands, InstanceData, Mesh};
+use uniforms::UniformBuffers;
+use material::{MaterialPipelines, RendererMaterials};
+use lights::{ClusteredLights, WorldLight};
+use shadows::{RendererShadows, ShadowCaster, ShadowSettings};
+use hdr::{RendererHdr, Tonemapping, HDR_FORMAT};
+use post::{RendererPostProcess, PostEffect, Lut};
+use overlay::{OverlayDrawData, RendererOverlay};
+use text::{RendererText, ATLAS_SIZE};
+use batches::{Batch, RendererBatches};
+use culling::{CullingSettings, RendererCulling};
+use recorder::Recorder;
+use backend::Backend;
+use capture::{CaptureSettings, RendererCapture};
+use screenshot::RendererScreenshots;
+
+use crate::camera::{Camera, CameraUniform};
+use crate::text::{Font, FontId, SdfAtlas, TextLayout};
 
 use ash::vk;
 use ash::extensions::ext;
 use ash::extensions::khr;
 
-use std::ffi;
+use gpu_allocator::MemoryLocation;
+
+use winit::event_loop::{EventLoop, EventLoopWindowTarget};
+use winit::window::WindowId;
+
+use glam::Mat4;
+
+use std::{ffi, mem, panic, thread};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+pub struct VulkanRenderer {
+    pub instance: ash::Instance,
+    pub debug: RendererDebug,
+    pub main_device: RendererDevice,
+    pub window: Option<RendererWindow>,
+    pub target: RenderTarget,
+    pub depth: RendererImage,
+    pub hdr: RendererHdr,
+    pub post: RendererPostProcess,
+    /// Created with [`VulkanRenderer::create_overlay`].
+    pub overlay: Option<RendererOverlay>,
+    /// Created with the first font, see [`VulkanRenderer::add_font`].
+    pub text: Option<RendererText>,
+    pub render_pass: vk::RenderPass,
+    pub graphics_pipeline: RendererPipeline,
+    pub camera_uniforms: UniformBuffers,
+    pub lights: ClusteredLights,
+    pub shadows: RendererShadows,
+    pub batches: RendererBatches,
+    pub culling: RendererCulling,
+    pub mesh_pipeline: RendererPipeline,
+    pub material_pipelines: MaterialPipelines,
+    pub command_pools: CommandPools,
+    pub graphics_command_buffers: Vec<vk::CommandBuffer>,
+    /// The secondary command buffers of the frame being recorded, in the order they're executed.
+    pub secondary_command_buffers: Vec<vk::CommandBuffer>,
+    pub diagnostics: RendererDiagnostics,
+    /// Started with [`VulkanRenderer::start_capture`].
+    pub capture: Option<RendererCapture>,
+    /// Taken with [`VulkanRenderer::capture_screenshot`].
+    pub screenshots: RendererScreenshots,
+    /// Per pixel of the scene's color and depth, from the config.
+    pub samples: vk::SampleCountFlags,
+    pub reverse_z: bool,
+    /// What the renderer was created with, the device is created again with it after it's lost.
+    pub config: RendererConfig,
+    /// The windows opened with [`VulkanRenderer::create_window`].
+    pub views: Vec<RendererView>,
+    /// The view whose frame is being recorded, its parts are swapped with the renderer's until it ends.
+    active_view: Option<usize>,
+    /// Set with [`VulkanRenderer::set_error_hook`].
+    error_hook: Box<dyn FnMut(&anyhow::Error) + Send>,
+    /// Whether the device and everything created from it were torn down and couldn't be created again, which
+    /// leaves the instance and the windows.
+    device_destroyed: bool,
+    /// The overlay's font atlas from when the device was torn down, until it's uploaded to a new one.
+    lost_overlay_font: Option<(vk::Extent2D, Vec<u8>)>,
+    /// The text's rasterized glyphs, likewise.
+    lost_text_atlas: Option<SdfAtlas>,
+}
+
+struct DeviceObjects {
+    main_device: RendererDevice,
+    target: RenderTarget,
+    depth: RendererImage,
+    hdr: RendererHdr,
+    post: RendererPostProcess,
+    render_pass: vk::RenderPass,
+    graphics_pipeline: RendererPipeline,
+    camera_uniforms: UniformBuffers,
+    lights: ClusteredLights,
+    shadows: RendererShadows,
+    batches: RendererBatches,
+    culling: RendererCulling,
+    mesh_pipeline: RendererPipeline,
+    material_pipelines: MaterialPipelines,
+    command_pools: CommandPools,
+    graphics_command_buffers: Vec<vk::CommandBuffer>,
+    diagnostics: RendererDiagnostics,
+}
+
+/// What `create_device_objects` created so far, so it can be torn down again when creating the rest fails.
+#[derive(Default)]
+struct PartialDeviceObjects {
+    target: Option<RenderTarget>,
+    depth: Option<RendererImage>,
+    hdr: Option<RendererHdr>,
+    post: Option<RendererPostProcess>,
+    render_pass: Option<vk::RenderPass>,
+    graphics_pipeline: Option<RendererPipeline>,
+    camera_uniforms: Option<UniformBuffers>,
+    lights: Option<ClusteredLights>,
+    shadows: Option<RendererShadows>,
+    batches: Option<RendererBatches>,
+    culling: Option<RendererCulling>,
+    mesh_pipeline: Option<RendererPipeline>,
+    material_pipelines: Option<MaterialPipelines>,
+    command_pools: Option<CommandPools>,
+    diagnostics: Option<RendererDiagnostics>,
+}
+
+impl PartialDeviceObjects {
+    /// In the order `cleanup_device` destroys them.
+    unsafe fn cleanup(mut self, device: &RendererDevice) {
+        if let Some(diagnostics) = self.diagnostics.take() {
+            diagnostics.cleanup(device);
+        }
+
+        if let Some(command_pools) = self.command_pools.take() {
+            command_pools.cleanup(device);
+        }
+
+        if let Some(graphics_pipeline) = self.graphics_pipeline.take() {
+            graphics_pipeline.cleanup(&device.logical_device);
+        }
+
+        if let Some(mesh_pipeline) = self.mesh_pipeline.take() {
+            mesh_pipeline.cleanup(&device.logical_device);
+        }
+
+        if let Some(material_pipelines) = self.material_pipelines.take() {
+            material_pipelines.cleanup(&device.logical_device);
+        }
+
+        if let Some(mut lights) = self.lights.take() {
+            lights.cleanup(device);
+        }
+
+        if let Some(mut shadows) = self.shadows.take() {
+            shadows.cleanup(device);
+        }
+
+        if let Some(mut batches) = self.batches.take() {
+            batches.cleanup(device);
+        }
+
+        if let Some(mut culling) = self.culling.take() {
+            culling.cleanup(device);
+        }
+
+        if let Some(mut camera_uniforms) = self.camera_uniforms.take() {
+            camera_uniforms.cleanup(device);
+        }
+
+        if let Some(render_pass) = self.render_pass.take() {
+            device.logical_device.destroy_render_pass(render_pass, None);
+        }
+
+        if let Some(mut post) = self.post.take() {
+            post.cleanup(device);
+        }
+
+        if let Some(mut hdr) = self.hdr.take() {
+            hdr.cleanup(device);
+        }
+
+        if let Some(mut target) = self.target.take() {
+            target.cleanup(device);
+        }
+
+        if let Some(mut depth) = self.depth.take() {
+            depth.cleanup(device);
+        }
+    }
+}
+
+impl VulkanRenderer {
+    fn used_layer_names(validation: bool) -> Vec<ffi::CString> {
+        if !validation {
+            return vec![];
+        }
+
+        vec![
+            ffi::CString::new("VK_LAYER_KHRONOS_validation").unwrap()
+        ]
+    }
+
+    fn used_extensions() -> Vec<*const i8> {
+        vec![
+            ext::DebugUtils::name().as_ptr(),
+        ]
+    }
+
+    /// Creates a renderer that presents to a new window, set up by the defaults and the environment.
+    pub fn new() -> Result<Self> {
+        Self::with_config(RendererConfig::from_env()?)
+    }
+
+    /// Like `new`, but presents in HDR10 when the window's surface offers it.
+    ///
+    /// The instance is created with `VK_EXT_swapchain_colorspace`, so this fails where the driver doesn't have it.
+    pub fn new_hdr() -> Result<Self> {
+        Self::with_config(RendererConfig {
+            hdr: true,
+            ..RendererConfig::from_env()?
+        })
+    }
+
+    /// Creates a renderer that presents to a new window, set up by `config`.
+    pub fn with_config(config: RendererConfig) -> Result<Self> {
+        config.validate()?;
+
+        let (event_loop, window) = RendererWindow::create_window(&config.window)?;
+
+        let used_layer_names = Self::used_layer_names(config.validation);
+
+        let used_layers = used_layer_names.iter()
+            .map(|layer_name| layer_name.as_ptr())
+            .collect();
+
+        let mut used_extensions = Self::used_extensions();
+
+        used_extensions.push(khr::Surface::name().as_ptr());
+
+        if config.hdr {
+            used_extensions.push(vk::ExtSwapchainColorspaceFn::name().as_ptr());
+        }
+
+        for ext_name in ash_window::enumerate_required_extensions(&window)? {
+            used_extensions.push(ext_name.as_ptr());
+        }
+
+        let entry = ash::Entry::linked();
+
+        let instance = Self::create_instance(&entry, &used_layers, &used_extensions, &config)?;
+
+        let mut window = RendererWindow::new(Some(event_loop), window, &entry, &instance)?;
+
+        window.hdr = config.hdr;
+        window.present_mode = config.present_mode.into();
+        window.image_count = config.frames_in_flight;
+
+        Self::with_instance(&entry, instance, &used_layers, Some(window), vk::Extent2D::default(), config)
+    }
+
+    /// Creates a renderer without a window that renders into an offscreen image of the given size.
+    pub fn headless(extent: vk::Extent2D) -> Result<Self> {
+        Self::headless_with_config(extent, RendererConfig::from_env()?)
+    }
+
+    /// Like `headless`, set up by `config`, of which the window and presentation settings don't matter.
+    pub fn headless_with_config(extent: vk::Extent2D, config: RendererConfig) -> Result<Self> {
+        config.validate()?;
+
+        let used_layer_names = Self::used_layer_names(config.validation);
+
+        let used_layers = used_layer_names.iter()
+            .map(|layer_name| layer_name.as_ptr())
+            .collect();
+
+        let used_extensions = Self::used_extensions();
+
+        let entry = ash::Entry::linked();
+
+        let instance = Self::create_instance(&entry, &used_layers, &used_extensions, &config)?;
+
+        Self::with_instance(&entry, instance, &used_layers, None, extent, config)
+    }
+
+    fn with_instance(
+        entry: &ash::Entry,
+        instance: ash::Instance,
+        used_layers: &Vec<*const i8>,
+        window: Option<RendererWindow>,
+        extent: vk::Extent2D,
+        config: RendererConfig
+    ) -> Result<Self> {
+        let debug = RendererDebug::new(entry, &instance)?;
+
+        let reverse_z = false;
+
+        let DeviceObjects {
+            main_device,
+            target,
+            depth,
+            hdr,
+            post,
+            render_pass,
+            graphics_pipeline,
+            camera_uniforms,
+            lights,
+            shadows,
+            batches,
+            culling,
+            mesh_pipeline,
+            material_pipelines,
+            command_pools,
+            graphics_command_buffers,
+            diagnostics,
+        } = Self::create_device_objects(&instance, used_layers, window.as_ref(), extent, reverse_z, &config)?;
+
+        let screenshots = RendererScreenshots::new(target.image_count() as usize);
+
+        Ok(Self {
+            instance,
+            debug,
+            main_device,
+            window,
+            target,
+            depth,
+            hdr,
+            post,
+            overlay: None,
+            text: None,
+            render_pass,
+            graphics_pipeline,
+            camera_uniforms,
+            lights,
+            shadows,
+            batches,
+            culling,
+            mesh_pipeline,
+            material_pipelines,
+            command_pools,
+            graphics_command_buffers,
+            secondary_command_buffers: vec![],
+            diagnostics,
+            capture: None,
+            screenshots,
+            samples: config.sample_count(),
+            reverse_z,
+            config,
+            views: vec![],
+            active_view: None,
+            error_hook: Box::new(|err| eprintln!("{:?}", err)),
+            device_destroyed: false,
+            lost_overlay_font: None,
+            lost_text_atlas: None,
+        })
+    }
+
+    /// Creates the device and everything that depends on it, which is everything but the instance and the window.
+    ///
+    /// What was created is destroyed again when creating the rest fails.
+    fn create_device_objects(
+        instance: &ash::Instance,
+        used_layers: &Vec<*const i8>,
+        window: Option<&RendererWindow>,
+        extent: vk::Extent2D,
+        reverse_z: bool,
+        config: &RendererConfig
+    ) -> Result<DeviceObjects> {
+        let mut main_device = match RendererDevice::new(instance, used_layers, config.device.as_deref())? {
+            None => anyhow::bail!("No fitting GPU found"),
+            Some(dev) => dev
+        };
+
+        let mut partial = PartialDeviceObjects::default();
+
+        let created = Self::create_on_device(
+            instance,
+            &main_device,
+            &mut partial,
+            window,
+            extent,
+            config.sample_count(),
+            reverse_z,
+        );
+
+        let graphics_command_buffers = match created {
+            Err(err) => {
+                unsafe {
+                    partial.cleanup(&main_device);
+
+                    main_device.cleanup();
+                };
+
+                return Err(err);
+            },
+            Ok(graphics_command_buffers) => graphics_command_buffers
+        };
+
+        // everything was created when that succeeded:
+        Ok(DeviceObjects {
+            main_device,
+            target: partial.target.take().unwrap(),
+            depth: partial.depth.take().unwrap(),
+            hdr: partial.hdr.take().unwrap(),
+            post: partial.post.take().unwrap(),
+            render_pass: partial.render_pass.take().unwrap(),
+            graphics_pipeline: partial.graphics_pipeline.take().unwrap(),
+            camera_uniforms: partial.camera_uniforms.take().unwrap(),
+            lights: partial.lights.take().unwrap(),
+            shadows: partial.shadows.take().unwrap(),
+            batches: partial.batches.take().unwrap(),
+            culling: partial.culling.take().unwrap(),
+            mesh_pipeline: partial.mesh_pipeline.take().unwrap(),
+            material_pipelines: partial.material_pipelines.take().unwrap(),
+            command_pools: partial.command_pools.take().unwrap(),
+            graphics_command_buffers,
+            diagnostics: partial.diagnostics.take().unwrap(),
+        })
+    }
+
+    /// Creates everything but the device into `partial`, returns the frames' command buffers.
+    fn create_on_device(
+        instance: &ash::Instance,
+        main_device: &RendererDevice,
+        partial: &mut PartialDeviceObjects,
+        window: Option<&RendererWindow>,
+        extent: vk::Extent2D,
+        samples: vk::SampleCountFlags,
+        reverse_z: bool
+    ) -> Result<Vec<vk::CommandBuffer>> {
+        // the scene's color and depth are multisampled, and depth is sampled for the depth pyramid too:
+        let limits = main_device.properties.limits;
+
+        let sample_counts = limits.framebuffer_color_sample_counts
+            & limits.framebuffer_depth_sample_counts
+            & limits.sampled_image_depth_sample_counts;
+
+        if !sample_counts.contains(samples) {
+            anyhow::bail!(
+                "The device can't render with {} samples per pixel, it can with {:?}",
+                samples.as_raw(),
+                sample_counts,
+            );
+        }
+
+        let target = partial.target.insert(match window {
+            Some(window) => RenderTarget::Swapchain(RendererSwapchain::new(instance, main_device, window)?),
+            None => RenderTarget::Offscreen(RendererOffscreen::new(main_device, extent)?),
+        });
+
+        let target_extent = target.extent();
+        let image_count = target.image_count();
+
+        let depth = partial.depth.insert(Self::create_depth(
+            main_device,
+            target_extent,
+            Self::pick_depth_format(
+                instance,
+                main_device,
+                vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT | vk::FormatFeatureFlags::SAMPLED_IMAGE,
+            )?,
+            samples,
+        )?);
+
+        let render_pass = *partial.render_pass.insert(Self::create_render_pass(main_device, depth.format, samples)?);
+
+        let hdr = RendererHdr::new(
+            main_device,
+            partial.target.as_ref().unwrap(),
+            render_pass,
+            depth.image_view,
+            samples,
+        )?;
+
+        let hdr = partial.hdr.insert(hdr);
+
+        partial.target.as_mut().unwrap().create_framebuffers(main_device, hdr.render_pass)?;
+
+        partial.graphics_pipeline = Some(RendererPipeline::new(main_device, target_extent, render_pass, samples)?);
+
+        let camera_uniforms = partial.camera_uniforms.insert(UniformBuffers::new(
+            main_device,
+            "camera",
+            mem::size_of::<CameraUniform>() as vk::DeviceSize,
+            image_count as usize,
+            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT | vk::ShaderStageFlags::COMPUTE,
+        )?);
+
+        let camera_set_layout = camera_uniforms.set_layout;
+
+        let lights = partial.lights.insert(ClusteredLights::new(main_device, image_count as usize, camera_set_layout)?);
+
+        let lights_set_layout = lights.set_layout;
+
+        let command_pools = partial.command_pools.insert(CommandPools::new(main_device, image_count as usize)?);
+
+        partial.post = Some(RendererPostProcess::new(
+            main_device,
+            command_pools,
+            partial.hdr.as_ref().unwrap(),
+            target_extent,
+        )?);
+
+        let shadows = partial.shadows.insert(RendererShadows::new(
+            main_device,
+            command_pools,
+            image_count as usize,
+            Self::pick_depth_format(
+                instance,
+                main_device,
+                vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT
+                    | vk::FormatFeatureFlags::SAMPLED_IMAGE
+                    | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR,
+            )?,
+            ShadowSettings::default(),
+        )?);
+
+        let shadows_set_layout = shadows.set_layout;
+
+        partial.batches = Some(RendererBatches::new(main_device, image_count as usize)?);
+
+        partial.culling = Some(RendererCulling::new(
+            main_device,
+            command_pools,
+            image_count as usize,
+            partial.depth.as_ref().unwrap(),
+        )?);
+
+        partial.mesh_pipeline = Some(RendererPipeline::mesh(
+            main_device,
+            target_extent,
+            render_pass,
+            camera_set_layout,
+            samples,
+            reverse_z,
+        )?);
+
+        partial.material_pipelines = Some(MaterialPipelines::new(
+            main_device,
+            target_extent,
+            render_pass,
+            [camera_set_layout, lights_set_layout, shadows_set_layout],
+            samples,
+            reverse_z,
+        )?);
+
+        // freed along with the pool:
+        let graphics_command_buffers = CommandPools::create_command_buffers(
+            main_device,
+            command_pools.graphics,
+            image_count,
+        )?;
+
+        partial.diagnostics = Some(RendererDiagnostics::new(instance, main_device, graphics_command_buffers.len())?);
+
+        Ok(graphics_command_buffers)
+    }
+
+    // sampled too, the depth pyramid for occlusion culling is built from it:
+    fn create_depth(
+        device: &RendererDevice,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        samples: vk::SampleCountFlags
+    ) -> Result<RendererImage> {
+        RendererImage::multisampled(
+            device,
+            "depth",
+            extent,
+            format,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            samples,
+        )
+    }
+
+    /// The most precise depth format that supports `features`.
+    fn pick_depth_format(
+        instance: &ash::Instance,
+        device: &RendererDevice,
+        features: vk::FormatFeatureFlags
+    ) -> Result<vk::Format> {
+        let candidates = [
+            vk::Format::D32_SFLOAT,
+            vk::Format::X8_D24_UNORM_PACK32,
+            vk::Format::D16_UNORM,
+        ];
+
+        for format in candidates {
+            let props = unsafe {
+                instance.get_physical_device_format_properties(device.physical_device, format)
+            };
+
+            if props.optimal_tiling_features.contains(features) {
+                return Ok(format);
+            }
+        }
+
+        anyhow::bail!("No supported depth format found")
+    }
+
+    /// Hands out the window's event loop, can only be done once and not at all when running headless.
+    pub fn acquire_event_loop(&mut self) -> Result<EventLoop<()>> {
+        match &mut self.window {
+            None => anyhow::bail!("A headless renderer has no event loop"),
+            Some(window) => window.acquire_event_loop()
+        }
+    }
+
+    /// Tears down the device and everything created from it, then creates all of it again.
+    ///
+    /// The instance, window and surface survive, which is what makes recovering from a lost device possible. When
+    /// creating the device fails, the renderer is left without one: it can still be dropped and this tried again,
+    /// but frames fail to begin.
+    pub fn recreate_device(&mut self) -> Result<()> {
+        if self.active_view.is_some() {
+            anyhow::bail!("The device can't be created again while a window's frame is recorded");
+        }
+
+        let extent = self.target.extent();
+
+        // the overlay's font atlas is uploaded again, and so are the glyphs that were rasterized. They're kept until
+        // they are, creating the device can fail and be tried again:
+        if let Some(overlay) = &mut self.overlay {
+            self.lost_overlay_font = Some((overlay.font_extent, mem::take(&mut overlay.font_pixels)));
+        }
+
+        if let Some(text) = &mut self.text {
+            self.lost_text_atlas = Some(mem::replace(&mut text.atlas, SdfAtlas::new(0, 0)));
+        }
+
+        if !self.device_destroyed {
+            unsafe {
+                // a lost device fails to wait, which is fine since nothing is running on it anymore:
+                let _ = self.main_device.logical_device.device_wait_idle();
+
+                self.cleanup_device();
+            }
+
+            // what's left of it mustn't be destroyed again, until there's a new device:
+            self.device_destroyed = true;
+        }
+
+        let used_layer_names = Self::used_layer_names(self.config.validation);
+
+        let used_layers = used_layer_names.iter()
+            .map(|layer_name| layer_name.as_ptr())
+            .collect();
+
+        let DeviceObjects {
+            main_device,
+            target,
+            depth,
+            hdr,
+            post,
+            render_pass,
+            graphics_pipeline,
+            camera_uniforms,
+            lights,
+            shadows,
+            batches,
+            culling,
+            mesh_pipeline,
+            material_pipelines,
+            command_pools,
+            graphics_command_buffers,
+            diagnostics,
+        } = Self::create_device_objects(
+            &self.instance,
+            &used_layers,
+            self.window.as_ref(),
+            extent,
+            self.reverse_z,
+            &self.config,
+        )?;
+
+        self.main_device = main_device;
+        self.target = target;
+        self.depth = depth;
+        self.hdr = hdr;
+        self.post = post;
+        self.render_pass = render_pass;
+        self.graphics_pipeline = graphics_pipeline;
+        self.camera_uniforms = camera_uniforms;
+        self.lights = lights;
+        self.shadows = shadows;
+        self.batches = batches;
+        self.culling = culling;
+        self.mesh_pipeline = mesh_pipeline;
+        self.material_pipelines = material_pipelines;
+        self.command_pools = command_pools;
+        self.graphics_command_buffers = graphics_command_buffers;
+        self.secondary_command_buffers.clear();
+        self.diagnostics = diagnostics;
+
+        self.device_destroyed = false;
+
+        // the views' windows survive too, their parts were torn down with the device:
+        let mut views = mem::take(&mut self.views).into_iter();
+
+        while let Some(view) = views.next() {
+            let created = match &view.window {
+                None => self.create_offscreen(view.target.extent(), view.window_config),
+                Some(window) => self.create_window_view(window, view.window_config),
+            };
+
+            let mut created = match created {
+                Err(err) => {
+                    // the windows that are left are closed, the renderer doesn't have them anymore:
+                    for window in [view.window].into_iter().chain(views.map(|view| view.window)).flatten() {
+                        unsafe {
+                            window.cleanup();
+                        };
+                    }
+
+                    return Err(err);
+                },
+                Ok(created) => created
+            };
+
+            created.window = view.window;
+            created.frame_interval = view.frame_interval;
+
+            self.views.push(created);
+        }
+
+        if let Some((font_extent, font_pixels)) = &self.lost_overlay_font {
+            self.create_overlay(*font_extent, font_pixels.clone())?;
+
+            self.lost_overlay_font = None;
+        }
+
+        if let Some(atlas) = &self.lost_text_atlas {
+            self.text = Some(self.create_text(atlas.clone())?);
+
+            self.lost_text_atlas = None;
+        }
+
+        Ok(())
+    }
+
+    /// Opens another window that's drawn with the same device and resources, with frames of its own through
+    /// [`VulkanRenderer::begin_window_frame`]. It presents like the renderer's window, in HDR10 if that does.
+    ///
+    /// `event_loop` is the renderer's, before it runs or while it does.
+    pub fn create_window(&mut self, event_loop: &EventLoopWindowTarget<()>, config: WindowConfig) -> Result<WindowId> {
+        if self.active_view.is_some() {
+            anyhow::bail!("Windows can't be opened while a window's frame is recorded");
+        }
+
+        let main_window = match &self.window {
+            None => anyhow::bail!("A headless renderer can't open windows, its instance can't present"),
+            Some(window) => window
+        };
+
+        config.validate()?;
+
+        let window = RendererWindow::build_window(event_loop, &config)?;
+
+        let entry = ash::Entry::linked();
+
+        let mut window = RendererWindow::new(None, window, &entry, &self.instance)?;
+
+        window.hdr = main_window.hdr;
+        window.present_mode = main_window.present_mode;
+        window.image_count = main_window.image_count;
+
+        let mut view = match self.create_window_view(&window, config) {
+            Err(err) => {
+                unsafe {
+                    window.cleanup();
+                };
+
+                return Err(err);
+            },
+            Ok(view) => view
+        };
+
+        let id = window.window.id();
+
+        view.window = Some(window);
+
+        self.views.push(view);
+
+        Ok(id)
+    }
+
+    /// Adds a view that renders into an offscreen image of `extent` instead of a window, drawn with the same device
+    /// and resources. Its frames are begun with [`VulkanRenderer::begin_view_frame`] and read back with
+    /// [`VulkanRenderer::read_view_pixels`], headless renderers can have them too.
+    ///
+    /// Returns where it is in `views`, closing a window that was opened before it moves it up by one.
+    pub fn create_offscreen_view(&mut self, extent: vk::Extent2D) -> Result<usize> {
+        if self.active_view.is_some() {
+            anyhow::bail!("Views can't be added while a window's frame is recorded");
+        }
+
+        let view = self.create_offscreen(extent, self.config.window.clone())?;
+
+        self.views.push(view);
+
+        Ok(self.views.len() - 1)
+    }
+
+    /// Closes a window opened with [`VulkanRenderer::create_window`], waits for the device to be idle.
+    pub fn destroy_window(&mut self, id: WindowId) -> Result<()> {
+        if self.active_view.is_some() {
+            anyhow::bail!("Windows can't be closed while a window's frame is recorded");
+        }
+
+        let index = self.view_index(id)?;
+
+        unsafe {
+            self.main_device.logical_device.device_wait_idle()?;
+        };
+
+        let mut view = self.views.remove(index);
+
+        unsafe {
+            view.cleanup(&self.main_device);
+
+            if let Some(window) = &view.window {
+                window.cleanup();
+            }
+        };
+
+        Ok(())
+    }
+
+    /// The view of a window opened with [`VulkanRenderer::create_window`].
+    pub fn view(&self, id: WindowId) -> Option<&RendererView> {
+        self.views.iter().find(|view| view.id() == Some(id))
+    }
+
+    pub fn view_mut(&mut self, id: WindowId) -> Option<&mut RendererView> {
+        self.views.iter_mut().find(|view| view.id() == Some(id))
+    }
+
+    /// Like `begin_frame`, for a window opened with [`VulkanRenderer::create_window`]. Until the frame ends,
+    /// everything that draws or changes settings does for that window.
+    ///
+    /// Captures only record the renderer's own window.
+    pub fn begin_window_frame(&mut self, id: WindowId) -> Result<Frame> {
+        let index = self.view_index(id)?;
+
+        self.begin_view_frame(index)
+    }
+
+    /// Like `begin_window_frame`, for the view at `index` in `views`, which is how offscreen views are drawn.
+    pub fn begin_view_frame(&mut self, index: usize) -> Result<Frame> {
+        if self.active_view.is_some() {
+            anyhow::bail!("A window's frame is being recorded already");
+        }
+
+        if index >= self.views.len() {
+            anyhow::bail!("There's no view {}, there are {}", index, self.views.len());
+        }
+
+        self.views[index].last_frame = Some(Instant::now());
+
+        self.swap_view(index);
+        self.active_view = Some(index);
+
+        let frame = self.begin_frame();
+
+        if frame.is_err() {
+            self.swap_view(index);
+            self.active_view = None;
+        }
+
+        frame
+    }
+
+    fn view_index(&self, id: WindowId) -> Result<usize> {
+        match self.views.iter().position(|view| view.id() == Some(id)) {
+            None => anyhow::bail!("There's no window {:?}", id),
+            Some(index) => Ok(index)
+        }
+    }
+
+    /// Trades the view's parts for the renderer's, and back when it's done again.
+    fn swap_view(&mut self, index: usize) {
+        let view = &mut self.views[index];
+
+        mem::swap(&mut self.window, &mut view.window);
+        mem::swap(&mut self.config.window, &mut view.window_config);
+        mem::swap(&mut self.target, &mut view.target);
+        mem::swap(&mut self.depth, &mut view.depth);
+        mem::swap(&mut self.hdr, &mut view.hdr);
+        mem::swap(&mut self.post, &mut view.post);
+        mem::swap(&mut self.culling, &mut view.culling);
+        mem::swap(&mut self.graphics_pipeline, &mut view.graphics_pipeline);
+        mem::swap(&mut self.mesh_pipeline, &mut view.mesh_pipeline);
+        mem::swap(&mut self.material_pipelines.pipelines, &mut view.material_pipelines);
+    }
+
+    /// The swapchain and everything with its size for another window, sharing the rest with the renderer's. The
+    /// view is handed the window once it's made.
+    fn create_window_view(&self, window: &RendererWindow, window_config: WindowConfig) -> Result<RendererView> {
+        let mut swapchain = RendererSwapchain::new(&self.instance, &self.main_device, window)?;
+
+        // the per frame resources were made for as many frames as the renderer's window has images:
+        swapchain.image_count = swapchain.image_count.min(self.target.image_count());
+
+        self.create_view(RenderTarget::Swapchain(swapchain), window_config)
+    }
+
+    /// An offscreen image and everything with its size, sharing the rest with the renderer's window.
+    fn create_offscreen(&self, extent: vk::Extent2D, window_config: WindowConfig) -> Result<RendererView> {
+        let offscreen = RendererOffscreen::new(&self.main_device, extent)?;
+
+        self.create_view(RenderTarget::Offscreen(offscreen), window_config)
+    }
+
+    fn create_view(&self, target: RenderTarget, window_config: WindowConfig) -> Result<RendererView> {
+        let mut view = RendererView::new(target, window_config);
+
+        let post = self.create_sized(&mut view)
+            .and_then(|()| RendererPostProcess::new(&self.main_device, &self.command_pools, &view.hdr, view.target.extent()));
+
+        match post {
+            Err(err) => {
+                unsafe {
+                    view.cleanup(&self.main_device);
+                };
+
+                Err(err)
+            },
+            Ok(post) => {
+                view.post = post;
+
+                Ok(view)
+            }
+        }
+    }
+
+    /// Everything with the size of `view`'s target but the post-process targets, into `view`. What was made before
+    /// a failure is left there to be cleaned up with it.
+    fn create_sized(&self, view: &mut RendererView) -> Result<()> {
+        let image_count = self.target.image_count();
+
+        let extent = view.target.extent();
+
+        view.depth = Self::create_depth(&self.main_device, extent, self.depth.format, self.samples)?;
+
+        view.hdr = RendererHdr::new(
+            &self.main_device,
+            &view.target,
+            self.render_pass,
+            view.depth.image_view,
+            self.samples,
+        )?;
+
+        view.target.create_framebuffers(&self.main_device, view.hdr.render_pass)?;
+
+        view.culling = RendererCulling::new(&self.main_device, &self.command_pools, image_count as usize, &view.depth)?;
+
+        view.graphics_pipeline = RendererPipeline::new(&self.main_device, extent, self.render_pass, self.samples)?;
+
+        view.mesh_pipeline = RendererPipeline::mesh(
+            &self.main_device,
+            extent,
+            self.render_pass,
+            self.camera_uniforms.set_layout,
+            self.samples,
+            self.reverse_z,
+        )?;
+
+        // with the renderer's set layout, so materials can be drawn in any window:
+        let mut material_pipelines = MaterialPipelines {
+            set_layout: self.material_pipelines.set_layout,
+            pipelines: vec![],
+        };
+
+        let rebuilt = material_pipelines.rebuild(
+            &self.main_device,
+            extent,
+            self.render_pass,
+            [self.camera_uniforms.set_layout, self.lights.set_layout, self.shadows.set_layout],
+            self.samples,
+            self.reverse_z,
+        );
+
+        view.material_pipelines = material_pipelines.pipelines;
+
+        rebuilt
+    }
+
+    /// Creates the swapchain again for the window's size, with everything that depends on it, keeping the
+    /// effects, the tonemapping, the LUT and the culling settings.
+    ///
+    /// Happens by itself before the next frame when the window is resized, or when presenting finds the swapchain
+    /// doesn't fit anymore. Waits for the device to be idle. A capture of the renderer's window ends, its frames all
+    /// have the same size.
+    pub fn recreate_swapchain(&mut self) -> Result<()> {
+        let window = match &mut self.window {
+            None => anyhow::bail!("A headless renderer has no swapchain"),
+            Some(window) => window
+        };
+
+        // a minimized window can't be presented to, the swapchain is created again once it's shown:
+        if window.is_minimized() {
+            window.outdated = true;
+
+            return Ok(());
+        }
+
+        window.outdated = false;
+
+        unsafe {
+            self.main_device.logical_device.device_wait_idle()?;
+        };
+
+        // only the renderer's own window is captured:
+        if self.capture.is_some() && self.active_view.is_none() {
+            let frames = self.stop_capture()?;
+
+            let stopped = anyhow::anyhow!("The window's size changed, the capture was stopped after {} frames", frames);
+
+            self.report(stopped);
+        }
+
+        // the per frame resources were made for as many frames as there were images:
+        let image_count = self.target.image_count();
+
+        let old_swapchain = match &self.target {
+            RenderTarget::Swapchain(swapchain) => swapchain.swapchain,
+            RenderTarget::Offscreen(_) => vk::SwapchainKHR::null()
+        };
+
+        let window = self.window.as_ref().unwrap();
+
+        let mut swapchain = RendererSwapchain::replacing(&self.instance, &self.main_device, window, old_swapchain)?;
+
+        swapchain.image_count = swapchain.image_count.min(image_count);
+
+        // everything new is made before the old is destroyed, a failure leaves the old in place to be cleaned up:
+        let mut sized = RendererView::new(RenderTarget::Swapchain(swapchain), self.config.window.clone());
+
+        if let Err(err) = self.create_sized(&mut sized) {
+            unsafe {
+                sized.cleanup(&self.main_device);
+            };
+
+            return Err(err);
+        }
+
+        sized.hdr.tonemapping = self.hdr.tonemapping;
+        sized.culling.settings = self.culling.settings;
+
+        mem::swap(&mut self.target, &mut sized.target);
+        mem::swap(&mut self.depth, &mut sized.depth);
+        mem::swap(&mut self.hdr, &mut sized.hdr);
+        mem::swap(&mut self.culling, &mut sized.culling);
+        mem::swap(&mut self.graphics_pipeline, &mut sized.graphics_pipeline);
+        mem::swap(&mut self.mesh_pipeline, &mut sized.mesh_pipeline);
+        mem::swap(&mut self.material_pipelines.pipelines, &mut sized.material_pipelines);
+
+        // the old ones, and a post-process without anything made yet:
+        unsafe {
+            sized.cleanup(&self.main_device);
+        };
+
+        // these keep their effects, the LUT and the font, they null what they destroy if making it again fails:
+        let extent = self.target.extent();
+
+        self.post.resize(&self.main_device, &self.hdr, extent)?;
+
+        if let Some(overlay) = &mut self.overlay {
+            overlay.fit(&self.main_device, &self.target)?;
+        }
+
+        if let Some(text) = &mut self.text {
+            text.fit(&self.main_device, &self.target)?;
+        }
+
+        Ok(())
+    }
+
+    /// The renderer's window or one of the views', if `id` is one of them.
+    pub fn renderer_window_mut(&mut self, id: WindowId) -> Option<&mut RendererWindow> {
+        self.window.iter_mut()
+            .chain(self.views.iter_mut().filter_map(|view| view.window.as_mut()))
+            .find(|window| window.window.id() == id)
+    }
+
+    /// Has the window's swapchain created again before its next frame, for when it was resized.
+    pub fn window_resized(&mut self, id: WindowId) {
+        if let Some(window) = self.renderer_window_mut(id) {
+            window.outdated = true;
+        }
+    }
+
+    /// Keeps up with the window moving to a monitor with another DPI. Its size in physical pixels changes along
+    /// with it, so the swapchain is created again before its next frame.
+    pub fn scale_factor_changed(&mut self, id: WindowId, scale_factor: f64) {
+        if let Some(window) = self.renderer_window_mut(id) {
+            window.scale_factor = scale_factor;
+            window.outdated = true;
+        }
+    }
+
+    /// Physical pixels per logical one, what UI is scaled by. Headless renderers have 1.
+    pub fn scale_factor(&self) -> f64 {
+        match &self.window {
+            None => 1.0,
+            Some(window) => window.scale_factor
+        }
+    }
+
+    /// Switches the window to `mode`, the swapchain is created again for its new size before the next frame.
+    ///
+    /// Switches the window whose frame is being recorded, if it's one of the views'.
+    pub fn set_window_mode(&mut self, mode: WindowMode) -> Result<()> {
+        let window = match &mut self.window {
+            None => anyhow::bail!("A headless renderer has no window"),
+            Some(window) => window
+        };
+
+        let config = WindowConfig {
+            mode,
+            ..self.config.window.clone()
+        };
+
+        window.apply_config(&config)?;
+
+        self.config.window = config;
+
+        Ok(())
+    }
+
+    /// Presents with `present_mode` from the next frame on, or with `Fifo` where the surface doesn't have it.
+    ///
+    /// Sets the present mode of the window whose frame is being recorded, if it's one of the views'.
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) -> Result<()> {
+        let window = match &mut self.window {
+            None => anyhow::bail!("A headless renderer doesn't present"),
+            Some(window) => window
+        };
+
+        window.present_mode = present_mode.into();
+        window.outdated = true;
+
+        // the views take theirs from the renderer's window when they're opened:
+        if self.active_view.is_none() {
+            self.config.present_mode = present_mode;
+        }
+
+        Ok(())
+    }
+
+    /// Switches between a window and borderless fullscreen, or out of exclusive fullscreen.
+    pub fn toggle_fullscreen(&mut self) -> Result<()> {
+        let mode = match self.config.window.mode {
+            WindowMode::Windowed => WindowMode::Borderless,
+            WindowMode::Borderless | WindowMode::Exclusive => WindowMode::Windowed,
+        };
+
+        self.set_window_mode(mode)
+    }
+
+    /// Switches between regular and reversed depth, which means building the depth tested pipelines again.
+    pub fn set_reverse_z(&mut self, reverse_z: bool) -> Result<()> {
+        if self.reverse_z == reverse_z {
+            return Ok(());
+        }
+
+        if self.active_view.is_some() {
+            anyhow::bail!("Depth can't be switched while a window's frame is recorded");
+        }
+
+        unsafe {
+            self.main_device.logical_device.device_wait_idle()?;
+        };
+
+        // every window has pipelines of its own:
+        for index in 0..self.views.len() {
+            self.swap_view(index);
+
+            let rebuilt = self.rebuild_depth_pipelines(reverse_z);
+
+            self.swap_view(index);
+
+            rebuilt?;
+        }
+
+        self.rebuild_depth_pipelines(reverse_z)?;
+
+        // the views share it, it sets its viewport while recording:
+        if let Some(text) = &mut self.text {
+            text.rebuild_world_pipeline(
+                &self.main_device,
+                &self.target,
+                self.render_pass,
+                self.camera_uniforms.set_layout,
+                self.samples,
+                reverse_z,
+            )?;
+        }
+
+        self.reverse_z = reverse_z;
+
+        Ok(())
+    }
+
+    fn rebuild_depth_pipelines(&mut self, reverse_z: bool) -> Result<()> {
+        unsafe {
+            self.mesh_pipeline.cleanup(&self.main_device.logical_device);
+        };
+
+        self.mesh_pipeline = RendererPipeline::mesh(
+            &self.main_device,
+            self.target.extent(),
+            self.render_pass,
+            self.camera_uniforms.set_layout,
+            self.samples,
+            reverse_z,
+        )?;
+
+        self.material_pipelines.rebuild(
+            &self.main_device,
+            self.target.extent(),
+            self.render_pass,
+            [self.camera_uniforms.set_layout, self.lights.set_layout, self.shadows.set_layout],
+            self.samples,
+            reverse_z,
+        )
+    }
+
+    fn graphics_queue(&self) -> vk::Queue {
+        match self.main_device.queue_family(vk::QueueFlags::GRAPHICS) {
+            None => panic!("No graphics queue family found, don't know what to do!"),
+            Some(qf) => qf.queues[0]
+        }
+    }
+
+    fn device_lost(&self, operation: &'static str) -> anyhow::Error {
+        self.diagnostics.device_lost(self.graphics_queue(), operation).into()
+    }
+
+    /// Sets what's done with the errors there's nobody to return to, like the ones of writing a capture when the
+    /// renderer is dropped or the ones the app runner gets. Until it's set they're printed to stderr.
+    pub fn set_error_hook(&mut self, hook: impl FnMut(&anyhow::Error) + Send + 'static) {
+        self.error_hook = Box::new(hook);
+    }
+
+    /// Hands `err` to the error hook.
+    pub fn report(&mut self, err: anyhow::Error) {
+        (self.error_hook)(&err);
+    }
+
+    /// Waits until the next frame may be recorded, then begins its command buffer and render pass.
+    ///
+    /// The render pass draws into the HDR target. When the frame ends, it goes through the [`post`] effects and is
+    /// tonemapped into the target's image.
+    ///
+    /// The pass that bins lights is recorded ahead of the render pass, it runs with the camera and lights
+    /// that are set before the frame ends. Shadow maps are recorded when the frame ends.
+    ///
+    /// The render pass is recorded into secondary command buffers, the frame's is the first of them and
+    /// [`VulkanRenderer::record_parallel`] can record more on worker threads.
+    ///
+    /// A lost device is reported as a [`diagnostics::DeviceLost`] error, after which
+    /// [`VulkanRenderer::recreate_device`] can be used to try again.
+    pub fn begin_frame(&mut self) -> Result<Frame> {
+        if self.device_destroyed {
+            anyhow::bail!("There's no device to draw with, creating it again after it was lost failed");
+        }
+
+        if self.window.as_ref().is_some_and(|window| window.outdated) {
+            self.recreate_swapchain()?;
+        }
+
+        // acquiring next image:
+        let (slot, image_index) = match &mut self.target {
+            RenderTarget::Swapchain(swapchain) => {
+                swapchain.current_image = (swapchain.current_image + 1) % swapchain.image_count as usize;
+
+                let acquired = self.main_device.acquire_next_image(swapchain, swapchain.image_available[swapchain.current_image]);
+
+                match acquired {
+                    Err(vk::Result::ERROR_DEVICE_LOST) => return Err(self.device_lost("image acquisition")),
+                    Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                        self.recreate_swapchain()?;
+
+                        if self.window.as_ref().is_some_and(|window| window.outdated) {
+                            anyhow::bail!("The window is minimized, there's nothing to draw into");
+                        }
+
+                        return self.begin_frame();
+                    },
+                    result => (swapchain.current_image, result?.0)
+                }
+            },
+            RenderTarget::Offscreen(offscreen) => (offscreen.current_image, 0),
+        };
+
+        // fences, the other windows' too since they share the per frame resources of this slot:
+        let fences = [self.target.may_begin_drawing()[slot]];
+
+        let shared_fences: Vec<vk::Fence> = fences.into_iter()
+            .chain(self.views.iter().filter_map(|view| view.target.may_begin_drawing().get(slot).copied()))
+            .collect();
+
+        let waited = self.main_device.wait_for_fences(&shared_fences, u64::MAX);
+
+        match waited {
+            Err(vk::Result::ERROR_DEVICE_LOST) => return Err(self.device_lost("fence wait")),
+            result => result?
+        };
+
+        // the fence is only reset when the frame is submitted, so a frame that fails before leaves it signalled:
+        self.culling.frame_finished(slot)?;
+
+        self.command_pools.reset_frame(&self.main_device, slot)?;
+
+        if let Some(capture) = &mut self.capture {
+            capture.frame_finished(slot)?;
+        }
+
+        self.screenshots.frame_finished(&self.main_device, slot)?;
+
+        self.secondary_command_buffers.clear();
+
+        // recording:
+        self.diagnostics.reset(slot);
+
+        let extent = self.target.extent();
+
+        self.lights.begin(extent);
+        self.shadows.begin();
+        self.batches.begin();
+
+        if let Some(text) = &mut self.text {
+            text.begin();
+        }
+        self.culling.begin();
+
+        self.passes().begin(slot)?;
+
+        // what's drawn on the frame itself goes first:
+        let secondary_command_buffer = self.command_pools.threads[0].secondary(&self.main_device, slot)?;
+
+        Self::begin_secondary(&self.main_device, self.render_pass, self.hdr.framebuffer, secondary_command_buffer)?;
+
+        Ok(Frame {
+            command_buffer: secondary_command_buffer,
+            image_index,
+            slot,
+            extent,
+        })
+    }
+
+    /// Draws what was queued, ends the render pass, runs the post-process effects and tonemaps the frame into the target, then submits it
+    /// and presents it when there's a swapchain.
+    ///
+    /// Ends a frame begun with [`VulkanRenderer::begin_window_frame`] too, handing the view its parts back.
+    pub fn end_frame(&mut self, frame: Frame) -> Result<()> {
+        let ended = self.submit_frame(frame);
+
+        if let Some(index) = self.active_view.take() {
+            self.swap_view(index);
+        }
+
+        ended
+    }
+
+    fn submit_frame(&mut self, frame: Frame) -> Result<()> {
+        self.lights.flush(frame.slot)?;
+
+        // queued draws and casters, merged into instanced ones:
+        let (batches, caster_batches) = self.batches.flush(&self.main_device, frame.slot)?;
+
+        let instance_buffer = self.batches.instance_buffers[frame.slot].buffer;
+
+        for batch in caster_batches {
+            self.shadows.casters.push(ShadowCaster {
+                vertex_buffer: batch.vertex_buffer,
+                index_buffer: batch.index_buffer,
+                index_count: batch.index_count,
+                model: Mat4::IDENTITY,
+                instance_buffer,
+                instances: batch.instances,
+            });
+        }
+
+        let shadow_command_buffer = self.shadows.record(&self.main_device, frame.slot)?;
+
+        let culled = self.culling.prepare(
+            &self.main_device,
+            frame.slot,
+            &batches,
+            &self.batches.instance_buffers[frame.slot],
+        )?;
+
+        let culling_command_buffer = if culled {
+            Some(self.culling.record(&self.main_device, frame.slot)?)
+        } else {
+            None
+        };
+
+        if let Some(text) = &mut self.text {
+            text.write(&self.main_device, &self.command_pools, &self.target, frame.slot)?;
+        }
+
+        // queued draws go last, after what the workers recorded:
+        let queued_frame = Frame {
+            command_buffer: self.command_pools.threads[0].secondary(&self.main_device, frame.slot)?,
+            ..frame
+        };
 
-use anyhow::Result;
+        Self::begin_secondary(&self.main_device, self.render_pass, self.hdr.framebuffer, queued_frame.command_buffer)?;
 
-pub struct VulkanRenderer {
-    pub instance: ash::Instance,
-    pub debug: RendererDebug,
-    pub main_device: RendererDevice,
-    pub window: RendererWindow,
-    pub swapchain: RendererSwapchain,
-    pub render_pass: vk::RenderPass,
-    pub graphics_pipeline: RendererPipeline,
-    pub command_pools: CommandPools,
-    pub graphics_command_buffers: Vec<vk::CommandBuffer>,
-}
+        self.draw_batches(&queued_frame, &batches, instance_buffer, culling_command_buffer.is_some());
 
-impl VulkanRenderer {
-    fn used_layer_names() -> Vec<ffi::CString> {
-        vec![
-            ffi::CString::new("VK_LAYER_KHRONOS_validation").unwrap()
-        ]
+        // blended over everything else, without hiding what's behind it:
+        if let Some(text) = &self.text {
+            text.record_world(
+                &self.main_device,
+                queued_frame.command_buffer,
+                self.camera_uniforms.descriptor_sets[frame.slot],
+                frame.extent,
+            );
+        }
+
+        self.main_device.end_command_buffer(frame.command_buffer)?;
+        self.main_device.end_command_buffer(queued_frame.command_buffer)?;
+
+        let secondary_command_buffers: Vec<vk::CommandBuffer> = [frame.command_buffer].into_iter()
+            .chain(self.secondary_command_buffers.drain(..))
+            .chain([queued_frame.command_buffer])
+            .collect();
+
+        self.passes().end(&frame, &secondary_command_buffers)?;
+
+        // submit, with the shadow maps rendered and the draws culled first:
+        let first: Vec<vk::CommandBuffer> = shadow_command_buffer.into_iter()
+            .chain(culling_command_buffer)
+            .collect();
+
+        self.diagnostics.submitted(frame.slot);
+
+        let submitted = self.passes().submit(&frame, &first);
+
+        match submitted {
+            Err(vk::Result::ERROR_DEVICE_LOST) => return Err(self.device_lost("queue submit")),
+            result => result?
+        };
+
+        let presented = self.passes().present(&frame);
+
+        match presented {
+            Err(vk::Result::ERROR_DEVICE_LOST) => return Err(self.device_lost("present")),
+            // the window changed in a way the swapchain doesn't fit anymore:
+            Ok(true) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                if let Some(window) = &mut self.window {
+                    window.outdated = true;
+                }
+            },
+            result => {
+                result?;
+            }
+        };
+
+        Ok(())
     }
 
-    fn used_extensions() -> Vec<*const i8> {
-        vec![
-            ext::DebugUtils::name().as_ptr(),
-            khr::Surface::name().as_ptr(),
-        ]
+    /// Borrows what recording, submitting and presenting the frame around its render pass needs.
+    fn passes(&mut self) -> FramePasses<'_> {
+        let queue = self.graphics_queue();
+
+        // only the renderer's own window is captured:
+        let capture = match self.active_view {
+            None => self.capture.as_mut(),
+            Some(_) => None
+        };
+
+        FramePasses {
+            device: &self.main_device,
+            queue,
+            render_pass: self.render_pass,
+            clear_color: self.config.clear_color,
+            reverse_z: self.reverse_z,
+            command_buffers: &self.graphics_command_buffers,
+            camera_sets: &self.camera_uniforms.descriptor_sets,
+            target: &self.target,
+            depth: &self.depth,
+            hdr: &self.hdr,
+            post: &mut self.post,
+            lights: &self.lights,
+            culling: &mut self.culling,
+            text: self.text.as_ref(),
+            capture,
+            screenshots: &mut self.screenshots,
+            overlay: self.overlay.as_mut(),
+        }
     }
 
-    pub fn new() -> Result<Self> {
-        let (event_loop, window) = RendererWindow::create_window()?;
+    /// Draws a frame with nothing but the hard-coded triangle in it.
+    pub fn draw_frame(&mut self) -> Result<()> {
+        let frame = self.begin_frame()?;
 
-        window.set_title("Vulkan Engine");
+        self.draw_triangle(&frame);
 
-        let used_layer_names = Self::used_layer_names();
+        self.end_frame(frame)
+    }
 
-        let used_layers = used_layer_names.iter()
-            .map(|layer_name| layer_name.as_ptr())
-            .collect();
+    pub fn draw_triangle(&mut self, frame: &Frame) {
+        self.begin_label(frame, "triangle");
 
-        let mut used_extensions = Self::used_extensions();
+        self.main_device.cmd_bind_pipeline(
+            frame.command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            self.graphics_pipeline.pipeline,
+        );
 
-        for ext_name in ash_window::enumerate_required_extensions(&window)? {
-            used_extensions.push(ext_name.as_ptr());
+        self.main_device.cmd_draw(frame.command_buffer, 3, 1, 0, 0);
+
+        self.end_label(frame);
+    }
+
+    /// Uploads the camera for this frame, everything drawn through the mesh pipeline is seen through it.
+    pub fn set_camera(&mut self, frame: &Frame, camera: &Camera) -> Result<()> {
+        debug_assert_eq!(camera.reverse_z, self.reverse_z, "camera and pipelines disagree on reverse-Z");
+
+        self.lights.set_camera(camera);
+        self.shadows.set_camera(camera);
+        self.culling.set_camera(camera);
+
+        self.camera_uniforms.write(frame.slot, &camera.uniform())
+    }
+
+    /// Replaces the lights of this frame, frames start out without any.
+    ///
+    /// Only the PBR pipelines are lit, see [`lights`] for how. Lights that cast shadows only do so on
+    /// what is drawn with [`VulkanRenderer::draw_material_mesh`], from what is queued with
+    /// [`VulkanRenderer::draw_shadow_caster`].
+    pub fn set_lights(&mut self, frame: &Frame, lights: &[WorldLight]) -> Result<()> {
+        self.shadows.set_lights(lights);
+
+        self.lights.set_lights(frame.slot, lights)
+    }
+
+    /// Queues a mesh to be rendered into the shadow maps of the frame being recorded.
+    ///
+    /// The maps are rendered when the frame ends, so the mesh has to stay alive until the frame has finished.
+    /// Casters with the same mesh are rendered as instances of one draw, see [`batches`].
+    pub fn draw_shadow_caster(&mut self, mesh: &Mesh, model: Mat4) {
+        self.batches.queue_caster(mesh, model);
+    }
+
+    /// Like `draw_shadow_caster`, with the model matrices of the `InstanceData` in `instances` at `range`.
+    pub fn draw_shadow_caster_instanced(&mut self, mesh: &Mesh, instances: &RendererBuffer, range: Range<u32>) {
+        self.shadows.add_caster(mesh, Mat4::IDENTITY, instances.buffer, range);
+    }
+
+    /// Replaces how the frame is tonemapped, takes effect with the next frame that ends.
+    pub fn set_tonemapping(&mut self, tonemapping: Tonemapping) {
+        self.hdr.tonemapping = tonemapping;
+    }
+
+    /// Replaces the post-process effects, which run in order on the frames that end from now on.
+    ///
+    /// Effects can also be toggled through [`RendererPostProcess::effects`] in `self.post`.
+    pub fn set_post_effects(&mut self, effects: Vec<PostEffect>) {
+        self.post.effects = effects;
+    }
+
+    /// Replaces the LUT that [`post::Effect::ColorGrade`] grades with, waits for the device to be idle.
+    pub fn set_lut(&mut self, lut: &Lut) -> Result<()> {
+        let max_size = self.main_device.properties.limits.max_image_dimension3_d;
+
+        if lut.size > max_size {
+            anyhow::bail!("LUTs can be up to {} colors along each side, not {}", max_size, lut.size);
         }
 
-        let entry = ash::Entry::linked();
+        unsafe {
+            self.main_device.logical_device.device_wait_idle()?;
+        };
+
+        self.post.set_lut(&self.main_device, &self.command_pools, lut)
+    }
+
+    /// Sets up the overlay that's drawn over every frame with [`VulkanRenderer::draw_overlay`], with a font atlas
+    /// of tightly packed RGBA8 `font_pixels`. An overlay that was set up before is replaced.
+    pub fn create_overlay(&mut self, font_extent: vk::Extent2D, font_pixels: Vec<u8>) -> Result<()> {
+        if let Some(mut overlay) = self.overlay.take() {
+            unsafe {
+                self.main_device.logical_device.device_wait_idle()?;
 
-        let instance = Self::create_instance(&entry, &used_layers, &used_extensions)?;
+                overlay.cleanup(&self.main_device);
+            };
+        }
 
-        let window = RendererWindow::new(event_loop, window, &entry, &instance)?;
+        self.overlay = Some(RendererOverlay::new(
+            &self.main_device,
+            &self.command_pools,
+            &self.target,
+            self.target.image_count() as usize,
+            font_extent,
+            font_pixels,
+        )?);
 
-        let debug = RendererDebug::new(&entry, &instance)?;
+        Ok(())
+    }
 
-        let main_device = match RendererDevice::new(&instance, &used_layers)? {
-            None => panic!("No fitting GPU found, don't know what to do"),
-            Some(dev) => dev
+    /// Draws `data` over the frame once it's tonemapped, see [`overlay`]. Drawing again in the same frame replaces
+    /// what was drawn before.
+    pub fn draw_overlay(&mut self, frame: &Frame, data: OverlayDrawData) -> Result<()> {
+        let overlay = match &mut self.overlay {
+            None => anyhow::bail!("There's no overlay to draw, it's set up with create_overlay"),
+            Some(overlay) => overlay
         };
 
-        let render_pass = Self::create_render_pass(&main_device, &window)?;
+        // one of the other windows can have images of another format:
+        if !overlay.fits(&self.target) {
+            unsafe {
+                self.main_device.logical_device.device_wait_idle()?;
+            };
 
-        let mut swapchain = RendererSwapchain::new(&instance, &main_device, &window)?;
-        swapchain.create_framebuffers(&main_device, render_pass)?;
+            overlay.fit(&self.main_device, &self.target)?;
+        }
 
-        let graphics_pipeline = RendererPipeline::new(&main_device, swapchain.extent, render_pass)?;
+        overlay.write(&self.main_device, frame.slot, data)
+    }
 
-        let command_pools = CommandPools::new(&main_device)?;
+    /// Makes `font` available to text, rasterizing its glyphs into the renderer's atlas as they're drawn.
+    pub fn add_font(&mut self, font: Font) -> Result<FontId> {
+        let text = match self.text.take() {
+            None => self.create_text(SdfAtlas::new(ATLAS_SIZE, ATLAS_SIZE))?,
+            Some(text) => text
+        };
 
-        let graphics_command_buffers = CommandPools::create_command_buffers(
-            &main_device,
-            command_pools.graphics,
-            swapchain.framebuffers.len() as u32
-        )?;
+        Ok(self.text.insert(text).atlas.add_font(font))
+    }
 
-        let renderer = Self {
-            instance,
-            debug,
-            main_device,
-            window,
-            swapchain,
-            render_pass,
-            graphics_pipeline,
-            command_pools,
-            graphics_command_buffers,
+    fn create_text(&self, atlas: SdfAtlas) -> Result<RendererText> {
+        RendererText::new(
+            &self.main_device,
+            &self.command_pools,
+            &self.target,
+            self.render_pass,
+            self.camera_uniforms.set_layout,
+            self.samples,
+            self.reverse_z,
+            atlas,
+        )
+    }
+
+    /// Draws `layout` over the tonemapped frame being recorded, with its top left corner at `position` in pixels,
+    /// see [`text`]. `font` is what it was laid out with.
+    pub fn draw_text(&mut self, font: FontId, layout: &TextLayout, position: [f32; 2], color: [u8; 4]) -> Result<()> {
+        match &mut self.text {
+            None => anyhow::bail!("There's no font to draw text with, they're added with add_font"),
+            Some(text) => text.queue_screen(font, layout, position, color)
+        }
+    }
+
+    /// Draws `layout` into the world of the frame being recorded, seen through its camera. `model` places the
+    /// layout's top left corner, with its lines along +X and going down -Y, facing +Z.
+    pub fn draw_world_text(&mut self, font: FontId, layout: &TextLayout, model: Mat4, color: [u8; 4]) -> Result<()> {
+        match &mut self.text {
+            None => anyhow::bail!("There's no font to draw text with, they're added with add_font"),
+            Some(text) => text.queue_world(font, layout, model, color)
+        }
+    }
+
+    /// Starts capturing every frame that ends from now on into a video or images, see [`capture`].
+    pub fn start_capture(&mut self, settings: CaptureSettings) -> Result<()> {
+        if self.capture.is_some() {
+            anyhow::bail!("Already capturing, the capture has to be stopped first");
+        }
+
+        self.capture = Some(RendererCapture::new(
+            &self.main_device,
+            settings,
+            &self.target,
+            self.target.image_count() as usize,
+        )?);
+
+        Ok(())
+    }
+
+    /// Waits for the device and the captured frames to be written, returns how many were.
+    pub fn stop_capture(&mut self) -> Result<u32> {
+        let mut capture = match self.capture.take() {
+            None => anyhow::bail!("Nothing is being captured"),
+            Some(capture) => capture
+        };
+
+        let waited = unsafe {
+            self.main_device.logical_device.device_wait_idle()
+        };
+
+        let finished = waited.map_err(anyhow::Error::from)
+            .and_then(|_| capture.finish());
+
+        unsafe {
+            capture.cleanup(&self.main_device);
+        };
+
+        finished
+    }
+
+    /// Takes a screenshot of the next frame that ends, see [`screenshot`]. Returns where it's saved: `path` ending in
+    /// `.png`, or `.exr` when the target is HDR.
+    pub fn capture_screenshot(&mut self, path: impl AsRef<Path>) -> Result<PathBuf> {
+        self.screenshots.request(&self.target, path.as_ref())
+    }
+
+    /// Waits for the device and every screenshot to be saved, returns where they were.
+    pub fn finish_screenshots(&mut self) -> Result<Vec<PathBuf>> {
+        unsafe {
+            self.main_device.logical_device.device_wait_idle()?;
+        };
+
+        self.screenshots.finish(&self.main_device)
+    }
+
+    /// Whether the capture has all the frames it was started for, apps can stop once it does.
+    pub fn capture_done(&self) -> bool {
+        match &self.capture {
+            None => false,
+            Some(capture) => capture.is_done()
+        }
+    }
+
+    /// Picks which tests queued draws are culled with, see [`culling`].
+    pub fn set_culling(&mut self, settings: CullingSettings) {
+        self.culling.settings = settings;
+    }
+
+    /// Replaces the shadow settings, changing the resolution waits for the device to be idle.
+    pub fn set_shadow_settings(&mut self, settings: ShadowSettings) -> Result<()> {
+        let max_resolution = self.main_device.properties.limits.max_image_dimension2_d;
+
+        if settings.resolution == 0 || settings.resolution > max_resolution {
+            anyhow::bail!("Shadow maps can be 1 to {} texels wide, not {}", max_resolution, settings.resolution);
+        }
+
+        if settings.resolution != self.shadows.settings.resolution {
+            unsafe {
+                self.main_device.logical_device.device_wait_idle()?;
+            };
+        }
+
+        self.shadows.set_settings(&self.main_device, &self.command_pools, settings)
+    }
+
+    pub fn draw_mesh(&self, frame: &Frame, mesh: &Mesh, model: Mat4) {
+        self.recorder().draw_mesh(frame, mesh, model);
+    }
+
+    /// Draws a mesh shaded by one of `materials`, through the PBR pipeline that fits it.
+    ///
+    /// `material` is an index into the materials `materials` was created from, `None` uses the default material.
+    pub fn draw_material_mesh(
+        &self,
+        frame: &Frame,
+        mesh: &Mesh,
+        model: Mat4,
+        materials: &RendererMaterials,
+        material: Option<usize>
+    ) {
+        self.recorder().draw_material_mesh(frame, mesh, model, materials, material);
+    }
+
+    /// Like `draw_material_mesh`, once for each `InstanceData` in `instances` at `range`.
+    ///
+    /// `instances` needs `VERTEX_BUFFER` usage, its model matrices place the instances in the world.
+    pub fn draw_material_mesh_instanced(
+        &self,
+        frame: &Frame,
+        mesh: &Mesh,
+        materials: &RendererMaterials,
+        material: Option<usize>,
+        instances: &RendererBuffer,
+        range: Range<u32>
+    ) {
+        self.recorder().draw_material_mesh_instanced(frame, mesh, materials, material, instances, range);
+    }
+
+    /// Like `draw_material_mesh_instanced`, with the draws read from `commands`.
+    ///
+    /// Each command's `first_instance` picks where its instances start in `instances`, see
+    /// [`Mesh::draw_indirect`] for what the device needs for that.
+    pub fn draw_material_mesh_indirect(
+        &self,
+        frame: &Frame,
+        mesh: &Mesh,
+        materials: &RendererMaterials,
+        material: Option<usize>,
+        instances: &RendererBuffer,
+        commands: &IndirectCommands
+    ) -> Result<()> {
+        self.recorder().draw_material_mesh_indirect(frame, mesh, materials, material, instances, commands)
+    }
+
+    /// Borrows what drawing into a frame needs, in a form worker threads can share.
+    pub fn recorder(&self) -> Recorder<'_> {
+        Recorder {
+            device: &self.main_device,
+            mesh_pipeline: &self.mesh_pipeline,
+            material_pipelines: &self.material_pipelines,
+            camera_sets: &self.camera_uniforms.descriptor_sets,
+            light_sets: &self.lights.descriptor_sets,
+            shadow_sets: &self.shadows.descriptor_sets,
+            single_instance: self.batches.single_instance.buffer,
+        }
+    }
+
+    /// Queues a mesh to be drawn when the frame ends, shaded like `draw_material_mesh` does.
+    ///
+    /// Queued draws with the same mesh and material are merged into one instanced draw, see [`batches`]. They're
+    /// drawn after everything else with blended materials last, but not sorted by depth, and what the camera
+    /// doesn't see is culled on the GPU first, see [`culling`]. The mesh and materials have to stay alive until the
+    /// frame has finished.
+    pub fn queue_material_mesh(
+        &mut self,
+        mesh: &Mesh,
+        instance: InstanceData,
+        materials: &RendererMaterials,
+        material: Option<usize>
+    ) {
+        let index = materials.index(material);
+
+        self.batches.queue(mesh, materials.keys[index], materials.descriptor_sets[index], instance);
+    }
+
+    /// Records part of the frame's render pass on worker threads, each of `jobs` on its own.
+    ///
+    /// A job gets a copy of `frame` with a secondary command buffer of its own, from its thread's pool in
+    /// `command_pools`, and a [`Recorder`] to draw into it with. The buffers are executed in the order of `jobs`
+    /// no matter which thread finishes first, after what's drawn on `frame` itself and the jobs of earlier calls,
+    /// and before the queued draws. Nothing is executed when one of the jobs fails.
+    pub fn record_parallel<F: FnOnce(&Recorder, &Frame) -> Result<()> + Send>(
+        &mut self,
+        frame: &Frame,
+        jobs: Vec<F>
+    ) -> Result<()> {
+        // the first thread's pools are the main thread's:
+        self.command_pools.add_threads(&self.main_device, jobs.len() + 1)?;
+
+        // allocated up front, each thread's pool is only touched by that thread while recording:
+        let mut command_buffers = Vec::with_capacity(jobs.len());
+
+        for thread in &mut self.command_pools.threads[1..=jobs.len()] {
+            command_buffers.push(thread.secondary(&self.main_device, frame.slot)?);
+        }
+
+        let (render_pass, framebuffer) = (self.render_pass, self.hdr.framebuffer);
+
+        let recorder = self.recorder();
+        let recorder = &recorder;
+
+        let recorded: Vec<Result<()>> = thread::scope(|scope| {
+            let workers: Vec<_> = jobs.into_iter()
+                .zip(&command_buffers)
+                .map(|(job, &command_buffer)| scope.spawn(move || {
+                    let worker_frame = Frame {
+                        command_buffer,
+                        ..*frame
+                    };
+
+                    Self::begin_secondary(recorder.device, render_pass, framebuffer, command_buffer)?;
+
+                    job(recorder, &worker_frame)?;
+
+                    recorder.device.end_command_buffer(command_buffer)?;
+
+                    Ok(())
+                }))
+                .collect();
+
+            // joined in order, so a panic is passed on like it would be without threads:
+            workers.into_iter()
+                .map(|worker| match worker.join() {
+                    Err(panic) => panic::resume_unwind(panic),
+                    Ok(result) => result
+                })
+                .collect()
+        });
+
+        for result in recorded {
+            result?;
+        }
+
+        self.secondary_command_buffers.extend(command_buffers);
+
+        Ok(())
+    }
+
+    /// Begins a secondary command buffer that continues the frame's render pass.
+    fn begin_secondary<B: Backend>(
+        backend: &B,
+        render_pass: vk::RenderPass,
+        framebuffer: vk::Framebuffer,
+        command_buffer: vk::CommandBuffer
+    ) -> Result<()> {
+        let inheritance_info = vk::CommandBufferInheritanceInfo::builder()
+            .render_pass(render_pass)
+            .subpass(0)
+            .framebuffer(framebuffer);
+
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT | vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE)
+            .inheritance_info(&inheritance_info);
+
+        backend.begin_command_buffer(command_buffer, &begin_info)
+    }
+
+    /// Draws `batches` with their instances in `instance_buffer`, or what's left of them when they were `culled`.
+    fn draw_batches(&self, frame: &Frame, batches: &[Batch], instance_buffer: vk::Buffer, culled: bool) {
+        let recorder = self.recorder();
+
+        for (index, batch) in batches.iter().enumerate() {
+            if !culled {
+                recorder.draw_batch(frame, batch, instance_buffer);
+
+                continue;
+            }
+
+            if recorder.bind_batch(frame, batch).is_some() {
+                self.culling.draw(&self.main_device, frame.command_buffer, frame.slot, index, batch);
+            }
+        }
+    }
+
+    /// Opens a debug label in the frame's command buffer, it shows up in device lost reports and debuggers.
+    pub fn begin_label(&mut self, frame: &Frame, name: &str) {
+        self.diagnostics.begin_label(&self.debug, frame.slot, frame.command_buffer, name);
+    }
+
+    pub fn end_label(&mut self, frame: &Frame) {
+        self.diagnostics.end_label(&self.debug, frame.slot, frame.command_buffer);
+    }
+
+    /// Reads the last rendered frame back as tightly packed RGBA8 pixels, only possible when running headless.
+    pub fn read_pixels(&self) -> Result<Vec<u8>> {
+        self.read_target(&self.target)
+    }
+
+    /// Like `read_pixels`, for the view at `index` in `views`, which has to be an offscreen one.
+    pub fn read_view_pixels(&self, index: usize) -> Result<Vec<u8>> {
+        if self.active_view.is_some() {
+            anyhow::bail!("Views can't be read back while a window's frame is recorded");
+        }
+
+        match self.views.get(index) {
+            None => anyhow::bail!("There's no view {}, there are {}", index, self.views.len()),
+            Some(view) => self.read_target(&view.target)
+        }
+    }
+
+    fn read_target(&self, target: &RenderTarget) -> Result<Vec<u8>> {
+        let offscreen = match target {
+            RenderTarget::Swapchain(_) => anyhow::bail!("Only offscreen targets can be read back"),
+            RenderTarget::Offscreen(offscreen) => offscreen
         };
 
-        renderer.fill_command_buffers()?;
+        self.main_device.wait_for_fences(&offscreen.may_begin_drawing, u64::MAX)?;
+
+        let extent = offscreen.extent;
+
+        let mut readback = RendererBuffer::new(
+            &self.main_device,
+            "readback",
+            extent.width as vk::DeviceSize * extent.height as vk::DeviceSize * 4,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuToCpu,
+        )?;
+
+        let copied = self.command_pools.one_time_submit(&self.main_device, |command_buffer| {
+            let region = vk::BufferImageCopy::builder()
+                .image_subresource(vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: 0,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                })
+                .image_extent(vk::Extent3D {
+                    width: extent.width,
+                    height: extent.height,
+                    depth: 1,
+                });
+
+            self.main_device.cmd_copy_image_to_buffer(
+                command_buffer,
+                offscreen.color.image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                readback.buffer,
+                &[region.build()],
+            );
+        });
+
+        // the buffer goes whether or not it could be read:
+        let pixels = copied.and_then(|()| Ok(readback.read()?.to_vec()));
+
+        unsafe {
+            readback.cleanup(&self.main_device);
+        };
 
-        Ok(renderer)
+        pixels
     }
 
     fn create_instance(
         entry: &ash::Entry,
         layer_name_pts: &Vec<*const i8>,
-        extension_name_pts: &Vec<*const i8>
+        extension_name_pts: &Vec<*const i8>,
+        config: &RendererConfig
     ) -> Result<ash::Instance> {
-        let app_name = ffi::CString::new("Vulkan App")?;
+        let app_name = config.app_name()?;
         let engine_name = ffi::CString::new("Vulkan Engine")?;
 
         let app_info = vk::ApplicationInfo::builder()
@@ -122,7 +2064,7 @@ impl VulkanRenderer {
             .engine_name(&engine_name)
             .application_version(vk::make_api_version(0, 1, 0, 0))
             .engine_version(vk::make_api_version(0, 1, 0, 0))
-            .api_version(vk::API_VERSION_1_1);
+            .api_version(config.vk_api_version()?);
 
         let instance_info = vk::InstanceCreateInfo::builder()
             .application_info(&app_info)
@@ -136,43 +2078,115 @@ impl VulkanRenderer {
         Ok(instance)
     }
 
-    fn create_render_pass(device: &RendererDevice, window: &RendererWindow) -> Result<vk::RenderPass> {
-        let formats = window.formats(device.physical_device)?;
-        let format = formats.first().unwrap();
+    /// The scene render pass, into the HDR target which it leaves ready to be tonemapped.
+    ///
+    /// With more than one sample, color and depth are multisampled and color is resolved into the HDR target,
+    /// which is the third attachment then.
+    fn create_render_pass(
+        device: &RendererDevice,
+        depth_format: vk::Format,
+        samples: vk::SampleCountFlags
+    ) -> Result<vk::RenderPass> {
+        let multisampled = samples != vk::SampleCountFlags::TYPE_1;
+
+        // a multisampled target is only needed until it's resolved:
+        let (color_store_op, color_final_layout) = if multisampled {
+            (vk::AttachmentStoreOp::DONT_CARE, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+        } else {
+            (vk::AttachmentStoreOp::STORE, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        };
 
-        let attachments = [
+        let mut attachments = vec![
+            vk::AttachmentDescription::builder()
+                .format(HDR_FORMAT)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(color_store_op)
+                .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .final_layout(color_final_layout)
+                .samples(samples)
+                .build(),
             vk::AttachmentDescription::builder()
-                .format(format.format)
+                .format(depth_format)
                 .load_op(vk::AttachmentLoadOp::CLEAR)
                 .store_op(vk::AttachmentStoreOp::STORE)
                 .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
                 .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
                 .initial_layout(vk::ImageLayout::UNDEFINED)
-                .final_layout(vk::ImageLayout::PRESENT_SRC_KHR)
-                .samples(vk::SampleCountFlags::TYPE_1)
-                .build()
+                .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+                .samples(samples)
+                .build(),
         ];
 
+        if multisampled {
+            attachments.push(
+                vk::AttachmentDescription::builder()
+                    .format(HDR_FORMAT)
+                    .load_op(vk::AttachmentLoadOp::DONT_CARE)
+                    .store_op(vk::AttachmentStoreOp::STORE)
+                    .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                    .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                    .initial_layout(vk::ImageLayout::UNDEFINED)
+                    .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                    .samples(vk::SampleCountFlags::TYPE_1)
+                    .build()
+            );
+        }
+
         let color_attachment_references = [vk::AttachmentReference {
             attachment: 0,
             layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
         }];
 
-        let subpasses = [
-            vk::SubpassDescription::builder()
-                .color_attachments(&color_attachment_references)
-                .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-                .build()
-        ];
+        let depth_attachment_reference = vk::AttachmentReference {
+            attachment: 1,
+            layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        };
 
+        let resolve_attachment_references = [vk::AttachmentReference {
+            attachment: 2,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        }];
+
+        let mut subpass = vk::SubpassDescription::builder()
+            .color_attachments(&color_attachment_references)
+            .depth_stencil_attachment(&depth_attachment_reference)
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS);
+
+        if multisampled {
+            subpass = subpass.resolve_attachments(&resolve_attachment_references);
+        }
+
+        let subpasses = [subpass.build()];
+
+        // the HDR target and depth are shared by every frame in flight, the previous frame has to be done
+        // tonemapping and building the depth pyramid:
         let subpass_dependencies = [
             vk::SubpassDependency::builder()
                 .src_subpass(vk::SUBPASS_EXTERNAL)
-                .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                .src_stage_mask(
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                        | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+                        | vk::PipelineStageFlags::FRAGMENT_SHADER
+                        | vk::PipelineStageFlags::COMPUTE_SHADER
+                )
                 .dst_subpass(0)
-                .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-                .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
-                .build()
+                .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS)
+                .dst_access_mask(
+                    vk::AccessFlags::COLOR_ATTACHMENT_READ
+                        | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
+                )
+                .build(),
+            vk::SubpassDependency::builder()
+                .src_subpass(0)
+                .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .dst_subpass(vk::SUBPASS_EXTERNAL)
+                .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ)
+                .build(),
         ];
 
         let render_pass_info = vk::RenderPassCreateInfo::builder()
@@ -187,72 +2201,90 @@ impl VulkanRenderer {
         Ok(render_pass)
     }
 
-    fn fill_command_buffers(&self) -> Result<()> {
-        for (i, &command_buffer) in self.graphics_command_buffers.iter().enumerate() {
-            let begin_info = vk::CommandBufferBeginInfo::builder();
+    unsafe fn cleanup_device(&mut self) {
+        for view in &mut self.views {
+            view.cleanup(&self.main_device);
+        }
 
-            unsafe {
-                self.main_device.logical_device.begin_command_buffer(command_buffer, &begin_info)?
-            };
+        if let Some(mut capture) = self.capture.take() {
+            capture.cleanup(&self.main_device);
+        }
 
-            let clear_values = [
-                vk::ClearValue {
-                    color: vk::ClearColorValue {
-                        float32: [0.0, 0.0, 0.0, 1.0],
-                    }
-                },
-            ];
-
-            let render_pass_begin_info = vk::RenderPassBeginInfo::builder()
-                .render_pass(self.render_pass)
-                .framebuffer(self.swapchain.framebuffers[i])
-                .render_area(vk::Rect2D {
-                    offset: vk::Offset2D { x: 0, y: 0 },
-                    extent: self.swapchain.extent,
-                })
-                .clear_values(&clear_values);
+        if let Some(mut overlay) = self.overlay.take() {
+            overlay.cleanup(&self.main_device);
+        }
 
-            unsafe {
-                self.main_device.logical_device.cmd_begin_render_pass(
-                    command_buffer,
-                    &render_pass_begin_info,
-                    vk::SubpassContents::INLINE,
-                );
+        if let Some(mut text) = self.text.take() {
+            text.cleanup(&self.main_device);
+        }
 
-                self.main_device.logical_device.cmd_bind_pipeline(
-                    command_buffer,
-                    vk::PipelineBindPoint::GRAPHICS,
-                    self.graphics_pipeline.pipeline,
-                );
+        self.screenshots.cleanup(&self.main_device);
 
-                self.main_device.logical_device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        self.diagnostics.cleanup(&self.main_device);
 
-                self.main_device.logical_device.cmd_end_render_pass(command_buffer);
+        self.command_pools.cleanup(&self.main_device);
 
-                self.main_device.logical_device.end_command_buffer(command_buffer)?;
-            };
-        }
+        self.graphics_pipeline.cleanup(&self.main_device.logical_device);
 
-        Ok(())
+        self.mesh_pipeline.cleanup(&self.main_device.logical_device);
+
+        self.material_pipelines.cleanup(&self.main_device.logical_device);
+
+        self.lights.cleanup(&self.main_device);
+
+        self.shadows.cleanup(&self.main_device);
+
+        self.batches.cleanup(&self.main_device);
+
+        self.culling.cleanup(&self.main_device);
+
+        self.camera_uniforms.cleanup(&self.main_device);
+
+        self.main_device.logical_device.destroy_render_pass(self.render_pass, None);
+
+        self.post.cleanup(&self.main_device);
+
+        self.hdr.cleanup(&self.main_device);
+
+        self.target.cleanup(&self.main_device);
+
+        self.depth.cleanup(&self.main_device);
+
+        self.main_device.cleanup();
     }
 }
 
 impl Drop for VulkanRenderer {
     fn drop(&mut self) {
-        unsafe {
-            self.main_device.logical_device.device_wait_idle().unwrap();
+        // it was torn down already when creating it again failed:
+        if !self.device_destroyed {
+            // frames that were captured but not written yet still are:
+            if self.capture.is_some() {
+                if let Err(err) = self.stop_capture() {
+                    self.report(err.context("Failed to finish the capture"));
+                }
+            }
 
-            self.command_pools.cleanup(&self.main_device);
+            if let Err(err) = self.finish_screenshots() {
+                self.report(err.context("Failed to save the screenshots"));
+            }
 
-            self.graphics_pipeline.cleanup(&self.main_device.logical_device);
-
-            self.main_device.logical_device.destroy_render_pass(self.render_pass, None);
+            unsafe {
+                // a lost device can't be waited on, but it can still be destroyed:
+                let _ = self.main_device.logical_device.device_wait_idle();
 
-            self.swapchain.cleanup(&self.main_device);
+                self.cleanup_device();
+            }
+        }
 
-            self.window.cleanup();
+        unsafe {
+            if let Some(window) = &self.window {
+                window.cleanup();
+            }
 
-            self.main_device.cleanup();
+            for window in self.views.iter().filter_map(|view| view.window.as_ref()) {
+                window.cleanup();
+            }
 
             self.debug.cleanup();
 
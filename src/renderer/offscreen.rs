@@ -0,0 +1,85 @@
+use ash::vk;
+
+use crate::renderer::device::RendererDevice;
+use crate::renderer::image::RendererImage;
+
+use anyhow::Result;
+
+/// A single color image that is rendered into instead of a swapchain, for rendering without a window.
+pub struct RendererOffscreen {
+    pub color: RendererImage,
+    pub framebuffers: Vec<vk::Framebuffer>,
+    pub extent: vk::Extent2D,
+    pub may_begin_drawing: Vec<vk::Fence>,
+    pub image_count: u32,
+    pub current_image: usize,
+}
+
+impl RendererOffscreen {
+    pub const FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+
+    pub const USAGE: vk::ImageUsageFlags = vk::ImageUsageFlags::from_raw(
+        vk::ImageUsageFlags::COLOR_ATTACHMENT.as_raw() | vk::ImageUsageFlags::TRANSFER_SRC.as_raw()
+    );
+
+    pub fn new(device: &RendererDevice, extent: vk::Extent2D) -> Result<RendererOffscreen> {
+        let color = RendererImage::new(
+            device,
+            "offscreen color",
+            extent,
+            Self::FORMAT,
+            Self::USAGE,
+        )?;
+
+        let fence_info = vk::FenceCreateInfo::builder()
+            .flags(vk::FenceCreateFlags::SIGNALED);
+
+        let fence = unsafe {
+            device.logical_device.create_fence(&fence_info, None)?
+        };
+
+        Ok(RendererOffscreen {
+            color,
+            framebuffers: vec![],
+            extent,
+            may_begin_drawing: vec![fence],
+            image_count: 1,
+            current_image: 0,
+        })
+    }
+
+    pub fn create_framebuffers(
+        &mut self,
+        device: &RendererDevice,
+        render_pass: vk::RenderPass
+    ) -> Result<()> {
+        let attachments = [self.color.image_view];
+
+        let framebuffer_info = vk::FramebufferCreateInfo::builder()
+            .render_pass(render_pass)
+            .attachments(&attachments)
+            .width(self.extent.width)
+            .height(self.extent.height)
+            .layers(1);
+
+        let framebuffer = unsafe {
+            device.logical_device.create_framebuffer(&framebuffer_info, None)?
+        };
+
+        self.framebuffers.push(framebuffer);
+
+        Ok(())
+    }
+
+    pub unsafe fn cleanup(&mut self, device: &RendererDevice) {
+        for fence in &self.may_begin_drawing {
+            device.logical_device.destroy_fence(*fence, None);
+        }
+
+        for framebuffer in &self.framebuffers {
+            device.logical_device.destroy_framebuffer(*framebuffer, None);
+        }
+
+        self.color.cleanup(device);
+    }
+}
@@ -0,0 +1,556 @@
+//! 2D triangles drawn over the finished frame, for debug UI like the [`debug_ui`](crate::debug_ui) panels.
+//!
+//! The overlay is the frame's last pass. It keeps what tonemapping left in the target's image and blends onto it,
+//! after captures and screenshots were taken, so those never have the overlay in them.
+//!
+//! Vertices are in whatever units the UI lays itself out in, [`OverlayDrawData`] says how those map to pixels.
+//! Their colors are sRGB encoded, like UI libraries hand them out, and are encoded for the target like the
+//! tonemapping pass does. Everything samples the font atlas, shapes that aren't text sample a white texel of it.
+//!
+//! What's drawn is written into host visible buffers of the frame's slot, which grow when a frame draws more.
+
+use ash::vk;
+
+use crate::renderer::device::RendererDevice;
+use crate::renderer::backend::Backend;
+use crate::renderer::command_pools::CommandPools;
+use crate::renderer::buffer::RendererBuffer;
+use crate::renderer::texture::{SamplerDesc, Texture};
+use crate::renderer::pipeline::{PipelineDesc, RendererPipeline};
+use crate::renderer::hdr::OutputEncoding;
+use crate::renderer::target::RenderTarget;
+
+use gpu_allocator::MemoryLocation;
+
+use std::{mem, slice};
+
+use anyhow::Result;
+
+/// Buffers start out with room for this many vertices or indices.
+const MIN_BUFFER_LENGTH: usize = 4096;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct OverlayVertex {
+    pub position: [f32; 2],
+    pub uv: [f32; 2],
+    /// sRGB encoded, with straight alpha.
+    pub color: [u8; 4],
+}
+
+impl OverlayVertex {
+    pub fn bindings() -> [vk::VertexInputBindingDescription; 1] {
+        [
+            vk::VertexInputBindingDescription {
+                binding: 0,
+                stride: mem::size_of::<OverlayVertex>() as u32,
+                input_rate: vk::VertexInputRate::VERTEX,
+            }
+        ]
+    }
+
+    pub fn attributes() -> [vk::VertexInputAttributeDescription; 3] {
+        let attribute = |location: u32, format: vk::Format, offset: usize| vk::VertexInputAttributeDescription {
+            location,
+            binding: 0,
+            format,
+            offset: offset as u32,
+        };
+
+        let f32_size = mem::size_of::<f32>();
+
+        [
+            attribute(0, vk::Format::R32G32_SFLOAT, 0),
+            attribute(1, vk::Format::R32G32_SFLOAT, 2 * f32_size),
+            attribute(2, vk::Format::R8G8B8A8_UNORM, 4 * f32_size),
+        ]
+    }
+}
+
+/// Indexed triangles that are clipped to a rectangle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OverlayDraw {
+    /// Left, top, right and bottom, in the same units as the vertices.
+    pub clip_rect: [f32; 4],
+    pub first_index: u32,
+    pub index_count: u32,
+    /// Added to each of the draw's indices.
+    pub vertex_offset: i32,
+}
+
+/// Everything the overlay draws in a frame.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OverlayDrawData {
+    pub vertices: Vec<OverlayVertex>,
+    pub indices: Vec<u16>,
+    pub draws: Vec<OverlayDraw>,
+    /// The top left corner of the target, and its size, in the vertices' units.
+    pub origin: [f32; 2],
+    pub size: [f32; 2],
+    /// Pixels per unit, for clipping.
+    pub scale: [f32; 2],
+}
+
+/// What the shaders read, positions go from the overlay's units to clip space with `scale` and `translate`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct OverlayConstants {
+    scale: [f32; 2],
+    translate: [f32; 2],
+    encoding: u32,
+    paper_white: f32,
+}
+
+pub struct RendererOverlay {
+    /// Loads the target's image and leaves it in the layout it was in.
+    pub render_pass: vk::RenderPass,
+    pub pipeline: RendererPipeline,
+    pub font: Texture,
+    /// What the font atlas was uploaded from, RGBA8, kept to upload it again with a new device.
+    pub font_extent: vk::Extent2D,
+    pub font_pixels: Vec<u8>,
+    pub set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_set: vk::DescriptorSet,
+    /// One for each frame in flight, created with the first frame that draws something.
+    pub vertex_buffers: Vec<Option<RendererBuffer>>,
+    pub index_buffers: Vec<Option<RendererBuffer>>,
+    pub encoding: OutputEncoding,
+    format: vk::Format,
+    final_layout: vk::ImageLayout,
+    /// What was written for the frame being recorded, drawn when it ends.
+    pending: Option<OverlayDrawData>,
+}
+
+impl RendererOverlay {
+    /// Uploads the font atlas, tightly packed RGBA8 `font_pixels`, and creates the pass for `target`'s images.
+    pub fn new(
+        device: &RendererDevice,
+        command_pools: &CommandPools,
+        target: &RenderTarget,
+        slots: usize,
+        font_extent: vk::Extent2D,
+        font_pixels: Vec<u8>
+    ) -> Result<RendererOverlay> {
+        let sampler_desc = SamplerDesc {
+            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            ..Default::default()
+        };
+
+        let font = Texture::from_rgba8(device, command_pools, "overlay font", font_extent, &font_pixels, false, &sampler_desc)?;
+
+        let mut overlay = RendererOverlay {
+            render_pass: vk::RenderPass::null(),
+            pipeline: RendererPipeline {
+                pipeline: vk::Pipeline::null(),
+                pipeline_layout: vk::PipelineLayout::null(),
+            },
+            font,
+            font_extent,
+            font_pixels,
+            set_layout: vk::DescriptorSetLayout::null(),
+            descriptor_pool: vk::DescriptorPool::null(),
+            descriptor_set: vk::DescriptorSet::null(),
+            vertex_buffers: (0..slots).map(|_| None).collect(),
+            index_buffers: (0..slots).map(|_| None).collect(),
+            encoding: target.output_encoding(),
+            format: target.format(),
+            final_layout: target.final_layout(),
+            pending: None,
+        };
+
+        let created = overlay.create_descriptors(device)
+            .and_then(|_| overlay.create_pass(device, target));
+
+        if let Err(err) = created {
+            unsafe {
+                overlay.cleanup(device);
+            };
+
+            return Err(err);
+        }
+
+        Ok(overlay)
+    }
+
+    fn create_descriptors(&mut self, device: &RendererDevice) -> Result<()> {
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .build()
+        ];
+
+        let set_layout_info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .bindings(&bindings);
+
+        self.set_layout = unsafe {
+            device.logical_device.create_descriptor_set_layout(&set_layout_info, None)?
+        };
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: 1,
+            }
+        ];
+
+        let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(1)
+            .pool_sizes(&pool_sizes);
+
+        self.descriptor_pool = unsafe {
+            device.logical_device.create_descriptor_pool(&pool_info, None)?
+        };
+
+        let set_layouts = [self.set_layout];
+
+        let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(self.descriptor_pool)
+            .set_layouts(&set_layouts);
+
+        self.descriptor_set = unsafe {
+            device.logical_device.allocate_descriptor_sets(&allocate_info)?
+        }[0];
+
+        let image_infos = [
+            vk::DescriptorImageInfo {
+                sampler: self.font.sampler,
+                image_view: self.font.image.image_view,
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            }
+        ];
+
+        let writes = [
+            vk::WriteDescriptorSet::builder()
+                .dst_set(self.descriptor_set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(&image_infos)
+                .build()
+        ];
+
+        unsafe {
+            device.logical_device.update_descriptor_sets(&writes, &[]);
+        };
+
+        Ok(())
+    }
+
+    fn create_pass(&mut self, device: &RendererDevice, target: &RenderTarget) -> Result<()> {
+        self.render_pass = Self::create_render_pass(device, target.format(), target.final_layout())?;
+
+        let push_constant_ranges = [
+            vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                offset: 0,
+                size: mem::size_of::<OverlayConstants>() as u32,
+            }
+        ];
+
+        // the viewport is set while recording, the pass draws into whichever target the frame has:
+        self.pipeline = RendererPipeline::from_desc(device, target.extent(), self.render_pass, &PipelineDesc {
+            vert_code: vk_shader_macros::include_glsl!("./shaders/overlay.vert"),
+            frag_code: vk_shader_macros::include_glsl!("./shaders/overlay.frag"),
+            vertex_bindings: &OverlayVertex::bindings(),
+            vertex_attributes: &OverlayVertex::attributes(),
+            set_layouts: &[self.set_layout],
+            push_constant_ranges: &push_constant_ranges,
+            blend: true,
+            dynamic_viewport: true,
+            ..Default::default()
+        })?;
+
+        self.encoding = target.output_encoding();
+        self.format = target.format();
+        self.final_layout = target.final_layout();
+
+        Ok(())
+    }
+
+    /// Draws onto what's there, which the previous passes left in `final_layout`. Screen space
+    /// [`text`](crate::renderer::text) is drawn in a pass like it.
+    pub(crate) fn create_render_pass(
+        device: &RendererDevice,
+        format: vk::Format,
+        final_layout: vk::ImageLayout
+    ) -> Result<vk::RenderPass> {
+        let attachments = [
+            vk::AttachmentDescription::builder()
+                .format(format)
+                .load_op(vk::AttachmentLoadOp::LOAD)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .initial_layout(final_layout)
+                .final_layout(final_layout)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .build(),
+        ];
+
+        let color_attachment_references = [vk::AttachmentReference {
+            attachment: 0,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        }];
+
+        let subpasses = [
+            vk::SubpassDescription::builder()
+                .color_attachments(&color_attachment_references)
+                .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+                .build()
+        ];
+
+        // after tonemapping wrote the image, and after captures and screenshots copied it:
+        let subpass_dependencies = [
+            vk::SubpassDependency::builder()
+                .src_subpass(vk::SUBPASS_EXTERNAL)
+                .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::TRANSFER)
+                .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .dst_subpass(0)
+                .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .build()
+        ];
+
+        let render_pass_info = vk::RenderPassCreateInfo::builder()
+            .attachments(&attachments)
+            .subpasses(&subpasses)
+            .dependencies(&subpass_dependencies);
+
+        let render_pass = unsafe {
+            device.logical_device.create_render_pass(&render_pass_info, None)?
+        };
+
+        Ok(render_pass)
+    }
+
+    /// Whether the pass was made for images like `target`'s, it has to be made again with `fit` if not.
+    pub fn fits(&self, target: &RenderTarget) -> bool {
+        self.format == target.format()
+            && self.final_layout == target.final_layout()
+            && self.encoding == target.output_encoding()
+    }
+
+    /// Creates the pass again for `target`'s images, the device has to be idle.
+    pub fn fit(&mut self, device: &RendererDevice, target: &RenderTarget) -> Result<()> {
+        if self.fits(target) {
+            return Ok(());
+        }
+
+        unsafe {
+            self.cleanup_pass(device);
+        };
+
+        self.create_pass(device, target)
+    }
+
+    /// Writes what's drawn into the buffers of `slot`, whose frame is being recorded. Only the last data written
+    /// before the frame ends is drawn.
+    pub fn write(&mut self, device: &RendererDevice, slot: usize, data: OverlayDrawData) -> Result<()> {
+        if data.draws.is_empty() {
+            self.pending = None;
+
+            return Ok(());
+        }
+
+        Self::write_buffer(device, &mut self.vertex_buffers[slot], "overlay vertices", vk::BufferUsageFlags::VERTEX_BUFFER, &data.vertices)?;
+        Self::write_buffer(device, &mut self.index_buffers[slot], "overlay indices", vk::BufferUsageFlags::INDEX_BUFFER, &data.indices)?;
+
+        self.pending = Some(data);
+
+        Ok(())
+    }
+
+    // the slot's frame finished on the GPU, so its buffer can be replaced by a bigger one:
+    pub(crate) fn write_buffer<T: Copy>(
+        device: &RendererDevice,
+        buffer: &mut Option<RendererBuffer>,
+        name: &str,
+        usage: vk::BufferUsageFlags,
+        data: &[T]
+    ) -> Result<()> {
+        let size = mem::size_of_val(data) as vk::DeviceSize;
+
+        if let Some(mut small) = buffer.take_if(|buffer| buffer.size < size) {
+            unsafe {
+                small.cleanup(device);
+            };
+        }
+
+        let buffer = match buffer {
+            Some(buffer) => buffer,
+            None => {
+                let length = data.len().next_power_of_two().max(MIN_BUFFER_LENGTH);
+
+                buffer.insert(RendererBuffer::new(
+                    device,
+                    name,
+                    (length * mem::size_of::<T>()) as vk::DeviceSize,
+                    usage,
+                    MemoryLocation::CpuToGpu,
+                )?)
+            }
+        };
+
+        buffer.write(data)
+    }
+
+    /// Records the pass into `framebuffer`, when something was written for `slot`.
+    pub fn record<B: Backend>(
+        &mut self,
+        backend: &B,
+        command_buffer: vk::CommandBuffer,
+        slot: usize,
+        framebuffer: vk::Framebuffer,
+        extent: vk::Extent2D,
+        paper_white: f32
+    ) {
+        let data = match self.pending.take() {
+            None => return,
+            Some(data) => data
+        };
+
+        let (vertex_buffer, index_buffer) = match (&self.vertex_buffers[slot], &self.index_buffers[slot]) {
+            (Some(vertex_buffer), Some(index_buffer)) => (vertex_buffer.buffer, index_buffer.buffer),
+            _ => return,
+        };
+
+        // from the overlay's units to -1 to 1 over the target:
+        let scale = [2.0 / data.size[0], 2.0 / data.size[1]];
+
+        let constants = OverlayConstants {
+            scale,
+            translate: [-1.0 - data.origin[0] * scale[0], -1.0 - data.origin[1] * scale[1]],
+            encoding: match self.encoding {
+                OutputEncoding::Linear => 0,
+                OutputEncoding::Srgb => 1,
+                OutputEncoding::Pq => 2,
+                OutputEncoding::ScRgb => 3,
+            },
+            paper_white,
+        };
+
+        let constants_bytes = unsafe {
+            slice::from_raw_parts(&constants as *const OverlayConstants as *const u8, mem::size_of::<OverlayConstants>())
+        };
+
+        let render_pass_begin_info = vk::RenderPassBeginInfo::builder()
+            .render_pass(self.render_pass)
+            .framebuffer(framebuffer)
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent,
+            });
+
+        let viewport = vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: extent.width as f32,
+            height: extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        };
+
+        backend.cmd_begin_render_pass(
+            command_buffer,
+            &render_pass_begin_info,
+            vk::SubpassContents::INLINE,
+        );
+
+        backend.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            self.pipeline.pipeline,
+        );
+
+        backend.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            self.pipeline.pipeline_layout,
+            0,
+            &[self.descriptor_set],
+            &[],
+        );
+
+        backend.cmd_push_constants(
+            command_buffer,
+            self.pipeline.pipeline_layout,
+            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            0,
+            constants_bytes,
+        );
+
+        backend.cmd_set_viewport(command_buffer, 0, &[viewport]);
+
+        backend.cmd_bind_vertex_buffers(command_buffer, 0, &[vertex_buffer], &[0]);
+        backend.cmd_bind_index_buffer(command_buffer, index_buffer, 0, vk::IndexType::UINT16);
+
+        for draw in &data.draws {
+            let scissor = match Self::scissor(draw.clip_rect, &data, extent) {
+                None => continue,
+                Some(scissor) => scissor
+            };
+
+            backend.cmd_set_scissor(command_buffer, 0, &[scissor]);
+
+            backend.cmd_draw_indexed(
+                command_buffer,
+                draw.index_count,
+                1,
+                draw.first_index,
+                draw.vertex_offset,
+                0,
+            );
+        }
+
+        backend.cmd_end_render_pass(command_buffer);
+    }
+
+    /// The clip rectangle in pixels, cut to the target. `None` when nothing of it is left.
+    fn scissor(clip_rect: [f32; 4], data: &OverlayDrawData, extent: vk::Extent2D) -> Option<vk::Rect2D> {
+        let pixel = |i: usize, max: u32| ((clip_rect[i] - data.origin[i % 2]) * data.scale[i % 2]).clamp(0.0, max as f32);
+
+        let left = pixel(0, extent.width);
+        let top = pixel(1, extent.height);
+        let right = pixel(2, extent.width);
+        let bottom = pixel(3, extent.height);
+
+        if right <= left || bottom <= top {
+            return None;
+        }
+
+        Some(vk::Rect2D {
+            offset: vk::Offset2D {
+                x: left as i32,
+                y: top as i32,
+            },
+            extent: vk::Extent2D {
+                width: (right - left).ceil() as u32,
+                height: (bottom - top).ceil() as u32,
+            },
+        })
+    }
+
+    // nulls what it destroys, `fit` can fail after it:
+    unsafe fn cleanup_pass(&mut self, device: &RendererDevice) {
+        mem::take(&mut self.pipeline).cleanup(&device.logical_device);
+
+        device.logical_device.destroy_render_pass(mem::take(&mut self.render_pass), None);
+    }
+
+    pub unsafe fn cleanup(&mut self, device: &RendererDevice) {
+        self.cleanup_pass(device);
+
+        for buffer in self.vertex_buffers.iter_mut().chain(&mut self.index_buffers).flatten() {
+            buffer.cleanup(device);
+        }
+
+        device.logical_device.destroy_descriptor_pool(self.descriptor_pool, None);
+        device.logical_device.destroy_descriptor_set_layout(self.set_layout, None);
+
+        self.font.cleanup(device);
+    }
+}
@@ -2,53 +2,297 @@ use ash::vk;
 
 use crate::renderer::device::RendererDevice;
 use crate::renderer::shader::Shader;
+use crate::renderer::mesh::{InstanceData, Vertex};
+use crate::renderer::material::MaterialKey;
+use crate::scene::AlphaMode;
 
 use std::ffi;
 
 use anyhow::Result;
 
+/// Everything that differs between the pipelines the engine creates.
+#[derive(Clone, Default)]
+pub struct PipelineDesc<'a> {
+    pub vert_code: &'a [u32],
+    pub frag_code: &'a [u32],
+    pub vertex_bindings: &'a [vk::VertexInputBindingDescription],
+    pub vertex_attributes: &'a [vk::VertexInputAttributeDescription],
+    pub set_layouts: &'a [vk::DescriptorSetLayout],
+    pub push_constant_ranges: &'a [vk::PushConstantRange],
+    pub depth_test: bool,
+    pub depth_write: bool,
+    pub cull_mode: vk::CullModeFlags,
+    /// Alpha blending, source over destination.
+    pub blend: bool,
+    /// With `blend`, the source color is added to the destination instead and the destination's alpha is kept.
+    pub additive: bool,
+    /// No fragment shader and no color attachment, only depth is written.
+    pub depth_only: bool,
+    /// Depth bias, set with `cmd_set_depth_bias` while recording.
+    pub depth_bias: bool,
+    /// Depth is cleared to 0 and nearer fragments have greater depth.
+    pub reverse_z: bool,
+    /// Of the render pass' attachments, one when it's left empty.
+    pub samples: vk::SampleCountFlags,
+    /// Viewport and scissor, set with `cmd_set_viewport` and `cmd_set_scissor` while recording instead of covering
+    /// the extent the pipeline was created with.
+    pub dynamic_viewport: bool,
+}
+
 pub struct RendererPipeline {
     pub pipeline: vk::Pipeline,
     pub pipeline_layout: vk::PipelineLayout,
 }
 
 impl RendererPipeline {
+    /// The hard-coded triangle, it doesn't take any vertex input.
     pub fn new(
+        device: &RendererDevice,
+        extent: vk::Extent2D,
+        render_pass: vk::RenderPass,
+        samples: vk::SampleCountFlags
+    ) -> Result<RendererPipeline> {
+        Self::from_desc(device, extent, render_pass, &PipelineDesc {
+            vert_code: vk_shader_macros::include_glsl!("./shaders/default.vert"),
+            frag_code: vk_shader_macros::include_glsl!("./shaders/default.frag"),
+            blend: true,
+            samples,
+            ..Default::default()
+        })
+    }
+
+    /// Draws `Mesh`es made of `Vertex`es, seen through the camera in set 0 and placed by a model matrix push constant.
+    pub fn mesh(
+        device: &RendererDevice,
+        extent: vk::Extent2D,
+        render_pass: vk::RenderPass,
+        camera_set_layout: vk::DescriptorSetLayout,
+        samples: vk::SampleCountFlags,
+        reverse_z: bool
+    ) -> Result<RendererPipeline> {
+        let push_constant_ranges = [
+            vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::VERTEX,
+                offset: 0,
+                size: std::mem::size_of::<[f32; 16]>() as u32,
+            }
+        ];
+
+        Self::from_desc(device, extent, render_pass, &PipelineDesc {
+            vert_code: vk_shader_macros::include_glsl!("./shaders/mesh.vert"),
+            frag_code: vk_shader_macros::include_glsl!("./shaders/default.frag"),
+            vertex_bindings: &Vertex::bindings(),
+            vertex_attributes: &Vertex::attributes(),
+            set_layouts: &[camera_set_layout],
+            push_constant_ranges: &push_constant_ranges,
+            depth_test: true,
+            depth_write: true,
+            blend: true,
+            samples,
+            reverse_z,
+            ..Default::default()
+        })
+    }
+
+    /// Draws `Mesh`es seen through the camera in set 0, with a PBR material in set 1, the clustered lights in set 2
+    /// and the shadow maps in set 3.
+    ///
+    /// Every draw is instanced, with `InstanceData` bound to the second binding. The model matrix push constant
+    /// is applied before each instance's.
+    ///
+    /// `set_layouts` are those four in order, `key` picks how blending, depth writes and culling are set up.
+    pub fn pbr(
+        device: &RendererDevice,
+        extent: vk::Extent2D,
+        render_pass: vk::RenderPass,
+        set_layouts: [vk::DescriptorSetLayout; 4],
+        key: MaterialKey,
+        samples: vk::SampleCountFlags,
+        reverse_z: bool
+    ) -> Result<RendererPipeline> {
+        let push_constant_ranges = [
+            vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::VERTEX,
+                offset: 0,
+                size: std::mem::size_of::<[f32; 16]>() as u32,
+            }
+        ];
+
+        // blended surfaces are drawn last and must not hide what's behind each other:
+        let blend = key.alpha_mode == AlphaMode::Blend;
+
+        let cull_mode = if key.double_sided {
+            vk::CullModeFlags::NONE
+        } else {
+            vk::CullModeFlags::BACK
+        };
+
+        let vertex_bindings = [Vertex::bindings()[0], InstanceData::bindings()[0]];
+
+        let vertex_attributes: Vec<vk::VertexInputAttributeDescription> = Vertex::attributes().into_iter()
+            .chain(InstanceData::attributes())
+            .collect();
+
+        Self::from_desc(device, extent, render_pass, &PipelineDesc {
+            vert_code: vk_shader_macros::include_glsl!("./shaders/pbr.vert"),
+            frag_code: vk_shader_macros::include_glsl!("./shaders/pbr.frag"),
+            vertex_bindings: &vertex_bindings,
+            vertex_attributes: &vertex_attributes,
+            set_layouts: &set_layouts,
+            push_constant_ranges: &push_constant_ranges,
+            depth_test: true,
+            depth_write: !blend,
+            cull_mode,
+            blend,
+            samples,
+            reverse_z,
+            ..Default::default()
+        })
+    }
+
+    /// Renders `Vertex` positions into a shadow map, placed by a push constant with the light's view projection and the model matrix multiplied.
+    ///
+    /// Instanced like `pbr`, only the instances' model matrices are read.
+    pub fn shadow(
         device: &RendererDevice,
         extent: vk::Extent2D,
         render_pass: vk::RenderPass
     ) -> Result<RendererPipeline> {
-        let vert = Shader::from_code_vert(
-            &device.logical_device,
-            vk_shader_macros::include_glsl!("./shaders/default.vert")
-        )?;
-        let frag = Shader::from_code_frag(
-            &device.logical_device,
-            vk_shader_macros::include_glsl!("./shaders/default.frag")
-        )?;
+        let push_constant_ranges = [
+            vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::VERTEX,
+                offset: 0,
+                size: std::mem::size_of::<[f32; 16]>() as u32,
+            }
+        ];
+
+        let vertex_bindings = [Vertex::bindings()[0], InstanceData::bindings()[0]];
+
+        // only the position and the instance's model matrix are read:
+        let instance_attributes = InstanceData::attributes();
+
+        let vertex_attributes = [Vertex::attributes()[0], instance_attributes[0], instance_attributes[1], instance_attributes[2], instance_attributes[3]];
+
+        Self::from_desc(device, extent, render_pass, &PipelineDesc {
+            vert_code: vk_shader_macros::include_glsl!("./shaders/shadow.vert"),
+            vertex_bindings: &vertex_bindings,
+            vertex_attributes: &vertex_attributes,
+            push_constant_ranges: &push_constant_ranges,
+            depth_test: true,
+            depth_write: true,
+            depth_only: true,
+            depth_bias: true,
+            ..Default::default()
+        })
+    }
+
+    pub fn from_desc(
+        device: &RendererDevice,
+        extent: vk::Extent2D,
+        render_pass: vk::RenderPass,
+        desc: &PipelineDesc
+    ) -> Result<RendererPipeline> {
+        let mut shaders = vec![Shader::from_code_vert(&device.logical_device, desc.vert_code)?];
+
+        if !desc.depth_only {
+            match Shader::from_code_frag(&device.logical_device, desc.frag_code) {
+                Err(err) => {
+                    unsafe {
+                        shaders[0].cleanup(&device.logical_device);
+                    };
+
+                    return Err(err);
+                },
+                Ok(frag) => shaders.push(frag)
+            }
+        }
 
         let entry_point = ffi::CString::new("main").unwrap();
 
-        let shader_stages = [
-            vert.shader_stage(&entry_point),
-            frag.shader_stage(&entry_point),
-        ];
+        let shader_stages: Vec<vk::PipelineShaderStageCreateInfo> = shaders.iter()
+            .map(|shader| shader.shader_stage(&entry_point))
+            .collect();
 
-        let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder();
+        let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder()
+            .vertex_binding_descriptions(desc.vertex_bindings)
+            .vertex_attribute_descriptions(desc.vertex_attributes);
 
-        let (pipeline_layout, pipeline) = Self::create_graphics_pipeline(
+        let created = Self::create_graphics_pipeline(
             &device.logical_device,
             render_pass,
             extent,
             vertex_input_info,
-            &shader_stages
-        )?;
+            &shader_stages,
+            desc
+        );
 
         unsafe {
-            vert.cleanup(&device.logical_device);
-            frag.cleanup(&device.logical_device);
+            for shader in &shaders {
+                shader.cleanup(&device.logical_device);
+            }
         }
 
+        let (pipeline_layout, pipeline) = created?;
+
+        Ok(RendererPipeline {
+            pipeline,
+            pipeline_layout,
+        })
+    }
+
+    /// A compute pipeline, there's nothing to configure but the shader and its inputs.
+    pub fn compute(
+        device: &RendererDevice,
+        code: &[u32],
+        set_layouts: &[vk::DescriptorSetLayout],
+        push_constant_ranges: &[vk::PushConstantRange]
+    ) -> Result<RendererPipeline> {
+        let shader = Shader::from_code_comp(&device.logical_device, code)?;
+
+        let entry_point = ffi::CString::new("main").unwrap();
+
+        let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(set_layouts)
+            .push_constant_ranges(push_constant_ranges);
+
+        let pipeline_layout = match unsafe { device.logical_device.create_pipeline_layout(&pipeline_layout_info, None) } {
+            Err(err) => {
+                unsafe {
+                    shader.cleanup(&device.logical_device);
+                };
+
+                return Err(err.into());
+            },
+            Ok(pipeline_layout) => pipeline_layout
+        };
+
+        let pipeline_info = vk::ComputePipelineCreateInfo::builder()
+            .stage(shader.shader_stage(&entry_point))
+            .layout(pipeline_layout);
+
+        let created = unsafe {
+            device.logical_device.create_compute_pipelines(
+                vk::PipelineCache::null(),
+                &[pipeline_info.build()],
+                None,
+            )
+        };
+
+        unsafe {
+            shader.cleanup(&device.logical_device);
+        };
+
+        let pipeline = match created {
+            Err((_, err)) => {
+                unsafe {
+                    device.logical_device.destroy_pipeline_layout(pipeline_layout, None);
+                };
+
+                return Err(err.into());
+            },
+            Ok(pipelines) => pipelines[0]
+        };
+
         Ok(RendererPipeline {
             pipeline,
             pipeline_layout,
@@ -60,7 +304,8 @@ impl RendererPipeline {
         render_pass: vk::RenderPass,
         extent: vk::Extent2D,
         vertex_input_info: vk::PipelineVertexInputStateCreateInfoBuilder,
-        shader_stages: &[vk::PipelineShaderStageCreateInfo]
+        shader_stages: &[vk::PipelineShaderStageCreateInfo],
+        desc: &PipelineDesc
     ) -> Result<(vk::PipelineLayout, vk::Pipeline)> {
         // input:
 
@@ -76,7 +321,7 @@ impl RendererPipeline {
                 width: extent.width as f32,
                 height: extent.height as f32,
                 min_depth: 0.0,
-                max_depth: 0.0,
+                max_depth: 1.0,
             }
         ];
 
@@ -99,40 +344,89 @@ impl RendererPipeline {
         let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
             .line_width(1.0)
             .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
-            .cull_mode(vk::CullModeFlags::NONE)
+            .cull_mode(desc.cull_mode)
+            .depth_bias_enable(desc.depth_bias)
             .polygon_mode(vk::PolygonMode::FILL);
 
         // multisampler:
 
+        let samples = if desc.samples.is_empty() {
+            vk::SampleCountFlags::TYPE_1
+        } else {
+            desc.samples
+        };
+
         let multisampler_info = vk::PipelineMultisampleStateCreateInfo::builder()
-            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+            .rasterization_samples(samples);
+
+        // depth:
+
+        let depth_compare_op = if desc.reverse_z {
+            vk::CompareOp::GREATER_OR_EQUAL
+        } else {
+            vk::CompareOp::LESS_OR_EQUAL
+        };
+
+        let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(desc.depth_test)
+            .depth_write_enable(desc.depth_write)
+            .depth_compare_op(depth_compare_op);
 
         // color blend:
 
-        let color_blend_attachments = [
-            vk::PipelineColorBlendAttachmentState::builder()
-                .blend_enable(true)
-                .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
-                .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
-                .color_blend_op(vk::BlendOp::ADD)
-                .src_alpha_blend_factor(vk::BlendFactor::SRC_ALPHA)
-                .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
-                .alpha_blend_op(vk::BlendOp::ADD)
-                .color_write_mask(
-                    vk::ColorComponentFlags::R
-                        | vk::ColorComponentFlags::G
-                        | vk::ColorComponentFlags::B
-                        | vk::ColorComponentFlags::A,
-                )
-                .build()
-        ];
+        let (src_color_blend_factor, dst_color_blend_factor, src_alpha_blend_factor, dst_alpha_blend_factor) = if desc.additive {
+            (vk::BlendFactor::ONE, vk::BlendFactor::ONE, vk::BlendFactor::ZERO, vk::BlendFactor::ONE)
+        } else {
+            (vk::BlendFactor::SRC_ALPHA, vk::BlendFactor::ONE_MINUS_SRC_ALPHA, vk::BlendFactor::SRC_ALPHA, vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        };
+
+        let color_blend_attachment = vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(desc.blend)
+            .src_color_blend_factor(src_color_blend_factor)
+            .dst_color_blend_factor(dst_color_blend_factor)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(src_alpha_blend_factor)
+            .dst_alpha_blend_factor(dst_alpha_blend_factor)
+            .alpha_blend_op(vk::BlendOp::ADD)
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+            )
+            .build();
+
+        // depth only passes have no color attachment to blend into:
+        let color_blend_attachments = if desc.depth_only {
+            vec![]
+        } else {
+            vec![color_blend_attachment]
+        };
 
         let color_blend_info = vk::PipelineColorBlendStateCreateInfo::builder()
             .attachments(&color_blend_attachments);
 
+        // dynamic state:
+
+        let mut dynamic_states = vec![];
+
+        if desc.depth_bias {
+            dynamic_states.push(vk::DynamicState::DEPTH_BIAS);
+        }
+
+        if desc.dynamic_viewport {
+            dynamic_states.extend([vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
+        }
+
+        let dynamic_info = vk::PipelineDynamicStateCreateInfo::builder()
+            .dynamic_states(&dynamic_states);
+
         // pipeline:
 
-        let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder();
+        let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(desc.set_layouts)
+            .push_constant_ranges(desc.push_constant_ranges);
+
         let pipeline_layout = unsafe {
             device.create_pipeline_layout(&pipeline_layout_info, None)?
         };
@@ -144,7 +438,9 @@ impl RendererPipeline {
             .viewport_state(&viewport_info)
             .rasterization_state(&rasterizer_info)
             .multisample_state(&multisampler_info)
+            .depth_stencil_state(&depth_stencil_info)
             .color_blend_state(&color_blend_info)
+            .dynamic_state(&dynamic_info)
             .layout(pipeline_layout)
             .render_pass(render_pass)
             .subpass(0);
@@ -154,7 +450,7 @@ impl RendererPipeline {
                 vk::PipelineCache::null(),
                 &[pipeline_info.build()],
                 None,
-            ).unwrap()
+            ).map_err(|(_, err)| err)?
         }[0];
 
         Ok((pipeline_layout, pipeline))
@@ -164,4 +460,4 @@ impl RendererPipeline {
         device.destroy_pipeline(self.pipeline, None);
         device.destroy_pipeline_layout(self.pipeline_layout, None);
     }
-}
\ No newline at end of file
+}
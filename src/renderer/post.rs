@@ -0,0 +1,747 @@
+//! Fullscreen effects that run on the HDR frame, between the scene render pass and tonemapping.
+//!
+//! Enabled effects run in the order they're in. Each one reads one of the two [`RendererHdr`] targets and writes
+//! the other, so effects don't need any memory of their own. Bloom takes two passes: it blurs what's bright into
+//! the other target, then adds that back on top of the frame, which stays where it was.
+//!
+//! The effects see scene colors, which can go past 1. That's why the 3D LUT is indexed in log2 space, see [`Lut`].
+
+use ash::vk;
+
+use crate::renderer::device::RendererDevice;
+use crate::renderer::backend::Backend;
+use crate::renderer::command_pools::CommandPools;
+use crate::renderer::image::RendererImage;
+use crate::renderer::pipeline::{PipelineDesc, RendererPipeline};
+use crate::renderer::hdr::{RendererHdr, HDR_FORMAT};
+
+use glam::Vec3;
+
+use std::{mem, slice};
+
+use anyhow::Result;
+
+/// Adds a blurred copy of everything brighter than `threshold` back onto the frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bloom {
+    pub threshold: f32,
+    pub intensity: f32,
+    /// How far apart the blur's taps are, in texels. It reaches 4 taps to either side.
+    pub radius: f32,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Bloom {
+            threshold: 1.0,
+            intensity: 0.5,
+            radius: 2.0,
+        }
+    }
+}
+
+/// Smooths jagged edges by blurring along them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fxaa {
+    /// How much of the local contrast in luma has to change for it to count as an edge.
+    pub edge_threshold: f32,
+    /// Contrast below this is never an edge, which leaves dark areas alone.
+    pub edge_threshold_min: f32,
+    /// The longest blur along an edge, in texels.
+    pub span_max: f32,
+}
+
+impl Default for Fxaa {
+    fn default() -> Self {
+        Fxaa {
+            edge_threshold: 0.125,
+            edge_threshold_min: 0.0312,
+            span_max: 8.0,
+        }
+    }
+}
+
+/// Darkens towards the corners.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vignette {
+    /// How much darker the corners get, from 0 to 1.
+    pub intensity: f32,
+    /// Where the darkening starts, from 0 in the middle to 1 in the corners.
+    pub radius: f32,
+}
+
+impl Default for Vignette {
+    fn default() -> Self {
+        Vignette {
+            intensity: 0.4,
+            radius: 0.5,
+        }
+    }
+}
+
+/// Moves red and blue apart towards the edges, like a cheap lens would.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChromaticAberration {
+    /// How far apart red and blue are at the edges, in UV.
+    pub strength: f32,
+}
+
+impl Default for ChromaticAberration {
+    fn default() -> Self {
+        ChromaticAberration {
+            strength: 0.01,
+        }
+    }
+}
+
+/// Noise that changes every frame and is as strong as the light it's on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FilmGrain {
+    pub intensity: f32,
+}
+
+impl Default for FilmGrain {
+    fn default() -> Self {
+        FilmGrain {
+            intensity: 0.1,
+        }
+    }
+}
+
+/// Grades colors through the renderer's LUT, see [`VulkanRenderer::set_lut`](crate::VulkanRenderer::set_lut).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColorGrade {
+    /// Mixes between the colors as they were at 0 and as graded at 1.
+    pub strength: f32,
+}
+
+impl Default for ColorGrade {
+    fn default() -> Self {
+        ColorGrade {
+            strength: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Effect {
+    Bloom(Bloom),
+    Fxaa(Fxaa),
+    Vignette(Vignette),
+    ChromaticAberration(ChromaticAberration),
+    FilmGrain(FilmGrain),
+    ColorGrade(ColorGrade),
+}
+
+/// An effect in the stack, disabled ones keep their place but are skipped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PostEffect {
+    pub effect: Effect,
+    pub enabled: bool,
+}
+
+impl PostEffect {
+    pub fn new(effect: Effect) -> PostEffect {
+        PostEffect {
+            effect,
+            enabled: true,
+        }
+    }
+}
+
+/// A 3D color lookup table, `size` colors along each side with red changing fastest, then green, then blue.
+///
+/// Both what a color looks up and what it finds are encoded in log2 space, from black up to about 64. So the
+/// lattice point `(r, g, b)` holds the graded [`Lut::decode`] of `(r, g, b) / (size - 1)`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lut {
+    pub size: u32,
+    pub colors: Vec<Vec3>,
+}
+
+impl Lut {
+    /// Colors this far below 1 are where the log2 encoding bends down into black.
+    pub const TOE: f32 = 1.0 / 1024.0;
+    /// How many stops the encoding covers above the toe.
+    pub const STOPS: f32 = 16.0;
+
+    /// Leaves every color as it is, even with only 2 colors along each side.
+    pub fn identity(size: u32) -> Lut {
+        Self::from_fn(size, |color| color)
+    }
+
+    /// Bakes `grade` into a LUT by running it on every lattice point.
+    pub fn from_fn<F: Fn(Vec3) -> Vec3>(size: u32, grade: F) -> Lut {
+        let size = size.max(2);
+
+        let mut colors = Vec::with_capacity((size * size * size) as usize);
+
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    let encoded = Vec3::new(r as f32, g as f32, b as f32) / (size - 1) as f32;
+
+                    colors.push(grade(Self::decode(encoded)));
+                }
+            }
+        }
+
+        Lut {
+            size,
+            colors,
+        }
+    }
+
+    /// Where `color` is looked up, each channel from 0 to 1.
+    pub fn encode(color: Vec3) -> Vec3 {
+        let encode = |channel: f32| ((1.0 + channel.max(0.0) / Self::TOE).log2() / Self::STOPS).clamp(0.0, 1.0);
+
+        Vec3::new(encode(color.x), encode(color.y), encode(color.z))
+    }
+
+    pub fn decode(encoded: Vec3) -> Vec3 {
+        let decode = |channel: f32| Self::TOE * ((channel * Self::STOPS).exp2() - 1.0);
+
+        Vec3::new(decode(encoded.x), decode(encoded.y), decode(encoded.z))
+    }
+
+    /// The encoded colors as `A2B10G10R10_UNORM_PACK32` texels.
+    fn texels(&self) -> Vec<u8> {
+        let mut texels = Vec::with_capacity(self.colors.len() * 4);
+
+        for color in &self.colors {
+            let encoded = Self::encode(*color) * 1023.0;
+
+            let texel = (encoded.x.round() as u32)
+                | (encoded.y.round() as u32) << 10
+                | (encoded.z.round() as u32) << 20
+                | 3 << 30;
+
+            texels.extend_from_slice(&texel.to_le_bytes());
+        }
+
+        texels
+    }
+}
+
+/// The effect's settings as the shaders read them, `params` means something else to each of them.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct EffectConstants {
+    texel_size: [f32; 2],
+    frame: u32,
+    pass: u32,
+    params: [f32; 4],
+}
+
+#[derive(Default)]
+pub struct RendererPostProcess {
+    pub effects: Vec<PostEffect>,
+    pub extent: vk::Extent2D,
+    /// Overwrites its target.
+    pub render_pass: vk::RenderPass,
+    /// Keeps what's in its target, for blending onto it.
+    pub blend_render_pass: vk::RenderPass,
+    /// Renders into the HDR target with the same index.
+    pub framebuffers: [vk::Framebuffer; 2],
+    pub sampler: vk::Sampler,
+    pub lut_sampler: vk::Sampler,
+    pub lut: RendererImage,
+    pub set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    /// Samples the HDR target with the same index and the LUT.
+    pub descriptor_sets: [vk::DescriptorSet; 2],
+    pub bloom: RendererPipeline,
+    pub bloom_composite: RendererPipeline,
+    pub fxaa: RendererPipeline,
+    pub vignette: RendererPipeline,
+    pub chromatic_aberration: RendererPipeline,
+    pub film_grain: RendererPipeline,
+    pub color_grade: RendererPipeline,
+    /// Counts recorded frames, so the grain changes every frame.
+    frame: u32,
+}
+
+impl RendererPostProcess {
+    /// Starts out without any effects and with an identity LUT.
+    pub fn new(
+        device: &RendererDevice,
+        command_pools: &CommandPools,
+        hdr: &RendererHdr,
+        extent: vk::Extent2D
+    ) -> Result<RendererPostProcess> {
+        let lut = Self::create_lut(device, command_pools, &Lut::identity(2))?;
+
+        let null_pipeline = || RendererPipeline {
+            pipeline: vk::Pipeline::null(),
+            pipeline_layout: vk::PipelineLayout::null(),
+        };
+
+        let mut post = RendererPostProcess {
+            effects: vec![],
+            extent,
+            render_pass: vk::RenderPass::null(),
+            blend_render_pass: vk::RenderPass::null(),
+            framebuffers: [vk::Framebuffer::null(); 2],
+            sampler: vk::Sampler::null(),
+            lut_sampler: vk::Sampler::null(),
+            lut,
+            set_layout: vk::DescriptorSetLayout::null(),
+            descriptor_pool: vk::DescriptorPool::null(),
+            descriptor_sets: [vk::DescriptorSet::null(); 2],
+            bloom: null_pipeline(),
+            bloom_composite: null_pipeline(),
+            fxaa: null_pipeline(),
+            vignette: null_pipeline(),
+            chromatic_aberration: null_pipeline(),
+            film_grain: null_pipeline(),
+            color_grade: null_pipeline(),
+            frame: 0,
+        };
+
+        if let Err(err) = post.create(device, hdr) {
+            unsafe {
+                post.cleanup(device);
+            };
+
+            return Err(err);
+        }
+
+        Ok(post)
+    }
+
+    fn create(&mut self, device: &RendererDevice, hdr: &RendererHdr) -> Result<()> {
+        // render passes, both are compatible with the same framebuffers:
+        self.render_pass = Self::create_render_pass(device, false)?;
+        self.blend_render_pass = Self::create_render_pass(device, true)?;
+
+        for (i, target) in hdr.targets.iter().enumerate() {
+            let attachments = [target.image_view];
+
+            let framebuffer_info = vk::FramebufferCreateInfo::builder()
+                .render_pass(self.render_pass)
+                .attachments(&attachments)
+                .width(self.extent.width)
+                .height(self.extent.height)
+                .layers(1);
+
+            self.framebuffers[i] = unsafe {
+                device.logical_device.create_framebuffer(&framebuffer_info, None)?
+            };
+        }
+
+        // samplers, effects read between texels:
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+
+        self.sampler = unsafe {
+            device.logical_device.create_sampler(&sampler_info, None)?
+        };
+
+        self.lut_sampler = unsafe {
+            device.logical_device.create_sampler(&sampler_info, None)?
+        };
+
+        // descriptors:
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .build(),
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .build(),
+        ];
+
+        let set_layout_info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .bindings(&bindings);
+
+        self.set_layout = unsafe {
+            device.logical_device.create_descriptor_set_layout(&set_layout_info, None)?
+        };
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: 4,
+            }
+        ];
+
+        let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(2)
+            .pool_sizes(&pool_sizes);
+
+        self.descriptor_pool = unsafe {
+            device.logical_device.create_descriptor_pool(&pool_info, None)?
+        };
+
+        let set_layouts = [self.set_layout; 2];
+
+        let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(self.descriptor_pool)
+            .set_layouts(&set_layouts);
+
+        let descriptor_sets = unsafe {
+            device.logical_device.allocate_descriptor_sets(&allocate_info)?
+        };
+
+        for (i, target) in hdr.targets.iter().enumerate() {
+            self.descriptor_sets[i] = descriptor_sets[i];
+
+            let image_infos = [
+                vk::DescriptorImageInfo {
+                    sampler: self.sampler,
+                    image_view: target.image_view,
+                    image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                }
+            ];
+
+            let writes = [
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(self.descriptor_sets[i])
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(&image_infos)
+                    .build()
+            ];
+
+            unsafe {
+                device.logical_device.update_descriptor_sets(&writes, &[]);
+            };
+        }
+
+        self.write_lut(device);
+
+        // pipelines:
+        self.bloom = self.create_pipeline(device, vk_shader_macros::include_glsl!("./shaders/bloom.frag"), false)?;
+        self.bloom_composite = self.create_pipeline(device, vk_shader_macros::include_glsl!("./shaders/bloom.frag"), true)?;
+        self.fxaa = self.create_pipeline(device, vk_shader_macros::include_glsl!("./shaders/fxaa.frag"), false)?;
+        self.vignette = self.create_pipeline(device, vk_shader_macros::include_glsl!("./shaders/vignette.frag"), false)?;
+        self.chromatic_aberration = self.create_pipeline(
+            device,
+            vk_shader_macros::include_glsl!("./shaders/chromatic_aberration.frag"),
+            false,
+        )?;
+        self.film_grain = self.create_pipeline(device, vk_shader_macros::include_glsl!("./shaders/film_grain.frag"), false)?;
+        self.color_grade = self.create_pipeline(device, vk_shader_macros::include_glsl!("./shaders/lut.frag"), false)?;
+
+        Ok(())
+    }
+
+    /// A fullscreen triangle through `frag_code`, `additive` ones add onto their target instead of replacing it.
+    fn create_pipeline(&self, device: &RendererDevice, frag_code: &[u32], additive: bool) -> Result<RendererPipeline> {
+        let push_constant_ranges = [
+            vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                offset: 0,
+                size: mem::size_of::<EffectConstants>() as u32,
+            }
+        ];
+
+        RendererPipeline::from_desc(device, self.extent, self.render_pass, &PipelineDesc {
+            vert_code: vk_shader_macros::include_glsl!("./shaders/fullscreen.vert"),
+            frag_code,
+            set_layouts: &[self.set_layout],
+            push_constant_ranges: &push_constant_ranges,
+            blend: additive,
+            additive,
+            ..Default::default()
+        })
+    }
+
+    /// Targets are left ready to be sampled by whatever comes next, another effect or tonemapping.
+    fn create_render_pass(device: &RendererDevice, blend: bool) -> Result<vk::RenderPass> {
+        let (load_op, initial_layout) = if blend {
+            (vk::AttachmentLoadOp::LOAD, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        } else {
+            (vk::AttachmentLoadOp::DONT_CARE, vk::ImageLayout::UNDEFINED)
+        };
+
+        let attachments = [
+            vk::AttachmentDescription::builder()
+                .format(HDR_FORMAT)
+                .load_op(load_op)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .initial_layout(initial_layout)
+                .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .build(),
+        ];
+
+        let color_attachment_references = [vk::AttachmentReference {
+            attachment: 0,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        }];
+
+        let subpasses = [
+            vk::SubpassDescription::builder()
+                .color_attachments(&color_attachment_references)
+                .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+                .build()
+        ];
+
+        // the target may still be read by the pass before, or have been written by it when blending onto it:
+        let subpass_dependencies = [
+            vk::SubpassDependency::builder()
+                .src_subpass(vk::SUBPASS_EXTERNAL)
+                .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::FRAGMENT_SHADER)
+                .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .dst_subpass(0)
+                .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .build(),
+            vk::SubpassDependency::builder()
+                .src_subpass(0)
+                .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .dst_subpass(vk::SUBPASS_EXTERNAL)
+                .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ)
+                .build(),
+        ];
+
+        let render_pass_info = vk::RenderPassCreateInfo::builder()
+            .attachments(&attachments)
+            .subpasses(&subpasses)
+            .dependencies(&subpass_dependencies);
+
+        let render_pass = unsafe {
+            device.logical_device.create_render_pass(&render_pass_info, None)?
+        };
+
+        Ok(render_pass)
+    }
+
+    fn create_lut(device: &RendererDevice, command_pools: &CommandPools, lut: &Lut) -> Result<RendererImage> {
+        let expected = (lut.size * lut.size * lut.size) as usize;
+
+        if lut.size < 2 || lut.colors.len() != expected {
+            anyhow::bail!("A LUT of size {} needs {} colors, not {}", lut.size, expected, lut.colors.len());
+        }
+
+        let mut image = RendererImage::volume(
+            device,
+            "lut",
+            vk::Extent2D {
+                width: lut.size,
+                height: lut.size,
+            },
+            lut.size,
+            vk::Format::A2B10G10R10_UNORM_PACK32,
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+        )?;
+
+        if let Err(err) = image.upload(device, command_pools, &lut.texels()) {
+            unsafe {
+                image.cleanup(device);
+            };
+
+            return Err(err);
+        }
+
+        Ok(image)
+    }
+
+    fn write_lut(&self, device: &RendererDevice) {
+        let image_infos = [
+            vk::DescriptorImageInfo {
+                sampler: self.lut_sampler,
+                image_view: self.lut.image_view,
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            }
+        ];
+
+        let writes: Vec<vk::WriteDescriptorSet> = self.descriptor_sets.iter()
+            .map(|set| {
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(*set)
+                    .dst_binding(1)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(&image_infos)
+                    .build()
+            })
+            .collect();
+
+        unsafe {
+            device.logical_device.update_descriptor_sets(&writes, &[]);
+        };
+    }
+
+    /// Replaces the LUT, the device must not be using the one before anymore.
+    pub fn set_lut(&mut self, device: &RendererDevice, command_pools: &CommandPools, lut: &Lut) -> Result<()> {
+        let image = Self::create_lut(device, command_pools, lut)?;
+
+        unsafe {
+            self.lut.cleanup(device);
+        };
+
+        self.lut = image;
+
+        self.write_lut(device);
+
+        Ok(())
+    }
+
+    /// Records the enabled effects after the scene render pass, returns the index of the HDR target they left the
+    /// frame in.
+    pub fn record<B: Backend>(&mut self, backend: &B, command_buffer: vk::CommandBuffer) -> usize {
+        self.frame = self.frame.wrapping_add(1);
+
+        let mut source = 0;
+
+        for effect in self.effects.iter().filter(|effect| effect.enabled) {
+            let (pipeline, params) = match effect.effect {
+                Effect::Bloom(bloom) => {
+                    let params = [bloom.threshold, bloom.intensity, bloom.radius, 0.0];
+
+                    // blurred into the other target and added back, so the frame doesn't move:
+                    self.pass(backend, command_buffer, &self.bloom, false, source, self.constants(0, params));
+                    self.pass(backend, command_buffer, &self.bloom_composite, true, 1 - source, self.constants(1, params));
+
+                    continue;
+                },
+                Effect::Fxaa(fxaa) => (&self.fxaa, [fxaa.edge_threshold, fxaa.edge_threshold_min, fxaa.span_max, 0.0]),
+                Effect::Vignette(vignette) => (&self.vignette, [vignette.intensity, vignette.radius, 0.0, 0.0]),
+                Effect::ChromaticAberration(aberration) => (&self.chromatic_aberration, [aberration.strength, 0.0, 0.0, 0.0]),
+                Effect::FilmGrain(grain) => (&self.film_grain, [grain.intensity, 0.0, 0.0, 0.0]),
+                Effect::ColorGrade(grade) => (&self.color_grade, [grade.strength, 0.0, 0.0, 0.0]),
+            };
+
+            self.pass(backend, command_buffer, pipeline, false, source, self.constants(0, params));
+
+            source = 1 - source;
+        }
+
+        source
+    }
+
+    fn constants(&self, pass: u32, params: [f32; 4]) -> EffectConstants {
+        EffectConstants {
+            texel_size: [1.0 / self.extent.width as f32, 1.0 / self.extent.height as f32],
+            frame: self.frame,
+            pass,
+            params,
+        }
+    }
+
+    /// Draws `pipeline` into the target that isn't `source`, `blend` keeps what's in it.
+    fn pass<B: Backend>(
+        &self,
+        backend: &B,
+        command_buffer: vk::CommandBuffer,
+        pipeline: &RendererPipeline,
+        blend: bool,
+        source: usize,
+        constants: EffectConstants
+    ) {
+        let constants_bytes = unsafe {
+            slice::from_raw_parts(&constants as *const EffectConstants as *const u8, mem::size_of::<EffectConstants>())
+        };
+
+        let render_pass = if blend {
+            self.blend_render_pass
+        } else {
+            self.render_pass
+        };
+
+        let render_pass_begin_info = vk::RenderPassBeginInfo::builder()
+            .render_pass(render_pass)
+            .framebuffer(self.framebuffers[1 - source])
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: self.extent,
+            });
+
+        backend.cmd_begin_render_pass(
+            command_buffer,
+            &render_pass_begin_info,
+            vk::SubpassContents::INLINE,
+        );
+
+        backend.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            pipeline.pipeline,
+        );
+
+        backend.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            pipeline.pipeline_layout,
+            0,
+            &[self.descriptor_sets[source]],
+            &[],
+        );
+
+        backend.cmd_push_constants(
+            command_buffer,
+            pipeline.pipeline_layout,
+            vk::ShaderStageFlags::FRAGMENT,
+            0,
+            constants_bytes,
+        );
+
+        backend.cmd_draw(command_buffer, 3, 1, 0, 0);
+
+        backend.cmd_end_render_pass(command_buffer);
+    }
+
+    /// Builds what depends on the HDR targets again for targets of `extent`, keeping the effects and the LUT.
+    pub fn resize(&mut self, device: &RendererDevice, hdr: &RendererHdr, extent: vk::Extent2D) -> Result<()> {
+        unsafe {
+            self.cleanup_targets(device);
+        };
+
+        self.extent = extent;
+
+        self.create(device, hdr)
+    }
+
+    pub unsafe fn cleanup(&mut self, device: &RendererDevice) {
+        self.cleanup_targets(device);
+
+        self.lut.cleanup(device);
+    }
+
+    /// Everything but the LUT. Leaves null handles behind, so it can run again after `create` failed halfway.
+    unsafe fn cleanup_targets(&mut self, device: &RendererDevice) {
+        for pipeline in [
+            &mut self.bloom,
+            &mut self.bloom_composite,
+            &mut self.fxaa,
+            &mut self.vignette,
+            &mut self.chromatic_aberration,
+            &mut self.film_grain,
+            &mut self.color_grade,
+        ] {
+            mem::take(pipeline).cleanup(&device.logical_device);
+        }
+
+        // the sets go with their pool:
+        self.descriptor_sets = Default::default();
+
+        device.logical_device.destroy_descriptor_pool(mem::take(&mut self.descriptor_pool), None);
+        device.logical_device.destroy_descriptor_set_layout(mem::take(&mut self.set_layout), None);
+        device.logical_device.destroy_sampler(mem::take(&mut self.lut_sampler), None);
+        device.logical_device.destroy_sampler(mem::take(&mut self.sampler), None);
+
+        for framebuffer in mem::take(&mut self.framebuffers) {
+            device.logical_device.destroy_framebuffer(framebuffer, None);
+        }
+
+        device.logical_device.destroy_render_pass(mem::take(&mut self.blend_render_pass), None);
+        device.logical_device.destroy_render_pass(mem::take(&mut self.render_pass), None);
+    }
+}
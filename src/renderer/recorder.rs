@@ -0,0 +1,192 @@
+use ash::vk;
+
+use crate::renderer::device::RendererDevice;
+use crate::renderer::pipeline::RendererPipeline;
+use crate::renderer::frame::Frame;
+use crate::renderer::mesh::{IndirectCommands, Mesh};
+use crate::renderer::material::{MaterialKey, MaterialPipelines, RendererMaterials};
+use crate::renderer::buffer::RendererBuffer;
+use crate::renderer::batches::Batch;
+use crate::renderer::backend::Backend;
+
+use glam::Mat4;
+
+use std::ops::Range;
+
+use anyhow::Result;
+
+/// What drawing into the render pass of a frame needs, borrowed from a `VulkanRenderer`.
+///
+/// Unlike the renderer, it can be shared between threads, see [`super::VulkanRenderer::record_parallel`]. It records
+/// through a [`Backend`], the renderer's device unless it's made by hand around another one.
+pub struct Recorder<'a, B: Backend = RendererDevice> {
+    pub device: &'a B,
+    pub mesh_pipeline: &'a RendererPipeline,
+    pub material_pipelines: &'a MaterialPipelines,
+    /// The sets of each frame slot, bound ahead of a material's.
+    pub camera_sets: &'a [vk::DescriptorSet],
+    pub light_sets: &'a [vk::DescriptorSet],
+    pub shadow_sets: &'a [vk::DescriptorSet],
+    /// Holds one identity instance, for the draws that aren't instanced.
+    pub single_instance: vk::Buffer,
+}
+
+impl<'a, B: Backend> Recorder<'a, B> {
+    pub fn draw_mesh(&self, frame: &Frame, mesh: &Mesh, model: Mat4) {
+        self.device.cmd_bind_pipeline(
+            frame.command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            self.mesh_pipeline.pipeline,
+        );
+
+        self.device.cmd_bind_descriptor_sets(
+            frame.command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            self.mesh_pipeline.pipeline_layout,
+            0,
+            &[self.camera_sets[frame.slot]],
+            &[],
+        );
+
+        self.push_model(frame, self.mesh_pipeline, model);
+
+        mesh.draw(self.device, frame.command_buffer);
+    }
+
+    /// Draws a mesh shaded by one of `materials`, through the PBR pipeline that fits it.
+    ///
+    /// `material` is an index into the materials `materials` was created from, `None` uses the default material.
+    pub fn draw_material_mesh(
+        &self,
+        frame: &Frame,
+        mesh: &Mesh,
+        model: Mat4,
+        materials: &RendererMaterials,
+        material: Option<usize>
+    ) {
+        let pipeline = self.bind_material(frame, materials, material);
+
+        self.push_model(frame, pipeline, model);
+
+        mesh.draw_instanced(self.device, frame.command_buffer, self.single_instance, 0..1);
+    }
+
+    /// Like `draw_material_mesh`, once for each `InstanceData` in `instances` at `range`.
+    ///
+    /// `instances` needs `VERTEX_BUFFER` usage, its model matrices place the instances in the world.
+    pub fn draw_material_mesh_instanced(
+        &self,
+        frame: &Frame,
+        mesh: &Mesh,
+        materials: &RendererMaterials,
+        material: Option<usize>,
+        instances: &RendererBuffer,
+        range: Range<u32>
+    ) {
+        let pipeline = self.bind_material(frame, materials, material);
+
+        self.push_model(frame, pipeline, Mat4::IDENTITY);
+
+        mesh.draw_instanced(self.device, frame.command_buffer, instances.buffer, range);
+    }
+
+    /// Like `draw_material_mesh_instanced`, with the draws read from `commands`.
+    ///
+    /// Each command's `first_instance` picks where its instances start in `instances`, see
+    /// [`Mesh::draw_indirect`] for what the device needs for that.
+    pub fn draw_material_mesh_indirect(
+        &self,
+        frame: &Frame,
+        mesh: &Mesh,
+        materials: &RendererMaterials,
+        material: Option<usize>,
+        instances: &RendererBuffer,
+        commands: &IndirectCommands
+    ) -> Result<()> {
+        let pipeline = self.bind_material(frame, materials, material);
+
+        self.push_model(frame, pipeline, Mat4::IDENTITY);
+
+        mesh.draw_indirect(self.device, frame.command_buffer, instances.buffer, commands)
+    }
+
+    /// Binds the PBR pipeline for `material` of `materials` with the frame's sets, and returns it.
+    pub fn bind_material(&self, frame: &Frame, materials: &RendererMaterials, material: Option<usize>) -> &'a RendererPipeline {
+        let index = materials.index(material);
+
+        self.bind_material_set(frame, materials.keys[index], materials.descriptor_sets[index])
+    }
+
+    pub fn bind_material_set(&self, frame: &Frame, key: MaterialKey, descriptor_set: vk::DescriptorSet) -> &'a RendererPipeline {
+        let pipeline = self.material_pipelines.get(key);
+
+        self.device.cmd_bind_pipeline(
+            frame.command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            pipeline.pipeline,
+        );
+
+        self.device.cmd_bind_descriptor_sets(
+            frame.command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            pipeline.pipeline_layout,
+            0,
+            &[
+                self.camera_sets[frame.slot],
+                descriptor_set,
+                self.light_sets[frame.slot],
+                self.shadow_sets[frame.slot],
+            ],
+            &[],
+        );
+
+        pipeline
+    }
+
+    pub fn push_model(&self, frame: &Frame, pipeline: &RendererPipeline, model: Mat4) {
+        let model = model.to_cols_array();
+
+        self.device.cmd_push_constants(
+            frame.command_buffer,
+            pipeline.pipeline_layout,
+            vk::ShaderStageFlags::VERTEX,
+            0,
+            RendererBuffer::bytes(&model),
+        );
+    }
+
+    /// Binds the material of a merged batch for its instances, `None` for batches of shadow casters which have none.
+    pub fn bind_batch(&self, frame: &Frame, batch: &Batch) -> Option<&'a RendererPipeline> {
+        let (key, descriptor_set) = batch.material?;
+
+        let pipeline = self.bind_material_set(frame, key, descriptor_set);
+
+        self.push_model(frame, pipeline, Mat4::IDENTITY);
+
+        Some(pipeline)
+    }
+
+    /// Draws a merged batch with its instances in `instance_buffer`, see [`crate::renderer::batches`].
+    pub fn draw_batch(&self, frame: &Frame, batch: &Batch, instance_buffer: vk::Buffer) {
+        if self.bind_batch(frame, batch).is_none() {
+            return;
+        }
+
+        self.device.cmd_bind_vertex_buffers(
+            frame.command_buffer,
+            0,
+            &[batch.vertex_buffer, instance_buffer],
+            &[0, 0],
+        );
+        self.device.cmd_bind_index_buffer(frame.command_buffer, batch.index_buffer, 0, vk::IndexType::UINT32);
+
+        self.device.cmd_draw_indexed(
+            frame.command_buffer,
+            batch.index_count,
+            batch.instances.end - batch.instances.start,
+            0,
+            0,
+            batch.instances.start,
+        );
+    }
+}
@@ -0,0 +1,471 @@
+//! Screenshots: the target's image as it is, copied into a buffer and saved as PNG, or as EXR for HDR targets.
+//!
+//! A screenshot is of the next frame that ends, since swapchain images can't be copied from once they're presented.
+//! The buffer is read once the frame's fence was waited for, and a thread of its own converts and writes it, so
+//! taking one doesn't hold up rendering. BGRA is swizzled into RGBA and `_UNORM` images that hold linear colors are
+//! sRGB encoded, like [`capture`](crate::renderer::capture) does. HDR10 is decoded from PQ and Rec. 2020 into linear
+//! Rec. 709 with 1.0 at 80 nits, which is what scRGB holds already.
+
+use ash::vk;
+
+use crate::renderer::backend::Backend;
+use crate::renderer::buffer::RendererBuffer;
+use crate::renderer::target::RenderTarget;
+use crate::renderer::hdr::OutputEncoding;
+
+use gpu_allocator::MemoryLocation;
+
+use std::{fs, thread};
+use std::path::{Path, PathBuf};
+use std::thread::JoinHandle;
+
+use anyhow::{Context, Result};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScreenshotFormat {
+    /// 8-bit sRGB.
+    Png,
+    /// 32-bit float linear Rec. 709, uncompressed.
+    Exr,
+}
+
+impl ScreenshotFormat {
+    /// What a target that's written with `encoding` is saved as.
+    pub fn new(encoding: OutputEncoding) -> ScreenshotFormat {
+        match encoding {
+            OutputEncoding::Pq | OutputEncoding::ScRgb => ScreenshotFormat::Exr,
+            OutputEncoding::Linear | OutputEncoding::Srgb => ScreenshotFormat::Png,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ScreenshotFormat::Png => "png",
+            ScreenshotFormat::Exr => "exr",
+        }
+    }
+}
+
+/// How big a texel of the formats screenshots can be taken of is.
+pub fn texel_size(format: vk::Format) -> Option<u32> {
+    match format {
+        vk::Format::B8G8R8A8_UNORM
+        | vk::Format::B8G8R8A8_SRGB
+        | vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SRGB
+        | vk::Format::A8B8G8R8_UNORM_PACK32
+        | vk::Format::A8B8G8R8_SRGB_PACK32
+        | vk::Format::A2B10G10R10_UNORM_PACK32
+        | vk::Format::A2R10G10B10_UNORM_PACK32 => Some(4),
+        vk::Format::R16G16B16A16_SFLOAT => Some(8),
+        _ => None,
+    }
+}
+
+/// What a frame's copy has to be saved as, until the frame has finished.
+struct Pending {
+    path: PathBuf,
+    format: vk::Format,
+    encoding: OutputEncoding,
+    extent: vk::Extent2D,
+    readback: RendererBuffer,
+}
+
+/// A screenshot's pixels, on their way to the file.
+enum Pixels {
+    Rgba8(Vec<u8>),
+    RgbaF32(Vec<f32>),
+}
+
+/// Screenshots that were asked for, copied and still being written.
+pub struct RendererScreenshots {
+    /// Where the screenshot of the next frame that ends goes.
+    pub requested: Option<PathBuf>,
+    /// The copy each frame in flight took, until it has been read.
+    pending: Vec<Option<Pending>>,
+    writers: Vec<JoinHandle<Result<PathBuf>>>,
+}
+
+impl RendererScreenshots {
+    pub fn new(count: usize) -> RendererScreenshots {
+        RendererScreenshots {
+            requested: None,
+            pending: (0..count).map(|_| None).collect(),
+            writers: vec![],
+        }
+    }
+
+    /// Asks for a screenshot of the next frame, returns where it goes: `path` with the extension of what `target`
+    /// is saved as.
+    pub fn request(&mut self, target: &RenderTarget, path: &Path) -> Result<PathBuf> {
+        if self.requested.is_some() {
+            anyhow::bail!("A screenshot is already waiting for the next frame");
+        }
+
+        if !target.usage().contains(vk::ImageUsageFlags::TRANSFER_SRC) {
+            anyhow::bail!("The target's images can't be copied from, so there can't be screenshots of them");
+        }
+
+        if texel_size(target.format()).is_none() {
+            anyhow::bail!("Don't know how to save screenshots of {:?} images", target.format());
+        }
+
+        let path = path.with_extension(ScreenshotFormat::new(target.output_encoding()).extension());
+
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {} for the screenshot", parent.display()))?;
+        }
+
+        self.requested = Some(path.clone());
+
+        Ok(path)
+    }
+
+    /// Records copying `target`'s image of the frame in `slot`, if a screenshot was asked for.
+    ///
+    /// The image is left in the layout the render pass left it in.
+    pub fn record<B: Backend>(
+        &mut self,
+        backend: &B,
+        command_buffer: vk::CommandBuffer,
+        slot: usize,
+        target: &RenderTarget,
+        image_index: u32
+    ) -> Result<()> {
+        let path = match self.requested.take() {
+            None => return Ok(()),
+            Some(path) => path
+        };
+
+        let format = target.format();
+        let extent = target.extent();
+
+        let texel_size = match texel_size(format) {
+            None => anyhow::bail!("Don't know how to save screenshots of {:?} images", format),
+            Some(texel_size) => texel_size
+        };
+
+        let readback = backend.create_buffer(
+            "screenshot readback",
+            (extent.width * extent.height * texel_size) as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuToCpu,
+        )?;
+
+        let image = target.image(image_index);
+        let layout = target.final_layout();
+
+        let color = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+
+        let barrier = |old_layout, new_layout, src_access_mask, dst_access_mask| {
+            vk::ImageMemoryBarrier::builder()
+                .src_access_mask(src_access_mask)
+                .dst_access_mask(dst_access_mask)
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(image)
+                .subresource_range(color)
+                .build()
+        };
+
+        // a capture may have copied the image already:
+        let to_copy = barrier(
+            layout,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            vk::AccessFlags::TRANSFER_READ,
+        );
+
+        let copied = barrier(
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            layout,
+            vk::AccessFlags::TRANSFER_READ,
+            vk::AccessFlags::empty(),
+        );
+
+        let region = vk::BufferImageCopy::builder()
+            .image_subresource(vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .image_extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            });
+
+        let read = vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::HOST_READ);
+
+        backend.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::TRANSFER,
+            &[],
+            &[],
+            &[to_copy],
+        );
+
+        backend.cmd_copy_image_to_buffer(
+            command_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            readback.buffer,
+            &[region.build()],
+        );
+
+        backend.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::HOST | vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            &[read.build()],
+            &[],
+            &[copied],
+        );
+
+        // the target can come back with more images than it had:
+        if slot >= self.pending.len() {
+            self.pending.resize_with(slot + 1, || None);
+        }
+
+        // a screenshot that was never read would leak its buffer:
+        if let Some(mut pending) = self.pending[slot].replace(Pending {
+            path,
+            format,
+            encoding: target.output_encoding(),
+            extent,
+            readback,
+        }) {
+            backend.destroy_buffer(&mut pending.readback);
+        }
+
+        Ok(())
+    }
+
+    /// Hands what the frame in `slot` copied to a writer, after its fence was waited for.
+    ///
+    /// Fails if a screenshot that was written since the last call failed to be.
+    pub fn frame_finished<B: Backend>(&mut self, backend: &B, slot: usize) -> Result<()> {
+        if let Some(mut pending) = self.pending.get_mut(slot).and_then(Option::take) {
+            let bytes = pending.readback.read().map(|bytes| bytes.to_vec());
+
+            unsafe {
+                backend.destroy_buffer(&mut pending.readback);
+            };
+
+            let bytes = bytes?;
+
+            let Pending { path, format, encoding, extent, .. } = pending;
+
+            self.writers.push(thread::spawn(move || {
+                match Self::convert(&bytes, format, encoding)? {
+                    Pixels::Rgba8(pixels) => {
+                        image::save_buffer(&path, &pixels, extent.width, extent.height, image::ColorType::Rgba8)?
+                    },
+                    Pixels::RgbaF32(pixels) => {
+                        let image = match image::Rgba32FImage::from_raw(extent.width, extent.height, pixels) {
+                            None => anyhow::bail!("The screenshot's pixels aren't {}x{}", extent.width, extent.height),
+                            Some(image) => image
+                        };
+
+                        image.save_with_format(&path, image::ImageFormat::OpenExr)?
+                    },
+                };
+
+                Ok(path)
+            }));
+        }
+
+        let (finished, writing): (Vec<_>, Vec<_>) = self.writers.drain(..)
+            .partition(|writer| writer.is_finished());
+
+        self.writers = writing;
+
+        for writer in finished {
+            Self::join(writer)?;
+        }
+
+        Ok(())
+    }
+
+    fn join(writer: JoinHandle<Result<PathBuf>>) -> Result<PathBuf> {
+        match writer.join() {
+            Err(_) => anyhow::bail!("A screenshot's writer panicked"),
+            Ok(written) => written.context("Failed to write a screenshot")
+        }
+    }
+
+    /// Writes every screenshot that's left and returns where they went.
+    ///
+    /// Every frame that took one has to have finished.
+    pub fn finish<B: Backend>(&mut self, backend: &B) -> Result<Vec<PathBuf>> {
+        for slot in 0..self.pending.len() {
+            self.frame_finished(backend, slot)?;
+        }
+
+        self.writers.drain(..)
+            .map(Self::join)
+            .collect()
+    }
+
+    /// Turns the image's texels into RGBA.
+    fn convert(bytes: &[u8], format: vk::Format, encoding: OutputEncoding) -> Result<Pixels> {
+        let words = || bytes.chunks_exact(4).map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]));
+
+        let rgba8 = match format {
+            vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB => {
+                bytes.chunks_exact(4).flat_map(|texel| [texel[2], texel[1], texel[0], 255]).collect()
+            },
+            vk::Format::R8G8B8A8_UNORM
+            | vk::Format::R8G8B8A8_SRGB
+            | vk::Format::A8B8G8R8_UNORM_PACK32
+            | vk::Format::A8B8G8R8_SRGB_PACK32 => {
+                bytes.chunks_exact(4).flat_map(|texel| [texel[0], texel[1], texel[2], 255]).collect()
+            },
+            vk::Format::A2B10G10R10_UNORM_PACK32 | vk::Format::A2R10G10B10_UNORM_PACK32 => {
+                // red is in the low bits of one and the high bits of the other:
+                let channels = words().map(|word| {
+                    let (low, middle, high) = (word & 0x3ff, (word >> 10) & 0x3ff, (word >> 20) & 0x3ff);
+
+                    match format {
+                        vk::Format::A2B10G10R10_UNORM_PACK32 => [low, middle, high],
+                        _ => [high, middle, low],
+                    }
+                });
+
+                if encoding == OutputEncoding::Pq {
+                    return Ok(Pixels::RgbaF32(channels.flat_map(|rgb| Self::decode_hdr10(rgb.map(|channel| channel as f32 / 1023.0))).collect()));
+                }
+
+                channels.flat_map(|[r, g, b]| [(r >> 2) as u8, (g >> 2) as u8, (b >> 2) as u8, 255]).collect()
+            },
+            vk::Format::R16G16B16A16_SFLOAT => {
+                let texels = bytes.chunks_exact(8).map(|texel| {
+                    [0, 2, 4].map(|offset| Self::half_to_f32(u16::from_le_bytes([texel[offset], texel[offset + 1]])))
+                });
+
+                if encoding == OutputEncoding::ScRgb {
+                    return Ok(Pixels::RgbaF32(texels.flat_map(|[r, g, b]| [r, g, b, 1.0]).collect()));
+                }
+
+                // encoded before they're quantized, 8-bit linear colors band in the dark:
+                let rgba8 = texels.flat_map(|rgb| {
+                    let [r, g, b] = rgb.map(|channel| {
+                        let channel = channel.clamp(0.0, 1.0);
+
+                        let encoded = match encoding {
+                            OutputEncoding::Linear => Self::srgb_transfer(channel),
+                            _ => channel,
+                        };
+
+                        (encoded * 255.0).round() as u8
+                    });
+
+                    [r, g, b, 255]
+                }).collect();
+
+                return Ok(Pixels::Rgba8(rgba8));
+            },
+            _ => anyhow::bail!("Don't know how to save screenshots of {:?} images", format),
+        };
+
+        let srgb = matches!(
+            format,
+            vk::Format::B8G8R8A8_SRGB | vk::Format::R8G8B8A8_SRGB | vk::Format::A8B8G8R8_SRGB_PACK32
+        );
+
+        // `_UNORM` images of offscreen targets hold linear colors, sRGB ones were encoded when they were written:
+        if encoding == OutputEncoding::Linear && !srgb {
+            Ok(Pixels::Rgba8(Self::encode_srgb(rgba8)))
+        } else {
+            Ok(Pixels::Rgba8(rgba8))
+        }
+    }
+
+    fn encode_srgb(mut rgba8: Vec<u8>) -> Vec<u8> {
+        let table: Vec<u8> = (0..=255)
+            .map(|value| (Self::srgb_transfer(value as f32 / 255.0) * 255.0).round() as u8)
+            .collect();
+
+        for texel in rgba8.chunks_exact_mut(4) {
+            for channel in &mut texel[..3] {
+                *channel = table[*channel as usize];
+            }
+        }
+
+        rgba8
+    }
+
+    /// A linear channel in 0 to 1 sRGB encoded.
+    fn srgb_transfer(linear: f32) -> f32 {
+        if linear <= 0.0031308 {
+            linear * 12.92
+        } else {
+            1.055 * linear.powf(1.0 / 2.4) - 0.055
+        }
+    }
+
+    /// From PQ and Rec. 2020 into linear Rec. 709 with 1.0 at 80 nits, the inverse of what tonemapping encodes.
+    fn decode_hdr10(pq: [f32; 3]) -> [f32; 4] {
+        const M1: f32 = 2610.0 / 16384.0;
+        const M2: f32 = 2523.0 / 32.0;
+        const C1: f32 = 3424.0 / 4096.0;
+        const C2: f32 = 2413.0 / 128.0;
+        const C3: f32 = 2392.0 / 128.0;
+
+        let [r, g, b] = pq.map(|channel| {
+            let power = channel.powf(1.0 / M2);
+
+            let y = ((power - C1).max(0.0) / (C2 - C3 * power)).powf(1.0 / M1);
+
+            y * 10000.0 / 80.0
+        });
+
+        [
+            1.660491 * r - 0.587641 * g - 0.072850 * b,
+            -0.12455 * r + 1.1329 * g - 0.008349 * b,
+            -0.018151 * r - 0.100579 * g + 1.11873 * b,
+            1.0,
+        ]
+    }
+
+    fn half_to_f32(bits: u16) -> f32 {
+        let sign = (bits as u32 >> 15) << 31;
+        let exponent = (bits as u32 >> 10) & 0x1f;
+        let mantissa = bits as u32 & 0x3ff;
+
+        match exponent {
+            // subnormal, which f32 has a normal number for:
+            0 => {
+                let value = mantissa as f32 / (1 << 24) as f32;
+
+                if sign != 0 { -value } else { value }
+            },
+            0x1f => f32::from_bits(sign | 0x7f80_0000 | mantissa << 13),
+            _ => f32::from_bits(sign | (exponent + 112) << 23 | mantissa << 13),
+        }
+    }
+
+    /// Drops screenshots that weren't read yet, their frames have to have finished.
+    pub unsafe fn cleanup<B: Backend>(&mut self, backend: &B) {
+        self.requested = None;
+
+        for pending in &mut self.pending {
+            if let Some(mut pending) = pending.take() {
+                backend.destroy_buffer(&mut pending.readback);
+            }
+        }
+    }
+}
@@ -36,6 +36,10 @@ impl Shader {
         Self::from_code(device, code, vk::ShaderStageFlags::FRAGMENT)
     }
 
+    pub fn from_code_comp(device: &ash::Device, code: &[u32]) -> Result<Shader> {
+        Self::from_code(device, code, vk::ShaderStageFlags::COMPUTE)
+    }
+
     pub fn shader_stage(&self, entry_point: &ffi::CString) -> vk::PipelineShaderStageCreateInfo {
         let stage = vk::PipelineShaderStageCreateInfo::builder()
             .stage(self.stage)
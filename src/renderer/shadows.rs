@@ -0,0 +1,879 @@
+//! Shadow maps for directional and spot lights.
+//!
+//! One directional light gets cascades: the part of the view frustum shadows are drawn in is split into
+//! ranges of depth, each covered by its own orthographic map, so that nearby shadows get most of the texels.
+//! A few spot lights get a perspective map each. All of them are layers of one depth image, rendered in a
+//! depth only pass ahead of the frame and sampled with percentage closer filtering by the PBR pipelines.
+
+use ash::vk;
+
+use crate::renderer::device::RendererDevice;
+use crate::renderer::backend::Backend;
+use crate::renderer::command_pools::CommandPools;
+use crate::renderer::buffer::RendererBuffer;
+use crate::renderer::image::RendererImage;
+use crate::renderer::pipeline::RendererPipeline;
+use crate::renderer::lights::WorldLight;
+use crate::renderer::mesh::Mesh;
+use crate::scene::LightKind;
+use crate::camera::{Camera, Projection};
+
+use gpu_allocator::MemoryLocation;
+use gpu_allocator::vulkan::Allocation;
+
+use glam::{Mat4, Vec3, Vec4};
+
+use std::{mem, slice};
+use std::ops::Range;
+
+use anyhow::Result;
+
+/// How many depth ranges the directional light's shadows can be split into.
+pub const MAX_CASCADES: usize = 4;
+
+/// How many spot lights can cast shadows at once.
+pub const MAX_SPOT_SHADOWS: usize = 4;
+
+/// The cascades come first, then a layer per spot light.
+const LAYERS: u32 = (MAX_CASCADES + MAX_SPOT_SHADOWS) as u32;
+
+/// Casters this many cascade radii towards the light from a cascade still throw shadows into it.
+const CASTER_REACH: f32 = 2.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShadowSettings {
+    /// Width and height of every shadow map, in texels.
+    pub resolution: u32,
+    /// How many cascades the directional shadows are split into, at most `MAX_CASCADES`.
+    pub cascade_count: u32,
+    /// Directional shadows end this far from the camera, or at its far plane when that's nearer.
+    pub max_distance: f32,
+    /// Blends the cascade splits between evenly spaced at 0 and logarithmically spaced at 1.
+    pub split_lambda: f32,
+    /// The depth bias the maps are rendered with, as passed to `vkCmdSetDepthBias`.
+    pub depth_bias_constant: f32,
+    pub depth_bias_slope: f32,
+    /// Receivers are moved off their surface by up to this many texels before they're looked up.
+    pub normal_bias: f32,
+    /// Filters over a square of `2 * pcf_radius + 1` texels on each side, 0 takes a single bilinear sample.
+    pub pcf_radius: u32,
+    /// Tints everything by the cascade it's in.
+    pub debug_cascades: bool,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        ShadowSettings {
+            resolution: 2048,
+            cascade_count: 4,
+            max_distance: 100.0,
+            split_lambda: 0.75,
+            depth_bias_constant: 1.25,
+            depth_bias_slope: 1.75,
+            normal_bias: 1.0,
+            pcf_radius: 1,
+            debug_cascades: false,
+        }
+    }
+}
+
+/// Where the shadow maps are and how to sample them, laid out for std140.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ShadowUniform {
+    /// From world space to the clip space of each cascade.
+    pub cascades: [Mat4; MAX_CASCADES],
+    /// From world space to the clip space of each spot light, indexed by its map minus `MAX_CASCADES`.
+    pub spots: [Mat4; MAX_SPOT_SHADOWS],
+    /// The view space depth each cascade ends at.
+    pub cascade_splits: Vec4,
+    /// How wide a texel of each cascade is, in world units.
+    pub cascade_texel_sizes: Vec4,
+    /// How wide a texel of each spot map is at a distance of 1 from the light, they grow with distance.
+    pub spot_texel_scales: Vec4,
+    pub cascade_count: u32,
+    pub pcf_radius: u32,
+    pub normal_bias: f32,
+    pub debug_cascades: u32,
+}
+
+/// A mesh that is rendered into the shadow maps of a frame, once for each of its instances.
+#[derive(Clone, Debug)]
+pub struct ShadowCaster {
+    pub vertex_buffer: vk::Buffer,
+    pub index_buffer: vk::Buffer,
+    pub index_count: u32,
+    pub model: Mat4,
+    /// Holds `InstanceData`, only the model matrices are read.
+    pub instance_buffer: vk::Buffer,
+    pub instances: Range<u32>,
+}
+
+struct Cascade {
+    view_projection: Mat4,
+    split: f32,
+    texel_size: f32,
+}
+
+/// Which shadow map each of `lights` gets, if any.
+///
+/// The first directional light that casts shadows gets the cascades, which start at layer 0, and the first
+/// `MAX_SPOT_SHADOWS` spot lights that cast them get a layer each after those. Point lights never do.
+pub fn shadow_maps(lights: &[WorldLight]) -> Vec<Option<usize>> {
+    let mut directional = false;
+    let mut spots = 0;
+
+    lights.iter()
+        .map(|light| {
+            if !light.casts_shadows {
+                return None;
+            }
+
+            match light.kind {
+                LightKind::Directional if !directional => {
+                    directional = true;
+
+                    Some(0)
+                },
+                LightKind::Spot { .. } if spots < MAX_SPOT_SHADOWS => {
+                    spots += 1;
+
+                    Some(MAX_CASCADES + spots - 1)
+                },
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// The shadow map layers, the pass that renders into them and the per frame set the PBR pipelines sample them through.
+pub struct RendererShadows {
+    pub settings: ShadowSettings,
+    /// Every cascade and spot map, as the layers of one depth image.
+    pub map: RendererImage,
+    /// One per layer, to render into.
+    pub layer_views: Vec<vk::ImageView>,
+    pub render_pass: vk::RenderPass,
+    pub framebuffers: Vec<vk::Framebuffer>,
+    pub pipeline: RendererPipeline,
+    /// Compares instead of returning depth, outside of the maps everything is lit.
+    pub sampler: vk::Sampler,
+    pub set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    /// One per frame in flight, like the uniform buffers.
+    pub descriptor_sets: Vec<vk::DescriptorSet>,
+    pub uniforms: Vec<RendererBuffer>,
+    /// The maps are rendered in their own command buffer, submitted ahead of the frame's.
+    pub command_buffers: Vec<vk::CommandBuffer>,
+    /// What the frame being recorded renders into the maps.
+    pub casters: Vec<ShadowCaster>,
+    /// What the frame being recorded writes to its uniform buffer when it ends.
+    pub uniform: ShadowUniform,
+    camera: Option<Camera>,
+    /// The lights of the frame that got a map, with the map they got.
+    lights: Vec<(WorldLight, usize)>,
+}
+
+impl RendererShadows {
+    /// `format` has to be a depth format that can be rendered to and sampled with linear filtering.
+    pub fn new(
+        device: &RendererDevice,
+        command_pools: &CommandPools,
+        count: usize,
+        format: vk::Format,
+        settings: ShadowSettings
+    ) -> Result<RendererShadows> {
+        let mut shadows = RendererShadows {
+            settings,
+            map: Self::null_map(format),
+            layer_views: Vec::with_capacity(LAYERS as usize),
+            render_pass: vk::RenderPass::null(),
+            framebuffers: Vec::with_capacity(LAYERS as usize),
+            pipeline: RendererPipeline {
+                pipeline: vk::Pipeline::null(),
+                pipeline_layout: vk::PipelineLayout::null(),
+            },
+            sampler: vk::Sampler::null(),
+            set_layout: vk::DescriptorSetLayout::null(),
+            descriptor_pool: vk::DescriptorPool::null(),
+            descriptor_sets: Vec::with_capacity(count),
+            uniforms: Vec::with_capacity(count),
+            command_buffers: Vec::with_capacity(count),
+            casters: Vec::new(),
+            uniform: ShadowUniform::default(),
+            camera: None,
+            lights: Vec::new(),
+        };
+
+        if let Err(err) = shadows.create(device, command_pools, count) {
+            unsafe {
+                shadows.cleanup(device);
+            };
+
+            return Err(err);
+        }
+
+        Ok(shadows)
+    }
+
+    fn null_map(format: vk::Format) -> RendererImage {
+        RendererImage {
+            image: vk::Image::null(),
+            image_view: vk::ImageView::null(),
+            allocation: Allocation::default(),
+            format,
+            extent: vk::Extent2D::default(),
+            depth: 1,
+            layers: LAYERS,
+            mip_levels: 1,
+            samples: vk::SampleCountFlags::TYPE_1,
+        }
+    }
+
+    fn create(&mut self, device: &RendererDevice, command_pools: &CommandPools, count: usize) -> Result<()> {
+        for _ in 0..count {
+            self.uniforms.push(RendererBuffer::new(
+                device,
+                "shadows",
+                mem::size_of::<ShadowUniform>() as vk::DeviceSize,
+                vk::BufferUsageFlags::UNIFORM_BUFFER,
+                MemoryLocation::CpuToGpu,
+            )?);
+        }
+
+        // sampler:
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .border_color(vk::BorderColor::FLOAT_OPAQUE_WHITE)
+            .compare_enable(true)
+            .compare_op(vk::CompareOp::LESS_OR_EQUAL);
+
+        self.sampler = unsafe {
+            device.logical_device.create_sampler(&sampler_info, None)?
+        };
+
+        // descriptors:
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .build(),
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .build(),
+        ];
+
+        let set_layout_info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .bindings(&bindings);
+
+        self.set_layout = unsafe {
+            device.logical_device.create_descriptor_set_layout(&set_layout_info, None)?
+        };
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: count as u32,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: count as u32,
+            },
+        ];
+
+        let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(count as u32)
+            .pool_sizes(&pool_sizes);
+
+        self.descriptor_pool = unsafe {
+            device.logical_device.create_descriptor_pool(&pool_info, None)?
+        };
+
+        let set_layouts = vec![self.set_layout; count];
+
+        let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(self.descriptor_pool)
+            .set_layouts(&set_layouts);
+
+        self.descriptor_sets = unsafe {
+            device.logical_device.allocate_descriptor_sets(&allocate_info)?
+        };
+
+        self.render_pass = Self::create_render_pass(device, self.map.format)?;
+
+        self.create_maps(device, command_pools)?;
+
+        self.command_buffers = CommandPools::create_command_buffers(device, command_pools.graphics, count as u32)?;
+
+        Ok(())
+    }
+
+    /// A pass of its own rather than a subpass of the frame's, every layer is rendered into separately.
+    fn create_render_pass(device: &RendererDevice, format: vk::Format) -> Result<vk::RenderPass> {
+        let attachments = [
+            vk::AttachmentDescription::builder()
+                .format(format)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .final_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .build(),
+        ];
+
+        let depth_attachment_reference = vk::AttachmentReference {
+            attachment: 0,
+            layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        };
+
+        let subpasses = [
+            vk::SubpassDescription::builder()
+                .depth_stencil_attachment(&depth_attachment_reference)
+                .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+                .build()
+        ];
+
+        // the previous frame's lookups have to finish before the map is rendered again,
+        // and this frame's have to wait until it is:
+        let subpass_dependencies = [
+            vk::SubpassDependency::builder()
+                .src_subpass(vk::SUBPASS_EXTERNAL)
+                .src_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+                .dst_subpass(0)
+                .dst_stage_mask(vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS)
+                .dst_access_mask(
+                    vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
+                )
+                .build(),
+            vk::SubpassDependency::builder()
+                .src_subpass(0)
+                .src_stage_mask(vk::PipelineStageFlags::LATE_FRAGMENT_TESTS)
+                .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+                .dst_subpass(vk::SUBPASS_EXTERNAL)
+                .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ)
+                .build(),
+        ];
+
+        let render_pass_info = vk::RenderPassCreateInfo::builder()
+            .attachments(&attachments)
+            .subpasses(&subpasses)
+            .dependencies(&subpass_dependencies);
+
+        let render_pass = unsafe {
+            device.logical_device.create_render_pass(&render_pass_info, None)?
+        };
+
+        Ok(render_pass)
+    }
+
+    /// Creates everything that depends on the resolution and points the descriptor sets at it.
+    fn create_maps(&mut self, device: &RendererDevice, command_pools: &CommandPools) -> Result<()> {
+        let extent = vk::Extent2D {
+            width: self.settings.resolution,
+            height: self.settings.resolution,
+        };
+
+        self.map = RendererImage::layered(
+            device,
+            "shadow maps",
+            extent,
+            self.map.format,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+                | vk::ImageUsageFlags::SAMPLED
+                | vk::ImageUsageFlags::TRANSFER_DST,
+            LAYERS,
+        )?;
+
+        for layer in 0..LAYERS {
+            let subresource_range = vk::ImageSubresourceRange::builder()
+                .aspect_mask(vk::ImageAspectFlags::DEPTH)
+                .base_mip_level(0)
+                .level_count(1)
+                .base_array_layer(layer)
+                .layer_count(1);
+
+            let image_view_info = vk::ImageViewCreateInfo::builder()
+                .image(self.map.image)
+                .view_type(vk::ImageViewType::TYPE_2D)
+                .format(self.map.format)
+                .subresource_range(*subresource_range);
+
+            let layer_view = unsafe {
+                device.logical_device.create_image_view(&image_view_info, None)?
+            };
+
+            self.layer_views.push(layer_view);
+
+            let attachments = [layer_view];
+
+            let framebuffer_info = vk::FramebufferCreateInfo::builder()
+                .render_pass(self.render_pass)
+                .attachments(&attachments)
+                .width(extent.width)
+                .height(extent.height)
+                .layers(1);
+
+            let framebuffer = unsafe {
+                device.logical_device.create_framebuffer(&framebuffer_info, None)?
+            };
+
+            self.framebuffers.push(framebuffer);
+        }
+
+        self.pipeline = RendererPipeline::shadow(device, extent, self.render_pass)?;
+
+        self.clear_maps(device, command_pools)?;
+
+        for (slot, &descriptor_set) in self.descriptor_sets.iter().enumerate() {
+            let buffer_infos = [
+                vk::DescriptorBufferInfo {
+                    buffer: self.uniforms[slot].buffer,
+                    offset: 0,
+                    range: mem::size_of::<ShadowUniform>() as vk::DeviceSize,
+                }
+            ];
+
+            let image_infos = [
+                vk::DescriptorImageInfo {
+                    sampler: self.sampler,
+                    image_view: self.map.image_view,
+                    image_layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+                }
+            ];
+
+            let writes = [
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_set)
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                    .buffer_info(&buffer_infos)
+                    .build(),
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_set)
+                    .dst_binding(1)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(&image_infos)
+                    .build(),
+            ];
+
+            unsafe {
+                device.logical_device.update_descriptor_sets(&writes, &[]);
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Clears every layer to the far plane and leaves them ready to be sampled, layers no light uses stay like that.
+    fn clear_maps(&self, device: &RendererDevice, command_pools: &CommandPools) -> Result<()> {
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::DEPTH,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: LAYERS,
+        };
+
+        command_pools.one_time_submit(device, |command_buffer| {
+            let to_transfer = vk::ImageMemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::empty())
+                .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .old_layout(vk::ImageLayout::UNDEFINED)
+                .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(self.map.image)
+                .subresource_range(subresource_range);
+
+            let to_shader_read = vk::ImageMemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ)
+                .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                .new_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(self.map.image)
+                .subresource_range(subresource_range);
+
+            let clear_value = vk::ClearDepthStencilValue {
+                depth: 1.0,
+                stencil: 0,
+            };
+
+            unsafe {
+                device.logical_device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[to_transfer.build()],
+                );
+
+                device.logical_device.cmd_clear_depth_stencil_image(
+                    command_buffer,
+                    self.map.image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &clear_value,
+                    &[subresource_range],
+                );
+
+                device.logical_device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::FRAGMENT_SHADER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[to_shader_read.build()],
+                );
+            };
+        })
+    }
+
+    /// Replaces the settings, a new resolution means creating the maps again.
+    ///
+    /// The device has to be idle when the resolution changes.
+    pub fn set_settings(
+        &mut self,
+        device: &RendererDevice,
+        command_pools: &CommandPools,
+        settings: ShadowSettings
+    ) -> Result<()> {
+        let resolution_changed = settings.resolution != self.settings.resolution;
+
+        self.settings = settings;
+
+        if resolution_changed {
+            unsafe {
+                self.cleanup_maps(device);
+            };
+
+            self.create_maps(device, command_pools)?;
+        }
+
+        Ok(())
+    }
+
+    /// Starts a frame without any casters, lights or camera.
+    pub fn begin(&mut self) {
+        self.casters.clear();
+        self.lights.clear();
+        self.camera = None;
+    }
+
+    /// The cascades are fitted to what this camera sees.
+    pub fn set_camera(&mut self, camera: &Camera) {
+        self.camera = Some(camera.clone());
+    }
+
+    /// Keeps the lights that get a map, see [`shadow_maps`].
+    pub fn set_lights(&mut self, lights: &[WorldLight]) {
+        self.lights = lights.iter()
+            .zip(shadow_maps(lights))
+            .filter_map(|(&light, map)| Some((light, map?)))
+            .collect();
+    }
+
+    /// Renders `instances` of `mesh` into every map of the frame, the mesh and `instance_buffer` have to stay alive
+    /// until the frame has finished.
+    pub fn add_caster(&mut self, mesh: &Mesh, model: Mat4, instance_buffer: vk::Buffer, instances: Range<u32>) {
+        self.casters.push(ShadowCaster {
+            vertex_buffer: mesh.vertex_buffer.buffer,
+            index_buffer: mesh.index_buffer.buffer,
+            index_count: mesh.index_count,
+            model,
+            instance_buffer,
+            instances,
+        });
+    }
+
+    /// Fills in the uniform and returns the layers to render this frame, with the view projection of each.
+    fn layers(&mut self) -> Vec<(usize, Mat4)> {
+        let settings = self.settings;
+
+        self.uniform = ShadowUniform {
+            pcf_radius: settings.pcf_radius,
+            normal_bias: settings.normal_bias,
+            debug_cascades: settings.debug_cascades as u32,
+            ..Default::default()
+        };
+
+        let mut layers = Vec::new();
+
+        for &(light, map) in &self.lights {
+            match light.kind {
+                LightKind::Directional => {
+                    // cascades need something to be fitted to:
+                    let camera = match &self.camera {
+                        None => continue,
+                        Some(camera) => camera
+                    };
+
+                    let cascades = Self::cascades(camera, light.direction, &settings);
+
+                    self.uniform.cascade_count = cascades.len() as u32;
+
+                    for (i, cascade) in cascades.into_iter().enumerate() {
+                        self.uniform.cascades[i] = cascade.view_projection;
+                        self.uniform.cascade_splits[i] = cascade.split;
+                        self.uniform.cascade_texel_sizes[i] = cascade.texel_size;
+
+                        layers.push((i, cascade.view_projection));
+                    }
+                },
+                LightKind::Spot { outer_cone_angle, .. } => {
+                    // the whole cone fits into the square map, short of a cone that opens up completely:
+                    let half_angle = outer_cone_angle.clamp(0.01, 1.55);
+
+                    let view_projection = Self::spot_view_projection(&light, half_angle);
+
+                    self.uniform.spots[map - MAX_CASCADES] = view_projection;
+                    self.uniform.spot_texel_scales[map - MAX_CASCADES] = 2.0 * half_angle.tan() / settings.resolution as f32;
+
+                    layers.push((map, view_projection));
+                },
+                LightKind::Point => {},
+            }
+        }
+
+        layers
+    }
+
+    /// Splits the camera's depth range up to `max_distance` and fits an orthographic map around each piece.
+    fn cascades(camera: &Camera, direction: Vec3, settings: &ShadowSettings) -> Vec<Cascade> {
+        let direction = direction.normalize_or_zero();
+
+        if direction == Vec3::ZERO {
+            return Vec::new();
+        }
+
+        let (near, far) = match camera.projection {
+            Projection::Perspective { near, far, .. } => (near, far),
+            Projection::Orthographic { near, far, .. } => (near, far),
+        };
+
+        // logarithmic splits can't start at 0:
+        let near = near.max(0.001);
+        let far = far.min(settings.max_distance).max(near * 2.0);
+
+        let count = settings.cascade_count.clamp(1, MAX_CASCADES as u32);
+
+        let up = if direction.y.abs() > 0.99 { Vec3::Z } else { Vec3::Y };
+
+        let light_view = Mat4::look_at_rh(Vec3::ZERO, direction, up);
+
+        let mut cascades = Vec::with_capacity(count as usize);
+        let mut start = near;
+
+        for i in 1..=count {
+            let p = i as f32 / count as f32;
+
+            let logarithmic = near * (far / near).powf(p);
+            let uniform = near + (far - near) * p;
+
+            let end = settings.split_lambda * logarithmic + (1.0 - settings.split_lambda) * uniform;
+
+            let corners = camera.frustum_corners(start, end);
+
+            let center = corners.iter().fold(Vec3::ZERO, |sum, &corner| sum + corner) / corners.len() as f32;
+
+            // a sphere around the piece keeps the cascade the same size however the camera turns:
+            let radius = corners.iter().fold(0.0f32, |radius, corner| radius.max(corner.distance(center)));
+            let radius = (radius * 16.0).ceil() / 16.0;
+
+            let texel_size = 2.0 * radius / settings.resolution as f32;
+
+            // moving the cascade in whole texels keeps shadow edges from crawling as the camera moves:
+            let center = light_view.transform_point3(center);
+
+            let center = Vec3::new(
+                (center.x / texel_size).floor() * texel_size,
+                (center.y / texel_size).floor() * texel_size,
+                center.z,
+            );
+
+            let center = light_view.inverse().transform_point3(center);
+
+            let eye = center - direction * radius * CASTER_REACH;
+
+            let view = Mat4::look_at_rh(eye, center, up);
+            let projection = Mat4::orthographic_rh(-radius, radius, -radius, radius, 0.0, radius * (CASTER_REACH + 1.0));
+
+            cascades.push(Cascade {
+                view_projection: projection * view,
+                split: end,
+                texel_size,
+            });
+
+            start = end;
+        }
+
+        cascades
+    }
+
+    fn spot_view_projection(light: &WorldLight, half_angle: f32) -> Mat4 {
+        let direction = light.direction.normalize_or_zero();
+
+        let up = if direction.y.abs() > 0.99 { Vec3::Z } else { Vec3::Y };
+
+        let far = light.reach();
+
+        let view = Mat4::look_at_rh(light.position, light.position + direction, up);
+        let projection = Mat4::perspective_rh(half_angle * 2.0, 1.0, far / 1000.0, far);
+
+        projection * view
+    }
+
+    /// Writes the frame's uniform and records the maps it uses, right before the frame is submitted.
+    ///
+    /// Returns the command buffer to submit ahead of the frame's, if any map is used at all.
+    pub fn record<B: Backend>(&mut self, backend: &B, slot: usize) -> Result<Option<vk::CommandBuffer>> {
+        let layers = self.layers();
+
+        backend.write_buffer(&mut self.uniforms[slot], 0, RendererBuffer::bytes(slice::from_ref(&self.uniform)))?;
+
+        if layers.is_empty() {
+            return Ok(None);
+        }
+
+        let command_buffer = self.command_buffers[slot];
+
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+        let clear_values = [
+            vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: 1.0,
+                    stencil: 0,
+                }
+            },
+        ];
+
+        backend.begin_command_buffer(command_buffer, &begin_info)?;
+
+        for (layer, view_projection) in layers {
+            let render_pass_begin_info = vk::RenderPassBeginInfo::builder()
+                .render_pass(self.render_pass)
+                .framebuffer(self.framebuffers[layer])
+                .render_area(vk::Rect2D {
+                    offset: vk::Offset2D { x: 0, y: 0 },
+                    extent: self.map.extent,
+                })
+                .clear_values(&clear_values);
+
+            backend.cmd_begin_render_pass(
+                command_buffer,
+                &render_pass_begin_info,
+                vk::SubpassContents::INLINE,
+            );
+
+            backend.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline.pipeline,
+            );
+
+            backend.cmd_set_depth_bias(
+                command_buffer,
+                self.settings.depth_bias_constant,
+                0.0,
+                self.settings.depth_bias_slope,
+            );
+
+            for caster in &self.casters {
+                let matrix = (view_projection * caster.model).to_cols_array();
+
+                let matrix_bytes = unsafe {
+                    slice::from_raw_parts(matrix.as_ptr() as *const u8, mem::size_of_val(&matrix))
+                };
+
+                backend.cmd_push_constants(
+                    command_buffer,
+                    self.pipeline.pipeline_layout,
+                    vk::ShaderStageFlags::VERTEX,
+                    0,
+                    matrix_bytes,
+                );
+
+                backend.cmd_bind_vertex_buffers(
+                    command_buffer,
+                    0,
+                    &[caster.vertex_buffer, caster.instance_buffer],
+                    &[0, 0],
+                );
+                backend.cmd_bind_index_buffer(command_buffer, caster.index_buffer, 0, vk::IndexType::UINT32);
+
+                backend.cmd_draw_indexed(
+                    command_buffer,
+                    caster.index_count,
+                    caster.instances.end - caster.instances.start,
+                    0,
+                    0,
+                    caster.instances.start,
+                );
+            }
+
+            backend.cmd_end_render_pass(command_buffer);
+        }
+
+        backend.end_command_buffer(command_buffer)?;
+
+        Ok(Some(command_buffer))
+    }
+
+    unsafe fn cleanup_maps(&mut self, device: &RendererDevice) {
+        self.pipeline.cleanup(&device.logical_device);
+
+        for framebuffer in self.framebuffers.drain(..) {
+            device.logical_device.destroy_framebuffer(framebuffer, None);
+        }
+
+        for layer_view in self.layer_views.drain(..) {
+            device.logical_device.destroy_image_view(layer_view, None);
+        }
+
+        self.map.cleanup(device);
+
+        self.map = Self::null_map(self.map.format);
+
+        self.pipeline = RendererPipeline {
+            pipeline: vk::Pipeline::null(),
+            pipeline_layout: vk::PipelineLayout::null(),
+        };
+    }
+
+    /// The command buffers go with the pool they were allocated from.
+    pub unsafe fn cleanup(&mut self, device: &RendererDevice) {
+        self.cleanup_maps(device);
+
+        device.logical_device.destroy_render_pass(self.render_pass, None);
+        device.logical_device.destroy_sampler(self.sampler, None);
+        device.logical_device.destroy_descriptor_pool(self.descriptor_pool, None);
+        device.logical_device.destroy_descriptor_set_layout(self.set_layout, None);
+
+        for buffer in &mut self.uniforms {
+            buffer.cleanup(device);
+        }
+
+        self.uniforms.clear();
+        self.descriptor_sets.clear();
+        self.command_buffers.clear();
+    }
+}
@@ -9,9 +9,14 @@ use anyhow::Result;
 pub struct RendererSwapchain {
     pub swapchain_loader: khr::Swapchain,
     pub swapchain: vk::SwapchainKHR,
+    pub images: Vec<vk::Image>,
     pub image_views: Vec<vk::ImageView>,
     pub framebuffers: Vec<vk::Framebuffer>,
     pub extent: vk::Extent2D,
+    pub format: vk::Format,
+    pub color_space: vk::ColorSpaceKHR,
+    /// Color attachment, and transfer source when the surface supports it so frames can be captured.
+    pub usage: vk::ImageUsageFlags,
     pub image_available: Vec<vk::Semaphore>,
     pub rendering_finished: Vec<vk::Semaphore>,
     pub may_begin_drawing: Vec<vk::Fence>,
@@ -24,6 +29,17 @@ impl RendererSwapchain {
         instance: &ash::Instance,
         device: &RendererDevice,
         window: &RendererWindow
+    ) -> Result<RendererSwapchain> {
+        Self::replacing(instance, device, window, vk::SwapchainKHR::null())
+    }
+
+    /// A swapchain taking over from `old_swapchain`, which can't be presented to anymore but still has to be
+    /// destroyed, even if this fails.
+    pub fn replacing(
+        instance: &ash::Instance,
+        device: &RendererDevice,
+        window: &RendererWindow,
+        old_swapchain: vk::SwapchainKHR,
     ) -> Result<RendererSwapchain> {
         // swapchain creation:
 
@@ -34,16 +50,28 @@ impl RendererSwapchain {
 
         let queue_families = [graphics_queue_family.index];
 
-        let capabilities = window.capabilities(device.physical_device)?;
+        let mut capabilities = window.capabilities(device.physical_device)?;
+
+        // some surfaces leave the size to the swapchain, it's the window's then:
+        capabilities.current_extent = window.extent(&capabilities);
 
         let formats = window.formats(device.physical_device)?;
-        let format = formats.first().unwrap();
+
+        // HDR10 when the window asks for it and the surface offers it, what the surface prefers otherwise:
+        let format = formats.iter()
+            .find(|format| window.hdr && format.color_space == vk::ColorSpaceKHR::HDR10_ST2084_EXT)
+            .unwrap_or_else(|| formats.first().unwrap());
+
+        let usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+            | (capabilities.supported_usage_flags & vk::ImageUsageFlags::TRANSFER_SRC);
 
         let (swapchain_loader, swapchain) = Self::create_swapchain(
-            window.surface,
+            window,
             &capabilities,
             format,
+            usage,
             &queue_families,
+            old_swapchain,
             instance,
             device,
         )?;
@@ -54,16 +82,20 @@ impl RendererSwapchain {
             swapchain_loader.get_swapchain_images(swapchain)?
         };
 
-        let image_views = Self::create_image_views(&images, &device)?;
+        let image_views = Self::create_image_views(&images, format.format, &device)?;
 
         let image_count = image_views.len() as u32;
 
         let mut swapchain = RendererSwapchain {
             swapchain_loader,
             swapchain,
+            images,
             image_views,
             framebuffers: vec![],
             extent: capabilities.current_extent,
+            format: format.format,
+            color_space: format.color_space,
+            usage,
             image_available: vec![],
             rendering_finished: vec![],
             may_begin_drawing: vec![],
@@ -77,26 +109,43 @@ impl RendererSwapchain {
     }
 
     fn create_swapchain(
-        surface: vk::SurfaceKHR,
+        window: &RendererWindow,
         capabilities: &vk::SurfaceCapabilitiesKHR,
         format: &vk::SurfaceFormatKHR,
+        usage: vk::ImageUsageFlags,
         queue_families: &[u32],
+        old_swapchain: vk::SwapchainKHR,
         instance: &ash::Instance,
         device: &RendererDevice,
     ) -> Result<(khr::Swapchain, vk::SwapchainKHR)> {
+        // FIFO is the only one every surface has:
+        let present_mode = if window.present_modes(device.physical_device)?.contains(&window.present_mode) {
+            window.present_mode
+        } else {
+            vk::PresentModeKHR::FIFO
+        };
+
+        // a maximum of 0 means there isn't one:
+        let mut image_count = window.image_count.max(capabilities.min_image_count);
+
+        if capabilities.max_image_count > 0 {
+            image_count = image_count.min(capabilities.max_image_count);
+        }
+
         let swapchain_info = vk::SwapchainCreateInfoKHR::builder()
-            .surface(surface)
-            .min_image_count(3.max(capabilities.min_image_count).min(capabilities.max_image_count))
+            .surface(window.surface)
+            .min_image_count(image_count)
             .image_format(format.format)
             .image_color_space(format.color_space)
             .image_extent(capabilities.current_extent)
             .image_array_layers(1)
-            .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+            .image_usage(usage)
             .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
             .queue_family_indices(&queue_families)
             .pre_transform(capabilities.current_transform)
             .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
-            .present_mode(vk::PresentModeKHR::FIFO);
+            .present_mode(present_mode)
+            .old_swapchain(old_swapchain);
 
         let swapchain_loader = khr::Swapchain::new(instance, &device.logical_device);
         let swapchain = unsafe {
@@ -106,7 +155,11 @@ impl RendererSwapchain {
         Ok((swapchain_loader, swapchain))
     }
 
-    fn create_image_views(images: &Vec<vk::Image>, device: &RendererDevice) -> Result<Vec<vk::ImageView>> {
+    fn create_image_views(
+        images: &Vec<vk::Image>,
+        format: vk::Format,
+        device: &RendererDevice
+    ) -> Result<Vec<vk::ImageView>> {
         let mut image_views = Vec::with_capacity(images.len());
 
         for image in images {
@@ -120,7 +173,7 @@ impl RendererSwapchain {
             let image_view_info = vk::ImageViewCreateInfo::builder()
                 .image(*image)
                 .view_type(vk::ImageViewType::TYPE_2D)
-                .format(vk::Format::B8G8R8A8_UNORM)
+                .format(format)
                 .subresource_range(*subresource_range);
 
             let image_view = unsafe {
@@ -160,13 +213,17 @@ impl RendererSwapchain {
         Ok(())
     }
 
-    pub fn create_framebuffers(&mut self, device: &RendererDevice, render_pass: vk::RenderPass) -> Result<()> {
+    pub fn create_framebuffers(
+        &mut self,
+        device: &RendererDevice,
+        render_pass: vk::RenderPass
+    ) -> Result<()> {
         for image_view in &self.image_views {
-            let image_view = [*image_view];
+            let attachments = [*image_view];
 
             let framebuffer_info = vk::FramebufferCreateInfo::builder()
                 .render_pass(render_pass)
-                .attachments(&image_view)
+                .attachments(&attachments)
                 .width(self.extent.width)
                 .height(self.extent.height)
                 .layers(1);
@@ -0,0 +1,101 @@
+use ash::vk;
+
+use crate::renderer::device::RendererDevice;
+use crate::renderer::swapchain::RendererSwapchain;
+use crate::renderer::offscreen::RendererOffscreen;
+use crate::renderer::hdr::OutputEncoding;
+
+use anyhow::Result;
+
+/// Where frames end up: the window's swapchain, or an offscreen image when running headless.
+pub enum RenderTarget {
+    Swapchain(RendererSwapchain),
+    Offscreen(RendererOffscreen),
+}
+
+impl RenderTarget {
+    pub fn extent(&self) -> vk::Extent2D {
+        match self {
+            RenderTarget::Swapchain(swapchain) => swapchain.extent,
+            RenderTarget::Offscreen(offscreen) => offscreen.extent,
+        }
+    }
+
+    pub fn format(&self) -> vk::Format {
+        match self {
+            RenderTarget::Swapchain(swapchain) => swapchain.format,
+            RenderTarget::Offscreen(_) => RendererOffscreen::FORMAT,
+        }
+    }
+
+    /// What the images can be used for, frames can only be captured with `TRANSFER_SRC`.
+    pub fn usage(&self) -> vk::ImageUsageFlags {
+        match self {
+            RenderTarget::Swapchain(swapchain) => swapchain.usage,
+            RenderTarget::Offscreen(_) => RendererOffscreen::USAGE,
+        }
+    }
+
+    pub fn image(&self, image_index: u32) -> vk::Image {
+        match self {
+            RenderTarget::Swapchain(swapchain) => swapchain.images[image_index as usize],
+            RenderTarget::Offscreen(offscreen) => offscreen.color.image,
+        }
+    }
+
+    /// How the tonemapping pass has to write colors for them to show up right.
+    pub fn output_encoding(&self) -> OutputEncoding {
+        match self {
+            RenderTarget::Swapchain(swapchain) => OutputEncoding::new(swapchain.format, swapchain.color_space),
+            RenderTarget::Offscreen(_) => OutputEncoding::Linear,
+        }
+    }
+
+    /// The layout the render pass leaves the image in.
+    pub fn final_layout(&self) -> vk::ImageLayout {
+        match self {
+            RenderTarget::Swapchain(_) => vk::ImageLayout::PRESENT_SRC_KHR,
+            RenderTarget::Offscreen(_) => vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        }
+    }
+
+    pub fn framebuffers(&self) -> &[vk::Framebuffer] {
+        match self {
+            RenderTarget::Swapchain(swapchain) => &swapchain.framebuffers,
+            RenderTarget::Offscreen(offscreen) => &offscreen.framebuffers,
+        }
+    }
+
+    pub fn image_count(&self) -> u32 {
+        match self {
+            RenderTarget::Swapchain(swapchain) => swapchain.image_count,
+            RenderTarget::Offscreen(offscreen) => offscreen.image_count,
+        }
+    }
+
+    pub fn may_begin_drawing(&self) -> &[vk::Fence] {
+        match self {
+            RenderTarget::Swapchain(swapchain) => &swapchain.may_begin_drawing,
+            RenderTarget::Offscreen(offscreen) => &offscreen.may_begin_drawing,
+        }
+    }
+
+    /// The scene is rendered elsewhere, these only have the color attachment the tonemapping pass writes to.
+    pub fn create_framebuffers(
+        &mut self,
+        device: &RendererDevice,
+        render_pass: vk::RenderPass
+    ) -> Result<()> {
+        match self {
+            RenderTarget::Swapchain(swapchain) => swapchain.create_framebuffers(device, render_pass),
+            RenderTarget::Offscreen(offscreen) => offscreen.create_framebuffers(device, render_pass),
+        }
+    }
+
+    pub unsafe fn cleanup(&mut self, device: &RendererDevice) {
+        match self {
+            RenderTarget::Swapchain(swapchain) => swapchain.cleanup(device),
+            RenderTarget::Offscreen(offscreen) => offscreen.cleanup(device),
+        }
+    }
+}
@@ -0,0 +1,605 @@
+//! Drawing the glyph quads of [`text`](crate::text) batches, from the signed distance field atlas.
+//!
+//! Text goes into one of two batches, and each batch is one draw. World space text is drawn in the frame's render
+//! pass after everything that was queued, so it's hidden by what's in front of it, and it's lit by nothing but
+//! tonemapped and post-processed like the rest of the world. Screen space text is drawn in a pass of its own over
+//! the tonemapped frame, in pixels from the top left corner, so captures and screenshots have it but the
+//! [`overlay`](crate::renderer::overlay) goes over it.
+//!
+//! Colors are sRGB encoded, like the overlay's. The atlas is uploaded again before the frame is drawn when glyphs
+//! were added to it, which waits for the device.
+
+use ash::vk;
+
+use crate::renderer::device::RendererDevice;
+use crate::renderer::backend::Backend;
+use crate::renderer::command_pools::CommandPools;
+use crate::renderer::buffer::RendererBuffer;
+use crate::renderer::texture::{SamplerDesc, Texture};
+use crate::renderer::pipeline::{PipelineDesc, RendererPipeline};
+use crate::renderer::overlay::RendererOverlay;
+use crate::renderer::hdr::OutputEncoding;
+use crate::renderer::target::RenderTarget;
+use crate::text::{FontId, SdfAtlas, TextBatch, TextLayout, TextVertex};
+
+use glam::{Mat4, Vec3};
+
+use std::{mem, slice};
+
+use anyhow::Result;
+
+/// The width and height of the atlas fonts are rasterized into.
+pub const ATLAS_SIZE: u32 = 1024;
+
+pub fn vertex_bindings() -> [vk::VertexInputBindingDescription; 1] {
+    [
+        vk::VertexInputBindingDescription {
+            binding: 0,
+            stride: mem::size_of::<TextVertex>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }
+    ]
+}
+
+pub fn vertex_attributes() -> [vk::VertexInputAttributeDescription; 3] {
+    let attribute = |location: u32, format: vk::Format, offset: usize| vk::VertexInputAttributeDescription {
+        location,
+        binding: 0,
+        format,
+        offset: offset as u32,
+    };
+
+    let f32_size = mem::size_of::<f32>();
+
+    [
+        attribute(0, vk::Format::R32G32B32_SFLOAT, 0),
+        attribute(1, vk::Format::R32G32_SFLOAT, 3 * f32_size),
+        attribute(2, vk::Format::R8G8B8A8_UNORM, 5 * f32_size),
+    ]
+}
+
+/// What the shaders read, screen space positions go from pixels to clip space with `scale` and `translate`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct TextConstants {
+    scale: [f32; 2],
+    translate: [f32; 2],
+    encoding: u32,
+    paper_white: f32,
+}
+
+/// Where the frame's batches are in the buffers of its slot, world space first.
+#[derive(Clone, Copy, Debug, Default)]
+struct Written {
+    slot: usize,
+    world_indices: u32,
+    screen_indices: u32,
+    screen_vertex_offset: i32,
+}
+
+pub struct RendererText {
+    pub atlas: SdfAtlas,
+    /// The atlas as RGBA8, with the distances in every channel.
+    pub texture: Texture,
+    pub set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_set: vk::DescriptorSet,
+    /// Draws into the frame's render pass, with the atlas in set 0 and the camera in set 1.
+    pub world_pipeline: RendererPipeline,
+    /// Loads the target's image and leaves it in the layout it was in.
+    pub render_pass: vk::RenderPass,
+    pub screen_pipeline: RendererPipeline,
+    /// One for each frame in flight, created with the first frame that draws text.
+    pub vertex_buffers: Vec<Option<RendererBuffer>>,
+    pub index_buffers: Vec<Option<RendererBuffer>>,
+    /// What's queued for the frame being recorded.
+    pub world: TextBatch,
+    pub screen: TextBatch,
+    pub encoding: OutputEncoding,
+    /// The atlas' version the texture was uploaded from.
+    uploaded: u64,
+    format: vk::Format,
+    final_layout: vk::ImageLayout,
+    written: Option<Written>,
+}
+
+impl RendererText {
+    /// Uploads `atlas` and creates the pipelines, for the frame's `render_pass` and for passes over `target`'s
+    /// images.
+    pub fn new(
+        device: &RendererDevice,
+        command_pools: &CommandPools,
+        target: &RenderTarget,
+        render_pass: vk::RenderPass,
+        camera_set_layout: vk::DescriptorSetLayout,
+        samples: vk::SampleCountFlags,
+        reverse_z: bool,
+        atlas: SdfAtlas
+    ) -> Result<RendererText> {
+        let slots = target.image_count() as usize;
+
+        let texture = Self::upload(device, command_pools, &atlas)?;
+
+        let mut text = RendererText {
+            uploaded: atlas.version,
+            atlas,
+            texture,
+            set_layout: vk::DescriptorSetLayout::null(),
+            descriptor_pool: vk::DescriptorPool::null(),
+            descriptor_set: vk::DescriptorSet::null(),
+            world_pipeline: RendererPipeline {
+                pipeline: vk::Pipeline::null(),
+                pipeline_layout: vk::PipelineLayout::null(),
+            },
+            render_pass: vk::RenderPass::null(),
+            screen_pipeline: RendererPipeline {
+                pipeline: vk::Pipeline::null(),
+                pipeline_layout: vk::PipelineLayout::null(),
+            },
+            vertex_buffers: (0..slots).map(|_| None).collect(),
+            index_buffers: (0..slots).map(|_| None).collect(),
+            world: TextBatch::new(),
+            screen: TextBatch::new(),
+            encoding: target.output_encoding(),
+            format: target.format(),
+            final_layout: target.final_layout(),
+            written: None,
+        };
+
+        let created = text.create_descriptors(device)
+            .and_then(|_| text.create_world_pipeline(device, target, render_pass, camera_set_layout, samples, reverse_z))
+            .and_then(|_| text.create_screen_pass(device, target));
+
+        if let Err(err) = created {
+            unsafe {
+                text.cleanup(device);
+            };
+
+            return Err(err);
+        }
+
+        Ok(text)
+    }
+
+    fn upload(device: &RendererDevice, command_pools: &CommandPools, atlas: &SdfAtlas) -> Result<Texture> {
+        let pixels: Vec<u8> = atlas.pixels.iter()
+            .flat_map(|&distance| [distance; 4])
+            .collect();
+
+        let extent = vk::Extent2D {
+            width: atlas.width,
+            height: atlas.height,
+        };
+
+        let sampler_desc = SamplerDesc {
+            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            ..Default::default()
+        };
+
+        Texture::from_rgba8(device, command_pools, "text atlas", extent, &pixels, false, &sampler_desc)
+    }
+
+    fn create_descriptors(&mut self, device: &RendererDevice) -> Result<()> {
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .build()
+        ];
+
+        let set_layout_info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .bindings(&bindings);
+
+        self.set_layout = unsafe {
+            device.logical_device.create_descriptor_set_layout(&set_layout_info, None)?
+        };
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: 1,
+            }
+        ];
+
+        let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(1)
+            .pool_sizes(&pool_sizes);
+
+        self.descriptor_pool = unsafe {
+            device.logical_device.create_descriptor_pool(&pool_info, None)?
+        };
+
+        let set_layouts = [self.set_layout];
+
+        let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(self.descriptor_pool)
+            .set_layouts(&set_layouts);
+
+        self.descriptor_set = unsafe {
+            device.logical_device.allocate_descriptor_sets(&allocate_info)?
+        }[0];
+
+        self.write_descriptor(device);
+
+        Ok(())
+    }
+
+    fn write_descriptor(&self, device: &RendererDevice) {
+        let image_infos = [
+            vk::DescriptorImageInfo {
+                sampler: self.texture.sampler,
+                image_view: self.texture.image.image_view,
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            }
+        ];
+
+        let writes = [
+            vk::WriteDescriptorSet::builder()
+                .dst_set(self.descriptor_set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(&image_infos)
+                .build()
+        ];
+
+        unsafe {
+            device.logical_device.update_descriptor_sets(&writes, &[]);
+        };
+    }
+
+    fn push_constant_ranges() -> [vk::PushConstantRange; 1] {
+        [
+            vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                offset: 0,
+                size: mem::size_of::<TextConstants>() as u32,
+            }
+        ]
+    }
+
+    fn create_world_pipeline(
+        &mut self,
+        device: &RendererDevice,
+        target: &RenderTarget,
+        render_pass: vk::RenderPass,
+        camera_set_layout: vk::DescriptorSetLayout,
+        samples: vk::SampleCountFlags,
+        reverse_z: bool
+    ) -> Result<()> {
+        // seen from behind too, and behind what's in front without hiding it:
+        self.world_pipeline = RendererPipeline::from_desc(device, target.extent(), render_pass, &PipelineDesc {
+            vert_code: vk_shader_macros::include_glsl!("./shaders/text_world.vert"),
+            frag_code: vk_shader_macros::include_glsl!("./shaders/text.frag"),
+            vertex_bindings: &vertex_bindings(),
+            vertex_attributes: &vertex_attributes(),
+            set_layouts: &[self.set_layout, camera_set_layout],
+            push_constant_ranges: &Self::push_constant_ranges(),
+            depth_test: true,
+            blend: true,
+            samples,
+            reverse_z,
+            dynamic_viewport: true,
+            ..Default::default()
+        })?;
+
+        Ok(())
+    }
+
+    fn create_screen_pass(&mut self, device: &RendererDevice, target: &RenderTarget) -> Result<()> {
+        self.render_pass = RendererOverlay::create_render_pass(device, target.format(), target.final_layout())?;
+
+        self.screen_pipeline = RendererPipeline::from_desc(device, target.extent(), self.render_pass, &PipelineDesc {
+            vert_code: vk_shader_macros::include_glsl!("./shaders/text.vert"),
+            frag_code: vk_shader_macros::include_glsl!("./shaders/text.frag"),
+            vertex_bindings: &vertex_bindings(),
+            vertex_attributes: &vertex_attributes(),
+            set_layouts: &[self.set_layout],
+            push_constant_ranges: &Self::push_constant_ranges(),
+            blend: true,
+            dynamic_viewport: true,
+            ..Default::default()
+        })?;
+
+        self.encoding = target.output_encoding();
+        self.format = target.format();
+        self.final_layout = target.final_layout();
+
+        Ok(())
+    }
+
+    /// Creates the world space pipeline again, for depth that's been switched. The device has to be idle.
+    pub fn rebuild_world_pipeline(
+        &mut self,
+        device: &RendererDevice,
+        target: &RenderTarget,
+        render_pass: vk::RenderPass,
+        camera_set_layout: vk::DescriptorSetLayout,
+        samples: vk::SampleCountFlags,
+        reverse_z: bool
+    ) -> Result<()> {
+        unsafe {
+            self.world_pipeline.cleanup(&device.logical_device);
+        };
+
+        self.create_world_pipeline(device, target, render_pass, camera_set_layout, samples, reverse_z)
+    }
+
+    /// Whether the screen space pass was made for images like `target`'s.
+    pub fn fits(&self, target: &RenderTarget) -> bool {
+        self.format == target.format()
+            && self.final_layout == target.final_layout()
+            && self.encoding == target.output_encoding()
+    }
+
+    /// Creates the screen space pass again for `target`'s images, the device has to be idle.
+    pub fn fit(&mut self, device: &RendererDevice, target: &RenderTarget) -> Result<()> {
+        if self.fits(target) {
+            return Ok(());
+        }
+
+        unsafe {
+            self.cleanup_screen_pass(device);
+        };
+
+        self.create_screen_pass(device, target)
+    }
+
+    /// Forgets what was queued, for a frame that's beginning.
+    pub fn begin(&mut self) {
+        self.world.clear();
+        self.screen.clear();
+        self.written = None;
+    }
+
+    /// Queues `layout` with its top left corner at `position`, in pixels from the target's top left corner.
+    pub fn queue_screen(&mut self, font: FontId, layout: &TextLayout, position: [f32; 2], color: [u8; 4]) -> Result<()> {
+        let transform = Mat4::from_translation(Vec3::new(position[0], position[1], 0.0));
+
+        self.screen.push(&mut self.atlas, font, layout, transform, color)
+    }
+
+    /// Queues `layout` placed by `model`, with its lines along +X and down -Y from the origin, facing +Z. One unit
+    /// of the layout is one unit of the world.
+    pub fn queue_world(&mut self, font: FontId, layout: &TextLayout, model: Mat4, color: [u8; 4]) -> Result<()> {
+        // the layout's Y goes down:
+        let transform = model * Mat4::from_scale(Vec3::new(1.0, -1.0, 1.0));
+
+        self.world.push(&mut self.atlas, font, layout, transform, color)
+    }
+
+    /// Uploads the atlas when it changed and writes what's queued into the buffers of `slot`, whose frame is being
+    /// recorded. Creates the screen space pass again if `target` needs another one, both wait for the device.
+    pub fn write(
+        &mut self,
+        device: &RendererDevice,
+        command_pools: &CommandPools,
+        target: &RenderTarget,
+        slot: usize
+    ) -> Result<()> {
+        self.written = None;
+
+        if self.world.is_empty() && self.screen.is_empty() {
+            return Ok(());
+        }
+
+        if self.uploaded != self.atlas.version || !self.fits(target) {
+            unsafe {
+                device.logical_device.device_wait_idle()?;
+            };
+        }
+
+        // the frames that sampled the old texture have finished:
+        if self.uploaded != self.atlas.version {
+            let mut texture = Self::upload(device, command_pools, &self.atlas)?;
+
+            mem::swap(&mut self.texture, &mut texture);
+
+            unsafe {
+                texture.cleanup(device);
+            };
+
+            self.write_descriptor(device);
+
+            self.uploaded = self.atlas.version;
+        }
+
+        // one of the other windows can have images of another format:
+        self.fit(device, target)?;
+
+        let vertices: Vec<TextVertex> = self.world.vertices.iter()
+            .chain(&self.screen.vertices)
+            .copied()
+            .collect();
+
+        let indices: Vec<u32> = self.world.indices.iter()
+            .chain(&self.screen.indices)
+            .copied()
+            .collect();
+
+        RendererOverlay::write_buffer(device, &mut self.vertex_buffers[slot], "text vertices", vk::BufferUsageFlags::VERTEX_BUFFER, &vertices)?;
+        RendererOverlay::write_buffer(device, &mut self.index_buffers[slot], "text indices", vk::BufferUsageFlags::INDEX_BUFFER, &indices)?;
+
+        self.written = Some(Written {
+            slot,
+            world_indices: self.world.indices.len() as u32,
+            screen_indices: self.screen.indices.len() as u32,
+            screen_vertex_offset: self.world.vertices.len() as i32,
+        });
+
+        Ok(())
+    }
+
+    /// Records the world space draw into `command_buffer`, inside the frame's render pass.
+    pub fn record_world<B: Backend>(
+        &self,
+        backend: &B,
+        command_buffer: vk::CommandBuffer,
+        camera_set: vk::DescriptorSet,
+        extent: vk::Extent2D
+    ) {
+        let written = match self.written.filter(|written| written.world_indices > 0) {
+            None => return,
+            Some(written) => written
+        };
+
+        // the HDR target holds linear colors:
+        let constants = TextConstants {
+            scale: [1.0, 1.0],
+            translate: [0.0, 0.0],
+            encoding: 0,
+            paper_white: 1.0,
+        };
+
+        self.bind(backend, command_buffer, written.slot, &self.world_pipeline, &[self.descriptor_set, camera_set], &constants);
+
+        Self::set_viewport(backend, command_buffer, extent);
+
+        backend.cmd_draw_indexed(command_buffer, written.world_indices, 1, 0, 0, 0);
+    }
+
+    /// Records the screen space pass into `framebuffer`, when there's screen space text.
+    pub fn record_screen<B: Backend>(
+        &self,
+        backend: &B,
+        command_buffer: vk::CommandBuffer,
+        framebuffer: vk::Framebuffer,
+        extent: vk::Extent2D,
+        paper_white: f32
+    ) {
+        let written = match self.written.filter(|written| written.screen_indices > 0) {
+            None => return,
+            Some(written) => written
+        };
+
+        let constants = TextConstants {
+            scale: [2.0 / extent.width as f32, 2.0 / extent.height as f32],
+            translate: [-1.0, -1.0],
+            encoding: match self.encoding {
+                OutputEncoding::Linear => 0,
+                OutputEncoding::Srgb => 1,
+                OutputEncoding::Pq => 2,
+                OutputEncoding::ScRgb => 3,
+            },
+            paper_white,
+        };
+
+        let render_pass_begin_info = vk::RenderPassBeginInfo::builder()
+            .render_pass(self.render_pass)
+            .framebuffer(framebuffer)
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent,
+            });
+
+        backend.cmd_begin_render_pass(
+            command_buffer,
+            &render_pass_begin_info,
+            vk::SubpassContents::INLINE,
+        );
+
+        self.bind(backend, command_buffer, written.slot, &self.screen_pipeline, &[self.descriptor_set], &constants);
+
+        Self::set_viewport(backend, command_buffer, extent);
+
+        backend.cmd_draw_indexed(
+            command_buffer,
+            written.screen_indices,
+            1,
+            written.world_indices,
+            written.screen_vertex_offset,
+            0,
+        );
+
+        backend.cmd_end_render_pass(command_buffer);
+    }
+
+    fn bind<B: Backend>(
+        &self,
+        backend: &B,
+        command_buffer: vk::CommandBuffer,
+        slot: usize,
+        pipeline: &RendererPipeline,
+        descriptor_sets: &[vk::DescriptorSet],
+        constants: &TextConstants
+    ) {
+        let (vertex_buffer, index_buffer) = match (&self.vertex_buffers[slot], &self.index_buffers[slot]) {
+            (Some(vertex_buffer), Some(index_buffer)) => (vertex_buffer.buffer, index_buffer.buffer),
+            _ => return,
+        };
+
+        let constants_bytes = unsafe {
+            slice::from_raw_parts(constants as *const TextConstants as *const u8, mem::size_of::<TextConstants>())
+        };
+
+        backend.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            pipeline.pipeline,
+        );
+
+        backend.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            pipeline.pipeline_layout,
+            0,
+            descriptor_sets,
+            &[],
+        );
+
+        backend.cmd_push_constants(
+            command_buffer,
+            pipeline.pipeline_layout,
+            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            0,
+            constants_bytes,
+        );
+
+        backend.cmd_bind_vertex_buffers(command_buffer, 0, &[vertex_buffer], &[0]);
+        backend.cmd_bind_index_buffer(command_buffer, index_buffer, 0, vk::IndexType::UINT32);
+    }
+
+    // the pipelines draw into whichever target the frame has:
+    fn set_viewport<B: Backend>(backend: &B, command_buffer: vk::CommandBuffer, extent: vk::Extent2D) {
+        let viewport = vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: extent.width as f32,
+            height: extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        };
+
+        let scissor = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent,
+        };
+
+        backend.cmd_set_viewport(command_buffer, 0, &[viewport]);
+        backend.cmd_set_scissor(command_buffer, 0, &[scissor]);
+    }
+
+    // nulls what it destroys, `fit` can fail after it:
+    unsafe fn cleanup_screen_pass(&mut self, device: &RendererDevice) {
+        mem::take(&mut self.screen_pipeline).cleanup(&device.logical_device);
+
+        device.logical_device.destroy_render_pass(mem::take(&mut self.render_pass), None);
+    }
+
+    pub unsafe fn cleanup(&mut self, device: &RendererDevice) {
+        self.cleanup_screen_pass(device);
+
+        self.world_pipeline.cleanup(&device.logical_device);
+
+        for buffer in self.vertex_buffers.iter_mut().chain(&mut self.index_buffers).flatten() {
+            buffer.cleanup(device);
+        }
+
+        device.logical_device.destroy_descriptor_pool(self.descriptor_pool, None);
+        device.logical_device.destroy_descriptor_set_layout(self.set_layout, None);
+
+        self.texture.cleanup(device);
+    }
+}
@@ -0,0 +1,104 @@
+use ash::vk;
+
+use crate::renderer::device::RendererDevice;
+use crate::renderer::command_pools::CommandPools;
+use crate::renderer::image::RendererImage;
+
+use anyhow::Result;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SamplerDesc {
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub address_mode_u: vk::SamplerAddressMode,
+    pub address_mode_v: vk::SamplerAddressMode,
+}
+
+impl Default for SamplerDesc {
+    fn default() -> Self {
+        SamplerDesc {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            address_mode_u: vk::SamplerAddressMode::REPEAT,
+            address_mode_v: vk::SamplerAddressMode::REPEAT,
+        }
+    }
+}
+
+/// A sampled image together with the sampler it is meant to be read with.
+pub struct Texture {
+    pub image: RendererImage,
+    pub sampler: vk::Sampler,
+}
+
+impl Texture {
+    /// Uploads tightly packed RGBA8 `pixels`, `srgb` tells whether they hold colors or linear data.
+    pub fn from_rgba8(
+        device: &RendererDevice,
+        command_pools: &CommandPools,
+        name: &str,
+        extent: vk::Extent2D,
+        pixels: &[u8],
+        srgb: bool,
+        sampler_desc: &SamplerDesc
+    ) -> Result<Texture> {
+        let expected = extent.width as usize * extent.height as usize * 4;
+
+        if pixels.len() != expected {
+            anyhow::bail!("Texture '{}' has {} bytes of pixels, expected {}", name, pixels.len(), expected);
+        }
+
+        let format = if srgb {
+            vk::Format::R8G8B8A8_SRGB
+        } else {
+            vk::Format::R8G8B8A8_UNORM
+        };
+
+        let mut image = RendererImage::new(
+            device,
+            name,
+            extent,
+            format,
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST
+        )?;
+
+        if let Err(err) = image.upload(device, command_pools, pixels) {
+            unsafe {
+                image.cleanup(device);
+            };
+
+            return Err(err);
+        }
+
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(sampler_desc.mag_filter)
+            .min_filter(sampler_desc.min_filter)
+            .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+            .address_mode_u(sampler_desc.address_mode_u)
+            .address_mode_v(sampler_desc.address_mode_v)
+            .address_mode_w(vk::SamplerAddressMode::REPEAT)
+            .max_lod(vk::LOD_CLAMP_NONE);
+
+        let sampler = match unsafe { device.logical_device.create_sampler(&sampler_info, None) } {
+            Err(err) => {
+                unsafe {
+                    image.cleanup(device);
+                };
+
+                return Err(err.into());
+            },
+            Ok(sampler) => sampler
+        };
+
+        Ok(Texture {
+            image,
+            sampler,
+        })
+    }
+
+    pub unsafe fn cleanup(&mut self, device: &RendererDevice) {
+        device.logical_device.destroy_sampler(self.sampler, None);
+
+        self.image.cleanup(device);
+    }
+}
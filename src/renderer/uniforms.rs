@@ -0,0 +1,124 @@
+use ash::vk;
+
+use crate::renderer::device::RendererDevice;
+use crate::renderer::buffer::RendererBuffer;
+
+use gpu_allocator::MemoryLocation;
+
+use std::slice;
+
+use anyhow::Result;
+
+/// One uniform buffer and descriptor set per frame in flight, so a frame can be written while older ones are still read.
+pub struct UniformBuffers {
+    pub buffers: Vec<RendererBuffer>,
+    pub set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_sets: Vec<vk::DescriptorSet>,
+}
+
+impl UniformBuffers {
+    pub fn new(
+        device: &RendererDevice,
+        name: &str,
+        size: vk::DeviceSize,
+        count: usize,
+        stages: vk::ShaderStageFlags
+    ) -> Result<UniformBuffers> {
+        let mut buffers = Vec::with_capacity(count);
+
+        for _ in 0..count {
+            buffers.push(RendererBuffer::new(
+                device,
+                name,
+                size,
+                vk::BufferUsageFlags::UNIFORM_BUFFER,
+                MemoryLocation::CpuToGpu,
+            )?);
+        }
+
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(stages)
+                .build()
+        ];
+
+        let set_layout_info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .bindings(&bindings);
+
+        let set_layout = unsafe {
+            device.logical_device.create_descriptor_set_layout(&set_layout_info, None)?
+        };
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: count as u32,
+            }
+        ];
+
+        let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(count as u32)
+            .pool_sizes(&pool_sizes);
+
+        let descriptor_pool = unsafe {
+            device.logical_device.create_descriptor_pool(&pool_info, None)?
+        };
+
+        let set_layouts = vec![set_layout; count];
+
+        let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&set_layouts);
+
+        let descriptor_sets = unsafe {
+            device.logical_device.allocate_descriptor_sets(&allocate_info)?
+        };
+
+        for (buffer, &descriptor_set) in buffers.iter().zip(&descriptor_sets) {
+            let buffer_infos = [
+                vk::DescriptorBufferInfo {
+                    buffer: buffer.buffer,
+                    offset: 0,
+                    range: size,
+                }
+            ];
+
+            let writes = [
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_set)
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                    .buffer_info(&buffer_infos)
+                    .build()
+            ];
+
+            unsafe {
+                device.logical_device.update_descriptor_sets(&writes, &[]);
+            };
+        }
+
+        Ok(UniformBuffers {
+            buffers,
+            set_layout,
+            descriptor_pool,
+            descriptor_sets,
+        })
+    }
+
+    pub fn write<T: Copy>(&mut self, slot: usize, data: &T) -> Result<()> {
+        self.buffers[slot].write(slice::from_ref(data))
+    }
+
+    pub unsafe fn cleanup(&mut self, device: &RendererDevice) {
+        device.logical_device.destroy_descriptor_pool(self.descriptor_pool, None);
+        device.logical_device.destroy_descriptor_set_layout(self.set_layout, None);
+
+        for buffer in &mut self.buffers {
+            buffer.cleanup(device);
+        }
+    }
+}
@@ -0,0 +1,806 @@
+//! Running video frames through a chain of compute shaders, from YUV 4:2:0 back to YUV 4:2:0.
+//!
+//! A frame's Y, U and V planes are uploaded into an `R8_UNORM` image each, rather than one multi-planar image with a
+//! sampler YCbCr conversion, so that chroma siting and the color matrix are up to us and match what
+//! [`capture`](crate::renderer::capture) encodes with. A pass turns the planes into RGB, then every filter reads the
+//! image the pass before wrote and writes an image of its own. The last image goes through the same conversion as
+//! captured frames do, into a buffer that's read once the frame is done.
+//!
+//! Filters see the R'G'B' the video was encoded from, as it is, nothing's decoded into linear light. Frames are
+//! processed one at a time and waited for, which is what a tool that reads and writes files wants.
+
+use ash::vk;
+
+use crate::renderer::device::RendererDevice;
+use crate::renderer::command_pools::CommandPools;
+use crate::renderer::buffer::RendererBuffer;
+use crate::renderer::image::RendererImage;
+use crate::renderer::pipeline::RendererPipeline;
+use crate::renderer::hdr::HDR_FORMAT;
+use crate::renderer::capture::{CaptureConstants, CaptureFormat};
+use crate::video::Yuv420;
+
+use gpu_allocator::MemoryLocation;
+
+use glam::{Mat4, Vec4};
+
+use std::{mem, slice};
+
+use anyhow::Result;
+
+/// Blurs reach at most this many pixels to either side.
+pub const MAX_BLUR_RADIUS: i32 = 64;
+
+/// One step of the chain.
+#[derive(Clone, Debug, PartialEq)]
+pub enum VideoFilter {
+    /// Resizes the frame, the filters after it see the new size.
+    Scale { width: u32, height: u32 },
+    /// A gaussian blur, `sigma` in pixels, done in two passes.
+    Blur { sigma: f32 },
+    /// Pushes pixels away from their blurred surroundings, 0 leaves the frame as it is.
+    Sharpen { amount: f32 },
+    /// Multiplies every color by a matrix, as RGB and a 1.
+    ColorMatrix { matrix: Mat4 },
+    /// A shader of your own, compiled to SPIR-V, with 8x8 invocations per group and one invocation per pixel.
+    ///
+    /// It reads the frame from `layout(set = 0, binding = 0) uniform sampler2D` and writes it to
+    /// `layout(set = 0, binding = 1, rgba16f) uniform writeonly image2D`, which is as big. `constants` are pushed
+    /// as they are, they have to be whole words and can be empty.
+    Custom { code: Vec<u32>, constants: Vec<u8> },
+}
+
+impl VideoFilter {
+    /// Scales how far colors are from the gray of the same luma, 0 makes the frame grayscale.
+    pub fn saturation(saturation: f32) -> VideoFilter {
+        // BT.601, like the frames:
+        let luma = Vec4::new(0.299, 0.587, 0.114, 0.0) * (1.0 - saturation);
+
+        let column = |axis: Vec4, weight: f32| axis * saturation + Vec4::new(weight, weight, weight, 0.0);
+
+        VideoFilter::ColorMatrix {
+            matrix: Mat4::from_cols(
+                column(Vec4::X, luma.x),
+                column(Vec4::Y, luma.y),
+                column(Vec4::Z, luma.z),
+                Vec4::W,
+            ),
+        }
+    }
+}
+
+/// How the blur pass is told which way to go.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct BlurConstants {
+    direction: [i32; 2],
+    radius: i32,
+    sigma: f32,
+}
+
+/// A filter's pass, which reads the stage before it and writes the stage after it.
+pub struct VideoPass {
+    pub pipeline: RendererPipeline,
+    pub descriptor_set: vk::DescriptorSet,
+    pub constants: Vec<u8>,
+}
+
+/// What a pass is created from.
+struct PassDesc<'a> {
+    code: &'a [u32],
+    constants: Vec<u8>,
+    extent: vk::Extent2D,
+}
+
+/// The planes frames are uploaded into, an image per step of the chain, and the passes between them.
+pub struct RendererVideoFilters {
+    pub input: vk::Extent2D,
+    pub output: vk::Extent2D,
+    /// Y, U and V.
+    pub planes: Vec<RendererImage>,
+    /// What frames are written into before they're copied into the planes, the planes one after the other.
+    pub staging: RendererBuffer,
+    /// The frame in RGB, converted from the planes and then once more per pass.
+    pub stages: Vec<RendererImage>,
+    pub readback: RendererBuffer,
+    pub sampler: vk::Sampler,
+    pub convert_set_layout: vk::DescriptorSetLayout,
+    pub stage_set_layout: vk::DescriptorSetLayout,
+    pub output_set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub convert_set: vk::DescriptorSet,
+    pub output_set: vk::DescriptorSet,
+    pub convert: RendererPipeline,
+    pub passes: Vec<VideoPass>,
+    /// Converts the last stage back into YUV 4:2:0, what captures go through.
+    pub output_pipeline: RendererPipeline,
+    output_constants: CaptureConstants,
+}
+
+impl RendererVideoFilters {
+    /// A chain that takes frames of `input` through `filters`, in order.
+    pub fn new(device: &RendererDevice, input: vk::Extent2D, filters: &[VideoFilter]) -> Result<RendererVideoFilters> {
+        if input.width == 0 || input.height == 0 {
+            anyhow::bail!("Frames can't be {}x{}", input.width, input.height);
+        }
+
+        let passes = Self::describe(input, filters)?;
+
+        let output = passes.last()
+            .map(|pass| pass.extent)
+            .unwrap_or(input);
+
+        let (output_constants, words) = CaptureConstants::layout(CaptureFormat::Y4m, output);
+
+        let null_image = |extent| RendererImage {
+            image: vk::Image::null(),
+            image_view: vk::ImageView::null(),
+            allocation: Default::default(),
+            format: vk::Format::R8_UNORM,
+            extent,
+            depth: 1,
+            layers: 1,
+            mip_levels: 1,
+            samples: vk::SampleCountFlags::TYPE_1,
+        };
+
+        let null_buffer = || RendererBuffer {
+            buffer: vk::Buffer::null(),
+            allocation: Default::default(),
+            size: 0,
+        };
+
+        let null_pipeline = || RendererPipeline {
+            pipeline: vk::Pipeline::null(),
+            pipeline_layout: vk::PipelineLayout::null(),
+        };
+
+        let mut video_filters = RendererVideoFilters {
+            input,
+            output,
+            planes: vec![],
+            staging: null_buffer(),
+            stages: vec![],
+            readback: null_buffer(),
+            sampler: vk::Sampler::null(),
+            convert_set_layout: vk::DescriptorSetLayout::null(),
+            stage_set_layout: vk::DescriptorSetLayout::null(),
+            output_set_layout: vk::DescriptorSetLayout::null(),
+            descriptor_pool: vk::DescriptorPool::null(),
+            convert_set: vk::DescriptorSet::null(),
+            output_set: vk::DescriptorSet::null(),
+            convert: null_pipeline(),
+            passes: Vec::with_capacity(passes.len()),
+            output_pipeline: null_pipeline(),
+            output_constants,
+        };
+
+        // planes the size of the frame and its chroma:
+        let (chroma_width, chroma_height) = Yuv420::chroma_size(input.width, input.height);
+
+        let chroma = vk::Extent2D {
+            width: chroma_width,
+            height: chroma_height,
+        };
+
+        video_filters.planes = vec![null_image(input), null_image(chroma), null_image(chroma)];
+
+        if let Err(err) = video_filters.create(device, &passes, words as vk::DeviceSize * 4) {
+            unsafe {
+                video_filters.cleanup(device);
+            };
+
+            return Err(err);
+        }
+
+        Ok(video_filters)
+    }
+
+    /// The passes `filters` take, and how big what each one writes is.
+    fn describe(input: vk::Extent2D, filters: &[VideoFilter]) -> Result<Vec<PassDesc<'_>>> {
+        let mut passes = Vec::with_capacity(filters.len());
+
+        let mut extent = input;
+
+        for filter in filters {
+            match filter {
+                VideoFilter::Scale { width, height } => {
+                    if *width == 0 || *height == 0 {
+                        anyhow::bail!("Frames can't be scaled to {}x{}", width, height);
+                    }
+
+                    extent = vk::Extent2D {
+                        width: *width,
+                        height: *height,
+                    };
+
+                    passes.push(PassDesc {
+                        code: vk_shader_macros::include_glsl!("./shaders/video_scale.comp"),
+                        constants: vec![],
+                        extent,
+                    });
+                },
+                VideoFilter::Blur { sigma } => {
+                    if sigma.is_nan() || *sigma <= 0.0 {
+                        anyhow::bail!("A blur needs a sigma above 0, not {}", sigma);
+                    }
+
+                    // past 3 sigma the weights don't add anything that 8-bit frames can hold:
+                    let radius = ((sigma * 3.0).ceil() as i32).min(MAX_BLUR_RADIUS);
+
+                    for direction in [[1, 0], [0, 1]] {
+                        passes.push(PassDesc {
+                            code: vk_shader_macros::include_glsl!("./shaders/video_blur.comp"),
+                            constants: Self::bytes(&BlurConstants {
+                                direction,
+                                radius,
+                                sigma: *sigma,
+                            }),
+                            extent,
+                        });
+                    }
+                },
+                VideoFilter::Sharpen { amount } => passes.push(PassDesc {
+                    code: vk_shader_macros::include_glsl!("./shaders/video_sharpen.comp"),
+                    constants: Self::bytes(amount),
+                    extent,
+                }),
+                VideoFilter::ColorMatrix { matrix } => passes.push(PassDesc {
+                    code: vk_shader_macros::include_glsl!("./shaders/video_color.comp"),
+                    constants: Self::bytes(&matrix.to_cols_array()),
+                    extent,
+                }),
+                VideoFilter::Custom { code, constants } => {
+                    // what every device can push:
+                    if constants.len() % 4 != 0 || constants.len() > 128 {
+                        anyhow::bail!("A filter's constants have to be whole words and at most 128 bytes, not {}", constants.len());
+                    }
+
+                    passes.push(PassDesc {
+                        code,
+                        constants: constants.clone(),
+                        extent,
+                    });
+                }
+            }
+        }
+
+        Ok(passes)
+    }
+
+    fn bytes<T: Copy>(value: &T) -> Vec<u8> {
+        unsafe {
+            slice::from_raw_parts(value as *const T as *const u8, mem::size_of::<T>()).to_vec()
+        }
+    }
+
+    fn create(&mut self, device: &RendererDevice, passes: &[PassDesc], readback_size: vk::DeviceSize) -> Result<()> {
+        // images:
+        for plane in &mut self.planes {
+            *plane = RendererImage::new(
+                device,
+                "video plane",
+                plane.extent,
+                vk::Format::R8_UNORM,
+                vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+            )?;
+        }
+
+        let extents: Vec<vk::Extent2D> = [self.input].into_iter()
+            .chain(passes.iter().map(|pass| pass.extent))
+            .collect();
+
+        for extent in extents {
+            self.stages.push(RendererImage::new(
+                device,
+                "video stage",
+                extent,
+                HDR_FORMAT,
+                vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED,
+            )?);
+        }
+
+        // buffers:
+        self.staging = RendererBuffer::new(
+            device,
+            "video staging",
+            Yuv420::frame_size(self.input.width, self.input.height)? as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            MemoryLocation::CpuToGpu,
+        )?;
+
+        self.readback = RendererBuffer::new(
+            device,
+            "video readback",
+            readback_size,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            MemoryLocation::GpuToCpu,
+        )?;
+
+        // bilinear for chroma and scaling, the other passes fetch texels:
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+
+        self.sampler = unsafe {
+            device.logical_device.create_sampler(&sampler_info, None)?
+        };
+
+        // descriptors:
+        let binding = |binding, descriptor_type| {
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(binding)
+                .descriptor_type(descriptor_type)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .build()
+        };
+
+        let convert_bindings = [
+            binding(0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+            binding(1, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+            binding(2, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+            binding(3, vk::DescriptorType::STORAGE_IMAGE),
+        ];
+
+        let stage_bindings = [
+            binding(0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+            binding(1, vk::DescriptorType::STORAGE_IMAGE),
+        ];
+
+        let output_bindings = [
+            binding(0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+            binding(1, vk::DescriptorType::STORAGE_BUFFER),
+        ];
+
+        for (set_layout, bindings) in [
+            (&mut self.convert_set_layout, &convert_bindings[..]),
+            (&mut self.stage_set_layout, &stage_bindings[..]),
+            (&mut self.output_set_layout, &output_bindings[..]),
+        ] {
+            let set_layout_info = vk::DescriptorSetLayoutCreateInfo::builder()
+                .bindings(bindings);
+
+            *set_layout = unsafe {
+                device.logical_device.create_descriptor_set_layout(&set_layout_info, None)?
+            };
+        }
+
+        let pass_count = passes.len() as u32;
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: 3 + pass_count + 1,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_IMAGE,
+                descriptor_count: 1 + pass_count,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: 1,
+            },
+        ];
+
+        let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(1 + pass_count + 1)
+            .pool_sizes(&pool_sizes);
+
+        self.descriptor_pool = unsafe {
+            device.logical_device.create_descriptor_pool(&pool_info, None)?
+        };
+
+        let set_layouts: Vec<vk::DescriptorSetLayout> = [self.convert_set_layout].into_iter()
+            .chain(passes.iter().map(|_| self.stage_set_layout))
+            .chain([self.output_set_layout])
+            .collect();
+
+        let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(self.descriptor_pool)
+            .set_layouts(&set_layouts);
+
+        let mut descriptor_sets = unsafe {
+            device.logical_device.allocate_descriptor_sets(&allocate_info)?
+        };
+
+        self.output_set = descriptor_sets.pop().unwrap();
+        self.convert_set = descriptor_sets.remove(0);
+
+        // the planes are sampled once they're uploaded, the stages stay in the general layout:
+        let sampled = |image: &RendererImage, image_layout| {
+            [
+                vk::DescriptorImageInfo {
+                    sampler: self.sampler,
+                    image_view: image.image_view,
+                    image_layout,
+                }
+            ]
+        };
+
+        let storage = |image: &RendererImage| {
+            [
+                vk::DescriptorImageInfo {
+                    sampler: vk::Sampler::null(),
+                    image_view: image.image_view,
+                    image_layout: vk::ImageLayout::GENERAL,
+                }
+            ]
+        };
+
+        let write = |descriptor_set, binding, descriptor_type, image_info: &[vk::DescriptorImageInfo]| {
+            vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(binding)
+                .descriptor_type(descriptor_type)
+                .image_info(image_info)
+                .build()
+        };
+
+        let plane_infos: Vec<_> = self.planes.iter()
+            .map(|plane| sampled(plane, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL))
+            .collect();
+
+        let first_stage = storage(&self.stages[0]);
+
+        let mut writes = vec![
+            write(self.convert_set, 0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, &plane_infos[0]),
+            write(self.convert_set, 1, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, &plane_infos[1]),
+            write(self.convert_set, 2, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, &plane_infos[2]),
+            write(self.convert_set, 3, vk::DescriptorType::STORAGE_IMAGE, &first_stage),
+        ];
+
+        // each pass reads the stage before it and writes the one after:
+        let source_infos: Vec<_> = self.stages.iter()
+            .map(|stage| sampled(stage, vk::ImageLayout::GENERAL))
+            .collect();
+
+        let destination_infos: Vec<_> = self.stages.iter()
+            .map(storage)
+            .collect();
+
+        for (index, &descriptor_set) in descriptor_sets.iter().enumerate() {
+            writes.push(write(descriptor_set, 0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, &source_infos[index]));
+            writes.push(write(descriptor_set, 1, vk::DescriptorType::STORAGE_IMAGE, &destination_infos[index + 1]));
+        }
+
+        let readback_infos = [
+            vk::DescriptorBufferInfo {
+                buffer: self.readback.buffer,
+                offset: 0,
+                range: self.readback.size,
+            }
+        ];
+
+        writes.push(write(self.output_set, 0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, &source_infos[passes.len()]));
+
+        writes.push(
+            vk::WriteDescriptorSet::builder()
+                .dst_set(self.output_set)
+                .dst_binding(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(&readback_infos)
+                .build()
+        );
+
+        unsafe {
+            device.logical_device.update_descriptor_sets(&writes, &[]);
+        };
+
+        // pipelines:
+        self.convert = RendererPipeline::compute(
+            device,
+            vk_shader_macros::include_glsl!("./shaders/video_yuv.comp"),
+            &[self.convert_set_layout],
+            &[],
+        )?;
+
+        for (pass, descriptor_set) in passes.iter().zip(descriptor_sets) {
+            let push_constant_ranges = [
+                vk::PushConstantRange {
+                    stage_flags: vk::ShaderStageFlags::COMPUTE,
+                    offset: 0,
+                    size: pass.constants.len() as u32,
+                }
+            ];
+
+            let push_constant_ranges = if pass.constants.is_empty() {
+                &[][..]
+            } else {
+                &push_constant_ranges[..]
+            };
+
+            let pipeline = RendererPipeline::compute(
+                device,
+                pass.code,
+                &[self.stage_set_layout],
+                push_constant_ranges,
+            )?;
+
+            self.passes.push(VideoPass {
+                pipeline,
+                descriptor_set,
+                constants: pass.constants.clone(),
+            });
+        }
+
+        let push_constant_ranges = [
+            vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::COMPUTE,
+                offset: 0,
+                size: mem::size_of::<CaptureConstants>() as u32,
+            }
+        ];
+
+        self.output_pipeline = RendererPipeline::compute(
+            device,
+            vk_shader_macros::include_glsl!("./shaders/capture.comp"),
+            &[self.output_set_layout],
+            &push_constant_ranges,
+        )?;
+
+        Ok(())
+    }
+
+    /// Runs `frame` through the chain and waits for what comes out, which is `output` big.
+    pub fn process(
+        &mut self,
+        device: &RendererDevice,
+        command_pools: &CommandPools,
+        frame: &Yuv420
+    ) -> Result<Yuv420> {
+        if frame.width != self.input.width || frame.height != self.input.height {
+            anyhow::bail!(
+                "A {}x{} frame can't go through filters made for {}x{}",
+                frame.width, frame.height, self.input.width, self.input.height,
+            );
+        }
+
+        self.staging.write_at(0, &frame.y)?;
+        self.staging.write_at(frame.y.len(), &frame.u)?;
+        self.staging.write_at(frame.y.len() + frame.u.len(), &frame.v)?;
+
+        command_pools.one_time_submit(device, |command_buffer| {
+            self.record(device, command_buffer, [0, frame.y.len(), frame.y.len() + frame.u.len()]);
+        })?;
+
+        Ok(self.output_constants.unpack_yuv(self.readback.read()?))
+    }
+
+    /// Records uploading the planes from `offsets` into the staging buffer, every pass, and the readback.
+    fn record(&self, device: &RendererDevice, command_buffer: vk::CommandBuffer, offsets: [usize; 3]) {
+        let color = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+
+        let barrier = |image: &RendererImage, old_layout, new_layout, src_access_mask, dst_access_mask| {
+            vk::ImageMemoryBarrier::builder()
+                .src_access_mask(src_access_mask)
+                .dst_access_mask(dst_access_mask)
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(image.image)
+                .subresource_range(color)
+                .build()
+        };
+
+        // the last frame was waited for, so what it left behind can be thrown away:
+        let to_upload: Vec<_> = self.planes.iter()
+            .map(|plane| barrier(
+                plane,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::AccessFlags::empty(),
+                vk::AccessFlags::TRANSFER_WRITE,
+            ))
+            .chain(self.stages.iter().map(|stage| barrier(
+                stage,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::GENERAL,
+                vk::AccessFlags::empty(),
+                vk::AccessFlags::SHADER_WRITE,
+            )))
+            .collect();
+
+        let uploaded: Vec<_> = self.planes.iter()
+            .map(|plane| barrier(
+                plane,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::AccessFlags::SHADER_READ,
+            ))
+            .collect();
+
+        // every stage is read by the pass after the one that wrote it:
+        let written: Vec<_> = self.stages.iter()
+            .map(|stage| barrier(
+                stage,
+                vk::ImageLayout::GENERAL,
+                vk::ImageLayout::GENERAL,
+                vk::AccessFlags::SHADER_WRITE,
+                vk::AccessFlags::SHADER_READ,
+            ))
+            .collect();
+
+        let converted = vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::HOST_READ);
+
+        let output_bytes = unsafe {
+            slice::from_raw_parts(
+                &self.output_constants as *const CaptureConstants as *const u8,
+                mem::size_of::<CaptureConstants>(),
+            )
+        };
+
+        let dispatch = |pipeline: &RendererPipeline, descriptor_set, groups: [u32; 2]| unsafe {
+            device.logical_device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, pipeline.pipeline);
+
+            device.logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                pipeline.pipeline_layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+
+            device.logical_device.cmd_dispatch(command_buffer, groups[0], groups[1], 1);
+        };
+
+        let compute_barrier = |barrier: vk::ImageMemoryBarrier| unsafe {
+            device.logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier],
+            );
+        };
+
+        // 8x8 invocations per group, one per pixel:
+        let groups = |extent: vk::Extent2D| [extent.width.div_ceil(8), extent.height.div_ceil(8)];
+
+        unsafe {
+            device.logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &to_upload,
+            );
+
+            for (plane, offset) in self.planes.iter().zip(offsets) {
+                let region = vk::BufferImageCopy::builder()
+                    .buffer_offset(offset as vk::DeviceSize)
+                    .image_subresource(vk::ImageSubresourceLayers {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        mip_level: 0,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    })
+                    .image_extent(vk::Extent3D {
+                        width: plane.extent.width,
+                        height: plane.extent.height,
+                        depth: 1,
+                    });
+
+                device.logical_device.cmd_copy_buffer_to_image(
+                    command_buffer,
+                    self.staging.buffer,
+                    plane.image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[region.build()],
+                );
+            }
+
+            device.logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &uploaded,
+            );
+        };
+
+        dispatch(&self.convert, self.convert_set, groups(self.input));
+
+        for (index, pass) in self.passes.iter().enumerate() {
+            compute_barrier(written[index]);
+
+            unsafe {
+                device.logical_device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, pass.pipeline.pipeline);
+
+                if !pass.constants.is_empty() {
+                    device.logical_device.cmd_push_constants(
+                        command_buffer,
+                        pass.pipeline.pipeline_layout,
+                        vk::ShaderStageFlags::COMPUTE,
+                        0,
+                        &pass.constants,
+                    );
+                }
+            };
+
+            dispatch(&pass.pipeline, pass.descriptor_set, groups(self.stages[index + 1].extent));
+        }
+
+        compute_barrier(written[self.passes.len()]);
+
+        unsafe {
+            device.logical_device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, self.output_pipeline.pipeline);
+
+            device.logical_device.cmd_push_constants(
+                command_buffer,
+                self.output_pipeline.pipeline_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                output_bytes,
+            );
+        };
+
+        // 8x8 invocations per group as in the shader, each covering 8x2 pixels:
+        dispatch(
+            &self.output_pipeline,
+            self.output_set,
+            [self.output.width.div_ceil(8).div_ceil(8), self.output.height.div_ceil(2).div_ceil(8)],
+        );
+
+        unsafe {
+            device.logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[converted.build()],
+                &[],
+                &[],
+            );
+        };
+    }
+
+    pub unsafe fn cleanup(&mut self, device: &RendererDevice) {
+        self.output_pipeline.cleanup(&device.logical_device);
+
+        for pass in &self.passes {
+            pass.pipeline.cleanup(&device.logical_device);
+        }
+
+        self.convert.cleanup(&device.logical_device);
+
+        device.logical_device.destroy_descriptor_pool(self.descriptor_pool, None);
+
+        device.logical_device.destroy_descriptor_set_layout(self.output_set_layout, None);
+        device.logical_device.destroy_descriptor_set_layout(self.stage_set_layout, None);
+        device.logical_device.destroy_descriptor_set_layout(self.convert_set_layout, None);
+
+        device.logical_device.destroy_sampler(self.sampler, None);
+
+        self.readback.cleanup(device);
+        self.staging.cleanup(device);
+
+        for stage in &mut self.stages {
+            stage.cleanup(device);
+        }
+
+        for plane in &mut self.planes {
+            plane.cleanup(device);
+        }
+    }
+}
@@ -0,0 +1,91 @@
+//! Windows besides the renderer's own, see [`super::VulkanRenderer::create_window`], and offscreen images that are
+//! drawn like them, see [`super::VulkanRenderer::create_offscreen_view`].
+//!
+//! A view has what a window needs to itself: the surface and swapchain, and everything with the swapchain's size,
+//! the depth buffer, HDR and post-process targets, the culling pyramid and the pipelines with its viewport. The
+//! device, meshes, textures, materials and per frame resources are shared with the renderer's window.
+//!
+//! While a view's frame is recorded, its parts are swapped with the renderer's, so everything that draws into the
+//! renderer's window draws into the view instead. Settings like the post-process effects and tonemapping belong to
+//! each window, and setting them between `begin_window_frame` and `end_frame` sets the view's.
+
+use crate::renderer::config::WindowConfig;
+use crate::renderer::culling::RendererCulling;
+use crate::renderer::device::RendererDevice;
+use crate::renderer::hdr::RendererHdr;
+use crate::renderer::image::RendererImage;
+use crate::renderer::pipeline::RendererPipeline;
+use crate::renderer::post::RendererPostProcess;
+use crate::renderer::target::RenderTarget;
+use crate::renderer::window::RendererWindow;
+
+use winit::window::WindowId;
+
+use std::time::{Duration, Instant};
+
+pub struct RendererView {
+    /// `None` for offscreen views, and while a window's frame is recorded, the renderer has it then.
+    pub window: Option<RendererWindow>,
+    pub window_config: WindowConfig,
+    pub target: RenderTarget,
+    pub depth: RendererImage,
+    pub hdr: RendererHdr,
+    pub post: RendererPostProcess,
+    pub culling: RendererCulling,
+    pub graphics_pipeline: RendererPipeline,
+    pub mesh_pipeline: RendererPipeline,
+    /// In the order of `MaterialKey::ALL`, with the renderer's material set layout.
+    pub material_pipelines: Vec<RendererPipeline>,
+    /// The least time between the view's frames, for windows that don't need to keep up with the display. Zero
+    /// renders as often as the present mode lets it.
+    pub frame_interval: Duration,
+    pub last_frame: Option<Instant>,
+}
+
+impl RendererView {
+    /// Nothing but the target yet, the renderer makes the rest for its size.
+    pub fn new(target: RenderTarget, window_config: WindowConfig) -> RendererView {
+        RendererView {
+            window: None,
+            window_config,
+            target,
+            depth: RendererImage::default(),
+            hdr: RendererHdr::default(),
+            post: RendererPostProcess::default(),
+            culling: RendererCulling::default(),
+            graphics_pipeline: RendererPipeline::default(),
+            mesh_pipeline: RendererPipeline::default(),
+            material_pipelines: vec![],
+            frame_interval: Duration::ZERO,
+            last_frame: None,
+        }
+    }
+
+    pub fn id(&self) -> Option<WindowId> {
+        self.window.as_ref().map(|window| window.window.id())
+    }
+
+    /// Whether `frame_interval` has passed since the last frame.
+    pub fn due(&self, now: Instant) -> bool {
+        match self.last_frame {
+            None => true,
+            Some(last_frame) => now.duration_since(last_frame) >= self.frame_interval
+        }
+    }
+
+    /// Everything but the window, which is destroyed along with the instance it was created on.
+    pub unsafe fn cleanup(&mut self, device: &RendererDevice) {
+        for pipeline in self.material_pipelines.drain(..) {
+            pipeline.cleanup(&device.logical_device);
+        }
+
+        self.mesh_pipeline.cleanup(&device.logical_device);
+        self.graphics_pipeline.cleanup(&device.logical_device);
+
+        self.culling.cleanup(device);
+        self.post.cleanup(device);
+        self.hdr.cleanup(device);
+        self.target.cleanup(device);
+        self.depth.cleanup(device);
+    }
+}
@@ -1,28 +1,174 @@
 use ash::vk;
 use ash::extensions::khr;
 
-use winit::event_loop::EventLoop;
-use winit::window::Window;
+use crate::renderer::config::{WindowConfig, WindowMode};
 
-use anyhow::Result;
+use winit::dpi::{LogicalPosition, LogicalSize};
+use winit::event_loop::{EventLoop, EventLoopWindowTarget};
+use winit::monitor::MonitorHandle;
+use winit::window::{Fullscreen, Icon, Window, WindowBuilder};
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
 
 pub struct RendererWindow {
     pub event_loop: Option<EventLoop<()>>,
     pub window: Window,
     pub surface: vk::SurfaceKHR,
     pub surface_loader: khr::Surface,
+    /// Present in HDR10 when the surface offers it, which takes `VK_EXT_swapchain_colorspace` on the instance.
+    pub hdr: bool,
+    /// What the swapchain presents with when the surface has it, `FIFO` otherwise.
+    pub present_mode: vk::PresentModeKHR,
+    /// How many images the swapchain asks for, at least what the surface needs.
+    pub image_count: u32,
+    /// Physical pixels per logical one, for scaling UI along with the DPI.
+    pub scale_factor: f64,
+    /// The window changed size since the swapchain was created, so it has to be created again.
+    pub outdated: bool,
 }
 
 impl RendererWindow {
-    pub fn create_window() -> Result<(EventLoop<()>, Window)> {
+    pub fn create_window(config: &WindowConfig) -> Result<(EventLoop<()>, Window)> {
         let event_loop = EventLoop::new();
-        let window = Window::new(&event_loop)?;
+
+        let window = Self::build_window(&event_loop, config)?;
 
         Ok((event_loop, window))
     }
 
+    /// Opens a window on a running event loop, or on one that isn't running yet.
+    pub fn build_window(event_loop: &EventLoopWindowTarget<()>, config: &WindowConfig) -> Result<Window> {
+        let fullscreen = Self::fullscreen(
+            config,
+            event_loop.available_monitors().collect(),
+            event_loop.primary_monitor(),
+        )?;
+
+        let icon = match &config.icon {
+            None => None,
+            Some(path) => Some(Self::load_icon(path)?)
+        };
+
+        let mut builder = WindowBuilder::new()
+            .with_title(&config.title)
+            .with_inner_size(LogicalSize::new(config.width, config.height))
+            .with_resizable(config.resizable)
+            .with_decorations(config.decorations)
+            .with_fullscreen(fullscreen)
+            .with_window_icon(icon);
+
+        if let Some([x, y]) = config.position {
+            builder = builder.with_position(LogicalPosition::new(x, y));
+        }
+
+        let window = builder.build(event_loop)?;
+
+        Self::apply_cursor(&window, config)?;
+
+        Ok(window)
+    }
+
+    /// What `config.mode` needs the window to be, on the monitor it asks for.
+    fn fullscreen(
+        config: &WindowConfig,
+        monitors: Vec<MonitorHandle>,
+        primary: Option<MonitorHandle>
+    ) -> Result<Option<Fullscreen>> {
+        let monitor = match config.monitor {
+            None => primary.or_else(|| monitors.first().cloned()),
+            Some(index) => match monitors.get(index) {
+                None => anyhow::bail!("There's no monitor {}, there are {}", index, monitors.len()),
+                Some(monitor) => Some(monitor.clone())
+            }
+        };
+
+        let fullscreen = match config.mode {
+            WindowMode::Windowed => None,
+            WindowMode::Borderless => Some(Fullscreen::Borderless(monitor)),
+            WindowMode::Exclusive => {
+                let video_modes: Vec<_> = match monitor {
+                    None => anyhow::bail!("There's no monitor to go fullscreen on"),
+                    Some(monitor) => monitor.video_modes().collect()
+                };
+
+                let modes: Vec<_> = video_modes.iter()
+                    .map(|mode| ([mode.size().width, mode.size().height], mode.refresh_rate(), mode.bit_depth()))
+                    .collect();
+
+                match config.pick_video_mode(&modes) {
+                    None => anyhow::bail!("The monitor has no video modes to go fullscreen with"),
+                    Some(i) => Some(Fullscreen::Exclusive(video_modes[i].clone()))
+                }
+            },
+        };
+
+        Ok(fullscreen)
+    }
+
+    fn load_icon(path: &Path) -> Result<Icon> {
+        let icon = image::open(path)
+            .with_context(|| format!("Failed to load the window icon {}", path.display()))?
+            .into_rgba8();
+
+        let (width, height) = icon.dimensions();
+
+        Ok(Icon::from_rgba(icon.into_raw(), width, height)?)
+    }
+
+    fn apply_cursor(window: &Window, config: &WindowConfig) -> Result<()> {
+        window.set_cursor_visible(config.cursor_visible);
+        window.set_cursor_icon(config.cursor_icon);
+
+        window.set_cursor_grab(config.cursor_grab).context("Failed to grab the cursor")?;
+
+        Ok(())
+    }
+
+    /// Switches to the mode, monitor and cursor settings in `config`, the swapchain has to be created again after.
+    pub fn apply_config(&mut self, config: &WindowConfig) -> Result<()> {
+        let fullscreen = Self::fullscreen(
+            config,
+            self.window.available_monitors().collect(),
+            self.window.primary_monitor(),
+        )?;
+
+        self.window.set_fullscreen(fullscreen);
+        self.window.set_resizable(config.resizable);
+        self.window.set_decorations(config.decorations);
+
+        Self::apply_cursor(&self.window, config)?;
+
+        self.outdated = true;
+
+        Ok(())
+    }
+
+    /// A minimized window has no size, and nothing can be presented to it.
+    pub fn is_minimized(&self) -> bool {
+        let size = self.window.inner_size();
+
+        size.width == 0 || size.height == 0
+    }
+
+    /// The size of the swapchain's images, the window's size where the surface leaves it to the swapchain.
+    pub fn extent(&self, capabilities: &vk::SurfaceCapabilitiesKHR) -> vk::Extent2D {
+        if capabilities.current_extent.width != u32::MAX {
+            return capabilities.current_extent;
+        }
+
+        let size = self.window.inner_size();
+
+        vk::Extent2D {
+            width: size.width.clamp(capabilities.min_image_extent.width, capabilities.max_image_extent.width),
+            height: size.height.clamp(capabilities.min_image_extent.height, capabilities.max_image_extent.height),
+        }
+    }
+
+    /// Windows opened while the event loop runs don't have one.
     pub fn new(
-        event_loop: EventLoop<()>,
+        event_loop: Option<EventLoop<()>>,
         window: Window,
         entry: &ash::Entry,
         instance: &ash::Instance
@@ -33,11 +179,18 @@ impl RendererWindow {
 
         let surface_loader = khr::Surface::new(entry, instance);
 
+        let scale_factor = window.scale_factor();
+
         Ok(RendererWindow {
-            event_loop: Some(event_loop),
+            event_loop,
             window,
             surface,
-            surface_loader
+            surface_loader,
+            hdr: false,
+            present_mode: vk::PresentModeKHR::FIFO,
+            image_count: 3,
+            scale_factor,
+            outdated: false,
         })
     }
 
@@ -61,6 +214,15 @@ impl RendererWindow {
         }
     }
 
+    pub fn present_modes(
+        &self,
+        physical_device: vk::PhysicalDevice
+    ) -> Result<Vec<vk::PresentModeKHR>, vk::Result> {
+        unsafe {
+            self.surface_loader.get_physical_device_surface_present_modes(physical_device, self.surface)
+        }
+    }
+
     pub fn formats(
         &self,
         physical_device: vk::PhysicalDevice
@@ -0,0 +1,115 @@
+//! Fills in vertex attributes that a model file left out.
+
+use crate::renderer::mesh::Vertex;
+
+use glam::{Vec2, Vec3};
+
+/// Gives every triangle its own three vertices, all facing the way the triangle does.
+pub fn flat_normals(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>) {
+    let mut unwelded = Vec::with_capacity(indices.len());
+
+    for triangle in indices.chunks_exact(3) {
+        let corners = [
+            vertices[triangle[0] as usize],
+            vertices[triangle[1] as usize],
+            vertices[triangle[2] as usize],
+        ];
+
+        let normal = face_normal(&corners).normalize_or_zero();
+
+        for mut corner in corners {
+            corner.normal = normal.into();
+
+            unwelded.push(corner);
+        }
+    }
+
+    *indices = (0..unwelded.len() as u32).collect();
+    *vertices = unwelded;
+}
+
+/// Averages the normals of the triangles around each vertex, weighted by their area.
+pub fn smooth_normals(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut normals = vec![Vec3::ZERO; vertices.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let corners = [
+            vertices[triangle[0] as usize],
+            vertices[triangle[1] as usize],
+            vertices[triangle[2] as usize],
+        ];
+
+        // not normalized, so that bigger triangles count for more:
+        let normal = face_normal(&corners);
+
+        for &index in triangle {
+            normals[index as usize] += normal;
+        }
+    }
+
+    for (vertex, normal) in vertices.iter_mut().zip(normals) {
+        vertex.normal = normal.normalize_or_zero().into();
+    }
+}
+
+/// Derives tangents from how the first UV set runs across each triangle.
+///
+/// Vertices without usable UVs get an arbitrary tangent perpendicular to their normal.
+pub fn generate_tangents(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut tangents = vec![Vec3::ZERO; vertices.len()];
+    let mut bitangents = vec![Vec3::ZERO; vertices.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [
+            vertices[triangle[0] as usize],
+            vertices[triangle[1] as usize],
+            vertices[triangle[2] as usize],
+        ];
+
+        let edge1 = Vec3::from(b.position) - Vec3::from(a.position);
+        let edge2 = Vec3::from(c.position) - Vec3::from(a.position);
+
+        let delta1 = Vec2::from(b.uv0) - Vec2::from(a.uv0);
+        let delta2 = Vec2::from(c.uv0) - Vec2::from(a.uv0);
+
+        let determinant = delta1.x * delta2.y - delta2.x * delta1.y;
+
+        if determinant.abs() <= f32::EPSILON {
+            continue;
+        }
+
+        let tangent = (edge1 * delta2.y - edge2 * delta1.y) / determinant;
+        let bitangent = (edge2 * delta1.x - edge1 * delta2.x) / determinant;
+
+        for &index in triangle {
+            tangents[index as usize] += tangent;
+            bitangents[index as usize] += bitangent;
+        }
+    }
+
+    for (i, vertex) in vertices.iter_mut().enumerate() {
+        let normal = Vec3::from(vertex.normal);
+
+        // Gram-Schmidt, the tangent has to be perpendicular to the normal:
+        let tangent = (tangents[i] - normal * normal.dot(tangents[i])).normalize_or_zero();
+
+        // normal maps have +y pointing up the image, which is towards -v:
+        let (tangent, handedness) = if tangent == Vec3::ZERO {
+            (normal.any_orthonormal_vector(), 1.0)
+        } else if normal.cross(tangent).dot(-bitangents[i]) < 0.0 {
+            (tangent, -1.0)
+        } else {
+            (tangent, 1.0)
+        };
+
+        vertex.tangent = tangent.extend(handedness).into();
+    }
+}
+
+fn face_normal(corners: &[Vertex; 3]) -> Vec3 {
+    let a = Vec3::from(corners[0].position);
+    let b = Vec3::from(corners[1].position);
+    let c = Vec3::from(corners[2].position);
+
+    (b - a).cross(c - a)
+}
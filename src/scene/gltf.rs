@@ -0,0 +1,630 @@
+//! glTF 2.0 loading, both `.gltf` files (with external or embedded buffers) and binary `.glb` files.
+//!
+//! Accessors are read by hand rather than through `gltf`'s reader, so that sparse accessors work
+//! and broken offsets turn into errors instead of panics.
+
+use crate::scene::{
+    geometry, AlphaMode, Light, LightKind, Material, MeshData, Node, Primitive, Scene, SceneCamera, TextureData,
+    TextureRef,
+};
+use crate::renderer::mesh::Vertex;
+use crate::renderer::texture::SamplerDesc;
+use crate::camera::Projection;
+
+use ash::vk;
+
+use gltf::accessor::{DataType, Dimensions};
+use gltf::accessor::sparse::IndexType;
+use gltf::image::Format;
+use gltf::khr_lights_punctual::Kind;
+use gltf::mesh::{Mode, Semantic};
+use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+
+use glam::{Mat4, Vec3, Vec4};
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+pub fn load(path: &Path) -> Result<Scene> {
+    let gltf = gltf::Gltf::open(path)
+        .with_context(|| format!("Failed to load glTF file {}", path.display()))?;
+
+    let base = path.parent().unwrap_or_else(|| Path::new("."));
+
+    import(gltf, Some(base))
+        .with_context(|| format!("Failed to load glTF file {}", path.display()))
+}
+
+/// Loads a `.gltf` or `.glb` file that is already in memory, it can only refer to embedded buffers and images.
+pub fn from_slice(bytes: &[u8]) -> Result<Scene> {
+    let gltf = gltf::Gltf::from_slice(bytes)
+        .context("Failed to load glTF data")?;
+
+    import(gltf, None)
+        .context("Failed to load glTF data")
+}
+
+/// `base` is the directory external files are read from, `None` when there is none.
+fn import(gltf: gltf::Gltf, base: Option<&Path>) -> Result<Scene> {
+    let gltf::Gltf { document, blob } = gltf;
+
+    let buffers = gltf::import_buffers(&document, base, blob)?;
+
+    let images = document.images()
+        .map(|image| load_image(&image, base, &buffers))
+        .collect::<Result<Vec<_>>>()?;
+
+    from_document(&document, &buffers, &images)
+}
+
+/// `gltf` slices buffer views without checking them, and only decodes `data:` URIs when it has a directory
+/// to resolve files against, so both are sorted out before it gets to decode the image.
+fn load_image(image: &gltf::Image, base: Option<&Path>, buffers: &[gltf::buffer::Data]) -> Result<gltf::image::Data> {
+    let base = match image.source() {
+        gltf::image::Source::View { view, .. } => {
+            let buffer_length = buffers.get(view.buffer().index()).map_or(0, |buffer| buffer.len());
+
+            if view.offset() + view.length() > buffer_length {
+                anyhow::bail!("Image {} lies outside of buffer {}", image.index(), view.buffer().index());
+            }
+
+            base
+        },
+        gltf::image::Source::Uri { uri, .. } => match base {
+            Some(base) => Some(base),
+            // nothing is read relative to it:
+            None if uri.starts_with("data:") => Some(Path::new(".")),
+            None => anyhow::bail!("Image {} refers to '{}', but glTF data in memory can only embed images", image.index(), uri),
+        }
+    };
+
+    gltf::image::Data::from_source(image.source(), base, buffers)
+        .with_context(|| format!("Failed to decode image {}", image.index()))
+}
+
+fn from_document(
+    document: &gltf::Document,
+    buffers: &[gltf::buffer::Data],
+    images: &[gltf::image::Data]
+) -> Result<Scene> {
+    // base color and emissive textures hold colors, everything else is linear data:
+    let mut srgb = vec![false; document.textures().len()];
+
+    for material in document.materials() {
+        let colors = [
+            material.pbr_metallic_roughness().base_color_texture(),
+            material.emissive_texture(),
+        ];
+
+        for info in colors.into_iter().flatten() {
+            srgb[info.texture().index()] = true;
+        }
+    }
+
+    let textures = document.textures()
+        .map(|texture| load_texture(&texture, images, srgb[texture.index()]))
+        .collect::<Result<_>>()?;
+
+    let materials = document.materials()
+        .map(|material| load_material(&material))
+        .collect::<Result<_>>()?;
+
+    let meshes = document.meshes()
+        .map(|mesh| load_mesh(&mesh, buffers))
+        .collect::<Result<_>>()?;
+
+    let cameras = document.cameras()
+        .map(|camera| load_camera(&camera))
+        .collect();
+
+    let lights = match document.lights() {
+        None => vec![],
+        Some(lights) => lights.map(|light| load_light(&light)).collect()
+    };
+
+    let nodes: Vec<Node> = document.nodes()
+        .map(|node| load_node(&node))
+        .collect();
+
+    // glTF asks for a strict tree, which also rules out cycles:
+    let mut parents = vec![None; nodes.len()];
+
+    for (index, node) in nodes.iter().enumerate() {
+        for &child in &node.children {
+            if let Some(parent) = parents[child] {
+                anyhow::bail!("Node {} is a child of both node {} and node {}", child, parent, index);
+            }
+
+            parents[child] = Some(index);
+        }
+    }
+
+    let roots: Vec<usize> = match document.default_scene().or_else(|| document.scenes().next()) {
+        None => (0..nodes.len()).filter(|&index| parents[index].is_none()).collect(),
+        Some(scene) => scene.nodes().map(|node| node.index()).collect()
+    };
+
+    if let Some(&root) = roots.iter().find(|&&root| parents[root].is_some()) {
+        anyhow::bail!("Node {} is a scene root but also the child of node {}", root, parents[root].unwrap());
+    }
+
+    Ok(Scene {
+        nodes,
+        roots,
+        meshes,
+        materials,
+        textures,
+        cameras,
+        lights,
+    })
+}
+
+fn load_node(node: &gltf::Node) -> Node {
+    Node {
+        name: node.name().map_or_else(|| format!("node {}", node.index()), str::to_owned),
+        transform: Mat4::from_cols_array_2d(&node.transform().matrix()),
+        children: node.children().map(|child| child.index()).collect(),
+        mesh: node.mesh().map(|mesh| mesh.index()),
+        camera: node.camera().map(|camera| camera.index()),
+        light: node.light().map(|light| light.index()),
+    }
+}
+
+fn load_camera(camera: &gltf::Camera) -> SceneCamera {
+    let (projection, aspect) = match camera.projection() {
+        gltf::camera::Projection::Perspective(perspective) => (
+            Projection::Perspective {
+                fov_y: perspective.yfov(),
+                near: perspective.znear(),
+                far: perspective.zfar().unwrap_or(f32::INFINITY),
+            },
+            perspective.aspect_ratio(),
+        ),
+        gltf::camera::Projection::Orthographic(orthographic) => (
+            Projection::Orthographic {
+                height: orthographic.ymag() * 2.0,
+                near: orthographic.znear(),
+                far: orthographic.zfar(),
+            },
+            (orthographic.ymag() != 0.0).then(|| orthographic.xmag() / orthographic.ymag()),
+        ),
+    };
+
+    SceneCamera {
+        name: camera.name().map_or_else(|| format!("camera {}", camera.index()), str::to_owned),
+        projection,
+        aspect,
+    }
+}
+
+fn load_light(light: &gltf::khr_lights_punctual::Light) -> Light {
+    let kind = match light.kind() {
+        Kind::Directional => LightKind::Directional,
+        Kind::Point => LightKind::Point,
+        Kind::Spot { inner_cone_angle, outer_cone_angle } => LightKind::Spot {
+            inner_cone_angle,
+            outer_cone_angle,
+        },
+    };
+
+    Light {
+        name: light.name().map_or_else(|| format!("light {}", light.index()), str::to_owned),
+        kind,
+        color: Vec3::from(light.color()),
+        intensity: light.intensity(),
+        range: light.range(),
+    }
+}
+
+fn load_material(material: &gltf::Material) -> Result<Material> {
+    let name = material.name()
+        .map(str::to_owned)
+        .or_else(|| material.index().map(|index| format!("material {}", index)))
+        .unwrap_or_default();
+
+    let texture_ref = |texture: gltf::Texture, uv_set: u32| {
+        if uv_set > 1 {
+            anyhow::bail!("Material '{}' reads texture {} with UV set {}, only 0 and 1 are supported", name, texture.index(), uv_set);
+        }
+
+        Ok(TextureRef {
+            texture: texture.index(),
+            uv_set,
+        })
+    };
+
+    let info_ref = |info: Option<gltf::texture::Info>| {
+        info.map(|info| texture_ref(info.texture(), info.tex_coord())).transpose()
+    };
+
+    let pbr = material.pbr_metallic_roughness();
+    let normal = material.normal_texture();
+    let occlusion = material.occlusion_texture();
+
+    let alpha_mode = match material.alpha_mode() {
+        gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+        gltf::material::AlphaMode::Mask => AlphaMode::Mask,
+        gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+    };
+
+    Ok(Material {
+        base_color_factor: Vec4::from(pbr.base_color_factor()),
+        base_color_texture: info_ref(pbr.base_color_texture())?,
+        metallic_factor: pbr.metallic_factor(),
+        roughness_factor: pbr.roughness_factor(),
+        metallic_roughness_texture: info_ref(pbr.metallic_roughness_texture())?,
+        normal_texture: normal.as_ref().map(|normal| texture_ref(normal.texture(), normal.tex_coord())).transpose()?,
+        normal_scale: normal.as_ref().map_or(1.0, |normal| normal.scale()),
+        occlusion_texture: occlusion.as_ref().map(|occlusion| texture_ref(occlusion.texture(), occlusion.tex_coord())).transpose()?,
+        occlusion_strength: occlusion.as_ref().map_or(1.0, |occlusion| occlusion.strength()),
+        emissive_factor: Vec3::from(material.emissive_factor()),
+        emissive_texture: info_ref(material.emissive_texture())?,
+        alpha_mode,
+        alpha_cutoff: material.alpha_cutoff().unwrap_or(0.5),
+        double_sided: material.double_sided(),
+        name,
+    })
+}
+
+fn load_texture(texture: &gltf::Texture, images: &[gltf::image::Data], srgb: bool) -> Result<TextureData> {
+    let name = texture.name().map_or_else(|| format!("texture {}", texture.index()), str::to_owned);
+
+    let image = match images.get(texture.source().index()) {
+        None => anyhow::bail!("Texture '{}' uses image {}, which wasn't loaded", name, texture.source().index()),
+        Some(image) => image
+    };
+
+    let pixels = to_rgba8(image).with_context(|| format!("Texture '{}' has unusable pixels", name))?;
+
+    let sampler = texture.sampler();
+
+    let address_mode = |wrapping: WrappingMode| match wrapping {
+        WrappingMode::ClampToEdge => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        WrappingMode::MirroredRepeat => vk::SamplerAddressMode::MIRRORED_REPEAT,
+        WrappingMode::Repeat => vk::SamplerAddressMode::REPEAT,
+    };
+
+    let mag_filter = match sampler.mag_filter() {
+        Some(MagFilter::Nearest) => vk::Filter::NEAREST,
+        _ => vk::Filter::LINEAR,
+    };
+
+    let min_filter = match sampler.min_filter() {
+        Some(MinFilter::Nearest)
+        | Some(MinFilter::NearestMipmapNearest)
+        | Some(MinFilter::NearestMipmapLinear) => vk::Filter::NEAREST,
+        _ => vk::Filter::LINEAR,
+    };
+
+    Ok(TextureData {
+        name,
+        width: image.width,
+        height: image.height,
+        pixels,
+        srgb,
+        sampler: SamplerDesc {
+            mag_filter,
+            min_filter,
+            address_mode_u: address_mode(sampler.wrap_s()),
+            address_mode_v: address_mode(sampler.wrap_t()),
+        },
+    })
+}
+
+/// Expands any decoded image into 8 bit RGBA, missing channels become 0 and alpha becomes opaque.
+fn to_rgba8(image: &gltf::image::Data) -> Result<Vec<u8>> {
+    let (channels, component_size) = match image.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+
+    let pixel_size = channels * component_size;
+    let expected = image.width as usize * image.height as usize * pixel_size;
+
+    if image.pixels.len() != expected {
+        anyhow::bail!("{}x{} {:?} image has {} bytes, expected {}", image.width, image.height, image.format, image.pixels.len(), expected);
+    }
+
+    let to_u8 = |bytes: &[u8]| match bytes.len() {
+        1 => bytes[0],
+        2 => (u16::from_ne_bytes([bytes[0], bytes[1]]) >> 8) as u8,
+        _ => (f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]).clamp(0.0, 1.0) * 255.0).round() as u8,
+    };
+
+    let mut pixels = Vec::with_capacity(image.width as usize * image.height as usize * 4);
+
+    for pixel in image.pixels.chunks_exact(pixel_size) {
+        let mut rgba = [0, 0, 0, u8::MAX];
+
+        for (channel, bytes) in pixel.chunks_exact(component_size).enumerate() {
+            rgba[channel] = to_u8(bytes);
+        }
+
+        pixels.extend_from_slice(&rgba);
+    }
+
+    Ok(pixels)
+}
+
+fn load_mesh(mesh: &gltf::Mesh, buffers: &[gltf::buffer::Data]) -> Result<MeshData> {
+    let name = mesh.name().map_or_else(|| format!("mesh {}", mesh.index()), str::to_owned);
+
+    let primitives = mesh.primitives()
+        .map(|primitive| {
+            load_primitive(&primitive, buffers)
+                .with_context(|| format!("Mesh '{}' primitive {} can't be loaded", name, primitive.index()))
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(MeshData {
+        name,
+        primitives,
+    })
+}
+
+fn load_primitive(primitive: &gltf::Primitive, buffers: &[gltf::buffer::Data]) -> Result<Primitive> {
+    let positions = match read_attribute(primitive, Semantic::Positions, &[Dimensions::Vec3], None, buffers)? {
+        None => anyhow::bail!("Primitive has no POSITION attribute"),
+        Some((positions, _)) => positions
+    };
+
+    let vertex_count = positions.len() / 3;
+
+    let mut vertices: Vec<Vertex> = positions.chunks_exact(3)
+        .map(|position| Vertex {
+            position: [position[0], position[1], position[2]],
+            color: [1.0; 4],
+            ..Default::default()
+        })
+        .collect();
+
+    let read = |semantic: Semantic, dimensions: &[Dimensions]| {
+        read_attribute(primitive, semantic, dimensions, Some(vertex_count), buffers)
+    };
+
+    let normals = read(Semantic::Normals, &[Dimensions::Vec3])?;
+    let tangents = read(Semantic::Tangents, &[Dimensions::Vec4])?;
+    let uv0 = read(Semantic::TexCoords(0), &[Dimensions::Vec2])?;
+    let uv1 = read(Semantic::TexCoords(1), &[Dimensions::Vec2])?;
+    let colors = read(Semantic::Colors(0), &[Dimensions::Vec3, Dimensions::Vec4])?;
+
+    for (i, vertex) in vertices.iter_mut().enumerate() {
+        if let Some((normals, _)) = &normals {
+            vertex.normal.copy_from_slice(&normals[i * 3..i * 3 + 3]);
+        }
+
+        if let Some((tangents, _)) = &tangents {
+            vertex.tangent.copy_from_slice(&tangents[i * 4..i * 4 + 4]);
+        }
+
+        if let Some((uv0, _)) = &uv0 {
+            vertex.uv0.copy_from_slice(&uv0[i * 2..i * 2 + 2]);
+        }
+
+        if let Some((uv1, _)) = &uv1 {
+            vertex.uv1.copy_from_slice(&uv1[i * 2..i * 2 + 2]);
+        }
+
+        if let Some((colors, components)) = &colors {
+            vertex.color[..*components].copy_from_slice(&colors[i * components..(i + 1) * components]);
+        }
+    }
+
+    let indices: Vec<u32> = match primitive.indices() {
+        None => (0..vertex_count as u32).collect(),
+        Some(accessor) => {
+            if accessor.dimensions() != Dimensions::Scalar || !matches!(accessor.data_type(), DataType::U8 | DataType::U16 | DataType::U32) {
+                anyhow::bail!("Indices are {:?} {:?}, expected unsigned integer scalars", accessor.data_type(), accessor.dimensions());
+            }
+
+            read_accessor(&accessor, buffers)
+                .context("Failed to read indices")?
+                .into_iter()
+                .map(|index| index as u32)
+                .collect()
+        }
+    };
+
+    if let Some(&index) = indices.iter().find(|&&index| index as usize >= vertex_count) {
+        anyhow::bail!("Index {} is out of range, there are only {} vertices", index, vertex_count);
+    }
+
+    let mut indices = match primitive.mode() {
+        Mode::Triangles => indices,
+        Mode::TriangleStrip => (2..indices.len())
+            .flat_map(|i| if i % 2 == 0 {
+                [indices[i - 2], indices[i - 1], indices[i]]
+            } else {
+                [indices[i - 1], indices[i - 2], indices[i]]
+            })
+            .collect(),
+        Mode::TriangleFan => (2..indices.len())
+            .flat_map(|i| [indices[0], indices[i - 1], indices[i]])
+            .collect(),
+        mode => anyhow::bail!("{:?} primitives aren't supported, only triangles", mode),
+    };
+
+    if indices.is_empty() || indices.len() % 3 != 0 {
+        anyhow::bail!("{} indices don't make up whole triangles", indices.len());
+    }
+
+    // glTF asks for flat normals when they are missing, and tangents are only needed for normal maps:
+    if normals.is_none() {
+        geometry::flat_normals(&mut vertices, &mut indices);
+    }
+
+    if tangents.is_none() {
+        geometry::generate_tangents(&mut vertices, &indices);
+    }
+
+    Ok(Primitive {
+        vertices,
+        indices,
+        material: primitive.material().index(),
+    })
+}
+
+/// Reads a vertex attribute as floats along with its component count, `None` when the primitive doesn't have it.
+fn read_attribute(
+    primitive: &gltf::Primitive,
+    semantic: Semantic,
+    dimensions: &[Dimensions],
+    vertex_count: Option<usize>,
+    buffers: &[gltf::buffer::Data]
+) -> Result<Option<(Vec<f32>, usize)>> {
+    let accessor = match primitive.get(&semantic) {
+        None => return Ok(None),
+        Some(accessor) => accessor
+    };
+
+    if !dimensions.contains(&accessor.dimensions()) {
+        anyhow::bail!("{:?} attribute is {:?}, expected one of {:?}", semantic, accessor.dimensions(), dimensions);
+    }
+
+    if let Some(vertex_count) = vertex_count {
+        if accessor.count() != vertex_count {
+            anyhow::bail!("{:?} attribute has {} elements, but there are {} vertices", semantic, accessor.count(), vertex_count);
+        }
+    }
+
+    let values = read_accessor(&accessor, buffers)
+        .with_context(|| format!("Failed to read {:?} attribute", semantic))?;
+
+    let values = values.into_iter()
+        .map(|value| value as f32)
+        .collect();
+
+    Ok(Some((values, accessor.dimensions().multiplicity())))
+}
+
+/// Reads every component of every element, normalized integers are mapped to floats and sparse values are applied.
+fn read_accessor(accessor: &gltf::Accessor, buffers: &[gltf::buffer::Data]) -> Result<Vec<f64>> {
+    let count = accessor.count();
+    let components = accessor.dimensions().multiplicity();
+
+    // without a buffer view, everything not given by the sparse values is zero:
+    let mut values = match accessor.view() {
+        None => vec![0.0; count * components],
+        Some(view) => read_elements(
+            buffers,
+            &view,
+            accessor.offset(),
+            count,
+            components,
+            accessor.data_type(),
+            accessor.normalized()
+        )?
+    };
+
+    if let Some(sparse) = accessor.sparse() {
+        let index_type = match sparse.indices().index_type() {
+            IndexType::U8 => DataType::U8,
+            IndexType::U16 => DataType::U16,
+            IndexType::U32 => DataType::U32,
+        };
+
+        let indices = read_elements(
+            buffers,
+            &sparse.indices().view(),
+            sparse.indices().offset(),
+            sparse.count(),
+            1,
+            index_type,
+            false
+        ).context("Failed to read sparse indices")?;
+
+        let replacements = read_elements(
+            buffers,
+            &sparse.values().view(),
+            sparse.values().offset(),
+            sparse.count(),
+            components,
+            accessor.data_type(),
+            accessor.normalized()
+        ).context("Failed to read sparse values")?;
+
+        for (i, &index) in indices.iter().enumerate() {
+            let index = index as usize;
+
+            if index >= count {
+                anyhow::bail!("Sparse index {} is out of range for an accessor of {} elements", index, count);
+            }
+
+            values[index * components..(index + 1) * components]
+                .copy_from_slice(&replacements[i * components..(i + 1) * components]);
+        }
+    }
+
+    Ok(values)
+}
+
+fn read_elements(
+    buffers: &[gltf::buffer::Data],
+    view: &gltf::buffer::View,
+    offset: usize,
+    count: usize,
+    components: usize,
+    data_type: DataType,
+    normalized: bool
+) -> Result<Vec<f64>> {
+    let buffer = match buffers.get(view.buffer().index()) {
+        None => anyhow::bail!("Buffer {} wasn't loaded", view.buffer().index()),
+        Some(buffer) => buffer
+    };
+
+    let component_size = data_type.size();
+    let element_size = components * component_size;
+    let stride = view.stride().unwrap_or(element_size);
+
+    let view_end = view.offset() + view.length();
+
+    if view_end > buffer.len() {
+        anyhow::bail!("Buffer view {} ends at byte {}, but buffer {} only has {} bytes", view.index(), view_end, view.buffer().index(), buffer.len());
+    }
+
+    let start = view.offset() + offset;
+
+    if count > 0 && start + stride * (count - 1) + element_size > view_end {
+        anyhow::bail!("{} elements of {} bytes, {} bytes apart, don't fit in buffer view {}", count, element_size, stride, view.index());
+    }
+
+    let mut values = Vec::with_capacity(count * components);
+
+    for element in 0..count {
+        let element_start = start + element * stride;
+
+        for component in 0..components {
+            let component_start = element_start + component * component_size;
+
+            values.push(read_component(&buffer[component_start..component_start + component_size], data_type, normalized));
+        }
+    }
+
+    Ok(values)
+}
+
+fn read_component(bytes: &[u8], data_type: DataType, normalized: bool) -> f64 {
+    let (value, max) = match data_type {
+        DataType::I8 => (bytes[0] as i8 as f64, i8::MAX as f64),
+        DataType::U8 => (bytes[0] as f64, u8::MAX as f64),
+        DataType::I16 => (i16::from_le_bytes([bytes[0], bytes[1]]) as f64, i16::MAX as f64),
+        DataType::U16 => (u16::from_le_bytes([bytes[0], bytes[1]]) as f64, u16::MAX as f64),
+        DataType::U32 => (u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64, u32::MAX as f64),
+        DataType::F32 => return f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+    };
+
+    if normalized {
+        (value / max).max(-1.0)
+    } else {
+        value
+    }
+}
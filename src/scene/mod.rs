@@ -0,0 +1,428 @@
+//! Scenes loaded from model files.
+//!
+//! A [`Scene`] is plain data that can be inspected without a GPU. [`SceneResources`] uploads it
+//! to a renderer and draws it.
+
+pub mod geometry;
+pub mod gltf;
+pub mod obj;
+
+use crate::VulkanRenderer;
+use crate::renderer::device::RendererDevice;
+use crate::renderer::frame::Frame;
+use crate::renderer::mesh::{InstanceData, Mesh, Vertex};
+use crate::renderer::texture::{SamplerDesc, Texture};
+use crate::renderer::material::RendererMaterials;
+use crate::renderer::lights::WorldLight;
+use crate::camera::{Camera, Projection};
+
+use ash::vk;
+
+use glam::{Mat4, Vec3, Vec4};
+
+use std::path::Path;
+
+use anyhow::Result;
+
+pub struct Node {
+    pub name: String,
+    /// Relative to the parent node.
+    pub transform: Mat4,
+    pub children: Vec<usize>,
+    pub mesh: Option<usize>,
+    pub camera: Option<usize>,
+    pub light: Option<usize>,
+}
+
+pub struct MeshData {
+    pub name: String,
+    pub primitives: Vec<Primitive>,
+}
+
+/// A triangle list drawn with a single material.
+pub struct Primitive {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub material: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AlphaMode {
+    Opaque,
+    /// Fragments with an alpha below `Material::alpha_cutoff` are discarded.
+    Mask,
+    Blend,
+}
+
+/// A texture used by a material, read with one of the vertex UV sets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextureRef {
+    pub texture: usize,
+    pub uv_set: u32,
+}
+
+/// Metallic-roughness material parameters, textures multiply their factor.
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub name: String,
+    pub base_color_factor: Vec4,
+    pub base_color_texture: Option<TextureRef>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    /// Roughness in the green channel, metalness in the blue one.
+    pub metallic_roughness_texture: Option<TextureRef>,
+    pub normal_texture: Option<TextureRef>,
+    pub normal_scale: f32,
+    pub occlusion_texture: Option<TextureRef>,
+    pub occlusion_strength: f32,
+    pub emissive_factor: Vec3,
+    pub emissive_texture: Option<TextureRef>,
+    pub alpha_mode: AlphaMode,
+    pub alpha_cutoff: f32,
+    pub double_sided: bool,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            name: String::new(),
+            base_color_factor: Vec4::ONE,
+            base_color_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            emissive_factor: Vec3::ZERO,
+            emissive_texture: None,
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            double_sided: false,
+        }
+    }
+}
+
+/// Decoded RGBA8 pixels.
+pub struct TextureData {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+    /// Colors (base color, emissive) are stored in sRGB, everything else is linear.
+    pub srgb: bool,
+    pub sampler: SamplerDesc,
+}
+
+pub struct SceneCamera {
+    pub name: String,
+    pub projection: Projection,
+    /// When missing, the camera takes the aspect ratio of whatever it renders to.
+    pub aspect: Option<f32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+    Directional,
+    Point,
+    /// Angles in radians, from the center of the cone.
+    Spot { inner_cone_angle: f32, outer_cone_angle: f32 },
+}
+
+/// Lights shine down their node's local `-z`.
+#[derive(Clone, Debug)]
+pub struct Light {
+    pub name: String,
+    pub kind: LightKind,
+    pub color: Vec3,
+    /// Lux for directional lights, candela for the others.
+    pub intensity: f32,
+    /// Past this distance the light has no effect, infinite when missing.
+    pub range: Option<f32>,
+}
+
+#[derive(Default)]
+pub struct Scene {
+    pub nodes: Vec<Node>,
+    /// Nodes without a parent.
+    pub roots: Vec<usize>,
+    pub meshes: Vec<MeshData>,
+    pub materials: Vec<Material>,
+    pub textures: Vec<TextureData>,
+    pub cameras: Vec<SceneCamera>,
+    pub lights: Vec<Light>,
+}
+
+impl Scene {
+    /// Loads a model file, picking the format from its extension.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Scene> {
+        let path = path.as_ref();
+
+        let extension = path.extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("gltf") | Some("glb") => gltf::load(path),
+            Some("obj") => obj::load(path, obj::Normals::SmoothingGroups),
+            _ => anyhow::bail!("Don't know how to load {}, expected a .gltf, .glb or .obj file", path.display()),
+        }
+    }
+
+    /// Transforms from each node's local space to world space, indexed like `nodes`.
+    ///
+    /// Nodes that can't be reached from `roots` aren't part of the scene and have none.
+    pub fn world_transforms(&self) -> Vec<Option<Mat4>> {
+        let mut transforms = vec![None; self.nodes.len()];
+
+        let mut stack: Vec<(usize, Mat4)> = self.roots.iter()
+            .map(|&root| (root, Mat4::IDENTITY))
+            .collect();
+
+        while let Some((index, parent)) = stack.pop() {
+            // loaders reject cycles, but a hand made scene could still have one:
+            if transforms[index].is_some() {
+                continue;
+            }
+
+            let node = &self.nodes[index];
+            let world = parent * node.transform;
+
+            transforms[index] = Some(world);
+
+            for &child in &node.children {
+                stack.push((child, world));
+            }
+        }
+
+        transforms
+    }
+
+    /// Every light of the scene placed by its node, ready to be submitted with `VulkanRenderer::set_lights`.
+    pub fn world_lights(&self) -> Vec<WorldLight> {
+        let world_transforms = self.world_transforms();
+
+        self.nodes.iter()
+            .zip(world_transforms)
+            .filter_map(|(node, world)| {
+                let light = &self.lights[node.light?];
+                let world = world?;
+
+                Some(WorldLight {
+                    kind: light.kind,
+                    position: world.transform_point3(Vec3::ZERO),
+                    direction: world.transform_vector3(-Vec3::Z).normalize_or_zero(),
+                    color: light.color,
+                    intensity: light.intensity,
+                    range: light.range,
+                    // glTF has no say in it, so every light that can cast shadows does:
+                    casts_shadows: light.kind != LightKind::Point,
+                })
+            })
+            .collect()
+    }
+
+    /// Builds a camera looking through the given node, `aspect` is used when the scene doesn't specify one.
+    pub fn camera(&self, node: usize, aspect: f32) -> Option<Camera> {
+        let scene_camera = &self.cameras[self.nodes[node].camera?];
+
+        let (_, rotation, position) = self.world_transforms()[node]?.to_scale_rotation_translation();
+
+        Some(Camera {
+            position,
+            rotation,
+            projection: scene_camera.projection,
+            aspect: scene_camera.aspect.unwrap_or(aspect),
+            reverse_z: false,
+        })
+    }
+}
+
+/// The GPU side of a `Scene`: one `Mesh` per primitive, one `Texture` per texture and the materials that use them.
+pub struct SceneResources {
+    pub meshes: Vec<Vec<Mesh>>,
+    pub textures: Vec<Texture>,
+    pub materials: RendererMaterials,
+}
+
+impl SceneResources {
+    pub fn new(renderer: &VulkanRenderer, scene: &Scene) -> Result<SceneResources> {
+        let device = &renderer.main_device;
+
+        let mut meshes = Vec::with_capacity(scene.meshes.len());
+        let mut textures = Vec::with_capacity(scene.textures.len());
+
+        let materials = Self::upload(renderer, scene, &mut meshes, &mut textures).and_then(|()| {
+            RendererMaterials::new(
+                device,
+                &renderer.command_pools,
+                renderer.material_pipelines.set_layout,
+                &scene.materials,
+                &textures,
+            )
+        });
+
+        match materials {
+            Err(err) => {
+                unsafe {
+                    Self::cleanup_meshes_and_textures(device, &mut meshes, &mut textures);
+                };
+
+                Err(err)
+            },
+            Ok(materials) => Ok(SceneResources {
+                meshes,
+                textures,
+                materials,
+            })
+        }
+    }
+
+    fn upload(
+        renderer: &VulkanRenderer,
+        scene: &Scene,
+        meshes: &mut Vec<Vec<Mesh>>,
+        textures: &mut Vec<Texture>
+    ) -> Result<()> {
+        let device = &renderer.main_device;
+
+        for mesh in &scene.meshes {
+            let mut primitives: Vec<Mesh> = Vec::with_capacity(mesh.primitives.len());
+
+            for primitive in &mesh.primitives {
+                let uploaded = Mesh::upload(
+                    device,
+                    &renderer.command_pools,
+                    &mesh.name,
+                    &primitive.vertices,
+                    &primitive.indices
+                );
+
+                match uploaded {
+                    Err(err) => {
+                        for mut primitive in primitives {
+                            unsafe {
+                                primitive.cleanup(device);
+                            };
+                        }
+
+                        return Err(err);
+                    },
+                    Ok(uploaded) => primitives.push(uploaded)
+                }
+            }
+
+            meshes.push(primitives);
+        }
+
+        for texture in &scene.textures {
+            textures.push(Texture::from_rgba8(
+                device,
+                &renderer.command_pools,
+                &texture.name,
+                vk::Extent2D {
+                    width: texture.width,
+                    height: texture.height,
+                },
+                &texture.pixels,
+                texture.srgb,
+                &texture.sampler,
+            )?);
+        }
+
+        Ok(())
+    }
+
+    /// Draws every node that has a mesh with its materials, blended primitives after all the others.
+    pub fn draw(&self, renderer: &VulkanRenderer, frame: &Frame, scene: &Scene, transform: Mat4) {
+        let world_transforms = scene.world_transforms();
+
+        let mut blended = Vec::new();
+
+        for (node, world) in scene.nodes.iter().zip(world_transforms) {
+            let (mesh, world) = match (node.mesh, world) {
+                (Some(mesh), Some(world)) => (mesh, world),
+                _ => continue
+            };
+
+            for (primitive, data) in self.meshes[mesh].iter().zip(&scene.meshes[mesh].primitives) {
+                let key = self.materials.keys[self.materials.index(data.material)];
+
+                if key.alpha_mode == AlphaMode::Blend {
+                    blended.push((primitive, data.material, transform * world));
+                } else {
+                    renderer.draw_material_mesh(frame, primitive, transform * world, &self.materials, data.material);
+                }
+            }
+        }
+
+        // not sorted, overlapping transparent surfaces can come out in the wrong order:
+        for (primitive, material, model) in blended {
+            renderer.draw_material_mesh(frame, primitive, model, &self.materials, material);
+        }
+    }
+
+    /// Like `draw`, but queued so primitives sharing a mesh and a material are drawn as instances of one draw.
+    pub fn queue(&self, renderer: &mut VulkanRenderer, scene: &Scene, transform: Mat4) {
+        let world_transforms = scene.world_transforms();
+
+        for (node, world) in scene.nodes.iter().zip(world_transforms) {
+            let (mesh, world) = match (node.mesh, world) {
+                (Some(mesh), Some(world)) => (mesh, world),
+                _ => continue
+            };
+
+            for (primitive, data) in self.meshes[mesh].iter().zip(&scene.meshes[mesh].primitives) {
+                let instance = InstanceData::new(transform * world, Vec4::ONE);
+
+                renderer.queue_material_mesh(primitive, instance, &self.materials, data.material);
+            }
+        }
+    }
+
+    /// Queues every opaque and masked primitive as a shadow caster, masked ones cast as if they were opaque.
+    pub fn draw_shadow_casters(&self, renderer: &mut VulkanRenderer, scene: &Scene, transform: Mat4) {
+        let world_transforms = scene.world_transforms();
+
+        for (node, world) in scene.nodes.iter().zip(world_transforms) {
+            let (mesh, world) = match (node.mesh, world) {
+                (Some(mesh), Some(world)) => (mesh, world),
+                _ => continue
+            };
+
+            for (primitive, data) in self.meshes[mesh].iter().zip(&scene.meshes[mesh].primitives) {
+                let key = self.materials.keys[self.materials.index(data.material)];
+
+                if key.alpha_mode != AlphaMode::Blend {
+                    renderer.draw_shadow_caster(primitive, transform * world);
+                }
+            }
+        }
+    }
+
+    unsafe fn cleanup_meshes_and_textures(
+        device: &RendererDevice,
+        meshes: &mut Vec<Vec<Mesh>>,
+        textures: &mut Vec<Texture>
+    ) {
+        for primitives in meshes.iter_mut() {
+            for primitive in primitives {
+                primitive.cleanup(device);
+            }
+        }
+
+        for texture in textures.iter_mut() {
+            texture.cleanup(device);
+        }
+
+        meshes.clear();
+        textures.clear();
+    }
+
+    pub unsafe fn cleanup(&mut self, device: &RendererDevice) {
+        self.materials.cleanup(device);
+
+        Self::cleanup_meshes_and_textures(device, &mut self.meshes, &mut self.textures);
+    }
+}
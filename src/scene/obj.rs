@@ -0,0 +1,521 @@
+//! Wavefront OBJ loading, with materials from MTL libraries.
+//!
+//! Every `o` or `g` becomes a mesh with a primitive per material. Polygons are triangulated as fans,
+//! so they are expected to be convex. Identical position/UV/normal triples are shared between faces.
+
+use crate::scene::{geometry, AlphaMode, Material, MeshData, Node, Primitive, Scene, TextureData, TextureRef};
+use crate::renderer::mesh::Vertex;
+use crate::renderer::texture::SamplerDesc;
+
+use glam::{Mat4, Vec3, Vec4};
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+/// What to do about faces that come without normals.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Normals {
+    /// Faces in a smoothing group (`s 1`) share averaged normals, faces outside of one (`s off`) are flat.
+    SmoothingGroups,
+    Flat,
+    Smooth,
+}
+
+pub fn load(path: &Path, normals: Normals) -> Result<Scene> {
+    let source = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read OBJ file {}", path.display()))?;
+
+    let base = path.parent().unwrap_or_else(|| Path::new("."));
+
+    parse(&source, Libraries::Files(base), normals)
+        .with_context(|| format!("Failed to load OBJ file {}", path.display()))
+}
+
+/// Loads an OBJ file that is already in memory. `mtl` stands in for every `mtllib` it names,
+/// and since there is no directory to look in, its materials can't have textures.
+pub fn from_str(obj: &str, mtl: Option<&str>, normals: Normals) -> Result<Scene> {
+    parse(obj, Libraries::Memory(mtl), normals)
+        .context("Failed to load OBJ data")
+}
+
+enum Libraries<'a> {
+    Files(&'a Path),
+    Memory(Option<&'a str>),
+}
+
+#[derive(Clone, Copy)]
+struct Corner {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+struct Face {
+    corners: [Corner; 3],
+    material: Option<usize>,
+    /// 0 when the face isn't in a smoothing group.
+    smoothing_group: u32,
+}
+
+struct Group {
+    name: String,
+    faces: Vec<Face>,
+}
+
+/// Vertices are shared between faces when all of this matches.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct VertexKey {
+    position: usize,
+    uv: Option<usize>,
+    normal: NormalKey,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum NormalKey {
+    Given(usize),
+    /// The bits of a face normal, so that faces in the same plane can still share vertices.
+    Flat([u32; 3]),
+    Smooth(u32),
+}
+
+#[derive(Default)]
+struct Materials {
+    materials: Vec<Material>,
+    names: HashMap<String, usize>,
+    textures: Vec<TextureData>,
+    texture_paths: HashMap<PathBuf, usize>,
+}
+
+fn parse(source: &str, libraries: Libraries, normals: Normals) -> Result<Scene> {
+    let mut positions: Vec<[f32; 3]> = vec![];
+    let mut colors: Vec<[f32; 4]> = vec![];
+    let mut uvs: Vec<[f32; 2]> = vec![];
+    let mut vertex_normals: Vec<[f32; 3]> = vec![];
+
+    let mut materials = Materials::default();
+
+    let mut groups = vec![Group {
+        name: "default".to_owned(),
+        faces: vec![],
+    }];
+
+    let mut material = None;
+    let mut smoothing_group = 0;
+
+    for (number, line) in source.lines().enumerate() {
+        let line = match line.find('#') {
+            None => line,
+            Some(comment) => &line[..comment]
+        };
+
+        let mut words = line.split_whitespace();
+
+        let keyword = match words.next() {
+            None => continue,
+            Some(keyword) => keyword
+        };
+
+        let arguments: Vec<&str> = words.collect();
+
+        let parsed: Result<()> = (|| {
+            match keyword {
+                "v" => {
+                    let values = floats(&arguments, 3, 7)?;
+
+                    positions.push([values[0], values[1], values[2]]);
+
+                    // a common extension puts a vertex color after the position:
+                    colors.push(match values.len() {
+                        6 | 7 => [values[3], values[4], values[5], 1.0],
+                        _ => [1.0; 4],
+                    });
+                },
+                "vt" => {
+                    let values = floats(&arguments, 1, 3)?;
+
+                    // OBJ puts v = 0 at the bottom of the image, Vulkan at the top:
+                    uvs.push([values[0], 1.0 - values.get(1).copied().unwrap_or(0.0)]);
+                },
+                "vn" => {
+                    let values = floats(&arguments, 3, 3)?;
+
+                    vertex_normals.push([values[0], values[1], values[2]]);
+                },
+                "f" => {
+                    if arguments.len() < 3 {
+                        anyhow::bail!("A face needs at least 3 vertices, got {}", arguments.len());
+                    }
+
+                    let corners = arguments.iter()
+                        .map(|corner| parse_corner(corner, positions.len(), uvs.len(), vertex_normals.len()))
+                        .collect::<Result<Vec<_>>>()?;
+
+                    let faces = &mut groups.last_mut().unwrap().faces;
+
+                    for i in 2..corners.len() {
+                        faces.push(Face {
+                            corners: [corners[0], corners[i - 1], corners[i]],
+                            material,
+                            smoothing_group,
+                        });
+                    }
+                },
+                "o" | "g" => {
+                    let name = arguments.join(" ");
+
+                    // the faces so far had no group, or a `g` right after an `o` names the same thing:
+                    let current = groups.last_mut().unwrap();
+
+                    if current.faces.is_empty() {
+                        current.name = name;
+                    } else {
+                        groups.push(Group {
+                            name,
+                            faces: vec![],
+                        });
+                    }
+                },
+                "usemtl" => {
+                    let name = arguments.join(" ");
+
+                    material = Some(materials.index_of(&name));
+                },
+                "mtllib" => {
+                    match &libraries {
+                        Libraries::Files(base) => {
+                            for file in &arguments {
+                                let path = base.join(file);
+
+                                let library = fs::read_to_string(&path)
+                                    .with_context(|| format!("Failed to read material library {}", path.display()))?;
+
+                                materials.parse_mtl(&library, path.parent())
+                                    .with_context(|| format!("Failed to load material library {}", path.display()))?;
+                            }
+                        },
+                        Libraries::Memory(Some(library)) => materials.parse_mtl(library, None)?,
+                        Libraries::Memory(None) => {},
+                    }
+                },
+                "s" => {
+                    smoothing_group = match arguments.first() {
+                        None | Some(&"off") => 0,
+                        Some(&"on") => 1,
+                        Some(group) => group.parse().with_context(|| format!("'{}' isn't a smoothing group", group))?,
+                    };
+                },
+                // points, lines, curves and everything else don't end up in a mesh:
+                _ => {},
+            }
+
+            Ok(())
+        })();
+
+        parsed.with_context(|| format!("Line {}: '{}'", number + 1, line.trim()))?;
+    }
+
+    let mut scene = Scene::default();
+
+    for group in groups.into_iter().filter(|group| !group.faces.is_empty()) {
+        let mesh = build_mesh(group, &positions, &colors, &uvs, &vertex_normals, normals);
+
+        scene.roots.push(scene.nodes.len());
+
+        scene.nodes.push(Node {
+            name: mesh.name.clone(),
+            transform: Mat4::IDENTITY,
+            children: vec![],
+            mesh: Some(scene.meshes.len()),
+            camera: None,
+            light: None,
+        });
+
+        scene.meshes.push(mesh);
+    }
+
+    scene.materials = materials.materials;
+    scene.textures = materials.textures;
+
+    Ok(scene)
+}
+
+fn floats(arguments: &[&str], min: usize, max: usize) -> Result<Vec<f32>> {
+    if arguments.len() < min || arguments.len() > max {
+        anyhow::bail!("Expected {} to {} numbers, got {}", min, max, arguments.len());
+    }
+
+    arguments.iter()
+        .map(|argument| argument.parse().with_context(|| format!("'{}' isn't a number", argument)))
+        .collect()
+}
+
+/// Parses `v`, `v/vt`, `v//vn` or `v/vt/vn` into zero based indices, negative indices count back from the end.
+fn parse_corner(corner: &str, position_count: usize, uv_count: usize, normal_count: usize) -> Result<Corner> {
+    let mut parts = corner.split('/');
+
+    let index = |part: Option<&str>, count: usize, what: &str| -> Result<Option<usize>> {
+        let part = match part {
+            None | Some("") => return Ok(None),
+            Some(part) => part
+        };
+
+        let index: i64 = part.parse().with_context(|| format!("'{}' isn't a {} index", part, what))?;
+
+        let resolved = match index {
+            0 => anyhow::bail!("{} indices start at 1", what),
+            index if index > 0 => index - 1,
+            index => count as i64 + index,
+        };
+
+        if resolved < 0 || resolved >= count as i64 {
+            anyhow::bail!("{} index {} is out of range, there are only {}", what, index, count);
+        }
+
+        Ok(Some(resolved as usize))
+    };
+
+    let position = match index(parts.next(), position_count, "position")? {
+        None => anyhow::bail!("'{}' has no position", corner),
+        Some(position) => position
+    };
+
+    Ok(Corner {
+        position,
+        uv: index(parts.next(), uv_count, "UV")?,
+        normal: index(parts.next(), normal_count, "normal")?,
+    })
+}
+
+fn build_mesh(
+    group: Group,
+    positions: &[[f32; 3]],
+    colors: &[[f32; 4]],
+    uvs: &[[f32; 2]],
+    vertex_normals: &[[f32; 3]],
+    normals: Normals
+) -> MeshData {
+    let smoothing_group = |face: &Face| match normals {
+        Normals::SmoothingGroups => face.smoothing_group,
+        Normals::Flat => 0,
+        Normals::Smooth => 1,
+    };
+
+    let face_normal = |face: &Face| {
+        let [a, b, c] = face.corners.map(|corner| Vec3::from(positions[corner.position]));
+
+        (b - a).cross(c - a)
+    };
+
+    // smooth normals are averaged over the whole group, so that material boundaries don't show:
+    let mut smooth_normals: HashMap<(usize, u32), Vec3> = HashMap::new();
+
+    for face in &group.faces {
+        let smoothing_group = smoothing_group(face);
+
+        if smoothing_group == 0 {
+            continue;
+        }
+
+        let normal = face_normal(face);
+
+        for corner in face.corners.iter().filter(|corner| corner.normal.is_none()) {
+            *smooth_normals.entry((corner.position, smoothing_group)).or_insert(Vec3::ZERO) += normal;
+        }
+    }
+
+    // one primitive per material, in the order they were first used:
+    let mut primitives: Vec<Primitive> = vec![];
+    let mut lookups: Vec<HashMap<VertexKey, u32>> = vec![];
+
+    for face in &group.faces {
+        let index = match primitives.iter().position(|primitive| primitive.material == face.material) {
+            Some(index) => index,
+            None => {
+                primitives.push(Primitive {
+                    vertices: vec![],
+                    indices: vec![],
+                    material: face.material,
+                });
+
+                lookups.push(HashMap::new());
+
+                primitives.len() - 1
+            }
+        };
+
+        let primitive = &mut primitives[index];
+        let lookup = &mut lookups[index];
+
+        let smoothing_group = smoothing_group(face);
+        let flat_normal = face_normal(face).normalize_or_zero();
+
+        for corner in face.corners {
+            let (normal_key, normal) = match (corner.normal, smoothing_group) {
+                (Some(normal), _) => (NormalKey::Given(normal), Vec3::from(vertex_normals[normal])),
+                (None, 0) => (NormalKey::Flat(flat_normal.to_array().map(f32::to_bits)), flat_normal),
+                (None, group) => (
+                    NormalKey::Smooth(group),
+                    smooth_normals[&(corner.position, group)].normalize_or_zero(),
+                ),
+            };
+
+            let key = VertexKey {
+                position: corner.position,
+                uv: corner.uv,
+                normal: normal_key,
+            };
+
+            let vertex_index = *lookup.entry(key).or_insert_with(|| {
+                primitive.vertices.push(Vertex {
+                    position: positions[corner.position],
+                    normal: normal.into(),
+                    uv0: corner.uv.map_or([0.0; 2], |uv| uvs[uv]),
+                    color: colors[corner.position],
+                    ..Default::default()
+                });
+
+                primitive.vertices.len() as u32 - 1
+            });
+
+            primitive.indices.push(vertex_index);
+        }
+    }
+
+    for primitive in &mut primitives {
+        geometry::generate_tangents(&mut primitive.vertices, &primitive.indices);
+    }
+
+    MeshData {
+        name: group.name,
+        primitives,
+    }
+}
+
+impl Materials {
+    /// Materials can be used before a library defines them, or without any library at all.
+    fn index_of(&mut self, name: &str) -> usize {
+        if let Some(&index) = self.names.get(name) {
+            return index;
+        }
+
+        self.materials.push(Material {
+            name: name.to_owned(),
+            metallic_factor: 0.0,
+            ..Default::default()
+        });
+
+        self.names.insert(name.to_owned(), self.materials.len() - 1);
+
+        self.materials.len() - 1
+    }
+
+    /// `base` is where textures are looked for, `None` when there is nowhere to look.
+    fn parse_mtl(&mut self, source: &str, base: Option<&Path>) -> Result<()> {
+        let mut current = None;
+
+        for (number, line) in source.lines().enumerate() {
+            let line = match line.find('#') {
+                None => line,
+                Some(comment) => &line[..comment]
+            };
+
+            let mut words = line.split_whitespace();
+
+            let keyword = match words.next() {
+                None => continue,
+                Some(keyword) => keyword
+            };
+
+            let arguments: Vec<&str> = words.collect();
+
+            let parsed: Result<()> = (|| {
+                if keyword == "newmtl" {
+                    current = Some(self.index_of(&arguments.join(" ")));
+
+                    return Ok(());
+                }
+
+                let material = match current {
+                    None => anyhow::bail!("'{}' comes before any newmtl", keyword),
+                    Some(material) => material
+                };
+
+                match keyword {
+                    "Kd" => {
+                        let color = floats(&arguments, 3, 3)?;
+                        let alpha = self.materials[material].base_color_factor.w;
+
+                        self.materials[material].base_color_factor = Vec4::new(color[0], color[1], color[2], alpha);
+                    },
+                    "Ke" => {
+                        let color = floats(&arguments, 3, 3)?;
+
+                        self.materials[material].emissive_factor = Vec3::new(color[0], color[1], color[2]);
+                    },
+                    "d" | "Tr" => {
+                        let value = floats(&arguments, 1, 1)?[0];
+                        let alpha = if keyword == "d" { value } else { 1.0 - value };
+
+                        self.materials[material].base_color_factor.w = alpha;
+
+                        self.materials[material].alpha_mode = if alpha < 1.0 {
+                            AlphaMode::Blend
+                        } else {
+                            AlphaMode::Opaque
+                        };
+                    },
+                    "map_Kd" => {
+                        // options like `-s 1 1 1` come first, the file name is last:
+                        let file = match arguments.last() {
+                            None => anyhow::bail!("map_Kd names no texture"),
+                            Some(file) => file
+                        };
+
+                        let texture = match base {
+                            None => anyhow::bail!("Can't load texture '{}' without a directory to look in", file),
+                            Some(base) => self.load_texture(&base.join(file))?
+                        };
+
+                        self.materials[material].base_color_texture = Some(TextureRef {
+                            texture,
+                            uv_set: 0,
+                        });
+                    },
+                    _ => {},
+                }
+
+                Ok(())
+            })();
+
+            parsed.with_context(|| format!("Line {}: '{}'", number + 1, line.trim()))?;
+        }
+
+        Ok(())
+    }
+
+    fn load_texture(&mut self, path: &Path) -> Result<usize> {
+        if let Some(&index) = self.texture_paths.get(path) {
+            return Ok(index);
+        }
+
+        let image = image::open(path)
+            .with_context(|| format!("Failed to load texture {}", path.display()))?
+            .to_rgba8();
+
+        self.textures.push(TextureData {
+            name: path.display().to_string(),
+            width: image.width(),
+            height: image.height(),
+            pixels: image.into_raw(),
+            srgb: true,
+            sampler: SamplerDesc::default(),
+        });
+
+        self.texture_paths.insert(path.to_owned(), self.textures.len() - 1);
+
+        Ok(self.textures.len() - 1)
+    }
+}
@@ -0,0 +1,289 @@
+//! Glyphs rasterized into one signed distance field, packed in rows.
+//!
+//! Each glyph is rasterized at [`SDF_SIZE`] with [`SDF_SPREAD`] pixels of room around it, then every pixel gets
+//! its distance to the outline from an exact Euclidean distance transform of the inside and of the outside. Pixels
+//! next to the outline take their distance from their coverage instead. 0.5 is on the outline, more is inside.
+
+use crate::text::Font;
+
+use ab_glyph::{Font as _, GlyphId, PxScale};
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+/// The size glyphs are rasterized at, text of any other size scales them.
+pub const SDF_SIZE: f32 = 48.0;
+
+/// How many pixels away from the outline distances reach before they're clamped.
+pub const SDF_SPREAD: u32 = 6;
+
+/// Space between glyphs, so that sampling one doesn't read its neighbours.
+const PADDING: u32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FontId(usize);
+
+/// Where a glyph is in the atlas, and where it's drawn relative to the pen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AtlasGlyph {
+    /// Left, top, right and bottom, from 0 to 1.
+    pub uv: [f32; 4],
+    /// From the pen on the baseline to the top left corner, with Y down, at `SDF_SIZE`.
+    pub offset: [f32; 2],
+    /// At `SDF_SIZE`, spread included.
+    pub size: [f32; 2],
+}
+
+#[derive(Clone)]
+pub struct SdfAtlas {
+    pub width: u32,
+    pub height: u32,
+    /// One distance per pixel.
+    pub pixels: Vec<u8>,
+    /// Changes whenever glyphs are added, to tell when the atlas has to be uploaded again.
+    pub version: u64,
+    fonts: Vec<Font>,
+    /// `None` for glyphs without an outline, like spaces.
+    glyphs: HashMap<(FontId, u16), Option<AtlasGlyph>>,
+    /// Where the next glyph goes in the current row, and how tall the row is.
+    cursor: [u32; 2],
+    row_height: u32,
+}
+
+impl SdfAtlas {
+    pub fn new(width: u32, height: u32) -> SdfAtlas {
+        SdfAtlas {
+            width,
+            height,
+            pixels: vec![0; width as usize * height as usize],
+            version: 0,
+            fonts: vec![],
+            glyphs: HashMap::new(),
+            cursor: [PADDING, PADDING],
+            row_height: 0,
+        }
+    }
+
+    pub fn add_font(&mut self, font: Font) -> FontId {
+        self.fonts.push(font);
+
+        FontId(self.fonts.len() - 1)
+    }
+
+    pub fn font(&self, id: FontId) -> Option<&Font> {
+        self.fonts.get(id.0)
+    }
+
+    /// The glyph `id` of `font`, rasterized first if it wasn't yet. Fails when the atlas is full.
+    pub fn glyph(&mut self, font: FontId, id: u16) -> Result<Option<AtlasGlyph>> {
+        if let Some(glyph) = self.glyphs.get(&(font, id)) {
+            return Ok(*glyph);
+        }
+
+        let glyph = self.rasterize(font, id)?;
+
+        self.glyphs.insert((font, id), glyph);
+
+        Ok(glyph)
+    }
+
+    /// How many glyphs were rasterized, outlines or not.
+    pub fn glyph_count(&self) -> usize {
+        self.glyphs.len()
+    }
+
+    fn rasterize(&mut self, font: FontId, id: u16) -> Result<Option<AtlasGlyph>> {
+        let font = match self.fonts.get(font.0) {
+            None => anyhow::bail!("The atlas has no font {:?}", font),
+            Some(font) => font.inner()
+        };
+
+        let glyph = GlyphId(id).with_scale_and_position(PxScale::from(SDF_SIZE), ab_glyph::point(0.0, 0.0));
+
+        let outlined = match font.outline_glyph(glyph) {
+            None => return Ok(None),
+            Some(outlined) => outlined
+        };
+
+        let bounds = outlined.px_bounds();
+
+        let width = bounds.width().ceil() as u32 + 2 * SDF_SPREAD;
+        let height = bounds.height().ceil() as u32 + 2 * SDF_SPREAD;
+
+        let mut coverage = vec![0.0; width as usize * height as usize];
+
+        outlined.draw(|x, y, value| {
+            let (x, y) = (x + SDF_SPREAD, y + SDF_SPREAD);
+
+            if x < width && y < height {
+                coverage[(y * width + x) as usize] = value;
+            }
+        });
+
+        let [x, y] = self.allocate(width, height)?;
+
+        let distances = distance_field(&coverage, width as usize, height as usize);
+
+        for row in 0..height {
+            let from = (row * width) as usize;
+            let to = ((y + row) * self.width + x) as usize;
+
+            self.pixels[to..to + width as usize].copy_from_slice(&distances[from..from + width as usize]);
+        }
+
+        self.version += 1;
+
+        Ok(Some(AtlasGlyph {
+            uv: [
+                x as f32 / self.width as f32,
+                y as f32 / self.height as f32,
+                (x + width) as f32 / self.width as f32,
+                (y + height) as f32 / self.height as f32,
+            ],
+            offset: [bounds.min.x - SDF_SPREAD as f32, bounds.min.y - SDF_SPREAD as f32],
+            size: [width as f32, height as f32],
+        }))
+    }
+
+    /// Finds room for a glyph in the current row, or in a new one under it.
+    fn allocate(&mut self, width: u32, height: u32) -> Result<[u32; 2]> {
+        if self.cursor[0] + width + PADDING > self.width {
+            self.cursor = [PADDING, self.cursor[1] + self.row_height + PADDING];
+            self.row_height = 0;
+        }
+
+        if self.cursor[0] + width + PADDING > self.width || self.cursor[1] + height + PADDING > self.height {
+            anyhow::bail!("The {}x{} text atlas is full", self.width, self.height);
+        }
+
+        let position = self.cursor;
+
+        self.cursor[0] += width + PADDING;
+        self.row_height = self.row_height.max(height);
+
+        Ok(position)
+    }
+}
+
+/// Signed distances to the edge of `coverage`, encoded so that 0.5 is on it and inside is greater.
+fn distance_field(coverage: &[f32], width: usize, height: usize) -> Vec<u8> {
+    let inside: Vec<bool> = coverage.iter().map(|&value| value >= 0.5).collect();
+
+    // squared distances to the nearest pixel inside, and to the nearest one outside:
+    let to_inside = distance_transform(&inside, width, height);
+    let to_outside = distance_transform(&inside.iter().map(|inside| !inside).collect::<Vec<bool>>(), width, height);
+
+    coverage.iter().enumerate()
+        .map(|(i, &value)| {
+            // in pixels, negative inside:
+            let distance = if inside[i] {
+                0.5 - to_outside[i].sqrt()
+            } else {
+                to_inside[i].sqrt() - 0.5
+            };
+
+            // next to the outline, coverage tells where it goes through the pixel:
+            let distance = if distance.abs() <= 0.5 {
+                0.5 - value
+            } else {
+                distance
+            };
+
+            let encoded = 0.5 - distance / (2.0 * SDF_SPREAD as f32);
+
+            (encoded.clamp(0.0, 1.0) * 255.0).round() as u8
+        })
+        .collect()
+}
+
+/// Squared distances to the nearest pixel that's set, along columns then rows, after Felzenszwalb and Huttenlocher.
+fn distance_transform(set: &[bool], width: usize, height: usize) -> Vec<f32> {
+    let mut distances: Vec<f32> = set.iter()
+        .map(|&set| if set { 0.0 } else { f32::INFINITY })
+        .collect();
+
+    let length = width.max(height);
+
+    let mut line = vec![0.0; length];
+    let mut output = vec![0.0; length];
+    let mut parabolas = vec![0; length];
+    let mut boundaries = vec![0.0; length + 1];
+
+    for x in 0..width {
+        for y in 0..height {
+            line[y] = distances[y * width + x];
+        }
+
+        distance_transform_1d(&line[..height], &mut output[..height], &mut parabolas, &mut boundaries);
+
+        for y in 0..height {
+            distances[y * width + x] = output[y];
+        }
+    }
+
+    for y in 0..height {
+        line[..width].copy_from_slice(&distances[y * width..(y + 1) * width]);
+
+        distance_transform_1d(&line[..width], &mut output[..width], &mut parabolas, &mut boundaries);
+
+        distances[y * width..(y + 1) * width].copy_from_slice(&output[..width]);
+    }
+
+    distances
+}
+
+/// The lower envelope of the parabolas rooted at each of `values`.
+fn distance_transform_1d(values: &[f32], output: &mut [f32], parabolas: &mut [usize], boundaries: &mut [f32]) {
+    // nothing to measure against, everything stays infinitely far:
+    let first = match values.iter().position(|value| value.is_finite()) {
+        None => {
+            output.fill(f32::INFINITY);
+
+            return;
+        },
+        Some(first) => first
+    };
+
+    let intersection = |q: usize, p: usize| {
+        ((values[q] + (q * q) as f32) - (values[p] + (p * p) as f32)) / (2.0 * q as f32 - 2.0 * p as f32)
+    };
+
+    let mut k = 0;
+
+    parabolas[0] = first;
+    boundaries[0] = f32::NEG_INFINITY;
+    boundaries[1] = f32::INFINITY;
+
+    for (q, value) in values.iter().enumerate().skip(first + 1) {
+        if !value.is_finite() {
+            continue;
+        }
+
+        let mut s = intersection(q, parabolas[k]);
+
+        while s <= boundaries[k] {
+            k -= 1;
+            s = intersection(q, parabolas[k]);
+        }
+
+        k += 1;
+
+        parabolas[k] = q;
+        boundaries[k] = s;
+        boundaries[k + 1] = f32::INFINITY;
+    }
+
+    k = 0;
+
+    for (q, output) in output.iter_mut().enumerate() {
+        while boundaries[k + 1] < q as f32 {
+            k += 1;
+        }
+
+        let p = parabolas[k];
+        let offset = q as f32 - p as f32;
+
+        *output = offset * offset + values[p];
+    }
+}
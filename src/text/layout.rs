@@ -0,0 +1,230 @@
+//! Laying text out into lines: kerning, wrapping at a width and aligning the lines.
+//!
+//! Lines break at `\n`, and with a `max_width` after the last word that fits. The spaces a line is broken at go
+//! with neither line. A word too long for a line of its own is broken where it overflows.
+
+use crate::text::Font;
+
+use std::ops::Range;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextStyle {
+    /// See [`Font`] for what it measures.
+    pub size: f32,
+    /// Where lines are wrapped, `None` only breaks them at `\n`.
+    pub max_width: Option<f32>,
+    /// Lines are aligned within `max_width`, or within the widest line without it.
+    pub align: Align,
+    /// Multiplies the font's distance between baselines.
+    pub line_spacing: f32,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        TextStyle {
+            size: 16.0,
+            max_width: None,
+            align: Align::Left,
+            line_spacing: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LayoutGlyph {
+    pub id: u16,
+    pub character: char,
+    /// Where the pen is on the baseline when the glyph is drawn, from the layout's top left corner with Y down.
+    pub position: [f32; 2],
+    pub line: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LayoutLine {
+    /// Into the layout's glyphs.
+    pub glyphs: Range<usize>,
+    /// Where it starts, after aligning.
+    pub x: f32,
+    /// Without the spaces at its end.
+    pub width: f32,
+    /// From the layout's top.
+    pub baseline: f32,
+}
+
+/// Text laid out with a font and a style, without anything to draw it with yet.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TextLayout {
+    pub glyphs: Vec<LayoutGlyph>,
+    pub lines: Vec<LayoutLine>,
+    /// Of the widest line.
+    pub width: f32,
+    /// From the first line's ascender to the last line's descender.
+    pub height: f32,
+    /// What the layout was laid out at, its glyphs are drawn at it.
+    pub size: f32,
+}
+
+impl TextLayout {
+    pub fn new(font: &Font, text: &str, style: &TextStyle) -> TextLayout {
+        let mut layout = TextLayout {
+            size: style.size,
+            ..Default::default()
+        };
+
+        let ascent = font.ascent(style.size);
+        let line_height = font.line_height(style.size) * style.line_spacing;
+
+        for paragraph in text.split('\n') {
+            let characters: Vec<(char, u16)> = paragraph.trim_end_matches('\r').chars()
+                .map(|character| (character, font.glyph_id(character)))
+                .collect();
+
+            let mut start = 0;
+
+            // an empty paragraph is still an empty line:
+            loop {
+                let (end, next) = Self::fit(font, &characters[start..], style);
+
+                let baseline = ascent + layout.lines.len() as f32 * line_height;
+
+                layout.push_line(font, &characters[start..start + end], style.size, baseline);
+
+                start += next;
+
+                if start >= characters.len() {
+                    break;
+                }
+            }
+        }
+
+        layout.width = layout.lines.iter()
+            .map(|line| line.width)
+            .fold(0.0, f32::max);
+
+        layout.height = ascent - font.descent(style.size) + (layout.lines.len() - 1) as f32 * line_height;
+
+        layout.align(style);
+
+        layout
+    }
+
+    /// How many of `characters` go on the next line, and how many of them are done with after it.
+    fn fit(font: &Font, characters: &[(char, u16)], style: &TextStyle) -> (usize, usize) {
+        let max_width = match style.max_width {
+            None => return (characters.len(), characters.len()),
+            Some(max_width) => max_width
+        };
+
+        let mut x = 0.0;
+        let mut previous = None;
+
+        // where the line can be broken, spaces after the first word:
+        let mut last_space = None;
+        let mut word = false;
+
+        for (i, &(character, id)) in characters.iter().enumerate() {
+            if let Some(previous) = previous {
+                x += font.kerning(previous, id, style.size);
+            }
+
+            x += font.advance(id, style.size);
+
+            previous = Some(id);
+
+            // spaces can hang over the end, they aren't drawn:
+            if character.is_whitespace() {
+                if word {
+                    last_space = Some(i);
+                }
+
+                continue;
+            }
+
+            word = true;
+
+            if x > max_width && i > 0 {
+                return match last_space {
+                    None => (i, i),
+                    Some(space) => {
+                        let end = characters[..space].iter()
+                            .rposition(|(character, _)| !character.is_whitespace())
+                            .map_or(0, |last| last + 1);
+
+                        let next = characters[space..].iter()
+                            .position(|(character, _)| !character.is_whitespace())
+                            .map_or(characters.len(), |first| space + first);
+
+                        (end, next)
+                    }
+                };
+            }
+        }
+
+        (characters.len(), characters.len())
+    }
+
+    fn push_line(&mut self, font: &Font, characters: &[(char, u16)], size: f32, baseline: f32) {
+        let first = self.glyphs.len();
+        let line = self.lines.len();
+
+        let mut x = 0.0;
+        let mut width = 0.0;
+        let mut previous = None;
+
+        for &(character, id) in characters {
+            if let Some(previous) = previous {
+                x += font.kerning(previous, id, size);
+            }
+
+            self.glyphs.push(LayoutGlyph {
+                id,
+                character,
+                position: [x, baseline],
+                line,
+            });
+
+            x += font.advance(id, size);
+
+            if !character.is_whitespace() {
+                width = x;
+            }
+
+            previous = Some(id);
+        }
+
+        self.lines.push(LayoutLine {
+            glyphs: first..self.glyphs.len(),
+            x: 0.0,
+            width,
+            baseline,
+        });
+    }
+
+    fn align(&mut self, style: &TextStyle) {
+        let factor = match style.align {
+            Align::Left => return,
+            Align::Center => 0.5,
+            Align::Right => 1.0,
+        };
+
+        let area = style.max_width.unwrap_or(self.width);
+
+        for line in &mut self.lines {
+            let offset = (area - line.width) * factor;
+
+            line.x += offset;
+
+            for glyph in &mut self.glyphs[line.glyphs.clone()] {
+                glyph.position[0] += offset;
+            }
+        }
+    }
+}
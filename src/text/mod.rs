@@ -0,0 +1,287 @@
+//! Text from TrueType and OpenType fonts, drawn from a signed distance field atlas.
+//!
+//! Everything here is plain data that works without a GPU: a [`Font`] is laid out into a [`TextLayout`], whose
+//! glyphs are rasterized into an [`SdfAtlas`] as they're first needed and turned into quads by a [`TextBatch`].
+//! The renderer's [`text`](crate::renderer::text) draws the batches, see [`VulkanRenderer::draw_text`] and
+//! [`VulkanRenderer::draw_world_text`].
+//!
+//! The atlas holds distances to the glyphs' outlines rather than their coverage, so the same glyphs stay sharp at
+//! any size and in any place in the world.
+//!
+//! [`VulkanRenderer::draw_text`]: crate::VulkanRenderer::draw_text
+//! [`VulkanRenderer::draw_world_text`]: crate::VulkanRenderer::draw_world_text
+
+pub mod atlas;
+pub mod layout;
+
+pub use atlas::{AtlasGlyph, FontId, SdfAtlas};
+pub use layout::{Align, LayoutGlyph, LayoutLine, TextLayout, TextStyle};
+
+use ab_glyph::{Font as _, FontArc, GlyphId};
+
+use ttf_parser::gpos::{PairAdjustment, PositioningSubtable};
+use ttf_parser::{Face, RawFaceTables, Tag};
+
+use glam::{Mat4, Vec3};
+
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+
+/// A parsed font, cheap to clone.
+///
+/// Sizes are the height from the lowest descender to the highest ascender, in whatever units the text is laid
+/// out in: pixels on screen, or world units.
+#[derive(Clone, Debug)]
+pub struct Font {
+    font: FontArc,
+    /// The `kern` feature's subtables in `GPOS`, in the order they're looked in.
+    pairs: Arc<PairAdjustments>,
+}
+
+/// Where the pair adjustments of a font's `kern` feature are, looked up in the font's `GPOS` table when they're
+/// needed rather than read out of it up front.
+#[derive(Clone, Debug, Default)]
+struct PairAdjustments {
+    /// The byte ranges of the `head`, `hhea`, `maxp` and `GPOS` tables in the font data.
+    tables: [Range<usize>; 4],
+    /// The lookup and subtable index of every pair adjustment subtable.
+    subtables: Vec<(u16, u16)>,
+}
+
+impl PairAdjustments {
+    /// The pair adjustment subtables of the `kern` feature, none when the font doesn't have a `GPOS` table.
+    fn parse(data: &[u8]) -> PairAdjustments {
+        let face = match Face::parse(data, 0) {
+            Err(_) => return PairAdjustments::default(),
+            Ok(face) => face
+        };
+
+        let gpos = match face.tables().gpos {
+            None => return PairAdjustments::default(),
+            Some(gpos) => gpos
+        };
+
+        let table = |tag: &[u8; 4]| face.raw_face().table_records.into_iter()
+            .find(|record| record.tag == Tag::from_bytes(tag))
+            .map(|record| record.offset as usize..record.offset as usize + record.length as usize)
+            .unwrap_or_default();
+
+        let lookups = gpos.features.into_iter()
+            .filter(|feature| feature.tag == Tag::from_bytes(b"kern"))
+            .flat_map(|feature| feature.lookup_indices);
+
+        let mut subtables = vec![];
+
+        for index in lookups {
+            let lookup = match gpos.lookups.get(index) {
+                None => continue,
+                Some(lookup) => lookup
+            };
+
+            for (subtable, kind) in lookup.subtables.into_iter::<PositioningSubtable>().enumerate() {
+                if let PositioningSubtable::Pair(_) = kind {
+                    subtables.push((index, subtable as u16));
+                }
+            }
+        }
+
+        PairAdjustments {
+            tables: [table(b"head"), table(b"hhea"), table(b"maxp"), table(b"GPOS")],
+            subtables,
+        }
+    }
+
+    /// The pair's adjustment in font units from the first subtable that has it, `None` when none of them do.
+    fn get(&self, data: &[u8], first: u16, second: u16) -> Option<f32> {
+        if self.subtables.is_empty() {
+            return None;
+        }
+
+        // only the tables a face can't do without and `GPOS` are parsed, none of them are walked:
+        let [head, hhea, maxp, gpos] = self.tables.clone().map(|range| data.get(range));
+
+        let face = Face::from_raw_tables(RawFaceTables {
+            head: head?,
+            hhea: hhea?,
+            maxp: maxp?,
+            gpos,
+            ..Default::default()
+        }).ok()?;
+
+        let gpos = face.tables().gpos?;
+
+        let (first, second) = (ttf_parser::GlyphId(first), ttf_parser::GlyphId(second));
+
+        self.subtables.iter().find_map(|&(lookup, subtable)| {
+            let (record, _) = match gpos.lookups.get(lookup)?.subtables.get::<PositioningSubtable>(subtable)? {
+                PositioningSubtable::Pair(PairAdjustment::Format1 { coverage, sets }) => {
+                    sets.get(coverage.get(first)?)?.get(second)?
+                },
+                // glyphs a class definition doesn't have are in class 0, only the first one has to be covered:
+                PositioningSubtable::Pair(PairAdjustment::Format2 { coverage, classes, matrix }) => {
+                    if !coverage.contains(first) {
+                        return None;
+                    }
+
+                    matrix.get((classes.0.get(first), classes.1.get(second)))?
+                },
+                _ => return None,
+            };
+
+            Some(record.x_advance as f32)
+        })
+    }
+}
+
+impl Font {
+    /// Parses a TTF or OTF font, the first one of a collection.
+    pub fn from_bytes(data: Vec<u8>) -> Result<Font> {
+        let font = FontArc::try_from_vec(data)
+            .context("Failed to parse font data")?;
+
+        let pairs = Arc::new(PairAdjustments::parse(font.font_data()));
+
+        Ok(Font {
+            font,
+            pairs,
+        })
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Font> {
+        let path = path.as_ref();
+
+        let data = fs::read(path)
+            .with_context(|| format!("Failed to read font file {}", path.display()))?;
+
+        Self::from_bytes(data)
+            .with_context(|| format!("Failed to load font file {}", path.display()))
+    }
+
+    /// The glyph of `character`, 0 is the font's glyph for characters it doesn't have.
+    pub fn glyph_id(&self, character: char) -> u16 {
+        self.font.glyph_id(character).0
+    }
+
+    /// How far the pen moves after `glyph`.
+    pub fn advance(&self, glyph: u16, size: f32) -> f32 {
+        self.font.h_advance_unscaled(GlyphId(glyph)) * self.scale(size)
+    }
+
+    /// The adjustment to `first`'s advance in front of `second`, negative when they move closer.
+    pub fn kerning(&self, first: u16, second: u16, size: f32) -> f32 {
+        let kerning = match self.font.kern_unscaled(GlyphId(first), GlyphId(second)) {
+            kerning if kerning != 0.0 => kerning,
+            _ => self.pair_adjustment(first, second),
+        };
+
+        kerning * self.scale(size)
+    }
+
+    /// From the baseline up to the highest ascender.
+    pub fn ascent(&self, size: f32) -> f32 {
+        self.font.ascent_unscaled() * self.scale(size)
+    }
+
+    /// From the baseline down to the lowest descender, negative.
+    pub fn descent(&self, size: f32) -> f32 {
+        self.font.descent_unscaled() * self.scale(size)
+    }
+
+    /// From one baseline to the next.
+    pub fn line_height(&self, size: f32) -> f32 {
+        (self.font.height_unscaled() + self.font.line_gap_unscaled()) * self.scale(size)
+    }
+
+    /// The advance `first` changes by in front of `second` in the `kern` feature of the font's `GPOS` table, in font
+    /// units. Fonts that were made after OpenType came along often only kern there.
+    fn pair_adjustment(&self, first: u16, second: u16) -> f32 {
+        self.pairs.get(self.font.font_data(), first, second).unwrap_or(0.0)
+    }
+
+    fn scale(&self, size: f32) -> f32 {
+        size / self.font.height_unscaled()
+    }
+
+    pub(crate) fn inner(&self) -> &FontArc {
+        &self.font
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TextVertex {
+    pub position: [f32; 3],
+    pub uv: [f32; 2],
+    /// sRGB encoded, with straight alpha.
+    pub color: [u8; 4],
+}
+
+/// Quads of glyphs in a vertex and an index buffer, for drawing many texts at once.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TextBatch {
+    pub vertices: Vec<TextVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl TextBatch {
+    pub fn new() -> TextBatch {
+        TextBatch::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.indices.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Adds a quad for each of `layout`'s glyphs that has an outline, rasterizing the ones `atlas` doesn't have yet.
+    ///
+    /// The quads' corners are in the layout's units, with X to the right and Y down from its top left corner,
+    /// and are put through `transform`. `font` has to be the font `layout` was laid out with.
+    pub fn push(
+        &mut self,
+        atlas: &mut SdfAtlas,
+        font: FontId,
+        layout: &TextLayout,
+        transform: Mat4,
+        color: [u8; 4]
+    ) -> Result<()> {
+        // the atlas' glyphs were rasterized at a size of their own:
+        let scale = layout.size / atlas::SDF_SIZE;
+
+        for glyph in &layout.glyphs {
+            let atlas_glyph = match atlas.glyph(font, glyph.id)? {
+                None => continue,
+                Some(atlas_glyph) => atlas_glyph
+            };
+
+            let left = glyph.position[0] + atlas_glyph.offset[0] * scale;
+            let top = glyph.position[1] + atlas_glyph.offset[1] * scale;
+            let right = left + atlas_glyph.size[0] * scale;
+            let bottom = top + atlas_glyph.size[1] * scale;
+
+            let [u0, v0, u1, v1] = atlas_glyph.uv;
+
+            let first = self.vertices.len() as u32;
+
+            for (x, y, u, v) in [(left, top, u0, v0), (right, top, u1, v0), (right, bottom, u1, v1), (left, bottom, u0, v1)] {
+                self.vertices.push(TextVertex {
+                    position: transform.transform_point3(Vec3::new(x, y, 0.0)).to_array(),
+                    uv: [u, v],
+                    color,
+                });
+            }
+
+            self.indices.extend_from_slice(&[first, first + 1, first + 2, first + 2, first + 3, first]);
+        }
+
+        Ok(())
+    }
+}
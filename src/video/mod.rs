@@ -0,0 +1,140 @@
+//! Uncompressed video frames and the files they're read from and written to.
+//!
+//! Frames are 8-bit YUV 4:2:0, what Y4M files hold and what [`capture`](crate::renderer::capture) converts rendered
+//! frames to. They can be inspected without a GPU. Raw I420 and NV12 frames are read into the same planes, see
+//! [`video_filters`](crate::renderer::video_filters) for running them through compute shaders.
+
+pub mod y4m;
+
+use anyhow::Result;
+
+/// The widest and tallest a frame can be, what desktop GPUs allow for a 2D image. Bigger frames are refused before
+/// anything is allocated for them, a header could claim gigabytes.
+pub const MAX_DIMENSION: u32 = 16384;
+
+/// How the planes of a raw frame are laid out, one frame after the other without any headers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RawFormat {
+    /// Y, then U, then V, what Y4M frames hold.
+    I420,
+    /// Y, then U and V interleaved.
+    Nv12,
+}
+
+/// A planar 8-bit YUV 4:2:0 frame, BT.601 with limited range.
+///
+/// U and V are half the size of Y along each side, rounded up, every sample sits in the middle of the 2x2 pixels it
+/// covers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Yuv420 {
+    pub width: u32,
+    pub height: u32,
+    pub y: Vec<u8>,
+    pub u: Vec<u8>,
+    pub v: Vec<u8>,
+}
+
+impl Yuv420 {
+    /// A black frame, fails when it's too big to be held.
+    pub fn new(width: u32, height: u32) -> Result<Yuv420> {
+        let size = Self::frame_size(width, height)?;
+
+        let luma = width as usize * height as usize;
+        let chroma = (size - luma) / 2;
+
+        Ok(Yuv420 {
+            width,
+            height,
+            y: vec![16; luma],
+            u: vec![128; chroma],
+            v: vec![128; chroma],
+        })
+    }
+
+    /// The size of the U and V planes of a frame.
+    pub fn chroma_size(width: u32, height: u32) -> (u32, u32) {
+        (width.div_ceil(2), height.div_ceil(2))
+    }
+
+    /// How many bytes a raw frame takes, in either format. Fails when a side is over [`MAX_DIMENSION`].
+    pub fn frame_size(width: u32, height: u32) -> Result<usize> {
+        if width > MAX_DIMENSION || height > MAX_DIMENSION {
+            anyhow::bail!("A {}x{} frame is too big, neither side can be over {}", width, height, MAX_DIMENSION);
+        }
+
+        let (chroma_width, chroma_height) = Self::chroma_size(width, height);
+
+        let luma = (width as usize).checked_mul(height as usize);
+        let chroma = (chroma_width as usize).checked_mul(chroma_height as usize);
+
+        let size = luma
+            .zip(chroma)
+            .and_then(|(luma, chroma)| luma.checked_add(chroma.checked_mul(2)?));
+
+        match size {
+            None => anyhow::bail!("A {}x{} frame is too big", width, height),
+            Some(size) => Ok(size)
+        }
+    }
+
+    /// Splits a raw frame of `frame_size` bytes into its planes.
+    pub fn from_raw(width: u32, height: u32, format: RawFormat, bytes: &[u8]) -> Result<Yuv420> {
+        let size = Self::frame_size(width, height)?;
+
+        if bytes.len() != size {
+            anyhow::bail!("A raw {}x{} frame takes {} bytes, not {}", width, height, size, bytes.len());
+        }
+
+        let (y, chroma) = bytes.split_at(width as usize * height as usize);
+
+        let (u, v) = match format {
+            RawFormat::I420 => {
+                let (u, v) = chroma.split_at(chroma.len() / 2);
+
+                (u.to_vec(), v.to_vec())
+            },
+            RawFormat::Nv12 => (
+                chroma.iter().step_by(2).copied().collect(),
+                chroma.iter().skip(1).step_by(2).copied().collect(),
+            ),
+        };
+
+        Ok(Yuv420 {
+            width,
+            height,
+            y: y.to_vec(),
+            u,
+            v,
+        })
+    }
+
+    /// The frame as `frame_size` raw bytes.
+    pub fn to_raw(&self, format: RawFormat) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.y.len() + self.u.len() + self.v.len());
+
+        bytes.extend_from_slice(&self.y);
+
+        match format {
+            RawFormat::I420 => {
+                bytes.extend_from_slice(&self.u);
+                bytes.extend_from_slice(&self.v);
+            },
+            RawFormat::Nv12 => {
+                for (u, v) in self.u.iter().zip(&self.v) {
+                    bytes.extend_from_slice(&[*u, *v]);
+                }
+            }
+        }
+
+        bytes
+    }
+
+    /// The Y, U and V of the pixel at `x`, `y`.
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 3] {
+        let (chroma_width, _) = Self::chroma_size(self.width, self.height);
+
+        let chroma = ((y / 2) * chroma_width + x / 2) as usize;
+
+        [self.y[(y * self.width + x) as usize], self.u[chroma], self.v[chroma]]
+    }
+}
@@ -0,0 +1,180 @@
+//! YUV4MPEG2 streams: a line of text with the frame size and rate, then every frame as raw planes.
+//!
+//! Only 8-bit 4:2:0 is read and written, see [`Yuv420`]. Streams that don't say what they hold are taken to be 4:2:0
+//! like the format says they are, whichever of its chroma sitings they name is read as centered.
+
+use crate::video::Yuv420;
+
+use std::io::{BufRead, Write};
+
+use anyhow::{Context, Result};
+
+/// The colour spaces of 8-bit 4:2:0, which only differ in where chroma is sited.
+const COLOUR_SPACES: &[&str] = &["420", "420jpeg", "420paldv", "420mpeg2"];
+
+/// Reads frames from a Y4M stream, its header is read when it's created.
+pub struct Y4mReader<R: BufRead> {
+    reader: R,
+    pub width: u32,
+    pub height: u32,
+    /// Frames per second, as a numerator and a denominator.
+    pub frame_rate: (u32, u32),
+    /// How many frames were read so far.
+    pub frames: u32,
+}
+
+impl<R: BufRead> Y4mReader<R> {
+    pub fn new(mut reader: R) -> Result<Y4mReader<R>> {
+        let mut header = String::new();
+
+        reader.read_line(&mut header)
+            .context("Failed to read the Y4M header")?;
+
+        let mut tags = header.trim_end().split(' ');
+
+        if tags.next() != Some("YUV4MPEG2") {
+            anyhow::bail!("Not a Y4M stream, it doesn't start with YUV4MPEG2");
+        }
+
+        let mut width = None;
+        let mut height = None;
+        let mut frame_rate = (25, 1);
+
+        for tag in tags.filter(|tag| !tag.is_empty()) {
+            // the key is a character, which needn't be ASCII in a broken header:
+            let (key, value) = match tag.chars().next() {
+                None => anyhow::bail!("The Y4M header has an empty tag"),
+                Some(key) => (key, &tag[key.len_utf8()..])
+            };
+
+            match key {
+                'W' => width = Some(value.parse().with_context(|| format!("Y4M width {} isn't a number", value))?),
+                'H' => height = Some(value.parse().with_context(|| format!("Y4M height {} isn't a number", value))?),
+                'F' => frame_rate = Self::ratio(value)?,
+                'C' if !COLOUR_SPACES.contains(&value) => {
+                    anyhow::bail!("Only 8-bit 4:2:0 Y4M streams can be read, not {}", value)
+                },
+                'I' if value != "p" && value != "?" => anyhow::bail!("Only progressive Y4M streams can be read"),
+                // aspect ratios and extensions don't change how frames are read:
+                _ => {}
+            }
+        }
+
+        let (width, height) = match (width, height) {
+            (Some(width), Some(height)) if width > 0 && height > 0 => (width, height),
+            _ => anyhow::bail!("The Y4M header doesn't say how big the frames are"),
+        };
+
+        // a header can claim frames too big to be held:
+        Yuv420::frame_size(width, height)?;
+
+        Ok(Y4mReader {
+            reader,
+            width,
+            height,
+            frame_rate,
+            frames: 0,
+        })
+    }
+
+    fn ratio(value: &str) -> Result<(u32, u32)> {
+        let parsed = value.split_once(':')
+            .and_then(|(numerator, denominator)| Some((numerator.parse().ok()?, denominator.parse().ok()?)));
+
+        match parsed {
+            Some((numerator, denominator)) if numerator > 0 && denominator > 0 => Ok((numerator, denominator)),
+            _ => anyhow::bail!("Y4M frame rate {} isn't a ratio", value),
+        }
+    }
+
+    /// The next frame, or `None` at the end of the stream.
+    pub fn read_frame(&mut self) -> Result<Option<Yuv420>> {
+        let mut marker = String::new();
+
+        if self.reader.read_line(&mut marker)? == 0 {
+            return Ok(None);
+        }
+
+        // frames can come with parameters of their own, which are ignored:
+        if !marker.starts_with("FRAME") {
+            anyhow::bail!("Y4M frame {} doesn't start with FRAME", self.frames);
+        }
+
+        let mut frame = Yuv420::new(self.width, self.height)?;
+
+        for plane in [&mut frame.y, &mut frame.u, &mut frame.v] {
+            self.reader.read_exact(plane)
+                .with_context(|| format!("Y4M frame {} ends early", self.frames))?;
+        }
+
+        self.frames += 1;
+
+        Ok(Some(frame))
+    }
+}
+
+/// Writes frames of one size to a Y4M stream, the header goes out when it's created.
+pub struct Y4mWriter<W: Write> {
+    writer: W,
+    pub width: u32,
+    pub height: u32,
+    /// How many frames were written so far.
+    pub frames: u32,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    pub fn new(writer: W, width: u32, height: u32, fps: u32) -> Result<Y4mWriter<W>> {
+        Self::with_frame_rate(writer, width, height, (fps, 1))
+    }
+
+    /// Like `new`, with frames per second as a numerator and a denominator.
+    pub fn with_frame_rate(mut writer: W, width: u32, height: u32, frame_rate: (u32, u32)) -> Result<Y4mWriter<W>> {
+        let (numerator, denominator) = frame_rate;
+
+        if width == 0 || height == 0 || numerator == 0 || denominator == 0 {
+            anyhow::bail!(
+                "Y4M streams can't be {}x{} at {}:{} frames per second",
+                width, height, numerator, denominator,
+            );
+        }
+
+        // progressive, square pixels, chroma centered between the pixels it covers:
+        writeln!(
+            writer,
+            "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C420jpeg XYSCSS=420JPEG",
+            width, height, numerator, denominator,
+        )?;
+
+        Ok(Y4mWriter {
+            writer,
+            width,
+            height,
+            frames: 0,
+        })
+    }
+
+    pub fn write_frame(&mut self, frame: &Yuv420) -> Result<()> {
+        if frame.width != self.width || frame.height != self.height {
+            anyhow::bail!(
+                "A {}x{} frame doesn't fit into a {}x{} Y4M stream",
+                frame.width, frame.height, self.width, self.height,
+            );
+        }
+
+        self.writer.write_all(b"FRAME\n")?;
+        self.writer.write_all(&frame.y)?;
+        self.writer.write_all(&frame.u)?;
+        self.writer.write_all(&frame.v)?;
+
+        self.frames += 1;
+
+        Ok(())
+    }
+
+    /// Flushes what's buffered and hands the writer back.
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}